@@ -0,0 +1,25 @@
+-- Expenses extracted from receipt/bill images (or logged manually)
+CREATE TABLE expenses (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+
+    merchant TEXT NOT NULL,
+    -- Amounts are stored in minor units (cents) to avoid float rounding
+    total_cents BIGINT NOT NULL,
+    -- ISO 4217 code, e.g. 'USD'
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    -- Date printed on the receipt (falls back to the date it was received)
+    purchased_on DATE NOT NULL,
+    -- Free-form category (groceries, dining, utilities, ...)
+    category VARCHAR(50) NOT NULL DEFAULT 'other',
+    -- [{description, quantity, amount_cents}]
+    line_items JSONB NOT NULL DEFAULT '[]',
+
+    -- Where this came from: 'receipt' or 'manual'
+    source VARCHAR(20) NOT NULL DEFAULT 'receipt',
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Index for reports over a date range
+CREATE INDEX idx_expenses_agent_date ON expenses(agent_id, purchased_on);
@@ -0,0 +1,26 @@
+-- Write-ahead log of incoming messages. Rows are written before a message is
+-- handed to an agent and deleted once its turn completes, so messages that
+-- were queued when the process died are replayed on startup.
+CREATE TABLE inbox_messages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+
+    source TEXT NOT NULL,
+    source_name TEXT,
+    message TEXT NOT NULL,
+    -- [{file, content_type, size}]
+    attachments JSONB NOT NULL DEFAULT '[]',
+    -- Provider timestamp (ms since epoch)
+    sent_at BIGINT NOT NULL,
+    reply_to TEXT NOT NULL,
+    reply_context TEXT,
+
+    -- 'pending' until processed; 'failed' once it has crashed too many turns
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    -- Number of times processing was started
+    attempts INTEGER NOT NULL DEFAULT 0,
+
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Index for replaying pending messages in arrival order
+CREATE INDEX idx_inbox_messages_pending ON inbox_messages(status, received_at);
@@ -0,0 +1,23 @@
+-- Turn journal: one row per event in an agent turn (input, LLM step, tool
+-- call, outgoing message, error), used to reconstruct full turn transcripts.
+CREATE TABLE turn_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    turn_id UUID NOT NULL,
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+
+    -- Order of the event within the turn
+    seq INTEGER NOT NULL,
+    -- Step number the event belongs to (NULL for turn-level events)
+    step INTEGER,
+    -- 'input', 'step', 'tool_call', 'output', 'error', 'end'
+    kind VARCHAR(20) NOT NULL,
+    content TEXT NOT NULL,
+    -- Kind-specific details (tool name/args, success flag, ...)
+    metadata JSONB NOT NULL DEFAULT '{}',
+    duration_ms BIGINT,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_turn_events_turn ON turn_events(turn_id, seq);
+CREATE INDEX idx_turn_events_agent ON turn_events(agent_id, created_at DESC);
@@ -0,0 +1,16 @@
+-- Outgoing messages held by the output guardrails until the owner releases
+-- or discards them.
+CREATE TABLE held_messages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    recipient TEXT NOT NULL,
+    -- The original, unredacted message
+    content TEXT NOT NULL,
+    -- Rules the message violated
+    violations TEXT[] NOT NULL DEFAULT '{}',
+    -- 'pending', 'released', 'discarded'
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    reviewed_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_held_messages_status ON held_messages(status, created_at);
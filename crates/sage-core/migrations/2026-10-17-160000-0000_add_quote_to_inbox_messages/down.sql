@@ -0,0 +1 @@
+ALTER TABLE inbox_messages DROP COLUMN quote;
@@ -0,0 +1,3 @@
+-- The message an incoming message replies to (Signal quote, Marmot reply
+-- tag), so replayed messages keep their reply context.
+ALTER TABLE inbox_messages ADD COLUMN quote JSONB;
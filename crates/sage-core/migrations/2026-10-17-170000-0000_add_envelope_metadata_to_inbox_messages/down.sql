@@ -0,0 +1,3 @@
+ALTER TABLE inbox_messages DROP COLUMN edited;
+ALTER TABLE inbox_messages DROP COLUMN mentions;
+ALTER TABLE inbox_messages DROP COLUMN message_id;
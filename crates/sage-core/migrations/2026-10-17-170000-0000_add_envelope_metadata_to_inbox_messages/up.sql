@@ -0,0 +1,5 @@
+-- Envelope metadata of an incoming message (transport message id, mentions,
+-- edit flag), so replayed messages are handled like live ones.
+ALTER TABLE inbox_messages ADD COLUMN message_id TEXT;
+ALTER TABLE inbox_messages ADD COLUMN mentions JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE inbox_messages ADD COLUMN edited BOOLEAN NOT NULL DEFAULT FALSE;
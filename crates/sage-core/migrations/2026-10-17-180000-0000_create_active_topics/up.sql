@@ -0,0 +1,8 @@
+-- The conversation thread ("/topic <name>") each chat is currently in.
+-- Chats without a row are in their main thread.
+CREATE TABLE active_topics (
+    -- Conversation identifier (user UUID/pubkey or group:<id>)
+    identifier TEXT PRIMARY KEY,
+    topic TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,14 @@
+-- Messages Sage sent to direct chats and their delivery/read receipts, so
+-- the agent knows when earlier messages are still unread.
+CREATE TABLE message_delivery (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Chat identifier (Signal UUID or number)
+    recipient TEXT NOT NULL,
+    -- Send time in Unix milliseconds (the clock Signal timestamps use)
+    sent_ms BIGINT NOT NULL,
+    delivered_at TIMESTAMPTZ,
+    read_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_message_delivery_recipient ON message_delivery(recipient, sent_ms);
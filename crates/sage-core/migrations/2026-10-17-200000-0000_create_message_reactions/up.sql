@@ -0,0 +1,21 @@
+-- Emoji reactions users put on Sage's messages, kept as an implicit quality
+-- signal per agent (served to evals/GEPA and used for style hints).
+CREATE TABLE message_reactions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    -- The reacted-to assistant message, when it could be matched
+    message_id UUID REFERENCES messages(id) ON DELETE SET NULL,
+    -- Signal timestamp of the reacted-to message (Unix milliseconds)
+    target_ms BIGINT NOT NULL,
+    -- Who reacted (Signal UUID or number)
+    reactor TEXT NOT NULL,
+    emoji TEXT NOT NULL,
+    -- 1 positive, -1 negative, 0 neutral
+    sentiment SMALLINT NOT NULL,
+    -- Length of the reacted-to message in characters
+    message_chars INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (agent_id, target_ms, reactor)
+);
+
+CREATE INDEX idx_message_reactions_agent ON message_reactions(agent_id, created_at);
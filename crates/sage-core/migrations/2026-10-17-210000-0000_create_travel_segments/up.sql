@@ -0,0 +1,30 @@
+-- Flights and hotel stays parsed from itineraries (pasted confirmations or
+-- forwarded emails). Times are stored in UTC with the IANA timezone of each
+-- end, so local times can be shown exactly.
+CREATE TABLE travel_segments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+
+    -- 'flight' or 'hotel'
+    kind VARCHAR(20) NOT NULL,
+    -- Flight number ("UA 123") or hotel name
+    title TEXT NOT NULL,
+    confirmation TEXT,
+    -- Departure airport/city (flights only)
+    origin TEXT,
+    -- Arrival airport/city, or the hotel's address
+    destination TEXT,
+
+    -- Departure, or hotel check-in
+    starts_at TIMESTAMPTZ NOT NULL,
+    starts_tz TEXT NOT NULL,
+    -- Arrival, or hotel check-out
+    ends_at TIMESTAMPTZ,
+    ends_tz TEXT,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- The same booking forwarded twice is stored once
+    UNIQUE (agent_id, kind, title, starts_at)
+);
+
+CREATE INDEX idx_travel_segments_agent_start ON travel_segments(agent_id, starts_at);
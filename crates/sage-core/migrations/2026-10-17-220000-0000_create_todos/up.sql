@@ -0,0 +1,24 @@
+-- Shared todo lists. A list belongs to a scope rather than an agent, so
+-- everyone in a group sees the same list: the Signal group identifier
+-- ("group:<id>"), "marmot:<nostr_group_id>" for a Marmot group (whose
+-- members each have their own agent), or the chat identifier of a direct chat.
+CREATE TABLE todos (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    scope TEXT NOT NULL,
+    title TEXT NOT NULL,
+    -- Chat identifier of whoever added it
+    created_by TEXT NOT NULL,
+    -- Member it is assigned to: identifier when known (Marmot pubkey), and
+    -- the name used when assigning
+    assignee TEXT,
+    assignee_name TEXT,
+    due_at TIMESTAMPTZ,
+    -- Scheduled reminder, cancelled when the todo is done
+    reminder_task_id UUID REFERENCES scheduled_tasks(id) ON DELETE SET NULL,
+    -- 'open' or 'done'
+    status VARCHAR(20) NOT NULL DEFAULT 'open',
+    completed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_todos_scope_status ON todos(scope, status);
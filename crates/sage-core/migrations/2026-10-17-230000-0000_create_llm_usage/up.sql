@@ -0,0 +1,18 @@
+-- LLM usage accounting: one row per model call (agent step, correction,
+-- vision, compaction, embedding) with its token counts and estimated cost.
+-- Calls made outside an agent's turn (e.g. email digests) have no agent.
+CREATE TABLE llm_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id UUID REFERENCES agents(id) ON DELETE CASCADE,
+    -- 'agent', 'correction', 'vision', 'compaction', 'embedding'
+    kind VARCHAR(20) NOT NULL,
+    model TEXT NOT NULL,
+    prompt_tokens BIGINT NOT NULL,
+    completion_tokens BIGINT NOT NULL,
+    -- Estimated from the configured per-million-token prices
+    cost_usd DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_llm_usage_agent ON llm_usage(agent_id, created_at DESC);
+CREATE INDEX idx_llm_usage_created ON llm_usage(created_at);
@@ -0,0 +1,29 @@
+-- Group polls. Like todos, a poll belongs to a chat scope ("group:<id>" or
+-- "marmot:<nostr_group_id>") so votes from every member count. It is closed
+-- and its result announced by a scheduled close_poll tool call.
+CREATE TABLE polls (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    scope TEXT NOT NULL,
+    -- Agent that created the poll (and announces the result)
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    question TEXT NOT NULL,
+    options TEXT[] NOT NULL,
+    closes_at TIMESTAMPTZ NOT NULL,
+    close_task_id UUID REFERENCES scheduled_tasks(id) ON DELETE SET NULL,
+    -- 'open' or 'closed'
+    status VARCHAR(20) NOT NULL DEFAULT 'open',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_polls_scope_status ON polls(scope, status, created_at DESC);
+
+-- One vote per member and poll; voting again changes it
+CREATE TABLE poll_votes (
+    poll_id UUID NOT NULL REFERENCES polls(id) ON DELETE CASCADE,
+    voter TEXT NOT NULL,
+    voter_name TEXT,
+    -- Index into polls.options
+    option_index INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (poll_id, voter)
+);
@@ -0,0 +1,9 @@
+-- Export encryption keys. A chat's owner sets a passphrase with /export-key;
+-- only the Argon2id-derived key and its salt are stored. Exports made for the
+-- agent (and its /topic threads) are encrypted with it before being sent.
+CREATE TABLE export_keys (
+    agent_id UUID PRIMARY KEY REFERENCES agents(id) ON DELETE CASCADE,
+    salt BYTEA NOT NULL,
+    key BYTEA NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
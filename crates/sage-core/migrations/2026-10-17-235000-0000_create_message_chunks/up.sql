@@ -0,0 +1,20 @@
+-- Overlapping chunks of long recall messages, each with its own embedding.
+-- One embedding of a long pasted document only reflects its beginning (the
+-- model truncates), so conversation_search also matches chunks and ranks a
+-- message by its best one.
+CREATE TABLE message_chunks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    agent_id UUID NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding VECTOR(768) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (message_id, chunk_index)
+);
+
+CREATE INDEX idx_message_chunks_agent ON message_chunks(agent_id);
+
+CREATE INDEX idx_message_chunks_embedding ON message_chunks
+    USING ivfflat (embedding vector_cosine_ops)
+    WITH (lists = 100);
@@ -0,0 +1,19 @@
+-- Where each memory came from: every block edit, archival insert and
+-- preference records the user message(s) of the turn that wrote it, so the
+-- agent can answer "where did you learn that?" (memory_source tool).
+CREATE TABLE memory_sources (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Owner of the memory (the main agent for /topic threads)
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    -- 'block', 'passage' or 'preference'
+    kind TEXT NOT NULL,
+    -- Block label, passage id (or group tag) or preference key
+    target TEXT NOT NULL,
+    -- What was written
+    excerpt TEXT NOT NULL,
+    -- Recall messages it was learned from (may be empty, e.g. scheduled turns)
+    message_ids UUID[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_memory_sources_agent ON memory_sources(agent_id, created_at DESC);
@@ -0,0 +1,17 @@
+DROP INDEX IF EXISTS idx_messages_embedding_hnsw;
+DROP INDEX IF EXISTS idx_message_chunks_embedding_hnsw;
+DROP INDEX IF EXISTS idx_passages_embedding_hnsw;
+DROP INDEX IF EXISTS idx_summaries_embedding_hnsw;
+
+CREATE INDEX idx_messages_embedding ON messages
+    USING ivfflat (embedding vector_cosine_ops)
+    WITH (lists = 100);
+CREATE INDEX idx_message_chunks_embedding ON message_chunks
+    USING ivfflat (embedding vector_cosine_ops)
+    WITH (lists = 100);
+CREATE INDEX idx_passages_embedding ON passages
+    USING ivfflat (embedding vector_cosine_ops)
+    WITH (lists = 100);
+CREATE INDEX idx_summaries_embedding ON summaries
+    USING ivfflat (embedding vector_cosine_ops)
+    WITH (lists = 50);
@@ -0,0 +1,8 @@
+-- IVFFlat indexes were created on empty tables, so their lists were never
+-- trained and they lose recall as the tables fill up. Drop them; HNSW
+-- indexes are built concurrently by the vector index maintenance task
+-- (vector_index.rs) once a table holds enough embeddings to need one.
+DROP INDEX IF EXISTS idx_messages_embedding;
+DROP INDEX IF EXISTS idx_message_chunks_embedding;
+DROP INDEX IF EXISTS idx_passages_embedding;
+DROP INDEX IF EXISTS idx_summaries_embedding;
@@ -0,0 +1,12 @@
+-- Messages waiting for their embedding. Stored messages get a zero vector
+-- and a job here; the embedding worker (memory/embedding_queue.rs) embeds
+-- them and deletes the job, retrying failures with backoff.
+CREATE TABLE embedding_jobs (
+    message_id UUID PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_embedding_jobs_next_attempt ON embedding_jobs(next_attempt_at);
@@ -0,0 +1,4 @@
+-- Writing system of each message (ISO 15924 code, e.g. Latn, Cyrl), detected
+-- when it is stored (memory/language.rs). NULL for messages without letters
+-- and messages stored before detection existed.
+ALTER TABLE messages ADD COLUMN script TEXT;
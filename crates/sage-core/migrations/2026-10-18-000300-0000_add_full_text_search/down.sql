@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_passages_search_tsv;
+ALTER TABLE passages DROP COLUMN search_tsv;
+
+DROP INDEX IF EXISTS idx_messages_search_tsv;
+ALTER TABLE messages DROP COLUMN search_tsv;
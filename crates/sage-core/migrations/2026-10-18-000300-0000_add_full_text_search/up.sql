@@ -0,0 +1,12 @@
+-- Full-text search vectors for hybrid retrieval (memory/fusion.rs). The
+-- 'simple' configuration keeps words as written (no stemming or stop words),
+-- so names, ticket numbers and non-English text match exactly.
+ALTER TABLE messages ADD COLUMN search_tsv tsvector
+    GENERATED ALWAYS AS (
+        to_tsvector('simple', content || ' ' || COALESCE(attachment_text, ''))
+    ) STORED;
+CREATE INDEX idx_messages_search_tsv ON messages USING gin(search_tsv);
+
+ALTER TABLE passages ADD COLUMN search_tsv tsvector
+    GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED;
+CREATE INDEX idx_passages_search_tsv ON passages USING gin(search_tsv);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_passages_expires_at;
+ALTER TABLE passages DROP COLUMN archived_at;
+ALTER TABLE passages DROP COLUMN last_retrieved_at;
+ALTER TABLE passages DROP COLUMN expires_at;
+ALTER TABLE passages DROP COLUMN importance;
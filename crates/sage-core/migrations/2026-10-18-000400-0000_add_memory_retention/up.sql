@@ -0,0 +1,8 @@
+-- Retention (memory/retention.rs): passages carry an importance that the
+-- weekly maintenance run decays when they go unused, and an optional expiry.
+-- Archived passages are kept but no longer searched or listed.
+ALTER TABLE passages ADD COLUMN importance REAL NOT NULL DEFAULT 1.0;
+ALTER TABLE passages ADD COLUMN expires_at TIMESTAMPTZ;
+ALTER TABLE passages ADD COLUMN last_retrieved_at TIMESTAMPTZ;
+ALTER TABLE passages ADD COLUMN archived_at TIMESTAMPTZ;
+CREATE INDEX idx_passages_expires_at ON passages (expires_at) WHERE expires_at IS NOT NULL;
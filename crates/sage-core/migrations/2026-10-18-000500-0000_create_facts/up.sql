@@ -0,0 +1,26 @@
+-- Structured facts about the user (memory/facts.rs): subject / predicate /
+-- object rows extracted from user messages by a background model pass. A
+-- correction supersedes the current row instead of editing text, so the
+-- history of a fact is kept.
+CREATE TABLE facts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Owner of the memory (the main agent for /topic threads)
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    -- Normalized keys: lowercase words joined by underscores ("user", "dog_rex")
+    subject TEXT NOT NULL,
+    predicate TEXT NOT NULL,
+    object TEXT NOT NULL,
+    -- 0-1, how sure the extraction was
+    confidence REAL NOT NULL DEFAULT 1.0,
+    -- Recall message the fact was learned from
+    source_message_id UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Set when a later fact replaced this one
+    superseded_at TIMESTAMPTZ
+);
+
+-- One current value per subject and predicate
+CREATE UNIQUE INDEX idx_facts_current ON facts (agent_id, subject, predicate)
+    WHERE superseded_at IS NULL;
+CREATE INDEX idx_facts_agent ON facts (agent_id, updated_at DESC);
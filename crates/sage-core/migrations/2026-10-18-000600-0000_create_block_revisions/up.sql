@@ -0,0 +1,20 @@
+-- Every edit of a core memory block (memory/block.rs), so a bad edit can be
+-- rolled back with memory_undo or the admin API. Each row holds the block's
+-- value before and after the edit and what made it.
+CREATE TABLE block_revisions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Owner of the block, as in blocks.agent_id
+    agent_id TEXT NOT NULL,
+    label VARCHAR NOT NULL,
+    -- The block's version after the edit
+    version INT NOT NULL,
+    previous_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    -- Tool that made the edit (memory_replace, ...), or admin, import, ...
+    source TEXT NOT NULL,
+    -- Arguments of the tool call, if a tool made the edit
+    tool_args JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_block_revisions_block ON block_revisions (agent_id, label, version DESC);
@@ -0,0 +1,20 @@
+-- Promises Sage makes in conversation ("I'll look into that and get back to
+-- you"), detected after each turn (commitments.rs). Each one gets a
+-- follow-up task, so Sage gets back to the user after the turn has ended.
+CREATE TABLE commitments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    -- What Sage committed to do, in a few words
+    description TEXT NOT NULL,
+    -- Sage's message that made the promise
+    promise TEXT NOT NULL,
+    due_at TIMESTAMPTZ NOT NULL,
+    -- Scheduled follow-up turn
+    task_id UUID REFERENCES scheduled_tasks(id) ON DELETE SET NULL,
+    -- 'open', 'kept' (followed up) or 'dropped'
+    status VARCHAR(20) NOT NULL DEFAULT 'open',
+    resolved_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_commitments_agent_status ON commitments(agent_id, status);
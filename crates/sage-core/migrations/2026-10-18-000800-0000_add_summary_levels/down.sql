@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_summaries_unrolled;
+ALTER TABLE summaries DROP COLUMN IF EXISTS rolled_into;
+ALTER TABLE summaries DROP COLUMN IF EXISTS level;
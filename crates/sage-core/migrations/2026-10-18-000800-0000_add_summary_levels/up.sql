@@ -0,0 +1,22 @@
+-- Hierarchical compaction (memory/compaction.rs): compaction writes level-1
+-- summaries, and every ROLLUP_SIZE unrolled summaries of one level are
+-- rolled into one summary a level up. The prompt shows the summaries no
+-- rollup has absorbed yet, so its summary text grows with the log of the
+-- history instead of with one ever-longer chain.
+ALTER TABLE summaries ADD COLUMN level INT NOT NULL DEFAULT 1;
+
+-- The higher-level summary that absorbed this one
+ALTER TABLE summaries ADD COLUMN rolled_into UUID REFERENCES summaries(id) ON DELETE SET NULL;
+
+-- Summaries written before levels each built on the previous one, so the
+-- latest already holds the older ones
+UPDATE summaries s
+SET rolled_into = latest.id
+FROM (
+    SELECT DISTINCT ON (agent_id) id, agent_id
+    FROM summaries
+    ORDER BY agent_id, to_sequence_id DESC, created_at DESC
+) latest
+WHERE s.agent_id = latest.agent_id AND s.id <> latest.id;
+
+CREATE INDEX idx_summaries_unrolled ON summaries(agent_id, level) WHERE rolled_into IS NULL;
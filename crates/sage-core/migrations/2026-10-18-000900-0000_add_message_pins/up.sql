@@ -0,0 +1,5 @@
+-- Pinned messages are kept out of compaction: they stay in context verbatim
+-- instead of being folded into a summary (pin_message tool, /pin)
+ALTER TABLE messages ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX idx_messages_pinned ON messages(agent_id, sequence_id) WHERE pinned;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_passages_namespace;
+ALTER TABLE passages DROP COLUMN IF EXISTS namespace;
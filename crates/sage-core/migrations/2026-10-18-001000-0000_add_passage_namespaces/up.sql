@@ -0,0 +1,5 @@
+-- Archival namespaces ("personal", "work", "project-apollo") keep unrelated
+-- memories apart in retrieval. NULL = no namespace, visible in all of them.
+ALTER TABLE passages ADD COLUMN namespace TEXT;
+
+CREATE INDEX idx_passages_namespace ON passages(agent_id, namespace) WHERE namespace IS NOT NULL;
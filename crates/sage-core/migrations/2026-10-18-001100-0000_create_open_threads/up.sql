@@ -0,0 +1,18 @@
+-- Open threads (memory/open_threads.rs): questions the user asked and topics
+-- left unresolved in messages that compaction summarized away. Extracted
+-- during compaction and shown with the summary until the agent resolves them.
+CREATE TABLE open_threads (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Conversation the messages belonged to (a /topic thread has its own)
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    -- The question or topic, one line
+    content TEXT NOT NULL,
+    -- When the compacted messages it came from started
+    raised_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Set when it was answered or settled
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_open_threads_open ON open_threads (agent_id, created_at)
+    WHERE resolved_at IS NULL;
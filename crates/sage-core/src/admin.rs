@@ -0,0 +1,548 @@
+//! Admin Introspection
+//!
+//! Read-only queries behind the `/admin` HTTP routes, so operators can debug
+//! Sage without psql: the agents (chat contexts) with their message counts,
+//! an agent's core memory blocks with their revisions, and the tail of its
+//! conversation. Pending
+//! schedules come from `SchedulerDb`, and compaction is triggered through the
+//! agent's `MemoryManager` (see `http_server`).
+//!
+//! The routes are only served when `HTTP_AUTH_TOKEN` is set. The
+//! `sage-admin` binary offers the same views from a shell, plus block edits.
+//!
+//! Production turns can also be exported as GEPA/eval examples
+//! (`production_examples`), anonymized (see `anonymize`) so real data can be
+//! used without sending personal details to the judge model's provider.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::anonymize::Anonymizer;
+use crate::db::DbConn;
+use crate::schema::{block_revisions, blocks, chat_contexts, messages, turn_events};
+use crate::threads;
+
+/// Messages returned by `recent_messages` unless asked otherwise
+pub const DEFAULT_MESSAGE_LIMIT: i64 = 50;
+
+/// Most messages one request can tail
+pub const MAX_MESSAGE_LIMIT: i64 = 500;
+
+/// Turns exported by `production_examples` unless asked otherwise
+pub const DEFAULT_EXAMPLE_LIMIT: i64 = 20;
+
+/// Most turns one export can contain
+pub const MAX_EXAMPLE_LIMIT: i64 = 200;
+
+/// Messages before a turn given as its `recent_conversation`
+const EXAMPLE_CONTEXT_MESSAGES: i64 = 10;
+
+/// An agent with its chat and activity
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentSummary {
+    pub id: Uuid,
+    /// Chat identifier (Signal UUID, `group:<id>`, pubkey, ...)
+    pub identifier: String,
+    pub context_type: String,
+    pub display_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub message_count: i64,
+    pub last_message_at: Option<DateTime<Utc>>,
+}
+
+/// A core memory block
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct BlockView {
+    pub label: String,
+    pub description: Option<String>,
+    pub value: String,
+    pub char_limit: i32,
+    pub read_only: bool,
+    pub version: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A recorded edit of a core memory block
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct RevisionView {
+    /// The block's version after the edit
+    pub version: i32,
+    pub previous_value: String,
+    pub value: String,
+    /// Tool that made the edit, or `admin`, `import`, ...
+    pub source: String,
+    pub tool_args: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Revisions returned by `block_revisions` unless asked otherwise
+pub const DEFAULT_REVISION_LIMIT: i64 = 20;
+
+/// A stored conversation message
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct MessageView {
+    pub id: Uuid,
+    pub sequence_id: i64,
+    pub role: String,
+    pub content: String,
+    pub attachment_text: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A production turn in the GEPA trainset format (`examples/gepa`), with
+/// what Sage actually did instead of a hand-written expectation
+#[derive(Debug, Clone, Serialize)]
+pub struct GepaExample {
+    pub id: String,
+    pub category: String,
+    pub input: String,
+    pub current_time: String,
+    pub persona_block: String,
+    pub human_block: String,
+    pub memory_metadata: String,
+    pub previous_context_summary: String,
+    pub recent_conversation: String,
+    pub is_first_time_user: bool,
+    /// Left for a curator to fill in
+    pub expected_behavior: String,
+    pub observed_response: ObservedResponse,
+}
+
+/// Messages Sage sent and tools it called in a turn
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ObservedResponse {
+    pub messages: Vec<String>,
+    pub tool_calls: Vec<String>,
+}
+
+/// A GEPA trainset file (`examples/gepa/trainset.json` layout)
+#[derive(Debug, Clone, Serialize)]
+pub struct GepaTrainset {
+    pub description: String,
+    pub version: String,
+    pub examples: Vec<GepaExample>,
+}
+
+impl GepaTrainset {
+    pub fn production(examples: Vec<GepaExample>) -> Self {
+        Self {
+            description: "Anonymized production turns; fill in expected_behavior before use"
+                .to_string(),
+            version: "2.0".to_string(),
+            examples,
+        }
+    }
+}
+
+impl GepaExample {
+    /// Every free-text field, for anonymization
+    fn texts_mut(&mut self) -> Vec<&mut String> {
+        let mut texts = vec![
+            &mut self.input,
+            &mut self.persona_block,
+            &mut self.human_block,
+            &mut self.recent_conversation,
+        ];
+        texts.extend(self.observed_response.messages.iter_mut());
+        texts
+    }
+}
+
+/// Replace personal details in `examples` with pseudonyms, consistently
+/// across all of them. `names` are known people (display names, senders).
+pub fn anonymize_examples(examples: &mut [GepaExample], names: &[String]) {
+    let mut anonymizer = Anonymizer::new();
+    for name in names {
+        anonymizer.add_name(name);
+    }
+    for example in examples.iter_mut() {
+        for text in example.texts_mut() {
+            anonymizer.seed_from(text);
+        }
+    }
+    for example in examples.iter_mut() {
+        for text in example.texts_mut() {
+            *text = anonymizer.anonymize(text);
+        }
+    }
+}
+
+pub struct AdminDb {
+    conn: Arc<DbConn>,
+}
+
+impl AdminDb {
+    /// Create a new AdminDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Every agent, most recently active first
+    pub fn list_agents(&self) -> Result<Vec<AgentSummary>> {
+        self.conn.run(|conn| {
+            let contexts: Vec<(Uuid, String, String, Option<String>, DateTime<Utc>)> =
+                chat_contexts::table
+                    .select((
+                        chat_contexts::id,
+                        chat_contexts::signal_identifier,
+                        chat_contexts::context_type,
+                        chat_contexts::display_name,
+                        chat_contexts::created_at,
+                    ))
+                    .load(conn)
+                    .context("Failed to load chat contexts")?;
+            let activity: HashMap<Uuid, (i64, Option<DateTime<Utc>>)> = messages::table
+                .group_by(messages::agent_id)
+                .select((
+                    messages::agent_id,
+                    diesel::dsl::count_star(),
+                    diesel::dsl::max(messages::created_at),
+                ))
+                .load::<(Uuid, i64, Option<DateTime<Utc>>)>(conn)
+                .context("Failed to count messages")?
+                .into_iter()
+                .map(|(agent_id, count, last)| (agent_id, (count, last)))
+                .collect();
+
+            let mut agents: Vec<AgentSummary> = contexts
+                .into_iter()
+                .map(|(id, identifier, context_type, display_name, created_at)| {
+                    let (message_count, last_message_at) =
+                        activity.get(&id).copied().unwrap_or((0, None));
+                    AgentSummary {
+                        id,
+                        identifier,
+                        context_type,
+                        display_name,
+                        created_at,
+                        message_count,
+                        last_message_at,
+                    }
+                })
+                .collect();
+            agents.sort_by(|a, b| {
+                b.last_message_at
+                    .cmp(&a.last_message_at)
+                    .then(b.created_at.cmp(&a.created_at))
+            });
+            Ok(agents)
+        })
+    }
+
+    /// Whether an agent exists
+    pub fn agent_exists(&self, agent_id: Uuid) -> Result<bool> {
+        self.conn.run(|conn| {
+            let found: Option<Uuid> = chat_contexts::table
+                .filter(chat_contexts::id.eq(agent_id))
+                .select(chat_contexts::id)
+                .first(conn)
+                .optional()
+                .context("Failed to look up agent")?;
+            Ok(found.is_some())
+        })
+    }
+
+    /// Find an agent by id, id prefix or chat identifier
+    #[allow(dead_code)]
+    pub fn resolve_agent(&self, query: &str) -> Result<Option<Uuid>> {
+        let query = query.trim();
+        if let Ok(id) = Uuid::parse_str(query) {
+            return Ok(self.agent_exists(id)?.then_some(id));
+        }
+        self.conn.run(|conn| {
+            let by_identifier: Option<Uuid> = chat_contexts::table
+                .filter(chat_contexts::signal_identifier.eq(query))
+                .select(chat_contexts::id)
+                .first(conn)
+                .optional()
+                .context("Failed to look up agent")?;
+            if by_identifier.is_some() || query.len() < 4 {
+                return Ok(by_identifier);
+            }
+            let ids: Vec<Uuid> = chat_contexts::table
+                .select(chat_contexts::id)
+                .load(conn)
+                .context("Failed to load agents")?;
+            let mut matches = ids
+                .into_iter()
+                .filter(|id| id.to_string().starts_with(&query.to_lowercase()));
+            match (matches.next(), matches.next()) {
+                (Some(id), None) => Ok(Some(id)),
+                (Some(_), Some(_)) => anyhow::bail!("'{}' matches more than one agent", query),
+                _ => Ok(None),
+            }
+        })
+    }
+
+    /// Agent whose core and archival memory `agent_id` uses: threads share
+    /// their main chat's
+    pub fn memory_owner(&self, agent_id: Uuid) -> Result<Uuid> {
+        self.conn.run(|conn| {
+            let identifier: Option<String> = chat_contexts::table
+                .filter(chat_contexts::id.eq(agent_id))
+                .select(chat_contexts::signal_identifier)
+                .first(conn)
+                .optional()
+                .context("Failed to look up agent")?;
+            let owner = match identifier.as_deref().map(threads::split_thread) {
+                Some((chat, Some(_))) => chat_contexts::table
+                    .filter(chat_contexts::signal_identifier.eq(chat))
+                    .select(chat_contexts::id)
+                    .first(conn)
+                    .optional()
+                    .context("Failed to look up main chat")?
+                    .unwrap_or(agent_id),
+                _ => agent_id,
+            };
+            Ok(owner)
+        })
+    }
+
+    /// An agent's core memory blocks. Threads share their main chat's blocks,
+    /// so a thread agent shows those.
+    pub fn blocks(&self, agent_id: Uuid) -> Result<Vec<BlockView>> {
+        let owner = self.memory_owner(agent_id)?;
+        self.conn.run(|conn| {
+            blocks::table
+                .filter(blocks::agent_id.eq(owner.to_string()))
+                .select((
+                    blocks::label,
+                    blocks::description,
+                    blocks::value,
+                    blocks::char_limit,
+                    blocks::read_only,
+                    blocks::version,
+                    blocks::updated_at,
+                ))
+                .order(blocks::label.asc())
+                .load(conn)
+                .context("Failed to load blocks")
+        })
+    }
+
+    /// A block's recorded edits, newest first
+    pub fn block_revisions(
+        &self,
+        agent_id: Uuid,
+        label: &str,
+        limit: i64,
+    ) -> Result<Vec<RevisionView>> {
+        let owner = self.memory_owner(agent_id)?;
+        self.conn.run(|conn| {
+            block_revisions::table
+                .filter(block_revisions::agent_id.eq(owner.to_string()))
+                .filter(block_revisions::label.eq(label))
+                .select((
+                    block_revisions::version,
+                    block_revisions::previous_value,
+                    block_revisions::value,
+                    block_revisions::source,
+                    block_revisions::tool_args,
+                    block_revisions::created_at,
+                ))
+                .order((
+                    block_revisions::version.desc(),
+                    block_revisions::created_at.desc(),
+                ))
+                .limit(limit.clamp(1, MAX_MESSAGE_LIMIT))
+                .load(conn)
+                .context("Failed to load block revisions")
+        })
+    }
+
+    /// Replace a core memory block's value, recorded as an `admin` revision.
+    /// Read-only blocks and values over the block's limit are refused.
+    #[allow(dead_code)]
+    pub fn set_block(&self, agent_id: Uuid, label: &str, value: &str) -> Result<BlockView> {
+        let owner = self.memory_owner(agent_id)?;
+        let block = self
+            .blocks(owner)?
+            .into_iter()
+            .find(|block| block.label == label)
+            .ok_or_else(|| anyhow::anyhow!("Block '{}' not found", label))?;
+        if block.read_only {
+            anyhow::bail!("Block '{}' is read-only", label);
+        }
+        let chars = value.chars().count();
+        if chars > block.char_limit as usize {
+            anyhow::bail!(
+                "Value is {} characters; block '{}' allows {}",
+                chars,
+                label,
+                block.char_limit
+            );
+        }
+
+        let owner = owner.to_string();
+        self.conn.run(|conn| {
+            conn.transaction(|conn| {
+                let updated: BlockView = diesel::update(
+                    blocks::table
+                        .filter(blocks::agent_id.eq(&owner))
+                        .filter(blocks::label.eq(label)),
+                )
+                .set(blocks::value.eq(value))
+                .returning((
+                    blocks::label,
+                    blocks::description,
+                    blocks::value,
+                    blocks::char_limit,
+                    blocks::read_only,
+                    blocks::version,
+                    blocks::updated_at,
+                ))
+                .get_result(conn)
+                .context("Failed to update block")?;
+                diesel::insert_into(block_revisions::table)
+                    .values((
+                        block_revisions::agent_id.eq(&owner),
+                        block_revisions::label.eq(label),
+                        block_revisions::version.eq(updated.version),
+                        block_revisions::previous_value.eq(&block.value),
+                        block_revisions::value.eq(value),
+                        block_revisions::source.eq("admin"),
+                    ))
+                    .execute(conn)
+                    .context("Failed to record block revision")?;
+                Ok(updated)
+            })
+        })
+    }
+
+    /// The agent's last `limit` finished turns as anonymized GEPA examples,
+    /// newest first. Core memory is as it is now, not as it was then.
+    pub fn production_examples(&self, agent_id: Uuid, limit: i64) -> Result<Vec<GepaExample>> {
+        let blocks = self.blocks(agent_id)?;
+        let block = |label: &str| {
+            blocks
+                .iter()
+                .find(|b| b.label == label)
+                .map(|b| b.value.clone())
+                .unwrap_or_default()
+        };
+        let (persona_block, human_block) = (block("persona"), block("human"));
+
+        self.conn.run(|conn| {
+            let names: Vec<String> = chat_contexts::table
+                .filter(chat_contexts::id.eq(agent_id))
+                .select(chat_contexts::display_name)
+                .first::<Option<String>>(conn)
+                .optional()
+                .context("Failed to look up agent")?
+                .flatten()
+                .into_iter()
+                .collect();
+
+            let turn_ids: Vec<Uuid> = turn_events::table
+                .filter(turn_events::agent_id.eq(agent_id))
+                .filter(turn_events::kind.eq("end"))
+                .order(turn_events::created_at.desc())
+                .limit(limit.clamp(1, MAX_EXAMPLE_LIMIT))
+                .select(turn_events::turn_id)
+                .load(conn)
+                .context("Failed to load turns")?;
+
+            let mut examples = Vec::new();
+            for turn_id in turn_ids {
+                let events: Vec<(String, String, serde_json::Value, DateTime<Utc>)> =
+                    turn_events::table
+                        .filter(turn_events::turn_id.eq(turn_id))
+                        .order(turn_events::seq.asc())
+                        .select((
+                            turn_events::kind,
+                            turn_events::content,
+                            turn_events::metadata,
+                            turn_events::created_at,
+                        ))
+                        .load(conn)
+                        .context("Failed to load turn events")?;
+                let Some((_, input, _, started_at)) =
+                    events.iter().find(|(kind, ..)| kind == "input").cloned()
+                else {
+                    continue;
+                };
+                let mut observed = ObservedResponse::default();
+                for (kind, content, metadata, _) in &events {
+                    match kind.as_str() {
+                        "output" => observed.messages.push(content.clone()),
+                        "tool_call" => observed
+                            .tool_calls
+                            .push(metadata["tool"].as_str().unwrap_or("unknown").to_string()),
+                        _ => {}
+                    }
+                }
+
+                let mut before: Vec<(String, String, DateTime<Utc>)> = messages::table
+                    .filter(messages::agent_id.eq(agent_id))
+                    .filter(messages::created_at.lt(started_at))
+                    .filter(messages::role.ne("tool"))
+                    .order(messages::sequence_id.desc())
+                    .limit(EXAMPLE_CONTEXT_MESSAGES)
+                    .select((messages::role, messages::content, messages::created_at))
+                    .load(conn)
+                    .context("Failed to load conversation")?;
+                before.reverse();
+                let recent_conversation = before
+                    .iter()
+                    .map(|(role, content, at)| {
+                        format!(
+                            "[{} @ {} UTC]: {}",
+                            role,
+                            at.format("%m/%d/%Y %H:%M:%S"),
+                            content
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                examples.push(GepaExample {
+                    id: format!("prod_{}", &turn_id.to_string()[..8]),
+                    category: "production".to_string(),
+                    input,
+                    current_time: format!("{} UTC", started_at.format("%m/%d/%Y %H:%M:%S (%A)")),
+                    persona_block: persona_block.clone(),
+                    human_block: human_block.clone(),
+                    memory_metadata: String::new(),
+                    previous_context_summary: String::new(),
+                    recent_conversation,
+                    is_first_time_user: human_block.trim().is_empty(),
+                    expected_behavior: String::new(),
+                    observed_response: observed,
+                });
+            }
+
+            anonymize_examples(&mut examples, &names);
+            Ok(examples)
+        })
+    }
+
+    /// The agent's last `limit` messages, oldest first
+    pub fn recent_messages(&self, agent_id: Uuid, limit: i64) -> Result<Vec<MessageView>> {
+        self.conn.run(|conn| {
+            let mut rows: Vec<MessageView> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .select((
+                    messages::id,
+                    messages::sequence_id,
+                    messages::role,
+                    messages::content,
+                    messages::attachment_text,
+                    messages::created_at,
+                ))
+                .order(messages::sequence_id.desc())
+                .limit(limit.clamp(1, MAX_MESSAGE_LIMIT))
+                .load(conn)
+                .context("Failed to load messages")?;
+            rows.reverse();
+            Ok(rows)
+        })
+    }
+}
@@ -5,24 +5,53 @@
 //! - Separate preferences
 //! - Separate scheduled tasks
 //! - Separate workspace directory
+//!
+//! A conversation thread (`/topic`, see `threads`) gets its own agent too,
+//! with its own history but the main agent's core memory.
 
 use anyhow::Result;
 use chrono::Utc;
+use chrono_tz::Tz;
 use diesel::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{debug, info};
+use tokio::sync::{Mutex, Notify};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
-use crate::config::Config;
-use crate::memory::MemoryManager;
-use crate::sage_agent::{SageAgent, ToolRegistry};
+use crate::capabilities::{CapabilitiesTool, Deployment};
+use crate::commitments::CommitmentDb;
+use crate::config::{Config, MessengerType};
+use crate::db::DbConn;
+use crate::expenses::{ExpenseDb, SpendingReportTool};
+use crate::export::{ExportConversationTool, ExportDb};
+use crate::guardrails::SecretScanner;
+use crate::itinerary::{AddItineraryTool, ItineraryDb, TravelPlansTool};
+use crate::maintenance;
+use crate::memory::{preference_keys, BlockManager, MemoryManager, RerankMode};
+use crate::messenger::{AttachmentOutbox, IncomingMessage, ReactionOutbox};
+use crate::model_router::ModelRouter;
+use crate::occasions;
+use crate::polls::{ClosePollTool, CreatePollTool, PollDb};
+use crate::research::{DeepResearchTool, WebFetchTool};
+use crate::roles::{RolePolicy, Roles};
+use crate::sage_agent::{SageAgent, ToolConcurrencyLimits, ToolRegistry};
 use crate::scheduler::SchedulerDb;
 use crate::scheduler_tools;
 use crate::schema::chat_contexts;
-use crate::shell_tool::ShellTool;
+use crate::shell_session::{
+    ShellJobKillTool, ShellJobStatusTool, ShellSessionManager, ShellSessionStartTool,
+};
+use crate::shell_tool::{ShellConfig, ShellTool};
+use crate::status_report;
+use crate::templates::{self, TemplateVars};
+use crate::threads;
+use crate::todos::{AddTodoTool, CompleteTodoTool, ListTodosTool, TodoDb};
+use crate::tools::WebSearchConfig;
+use crate::turn_journal::{ExplainLastActionTool, TurnJournal, TurnTranscriptTool};
+use crate::workspace_snapshot::{WorkspaceRollbackTool, WorkspaceSnapshots};
 
 /// Row from chat_contexts table
 #[derive(Queryable, Selectable, Debug, Clone)]
@@ -49,19 +78,167 @@ struct NewChatContext<'a> {
 
 /// Context type for chat
 #[derive(Debug, Clone, Copy, PartialEq)]
-#[allow(dead_code)]
 pub enum ContextType {
     Direct,
     Group,
+    /// A direct chat with an ephemeral role (see `roles`): only the last
+    /// `GUEST_WINDOW_MESSAGES` are in context, no long-term memory is
+    /// written, and everything is purged after `GUEST_RETENTION_HOURS`
+    Guest,
 }
 
 impl ContextType {
+    /// Group conversations are identified by a `group:` prefix
+    pub fn for_identifier(identifier: &str) -> Self {
+        if crate::messenger::group_id(identifier).is_some() {
+            ContextType::Group
+        } else {
+            ContextType::Direct
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             ContextType::Direct => "direct",
             ContextType::Group => "group",
+            ContextType::Guest => "guest",
+        }
+    }
+}
+
+/// Per-agent inbox of incoming messages waiting to be processed.
+///
+/// Messages that arrive while the agent is mid-turn accumulate here; the
+/// agent's worker drains them once the turn ends (optionally coalesced into a
+/// single turn), or earlier if the turn is interrupted.
+///
+/// It also tracks whether a turn is running, so an owner's `/reset` can stop
+/// it (see `commands`).
+#[derive(Default)]
+pub struct AgentInbox {
+    pending: std::sync::Mutex<VecDeque<IncomingMessage>>,
+    notify: Notify,
+    running: AtomicBool,
+    stop_requested: AtomicBool,
+}
+
+impl AgentInbox {
+    /// Queue a message and wake the agent's worker
+    pub fn push(&self, msg: IncomingMessage) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.push_back(msg);
+        }
+        self.notify.notify_one();
+    }
+
+    /// Take the oldest queued message
+    pub fn pop(&self) -> Option<IncomingMessage> {
+        self.pending.lock().ok()?.pop_front()
+    }
+
+    /// Take every queued message, oldest first
+    pub fn drain(&self) -> Vec<IncomingMessage> {
+        self.pending
+            .lock()
+            .map(|mut pending| pending.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether any messages are waiting
+    pub fn has_pending(&self) -> bool {
+        self.pending
+            .lock()
+            .map(|pending| !pending.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// Number of queued messages
+    pub fn pending_count(&self) -> usize {
+        self.pending
+            .lock()
+            .map(|pending| pending.len())
+            .unwrap_or(0)
+    }
+
+    /// Wait until a message is pushed (returns immediately if one was pushed
+    /// since the last wait)
+    pub async fn wait(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Mark a turn as started or finished. Finishing clears any stop request.
+    pub fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::SeqCst);
+        if !running {
+            self.stop_requested.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Whether a turn is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Drop the queued messages and ask the running turn (if any) to stop
+    /// after its current step. Returns the dropped messages and whether a
+    /// turn was running.
+    pub fn request_stop(&self) -> (Vec<IncomingMessage>, bool) {
+        let running = self.is_running();
+        if running {
+            self.stop_requested.store(true, Ordering::SeqCst);
+        }
+        (self.drain(), running)
+    }
+
+    /// Whether the running turn was asked to stop
+    pub fn stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::SeqCst)
+    }
+}
+
+/// Merge several queued messages from the same conversation into one turn.
+///
+/// Texts are joined in order, attachments and durable inbox ids concatenated,
+/// the most recent timestamp, message id and reply context kept, mentions
+/// merged, and the first quote kept. The batch counts as an edit if any
+/// message in it is one.
+/// Returns None for an empty batch.
+pub fn coalesce_messages(messages: Vec<IncomingMessage>) -> Option<IncomingMessage> {
+    let mut iter = messages.into_iter();
+    let mut merged = iter.next()?;
+
+    for msg in iter {
+        if !msg.message.trim().is_empty() {
+            if merged.message.trim().is_empty() {
+                merged.message = msg.message;
+            } else {
+                merged.message = format!("{}\n{}", merged.message, msg.message);
+            }
+        }
+        merged.attachments.extend(msg.attachments);
+        merged.timestamp = merged.timestamp.max(msg.timestamp);
+        merged.inbox_ids.extend(msg.inbox_ids);
+        if msg.reply_context.is_some() {
+            merged.reply_context = msg.reply_context;
+        }
+        if msg.source_name.is_some() {
+            merged.source_name = msg.source_name;
+        }
+        if msg.message_id.is_some() {
+            merged.message_id = msg.message_id;
+        }
+        if merged.quote.is_none() {
+            merged.quote = msg.quote;
+        }
+        for mention in msg.mentions {
+            if !merged.mentions.contains(&mention) {
+                merged.mentions.push(mention);
+            }
         }
+        merged.edited |= msg.edited;
     }
+
+    Some(merged)
 }
 
 /// Cached agent with its tools and metadata
@@ -80,22 +257,69 @@ pub struct AgentManager {
     maple_api_key: String,
     maple_model: String,
     maple_embedding_model: String,
+    /// How memory search results are re-ranked
+    memory_rerank: RerankMode,
     /// Brave API key for web search
     brave_api_key: Option<String>,
+    /// Web search options
+    web_search_config: WebSearchConfig,
+    /// Shell tool options
+    shell_config: ShellConfig,
     /// Base workspace path
     workspace_base: PathBuf,
     /// Scheduler database (shared across all agents)
     scheduler_db: Arc<SchedulerDb>,
+    /// Expense database (shared across all agents)
+    expense_db: Arc<ExpenseDb>,
+    /// Flights and hotel stays (shared across all agents)
+    itinerary_db: Arc<ItineraryDb>,
+    /// Shared todo lists (shared across all agents)
+    todo_db: Arc<TodoDb>,
+    /// Promises awaiting follow-up (shared across all agents)
+    commitment_db: Arc<CommitmentDb>,
+    /// Group polls (shared across all agents)
+    poll_db: Arc<PollDb>,
+    /// Export passphrase keys and export queries (shared across all agents)
+    export_db: Arc<ExportDb>,
+    /// Turn journal (shared across all agents)
+    turn_journal: Arc<TurnJournal>,
     /// Database connection for chat_contexts
-    db_conn: Arc<std::sync::Mutex<diesel::PgConnection>>,
+    db_conn: Arc<DbConn>,
+    /// Shared by all agents to scrub secrets from tool output
+    secret_scanner: Arc<SecretScanner>,
+    /// Shared by all agents' tool registries
+    tool_limits: Arc<ToolConcurrencyLimits>,
+    /// Workspace snapshots kept per agent (0 disables snapshots)
+    workspace_snapshot_keep: usize,
+    /// Cron schedule of the self-maintenance routine (None = off)
+    self_maintenance_cron: Option<String>,
+    /// Cron schedule of the owners' status report (None = off)
+    status_report_cron: Option<String>,
+    /// Roles of users and groups: owners get the status report, policies
+    /// limit tools, budget and proactive messages
+    roles: Roles,
+    /// Append cited web sources to messages
+    cite_sources: bool,
+    /// Extend the turn's context between steps instead of rebuilding it
+    partial_context_refresh: bool,
+    /// Sends simple turns to a cheaper model (None = always the flagship)
+    model_router: Option<ModelRouter>,
+    /// Messages a guest context keeps in the prompt
+    guest_window_messages: usize,
+    /// Local hour of birthday and anniversary check-ins (None = off)
+    occasion_checkin_hour: Option<u32>,
+    /// What the deployment offers, for the capabilities tool
+    deployment: Arc<Deployment>,
     /// Cached agents
     agents: Mutex<HashMap<Uuid, CachedAgent>>,
+    /// Per-agent inboxes of messages waiting to be processed
+    inboxes: std::sync::Mutex<HashMap<Uuid, Arc<AgentInbox>>>,
 }
 
 impl AgentManager {
     /// Create a new agent manager
     pub fn new(config: &Config, scheduler_db: Arc<SchedulerDb>) -> Result<Self> {
-        let conn = diesel::PgConnection::establish(&config.database_url)?;
+        let conn = DbConn::connect(&config.database_url)?;
 
         // Ensure workspace base directory exists
         let workspace_base = PathBuf::from(&config.workspace_path);
@@ -106,17 +330,52 @@ impl AgentManager {
             .clone()
             .ok_or_else(|| anyhow::anyhow!("MAPLE_API_KEY not set"))?;
 
+        let secret_scanner = Arc::new(SecretScanner::from_config(config));
+        info!(
+            "Secret scanner: {} configured secret(s) redacted from tool output",
+            secret_scanner.secret_count()
+        );
+
         Ok(Self {
             database_url: config.database_url.clone(),
             maple_api_url: config.maple_api_url.clone(),
             maple_api_key,
             maple_model: config.maple_model.clone(),
             maple_embedding_model: config.maple_embedding_model.clone(),
+            memory_rerank: config.memory_rerank,
             brave_api_key: config.brave_api_key.clone(),
+            web_search_config: config.web_search_config(),
+            shell_config: config.shell_config(),
             workspace_base,
             scheduler_db,
-            db_conn: Arc::new(std::sync::Mutex::new(conn)),
+            expense_db: Arc::new(ExpenseDb::connect(&config.database_url)?),
+            itinerary_db: Arc::new(ItineraryDb::connect(&config.database_url)?),
+            todo_db: Arc::new(TodoDb::connect(
+                &config.database_url,
+                config.messenger_type == MessengerType::Marmot,
+            )?),
+            commitment_db: Arc::new(CommitmentDb::connect(&config.database_url)?),
+            poll_db: Arc::new(PollDb::connect(
+                &config.database_url,
+                config.messenger_type == MessengerType::Marmot,
+            )?),
+            export_db: Arc::new(ExportDb::connect(&config.database_url)?),
+            turn_journal: Arc::new(TurnJournal::connect(&config.database_url)?),
+            db_conn: Arc::new(conn),
+            secret_scanner,
+            tool_limits: Arc::new(ToolConcurrencyLimits::new(&config.tool_concurrency_limits)),
+            workspace_snapshot_keep: config.workspace_snapshot_keep,
+            self_maintenance_cron: config.self_maintenance_cron.clone(),
+            status_report_cron: config.status_report_cron.clone(),
+            roles: config.roles(),
+            cite_sources: config.cite_sources,
+            partial_context_refresh: config.partial_context_refresh,
+            model_router: ModelRouter::from_config(config),
+            guest_window_messages: config.guest_window_messages,
+            occasion_checkin_hour: config.occasion_checkin_hour,
+            deployment: Arc::new(Deployment::from_config(config)),
             agents: Mutex::new(HashMap::new()),
+            inboxes: std::sync::Mutex::new(HashMap::new()),
         })
     }
 
@@ -124,12 +383,21 @@ impl AgentManager {
     ///
     /// For direct messages, signal_identifier is the user's UUID
     /// For group messages, signal_identifier is the group ID
+    /// For a conversation thread, it is `<identifier>#topic:<name>`
     pub async fn get_or_create_agent(
         &self,
         signal_identifier: &str,
         context_type: ContextType,
         display_name: Option<&str>,
     ) -> Result<(Uuid, Arc<Mutex<SageAgent>>)> {
+        // Direct chats with an ephemeral role are guest contexts
+        let context_type = match context_type {
+            ContextType::Direct if self.roles.policy_for(signal_identifier).ephemeral => {
+                ContextType::Guest
+            }
+            other => other,
+        };
+
         // First, look up or create the chat context
         let context = self.get_or_create_context(signal_identifier, context_type, display_name)?;
         let agent_id = context.id;
@@ -148,7 +416,16 @@ impl AgentManager {
             "Creating new agent for {} (id: {})",
             signal_identifier, agent_id
         );
-        let agent = self.create_agent(agent_id).await?;
+        let core = match threads::split_thread(signal_identifier) {
+            (chat, Some(_)) => {
+                Some(Box::pin(self.core_memory(chat, context_type, display_name)).await?)
+            }
+            (_, None) => None,
+        };
+        let guest = context_type == ContextType::Guest;
+        let agent = self
+            .create_agent(agent_id, signal_identifier, core, guest)
+            .await?;
         let agent = Arc::new(Mutex::new(agent));
 
         // Cache it
@@ -166,6 +443,23 @@ impl AgentManager {
         Ok((agent_id, agent))
     }
 
+    /// Core memory of a chat's main agent, shared with its threads
+    async fn core_memory(
+        &self,
+        signal_identifier: &str,
+        context_type: ContextType,
+        display_name: Option<&str>,
+    ) -> Result<(Uuid, BlockManager)> {
+        let (agent_id, agent) = self
+            .get_or_create_agent(signal_identifier, context_type, display_name)
+            .await?;
+        let agent = agent.lock().await;
+        let memory = agent
+            .memory()
+            .ok_or_else(|| anyhow::anyhow!("Agent {} has no memory", agent_id))?;
+        Ok((agent_id, memory.blocks().clone()))
+    }
+
     /// Look up or create a chat context in the database
     fn get_or_create_context(
         &self,
@@ -173,71 +467,116 @@ impl AgentManager {
         context_type: ContextType,
         display_name: Option<&str>,
     ) -> Result<ChatContext> {
-        let mut conn = self
-            .db_conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        // Try to find existing context
-        let existing: Option<ChatContext> = chat_contexts::table
-            .filter(chat_contexts::signal_identifier.eq(signal_identifier))
-            .select(ChatContext::as_select())
-            .first(&mut *conn)
-            .optional()?;
-
-        if let Some(ctx) = existing {
-            debug!(
-                "Found existing context for {}: {}",
-                signal_identifier, ctx.id
+        self.db_conn.run(|conn| {
+            // Try to find existing context
+            let existing: Option<ChatContext> = chat_contexts::table
+                .filter(chat_contexts::signal_identifier.eq(signal_identifier))
+                .select(ChatContext::as_select())
+                .first(conn)
+                .optional()?;
+
+            if let Some(mut ctx) = existing {
+                debug!(
+                    "Found existing context for {}: {}",
+                    signal_identifier, ctx.id
+                );
+                // A direct chat becomes a guest context (and back) when its role
+                // changes
+                let switched = matches!(
+                    (ctx.context_type.as_str(), context_type),
+                    ("direct", ContextType::Guest) | ("guest", ContextType::Direct)
+                );
+                if switched {
+                    info!("Chat context {} is now {}", ctx.id, context_type.as_str());
+                    diesel::update(chat_contexts::table.filter(chat_contexts::id.eq(ctx.id)))
+                        .set(chat_contexts::context_type.eq(context_type.as_str()))
+                        .execute(conn)?;
+                    ctx.context_type = context_type.as_str().to_string();
+                }
+                return Ok(ctx);
+            }
+
+            // Create new context
+            let new_id = Uuid::new_v4();
+            info!(
+                "Creating new chat context for {} -> {}",
+                signal_identifier, new_id
             );
-            return Ok(ctx);
-        }
 
-        // Create new context
-        let new_id = Uuid::new_v4();
-        info!(
-            "Creating new chat context for {} -> {}",
-            signal_identifier, new_id
-        );
+            let new_context = NewChatContext {
+                id: new_id,
+                signal_identifier,
+                context_type: context_type.as_str(),
+                display_name,
+            };
 
-        let new_context = NewChatContext {
-            id: new_id,
-            signal_identifier,
-            context_type: context_type.as_str(),
-            display_name,
-        };
+            diesel::insert_into(chat_contexts::table)
+                .values(&new_context)
+                .execute(conn)?;
 
-        diesel::insert_into(chat_contexts::table)
-            .values(&new_context)
-            .execute(&mut *conn)?;
-
-        // Return the created context
-        Ok(ChatContext {
-            id: new_id,
-            signal_identifier: signal_identifier.to_string(),
-            context_type: context_type.as_str().to_string(),
-            display_name: display_name.map(|s| s.to_string()),
-            created_at: Utc::now(),
-            reply_context: None,
+            // Return the created context
+            Ok(ChatContext {
+                id: new_id,
+                signal_identifier: signal_identifier.to_string(),
+                context_type: context_type.as_str().to_string(),
+                display_name: display_name.map(|s| s.to_string()),
+                created_at: Utc::now(),
+                reply_context: None,
+            })
         })
     }
 
-    /// Create a new SageAgent for the given agent_id
-    async fn create_agent(&self, agent_id: Uuid) -> Result<SageAgent> {
+    /// Create a new SageAgent for the given agent_id. A thread agent is given
+    /// its main agent's id and core memory. A guest agent gets ephemeral
+    /// memory and no routines.
+    async fn create_agent(
+        &self,
+        agent_id: Uuid,
+        signal_identifier: &str,
+        core: Option<(Uuid, BlockManager)>,
+        guest: bool,
+    ) -> Result<SageAgent> {
         // Create workspace directory for this agent
         let workspace = self.workspace_base.join(agent_id.to_string());
         std::fs::create_dir_all(&workspace)?;
         info!("Agent workspace: {}", workspace.display());
 
         // Initialize memory manager for this agent
-        let memory_manager = MemoryManager::new(
-            agent_id,
-            &self.database_url,
-            &self.maple_api_url,
-            &self.maple_api_key,
-            &self.maple_embedding_model,
-        )
-        .await?;
+        let is_thread = core.is_some();
+        let core_agent_id = core.as_ref().map_or(agent_id, |(id, _)| *id);
+        let memory_manager = match core {
+            Some((core_agent_id, core_blocks)) => MemoryManager::new_thread(
+                agent_id,
+                core_agent_id,
+                core_blocks,
+                &self.database_url,
+                &self.maple_api_url,
+                &self.maple_api_key,
+                &self.maple_embedding_model,
+            )
+            .await?
+            .with_rerank(self.memory_rerank),
+            None => MemoryManager::new(
+                agent_id,
+                &self.database_url,
+                &self.maple_api_url,
+                &self.maple_api_key,
+                &self.maple_embedding_model,
+            )
+            .await?
+            .with_rerank(self.memory_rerank),
+        };
+        let memory_manager = if guest {
+            memory_manager.with_ephemeral_window(self.guest_window_messages)
+        } else {
+            memory_manager
+        };
+        let memory_manager = if ContextType::for_identifier(signal_identifier) == ContextType::Group
+        {
+            memory_manager.with_group()
+        } else {
+            memory_manager
+        };
 
         // Get default timezone from preferences (or UTC)
         let default_timezone = memory_manager
@@ -246,8 +585,49 @@ impl AgentManager {
             .flatten()
             .unwrap_or_else(|| "UTC".to_string());
 
+        // Threads share the main agent's memory, so only main agents get the
+        // self-maintenance routine; guests' memory is purged instead
+        if let (Some(cron), false) = (&self.self_maintenance_cron, is_thread || guest) {
+            if let Err(e) =
+                maintenance::ensure_scheduled(&self.scheduler_db, agent_id, cron, &default_timezone)
+            {
+                warn!(
+                    "Failed to schedule self-maintenance for agent {}: {}",
+                    agent_id, e
+                );
+            }
+        }
+
+        if let (Some(cron), false) = (&self.status_report_cron, is_thread || guest) {
+            if self.roles.is_owner(signal_identifier) {
+                if let Err(e) = status_report::ensure_scheduled(
+                    &self.scheduler_db,
+                    agent_id,
+                    cron,
+                    &default_timezone,
+                ) {
+                    warn!(
+                        "Failed to schedule status reports for agent {}: {}",
+                        agent_id, e
+                    );
+                }
+            }
+        }
+
+        // Birthdays and anniversaries learned before this load (or before
+        // check-ins were turned on) get their yearly tasks
+        if let (Some(hour), false) = (self.occasion_checkin_hour, is_thread || guest) {
+            if let Err(e) = occasions::sync_memory(&self.scheduler_db, &memory_manager, hour) {
+                warn!(
+                    "Failed to sync occasion check-ins for agent {}: {}",
+                    agent_id, e
+                );
+            }
+        }
+
         // Create tool registry
         let mut tools = ToolRegistry::new();
+        tools.set_concurrency_limits(self.tool_limits.clone());
 
         // Register memory tools
         for tool in memory_manager.tools() {
@@ -258,7 +638,8 @@ impl AgentManager {
         tools.register(Arc::new(scheduler_tools::ScheduleTaskTool::new(
             self.scheduler_db.clone(),
             agent_id,
-            default_timezone.clone(),
+            memory_manager.db().clone(),
+            core_agent_id,
         )));
         tools.register(Arc::new(scheduler_tools::ListSchedulesTool::new(
             self.scheduler_db.clone(),
@@ -268,111 +649,439 @@ impl AgentManager {
             self.scheduler_db.clone(),
         )));
 
-        // Register shell tool with agent-specific workspace
-        tools.register(Arc::new(ShellTool::new(workspace.to_string_lossy())));
+        // Register expense tools (with this agent's ID)
+        tools.register(Arc::new(SpendingReportTool::new(
+            self.expense_db.clone(),
+            agent_id,
+            default_timezone.clone(),
+        )));
+
+        // Register travel tools (with this agent's ID)
+        tools.register(Arc::new(AddItineraryTool::new(
+            self.itinerary_db.clone(),
+            self.scheduler_db.clone(),
+            agent_id,
+            default_timezone.clone(),
+        )));
+        tools.register(Arc::new(TravelPlansTool::new(
+            self.itinerary_db.clone(),
+            agent_id,
+            default_timezone.clone(),
+        )));
+
+        // Register shared todo tools (with this agent's ID)
+        tools.register(Arc::new(AddTodoTool::new(
+            self.todo_db.clone(),
+            self.scheduler_db.clone(),
+            agent_id,
+            default_timezone.clone(),
+        )));
+        tools.register(Arc::new(ListTodosTool::new(
+            self.todo_db.clone(),
+            agent_id,
+            default_timezone.clone(),
+        )));
+        tools.register(Arc::new(CompleteTodoTool::new(
+            self.todo_db.clone(),
+            self.scheduler_db.clone(),
+            agent_id,
+        )));
+
+        // Register poll tools (with this agent's ID)
+        tools.register(Arc::new(CreatePollTool::new(
+            self.poll_db.clone(),
+            self.scheduler_db.clone(),
+            agent_id,
+            default_timezone.clone(),
+        )));
+        tools.register(Arc::new(ClosePollTool::new(
+            self.poll_db.clone(),
+            self.scheduler_db.clone(),
+            agent_id,
+        )));
+
+        // Register turn journal tools (with this agent's ID)
+        tools.register(Arc::new(TurnTranscriptTool::new(
+            self.turn_journal.clone(),
+            agent_id,
+        )));
+        tools.register(Arc::new(ExplainLastActionTool::new(
+            self.turn_journal.clone(),
+            agent_id,
+        )));
+
+        // Register shell tool with agent-specific workspace. Snapshots live
+        // outside the workspace so they aren't captured in each other.
+        let mut shell =
+            ShellTool::new(workspace.to_string_lossy()).with_config(self.shell_config.clone());
+        if self.workspace_snapshot_keep > 0 {
+            let snapshots = Arc::new(WorkspaceSnapshots::new(
+                &workspace,
+                self.workspace_base
+                    .join(".snapshots")
+                    .join(agent_id.to_string()),
+                self.workspace_snapshot_keep,
+            ));
+            shell = shell.with_snapshots(snapshots.clone());
+            tools.register(Arc::new(WorkspaceRollbackTool::new(snapshots)));
+        }
+        tools.register(Arc::new(shell));
         info!("Shell tool registered (workspace: {})", workspace.display());
 
-        // Register web search if configured
+        // Register shell session tools (background jobs live as long as the agent)
+        let shell_sessions = Arc::new(ShellSessionManager::new(&workspace));
+        tools.register(Arc::new(
+            ShellSessionStartTool::new(shell_sessions.clone())
+                .with_config(self.shell_config.clone()),
+        ));
+        tools.register(Arc::new(ShellJobStatusTool::new(shell_sessions.clone())));
+        tools.register(Arc::new(ShellJobKillTool::new(shell_sessions)));
+
+        // Register send_file and export_conversation (files are delivered by the
+        // worker after each step)
+        let outbox: AttachmentOutbox = Arc::default();
+        tools.register(Arc::new(crate::tools::SendFileTool::new(
+            &workspace,
+            outbox.clone(),
+        )));
+        tools.register(Arc::new(ExportConversationTool::new(
+            self.export_db.clone(),
+            agent_id,
+            core_agent_id,
+            &workspace,
+            outbox.clone(),
+        )));
+
+        // Register react (reactions are sent by the worker after each step)
+        let reactions: ReactionOutbox = Arc::default();
+        tools.register(Arc::new(crate::tools::ReactTool::new(reactions.clone())));
+
+        // Register web fetch (no API key needed)
+        tools.register(Arc::new(WebFetchTool::new()?));
+
+        // Register web search and deep research if configured
         if let Some(ref api_key) = self.brave_api_key {
-            tools.register(Arc::new(crate::WebSearchTool::new(api_key)?));
-            debug!("Web search tool registered");
+            tools.register(Arc::new(crate::WebSearchTool::new(
+                api_key,
+                self.web_search_config.clone(),
+            )?));
+            // Deep research saves its answer to archival memory, which
+            // guests don't write
+            if !guest {
+                tools.register(Arc::new(DeepResearchTool::new(
+                    api_key,
+                    memory_manager.archival().clone(),
+                )?));
+            }
+            debug!("Web search and deep research tools registered");
         }
 
         // Register done tool
         tools.register(Arc::new(crate::DoneTool));
 
+        // Drop the tools this conversation's role may not use
+        let role = self.roles.role(signal_identifier);
+        let policy = self.roles.policy(role);
+        if !policy.denied_tools.is_empty() {
+            tools.retain(|name| name == "done" || policy.allows_tool(name));
+            info!(
+                "Role {} for {}: denied tools {:?}",
+                role.as_str(),
+                signal_identifier,
+                policy.denied_tools
+            );
+        }
+
+        // Registered last, so it lists exactly the tools this conversation has
+        if policy.allows_tool("capabilities") {
+            let summaries = tools.summaries();
+            tools.register(Arc::new(CapabilitiesTool::new(
+                self.deployment.clone(),
+                role,
+                policy.clone(),
+                summaries,
+                memory_manager.blocks().clone(),
+                agent_id,
+            )));
+        }
+
         // Configure LLM
         SageAgent::configure_lm(&self.maple_api_url, &self.maple_api_key, &self.maple_model)
             .await?;
 
         // Create agent
-        let agent = SageAgent::new(tools, memory_manager);
+        let mut agent = SageAgent::new(tools, memory_manager)
+            .with_secret_scanner(self.secret_scanner.clone())
+            .with_attachment_outbox(outbox)
+            .with_reaction_outbox(reactions);
+        if self.cite_sources {
+            agent = agent.with_citations();
+        }
+        if self.partial_context_refresh {
+            agent = agent.with_partial_context_refresh();
+        }
+        if let Some(router) = &self.model_router {
+            agent = agent.with_model_router(router.clone());
+        }
 
         Ok(agent)
     }
 
+    /// Variables for a message template sent to this chat (see `templates`).
+    /// The weather is only looked up when the template uses it.
+    pub async fn template_vars(
+        &self,
+        signal_identifier: &str,
+        template: &str,
+    ) -> Result<TemplateVars> {
+        let (agent_id, agent) = self
+            .get_or_create_agent(signal_identifier, ContextType::Direct, None)
+            .await?;
+        let (timezone, name, location) = {
+            let agent = agent.lock().await;
+            let pref = |key: &str| {
+                agent
+                    .memory()
+                    .and_then(|m| m.get_preference(key).ok().flatten())
+            };
+            (
+                pref(preference_keys::TIMEZONE),
+                pref(preference_keys::DISPLAY_NAME),
+                pref("location"),
+            )
+        };
+
+        let tz: Tz = timezone
+            .as_deref()
+            .and_then(|tz| tz.parse().ok())
+            .unwrap_or(Tz::UTC);
+        let mut vars = TemplateVars::at(Utc::now(), tz);
+
+        let name = match name {
+            Some(name) => Some(name),
+            None => self.db_conn.run(|conn| {
+                Ok(chat_contexts::table
+                    .filter(chat_contexts::id.eq(agent_id))
+                    .select(chat_contexts::display_name)
+                    .first::<Option<String>>(conn)
+                    .optional()?
+                    .flatten())
+            })?,
+        };
+        if let Some(name) = name {
+            vars = vars.with("user_name", name);
+        }
+
+        let location = location.or_else(|| timezone.as_deref().and_then(templates::timezone_city));
+        if let (true, Some(api_key), Some(location)) = (
+            templates::uses(template, "weather"),
+            &self.brave_api_key,
+            location,
+        ) {
+            match templates::weather(api_key, &location).await {
+                Ok(Some(weather)) => vars = vars.with("weather", weather),
+                Ok(None) => debug!("No weather found for {}", location),
+                Err(e) => warn!("Weather lookup for {} failed: {}", location, e),
+            }
+        }
+        Ok(vars)
+    }
+
+    /// Local hour of birthday and anniversary check-ins (None = off)
+    pub fn occasion_checkin_hour(&self) -> Option<u32> {
+        self.occasion_checkin_hour
+    }
+
+    /// Role policy of a user, group or thread identifier
+    pub fn role_policy(&self, identifier: &str) -> RolePolicy {
+        self.roles.policy_for(identifier)
+    }
+
+    /// Get (or create) the inbox for an agent
+    pub fn inbox(&self, agent_id: Uuid) -> Arc<AgentInbox> {
+        match self.inboxes.lock() {
+            Ok(mut inboxes) => inboxes.entry(agent_id).or_default().clone(),
+            // A poisoned map only loses queueing state; hand out a fresh inbox
+            Err(_) => Arc::new(AgentInbox::default()),
+        }
+    }
+
+    /// Expense database shared by all agents (used for receipt extraction)
+    pub fn expense_db(&self) -> Arc<ExpenseDb> {
+        self.expense_db.clone()
+    }
+
+    /// Itinerary database shared by all agents (used for forwarded bookings)
+    pub fn itinerary_db(&self) -> Arc<ItineraryDb> {
+        self.itinerary_db.clone()
+    }
+
+    /// Export key store shared by all agents (used by `/export-key`)
+    pub fn export_db(&self) -> Arc<ExportDb> {
+        self.export_db.clone()
+    }
+
+    /// Poll database shared by all agents (used to count incoming votes)
+    pub fn poll_db(&self) -> Arc<PollDb> {
+        self.poll_db.clone()
+    }
+
+    /// Commitment database shared by all agents
+    pub fn commitment_db(&self) -> Arc<CommitmentDb> {
+        self.commitment_db.clone()
+    }
+
+    /// Scheduler database shared by all agents
+    pub fn scheduler_db(&self) -> Arc<SchedulerDb> {
+        self.scheduler_db.clone()
+    }
+
+    /// Turn journal shared by all agents
+    pub fn turn_journal(&self) -> Arc<TurnJournal> {
+        self.turn_journal.clone()
+    }
+
     /// Get agent_id for a signal identifier (if exists)
     #[allow(dead_code)]
     pub fn get_agent_id(&self, signal_identifier: &str) -> Result<Option<Uuid>> {
-        let mut conn = self
-            .db_conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let result: Option<Uuid> = chat_contexts::table
-            .filter(chat_contexts::signal_identifier.eq(signal_identifier))
-            .select(chat_contexts::id)
-            .first(&mut *conn)
-            .optional()?;
+        self.db_conn.run(|conn| {
+            let result: Option<Uuid> = chat_contexts::table
+                .filter(chat_contexts::signal_identifier.eq(signal_identifier))
+                .select(chat_contexts::id)
+                .first(conn)
+                .optional()?;
 
-        Ok(result)
+            Ok(result)
+        })
     }
 
     /// Get signal_identifier for an agent_id (reverse lookup for scheduled tasks)
     pub fn get_signal_identifier(&self, agent_id: Uuid) -> Result<Option<String>> {
-        let mut conn = self
-            .db_conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let result: Option<String> = chat_contexts::table
-            .filter(chat_contexts::id.eq(agent_id))
-            .select(chat_contexts::signal_identifier)
-            .first(&mut *conn)
-            .optional()?;
+        self.db_conn.run(|conn| {
+            let result: Option<String> = chat_contexts::table
+                .filter(chat_contexts::id.eq(agent_id))
+                .select(chat_contexts::signal_identifier)
+                .first(conn)
+                .optional()?;
 
-        Ok(result)
+            Ok(result)
+        })
     }
 
     /// Update the reply_context for a given identifier (e.g. Marmot group_id for a pubkey)
+    ///
+    /// Deferred while the database is down; the in-memory route is authoritative meanwhile.
     pub fn update_reply_context(&self, signal_identifier: &str, reply_ctx: &str) -> Result<()> {
-        let mut conn = self
-            .db_conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        diesel::update(
-            chat_contexts::table.filter(chat_contexts::signal_identifier.eq(signal_identifier)),
-        )
-        .set(chat_contexts::reply_context.eq(Some(reply_ctx)))
-        .execute(&mut *conn)?;
+        let signal_identifier = signal_identifier.to_string();
+        let reply_ctx = reply_ctx.to_string();
 
-        Ok(())
+        self.db_conn
+            .execute_or_defer("update_reply_context", move |conn| {
+                diesel::update(
+                    chat_contexts::table
+                        .filter(chat_contexts::signal_identifier.eq(signal_identifier)),
+                )
+                .set(chat_contexts::reply_context.eq(Some(reply_ctx)))
+                .execute(conn)
+            })
     }
 
     /// Load all reply_context mappings (identifier -> reply_context) for route restoration
     pub fn load_reply_contexts(&self) -> Result<Vec<(String, String)>> {
-        let mut conn = self
-            .db_conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
+        self.db_conn.run(|conn| {
+            let results: Vec<(String, Option<String>)> = chat_contexts::table
+                .select((
+                    chat_contexts::signal_identifier,
+                    chat_contexts::reply_context,
+                ))
+                .filter(chat_contexts::reply_context.is_not_null())
+                .load(conn)?;
 
-        let results: Vec<(String, Option<String>)> = chat_contexts::table
-            .select((
-                chat_contexts::signal_identifier,
-                chat_contexts::reply_context,
-            ))
-            .filter(chat_contexts::reply_context.is_not_null())
-            .load(&mut *conn)?;
-
-        Ok(results
-            .into_iter()
-            .filter_map(|(id, ctx)| ctx.map(|c| (id, c)))
-            .collect())
+            Ok(results
+                .into_iter()
+                .filter_map(|(id, ctx)| ctx.map(|c| (id, c)))
+                .collect())
+        })
     }
 
     /// Get all chat contexts
     #[allow(dead_code)]
     pub fn list_contexts(&self) -> Result<Vec<ChatContext>> {
-        let mut conn = self
-            .db_conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
+        self.db_conn.run(|conn| {
+            let results = chat_contexts::table
+                .select(ChatContext::as_select())
+                .load(conn)?;
 
-        let results = chat_contexts::table
-            .select(ChatContext::as_select())
-            .load(&mut *conn)?;
+            Ok(results)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messenger::IncomingAttachment;
+
+    fn msg(text: &str, timestamp: u64) -> IncomingMessage {
+        IncomingMessage {
+            source: "user-1".to_string(),
+            source_name: None,
+            message: text.to_string(),
+            attachments: vec![],
+            timestamp,
+            reply_to: "user-1".to_string(),
+            reply_context: None,
+            message_id: Some(timestamp.to_string()),
+            quote: None,
+            mentions: vec![],
+            edited: false,
+            reaction: None,
+            own_message: false,
+            inbox_ids: vec![Uuid::new_v4()],
+        }
+    }
+
+    #[test]
+    fn test_coalesce_messages() {
+        let mut second = msg("", 2);
+        second.attachments.push(IncomingAttachment {
+            file: "photo".to_string(),
+            content_type: "image/png".to_string(),
+            size: Some(10),
+        });
+        let merged = coalesce_messages(vec![msg("hey", 1), second, msg("also this", 3)]).unwrap();
+
+        assert_eq!(merged.message, "hey\nalso this");
+        assert_eq!(merged.attachments.len(), 1);
+        assert_eq!(merged.timestamp, 3);
+        assert_eq!(merged.message_id.as_deref(), Some("3"));
+        assert_eq!(merged.inbox_ids.len(), 3);
+        assert!(coalesce_messages(vec![]).is_none());
+    }
+
+    #[test]
+    fn test_context_type_for_identifier() {
+        assert_eq!(
+            ContextType::for_identifier("group:abc123=="),
+            ContextType::Group
+        );
+        assert_eq!(
+            ContextType::for_identifier("alice-uuid"),
+            ContextType::Direct
+        );
+    }
 
-        Ok(results)
+    #[test]
+    fn test_inbox_queue_order() {
+        let inbox = AgentInbox::default();
+        assert!(!inbox.has_pending());
+        inbox.push(msg("one", 1));
+        inbox.push(msg("two", 2));
+        inbox.push(msg("three", 3));
+        assert!(inbox.has_pending());
+        assert_eq!(inbox.pop().unwrap().message, "one");
+        let rest: Vec<String> = inbox.drain().into_iter().map(|m| m.message).collect();
+        assert_eq!(rest, vec!["two", "three"]);
+        assert!(inbox.pop().is_none());
     }
 }
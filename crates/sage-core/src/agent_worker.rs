@@ -0,0 +1,1038 @@
+//! Per-agent message workers
+//!
+//! Incoming messages are pushed into the agent's inbox (see
+//! `AgentManager::inbox`) and processed by one worker task per agent. Each
+//! worker handles its agent's messages strictly in order, while workers for
+//! different agents run concurrently - so one user's long tool chain no longer
+//! blocks everyone else.
+//!
+//! Messages that arrive while a turn is running wait in the inbox. When the
+//! turn ends they are coalesced into a single follow-up turn (INBOX_COALESCE),
+//! and with INBOX_INTERRUPT the running turn stops after its current step so
+//! the new messages are handled right away.
+//!
+//! Messages are backed by the durable inbox (see `durable_inbox`): a turn's
+//! rows are acked only after the turn ends, even if it panicked.
+//!
+//! While a step is running, the typing indicator is refreshed every
+//! TYPING_HEARTBEAT_SECS so a long generation doesn't look like Sage went
+//! away (clients drop the indicator after about 15 seconds).
+//!
+//! A watchdog bounds the whole turn (TURN_TIMEOUT_SECS, counted from when the
+//! message is picked up): when it fires, the running step is dropped, the user
+//! is told the turn was stopped, and a `turn_timeout` incident is logged. If
+//! the turn is stuck outside a step, a backstop aborts its task
+//! BACKSTOP_GRACE_SECS later, with the same reply and incident.
+//!
+//! An owner's `/reset` (see `commands`) stops the running turn after its
+//! current step, the same way.
+//!
+//! With STREAM_RESPONSES, each step streams the LLM answer and a forwarder
+//! task sends every message as soon as the agent has it (see `streaming`);
+//! the step result says how many already went out, so they are stored but not
+//! sent again.
+//!
+//! Each turn runs in its own task. If it panics (a tool or the memory layer
+//! hitting a bug), only that turn fails: the user is told, a `turn_panic`
+//! incident is logged, and the worker carries on with the next message.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::agent_manager::{coalesce_messages, AgentInbox, AgentManager};
+use crate::config::Config;
+use crate::delivery::{self, DeliveryDb};
+use crate::durable_inbox::InboxDb;
+use crate::feedback::{self, FeedbackDb};
+use crate::memory::preference_keys;
+use crate::messenger::{
+    split_message, IncomingMessage, Messenger, MessengerCapabilities, QuotedMessage,
+};
+use crate::sage_agent::SageAgent;
+use crate::turn_journal::TurnRecorder;
+use crate::{commitments, expenses, occasions, roles, signal, speech, usage, vision};
+
+/// Reply when a turn runs past `TURN_TIMEOUT_SECS`
+const TIMEOUT_REPLY: &str =
+    "Sorry, that took too long, so I've stopped it. Try again, or ask for something smaller.";
+
+/// Time past the turn limit before the whole turn task is aborted
+const BACKSTOP_GRACE_SECS: u64 = 60;
+
+/// Shared dependencies needed to process a turn
+pub struct WorkerContext {
+    pub config: Arc<Config>,
+    pub agent_manager: Arc<AgentManager>,
+    pub messenger: Arc<dyn Messenger>,
+    pub inbox_db: Arc<InboxDb>,
+    pub delivery_db: Arc<DeliveryDb>,
+    pub feedback_db: Arc<FeedbackDb>,
+}
+
+/// Routes incoming messages to per-agent worker tasks
+pub struct AgentWorkers {
+    ctx: Arc<WorkerContext>,
+    workers: HashMap<Uuid, JoinHandle<()>>,
+}
+
+impl AgentWorkers {
+    pub fn new(ctx: Arc<WorkerContext>) -> Self {
+        Self {
+            ctx,
+            workers: HashMap::new(),
+        }
+    }
+
+    /// Queue a message for an agent, spawning its worker if needed
+    pub fn dispatch(&mut self, agent_id: Uuid, agent: Arc<Mutex<SageAgent>>, msg: IncomingMessage) {
+        let inbox = self.ctx.agent_manager.inbox(agent_id);
+        inbox.push(msg);
+
+        if let Some(worker) = self.workers.get(&agent_id) {
+            if !worker.is_finished() {
+                return;
+            }
+            // Worker is gone (e.g. it panicked) - respawn it
+            warn!("Worker for agent {} has stopped - restarting it", agent_id);
+        }
+
+        let ctx = self.ctx.clone();
+        let handle = tokio::spawn(run_worker(ctx, agent_id, agent, inbox));
+        self.workers.insert(agent_id, handle);
+        info!("Started message worker for agent {}", agent_id);
+    }
+
+    /// Number of agents with a live worker
+    #[allow(dead_code)]
+    pub fn active_workers(&self) -> usize {
+        self.workers.values().filter(|w| !w.is_finished()).count()
+    }
+}
+
+/// Process an agent's inbox forever, one turn at a time
+async fn run_worker(
+    ctx: Arc<WorkerContext>,
+    agent_id: Uuid,
+    agent: Arc<Mutex<SageAgent>>,
+    inbox: Arc<AgentInbox>,
+) {
+    loop {
+        let next = if ctx.config.inbox_coalesce {
+            let batch = inbox.drain();
+            if batch.len() > 1 {
+                info!(
+                    "Coalescing {} queued messages for agent {}",
+                    batch.len(),
+                    agent_id
+                );
+            }
+            coalesce_messages(batch)
+        } else {
+            inbox.pop()
+        };
+
+        match next {
+            Some(msg) => {
+                let inbox_ids = msg.inbox_ids.clone();
+                if let Err(e) = ctx.inbox_db.begin_attempt(&inbox_ids) {
+                    warn!("Failed to record inbox attempt: {}", e);
+                }
+                inbox.set_running(true);
+                run_turn(&ctx, agent_id, &agent, msg).await;
+                inbox.set_running(false);
+                // Turn is done - drop the write-ahead copy
+                if let Err(e) = ctx.inbox_db.ack(&inbox_ids) {
+                    warn!("Failed to ack inbox messages: {}", e);
+                }
+            }
+            None => inbox.wait().await,
+        }
+    }
+}
+
+/// Run `process_message` in its own task, so a panic fails this turn
+/// instead of taking the worker down with it
+async fn run_turn(
+    ctx: &Arc<WorkerContext>,
+    agent_id: Uuid,
+    agent: &Arc<Mutex<SageAgent>>,
+    msg: IncomingMessage,
+) {
+    const PANIC_REPLY: &str =
+        "Sorry, something went wrong on my side while handling that message. Please try again.";
+
+    let recipient = msg.reply_to.clone();
+    let started = Instant::now();
+    let turn = {
+        let ctx = ctx.clone();
+        let agent = agent.clone();
+        tokio::spawn(usage::with_agent(agent_id, async move {
+            process_message(&ctx, agent_id, &agent, msg).await
+        }))
+    };
+    let abort = turn.abort_handle();
+    let joined = match backstop_limit(ctx.config.turn_timeout_secs) {
+        Some(limit) => tokio::time::timeout(limit, turn).await,
+        None => Ok(turn.await),
+    };
+
+    let reply = match joined {
+        Ok(Ok(())) => return,
+        Ok(Err(e)) if e.is_panic() => {
+            error!(
+                target: "incident",
+                kind = "turn_panic",
+                agent_id = %agent_id,
+                "Turn panicked: {}",
+                panic_message(e.into_panic())
+            );
+            PANIC_REPLY
+        }
+        Ok(Err(e)) => {
+            warn!("Turn for agent {} was cancelled: {}", agent_id, e);
+            return;
+        }
+        Err(_) => {
+            // Stuck outside a step (attachments, delivery), where the step
+            // loop's watchdog can't reach: drop the whole turn
+            abort.abort();
+            let elapsed = started.elapsed().as_secs();
+            error!(
+                target: "incident",
+                kind = "turn_timeout",
+                agent_id = %agent_id,
+                stage = "backstop",
+                elapsed_secs = elapsed,
+                limit_secs = ctx.config.turn_timeout_secs,
+                "Turn aborted by backstop after {}s",
+                elapsed
+            );
+            TIMEOUT_REPLY
+        }
+    };
+
+    // The step may have stopped halfway; start the next turn clean
+    agent.lock().await.clear_tool_results();
+    let client = ctx.messenger.as_ref();
+    if let Err(e) = client.send_message(&recipient, reply) {
+        error!("Failed to send error reply: {}", e);
+    }
+    if let Err(e) = client.end_turn(&recipient) {
+        warn!("Failed to end turn for {}: {}", recipient, e);
+    }
+}
+
+/// Deadline for the whole turn task: the turn limit plus `BACKSTOP_GRACE_SECS`
+/// for the step loop's own watchdog to stop it cleanly first. None when
+/// `TURN_TIMEOUT_SECS` is 0.
+fn backstop_limit(turn_timeout_secs: u64) -> Option<Duration> {
+    (turn_timeout_secs > 0).then(|| Duration::from_secs(turn_timeout_secs + BACKSTOP_GRACE_SECS))
+}
+
+/// Text of a panic payload (`panic!` with a literal or a format string)
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+/// Run a full turn for one incoming message: vision pre-processing, storage,
+/// the agent step loop, and delivery of replies.
+pub async fn process_message(
+    ctx: &WorkerContext,
+    agent_id: Uuid,
+    agent: &Arc<Mutex<SageAgent>>,
+    msg: IncomingMessage,
+) {
+    // The turn limit counts attachment processing too
+    let turn_started = Instant::now();
+    let config = &ctx.config;
+    let agent_manager = &ctx.agent_manager;
+    let messenger = &ctx.messenger;
+
+    let user_name = msg.source_name.as_deref().unwrap_or(&msg.source);
+    info!("Using agent {} for user {}", agent_id, user_name);
+
+    // Persist reply context (e.g. Marmot group_id) for route restoration after restart
+    if let Some(ref ctx) = msg.reply_context {
+        if let Err(e) = agent_manager.update_reply_context(&msg.reply_to, ctx) {
+            warn!("Failed to persist reply context: {}", e);
+        }
+    }
+
+    // Roles with a daily budget stop getting turns (and attachment
+    // processing) once it is spent
+    let policy = agent_manager.role_policy(&msg.reply_to);
+    if let Some(spent) = policy
+        .daily_budget_usd
+        .and_then(|_| usage::spent_today(agent_id))
+    {
+        if !policy.within_budget(spent) {
+            info!(
+                "Agent {} is over its daily budget (${:.2} spent)",
+                agent_id, spent
+            );
+            let client = messenger.as_ref();
+            if let Err(e) = client.send_message(&msg.reply_to, roles::BUDGET_REPLY) {
+                error!("Failed to send budget reply: {}", e);
+            }
+            if let Err(e) = client.end_turn(&msg.reply_to) {
+                warn!("Failed to end turn for {}: {}", msg.reply_to, e);
+            }
+            return;
+        }
+    }
+
+    let caps = messenger.capabilities();
+
+    // Send typing indicator early
+    if caps.typing {
+        let client = messenger.as_ref();
+        let _ = client.send_typing(&msg.reply_to, false);
+    }
+
+    // Check for image attachments and run vision pre-processing
+    let image_text = {
+        let image_attachment = msg
+            .attachments
+            .iter()
+            .find(|a| vision::is_supported_image(&a.content_type));
+        if let Some(attachment) = image_attachment {
+            let attachment_path = signal::attachment_path(&attachment.file);
+            info!(
+                "Image attachment detected: {} ({}) at {}",
+                attachment.file, attachment.content_type, attachment_path
+            );
+
+            let recent_context = {
+                let agent_guard = agent.lock().await;
+                match agent_guard.get_recent_messages_for_vision(6) {
+                    Ok(ctx) => ctx,
+                    Err(e) => {
+                        warn!("Failed to get recent messages for vision context: {}", e);
+                        String::new()
+                    }
+                }
+            };
+
+            let vision_config = config.vision_config();
+            match vision::describe_image(
+                &vision_config,
+                &attachment_path,
+                &attachment.content_type,
+                &msg.message,
+                &recent_context,
+            )
+            .await
+            {
+                Ok(description) => {
+                    info!("Image described ({} chars)", description.len());
+
+                    // Receipts/bills get a structured extraction pass into the expenses table
+                    let mut description = description;
+                    if vision::looks_like_receipt(&description, &msg.message) {
+                        match expenses::record_receipt(
+                            &agent_manager.expense_db(),
+                            agent_id,
+                            &vision_config,
+                            &attachment_path,
+                            &attachment.content_type,
+                        )
+                        .await
+                        {
+                            Ok(Some(note)) => {
+                                description = format!("{}\n\n{}", description, note);
+                            }
+                            Ok(None) => {}
+                            Err(e) => warn!("Failed to record receipt: {}", e),
+                        }
+                    }
+
+                    Some(description)
+                }
+                Err(e) => {
+                    error!("Failed to describe image: {}", e);
+                    Some("[Image attached but could not be processed]".to_string())
+                }
+            }
+        } else {
+            None
+        }
+    };
+
+    // Transcribe voice messages
+    let voice_text = match (
+        config.speech_config(),
+        msg.attachments
+            .iter()
+            .find(|a| speech::is_supported_audio(&a.content_type)),
+    ) {
+        (Some(speech_config), Some(attachment)) => {
+            let attachment_path = signal::attachment_path(&attachment.file);
+            info!(
+                "Voice attachment detected: {} ({}) at {}",
+                attachment.file, attachment.content_type, attachment_path
+            );
+            match speech::transcribe(&speech_config, &attachment_path, &attachment.content_type)
+                .await
+            {
+                Ok(transcript) => Some(speech::voice_note(&transcript)),
+                Err(e) => {
+                    error!("Failed to transcribe voice message: {}", e);
+                    Some(format!(
+                        "{}voice message attached but could not be transcribed]",
+                        speech::VOICE_PREFIX
+                    ))
+                }
+            }
+        }
+        (None, Some(attachment)) => {
+            warn!(
+                "Voice attachment {} received but SPEECH_API_URL is not set",
+                attachment.file
+            );
+            None
+        }
+        _ => None,
+    };
+
+    // Tell the agent which message the user is replying to or editing
+    let message_text = msg.context_text();
+
+    let attachment_text = combine_attachment_text(image_text, voice_text);
+    let user_message = if let Some(ref att) = attachment_text {
+        let att = speech::display_attachment_text(att);
+        if message_text.is_empty() {
+            att
+        } else {
+            format!("{}\n\n{}", message_text, att)
+        }
+    } else {
+        message_text.clone()
+    };
+
+    // Store incoming message (embedded in the background by the embedding queue)
+    {
+        let agent_guard = agent.lock().await;
+        match agent_guard.store_message_sync_with_attachment(
+            &msg.source,
+            "user",
+            &message_text,
+            attachment_text.as_deref(),
+        ) {
+            Ok(msg_id) => {
+                tracing::debug!("Stored user message {}", msg_id);
+                // Facts are about the one user of a direct chat, and guests'
+                // memory keeps none
+                if config.fact_extraction && msg.group_id().is_none() {
+                    if let Some(memory) = agent_guard.memory().filter(|m| !m.is_ephemeral()) {
+                        let facts = memory.facts().clone();
+                        // A new birthday or anniversary gets its check-in
+                        // right away (see `occasions`)
+                        let occasion_sync = config.occasion_checkin_hour.map(|hour| {
+                            let timezone = memory
+                                .get_preference(preference_keys::TIMEZONE)
+                                .ok()
+                                .flatten()
+                                .unwrap_or_else(|| "UTC".to_string());
+                            (
+                                agent_manager.scheduler_db(),
+                                occasions::enabled(memory),
+                                timezone,
+                                hour,
+                            )
+                        });
+                        let text = match attachment_text {
+                            Some(ref att) => format!("{}\n{}", message_text, att),
+                            None => message_text.clone(),
+                        };
+                        tokio::spawn(usage::with_agent(agent_id, async move {
+                            match facts.extract(msg_id, &text).await {
+                                Ok(changes) if !changes.is_empty() => {
+                                    info!(
+                                        "Stored {} fact(s) from message {}",
+                                        changes.len(),
+                                        msg_id
+                                    );
+                                    let dated = changes.iter().any(|(fact, _)| {
+                                        occasions::OccasionKind::from_predicate(&fact.predicate)
+                                            .is_some()
+                                    });
+                                    if let (true, Some((scheduler_db, enabled, timezone, hour))) =
+                                        (dated, occasion_sync)
+                                    {
+                                        if let Err(e) = occasions::sync_facts(
+                                            &scheduler_db,
+                                            &facts,
+                                            enabled,
+                                            &timezone,
+                                            hour,
+                                        ) {
+                                            warn!("Failed to sync occasion check-ins: {}", e);
+                                        }
+                                    }
+                                }
+                                Ok(_) => {}
+                                Err(e) => warn!("Fact extraction failed: {}", e),
+                            }
+                        }));
+                    }
+                }
+            }
+            Err(e) => error!("Failed to store message: {}", e),
+        }
+    }
+
+    // Process message with agent
+    let recipient = msg.reply_to.clone();
+
+    // Transports like email get the whole turn as one message at the end
+    let single_reply = caps.single_reply;
+    let user_message = if single_reply {
+        format!(
+            "{}\n\n[Received by email: your messages this turn are sent as one email, so write a complete, well-formatted reply rather than several short chat messages]",
+            user_message
+        )
+    } else {
+        user_message
+    };
+
+    // Tell the agent if its earlier messages are still unread
+    let delivery_note = if caps.receipts {
+        match ctx.delivery_db.status(&recipient) {
+            Ok(status) => delivery::delivery_note(&status),
+            Err(e) => {
+                warn!("Failed to load delivery status for {}: {}", recipient, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let user_message = match delivery_note {
+        Some(note) => format!("{}\n\n{}", user_message, note),
+        None => user_message,
+    };
+
+    // Ask for shorter replies if the user keeps reacting badly to long ones
+    let style_note = if ctx.config.reaction_style_hints {
+        match ctx.feedback_db.summary(agent_id) {
+            Ok(summary) => feedback::style_note(&summary),
+            Err(e) => {
+                warn!("Failed to load reaction feedback for {}: {}", agent_id, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let user_message = match style_note {
+        Some(note) => format!("{}\n\n{}", user_message, note),
+        None => user_message,
+    };
+
+    let turn = TurnRecorder::start(agent_manager.turn_journal(), agent_id);
+    turn.input(&msg.source, &user_message);
+    info!("Turn {} started for agent {}", turn.turn_id(), agent_id);
+
+    let mut had_error = false;
+    let max_steps = 10;
+    let heartbeat = (caps.typing && ctx.config.typing_heartbeat_secs > 0)
+        .then(|| Duration::from_secs(ctx.config.typing_heartbeat_secs));
+    let turn_limit =
+        (config.turn_timeout_secs > 0).then(|| Duration::from_secs(config.turn_timeout_secs));
+    let mut timed_out = false;
+    let mut steps_run = 0;
+    let mut pending_reply: Vec<String> = Vec::new();
+    // Everything sent this turn, checked for promises afterwards
+    let mut sent_replies: Vec<String> = Vec::new();
+
+    // In busy (group) conversations the first reply quotes the user's message
+    let mut reply_quote = (caps.quotes && msg.group_id().is_some()).then(|| QuotedMessage {
+        author: msg.source.clone(),
+        id: msg.target_id(),
+        text: None,
+        from_self: false,
+    });
+
+    for step_num in 0..max_steps {
+        let remaining = match turn_limit {
+            Some(limit) => match limit.checked_sub(turn_started.elapsed()) {
+                Some(left) if !left.is_zero() => Some(left),
+                _ => {
+                    timed_out = true;
+                    break;
+                }
+            },
+            None => None,
+        };
+        steps_run = step_num + 1;
+        // Streamed messages go out from the forwarder while the step runs
+        let (stream_tx, forwarder) = if config.stream_responses && !single_reply {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let forwarder = tokio::spawn(forward_streamed(
+                messenger.clone(),
+                caps,
+                recipient.clone(),
+                reply_quote.take(),
+                rx,
+            ));
+            (Some(tx), Some(forwarder))
+        } else {
+            (None, None)
+        };
+        let step = with_typing_heartbeat(&messenger, &recipient, heartbeat, async {
+            let mut agent_guard = agent.lock().await;
+            if let Some(tx) = stream_tx {
+                agent_guard.stream_messages_to(tx);
+            }
+            agent_guard.step(&user_message, step_num == 0).await
+        });
+        let step_result = match remaining {
+            // Dropping the step on timeout cancels its LLM call or tool
+            // (a shell command's process group is killed, see `GroupGuard`)
+            Some(left) => tokio::time::timeout(left, step).await.ok(),
+            None => Some(step.await),
+        };
+
+        // The step is over, so the forwarder's channel is closed
+        let streamed = match forwarder {
+            Some(forwarder) => match forwarder.await {
+                Ok((sent, quote)) => {
+                    reply_quote = reply_quote.or(quote);
+                    sent
+                }
+                Err(e) => {
+                    error!("Streamed reply forwarder failed: {}", e);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        if !matches!(step_result, Some(Ok(_))) {
+            // Messages sent before the step failed stay in history
+            for response in &streamed {
+                turn.output(step_num, response);
+                sent_replies.push(response.clone());
+                let agent_guard = agent.lock().await;
+                if let Err(e) = agent_guard.store_message_sync(&recipient, "assistant", response) {
+                    error!("Failed to store assistant message: {}", e);
+                }
+            }
+        }
+        let Some(step_result) = step_result else {
+            timed_out = true;
+            break;
+        };
+
+        match step_result {
+            Ok(result) => {
+                turn.step(step_num, &result);
+                let msg_count = result.messages.len();
+                let mut messages_to_store: Vec<String> = Vec::new();
+
+                for (i, response) in result.messages.iter().enumerate() {
+                    // Sent by the forwarder while the answer streamed
+                    let already_sent = i < result.streamed;
+                    if !already_sent {
+                        let log_preview: String = response.chars().take(50).collect();
+                        info!(
+                            "Sending response ({}/{}): {}...",
+                            i + 1,
+                            msg_count,
+                            log_preview
+                        );
+                    }
+
+                    if single_reply {
+                        pending_reply.push(response.clone());
+                    } else {
+                        if !already_sent {
+                            let client = messenger.as_ref();
+                            let quote = reply_quote.take();
+                            if let Err(e) =
+                                send_reply(client, &caps, &recipient, response, quote.as_ref())
+                            {
+                                error!("Failed to send reply: {}", e);
+                            }
+                        }
+                        turn.output(step_num, response);
+                    }
+
+                    messages_to_store.push(response.clone());
+                    sent_replies.push(response.clone());
+
+                    if !single_reply && i < msg_count - 1 && i + 1 >= result.streamed {
+                        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                        if caps.typing {
+                            let client = messenger.as_ref();
+                            let _ = client.send_typing(&recipient, false);
+                        }
+                        tokio::time::sleep(tokio::time::Duration::from_millis(1450)).await;
+                    }
+                }
+
+                for attachment in &result.attachments {
+                    let name = attachment
+                        .path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let client = messenger.as_ref();
+                    if !caps.attachments {
+                        warn!("Messenger can't send files; dropping {}", name);
+                        let notice = format!(
+                            "I made {} for you, but I can't send files over this channel.",
+                            name
+                        );
+                        let _ = client.send_message(&recipient, &notice);
+                        continue;
+                    }
+                    match client.send_attachment(
+                        &recipient,
+                        &attachment.path,
+                        attachment.caption.as_deref(),
+                    ) {
+                        Ok(()) => turn.output(step_num, &format!("[Attachment: {}]", name)),
+                        Err(e) => {
+                            error!("Failed to send attachment {}: {}", name, e);
+                            let notice =
+                                format!("I tried to send you {}, but it failed: {}", name, e);
+                            let _ = client.send_message(&recipient, &notice);
+                        }
+                    }
+                }
+
+                for emoji in &result.reactions {
+                    let client = messenger.as_ref();
+                    let reacted = if caps.reactions {
+                        let target = msg.target_id().parse().unwrap_or(msg.timestamp);
+                        client.send_reaction(&recipient, &msg.source, target, emoji)
+                    } else {
+                        Err(anyhow::anyhow!("reactions not supported"))
+                    };
+                    match reacted {
+                        Ok(()) => turn.output(step_num, &format!("[Reaction: {}]", emoji)),
+                        Err(e) => {
+                            // Transports without reactions get the emoji as a message
+                            info!("Reaction not sent ({}), sending as message", e);
+                            if single_reply {
+                                pending_reply.push(emoji.clone());
+                            } else if let Err(e) = client.send_message(&recipient, emoji) {
+                                error!("Failed to send reaction: {}", e);
+                            } else {
+                                turn.output(step_num, emoji);
+                            }
+                        }
+                    }
+                    // Keep the reaction in history so the agent knows it answered
+                    messages_to_store.push(format!("[Reacted {}]", emoji));
+                }
+
+                if msg_count > 0 && !single_reply && caps.typing {
+                    let client = messenger.as_ref();
+                    let _ = client.send_typing(&recipient, true);
+                }
+
+                // Embedded in the background by the embedding queue
+                for response in &messages_to_store {
+                    let agent_guard = agent.lock().await;
+                    if let Err(e) =
+                        agent_guard.store_message_sync(&recipient, "assistant", response)
+                    {
+                        error!("Failed to store assistant message: {}", e);
+                    }
+                }
+
+                if !result.executed_tools.is_empty() {
+                    let agent_clone = agent.clone();
+                    let recipient_clone = recipient.clone();
+                    let executed_tools = result.executed_tools.clone();
+                    tokio::spawn(async move {
+                        let agent_guard = agent_clone.lock().await;
+                        for executed in &executed_tools {
+                            if let Err(e) = agent_guard
+                                .store_tool_message(
+                                    &recipient_clone,
+                                    &executed.tool_call,
+                                    &executed.result,
+                                )
+                                .await
+                            {
+                                error!("Failed to store tool message: {}", e);
+                            }
+                        }
+                    });
+                    info!(
+                        "Queued {} tool calls for storage",
+                        result.executed_tools.len()
+                    );
+                }
+
+                if result.done {
+                    break;
+                }
+
+                // An owner sent /reset: end the turn quietly
+                if agent_manager.inbox(agent_id).stop_requested() {
+                    info!(
+                        "Stopping turn for agent {} after step {}: reset by owner",
+                        agent_id, step_num
+                    );
+                    turn.error(step_num, "Turn stopped by owner (/reset)");
+                    break;
+                }
+
+                // New messages arrived mid-turn: stop here and let the worker
+                // start a fresh turn with them
+                if config.inbox_interrupt && agent_manager.inbox(agent_id).has_pending() {
+                    info!(
+                        "Interrupting turn for agent {} after step {}: new messages queued",
+                        agent_id, step_num
+                    );
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("Agent error at step {}: {}", step_num, e);
+                turn.error(step_num, &e.to_string());
+                had_error = true;
+                break;
+            }
+        }
+    }
+
+    if timed_out {
+        let elapsed = turn_started.elapsed().as_secs();
+        error!(
+            target: "incident",
+            kind = "turn_timeout",
+            agent_id = %agent_id,
+            turn_id = %turn.turn_id(),
+            steps_run,
+            elapsed_secs = elapsed,
+            limit_secs = config.turn_timeout_secs,
+            "Turn stopped by watchdog after {}s",
+            elapsed
+        );
+        turn.error(
+            steps_run.saturating_sub(1),
+            &format!("Turn timed out after {}s and was stopped", elapsed),
+        );
+    }
+
+    const ERROR_REPLY: &str = "Sorry, I encountered an error processing your message.";
+
+    if single_reply {
+        if timed_out {
+            pending_reply.push(TIMEOUT_REPLY.to_string());
+        } else if had_error {
+            pending_reply.push(ERROR_REPLY.to_string());
+        }
+        if !pending_reply.is_empty() {
+            let body = pending_reply.join("\n\n");
+            {
+                let client = messenger.as_ref();
+                if let Err(e) = send_reply(client, &caps, &recipient, &body, reply_quote.as_ref()) {
+                    error!("Failed to send reply: {}", e);
+                }
+            }
+            turn.output(steps_run.saturating_sub(1), &body);
+        }
+    } else if timed_out {
+        let client = messenger.as_ref();
+        let _ = client.send_message(&recipient, TIMEOUT_REPLY);
+    } else if had_error {
+        let client = messenger.as_ref();
+        let _ = client.send_message(&recipient, ERROR_REPLY);
+    }
+
+    {
+        let client = messenger.as_ref();
+        if let Err(e) = client.end_turn(&recipient) {
+            warn!("Failed to end turn for {}: {}", recipient, e);
+        }
+    }
+
+    // Promises made this turn get a follow-up (see `commitments`)
+    if config.commitment_tracking
+        && policy.proactive
+        && commitments::mentions_promise(&sent_replies)
+    {
+        let agent_guard = agent.lock().await;
+        if let Some(memory) = agent_guard.memory().filter(|m| !m.is_ephemeral()) {
+            let timezone = memory
+                .get_preference(preference_keys::TIMEZONE)
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| "UTC".to_string());
+            let commitment_db = agent_manager.commitment_db();
+            let scheduler_db = agent_manager.scheduler_db();
+            let user_message = message_text.clone();
+            tokio::spawn(usage::with_agent(agent_id, async move {
+                match commitments::track(
+                    &commitment_db,
+                    &scheduler_db,
+                    agent_id,
+                    &timezone,
+                    &user_message,
+                    &sent_replies,
+                )
+                .await
+                {
+                    Ok(recorded) if !recorded.is_empty() => {
+                        info!("Recorded {} commitment(s)", recorded.len())
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Commitment detection failed: {}", e),
+                }
+            }));
+        }
+    }
+
+    turn.finish(steps_run);
+}
+
+/// Run `step`, re-sending the typing indicator every `every` until it
+/// finishes. The first refresh comes after one interval, so quick steps send
+/// nothing extra.
+async fn with_typing_heartbeat<F: Future>(
+    messenger: &Arc<dyn Messenger>,
+    recipient: &str,
+    every: Option<Duration>,
+    step: F,
+) -> F::Output {
+    let Some(every) = every else {
+        return step.await;
+    };
+    tokio::pin!(step);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    loop {
+        tokio::select! {
+            output = &mut step => return output,
+            _ = ticker.tick() => {
+                let client = messenger.as_ref();
+                let _ = client.send_typing(recipient, false);
+            }
+        }
+    }
+}
+
+/// Send messages as the agent streams them. Returns what was sent, and the
+/// quote if no message used it.
+async fn forward_streamed(
+    messenger: Arc<dyn Messenger>,
+    caps: MessengerCapabilities,
+    recipient: String,
+    mut quote: Option<QuotedMessage>,
+    mut rx: mpsc::UnboundedReceiver<String>,
+) -> (Vec<String>, Option<QuotedMessage>) {
+    let mut sent = Vec::new();
+    while let Some(response) = rx.recv().await {
+        let log_preview: String = response.chars().take(50).collect();
+        info!(
+            "Sending streamed response ({}): {}...",
+            sent.len() + 1,
+            log_preview
+        );
+        let client = messenger.as_ref();
+        if let Err(e) = send_reply(client, &caps, &recipient, &response, quote.take().as_ref()) {
+            error!("Failed to send reply: {}", e);
+        }
+        // The model is still writing
+        if caps.typing {
+            let _ = client.send_typing(&recipient, false);
+        }
+        sent.push(response);
+    }
+    (sent, quote)
+}
+
+/// Send a reply, split to fit the transport's message length limit. With a
+/// quote, the first part quotes the message being answered.
+fn send_reply(
+    client: &dyn Messenger,
+    caps: &MessengerCapabilities,
+    recipient: &str,
+    text: &str,
+    mut quote: Option<&QuotedMessage>,
+) -> anyhow::Result<()> {
+    let parts = match caps.max_message_length {
+        Some(max) => split_message(text, max),
+        None => vec![text.to_string()],
+    };
+    for part in parts {
+        match quote.take() {
+            Some(quote) => client.send_quoted(recipient, &part, quote)?,
+            None => client.send_message(recipient, &part)?,
+        }
+    }
+    Ok(())
+}
+
+/// Merge the image description and voice transcript into the text stored with
+/// the user message. Image-only text stays a bare description (see
+/// `speech::display_attachment_text`); anything with a voice note is stored
+/// already labelled.
+fn combine_attachment_text(image: Option<String>, voice: Option<String>) -> Option<String> {
+    match (image, voice) {
+        (Some(image), Some(voice)) => Some(format!("{}\n[Uploaded Image: {}]", voice, image)),
+        (image, voice) => voice.or(image),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_attachment_text() {
+        assert_eq!(combine_attachment_text(None, None), None);
+        assert_eq!(
+            combine_attachment_text(Some("A cat".to_string()), None).as_deref(),
+            Some("A cat")
+        );
+
+        let both = combine_attachment_text(
+            Some("A cat".to_string()),
+            Some("[Voice message: look at him]".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            speech::display_attachment_text(&both),
+            "[Voice message: look at him]\n[Uploaded Image: A cat]"
+        );
+    }
+
+    #[test]
+    fn test_backstop_limit() {
+        assert_eq!(backstop_limit(0), None);
+        assert_eq!(backstop_limit(300), Some(Duration::from_secs(360)));
+    }
+
+    #[tokio::test]
+    async fn test_panic_message() {
+        let err = tokio::spawn(async { panic!("tool blew up") })
+            .await
+            .unwrap_err();
+        assert!(err.is_panic());
+        assert_eq!(panic_message(err.into_panic()), "tool blew up");
+
+        let err = tokio::spawn(async { panic!("step {} failed", 3) })
+            .await
+            .unwrap_err();
+        assert_eq!(panic_message(err.into_panic()), "step 3 failed");
+        assert_eq!(panic_message(Box::new(42)), "unknown panic");
+    }
+}
@@ -0,0 +1,301 @@
+//! Dataset Anonymization
+//!
+//! Production turns exported for GEPA and evals (see
+//! `AdminDb::production_examples`) go to the judge model's provider, so
+//! personal details are swapped for pseudonyms first. Substitution is
+//! consistent across a whole export: "Alice" becomes the same stand-in in
+//! every example, so conversations still make sense.
+//!
+//! Replaced:
+//! - email addresses, phone numbers, UUIDs (Signal ids), Nostr keys, street
+//!   addresses and other long digit runs (account numbers, codes), found by
+//!   pattern
+//! - names and places, which can't be found reliably by pattern: they are
+//!   seeded from what Sage knows (display names, "Name:" / "Location:" lines
+//!   in the human block, "my name is ...", "I live in ...") and then replaced
+//!   wherever they appear
+//!
+//! Dates, times and amounts are kept - they carry the behaviour being
+//! evaluated. Names nobody ever stated stay as they are; this is a
+//! best-effort scrub, not a guarantee.
+
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+const FIRST_NAMES: &[&str] = &[
+    "Avery", "Blake", "Casey", "Devon", "Emerson", "Finley", "Harper", "Jordan", "Kendall",
+    "Logan", "Morgan", "Parker", "Quinn", "Riley", "Rowan", "Sawyer", "Taylor", "Skyler",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Adler", "Brooks", "Carter", "Dalton", "Ellis", "Foster", "Grant", "Hayes", "Irving", "Jensen",
+    "Keller", "Lowell", "Mercer", "Nolan", "Porter", "Reed", "Sutton", "Turner",
+];
+
+const PLACES: &[&str] = &[
+    "Springfield",
+    "Riverton",
+    "Fairview",
+    "Lakewood",
+    "Maplewood",
+    "Oakdale",
+    "Brookside",
+    "Clearwater",
+    "Greenville",
+    "Hillcrest",
+    "Kingsport",
+    "Millbrook",
+];
+
+/// Patterns found without seeding, tried in this order at each position
+static PATTERNS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        // ISO dates look like phone numbers; matched first so they're kept
+        r"(?P<date>\b\d{4}-\d{2}-\d{2}\b)|",
+        r"(?P<email>[\w.+-]+@[\w-]+(?:\.[\w-]+)+)",
+        r"|(?P<uuid>\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b)",
+        r"|(?P<nostr>\b(?:npub|nsec)1[0-9a-z]{20,}\b|\b[0-9a-f]{64}\b)",
+        r"|(?P<address>\b\d{1,5}\s+(?:[A-Z][a-z]+\s+){1,3}(?:Street|St|Avenue|Ave|Road|Rd|Boulevard|Blvd|Lane|Ln|Drive|Dr|Court|Ct|Way|Place|Pl)\b)",
+        r"|(?P<phone>(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)[\s.-]?)?\d{2,4}(?:[\s.-]\d{2,4}){2,3}\b|\+\d{8,15}\b)",
+        r"|(?P<digits>\b\d{6,}\b)",
+    ))
+    .expect("valid anonymization patterns")
+});
+
+/// Name statements that seed the name list
+static NAME_HINTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:(?i:my name is|call me|^name:|\bname:)\s*)(\p{Lu}[\p{L}'-]+(?:[ \t]+\p{Lu}[\p{L}'-]+)?)",
+    )
+    .expect("valid name hint pattern")
+});
+
+/// Place statements that seed the place list
+static PLACE_HINTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:(?i:\b(?:live|lives|living|lived|based|moved|moving|from)\s+(?:in|to)?\s*|location:\s*|city:\s*))(\p{Lu}[\p{L}'-]+(?:[ \t]+\p{Lu}[\p{L}'-]+)?)",
+    )
+    .expect("valid place hint pattern")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Email,
+    Uuid,
+    Nostr,
+    Address,
+    Phone,
+    Digits,
+    FirstName,
+    LastName,
+    Place,
+}
+
+/// Replaces personal details with consistent pseudonyms
+#[derive(Debug, Default)]
+pub struct Anonymizer {
+    /// (kind, original lowercased) -> pseudonym
+    pseudonyms: HashMap<(Kind, String), String>,
+    counters: HashMap<Kind, usize>,
+    /// Seeded names and places (lowercased) -> kind
+    terms: HashMap<String, Kind>,
+    /// Matches any seeded term; rebuilt when terms change
+    term_pattern: Option<Regex>,
+}
+
+impl Anonymizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat `name` (and each part of it) as a person's name
+    pub fn add_name(&mut self, name: &str) {
+        let parts: Vec<&str> = name.split_whitespace().collect();
+        match parts.as_slice() {
+            [] => {}
+            [first] => self.add_term(first, Kind::FirstName),
+            [first, rest @ ..] => {
+                self.add_term(first, Kind::FirstName);
+                for last in rest {
+                    self.add_term(last, Kind::LastName);
+                }
+            }
+        }
+    }
+
+    /// Treat `place` as a location
+    pub fn add_place(&mut self, place: &str) {
+        let place = place.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !place.is_empty() {
+            self.add_term(&place, Kind::Place);
+        }
+    }
+
+    /// Seed names and places from statements in `text` ("my name is ...",
+    /// "Name: ...", "I live in ...")
+    pub fn seed_from(&mut self, text: &str) {
+        let names: Vec<String> = NAME_HINTS
+            .captures_iter(text)
+            .map(|c| c[1].to_string())
+            .collect();
+        let places: Vec<String> = PLACE_HINTS
+            .captures_iter(text)
+            .map(|c| c[1].to_string())
+            .collect();
+        for name in names {
+            self.add_name(&name);
+        }
+        for place in places {
+            self.add_place(&place);
+        }
+    }
+
+    fn add_term(&mut self, term: &str, kind: Kind) {
+        let term = term.trim_matches(|c: char| !c.is_alphanumeric());
+        // Too short to replace safely ("Al", "Jo" inside other words is
+        // prevented by word boundaries, but "I" or "A" is not worth it)
+        if term.chars().count() < 3 {
+            return;
+        }
+        let key = term.to_lowercase();
+        if self.terms.insert(key, kind) != Some(kind) {
+            self.term_pattern = None;
+        }
+    }
+
+    /// Pseudonym for `original` of `kind`, the same one every time
+    fn pseudonym(&mut self, kind: Kind, original: &str) -> String {
+        let key = (kind, original.to_lowercase());
+        if let Some(existing) = self.pseudonyms.get(&key) {
+            return existing.clone();
+        }
+        let n = self.counters.entry(kind).or_default();
+        let index = *n;
+        *n += 1;
+        let pseudonym = match kind {
+            Kind::Email => format!("user{}@example.com", index + 1),
+            Kind::Uuid => format!("00000000-0000-4000-8000-{:012}", index + 1),
+            Kind::Nostr => format!("npub1anonymized{}", index + 1),
+            Kind::Address => format!("{} Example Street", 100 + index),
+            Kind::Phone => format!("+1-555-01{:02}", index % 100),
+            Kind::Digits => format!("{:0width$}", index + 1, width = original.len()),
+            Kind::FirstName => cycle(FIRST_NAMES, index),
+            Kind::LastName => cycle(LAST_NAMES, index),
+            Kind::Place => cycle(PLACES, index),
+        };
+        self.pseudonyms.insert(key, pseudonym.clone());
+        pseudonym
+    }
+
+    /// `text` with personal details replaced
+    pub fn anonymize(&mut self, text: &str) -> String {
+        let patterned = PATTERNS
+            .replace_all(text, |caps: &Captures| {
+                if caps.name("date").is_some() {
+                    return caps[0].to_string();
+                }
+                let (kind, matched) = [
+                    ("email", Kind::Email),
+                    ("uuid", Kind::Uuid),
+                    ("nostr", Kind::Nostr),
+                    ("address", Kind::Address),
+                    ("phone", Kind::Phone),
+                    ("digits", Kind::Digits),
+                ]
+                .into_iter()
+                .find_map(|(group, kind)| caps.name(group).map(|m| (kind, m.as_str())))
+                .expect("one group matched");
+                self.pseudonym(kind, matched)
+            })
+            .into_owned();
+
+        if self.terms.is_empty() {
+            return patterned;
+        }
+        if self.term_pattern.is_none() {
+            let mut terms: Vec<&String> = self.terms.keys().collect();
+            // Longest first so "New York" wins over "New"
+            terms.sort_by_key(|t| std::cmp::Reverse(t.len()));
+            let alternation = terms
+                .iter()
+                .map(|t| regex::escape(t).replace(' ', r"\s+"))
+                .collect::<Vec<_>>()
+                .join("|");
+            self.term_pattern = Regex::new(&format!(r"(?i)\b(?:{})\b", alternation)).ok();
+        }
+        let Some(pattern) = self.term_pattern.clone() else {
+            return patterned;
+        };
+        pattern
+            .replace_all(&patterned, |caps: &Captures| {
+                let matched = &caps[0];
+                let normalized = matched.split_whitespace().collect::<Vec<_>>().join(" ");
+                match self.terms.get(&normalized.to_lowercase()).copied() {
+                    Some(kind) => self.pseudonym(kind, &normalized),
+                    None => matched.to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// The `index`th entry of `list`, numbered once the list runs out
+fn cycle(list: &[&str], index: usize) -> String {
+    let name = list[index % list.len()];
+    match index / list.len() {
+        0 => name.to_string(),
+        round => format!("{}{}", name, round + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        let mut anon = Anonymizer::new();
+        let text = anon.anonymize(
+            "Mail alice.smith@gmail.com or call +1 415 555 2671. Ref 48213377, \
+             I'm at 42 Baker Street. Signal id 3f2504e0-4f89-11d3-9a0c-0305e82c3301.",
+        );
+        assert_eq!(
+            text,
+            "Mail user1@example.com or call +1-555-0100. Ref 00000001, \
+             I'm at 100 Example Street. Signal id 00000000-0000-4000-8000-000000000001."
+        );
+        // Consistent across calls, amounts and dates untouched
+        assert_eq!(
+            anon.anonymize("alice.smith@gmail.com paid $120.50 on 03/14/2026 (2026-03-14)"),
+            "user1@example.com paid $120.50 on 03/14/2026 (2026-03-14)"
+        );
+    }
+
+    #[test]
+    fn test_names_and_places() {
+        let mut anon = Anonymizer::new();
+        anon.seed_from("Name: Alice Smith\nLives in Portland, works remotely");
+        anon.add_name("Bob");
+        let text = anon.anonymize("alice and Bob flew from Portland. ALICE SMITH booked it.");
+        assert_eq!(
+            text,
+            "Avery and Blake flew from Springfield. Avery Adler booked it."
+        );
+        // Word boundaries: "Bobcat" is not Bob
+        assert_eq!(anon.anonymize("a Bobcat"), "a Bobcat");
+    }
+
+    #[test]
+    fn test_seed_from() {
+        let mut anon = Anonymizer::new();
+        anon.seed_from("hey, my name is Priya and I moved to New Orleans last year");
+        assert_eq!(
+            anon.anonymize("Priya loves New Orleans"),
+            "Avery loves Springfield"
+        );
+        // "I" and other short words are never seeded
+        anon.add_name("Al");
+        assert_eq!(anon.anonymize("Al is here"), "Al is here");
+    }
+}
@@ -127,6 +127,7 @@ struct TrainingExample {
     current_time: String,
     persona_block: String,
     human_block: String,
+    memory_blocks: String,
     memory_metadata: String,
     previous_context_summary: String,
     recent_conversation: String,
@@ -150,6 +151,7 @@ fn load_trainset() -> Vec<TrainingExample> {
                 current_time: e["current_time"].as_str().unwrap_or("").to_string(),
                 persona_block: e["persona_block"].as_str().unwrap_or("").to_string(),
                 human_block: e["human_block"].as_str().unwrap_or("").to_string(),
+                memory_blocks: e["memory_blocks"].as_str().unwrap_or("").to_string(),
                 memory_metadata: e["memory_metadata"].as_str().unwrap_or("").to_string(),
                 previous_context_summary: e["previous_context_summary"]
                     .as_str()
@@ -217,6 +219,7 @@ async fn run_evaluation_async() -> Result<()> {
             current_time: example.current_time.clone(),
             persona_block: example.persona_block.clone(),
             human_block: example.human_block.clone(),
+            memory_blocks: example.memory_blocks.clone(),
             memory_metadata: example.memory_metadata.clone(),
             previous_context_summary: example.previous_context_summary.clone(),
             recent_conversation: example.recent_conversation.clone(),
@@ -601,6 +604,7 @@ async fn evaluate_instruction(
             current_time: example.current_time.clone(),
             persona_block: example.persona_block.clone(),
             human_block: example.human_block.clone(),
+            memory_blocks: example.memory_blocks.clone(),
             memory_metadata: example.memory_metadata.clone(),
             previous_context_summary: example.previous_context_summary.clone(),
             recent_conversation: example.recent_conversation.clone(),
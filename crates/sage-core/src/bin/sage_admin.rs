@@ -0,0 +1,930 @@
+//! Admin CLI for Sage
+//!
+//! Inspects and fixes up agents from a shell, without hand-written SQL. Reads
+//! `DATABASE_URL` (and the `MAPLE_*` settings for archival search) from the
+//! environment or `.env`, like Sage itself.
+//!
+//! Agents can be named by id, a unique id prefix, or chat identifier (Signal
+//! UUID, `group:<id>`, Nostr pubkey, ...).
+//!
+//! Usage:
+//!   cargo run --bin sage-admin -- agents list
+//!   cargo run --bin sage-admin -- memory show <agent>
+//!   cargo run --bin sage-admin -- memory edit-block <agent> <label> --value <text>
+//!   cargo run --bin sage-admin -- memory history <agent> <label>
+//!   cargo run --bin sage-admin -- memory rollback <agent> <label> --version <n>
+//!   cargo run --bin sage-admin -- memory export <agent> --out memory.json
+//!   cargo run --bin sage-admin -- memory import <agent> memory.json
+//!   cargo run --bin sage-admin -- memory compact <agent> [--apply]
+//!   cargo run --bin sage-admin -- archival search <agent> <query>
+//!   cargo run --bin sage-admin -- schedule list <agent>
+//!   cargo run --bin sage-admin -- schedule cancel <task id>
+//!   cargo run --bin sage-admin -- messages tail <agent>
+//!   cargo run --bin sage-admin -- export decrypt <file>
+//!   cargo run --bin sage-admin -- gepa export <agent> --out trainset.json
+//!   cargo run --bin sage-admin -- migrate identities [--apply]
+
+use anyhow::{Context, Result};
+use sage_core::admin::{
+    AdminDb, GepaTrainset, DEFAULT_EXAMPLE_LIMIT, DEFAULT_MESSAGE_LIMIT, DEFAULT_REVISION_LIMIT,
+};
+use sage_core::agent_manager::{AgentManager, ContextType};
+use sage_core::export;
+use sage_core::identity_migration::MigrationDb;
+use sage_core::memory::{
+    ArchivalManager, BlockManager, EditCause, EmbeddingService, MemoryBundle, MemoryDb,
+    MemoryManager,
+};
+use sage_core::model_limits;
+use sage_core::sage_agent::SageAgent;
+use sage_core::scheduler::SchedulerDb;
+use sage_core::Config;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+const USAGE: &str = "Usage:
+  sage-admin agents list
+      All agents with their chat and message counts, most recently active first.
+  sage-admin memory show <agent>
+      The agent's core memory blocks.
+  sage-admin memory edit-block <agent> <label> (--value <text> | --file <path>)
+      Replace a block's value (--file - reads stdin). Restart Sage for a
+      loaded agent to pick up the edit.
+  sage-admin memory history <agent> <label> [--limit <n>]
+      The block's recorded edits, newest first, with what made each one.
+  sage-admin memory rollback <agent> <label> [--version <n>]
+      Restore the block as it was at version n (default: before its last
+      edit). Restart Sage for a loaded agent to pick it up, or use the
+      /admin rollback route instead.
+  sage-admin memory export <agent> [--out <path>]
+      Blocks, archival passages, summaries and preferences as a portable
+      JSON bundle (Letta-style field names; stdout unless --out).
+  sage-admin memory import <agent> <file>
+      Merge a bundle (or a Letta agent file's blocks) into the agent's
+      memory (- reads stdin). Passages and summaries are embedded again.
+  sage-admin memory compact <agent> [--apply]
+      Which messages compaction would summarize now and the context tokens
+      it would save; --apply runs it. For an agent Sage has loaded, prefer
+      POST /admin/agents/<id>/compact, which waits for a running turn.
+  sage-admin archival search <agent> <query> [--limit <n>] [--tag <tag>]
+      Semantic search of the agent's archival memory.
+  sage-admin schedule list <agent> [--all]
+      The agent's pending scheduled tasks (--all includes finished ones).
+  sage-admin schedule cancel <task id>
+      Cancel a pending scheduled task.
+  sage-admin messages tail <agent> [--limit <n>]
+      The agent's most recent messages.
+  sage-admin export decrypt <file> [--out <path>]
+      Decrypt an encrypted export (.sage-enc). The passphrase is read from
+      SAGE_EXPORT_PASSPHRASE or the first line of stdin.
+  sage-admin gepa export <agent> [--limit <n>] [--out <path>]
+      The agent's recent turns as an anonymized GEPA trainset (stdout
+      unless --out). Fill in expected_behavior before optimizing.
+  sage-admin migrate identities [--from <agent id>] [--primary <identifier>] [--apply]
+      Move data stored under the old single agent (the nil UUID, or the
+      only agent without a chat) to per-identity agents: messages by
+      sender, core blocks to each (`human` only to the primary identity,
+      by default the one with most messages), archival passages to the
+      primary. Prints the plan; --apply carries it out. Stop Sage first.
+
+<agent> is an agent id, a unique id prefix, or a chat identifier.";
+
+/// A `sage-admin` command
+#[derive(Debug, Clone, PartialEq)]
+enum AdminCommand {
+    ListAgents,
+    ShowMemory {
+        agent: String,
+    },
+    EditBlock {
+        agent: String,
+        label: String,
+        value: BlockValue,
+    },
+    BlockHistory {
+        agent: String,
+        label: String,
+        limit: i64,
+    },
+    RollbackBlock {
+        agent: String,
+        label: String,
+        version: Option<i32>,
+    },
+    ExportMemory {
+        agent: String,
+        out: Option<String>,
+    },
+    ImportMemory {
+        agent: String,
+        /// A file, or stdin for "-"
+        file: String,
+    },
+    CompactMemory {
+        agent: String,
+        apply: bool,
+    },
+    SearchArchival {
+        agent: String,
+        query: String,
+        limit: usize,
+        tag: Option<String>,
+    },
+    ListSchedules {
+        agent: String,
+        all: bool,
+    },
+    CancelSchedule {
+        task_id: Uuid,
+    },
+    TailMessages {
+        agent: String,
+        limit: i64,
+    },
+    DecryptExport {
+        file: String,
+        out: Option<String>,
+    },
+    ExportGepa {
+        agent: String,
+        limit: i64,
+        out: Option<String>,
+    },
+    MigrateIdentities {
+        from: Option<Uuid>,
+        primary: Option<String>,
+        apply: bool,
+    },
+}
+
+/// Where a block edit's new value comes from
+#[derive(Debug, Clone, PartialEq)]
+enum BlockValue {
+    Text(String),
+    /// A file, or stdin for "-"
+    File(String),
+}
+
+/// Flags that take no value
+const SWITCHES: [&str; 2] = ["all", "apply"];
+
+/// Split arguments into positionals and `--flag [value]` options
+fn split_args(args: &[String]) -> Result<(Vec<String>, HashMap<String, String>)> {
+    let mut positional = Vec::new();
+    let mut options = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.strip_prefix("--") {
+            Some(key) if SWITCHES.contains(&key) => {
+                options.insert(key.to_string(), String::new());
+            }
+            Some(key) => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--{} needs a value", key))?;
+                options.insert(key.to_string(), value.clone());
+            }
+            None => positional.push(arg.clone()),
+        }
+    }
+    Ok((positional, options))
+}
+
+fn parse_number<T: std::str::FromStr>(
+    options: &mut HashMap<String, String>,
+    key: &str,
+) -> Result<Option<T>> {
+    options
+        .remove(key)
+        .map(|v| {
+            v.parse()
+                .map_err(|_| anyhow::anyhow!("invalid --{} '{}'", key, v))
+        })
+        .transpose()
+}
+
+/// Parse the arguments after `sage-admin`
+fn parse_args(args: &[String]) -> Result<AdminCommand> {
+    let (positional, mut options) = split_args(args)?;
+    let words: Vec<&str> = positional.iter().map(String::as_str).collect();
+
+    let command = match words.as_slice() {
+        ["agents", "list"] => AdminCommand::ListAgents,
+        ["memory", "show", agent] => AdminCommand::ShowMemory {
+            agent: agent.to_string(),
+        },
+        ["memory", "edit-block", agent, label] => {
+            let value = match (options.remove("value"), options.remove("file")) {
+                (Some(text), None) => BlockValue::Text(text),
+                (None, Some(path)) => BlockValue::File(path),
+                _ => anyhow::bail!(
+                    "edit-block needs exactly one of --value or --file\n\n{}",
+                    USAGE
+                ),
+            };
+            AdminCommand::EditBlock {
+                agent: agent.to_string(),
+                label: label.to_string(),
+                value,
+            }
+        }
+        ["memory", "history", agent, label] => AdminCommand::BlockHistory {
+            agent: agent.to_string(),
+            label: label.to_string(),
+            limit: parse_number(&mut options, "limit")?.unwrap_or(DEFAULT_REVISION_LIMIT),
+        },
+        ["memory", "rollback", agent, label] => AdminCommand::RollbackBlock {
+            agent: agent.to_string(),
+            label: label.to_string(),
+            version: parse_number(&mut options, "version")?,
+        },
+        ["memory", "export", agent] => AdminCommand::ExportMemory {
+            agent: agent.to_string(),
+            out: options.remove("out"),
+        },
+        ["memory", "import", agent, file] => AdminCommand::ImportMemory {
+            agent: agent.to_string(),
+            file: file.to_string(),
+        },
+        ["memory", "compact", agent] => AdminCommand::CompactMemory {
+            agent: agent.to_string(),
+            apply: options.remove("apply").is_some(),
+        },
+        ["archival", "search", agent, query @ ..] if !query.is_empty() => {
+            AdminCommand::SearchArchival {
+                agent: agent.to_string(),
+                query: query.join(" "),
+                limit: parse_number(&mut options, "limit")?.unwrap_or(DEFAULT_SEARCH_LIMIT),
+                tag: options.remove("tag"),
+            }
+        }
+        ["schedule", "list", agent] => AdminCommand::ListSchedules {
+            agent: agent.to_string(),
+            all: options.remove("all").is_some(),
+        },
+        ["schedule", "cancel", task_id] => AdminCommand::CancelSchedule {
+            task_id: Uuid::parse_str(task_id)
+                .with_context(|| format!("invalid task id '{}'", task_id))?,
+        },
+        ["messages", "tail", agent] => AdminCommand::TailMessages {
+            agent: agent.to_string(),
+            limit: parse_number(&mut options, "limit")?.unwrap_or(DEFAULT_MESSAGE_LIMIT),
+        },
+        ["export", "decrypt", file] => AdminCommand::DecryptExport {
+            file: file.to_string(),
+            out: options.remove("out"),
+        },
+        ["gepa", "export", agent] => AdminCommand::ExportGepa {
+            agent: agent.to_string(),
+            limit: parse_number(&mut options, "limit")?.unwrap_or(DEFAULT_EXAMPLE_LIMIT),
+            out: options.remove("out"),
+        },
+        ["migrate", "identities"] => AdminCommand::MigrateIdentities {
+            from: options
+                .remove("from")
+                .map(|from| {
+                    Uuid::parse_str(&from).with_context(|| format!("invalid agent id '{}'", from))
+                })
+                .transpose()?,
+            primary: options.remove("primary"),
+            apply: options.remove("apply").is_some(),
+        },
+        _ => anyhow::bail!("unknown command\n\n{}", USAGE),
+    };
+
+    if let Some(key) = options.keys().next() {
+        anyhow::bail!("unknown option --{}\n\n{}", key, USAGE);
+    }
+    Ok(command)
+}
+
+fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let command = parse_args(&args)?;
+    // Decrypting needs no database
+    if let AdminCommand::DecryptExport { file, out } = &command {
+        return decrypt_export(file, out.as_deref());
+    }
+    let config = Config::from_env()?;
+    let admin = AdminDb::connect(&config.database_url)?;
+
+    match command {
+        AdminCommand::ListAgents => list_agents(&admin),
+        AdminCommand::ShowMemory { agent } => show_memory(&admin, &agent),
+        AdminCommand::EditBlock {
+            agent,
+            label,
+            value,
+        } => edit_block(&admin, &agent, &label, value),
+        AdminCommand::BlockHistory {
+            agent,
+            label,
+            limit,
+        } => block_history(&admin, &agent, &label, limit),
+        AdminCommand::RollbackBlock {
+            agent,
+            label,
+            version,
+        } => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(rollback_block(&config, &admin, &agent, &label, version))
+        }
+        AdminCommand::ExportMemory { agent, out } => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(export_memory(&config, &admin, &agent, out.as_deref()))
+        }
+        AdminCommand::ImportMemory { agent, file } => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(import_memory(&config, &admin, &agent, &file))
+        }
+        AdminCommand::CompactMemory { agent, apply } => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(compact_memory(&config, &admin, &agent, apply))
+        }
+        AdminCommand::SearchArchival {
+            agent,
+            query,
+            limit,
+            tag,
+        } => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(search_archival(&config, &admin, &agent, &query, limit, tag))
+        }
+        AdminCommand::ListSchedules { agent, all } => list_schedules(&config, &admin, &agent, all),
+        AdminCommand::CancelSchedule { task_id } => {
+            let scheduler = SchedulerDb::connect(&config.database_url)?;
+            if scheduler.cancel_task(task_id)? {
+                println!("Cancelled task {}", task_id);
+            } else {
+                println!("No pending task {}", task_id);
+            }
+            Ok(())
+        }
+        AdminCommand::TailMessages { agent, limit } => tail_messages(&admin, &agent, limit),
+        AdminCommand::ExportGepa { agent, limit, out } => {
+            export_gepa(&admin, &agent, limit, out.as_deref())
+        }
+        AdminCommand::MigrateIdentities {
+            from,
+            primary,
+            apply,
+        } => {
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(migrate_identities(&config, from, primary.as_deref(), apply))
+        }
+        AdminCommand::DecryptExport { .. } => unreachable!("handled before connecting"),
+    }
+}
+
+/// Resolve an agent argument or fail with a helpful message
+fn agent_id(admin: &AdminDb, agent: &str) -> Result<Uuid> {
+    admin
+        .resolve_agent(agent)?
+        .ok_or_else(|| anyhow::anyhow!("no agent '{}' (see `sage-admin agents list`)", agent))
+}
+
+fn list_agents(admin: &AdminDb) -> Result<()> {
+    let agents = admin.list_agents()?;
+    println!("{} agent(s)", agents.len());
+    for agent in agents {
+        let last = agent
+            .last_message_at
+            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{}  {:<6}  {:>6} msgs  last {}  {}{}",
+            agent.id,
+            agent.context_type,
+            agent.message_count,
+            last,
+            agent.identifier,
+            agent
+                .display_name
+                .map(|name| format!(" ({})", name))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+fn show_memory(admin: &AdminDb, agent: &str) -> Result<()> {
+    let id = agent_id(admin, agent)?;
+    for block in admin.blocks(id)? {
+        println!(
+            "== {} ({}/{} chars, v{}{}, updated {})",
+            block.label,
+            block.value.chars().count(),
+            block.char_limit,
+            block.version,
+            if block.read_only { ", read-only" } else { "" },
+            block.updated_at.format("%Y-%m-%d %H:%M")
+        );
+        if let Some(description) = block.description.filter(|d| !d.is_empty()) {
+            println!("# {}", description);
+        }
+        println!("{}\n", block.value);
+    }
+    Ok(())
+}
+
+fn edit_block(admin: &AdminDb, agent: &str, label: &str, value: BlockValue) -> Result<()> {
+    let id = agent_id(admin, agent)?;
+    let value = match value {
+        BlockValue::Text(text) => text,
+        BlockValue::File(path) if path == "-" => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read stdin")?;
+            text
+        }
+        BlockValue::File(path) => {
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?
+        }
+    };
+    let block = admin.set_block(id, label, value.trim_end())?;
+    println!(
+        "Updated block '{}' ({}/{} chars, now v{})",
+        block.label,
+        block.value.chars().count(),
+        block.char_limit,
+        block.version
+    );
+    Ok(())
+}
+
+fn block_history(admin: &AdminDb, agent: &str, label: &str, limit: i64) -> Result<()> {
+    let id = agent_id(admin, agent)?;
+    let revisions = admin.block_revisions(id, label, limit)?;
+    if revisions.is_empty() {
+        println!("No recorded edits of block '{}'", label);
+        return Ok(());
+    }
+    for revision in revisions {
+        println!(
+            "== v{}  {}  {}{}",
+            revision.version,
+            revision.created_at.format("%Y-%m-%d %H:%M"),
+            revision.source,
+            revision
+                .tool_args
+                .map(|args| format!(" {}", args))
+                .unwrap_or_default()
+        );
+        println!("{}\n", revision.value);
+    }
+    Ok(())
+}
+
+async fn rollback_block(
+    config: &Config,
+    admin: &AdminDb,
+    agent: &str,
+    label: &str,
+    version: Option<i32>,
+) -> Result<()> {
+    // Rolling back embeds nothing, so no API key is needed
+    let api_key = config.maple_api_key.clone().unwrap_or_default();
+    let memory = memory_manager(config, admin, agent, &api_key).await?;
+    let restored = memory
+        .blocks()
+        .rollback(label, version, &EditCause::other("sage-admin"))?;
+    println!("Restored block '{}' to version {}", label, restored);
+    println!("Restart Sage for a loaded agent to pick up the rollback.");
+    Ok(())
+}
+
+/// The memory of an agent's main chat (threads share it)
+async fn memory_manager(
+    config: &Config,
+    admin: &AdminDb,
+    agent: &str,
+    api_key: &str,
+) -> Result<MemoryManager> {
+    let id = admin.memory_owner(agent_id(admin, agent)?)?;
+    MemoryManager::new(
+        id,
+        &config.database_url,
+        &config.maple_api_url,
+        api_key,
+        &config.maple_embedding_model,
+    )
+    .await
+}
+
+async fn export_memory(
+    config: &Config,
+    admin: &AdminDb,
+    agent: &str,
+    out: Option<&str>,
+) -> Result<()> {
+    // Exporting embeds nothing, so no API key is needed
+    let api_key = config.maple_api_key.clone().unwrap_or_default();
+    let memory = memory_manager(config, admin, agent, &api_key).await?;
+    let bundle = memory.export()?;
+    let json = serde_json::to_string_pretty(&bundle)?;
+    match out {
+        Some(path) => {
+            std::fs::write(path, json).with_context(|| format!("Failed to write {}", path))?;
+            println!(
+                "Wrote {} block(s), {} passage(s), {} summary(ies) and {} preference(s) to {}",
+                bundle.blocks.len(),
+                bundle.passages.len(),
+                bundle.summaries.len(),
+                bundle.preferences.len(),
+                path
+            );
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+async fn import_memory(config: &Config, admin: &AdminDb, agent: &str, file: &str) -> Result<()> {
+    let json = if file == "-" {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read stdin")?;
+        text
+    } else {
+        std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file))?
+    };
+    let bundle = MemoryBundle::from_json(&json).context("Invalid memory bundle")?;
+    let api_key = config
+        .maple_api_key
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("MAPLE_API_KEY must be set to embed imported memory"))?;
+    let memory = memory_manager(config, admin, agent, api_key).await?;
+    let report = memory.import(&bundle).await?;
+    println!("{}", report.summary());
+    println!("Restart Sage for a loaded agent to pick up imported blocks.");
+    Ok(())
+}
+
+async fn compact_memory(config: &Config, admin: &AdminDb, agent: &str, apply: bool) -> Result<()> {
+    // Compaction is per conversation, so a thread compacts its own history
+    let id = agent_id(admin, agent)?;
+    let owner = admin.memory_owner(id)?;
+    let api_key = match (apply, config.maple_api_key.as_deref()) {
+        (_, Some(key)) => key,
+        (true, None) => anyhow::bail!("MAPLE_API_KEY must be set to summarize"),
+        // Previewing calls no model
+        (false, None) => "",
+    };
+    // The compaction threshold depends on the model's context window
+    model_limits::detect(
+        &config.maple_api_url,
+        api_key,
+        &config.maple_model,
+        (config.model_context_window, config.model_max_output_tokens),
+    )
+    .await;
+    let memory = if owner == id {
+        MemoryManager::new(
+            id,
+            &config.database_url,
+            &config.maple_api_url,
+            api_key,
+            &config.maple_embedding_model,
+        )
+        .await?
+    } else {
+        let blocks = BlockManager::new(owner, MemoryDb::new(&config.database_url)?)?;
+        MemoryManager::new_thread(
+            id,
+            owner,
+            blocks,
+            &config.database_url,
+            &config.maple_api_url,
+            api_key,
+            &config.maple_embedding_model,
+        )
+        .await?
+    };
+
+    let plan = memory.preview_compaction()?;
+    println!("{}", plan.render());
+    if !apply {
+        println!("Dry run; run again with --apply to compact.");
+        return Ok(());
+    }
+
+    SageAgent::configure_lm(&config.maple_api_url, api_key, &config.maple_model).await?;
+    let result = memory.run_compaction().await?;
+    println!(
+        "\nWrote summary {} of messages {} to {}:\n{}",
+        result.id, result.from_sequence_id, result.to_sequence_id, result.summary
+    );
+    Ok(())
+}
+
+async fn search_archival(
+    config: &Config,
+    admin: &AdminDb,
+    agent: &str,
+    query: &str,
+    limit: usize,
+    tag: Option<String>,
+) -> Result<()> {
+    let id = admin.memory_owner(agent_id(admin, agent)?)?;
+    let api_key = config
+        .maple_api_key
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("MAPLE_API_KEY must be set to embed the query"))?;
+    let embedding = EmbeddingService::new(
+        &config.maple_api_url,
+        api_key,
+        &config.maple_embedding_model,
+    );
+    let archival = ArchivalManager::new(id, MemoryDb::new(&config.database_url)?, embedding);
+
+    let results = archival.search(query, limit, tag.map(|t| vec![t])).await?;
+    if results.is_empty() {
+        println!("No matching passages");
+    }
+    for result in results {
+        println!(
+            "[{:.2}] {}  {}  [{}]\n{}\n",
+            result.relevance_score,
+            result.passage.id,
+            result.passage.created_at.format("%Y-%m-%d %H:%M"),
+            result.passage.tags.join(", "),
+            result.passage.content
+        );
+    }
+    Ok(())
+}
+
+fn list_schedules(config: &Config, admin: &AdminDb, agent: &str, all: bool) -> Result<()> {
+    let id = agent_id(admin, agent)?;
+    let scheduler = SchedulerDb::connect(&config.database_url)?;
+    let tasks = scheduler.get_tasks_by_agent(id, (!all).then_some("pending"))?;
+    if tasks.is_empty() {
+        println!("No {}scheduled tasks", if all { "" } else { "pending " });
+    }
+    for task in tasks {
+        println!(
+            "{}  {:<9}  {:<11}  next {} ({}){}  {}",
+            task.id,
+            task.status.as_str(),
+            task.task_type.as_str(),
+            task.next_run_at.format("%Y-%m-%d %H:%M UTC"),
+            task.timezone,
+            task.cron_expression
+                .map(|cron| format!("  cron '{}'", cron))
+                .unwrap_or_default(),
+            task.description
+        );
+    }
+    Ok(())
+}
+
+fn tail_messages(admin: &AdminDb, agent: &str, limit: i64) -> Result<()> {
+    let id = agent_id(admin, agent)?;
+    for message in admin.recent_messages(id, limit)? {
+        println!(
+            "#{} {} {}: {}",
+            message.sequence_id,
+            message.created_at.format("%Y-%m-%d %H:%M:%S"),
+            message.role,
+            message.content
+        );
+        if let Some(text) = message.attachment_text {
+            println!("    [attachment] {}", text);
+        }
+    }
+    Ok(())
+}
+
+fn export_gepa(admin: &AdminDb, agent: &str, limit: i64, out: Option<&str>) -> Result<()> {
+    let id = agent_id(admin, agent)?;
+    let examples = admin.production_examples(id, limit)?;
+    let count = examples.len();
+    let json = serde_json::to_string_pretty(&GepaTrainset::production(examples))?;
+    match out {
+        Some(path) => {
+            std::fs::write(path, json).with_context(|| format!("Failed to write {}", path))?;
+            println!("Wrote {} anonymized example(s) to {}", count, path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+fn decrypt_export(file: &str, out: Option<&str>) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("Failed to read {}", file))?;
+    let passphrase = match std::env::var("SAGE_EXPORT_PASSPHRASE") {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            eprint!("Export passphrase: ");
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .context("Failed to read passphrase")?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    let plaintext = export::decrypt(&passphrase, &data)?;
+
+    let out = out.map(String::from).unwrap_or_else(|| {
+        file.strip_suffix(&format!(".{}", export::ENCRYPTED_EXTENSION))
+            .map(String::from)
+            .unwrap_or_else(|| format!("{}.json", file))
+    });
+    std::fs::write(&out, plaintext).with_context(|| format!("Failed to write {}", out))?;
+    println!("Decrypted to {}", out);
+    Ok(())
+}
+
+async fn migrate_identities(
+    config: &Config,
+    from: Option<Uuid>,
+    primary: Option<&str>,
+    apply: bool,
+) -> Result<()> {
+    let migration = MigrationDb::connect(&config.database_url)?;
+    let source = match from {
+        Some(source) => source,
+        None => {
+            let orphans = migration.orphan_agents()?;
+            match orphans.as_slice() {
+                [] => {
+                    println!("Every agent with messages has a chat; nothing to migrate.");
+                    return Ok(());
+                }
+                _ if orphans.iter().any(|o| o.id.is_nil()) => Uuid::nil(),
+                [orphan] => orphan.id,
+                _ => anyhow::bail!(
+                    "several agents have messages but no chat, pick one with --from:\n{}",
+                    orphans
+                        .iter()
+                        .map(|o| format!("  {}  {} msgs", o.id, o.messages))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                ),
+            }
+        }
+    };
+
+    let plan = migration.plan(source, primary)?;
+    println!("{}", plan.render());
+    if plan.is_empty() {
+        return Ok(());
+    }
+    if !apply {
+        println!("\nDry run; run again with --apply to migrate (stop Sage first).");
+        return Ok(());
+    }
+
+    let scheduler_db = Arc::new(SchedulerDb::connect(&config.database_url)?);
+    let manager = AgentManager::new(config, scheduler_db)?;
+    let mut agents = HashMap::new();
+    for identity in &plan.identities {
+        let (id, _) = manager
+            .get_or_create_agent(
+                &identity.identifier,
+                ContextType::for_identifier(&identity.identifier),
+                None,
+            )
+            .await?;
+        agents.insert(identity.identifier.clone(), id);
+    }
+    let report = migration.apply(&plan, &agents)?;
+    println!(
+        "\nMoved {} message(s) and {} passage(s), wrote {} block(s) to {} agent(s)",
+        report.messages,
+        report.passages,
+        report.blocks,
+        agents.len()
+    );
+    println!("Restart Sage for the agents to pick up their migrated memory.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args("agents list")).unwrap(),
+            AdminCommand::ListAgents
+        );
+        assert_eq!(
+            parse_args(&args("memory edit-block group:abc human --value Alice")).unwrap(),
+            AdminCommand::EditBlock {
+                agent: "group:abc".to_string(),
+                label: "human".to_string(),
+                value: BlockValue::Text("Alice".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_args(&args(
+                "archival search 1a2b dentist appointment --tag email"
+            ))
+            .unwrap(),
+            AdminCommand::SearchArchival {
+                agent: "1a2b".to_string(),
+                query: "dentist appointment".to_string(),
+                limit: DEFAULT_SEARCH_LIMIT,
+                tag: Some("email".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("schedule list 1a2b --all")).unwrap(),
+            AdminCommand::ListSchedules {
+                agent: "1a2b".to_string(),
+                all: true,
+            }
+        );
+        assert_eq!(
+            parse_args(&args("messages tail 1a2b --limit 5")).unwrap(),
+            AdminCommand::TailMessages {
+                agent: "1a2b".to_string(),
+                limit: 5,
+            }
+        );
+        assert!(parse_args(&args("memory edit-block 1a2b human")).is_err());
+        assert_eq!(
+            parse_args(&args("memory export 1a2b --out memory.json")).unwrap(),
+            AdminCommand::ExportMemory {
+                agent: "1a2b".to_string(),
+                out: Some("memory.json".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("memory import 1a2b -")).unwrap(),
+            AdminCommand::ImportMemory {
+                agent: "1a2b".to_string(),
+                file: "-".to_string(),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("memory rollback 1a2b human --version 4")).unwrap(),
+            AdminCommand::RollbackBlock {
+                agent: "1a2b".to_string(),
+                label: "human".to_string(),
+                version: Some(4),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("memory history 1a2b human")).unwrap(),
+            AdminCommand::BlockHistory {
+                agent: "1a2b".to_string(),
+                label: "human".to_string(),
+                limit: DEFAULT_REVISION_LIMIT,
+            }
+        );
+        assert!(parse_args(&args("memory rollback 1a2b human --version last")).is_err());
+        assert!(parse_args(&args("schedule cancel not-a-uuid")).is_err());
+        assert!(parse_args(&args("messages tail 1a2b --limit many")).is_err());
+        assert!(parse_args(&args("messages tail 1a2b --since today")).is_err());
+        assert_eq!(
+            parse_args(&args("export decrypt export.json.sage-enc")).unwrap(),
+            AdminCommand::DecryptExport {
+                file: "export.json.sage-enc".to_string(),
+                out: None,
+            }
+        );
+        assert_eq!(
+            parse_args(&args("gepa export 1a2b --out trainset.json")).unwrap(),
+            AdminCommand::ExportGepa {
+                agent: "1a2b".to_string(),
+                limit: DEFAULT_EXAMPLE_LIMIT,
+                out: Some("trainset.json".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("memory compact 1a2b")).unwrap(),
+            AdminCommand::CompactMemory {
+                agent: "1a2b".to_string(),
+                apply: false,
+            }
+        );
+        assert_eq!(
+            parse_args(&args("migrate identities --primary alice --apply")).unwrap(),
+            AdminCommand::MigrateIdentities {
+                from: None,
+                primary: Some("alice".to_string()),
+                apply: true,
+            }
+        );
+        assert!(parse_args(&args("migrate identities --from nil")).is_err());
+        assert!(parse_args(&args("agents delete")).is_err());
+    }
+}
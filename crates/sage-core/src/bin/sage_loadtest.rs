@@ -0,0 +1,684 @@
+//! Load test for Sage
+//!
+//! Drives synthetic conversations through the same path as `main.rs`: each
+//! message is written to the durable inbox, gets its agent from
+//! `AgentManager::get_or_create_agent` and goes to the per-agent workers, which
+//! run full turns against the real database in `DATABASE_URL`. The chat and
+//! embedding API is mocked in process (an OpenAI-compatible server on a local
+//! port with a fixed delay per request), and replies go to a mock messenger
+//! whose `end_turn` marks the turn as finished.
+//!
+//! Each synthetic user sends its next message once the previous turn has
+//! ended, so `--users` is the number of concurrent turns. The report gives
+//! turn latency percentiles and the connection pool's checkout counters
+//! (`DbConn::stats`), which show contention for database connections.
+//!
+//! Every run creates new agents (`loadtest-<run>-<n>`), so point
+//! `DATABASE_URL` at a scratch database.
+//!
+//! Usage:
+//!   cargo run --release --bin sage-loadtest -- --users 20 --turns 10
+//!   cargo run --release --bin sage-loadtest -- --users 50 --lm-latency-ms 800 --pool-size 20
+
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use sage_core::agent_manager::{AgentManager, ContextType};
+use sage_core::agent_worker::{AgentWorkers, WorkerContext};
+use sage_core::db::{self, DbConn, PoolStats};
+use sage_core::delivery::DeliveryDb;
+use sage_core::durable_inbox::InboxDb;
+use sage_core::feedback::FeedbackDb;
+use sage_core::memory;
+use sage_core::messenger::{IncomingMessage, Messenger};
+use sage_core::sage_agent::SageAgent;
+use sage_core::scheduler::SchedulerDb;
+use sage_core::{model_limits, usage, Config};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+const USAGE: &str = "Usage:
+  sage-loadtest [--users <n>] [--turns <n>] [--lm-latency-ms <ms>] [--pool-size <n>]
+
+  --users          Concurrent synthetic conversations (default 10)
+  --turns          Messages each conversation sends (default 5)
+  --lm-latency-ms  Delay of each mocked chat completion (default 300)
+  --pool-size      Database connections (default DATABASE_POOL_SIZE)
+
+Reads DATABASE_URL and the other settings from the environment or .env like
+Sage. The model API is mocked; every run creates new agents, so use a
+scratch database.";
+
+/// Messages the synthetic users send, in turn
+const SYNTHETIC_MESSAGES: [&str; 6] = [
+    "Hey, how's it going?",
+    "Remind me what we talked about last time.",
+    "I'm planning a trip to Lisbon next month, any tips?",
+    "My sister's birthday is on the 14th, she loves hiking.",
+    "Can you keep track of that for me?",
+    "Thanks, that's all for now.",
+];
+
+/// Embedding size of the `VECTOR(768)` columns
+const EMBEDDING_DIMENSIONS: usize = 768;
+
+/// Longest a user waits for a turn to end before giving up on the rest
+const TURN_WAIT: Duration = Duration::from_secs(300);
+
+/// Load test settings
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    users: usize,
+    turns: usize,
+    lm_latency: Duration,
+    pool_size: Option<u32>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            users: 10,
+            turns: 5,
+            lm_latency: Duration::from_millis(300),
+            pool_size: None,
+        }
+    }
+}
+
+/// Parse the arguments after `sage-loadtest`
+fn parse_args(args: &[String]) -> Result<Options> {
+    let mut options = Options::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let key = arg
+            .strip_prefix("--")
+            .ok_or_else(|| anyhow::anyhow!("unexpected argument '{}'\n\n{}", arg, USAGE))?;
+        let value = iter
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("--{} needs a value", key))?;
+        let number: u64 = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid --{} '{}'", key, value))?;
+        match key {
+            "users" => options.users = number as usize,
+            "turns" => options.turns = number as usize,
+            "lm-latency-ms" => options.lm_latency = Duration::from_millis(number),
+            "pool-size" => options.pool_size = Some(number as u32),
+            _ => anyhow::bail!("unknown option --{}\n\n{}", key, USAGE),
+        }
+    }
+    if options.users == 0 || options.turns == 0 {
+        anyhow::bail!("--users and --turns must be at least 1");
+    }
+    Ok(options)
+}
+
+// ============================================================================
+// Mocked model API
+// ============================================================================
+
+/// Requests served by the mocked model API
+#[derive(Default)]
+struct MockCounters {
+    chat: AtomicU64,
+    embeddings: AtomicU64,
+}
+
+#[derive(Clone)]
+struct MockLm {
+    latency: Duration,
+    counters: Arc<MockCounters>,
+}
+
+/// Output fields a DSRs chat prompt asks for: the `[[ ## field ## ]]` markers
+/// of the system message that the user message doesn't fill in (inputs are
+/// a marker line followed by the value), in order
+fn output_fields(system: &str, user: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    for marker in system.split("[[ ## ").skip(1) {
+        let Some((name, _)) = marker.split_once(" ## ]]") else {
+            continue;
+        };
+        let name = name.trim();
+        if name == "completed"
+            || fields.iter().any(|f| f == name)
+            || user.contains(&format!("[[ ## {} ## ]]\n", name))
+        {
+            continue;
+        }
+        fields.push(name.to_string());
+    }
+    fields
+}
+
+/// Canned value for an output field
+fn canned_value(field: &str) -> String {
+    match field {
+        "messages" => json!(["Sounds good - noted."]).to_string(),
+        "tool_calls" => json!([{"name": "done", "args": {}}]).to_string(),
+        "summary" | "rewritten" => "The user chatted about plans and family.".to_string(),
+        _ => "[]".to_string(),
+    }
+}
+
+/// Text of a chat message's `content` (a string or a list of parts)
+fn message_text(message: &Value) -> String {
+    match &message["content"] {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+async fn chat_completions(State(lm): State<MockLm>, Json(body): Json<Value>) -> Json<Value> {
+    lm.counters.chat.fetch_add(1, Ordering::Relaxed);
+    tokio::time::sleep(lm.latency).await;
+
+    let messages = body["messages"].as_array().cloned().unwrap_or_default();
+    let text_of = |role: &str| {
+        messages
+            .iter()
+            .filter(|m| m["role"] == role)
+            .map(message_text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let system = text_of("system");
+    let user = text_of("user");
+    let mut fields = output_fields(&system, &user);
+    if fields.is_empty() {
+        fields = vec!["messages".to_string(), "tool_calls".to_string()];
+    }
+    let mut content = String::new();
+    for field in &fields {
+        content.push_str(&format!(
+            "[[ ## {} ## ]]\n{}\n\n",
+            field,
+            canned_value(field)
+        ));
+    }
+    content.push_str("[[ ## completed ## ]]");
+
+    let prompt_tokens = (system.len() + user.len()) / 4;
+    let completion_tokens = content.len() / 4;
+    Json(json!({
+        "id": format!("chatcmpl-{}", Uuid::new_v4().simple()),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": body["model"],
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop",
+        }],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    }))
+}
+
+/// Deterministic unit vector for `text`, so equal texts embed the same
+fn mock_embedding(text: &str) -> Vec<f32> {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let mut state = hasher.finish() | 1;
+    let mut vector: Vec<f32> = (0..EMBEDDING_DIMENSIONS)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 2000) as f32 / 1000.0 - 1.0
+        })
+        .collect();
+    let norm = vector
+        .iter()
+        .map(|x| x * x)
+        .sum::<f32>()
+        .sqrt()
+        .max(f32::EPSILON);
+    vector.iter_mut().for_each(|x| *x /= norm);
+    vector
+}
+
+async fn embeddings(State(lm): State<MockLm>, Json(body): Json<Value>) -> Json<Value> {
+    lm.counters.embeddings.fetch_add(1, Ordering::Relaxed);
+    let inputs: Vec<String> = match &body["input"] {
+        Value::String(text) => vec![text.clone()],
+        Value::Array(items) => items
+            .iter()
+            .map(|i| i.as_str().unwrap_or_default().to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let tokens: usize = inputs.iter().map(|i| i.len() / 4).sum();
+    let data: Vec<Value> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, text)| {
+            json!({"object": "embedding", "index": index, "embedding": mock_embedding(text)})
+        })
+        .collect();
+    Json(json!({
+        "object": "list",
+        "data": data,
+        "model": body["model"],
+        "usage": {"prompt_tokens": tokens, "total_tokens": tokens},
+    }))
+}
+
+/// Serve the mocked model API on a free local port; returns its base URL
+async fn spawn_mock_lm(lm: MockLm) -> Result<String> {
+    let app = Router::new()
+        .route("/chat/completions", post(chat_completions))
+        .route("/embeddings", post(embeddings))
+        .with_state(lm);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            eprintln!("Mock model API stopped: {}", e);
+        }
+    });
+    Ok(format!("http://{}", addr))
+}
+
+// ============================================================================
+// Mock messenger
+// ============================================================================
+
+/// Conversations waiting for their turns to end
+#[derive(Default)]
+struct Conversations {
+    turn_ended: std::sync::Mutex<HashMap<String, mpsc::UnboundedSender<()>>>,
+    replies: AtomicU64,
+}
+
+impl Conversations {
+    /// Channel that gets a value each time a turn for `recipient` ends
+    fn watch(&self, recipient: &str) -> mpsc::UnboundedReceiver<()> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        if let Ok(mut turns) = self.turn_ended.lock() {
+            turns.insert(recipient.to_string(), tx);
+        }
+        rx
+    }
+}
+
+/// Messenger that counts replies and reports finished turns
+struct LoadMessenger(Arc<Conversations>);
+
+impl Messenger for LoadMessenger {
+    fn send_message(&self, _recipient: &str, _message: &str) -> Result<()> {
+        self.0.replies.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn send_typing(&self, _recipient: &str, _stop: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_turn(&self, recipient: &str) -> Result<()> {
+        if let Some(tx) = self
+            .0
+            .turn_ended
+            .lock()
+            .ok()
+            .and_then(|turns| turns.get(recipient).cloned())
+        {
+            let _ = tx.send(());
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Load generation and report
+// ============================================================================
+
+/// Turn latencies of one synthetic user
+#[derive(Debug, Default)]
+struct UserResult {
+    latencies: Vec<Duration>,
+    /// Turns that didn't end within `TURN_WAIT`
+    timeouts: usize,
+}
+
+/// Send `turns` messages as one user, each after the previous turn ended
+async fn run_user(
+    identifier: String,
+    turns: usize,
+    tx: mpsc::Sender<IncomingMessage>,
+    mut turn_ended: mpsc::UnboundedReceiver<()>,
+) -> UserResult {
+    let mut result = UserResult::default();
+    for turn in 0..turns {
+        let msg = IncomingMessage {
+            source: identifier.clone(),
+            source_name: Some(format!("Load test {}", identifier)),
+            message: SYNTHETIC_MESSAGES[turn % SYNTHETIC_MESSAGES.len()].to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            reply_to: identifier.clone(),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        if tx.send(msg).await.is_err() {
+            break;
+        }
+        match tokio::time::timeout(TURN_WAIT, turn_ended.recv()).await {
+            Ok(Some(())) => result.latencies.push(started.elapsed()),
+            _ => {
+                result.timeouts += 1;
+                break;
+            }
+        }
+    }
+    result
+}
+
+/// Nearest-rank percentile of sorted durations
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.0}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Everything a run measured
+struct Report {
+    options: Options,
+    latencies: Vec<Duration>,
+    timeouts: usize,
+    elapsed: Duration,
+    replies: u64,
+    chat_requests: u64,
+    embedding_requests: u64,
+    pool: PoolStats,
+    pool_size: u32,
+}
+
+impl Report {
+    fn render(&self) -> String {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        let throughput = sorted.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON);
+        let contended = if self.pool.checkouts == 0 {
+            0.0
+        } else {
+            self.pool.contended as f64 * 100.0 / self.pool.checkouts as f64
+        };
+        [
+            format!(
+                "{} users x {} turns, mocked LM latency {}",
+                self.options.users,
+                self.options.turns,
+                millis(self.options.lm_latency)
+            ),
+            format!(
+                "Turns: {} finished, {} timed out, {:.1}/s over {:.1}s",
+                sorted.len(),
+                self.timeouts,
+                throughput,
+                self.elapsed.as_secs_f64()
+            ),
+            format!(
+                "Turn latency: p50 {}  p90 {}  p99 {}  max {}",
+                millis(percentile(&sorted, 50.0)),
+                millis(percentile(&sorted, 90.0)),
+                millis(percentile(&sorted, 99.0)),
+                millis(sorted.last().copied().unwrap_or_default())
+            ),
+            format!(
+                "Model API: {} chat completions, {} embedding requests; {} replies sent",
+                self.chat_requests, self.embedding_requests, self.replies
+            ),
+            format!(
+                "DB pool ({} max): {} checkouts, {:.1}% found no idle connection, {} exhausted",
+                self.pool_size, self.pool.checkouts, contended, self.pool.exhausted
+            ),
+            format!(
+                "DB checkout wait: mean {:.2}ms  max {}",
+                self.pool.mean_wait().as_secs_f64() * 1000.0,
+                millis(self.pool.max_wait)
+            ),
+        ]
+        .join("\n")
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let options = parse_args(&args)?;
+
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::new(
+            std::env::var("RUST_LOG").unwrap_or_else(|_| "warn".into()),
+        ))
+        .init();
+
+    let counters = Arc::new(MockCounters::default());
+    let api_url = spawn_mock_lm(MockLm {
+        latency: options.lm_latency,
+        counters: counters.clone(),
+    })
+    .await?;
+
+    let mut config = Config::from_env()?;
+    config.maple_api_url = api_url;
+    config.maple_api_key = Some("loadtest".to_string());
+    if let Some(size) = options.pool_size {
+        config.database_pool_size = size;
+    }
+    let api_key = "loadtest";
+
+    db::configure_pool(config.database_pool_size);
+    db::configure_vector_search(config.vector_ef_search);
+    {
+        use diesel::prelude::*;
+        use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+        const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+        let mut conn = diesel::PgConnection::establish(&config.database_url)
+            .context("Failed to connect to DATABASE_URL")?;
+        conn.run_pending_migrations(MIGRATIONS)
+            .map_err(|e| anyhow::anyhow!("Migration failed: {}", e))?;
+    }
+
+    // Known limits, so nothing asks the mock for `GET /models`
+    model_limits::detect(
+        &config.maple_api_url,
+        api_key,
+        &config.maple_model,
+        (
+            config.model_context_window.or(Some(32_768)),
+            config.model_max_output_tokens.or(Some(4_096)),
+        ),
+    )
+    .await;
+    SageAgent::configure_lm(&config.maple_api_url, api_key, &config.maple_model).await?;
+    let usage_db = Arc::new(usage::UsageDb::connect(&config.database_url)?);
+    usage::install(
+        usage_db,
+        usage::Prices::from_config(&config),
+        &config.maple_model,
+    );
+
+    tokio::spawn(memory::run_embedding_worker(
+        memory::MemoryDb::new(&config.database_url)?,
+        memory::EmbeddingService::new(
+            &config.maple_api_url,
+            api_key,
+            &config.maple_embedding_model,
+        ),
+    ));
+
+    let scheduler_db = Arc::new(SchedulerDb::connect(&config.database_url)?);
+    let agent_manager = Arc::new(AgentManager::new(&config, scheduler_db)?);
+    let inbox_db = Arc::new(InboxDb::connect(&config.database_url)?);
+    let conversations = Arc::new(Conversations::default());
+    let messenger: Arc<dyn Messenger> = Arc::new(LoadMessenger(conversations.clone()));
+    let mut workers = AgentWorkers::new(Arc::new(WorkerContext {
+        config: Arc::new(config.clone()),
+        agent_manager: agent_manager.clone(),
+        messenger,
+        inbox_db: inbox_db.clone(),
+        delivery_db: Arc::new(DeliveryDb::connect(&config.database_url)?),
+        feedback_db: Arc::new(FeedbackDb::connect(&config.database_url)?),
+    }));
+
+    let run_id = Uuid::new_v4().simple().to_string();
+    println!(
+        "Load test {}: {} users, {} turns each (agents loadtest-{}-<n>)",
+        &run_id[..8],
+        options.users,
+        options.turns,
+        &run_id[..8]
+    );
+
+    // Dispatch like the main loop: durable inbox, agent lookup, worker
+    let (tx, mut rx) = mpsc::channel::<IncomingMessage>(options.users.max(1) * 2);
+    let dispatcher = tokio::spawn(async move {
+        while let Some(mut msg) = rx.recv().await {
+            match inbox_db.enqueue(&msg) {
+                Ok(id) => msg.inbox_ids.push(id),
+                Err(e) => eprintln!("Failed to persist message: {}", e),
+            }
+            match agent_manager
+                .get_or_create_agent(
+                    &msg.reply_to,
+                    ContextType::Direct,
+                    msg.source_name.as_deref(),
+                )
+                .await
+            {
+                Ok((agent_id, agent)) => workers.dispatch(agent_id, agent, msg),
+                Err(e) => eprintln!("Failed to get/create agent for {}: {}", msg.reply_to, e),
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let users: Vec<_> = (0..options.users)
+        .map(|n| {
+            let identifier = format!("loadtest-{}-{}", &run_id[..8], n);
+            let turn_ended = conversations.watch(&identifier);
+            tokio::spawn(run_user(identifier, options.turns, tx.clone(), turn_ended))
+        })
+        .collect();
+    drop(tx);
+
+    let mut latencies = Vec::new();
+    let mut timeouts = 0;
+    for user in users {
+        let result = user.await?;
+        latencies.extend(result.latencies);
+        timeouts += result.timeouts;
+    }
+    let elapsed = started.elapsed();
+    dispatcher.abort();
+
+    let report = Report {
+        options,
+        latencies,
+        timeouts,
+        elapsed,
+        replies: conversations.replies.load(Ordering::Relaxed),
+        chat_requests: counters.chat.load(Ordering::Relaxed),
+        embedding_requests: counters.embeddings.load(Ordering::Relaxed),
+        pool: DbConn::connect(&config.database_url)?.stats(),
+        pool_size: config.database_pool_size,
+    };
+    println!("{}", report.render());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&[]).unwrap(), Options::default());
+        let options = parse_args(&args(&[
+            "--users",
+            "50",
+            "--turns",
+            "3",
+            "--lm-latency-ms",
+            "800",
+            "--pool-size",
+            "20",
+        ]))
+        .unwrap();
+        assert_eq!(options.users, 50);
+        assert_eq!(options.turns, 3);
+        assert_eq!(options.lm_latency, Duration::from_millis(800));
+        assert_eq!(options.pool_size, Some(20));
+
+        assert!(parse_args(&args(&["--users"])).is_err());
+        assert!(parse_args(&args(&["--users", "0"])).is_err());
+        assert!(parse_args(&args(&["--speed", "3"])).is_err());
+        assert!(parse_args(&args(&["users"])).is_err());
+    }
+
+    #[test]
+    fn test_output_fields() {
+        let system = "Your input fields are:\n[[ ## persona_block ## ]]\n[[ ## input ## ]]\n\
+                      Your output fields are:\n[[ ## messages ## ]]\n[[ ## tool_calls ## ]]\n\
+                      [[ ## completed ## ]]";
+        let user = "[[ ## persona_block ## ]]\nI am Sage\n\n[[ ## input ## ]]\nhi\n\n\
+                    Respond with the corresponding output fields, starting with the field \
+                    `[[ ## messages ## ]]`, then `[[ ## tool_calls ## ]]`";
+        assert_eq!(output_fields(system, user), vec!["messages", "tool_calls"]);
+        assert!(output_fields("no markers", "").is_empty());
+    }
+
+    #[test]
+    fn test_mock_embedding() {
+        let a = mock_embedding("hello");
+        assert_eq!(a.len(), EMBEDDING_DIMENSIONS);
+        assert_eq!(a, mock_embedding("hello"));
+        assert_ne!(a, mock_embedding("goodbye"));
+        let norm: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_millis(100));
+        assert_eq!(percentile(&sorted[..1], 99.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,266 @@
+//! Capabilities
+//!
+//! Asked "what can you do?", the model describes features from its training
+//! or its instruction, including ones this deployment doesn't have (no web
+//! search without `BRAVE_API_KEY`, no shell for a role that denies it). The
+//! `capabilities` tool answers from the running configuration instead: the
+//! messenger and model, the background features that are on (`Deployment`,
+//! built once from `Config`), the role of the conversation with its budget,
+//! the persona block, and the tools actually registered for this agent.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::{Config, MessengerType};
+use crate::memory::BlockManager;
+use crate::roles::{Role, RolePolicy};
+use crate::sage_agent::{Tool, ToolResult};
+use crate::usage;
+
+/// Longest persona excerpt shown
+const PERSONA_CHARS: usize = 300;
+
+/// What the whole deployment offers, the same for every agent
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub messenger: &'static str,
+    pub model: String,
+    /// Things that happen without a tool call, one line each
+    pub features: Vec<String>,
+}
+
+impl Deployment {
+    pub fn from_config(config: &Config) -> Self {
+        let mut features = Vec::new();
+        features
+            .push("Images sent in the chat are described (receipts become expenses)".to_string());
+        if config.speech_api_url.is_some() {
+            features.push("Voice messages are transcribed".to_string());
+        }
+        if config.fact_extraction {
+            features
+                .push("Facts from the user's messages are stored as structured memory".to_string());
+        }
+        if let Some(hour) = config.occasion_checkin_hour {
+            features.push(format!(
+                "Birthday and anniversary check-ins at {}:00 the user's time",
+                hour
+            ));
+        }
+        if config.commitment_tracking {
+            features
+                .push("Promises to get back to the user are followed up automatically".to_string());
+        }
+        if config.self_maintenance_cron.is_some() {
+            features.push("Weekly self-maintenance of memory and schedules".to_string());
+        }
+        if config.ingest_email_mailbox.is_some() {
+            features.push(
+                "Forwarded confirmation emails are filed as expenses and travel plans".to_string(),
+            );
+        }
+        if config.brave_api_key.is_none() {
+            features.push("No web search (no search API key configured)".to_string());
+        }
+        Self {
+            messenger: messenger_name(&config.messenger_type),
+            model: config.maple_model.clone(),
+            features,
+        }
+    }
+}
+
+/// Display name of a messenger
+pub fn messenger_name(messenger: &MessengerType) -> &'static str {
+    match messenger {
+        MessengerType::Signal => "Signal",
+        MessengerType::Marmot => "Marmot (Nostr)",
+        MessengerType::Email => "email",
+        MessengerType::Webhook => "HTTP webhook",
+    }
+}
+
+/// First sentence of a tool description
+fn summary(description: &str) -> &str {
+    match description.find(". ") {
+        Some(end) => &description[..end + 1],
+        None => description,
+    }
+}
+
+/// The capabilities report
+pub fn describe(
+    deployment: &Deployment,
+    role: Role,
+    policy: &RolePolicy,
+    spent_today: Option<f64>,
+    persona: Option<&str>,
+    tools: &[(String, String)],
+) -> String {
+    let mut lines = vec![format!(
+        "Deployment: {} messenger, chat model {}",
+        deployment.messenger, deployment.model
+    )];
+    if let Some(persona) = persona.map(str::trim).filter(|p| !p.is_empty()) {
+        let excerpt: String = persona.chars().take(PERSONA_CHARS).collect();
+        let more = if excerpt.len() < persona.len() {
+            "…"
+        } else {
+            ""
+        };
+        lines.push(format!(
+            "Persona: {}{}",
+            excerpt.split_whitespace().collect::<Vec<_>>().join(" "),
+            more
+        ));
+    }
+
+    let budget = match (policy.daily_budget_usd, spent_today) {
+        (Some(budget), Some(spent)) => {
+            format!("daily budget ${:.2}, ${:.2} spent today", budget, spent)
+        }
+        (Some(budget), None) => format!("daily budget ${:.2}", budget),
+        (None, _) => "no daily budget".to_string(),
+    };
+    lines.push(format!(
+        "This conversation: role {} ({}; proactive messages {}{})",
+        role.as_str(),
+        budget,
+        if policy.proactive { "on" } else { "off" },
+        if policy.ephemeral {
+            "; guest context without long-term memory"
+        } else {
+            ""
+        }
+    ));
+
+    if !deployment.features.is_empty() {
+        lines.push("Features:".to_string());
+        lines.extend(deployment.features.iter().map(|f| format!("- {}", f)));
+    }
+    lines.push(format!("Tools ({}):", tools.len()));
+    lines.extend(
+        tools
+            .iter()
+            .map(|(name, description)| format!("- {}: {}", name, summary(description))),
+    );
+    if !policy.denied_tools.is_empty() {
+        lines.push(format!(
+            "Not available to this role: {}",
+            policy.denied_tools.join(", ")
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Describe what this deployment and conversation can do
+pub struct CapabilitiesTool {
+    deployment: Arc<Deployment>,
+    role: Role,
+    policy: RolePolicy,
+    /// Name and description of each registered tool
+    tools: Vec<(String, String)>,
+    blocks: BlockManager,
+    agent_id: Uuid,
+}
+
+impl CapabilitiesTool {
+    pub fn new(
+        deployment: Arc<Deployment>,
+        role: Role,
+        policy: RolePolicy,
+        tools: Vec<(String, String)>,
+        blocks: BlockManager,
+        agent_id: Uuid,
+    ) -> Self {
+        Self {
+            deployment,
+            role,
+            policy,
+            tools,
+            blocks,
+            agent_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for CapabilitiesTool {
+    fn name(&self) -> &str {
+        "capabilities"
+    }
+
+    fn description(&self) -> &str {
+        "List what you can actually do in this deployment: messenger, enabled features, this conversation's role and budget, your persona, and every tool you have. Call this before answering 'what can you do?' instead of guessing."
+    }
+
+    fn args_schema(&self) -> &str {
+        "{}"
+    }
+
+    async fn execute(&self, _args: &HashMap<String, String>) -> Result<ToolResult> {
+        let persona = self.blocks.get("persona").map(|b| b.value);
+        let spent = self
+            .policy
+            .daily_budget_usd
+            .and_then(|_| usage::spent_today(self.agent_id));
+        Ok(ToolResult::success(describe(
+            &self.deployment,
+            self.role,
+            &self.policy,
+            spent,
+            persona.as_deref(),
+            &self.tools,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let deployment = Deployment {
+            messenger: "Signal",
+            model: "llama".to_string(),
+            features: vec!["Voice messages are transcribed".to_string()],
+        };
+        let policy = RolePolicy {
+            denied_tools: vec!["shell".to_string()],
+            daily_budget_usd: Some(1.0),
+            proactive: false,
+            ephemeral: false,
+        };
+        let tools = vec![
+            (
+                "web_search".to_string(),
+                "Search the web. Returns results.".to_string(),
+            ),
+            ("done".to_string(), "End the turn".to_string()),
+        ];
+        let report = describe(
+            &deployment,
+            Role::Trusted,
+            &policy,
+            Some(0.25),
+            Some("I am Sage,\na friend."),
+            &tools,
+        );
+        assert_eq!(
+            report,
+            "Deployment: Signal messenger, chat model llama\n\
+             Persona: I am Sage, a friend.\n\
+             This conversation: role trusted (daily budget $1.00, $0.25 spent today; proactive messages off)\n\
+             Features:\n\
+             - Voice messages are transcribed\n\
+             Tools (2):\n\
+             - web_search: Search the web.\n\
+             - done: End the turn\n\
+             Not available to this role: shell"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Source Citations
+//!
+//! Answers built from web results are easier to trust when they say where
+//! they came from. During a turn, every URL returned by `web_search`,
+//! `web_fetch` or `deep_research` gets a stable number in a `SourceLedger`,
+//! and the tool result shown to the agent ends with that numbering so it can
+//! cite claims as `[n]`. Before a message goes out, the sources it actually
+//! cites are appended as a compact list (`CITE_SOURCES`, default on).
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Tools whose output carries web sources
+pub const SOURCE_TOOLS: &[&str] = &["web_search", "web_fetch", "deep_research"];
+
+/// Sources numbered per tool result (the rest are unlikely to be cited)
+const MAX_SOURCES_PER_RESULT: usize = 8;
+
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>()\[\]{}"'`|]+"#).unwrap());
+static CITATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[(\d{1,2})\]").unwrap());
+
+/// Distinct URLs in a tool's output, in order of appearance
+pub fn extract_urls(output: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for m in URL.find_iter(output) {
+        let url = m
+            .as_str()
+            .trim_end_matches(['.', ',', ';', ':', '!', '?', '*']);
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+/// Source numbers cited in a message, in order, without repeats
+pub fn cited_numbers(message: &str) -> Vec<usize> {
+    let mut cited = Vec::new();
+    for cap in CITATION.captures_iter(message) {
+        if let Ok(n) = cap[1].parse::<usize>() {
+            if !cited.contains(&n) {
+                cited.push(n);
+            }
+        }
+    }
+    cited
+}
+
+/// Web sources seen during the current turn, numbered from 1
+#[derive(Debug, Clone, Default)]
+pub struct SourceLedger {
+    urls: Vec<String>,
+}
+
+impl SourceLedger {
+    /// Forget the previous turn's sources
+    pub fn clear(&mut self) {
+        self.urls.clear();
+    }
+
+    /// Number the sources in a tool result and return the note telling the
+    /// agent how to cite them (None if the output had no URLs)
+    pub fn track(&mut self, output: &str) -> Option<String> {
+        let numbered: Vec<String> = extract_urls(output)
+            .into_iter()
+            .take(MAX_SOURCES_PER_RESULT)
+            .map(|url| {
+                let n = match self.urls.iter().position(|u| *u == url) {
+                    Some(i) => i + 1,
+                    None => {
+                        self.urls.push(url.clone());
+                        self.urls.len()
+                    }
+                };
+                format!("[{}] {}", n, url)
+            })
+            .collect();
+        if numbered.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "[Sources - cite facts from these as [n] in your messages; a sources list is added automatically]\n{}",
+            numbered.join("\n")
+        ))
+    }
+
+    /// Append the sources a message cites. Numbers that don't match a source
+    /// (e.g. "[2]" in a list) are ignored; a message citing nothing is
+    /// returned unchanged.
+    pub fn cite(&self, message: &str) -> String {
+        let lines: Vec<String> = cited_numbers(message)
+            .into_iter()
+            .filter_map(|n| {
+                let url = self.urls.get(n.checked_sub(1)?)?;
+                Some(format!("[{}] {}", n, url))
+            })
+            .collect();
+        if lines.is_empty() {
+            return message.to_string();
+        }
+        format!("{}\n\nSources:\n{}", message.trim_end(), lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_urls() {
+        let output = "1. Rust\n   URL: https://www.rust-lang.org/\n   Fast.\n\
+                      See (https://doc.rust-lang.org/book/ch01.html). Again: https://www.rust-lang.org/, \
+                      and **https://blog.rust-lang.org/2024/**";
+        assert_eq!(
+            extract_urls(output),
+            vec![
+                "https://www.rust-lang.org/",
+                "https://doc.rust-lang.org/book/ch01.html",
+                "https://blog.rust-lang.org/2024/",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ledger_numbers_and_cites() {
+        let mut ledger = SourceLedger::default();
+        let note = ledger
+            .track("URL: https://a.example/x\nURL: https://b.example/y")
+            .unwrap();
+        assert!(note.ends_with("[1] https://a.example/x\n[2] https://b.example/y"));
+
+        // A URL seen again keeps its number
+        let note = ledger
+            .track("https://c.example/z and https://a.example/x")
+            .unwrap();
+        assert!(note.ends_with("[3] https://c.example/z\n[1] https://a.example/x"));
+        assert_eq!(ledger.track("no links here"), None);
+
+        assert_eq!(
+            ledger.cite("Rust is fast [3][1]. Also safe [1]. See item [9]."),
+            "Rust is fast [3][1]. Also safe [1]. See item [9].\n\nSources:\n[3] https://c.example/z\n[1] https://a.example/x"
+        );
+        assert_eq!(ledger.cite("No citations."), "No citations.");
+
+        ledger.clear();
+        assert_eq!(ledger.cite("Fast [1]."), "Fast [1].");
+    }
+}
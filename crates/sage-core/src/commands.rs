@@ -0,0 +1,332 @@
+//! Owner Chat Commands
+//!
+//! Messages starting with `/` from an owner (`OWNER_USERS`) are handled here
+//! instead of by the agent - an escape hatch for when it misbehaves:
+//!
+//! - `/status`: the chat's agent, whether a turn is running, queued messages,
+//!   pending scheduled tasks and memory block usage
+//! - `/reset`: stop the running turn after its current step and drop queued
+//!   messages
+//! - `/forget <label>`: wipe a core memory block
+//! - `/tasks`: list pending scheduled tasks; `/tasks cancel <id>` cancels one
+//!   (an id prefix from the list is enough)
+//! - `/occasions`: birthday and anniversary check-ins found in memory (see
+//!   `occasions`); `/occasions off` and `/occasions on` turn them all off or
+//!   back on
+//! - `/help`: list the commands
+//!
+//! Commands apply to the chat they are sent in (its active `/topic` thread in
+//! a direct chat). Anything else starting with `/` goes to the agent as usual.
+
+use anyhow::Result;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::agent_manager::{AgentManager, ContextType};
+use crate::durable_inbox::InboxDb;
+use crate::memory::EditCause;
+use crate::messenger::IncomingMessage;
+use crate::occasions;
+
+/// An owner chat command
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnerCommand {
+    Status,
+    Reset,
+    Forget(String),
+    Tasks,
+    CancelTask(String),
+    Occasions,
+    SetOccasions(bool),
+    Help,
+}
+
+const HELP: &str = "Owner commands:
+/status - agent, running turn, queue, tasks and memory usage
+/reset - stop the current turn and drop queued messages
+/forget <block> - wipe a core memory block
+/tasks - pending scheduled tasks; /tasks cancel <id> cancels one
+/occasions - birthday and anniversary check-ins; /occasions off|on";
+
+/// Whether `user_id` may use owner commands. There is no wildcard: owners
+/// are listed by id.
+pub fn is_owner(user_id: &str, owners: &[String]) -> bool {
+    owners.iter().any(|owner| owner == user_id)
+}
+
+/// Parse an owner command. Returns None for any other message.
+pub fn parse_owner_command(text: &str) -> Option<OwnerCommand> {
+    let text = text.trim();
+    let rest = text.strip_prefix('/')?;
+    let (name, args) = match rest.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (rest, ""),
+    };
+    let words: Vec<&str> = args.split_whitespace().collect();
+
+    match (name, words.as_slice()) {
+        ("status", []) => Some(OwnerCommand::Status),
+        ("reset", []) => Some(OwnerCommand::Reset),
+        ("forget", [label]) => Some(OwnerCommand::Forget(label.to_lowercase())),
+        ("tasks", []) => Some(OwnerCommand::Tasks),
+        ("tasks", ["cancel", id]) => Some(OwnerCommand::CancelTask(id.to_lowercase())),
+        ("occasions", []) => Some(OwnerCommand::Occasions),
+        ("occasions", ["off"]) => Some(OwnerCommand::SetOccasions(false)),
+        ("occasions", ["on"]) => Some(OwnerCommand::SetOccasions(true)),
+        ("help", []) => Some(OwnerCommand::Help),
+        _ => None,
+    }
+}
+
+/// Run an owner command for the chat `msg` came from (`identifier` is the
+/// agent it is handled under) and return the reply
+pub async fn run(
+    command: OwnerCommand,
+    msg: &IncomingMessage,
+    identifier: &str,
+    agent_manager: &AgentManager,
+    inbox_db: &InboxDb,
+) -> Result<String> {
+    info!(
+        "Owner command {:?} from {} in {}",
+        command, msg.source, identifier
+    );
+    if command == OwnerCommand::Help {
+        return Ok(HELP.to_string());
+    }
+
+    let (agent_id, agent) = agent_manager
+        .get_or_create_agent(
+            identifier,
+            ContextType::for_identifier(&msg.reply_to),
+            msg.source_name.as_deref(),
+        )
+        .await?;
+    let inbox = agent_manager.inbox(agent_id);
+
+    match command {
+        OwnerCommand::Status => {
+            let tasks = pending_tasks(agent_manager, agent_id)?;
+            let mut lines = vec![
+                format!("Sage {} - agent {}", env!("CARGO_PKG_VERSION"), agent_id),
+                format!(
+                    "Turn: {}",
+                    if inbox.is_running() {
+                        "running"
+                    } else {
+                        "idle"
+                    }
+                ),
+                format!("Queued messages: {}", inbox.pending_count()),
+                format!("Pending scheduled tasks: {}", tasks.len()),
+            ];
+            // Waits for a running step to finish, like any memory access
+            let agent = agent.lock().await;
+            if let Some(memory) = agent.memory() {
+                lines.push("Memory blocks:".to_string());
+                for block in memory.blocks().all() {
+                    lines.push(format!(
+                        "- {}: {}/{} chars{}",
+                        block.label,
+                        block.value.chars().count(),
+                        block.char_limit,
+                        if block.read_only { " (read-only)" } else { "" }
+                    ));
+                }
+            }
+            Ok(lines.join("\n"))
+        }
+        OwnerCommand::Reset => {
+            let (dropped, was_running) = inbox.request_stop();
+            let inbox_ids: Vec<Uuid> = dropped
+                .iter()
+                .flat_map(|m| m.inbox_ids.iter().copied())
+                .collect();
+            if let Err(e) = inbox_db.ack(&inbox_ids) {
+                warn!("Failed to ack dropped messages: {}", e);
+            }
+            Ok(match (was_running, dropped.len()) {
+                (false, 0) => "Nothing to reset - I'm idle.".to_string(),
+                (running, queued) => format!(
+                    "Reset: {}dropped {} queued message(s).",
+                    if running {
+                        "stopping the current turn after its current step, "
+                    } else {
+                        ""
+                    },
+                    queued
+                ),
+            })
+        }
+        OwnerCommand::Forget(label) => {
+            let agent = agent.lock().await;
+            let Some(memory) = agent.memory() else {
+                return Ok("This agent has no memory.".to_string());
+            };
+            let blocks = memory.blocks();
+            if !blocks.has(&label) {
+                let labels: Vec<String> = blocks.all().into_iter().map(|b| b.label).collect();
+                return Ok(format!(
+                    "No block '{}'. Blocks: {}",
+                    label,
+                    labels.join(", ")
+                ));
+            }
+            blocks.update(&label, "", &EditCause::other("/forget"))?;
+            Ok(format!("Wiped the '{}' memory block.", label))
+        }
+        OwnerCommand::Tasks => {
+            let tasks = pending_tasks(agent_manager, agent_id)?;
+            if tasks.is_empty() {
+                return Ok("No pending scheduled tasks.".to_string());
+            }
+            let mut lines = vec![format!("{} pending task(s):", tasks.len())];
+            for task in tasks {
+                lines.push(format!(
+                    "{}  {}{}  {}",
+                    &task.id.to_string()[..8],
+                    task.next_run_at.format("%Y-%m-%d %H:%M UTC"),
+                    task.cron_expression
+                        .map(|cron| format!(" (cron '{}')", cron))
+                        .unwrap_or_default(),
+                    task.description
+                ));
+            }
+            lines.push("/tasks cancel <id> cancels one.".to_string());
+            Ok(lines.join("\n"))
+        }
+        OwnerCommand::CancelTask(prefix) => {
+            let tasks = pending_tasks(agent_manager, agent_id)?;
+            let matches: Vec<_> = tasks
+                .iter()
+                .filter(|t| t.id.to_string().starts_with(&prefix))
+                .collect();
+            match matches.as_slice() {
+                [] => Ok(format!("No pending task '{}' (see /tasks).", prefix)),
+                [task] => {
+                    if agent_manager.scheduler_db().cancel_task(task.id)? {
+                        Ok(format!("Cancelled: {}", task.description))
+                    } else {
+                        Ok(format!("Task '{}' is no longer pending.", prefix))
+                    }
+                }
+                _ => Ok(format!(
+                    "'{}' matches {} tasks - use more of the id.",
+                    prefix,
+                    matches.len()
+                )),
+            }
+        }
+        OwnerCommand::Occasions => {
+            let agent = agent.lock().await;
+            let Some(memory) = agent.memory() else {
+                return Ok("This agent has no memory.".to_string());
+            };
+            if agent_manager.occasion_checkin_hour().is_none() {
+                return Ok("Check-ins are off (OCCASION_CHECKIN_HOUR).".to_string());
+            }
+            let lines = occasions::list(
+                &agent_manager.scheduler_db(),
+                memory.facts().core_agent_id(),
+            )?;
+            let state = if occasions::enabled(memory) {
+                "/occasions off turns them all off; /tasks cancel <id> skips one."
+            } else {
+                "Check-ins are off for this chat - /occasions on turns them back on."
+            };
+            if lines.is_empty() {
+                return Ok(format!(
+                    "No birthdays or anniversaries in memory yet. {}",
+                    state
+                ));
+            }
+            Ok(format!(
+                "{} occasion(s):\n{}\n{}",
+                lines.len(),
+                lines.join("\n"),
+                state
+            ))
+        }
+        OwnerCommand::SetOccasions(on) => {
+            let agent = agent.lock().await;
+            let Some(memory) = agent.memory() else {
+                return Ok("This agent has no memory.".to_string());
+            };
+            let Some(hour) = agent_manager.occasion_checkin_hour() else {
+                return Ok("Check-ins are off (OCCASION_CHECKIN_HOUR).".to_string());
+            };
+            memory.db().preferences().set(
+                memory.facts().core_agent_id(),
+                occasions::PREFERENCE,
+                if on { "on" } else { "off" },
+            )?;
+            let report = occasions::sync_memory(&agent_manager.scheduler_db(), memory, hour)?;
+            Ok(if on {
+                format!(
+                    "Birthday and anniversary check-ins are on ({} scheduled).",
+                    report.added
+                )
+            } else {
+                format!(
+                    "Birthday and anniversary check-ins are off ({} removed).",
+                    report.retired
+                )
+            })
+        }
+        OwnerCommand::Help => unreachable!("answered above"),
+    }
+}
+
+/// Pending tasks of an agent, soonest first
+fn pending_tasks(
+    agent_manager: &AgentManager,
+    agent_id: Uuid,
+) -> Result<Vec<crate::scheduler::ScheduledTask>> {
+    let mut tasks = agent_manager
+        .scheduler_db()
+        .get_tasks_by_agent(agent_id, Some("pending"))?;
+    tasks.sort_by_key(|t| t.next_run_at);
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_owner_command() {
+        assert_eq!(parse_owner_command(" /status "), Some(OwnerCommand::Status));
+        assert_eq!(parse_owner_command("/reset"), Some(OwnerCommand::Reset));
+        assert_eq!(
+            parse_owner_command("/forget Human"),
+            Some(OwnerCommand::Forget("human".to_string()))
+        );
+        assert_eq!(parse_owner_command("/tasks"), Some(OwnerCommand::Tasks));
+        assert_eq!(
+            parse_owner_command("/tasks cancel 1A2B3C4D"),
+            Some(OwnerCommand::CancelTask("1a2b3c4d".to_string()))
+        );
+        assert_eq!(
+            parse_owner_command("/occasions"),
+            Some(OwnerCommand::Occasions)
+        );
+        assert_eq!(
+            parse_owner_command("/occasions off"),
+            Some(OwnerCommand::SetOccasions(false))
+        );
+        assert_eq!(parse_owner_command("/occasions maybe"), None);
+        assert_eq!(parse_owner_command("/forget"), None);
+        assert_eq!(parse_owner_command("/statusbar"), None);
+        assert_eq!(parse_owner_command("/topic budget"), None);
+        assert_eq!(parse_owner_command("reset please"), None);
+    }
+
+    #[test]
+    fn test_is_owner() {
+        let owners = vec!["uuid-1".to_string()];
+        assert!(is_owner("uuid-1", &owners));
+        assert!(!is_owner("uuid-2", &owners));
+        // No wildcard
+        assert!(!is_owner("uuid-2", &["*".to_string()]));
+    }
+}
@@ -0,0 +1,364 @@
+//! Commitments
+//!
+//! Sage often promises to come back to something ("I'll look into that and
+//! get back to you"), but a turn ends when its messages are sent, so nothing
+//! made it keep the promise. After a turn whose replies sound like one
+//! (`mentions_promise`), a background model pass (`DetectCommitments`) lists
+//! the commitments the replies make, each with a follow-up time. They are
+//! stored in the `commitments` table with a one-off `commitment` task.
+//!
+//! When the task fires and the commitment is still open, the agent runs a
+//! follow-up turn (`follow_up_prompt`) with its tools, so it can do what it
+//! said and send the result, or stay quiet if the conversation shows it
+//! already did. The commitment is then marked `kept`. Roles without
+//! proactive messages get no follow-ups (`COMMITMENT_TRACKING` turns the
+//! whole thing off).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use dspy_rs::{BamlType, Predict, Signature};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::scheduler::{CommitmentPayload, SchedulerDb, TaskPayload, TaskType};
+use crate::schema::commitments;
+use crate::usage::{self, CallKind};
+
+/// Follow-up delay when the model gives none
+pub const DEFAULT_FOLLOW_UP_HOURS: f64 = 24.0;
+
+/// Earliest and latest follow-up, in hours from the promise
+const MIN_FOLLOW_UP_HOURS: f64 = 0.25;
+const MAX_FOLLOW_UP_HOURS: f64 = 7.0 * 24.0;
+
+/// Most commitments recorded from one turn
+const MAX_PER_TURN: usize = 3;
+
+/// Longest description stored
+const MAX_DESCRIPTION_CHARS: usize = 200;
+
+/// Most steps of a follow-up turn
+pub const FOLLOW_UP_STEPS: usize = 6;
+
+/// Phrases a promise is made with; replies without any of them are not
+/// worth a model call
+const PROMISE_MARKERS: &[&str] = &[
+    "i'll",
+    "i will",
+    "i'm going to",
+    "i am going to",
+    "let me",
+    "get back to you",
+    "follow up",
+    "circle back",
+    "remind you",
+];
+
+/// Instruction for the detection DSRs signature
+pub const DETECT_INSTRUCTION: &str = r#"You keep track of promises a personal assistant makes to its user. Read the assistant's replies from its latest turn and list every commitment to do something LATER, after this turn: looking something up and getting back to the user, checking on something, following up on a plan, sending something. Ignore things the replies already did, offers the user has not taken up ("let me know if you want..."), and reminders the assistant already scheduled with a tool.
+
+For each commitment give a short description of what the assistant will do ("check whether the museum is open on Monday"), the sentence of the reply that made the promise, and in how many hours to follow up: when the user was promised an answer ("tonight", "tomorrow morning", "in an hour") use that, counting from the current time; otherwise pick a sensible delay, usually 24 hours. Skip commitments that are already open. Return none if the replies make no commitment."#;
+
+/// A commitment as returned by the detection model
+#[derive(Clone, Debug, Default, BamlType)]
+pub struct DetectedCommitment {
+    /// What the assistant will do
+    pub description: String,
+    /// The sentence that made the promise
+    pub promise: String,
+    /// Hours from now to follow up
+    pub follow_up_hours: f64,
+}
+
+/// DSRs signature for detecting commitments in the assistant's replies
+#[derive(Signature, Clone, Debug)]
+pub struct DetectCommitments {
+    #[input(desc = "Current date and time in the user's timezone")]
+    pub current_time: String,
+
+    #[input(desc = "Commitments already open, one per line")]
+    pub open_commitments: String,
+
+    #[input(desc = "The user's message the assistant replied to")]
+    pub user_message: String,
+
+    #[input(desc = "The assistant's replies this turn")]
+    pub replies: String,
+
+    #[output(desc = "Commitments the replies make (can be empty)")]
+    pub commitments: Vec<DetectedCommitment>,
+}
+
+/// A stored commitment
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = commitments)]
+#[allow(dead_code)]
+pub struct Commitment {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub description: String,
+    pub promise: String,
+    pub due_at: DateTime<Utc>,
+    pub task_id: Option<Uuid>,
+    pub status: String,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Diesel model for inserting a new commitment
+#[derive(Insertable)]
+#[diesel(table_name = commitments)]
+struct NewCommitment<'a> {
+    agent_id: Uuid,
+    description: &'a str,
+    promise: &'a str,
+    due_at: DateTime<Utc>,
+}
+
+/// Whether any reply contains a phrase a promise is made with
+pub fn mentions_promise(replies: &[String]) -> bool {
+    replies.iter().any(|reply| {
+        let reply = reply.to_lowercase().replace('’', "'");
+        PROMISE_MARKERS.iter().any(|marker| reply.contains(marker))
+    })
+}
+
+/// When to follow up on a commitment made at `now`: `hours` later, kept
+/// between 15 minutes and a week (the default delay when not positive)
+pub fn follow_up_at(now: DateTime<Utc>, hours: f64) -> DateTime<Utc> {
+    let hours = if hours.is_finite() && hours > 0.0 {
+        hours.clamp(MIN_FOLLOW_UP_HOURS, MAX_FOLLOW_UP_HOURS)
+    } else {
+        DEFAULT_FOLLOW_UP_HOURS
+    };
+    now + Duration::minutes((hours * 60.0).round() as i64)
+}
+
+/// Instruction for the agent's follow-up turn
+pub fn follow_up_prompt(commitment: &Commitment) -> String {
+    format!(
+        "[Follow-up due] On {} you told the user: \"{}\" You committed to: {}. Follow through now: do what you said, using your tools if needed, and send the user what you found or did. If the conversation shows you already followed up, call done without sending anything.",
+        commitment.created_at.format("%Y-%m-%d %H:%M UTC"),
+        commitment.promise,
+        commitment.description
+    )
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct CommitmentDb {
+    conn: Arc<DbConn>,
+}
+
+impl CommitmentDb {
+    /// Create a new CommitmentDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    fn insert(&self, commitment: &NewCommitment) -> Result<Commitment> {
+        self.conn.run(|conn| {
+            diesel::insert_into(commitments::table)
+                .values(commitment)
+                .returning(Commitment::as_returning())
+                .get_result(conn)
+                .context("Failed to insert commitment")
+        })
+    }
+
+    fn set_task(&self, id: Uuid, task_id: Uuid) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::update(commitments::table.filter(commitments::id.eq(id)))
+                .set(commitments::task_id.eq(task_id))
+                .execute(conn)
+                .context("Failed to link commitment follow-up")?;
+            Ok(())
+        })
+    }
+
+    /// A commitment by id
+    pub fn get(&self, id: Uuid) -> Result<Option<Commitment>> {
+        self.conn.run(|conn| {
+            commitments::table
+                .filter(commitments::id.eq(id))
+                .select(Commitment::as_select())
+                .first(conn)
+                .optional()
+                .context("Failed to load commitment")
+        })
+    }
+
+    /// An agent's open commitments, soonest due first
+    pub fn open(&self, agent_id: Uuid) -> Result<Vec<Commitment>> {
+        self.conn.run(|conn| {
+            commitments::table
+                .filter(commitments::agent_id.eq(agent_id))
+                .filter(commitments::status.eq("open"))
+                .order(commitments::due_at.asc())
+                .select(Commitment::as_select())
+                .load(conn)
+                .context("Failed to query commitments")
+        })
+    }
+
+    /// Close an open commitment as `kept` or `dropped`. Returns false if it
+    /// was not open.
+    pub fn resolve(&self, id: Uuid, status: &str) -> Result<bool> {
+        self.conn.run(|conn| {
+            let updated = diesel::update(
+                commitments::table
+                    .filter(commitments::id.eq(id))
+                    .filter(commitments::status.eq("open")),
+            )
+            .set((
+                commitments::status.eq(status),
+                commitments::resolved_at.eq(Some(Utc::now())),
+            ))
+            .execute(conn)
+            .context("Failed to resolve commitment")?;
+            Ok(updated > 0)
+        })
+    }
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Ask the chat model which commitments a turn's replies make
+pub async fn detect(
+    user_message: &str,
+    replies: &[String],
+    open: &[Commitment],
+    current_time: &str,
+) -> Result<Vec<DetectedCommitment>> {
+    let open: Vec<String> = open
+        .iter()
+        .map(|c| {
+            format!(
+                "- {} (due {})",
+                c.description,
+                c.due_at.format("%Y-%m-%d %H:%M UTC")
+            )
+        })
+        .collect();
+
+    let predictor = Predict::<DetectCommitments>::builder()
+        .instruction(DETECT_INSTRUCTION)
+        .build();
+    let result = predictor
+        .call_with_meta(DetectCommitmentsInput {
+            current_time: current_time.to_string(),
+            open_commitments: open.join("\n"),
+            user_message: user_message.to_string(),
+            replies: replies.join("\n"),
+        })
+        .await?;
+    usage::record_chat(
+        CallKind::Commitments,
+        result.lm_usage.prompt_tokens as i64,
+        result.lm_usage.completion_tokens as i64,
+    );
+    Ok(result.output.commitments)
+}
+
+/// Record the commitments a turn's replies make and schedule their
+/// follow-ups. Replies without a promise phrase cost nothing.
+pub async fn track(
+    db: &CommitmentDb,
+    scheduler_db: &SchedulerDb,
+    agent_id: Uuid,
+    timezone: &str,
+    user_message: &str,
+    replies: &[String],
+) -> Result<Vec<Commitment>> {
+    if !mentions_promise(replies) {
+        return Ok(Vec::new());
+    }
+    let now = Utc::now();
+    let tz: Tz = timezone.parse().unwrap_or(chrono_tz::UTC);
+    let current_time = now
+        .with_timezone(&tz)
+        .format("%A, %Y-%m-%d %H:%M %Z")
+        .to_string();
+    let open = db.open(agent_id)?;
+    let detected = detect(user_message, replies, &open, &current_time).await?;
+
+    let mut recorded = Vec::new();
+    for found in detected.iter().take(MAX_PER_TURN) {
+        let description: String = found
+            .description
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(MAX_DESCRIPTION_CHARS)
+            .collect();
+        if description.is_empty() {
+            continue;
+        }
+        let commitment = db.insert(&NewCommitment {
+            agent_id,
+            description: &description,
+            promise: found.promise.trim(),
+            due_at: follow_up_at(now, found.follow_up_hours),
+        })?;
+        let task = scheduler_db.create_task(
+            agent_id,
+            TaskType::Commitment,
+            TaskPayload::Commitment(CommitmentPayload {
+                commitment_id: commitment.id,
+                description: description.clone(),
+            }),
+            commitment.due_at,
+            None,
+            timezone.to_string(),
+            format!("Follow-up: {}", description),
+        )?;
+        db.set_task(commitment.id, task.id)?;
+        info!(
+            "Recorded commitment {} for agent {}: {} (follow-up at {})",
+            commitment.id, agent_id, description, commitment.due_at
+        );
+        recorded.push(commitment);
+    }
+    Ok(recorded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_mentions_promise() {
+        assert!(mentions_promise(&[
+            "Good question!".to_string(),
+            "I’ll look into it and get back to you tonight.".to_string(),
+        ]));
+        assert!(mentions_promise(
+            &["Let me check the schedule.".to_string()]
+        ));
+        assert!(!mentions_promise(&["The museum opens at 10.".to_string()]));
+        assert!(!mentions_promise(&[]));
+    }
+
+    #[test]
+    fn test_follow_up_at() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        assert_eq!(follow_up_at(now, 2.5), now + Duration::minutes(150));
+        assert_eq!(follow_up_at(now, 0.0), now + Duration::hours(24));
+        assert_eq!(follow_up_at(now, f64::NAN), now + Duration::hours(24));
+        assert_eq!(follow_up_at(now, 0.01), now + Duration::minutes(15));
+        assert_eq!(follow_up_at(now, 1000.0), now + Duration::days(7));
+    }
+}
@@ -1,11 +1,27 @@
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 
+use crate::email::EmailConfig;
+use crate::email_ingest;
+use crate::guardrails::{GuardAction, OutputGuardConfig};
+use crate::http_server::HttpServerConfig;
 use crate::marmot::MarmotConfig;
+use crate::memory::{RerankMode, RetentionPolicy};
+use crate::messenger::AttachmentPolicy;
+use crate::roles::{self, Role, RolePolicy, Roles};
+use crate::sage_agent::ToolConcurrencyLimits;
+use crate::shell_tool::ShellConfig;
+use crate::signal::GroupGate;
+use crate::speech::SpeechConfig;
+use crate::tools::WebSearchConfig;
+use crate::vision::VisionConfig;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum MessengerType {
     Signal,
     Marmot,
+    Email,
+    Webhook,
 }
 
 #[derive(Debug, Clone)]
@@ -16,8 +32,23 @@ pub struct Config {
     pub maple_model: String,
     pub maple_embedding_model: String,
     pub maple_vision_model: String,
+    /// Longest side of images sent to the vision model, in pixels (0 = as is)
+    pub vision_max_image_px: u32,
+
+    /// Whisper-compatible transcription endpoint for voice messages (unset = off)
+    pub speech_api_url: Option<String>,
+    pub speech_api_key: Option<String>,
+    pub speech_model: String,
 
     pub database_url: String,
+    /// Connections in the shared database pool
+    pub database_pool_size: u32,
+    /// HNSW candidates per vector search (`hnsw.ef_search`)
+    pub vector_ef_search: u32,
+    /// Embedded rows a table needs before it gets an HNSW index
+    pub vector_index_min_rows: i64,
+    /// How `archival_search` and `conversation_search` results are re-ranked
+    pub memory_rerank: RerankMode,
 
     /// Which messaging provider to use
     pub messenger_type: MessengerType,
@@ -28,6 +59,15 @@ pub struct Config {
     /// If set, connect to signal-cli daemon via TCP instead of spawning subprocess
     pub signal_cli_host: Option<String>,
     pub signal_cli_port: u16,
+    /// Signal group IDs Sage participates in ("*" for all; empty = ignore groups)
+    pub signal_allowed_groups: Vec<String>,
+    /// Sage's own account UUID, to recognize @mentions that carry no number
+    pub signal_account_uuid: Option<String>,
+    /// Words that address Sage in a group without an @mention
+    pub signal_group_triggers: Vec<String>,
+    /// Treat the account's Note to Self as a chat (Sage linked to the user's
+    /// own account)
+    pub signal_note_to_self: bool,
 
     // Marmot-specific config
     pub marmot_binary: String,
@@ -36,12 +76,148 @@ pub struct Config {
     pub marmot_allowed_pubkeys: Vec<String>,
     pub marmot_auto_accept_welcomes: bool,
 
+    // Email-specific config
+    pub email_imap_host: String,
+    pub email_imap_port: u16,
+    pub email_smtp_host: String,
+    pub email_smtp_port: u16,
+    pub email_username: String,
+    pub email_password: String,
+    /// Address replies are sent from (defaults to EMAIL_USERNAME)
+    pub email_address: String,
+    pub email_mailbox: String,
+    pub email_poll_secs: u64,
+    pub email_allowed_senders: Vec<String>,
+    /// IMAP mailbox of forwarded mail to store in memory (None = off)
+    pub ingest_email_mailbox: Option<String>,
+    /// Forwarding address -> chat identifier of the user it belongs to
+    pub ingest_email_routes: HashMap<String, String>,
+
+    // Webhook-specific config
+    pub webhook_allowed_users: Vec<String>,
+    /// How long POST /message waits for the turn before answering "pending"
+    pub webhook_reply_timeout_secs: u64,
+
     pub brave_api_key: Option<String>,
+    /// Results per web search unless the agent asks for a count
+    pub web_search_count: u32,
+    /// Freshness filter of web searches unless the agent gives one
+    pub web_search_freshness: Option<String>,
+    /// Fetch Brave's AI summary with each web search
+    pub brave_summarizer: bool,
+
+    /// Maximum size of an incoming attachment in bytes
+    pub attachment_max_bytes: u64,
+    /// MIME types accepted for incoming attachments (supports "type/*")
+    pub attachment_allowed_types: Vec<String>,
+
+    /// Keywords that may not appear in outgoing messages (case-insensitive)
+    pub output_blocklist: Vec<String>,
+    /// Regular expressions that may not match outgoing messages
+    pub output_block_patterns: Vec<String>,
+    /// Maximum outgoing message length in characters (0 = no limit)
+    pub output_max_chars: usize,
+    /// Block API keys, private keys, and other credentials in outgoing messages
+    pub output_block_secrets: bool,
+    /// What to do with a violating message: redact it or hold it for review
+    pub output_guard_action: GuardAction,
+
+    /// Max concurrent executions per tool across all agents (e.g. shell -> 1)
+    pub tool_concurrency_limits: HashMap<String, usize>,
+    /// Workspace snapshots kept per agent before destructive shell commands (0 = off)
+    pub workspace_snapshot_keep: usize,
+    /// Cron schedule of each agent's self-maintenance routine (None = off)
+    pub self_maintenance_cron: Option<String>,
+    /// Cron schedule of the owners' status report (None = off)
+    pub status_report_cron: Option<String>,
+    /// Daily LLM spend the status report compares against, in USD
+    pub status_budget_usd: Option<f64>,
+    /// Template wrapping owner alerts (status report, maintenance summary);
+    /// `{{report}}` is the alert text (see `templates`)
+    pub owner_alert_template: Option<String>,
+    /// Local hour of birthday and anniversary check-ins (None = off)
+    pub occasion_checkin_hour: Option<u32>,
+    /// Decay archival passages unused for this many days (0 = off)
+    pub memory_decay_days: u32,
+    /// Delete summarized messages older than this many days (0 = off)
+    pub message_retention_days: u32,
+    /// Merge near-duplicate archival passages during self-maintenance
+    pub archival_consolidation: bool,
+    /// Extract structured facts from user messages in the background
+    pub fact_extraction: bool,
+    /// Detect promises in Sage's replies and schedule follow-ups
+    pub commitment_tracking: bool,
+
+    /// Merge messages that queue up while the agent is busy into one turn
+    pub inbox_coalesce: bool,
+    /// Cut the current turn short (after the running step) when new messages arrive
+    pub inbox_interrupt: bool,
+    /// Seconds between typing indicator refreshes while a step runs (0 = off)
+    pub typing_heartbeat_secs: u64,
+    /// Stream LLM answers and send each message as soon as it is complete
+    pub stream_responses: bool,
+    /// Connect timeout of streamed LLM calls
+    pub stream_connect_timeout_secs: u64,
+    /// Longest wait for the next chunk of a streamed answer
+    pub stream_read_timeout_secs: u64,
+    /// Wall-clock limit for a whole agent turn, steps and tools included (0 = off)
+    pub turn_timeout_secs: u64,
+    /// Chat model context window, overriding detection (see `model_limits`)
+    pub model_context_window: Option<usize>,
+    /// Chat model max output tokens, overriding detection
+    pub model_max_output_tokens: Option<usize>,
+    /// Sender ids allowed to use owner chat commands (`/status`, `/reset`, ...)
+    pub owner_users: Vec<String>,
+    /// Roles of listed users and groups (`USER_ROLES`, see `roles`)
+    pub user_roles: HashMap<String, Role>,
+    /// Role of everyone not listed
+    pub default_role: Role,
+    /// Per-role policies, built-in defaults with `ROLE_<ROLE>_*` overrides
+    pub role_policies: HashMap<Role, RolePolicy>,
+    /// Messages a guest context keeps in the prompt
+    pub guest_window_messages: usize,
+    /// Hours after which a guest context's messages and memory are purged
+    pub guest_retention_hours: u64,
+
+    /// Ask for shorter replies when the user keeps reacting badly to long ones
+    pub reaction_style_hints: bool,
+
+    /// Append the web sources a message cites
+    pub cite_sources: bool,
+
+    /// Extend the turn's context between steps instead of rebuilding it
+    pub partial_context_refresh: bool,
+
+    /// Cheaper model for simple turns (None = every turn uses `maple_model`)
+    pub router_cheap_model: Option<String>,
+    /// Longest message the router may send to the cheap model
+    pub router_max_chars: usize,
+    /// USD per million prompt tokens of the cheap model (None = chat price)
+    pub router_cheap_prompt_price_per_mtok: Option<f64>,
+    /// USD per million completion tokens of the cheap model (None = chat price)
+    pub router_cheap_completion_price_per_mtok: Option<f64>,
+
+    /// USD per million prompt tokens, for `llm_usage` cost estimates
+    pub llm_prompt_price_per_mtok: f64,
+    /// USD per million completion tokens
+    pub llm_completion_price_per_mtok: f64,
+    /// USD per million embedded tokens
+    pub embedding_price_per_mtok: f64,
 
     /// Workspace directory for shell commands and file operations
     pub workspace_path: String,
+    /// Programs shell commands may run (empty = any)
+    pub shell_allowed_binaries: Vec<String>,
 
     pub http_port: u16,
+
+    /// Address the HTTP server (health, admin) binds to; 127.0.0.1 unless
+    /// HTTP_AUTH_TOKEN is set
+    pub http_bind_address: String,
+    /// Port for the HTTP server
+    pub health_port: u16,
+    /// Optional bearer token required on all HTTP endpoints
+    pub http_auth_token: Option<String>,
 }
 
 impl Config {
@@ -56,8 +232,39 @@ impl Config {
             maple_vision_model: std::env::var("MAPLE_VISION_MODEL").unwrap_or_else(|_| {
                 std::env::var("MAPLE_MODEL").unwrap_or_else(|_| "kimi-k2-5".to_string())
             }),
+            vision_max_image_px: std::env::var("VISION_MAX_IMAGE_PX")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2048),
+
+            speech_api_url: std::env::var("SPEECH_API_URL")
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            speech_api_key: std::env::var("SPEECH_API_KEY").ok(),
+            speech_model: std::env::var("SPEECH_MODEL").unwrap_or_else(|_| "whisper-1".to_string()),
 
             database_url: std::env::var("DATABASE_URL").context("DATABASE_URL must be set")?,
+            database_pool_size: std::env::var("DATABASE_POOL_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(crate::db::DEFAULT_POOL_SIZE),
+            vector_ef_search: std::env::var("VECTOR_EF_SEARCH")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(crate::db::DEFAULT_EF_SEARCH),
+            vector_index_min_rows: std::env::var("VECTOR_INDEX_MIN_ROWS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(crate::vector_index::DEFAULT_INDEX_MIN_ROWS),
+            memory_rerank: match std::env::var("MEMORY_RERANK") {
+                Ok(s) => RerankMode::parse(&s).with_context(|| {
+                    format!("MEMORY_RERANK must be 'off', 'score' or 'llm', got '{}'", s)
+                })?,
+                Err(_) => RerankMode::Off,
+            },
 
             messenger_type: match std::env::var("MESSENGER")
                 .unwrap_or_else(|_| "signal".to_string())
@@ -65,6 +272,8 @@ impl Config {
                 .as_str()
             {
                 "marmot" => MessengerType::Marmot,
+                "email" => MessengerType::Email,
+                "webhook" => MessengerType::Webhook,
                 _ => MessengerType::Signal,
             },
 
@@ -77,6 +286,26 @@ impl Config {
                 .unwrap_or_else(|_| "7583".to_string())
                 .parse()
                 .unwrap_or(7583),
+            signal_allowed_groups: std::env::var("SIGNAL_ALLOWED_GROUPS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|g| g.trim().to_string())
+                        .filter(|g| !g.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            signal_account_uuid: std::env::var("SIGNAL_ACCOUNT_UUID")
+                .ok()
+                .filter(|u| !u.trim().is_empty()),
+            signal_group_triggers: std::env::var("SIGNAL_GROUP_TRIGGERS")
+                .unwrap_or_else(|_| "sage".to_string())
+                .split(',')
+                .map(|t| t.trim().to_lowercase())
+                .filter(|t| !t.is_empty())
+                .collect(),
+            signal_note_to_self: std::env::var("SIGNAL_NOTE_TO_SELF")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
 
             marmot_binary: std::env::var("MARMOT_BINARY").unwrap_or_else(|_| "marmotd".to_string()),
             marmot_relays: std::env::var("MARMOT_RELAYS")
@@ -108,18 +337,337 @@ impl Config {
                 .map(|s| s != "false" && s != "0")
                 .unwrap_or(true),
 
+            email_imap_host: std::env::var("EMAIL_IMAP_HOST").unwrap_or_default(),
+            email_imap_port: std::env::var("EMAIL_IMAP_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(993),
+            email_smtp_host: std::env::var("EMAIL_SMTP_HOST").unwrap_or_default(),
+            email_smtp_port: std::env::var("EMAIL_SMTP_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(587),
+            email_username: std::env::var("EMAIL_USERNAME").unwrap_or_default(),
+            email_password: std::env::var("EMAIL_PASSWORD").unwrap_or_default(),
+            email_address: std::env::var("EMAIL_ADDRESS")
+                .or_else(|_| std::env::var("EMAIL_USERNAME"))
+                .unwrap_or_default(),
+            email_mailbox: std::env::var("EMAIL_MAILBOX").unwrap_or_else(|_| "INBOX".to_string()),
+            email_poll_secs: std::env::var("EMAIL_POLL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            email_allowed_senders: std::env::var("EMAIL_ALLOWED_SENDERS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|a| a.trim().to_lowercase())
+                        .filter(|a| !a.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ingest_email_mailbox: std::env::var("INGEST_EMAIL_MAILBOX")
+                .ok()
+                .filter(|s| !s.trim().is_empty()),
+            ingest_email_routes: email_ingest::parse_routes(
+                &std::env::var("INGEST_EMAIL_ROUTES").unwrap_or_default(),
+            )
+            .context("Invalid INGEST_EMAIL_ROUTES")?,
+
+            webhook_allowed_users: std::env::var("WEBHOOK_ALLOWED_USERS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|u| u.trim().to_string())
+                        .filter(|u| !u.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            webhook_reply_timeout_secs: std::env::var("WEBHOOK_REPLY_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+
             brave_api_key: std::env::var("BRAVE_API_KEY").ok(),
+            web_search_count: std::env::var("WEB_SEARCH_COUNT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| (1..=20).contains(n))
+                .unwrap_or(10),
+            web_search_freshness: match std::env::var("WEB_SEARCH_FRESHNESS") {
+                Ok(s) if s.trim().is_empty() => None,
+                Ok(s) => Some(crate::tools::parse_freshness(&s).with_context(|| {
+                    format!(
+                        "WEB_SEARCH_FRESHNESS must be pd, pw, pm, py or YYYY-MM-DDtoYYYY-MM-DD, got '{}'",
+                        s
+                    )
+                })?),
+                Err(_) => None,
+            },
+            brave_summarizer: std::env::var("BRAVE_SUMMARIZER")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+
+            attachment_max_bytes: std::env::var("ATTACHMENT_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(25 * 1024 * 1024),
+            attachment_allowed_types: std::env::var("ATTACHMENT_ALLOWED_TYPES")
+                .map(|s| {
+                    s.split(',')
+                        .map(|t| t.trim().to_lowercase())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .unwrap_or_else(|_| {
+                    ["image/jpeg", "image/png", "image/webp", "image/gif"]
+                        .iter()
+                        .map(|t| t.to_string())
+                        .collect()
+                }),
+
+            output_blocklist: std::env::var("OUTPUT_BLOCKLIST")
+                .map(|s| {
+                    s.split(',')
+                        .map(|k| k.trim().to_string())
+                        .filter(|k| !k.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            // Semicolon-separated, since regexes commonly contain commas
+            output_block_patterns: std::env::var("OUTPUT_BLOCK_PATTERNS")
+                .map(|s| {
+                    s.split(';')
+                        .map(|p| p.trim().to_string())
+                        .filter(|p| !p.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            output_max_chars: std::env::var("OUTPUT_MAX_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            output_block_secrets: std::env::var("OUTPUT_BLOCK_SECRETS")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+            output_guard_action: match std::env::var("OUTPUT_GUARD_ACTION") {
+                Ok(s) => GuardAction::parse(&s).with_context(|| {
+                    format!(
+                        "OUTPUT_GUARD_ACTION must be 'redact' or 'hold', got '{}'",
+                        s
+                    )
+                })?,
+                Err(_) => GuardAction::Redact,
+            },
+
+            tool_concurrency_limits: ToolConcurrencyLimits::parse(
+                &std::env::var("TOOL_CONCURRENCY_LIMITS")
+                    .unwrap_or_else(|_| "shell=1,web_search=3".to_string()),
+            )
+            .context("Invalid TOOL_CONCURRENCY_LIMITS")?,
+            workspace_snapshot_keep: std::env::var("WORKSPACE_SNAPSHOT_KEEP")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            self_maintenance_cron: match std::env::var("SELF_MAINTENANCE_CRON") {
+                Ok(s) if s.is_empty() || s == "off" || s == "false" => None,
+                Ok(s) => Some(s),
+                Err(_) => Some(crate::maintenance::DEFAULT_CRON.to_string()),
+            },
+            status_report_cron: std::env::var("STATUS_REPORT_CRON")
+                .ok()
+                .filter(|s| !s.is_empty() && s != "off" && s != "false"),
+            status_budget_usd: std::env::var("STATUS_BUDGET_USD")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|budget: &f64| *budget > 0.0),
+            owner_alert_template: std::env::var("OWNER_ALERT_TEMPLATE")
+                .ok()
+                .map(|s| s.replace("\\n", "\n"))
+                .filter(|s| !s.trim().is_empty()),
+            occasion_checkin_hour: match std::env::var("OCCASION_CHECKIN_HOUR") {
+                Ok(s) if s == "off" || s == "false" => None,
+                Ok(s) => Some(
+                    s.parse()
+                        .ok()
+                        .filter(|hour| *hour < 24)
+                        .unwrap_or(crate::occasions::DEFAULT_HOUR),
+                ),
+                Err(_) => Some(crate::occasions::DEFAULT_HOUR),
+            },
+            memory_decay_days: std::env::var("MEMORY_DECAY_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            message_retention_days: std::env::var("MESSAGE_RETENTION_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            archival_consolidation: std::env::var("ARCHIVAL_CONSOLIDATION")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+            fact_extraction: std::env::var("FACT_EXTRACTION")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+            commitment_tracking: std::env::var("COMMITMENT_TRACKING")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+
+            inbox_coalesce: std::env::var("INBOX_COALESCE")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+            inbox_interrupt: std::env::var("INBOX_INTERRUPT")
+                .map(|s| s == "true" || s == "1")
+                .unwrap_or(false),
+            typing_heartbeat_secs: std::env::var("TYPING_HEARTBEAT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10),
+            stream_responses: std::env::var("STREAM_RESPONSES")
+                .map(|s| s == "true" || s == "1")
+                .unwrap_or(false),
+            stream_connect_timeout_secs: std::env::var("STREAM_CONNECT_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(10),
+            stream_read_timeout_secs: std::env::var("STREAM_READ_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(60),
+            turn_timeout_secs: std::env::var("TURN_TIMEOUT_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            model_context_window: std::env::var("MODEL_CONTEXT_WINDOW")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0),
+            model_max_output_tokens: std::env::var("MODEL_MAX_OUTPUT_TOKENS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0),
+            owner_users: std::env::var("OWNER_USERS")
+                .map(|s| {
+                    s.split(',')
+                        .map(|u| u.trim().to_string())
+                        .filter(|u| !u.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            user_roles: roles::parse_assignments(
+                &std::env::var("USER_ROLES").unwrap_or_default(),
+            )
+            .context("Invalid USER_ROLES")?,
+            default_role: match std::env::var("DEFAULT_ROLE") {
+                Ok(s) => Role::parse(&s)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid DEFAULT_ROLE '{}'", s))?,
+                Err(_) => Role::Trusted,
+            },
+            role_policies: Role::ALL
+                .iter()
+                .map(|role| Ok((*role, role_policy_from_env(*role)?)))
+                .collect::<Result<HashMap<_, _>>>()?,
+            guest_window_messages: std::env::var("GUEST_WINDOW_MESSAGES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(20),
+            guest_retention_hours: std::env::var("GUEST_RETENTION_HOURS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(24),
+
+            reaction_style_hints: std::env::var("REACTION_STYLE_HINTS")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+
+            cite_sources: std::env::var("CITE_SOURCES")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+
+            partial_context_refresh: std::env::var("PARTIAL_CONTEXT_REFRESH")
+                .map(|s| s != "false" && s != "0")
+                .unwrap_or(true),
+
+            router_cheap_model: std::env::var("ROUTER_CHEAP_MODEL")
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+            router_max_chars: std::env::var("ROUTER_MAX_CHARS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(80),
+            router_cheap_prompt_price_per_mtok: std::env::var(
+                "ROUTER_CHEAP_PROMPT_PRICE_PER_MTOK",
+            )
+            .ok()
+            .and_then(|s| s.parse().ok()),
+            router_cheap_completion_price_per_mtok: std::env::var(
+                "ROUTER_CHEAP_COMPLETION_PRICE_PER_MTOK",
+            )
+            .ok()
+            .and_then(|s| s.parse().ok()),
+
+            llm_prompt_price_per_mtok: std::env::var("LLM_PROMPT_PRICE_PER_MTOK")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.0),
+            llm_completion_price_per_mtok: std::env::var("LLM_COMPLETION_PRICE_PER_MTOK")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.0),
+            embedding_price_per_mtok: std::env::var("EMBEDDING_PRICE_PER_MTOK")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0.0),
 
             workspace_path: std::env::var("SAGE_WORKSPACE")
                 .unwrap_or_else(|_| "/workspace".to_string()),
+            shell_allowed_binaries: std::env::var("SHELL_ALLOWED_BINARIES")
+                .map(|s| {
+                    s.split(',')
+                        .map(|b| b.trim().to_string())
+                        .filter(|b| !b.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
 
             http_port: std::env::var("HTTP_PORT")
                 .unwrap_or_else(|_| "3000".to_string())
                 .parse()
                 .context("HTTP_PORT must be a valid port number")?,
+
+            // Without a token nothing is protected, so only listen locally
+            http_bind_address: std::env::var("HTTP_BIND_ADDRESS").unwrap_or_else(|_| {
+                let has_token = std::env::var("HTTP_AUTH_TOKEN")
+                    .map(|t| !t.trim().is_empty())
+                    .unwrap_or(false);
+                if has_token { "0.0.0.0" } else { "127.0.0.1" }.to_string()
+            }),
+            health_port: std::env::var("HEALTH_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(8080),
+            http_auth_token: std::env::var("HTTP_AUTH_TOKEN")
+                .ok()
+                .filter(|t| !t.trim().is_empty()),
         })
     }
 
+    pub fn signal_group_gate(&self) -> GroupGate {
+        GroupGate {
+            self_ids: self
+                .signal_phone_number
+                .iter()
+                .chain(self.signal_account_uuid.iter())
+                .cloned()
+                .collect(),
+            triggers: self.signal_group_triggers.clone(),
+            note_to_self: self.signal_note_to_self,
+        }
+    }
+
     pub fn marmot_config(&self) -> MarmotConfig {
         MarmotConfig {
             binary_path: self.marmot_binary.clone(),
@@ -130,10 +678,141 @@ impl Config {
         }
     }
 
+    pub fn email_config(&self) -> EmailConfig {
+        EmailConfig {
+            imap_host: self.email_imap_host.clone(),
+            imap_port: self.email_imap_port,
+            smtp_host: self.email_smtp_host.clone(),
+            smtp_port: self.email_smtp_port,
+            username: self.email_username.clone(),
+            password: self.email_password.clone(),
+            from_address: self.email_address.clone(),
+            mailbox: self.email_mailbox.clone(),
+            poll_interval: std::time::Duration::from_secs(self.email_poll_secs.max(10)),
+        }
+    }
+
+    pub fn shell_config(&self) -> ShellConfig {
+        ShellConfig {
+            allowed_binaries: self.shell_allowed_binaries.clone(),
+        }
+    }
+
+    pub fn web_search_config(&self) -> WebSearchConfig {
+        WebSearchConfig {
+            default_count: self.web_search_count,
+            default_freshness: self.web_search_freshness.clone(),
+            summarizer: self.brave_summarizer,
+        }
+    }
+
+    pub fn vision_config(&self) -> VisionConfig {
+        VisionConfig {
+            api_url: self.maple_api_url.clone(),
+            api_key: self.maple_api_key.clone().unwrap_or_default(),
+            model: self.maple_vision_model.clone(),
+            max_image_px: self.vision_max_image_px,
+        }
+    }
+
+    pub fn retention_policy(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            decay_days: self.memory_decay_days,
+            message_retention_days: self.message_retention_days,
+        }
+    }
+
+    pub fn roles(&self) -> Roles {
+        self.role_policies.iter().fold(
+            Roles::new(
+                self.user_roles.clone(),
+                self.owner_users.clone(),
+                self.default_role,
+            ),
+            |roles, (role, policy)| roles.with_policy(*role, policy.clone()),
+        )
+    }
+
+    pub fn http_server_config(&self) -> HttpServerConfig {
+        HttpServerConfig {
+            bind_address: self.http_bind_address.clone(),
+            port: self.health_port,
+            auth_token: self.http_auth_token.clone(),
+        }
+    }
+
+    /// Attachment limits. Voice messages are accepted whenever transcription
+    /// is configured.
+    pub fn attachment_policy(&self) -> AttachmentPolicy {
+        let mut allowed_types = self.attachment_allowed_types.clone();
+        if self.speech_api_url.is_some() {
+            for audio in crate::speech::SUPPORTED_AUDIO {
+                if !allowed_types.iter().any(|t| t == audio) {
+                    allowed_types.push(audio.to_string());
+                }
+            }
+        }
+        AttachmentPolicy {
+            max_bytes: self.attachment_max_bytes,
+            allowed_types,
+        }
+    }
+
+    pub fn speech_config(&self) -> Option<SpeechConfig> {
+        self.speech_api_url.as_ref().map(|api_url| SpeechConfig {
+            api_url: api_url.clone(),
+            api_key: self.speech_api_key.clone(),
+            model: self.speech_model.clone(),
+        })
+    }
+
+    pub fn output_guard_config(&self) -> OutputGuardConfig {
+        OutputGuardConfig {
+            blocklist: self.output_blocklist.clone(),
+            patterns: self.output_block_patterns.clone(),
+            max_chars: self.output_max_chars,
+            block_secrets: self.output_block_secrets,
+            action: self.output_guard_action,
+        }
+    }
+
     pub fn allowed_users(&self) -> &[String] {
         match self.messenger_type {
             MessengerType::Signal => &self.signal_allowed_users,
             MessengerType::Marmot => &self.marmot_allowed_pubkeys,
+            MessengerType::Email => &self.email_allowed_senders,
+            MessengerType::Webhook => &self.webhook_allowed_users,
         }
     }
+
+    /// Whether a direct message is the user's own Note to Self (always
+    /// allowed: it comes from the account Sage is linked to)
+    pub fn is_note_to_self(&self, source: &str) -> bool {
+        self.messenger_type == MessengerType::Signal
+            && self.signal_note_to_self
+            && self.signal_group_gate().is_self_id(source)
+    }
+}
+
+/// A role's built-in policy with its `ROLE_<ROLE>_*` overrides
+fn role_policy_from_env(role: Role) -> Result<RolePolicy> {
+    let var = |name: &str| std::env::var(format!("ROLE_{}_{}", role.as_str().to_uppercase(), name));
+    let mut policy = RolePolicy::default_for(role);
+    if let Ok(tools) = var("DENY_TOOLS") {
+        policy.denied_tools = roles::parse_tool_list(&tools);
+    }
+    if let Ok(budget) = var("DAILY_BUDGET_USD") {
+        let budget: f64 = budget
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid daily budget for role {}", role.as_str()))?;
+        policy.daily_budget_usd = (budget > 0.0).then_some(budget);
+    }
+    if let Ok(proactive) = var("PROACTIVE") {
+        policy.proactive = proactive != "false" && proactive != "0";
+    }
+    if let Ok(ephemeral) = var("EPHEMERAL") {
+        policy.ephemeral = ephemeral != "false" && ephemeral != "0";
+    }
+    Ok(policy)
 }
@@ -0,0 +1,487 @@
+//! Pooled, resilient PostgreSQL connections
+//!
+//! Every DB access struct (`MemoryDb`, `SchedulerDb`, the agent manager's
+//! `chat_contexts` queries, ...) holds a `DbConn`. All `DbConn`s for the same
+//! database URL share one r2d2 connection pool, so concurrent agents each get
+//! a connection instead of queueing behind a single mutex. `lock()` checks a
+//! connection out until the guard is dropped.
+//!
+//! Diesel is blocking, and most queries are reached from async tasks (agent
+//! steps, tools, the scheduler loop), often while an agent's tokio mutex is
+//! held. `run()` executes a query through `tokio::task::block_in_place`, so
+//! the runtime moves the worker's other tasks to another thread instead of
+//! stalling them for the query's duration. Every DB access struct queries
+//! through it.
+//!
+//! The pool also survives Postgres restarts:
+//!
+//! - Connections are pinged on checkout and replaced if the server went away.
+//! - Repeated connect failures open a circuit breaker, so callers fail fast
+//!   with "Database unavailable" instead of each blocking on a connect attempt.
+//!   After a cooldown (doubling up to a minute) one attempt is let through.
+//! - Writes that are safe to apply late (embedding backfills, reply context)
+//!   can go through `execute_or_defer`, which queues them while the database
+//!   is down and flushes them on reconnect.
+//!
+//! New connections get the HNSW search settings (`configure_vector_search`,
+//! see `vector_index`).
+//!
+//! Each pool counts its checkouts and how long they waited (`DbConn::stats`),
+//! so load tests (`sage-loadtest`) can see contention for connections.
+
+use anyhow::Result;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PooledConnection};
+use std::collections::{HashMap, VecDeque};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::{info, warn};
+
+/// Connections per pool unless `configure_pool` says otherwise
+pub const DEFAULT_POOL_SIZE: u32 = 10;
+
+/// HNSW candidates per scan unless `configure_vector_search` says otherwise
+/// (pgvector's own default is 40)
+pub const DEFAULT_EF_SEARCH: u32 = 100;
+
+/// How long `lock()` waits for a free (or new) connection
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Consecutive connect failures before the circuit opens
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// First cooldown once the circuit opens; doubles per further failure
+const BASE_COOLDOWN: Duration = Duration::from_secs(2);
+
+/// Upper bound on the cooldown
+const MAX_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Maximum number of writes held while the database is unavailable
+const MAX_DEFERRED_WRITES: usize = 500;
+
+/// A write that can be applied after the database comes back
+type DeferredWrite = Box<dyn FnOnce(&mut PgConnection) -> QueryResult<usize> + Send>;
+
+type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+/// Tracks consecutive connect failures and when the next attempt is allowed
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Returns the remaining cooldown if the circuit is open at `now`
+    pub fn check(&self, now: Instant) -> Option<Duration> {
+        self.open_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    /// Record a failed connect attempt at `now`
+    pub fn record_failure(&mut self, now: Instant) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= FAILURE_THRESHOLD {
+            let exponent = (self.consecutive_failures - FAILURE_THRESHOLD).min(16);
+            let cooldown = BASE_COOLDOWN
+                .saturating_mul(1 << exponent)
+                .min(MAX_COOLDOWN);
+            self.open_until = Some(now + cooldown);
+        }
+    }
+
+    /// Record a successful connect (or ping)
+    pub fn record_success(&mut self) {
+        self.consecutive_failures = 0;
+        self.open_until = None;
+    }
+
+    pub fn is_open(&self, now: Instant) -> bool {
+        self.check(now).is_some()
+    }
+
+    /// Whether the last connect attempt failed
+    pub fn is_failing(&self) -> bool {
+        self.consecutive_failures > 0
+    }
+}
+
+/// Checkout counters of one pool, since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStats {
+    /// Connections handed out
+    pub checkouts: u64,
+    /// Checkouts that found no idle connection
+    pub contended: u64,
+    /// Checkouts that timed out with every connection in use
+    pub exhausted: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    /// Open and idle connections when the stats were taken
+    pub connections: u32,
+    pub idle_connections: u32,
+}
+
+impl PoolStats {
+    /// Count one checkout attempt that took `wait`
+    pub fn record(&mut self, wait: Duration, contended: bool) {
+        self.checkouts += 1;
+        if contended {
+            self.contended += 1;
+        }
+        self.total_wait += wait;
+        self.max_wait = self.max_wait.max(wait);
+    }
+
+    /// Average time a checkout waited
+    #[allow(dead_code)]
+    pub fn mean_wait(&self) -> Duration {
+        match u32::try_from(self.checkouts) {
+            Ok(0) => Duration::ZERO,
+            Ok(n) => self.total_wait / n,
+            Err(_) => {
+                Duration::from_secs_f64(self.total_wait.as_secs_f64() / self.checkouts as f64)
+            }
+        }
+    }
+}
+
+/// A pool and the failure handling shared by every `DbConn` using it
+struct SharedPool {
+    pool: PgPool,
+    breaker: Mutex<CircuitBreaker>,
+    deferred: Mutex<VecDeque<(String, DeferredWrite)>>,
+    stats: Mutex<PoolStats>,
+}
+
+static POOL_SIZE: OnceLock<u32> = OnceLock::new();
+
+static EF_SEARCH: OnceLock<u32> = OnceLock::new();
+
+/// Pools by database URL
+static POOLS: OnceLock<Mutex<HashMap<String, Arc<SharedPool>>>> = OnceLock::new();
+
+/// Set the size of the connection pools (`DATABASE_POOL_SIZE`). Call once at
+/// startup, before the first `DbConn::connect`.
+pub fn configure_pool(max_size: u32) {
+    let _ = POOL_SIZE.set(max_size.max(1));
+}
+
+/// Set `hnsw.ef_search` for pooled connections (`VECTOR_EF_SEARCH`, 1-1000).
+/// Call once at startup, before the first `DbConn::connect`.
+pub fn configure_vector_search(ef_search: u32) {
+    let _ = EF_SEARCH.set(ef_search.clamp(1, 1000));
+}
+
+/// Session settings applied to every new pooled connection
+#[derive(Debug)]
+struct SessionSettings {
+    ef_search: u32,
+}
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for SessionSettings {
+    fn on_acquire(&self, conn: &mut PgConnection) -> std::result::Result<(), diesel::r2d2::Error> {
+        // Placeholders until pgvector loads in the session; pgvector before
+        // 0.8 drops the unknown iterative_scan with a warning
+        diesel::sql_query(format!("SET hnsw.ef_search = {}", self.ef_search))
+            .execute(conn)
+            .map_err(diesel::r2d2::Error::QueryError)?;
+        diesel::sql_query("SET hnsw.iterative_scan = strict_order")
+            .execute(conn)
+            .map_err(diesel::r2d2::Error::QueryError)?;
+        Ok(())
+    }
+}
+
+/// Database connection handle shared by the DB access structs
+pub struct DbConn {
+    shared: Arc<SharedPool>,
+}
+
+/// Checked-out connection; derefs to `PgConnection` and goes back to the pool
+/// when dropped
+pub struct DbGuard {
+    conn: PooledConnection<ConnectionManager<PgConnection>>,
+}
+
+impl Deref for DbGuard {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        &self.conn
+    }
+}
+
+impl DerefMut for DbGuard {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        &mut self.conn
+    }
+}
+
+impl DbConn {
+    /// Connect to the database, joining the pool for `database_url` (created
+    /// on first use). Fails if the pool can't open a first connection, so
+    /// misconfiguration still surfaces at startup.
+    pub fn connect(database_url: &str) -> Result<Self> {
+        let mut pools = POOLS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire pool registry lock"))?;
+        if let Some(shared) = pools.get(database_url) {
+            return Ok(Self {
+                shared: shared.clone(),
+            });
+        }
+
+        let max_size = POOL_SIZE.get().copied().unwrap_or(DEFAULT_POOL_SIZE);
+        let ef_search = EF_SEARCH.get().copied().unwrap_or(DEFAULT_EF_SEARCH);
+        let pool = Pool::builder()
+            .max_size(max_size)
+            .min_idle(Some(1))
+            .connection_timeout(CHECKOUT_TIMEOUT)
+            .test_on_check_out(true)
+            .connection_customizer(Box::new(SessionSettings { ef_search }))
+            .build(ConnectionManager::<PgConnection>::new(database_url))?;
+        info!("Database pool ready ({} connections max)", max_size);
+
+        let shared = Arc::new(SharedPool {
+            pool,
+            breaker: Mutex::new(CircuitBreaker::default()),
+            deferred: Mutex::new(VecDeque::new()),
+            stats: Mutex::new(PoolStats::default()),
+        });
+        pools.insert(database_url.to_string(), shared.clone());
+        Ok(Self { shared })
+    }
+
+    /// Check out a connection, replacing it first if it was lost.
+    ///
+    /// Fails fast with "Database unavailable" while the circuit is open.
+    pub fn lock(&self) -> Result<DbGuard> {
+        let now = Instant::now();
+        if let Some(remaining) = self.breaker()?.check(now) {
+            anyhow::bail!(
+                "Database unavailable (retrying in {}s)",
+                remaining.as_secs().max(1)
+            );
+        }
+
+        let contended = self.shared.pool.state().idle_connections == 0;
+        let started = Instant::now();
+        let checkout = self.shared.pool.get();
+        if let Ok(mut stats) = self.shared.stats.lock() {
+            stats.record(started.elapsed(), contended);
+        }
+
+        match checkout {
+            Ok(mut conn) => {
+                let recovered = {
+                    let mut breaker = self.breaker()?;
+                    let recovered = breaker.is_failing();
+                    breaker.record_success();
+                    recovered
+                };
+                if recovered {
+                    info!("Reconnected to database");
+                }
+                self.flush_deferred(&mut conn);
+                Ok(DbGuard { conn })
+            }
+            Err(e) => {
+                // Every connection busy is load, not an outage
+                let state = self.shared.pool.state();
+                if state.connections >= self.shared.pool.max_size() && state.idle_connections == 0 {
+                    if let Ok(mut stats) = self.shared.stats.lock() {
+                        stats.exhausted += 1;
+                    }
+                    anyhow::bail!(
+                        "Database pool exhausted ({} connections in use): {}",
+                        state.connections,
+                        e
+                    );
+                }
+                let mut breaker = self.breaker()?;
+                breaker.record_failure(now);
+                if breaker.is_open(now) {
+                    warn!("Database unreachable, failing fast until it recovers");
+                }
+                anyhow::bail!("Database unavailable: {}", e)
+            }
+        }
+    }
+
+    /// Check out a connection and run `f` on it, without stalling the async
+    /// runtime (see module docs). Outside a multi-threaded runtime it simply
+    /// runs inline.
+    pub fn run<T>(&self, f: impl FnOnce(&mut PgConnection) -> Result<T>) -> Result<T> {
+        let query = || {
+            let mut conn = self.lock()?;
+            f(&mut conn)
+        };
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(query)
+            }
+            _ => query(),
+        }
+    }
+
+    /// Run a write now, or queue it if the database is unavailable.
+    ///
+    /// Only use this for writes that are still correct when applied late and
+    /// whose result the caller doesn't need. Errors from a live database are
+    /// returned as usual.
+    pub fn execute_or_defer<F>(&self, label: &str, write: F) -> Result<()>
+    where
+        F: FnOnce(&mut PgConnection) -> QueryResult<usize> + Send + 'static,
+    {
+        // Left in place when no connection could be checked out
+        let mut pending = Some(write);
+        let result = self.run(|conn| {
+            if let Some(write) = pending.take() {
+                write(conn)?;
+            }
+            Ok(())
+        });
+        match (result, pending) {
+            (Ok(()), _) => Ok(()),
+            // The write ran against a live database
+            (Err(e), None) => Err(e),
+            (Err(e), Some(write)) => {
+                let mut deferred = self
+                    .shared
+                    .deferred
+                    .lock()
+                    .map_err(|_| anyhow::anyhow!("Failed to acquire deferred write lock"))?;
+                if deferred.len() >= MAX_DEFERRED_WRITES {
+                    if let Some((dropped, _)) = deferred.pop_front() {
+                        warn!("Deferred write queue full, dropping oldest ({})", dropped);
+                    }
+                }
+                warn!("Deferring write '{}': {}", label, e);
+                deferred.push_back((label.to_string(), Box::new(write)));
+                Ok(())
+            }
+        }
+    }
+
+    /// Number of writes waiting for the database to come back
+    pub fn deferred_len(&self) -> usize {
+        self.shared.deferred.lock().map(|d| d.len()).unwrap_or(0)
+    }
+
+    /// Connections open in the pool, and how many of them are idle
+    pub fn pool_state(&self) -> (u32, u32) {
+        let state = self.shared.pool.state();
+        (state.connections, state.idle_connections)
+    }
+
+    /// Checkout counters of this connection's pool
+    #[allow(dead_code)]
+    pub fn stats(&self) -> PoolStats {
+        let state = self.shared.pool.state();
+        let mut stats = self
+            .shared
+            .stats
+            .lock()
+            .map(|stats| *stats)
+            .unwrap_or_default();
+        stats.connections = state.connections;
+        stats.idle_connections = state.idle_connections;
+        stats
+    }
+
+    fn breaker(&self) -> Result<MutexGuard<'_, CircuitBreaker>> {
+        self.shared
+            .breaker
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))
+    }
+
+    /// Apply queued writes in order once the database is back
+    fn flush_deferred(&self, conn: &mut PgConnection) {
+        let pending: Vec<_> = match self.shared.deferred.lock() {
+            Ok(mut deferred) => deferred.drain(..).collect(),
+            Err(_) => return,
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        info!("Flushing {} deferred database writes", pending.len());
+        for (label, write) in pending {
+            if let Err(e) = write(conn) {
+                warn!("Deferred write '{}' failed: {}", label, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_stats() {
+        let mut stats = PoolStats::default();
+        assert_eq!(stats.mean_wait(), Duration::ZERO);
+
+        stats.record(Duration::from_millis(1), false);
+        stats.record(Duration::from_millis(5), true);
+        assert_eq!(stats.checkouts, 2);
+        assert_eq!(stats.contended, 1);
+        assert_eq!(stats.mean_wait(), Duration::from_millis(3));
+        assert_eq!(stats.max_wait, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            breaker.record_failure(now);
+            assert!(!breaker.is_open(now));
+        }
+        breaker.record_failure(now);
+        assert_eq!(breaker.check(now), Some(BASE_COOLDOWN));
+        assert!(!breaker.is_open(now + BASE_COOLDOWN));
+    }
+
+    #[test]
+    fn test_breaker_cooldown_doubles_and_caps() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        for _ in 0..FAILURE_THRESHOLD + 1 {
+            breaker.record_failure(now);
+        }
+        assert_eq!(breaker.check(now), Some(BASE_COOLDOWN * 2));
+
+        for _ in 0..20 {
+            breaker.record_failure(now);
+        }
+        assert_eq!(breaker.check(now), Some(MAX_COOLDOWN));
+    }
+
+    #[test]
+    fn test_breaker_resets_on_success() {
+        let now = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        for _ in 0..FAILURE_THRESHOLD {
+            breaker.record_failure(now);
+        }
+        assert!(breaker.is_open(now));
+        breaker.record_success();
+        assert!(!breaker.is_open(now));
+        assert!(!breaker.is_failing());
+
+        breaker.record_failure(now);
+        assert!(!breaker.is_open(now));
+    }
+}
@@ -0,0 +1,305 @@
+//! Delivery Tracking
+//!
+//! Records each message Sage sends in a direct chat (`message_delivery`) and
+//! updates it from the recipient's delivery and read receipts, so the agent
+//! can be told that earlier messages are still unread before it adds more.
+//!
+//! signal-cli doesn't report the timestamp it gives an outgoing message, so
+//! rows carry Sage's own send time (the same clock signal-cli stamps with).
+//! Receipts are applied "up to": a read receipt for a message marks it and
+//! everything sent before it as read, which is how Signal clients read.
+//! Group chats aren't tracked; their receipts come from each member.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::messenger::{self, Messenger, MessengerCapabilities, QuotedMessage};
+use crate::schema::message_delivery;
+
+/// Allowed gap between Sage's recorded send time and the Signal timestamp in
+/// a receipt, in milliseconds
+const CLOCK_TOLERANCE_MS: i64 = 5_000;
+
+/// Only messages sent this recently count as unread
+const UNREAD_WINDOW_DAYS: i64 = 7;
+
+/// Rows older than this are pruned
+pub const RETENTION_DAYS: i64 = 30;
+
+/// What a receipt reports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+/// A delivery or read receipt for messages Sage sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    /// Who sent the receipt (the chat identifier of a direct chat)
+    pub source: String,
+    pub kind: ReceiptKind,
+    /// Timestamps of the messages the receipt covers
+    pub timestamps: Vec<u64>,
+}
+
+/// Unread state of the messages sent to a chat
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeliveryStatus {
+    /// Recent messages not yet read
+    pub unread: i64,
+    /// Of those, how many haven't been delivered either
+    pub undelivered: i64,
+}
+
+/// Line telling the agent its earlier messages are still unread
+pub fn delivery_note(status: &DeliveryStatus) -> Option<String> {
+    if status.unread == 0 {
+        return None;
+    }
+    let messages = if status.unread == 1 {
+        "your last message".to_string()
+    } else {
+        format!("your last {} messages", status.unread)
+    };
+    let delivered = if status.undelivered == 0 {
+        String::new()
+    } else if status.undelivered == status.unread {
+        " (not delivered to their phone yet)".to_string()
+    } else {
+        format!(" ({} not delivered yet)", status.undelivered)
+    };
+    Some(format!(
+        "[Delivery: the user hasn't read {}{}. Avoid piling on more unprompted messages.]",
+        messages, delivered
+    ))
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+#[derive(Insertable)]
+#[diesel(table_name = message_delivery)]
+struct NewDelivery<'a> {
+    id: Uuid,
+    recipient: &'a str,
+    sent_ms: i64,
+}
+
+pub struct DeliveryDb {
+    conn: Arc<DbConn>,
+}
+
+impl DeliveryDb {
+    /// Create a new DeliveryDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Record a message sent to a direct chat (group messages are ignored)
+    pub fn record_sent(&self, recipient: &str, sent_at: DateTime<Utc>) -> Result<()> {
+        if messenger::group_id(recipient).is_some() {
+            return Ok(());
+        }
+        self.conn.run(|conn| {
+            diesel::insert_into(message_delivery::table)
+                .values(&NewDelivery {
+                    id: Uuid::new_v4(),
+                    recipient,
+                    sent_ms: sent_at.timestamp_millis(),
+                })
+                .execute(conn)
+                .context("Failed to record sent message")?;
+            Ok(())
+        })
+    }
+
+    /// Apply a receipt to the messages it covers and everything sent to the
+    /// same chat before them. Returns the number of rows updated.
+    pub fn apply_receipt(&self, receipt: &Receipt) -> Result<usize> {
+        let Some(latest) = receipt.timestamps.iter().max() else {
+            return Ok(0);
+        };
+        let up_to = *latest as i64 + CLOCK_TOLERANCE_MS;
+        let now = Utc::now();
+        self.conn.run(|conn| {
+            let rows = message_delivery::table
+                .filter(message_delivery::recipient.eq(&receipt.source))
+                .filter(message_delivery::sent_ms.le(up_to));
+
+            let delivered = diesel::update(
+                rows.clone()
+                    .filter(message_delivery::delivered_at.is_null()),
+            )
+            .set(message_delivery::delivered_at.eq(now))
+            .execute(conn)
+            .context("Failed to apply delivery receipt")?;
+            if receipt.kind == ReceiptKind::Delivered {
+                return Ok(delivered);
+            }
+
+            diesel::update(rows.filter(message_delivery::read_at.is_null()))
+                .set(message_delivery::read_at.eq(now))
+                .execute(conn)
+                .context("Failed to apply read receipt")
+        })
+    }
+
+    /// Unread messages recently sent to a chat. Chats that have never sent a
+    /// read receipt (e.g. read receipts turned off) report nothing unread.
+    pub fn status(&self, recipient: &str) -> Result<DeliveryStatus> {
+        self.conn.run(|conn| {
+            let sends_receipts: bool = diesel::select(diesel::dsl::exists(
+                message_delivery::table
+                    .filter(message_delivery::recipient.eq(recipient))
+                    .filter(message_delivery::read_at.is_not_null()),
+            ))
+            .get_result(conn)?;
+            if !sends_receipts {
+                return Ok(DeliveryStatus::default());
+            }
+
+            let since = (Utc::now() - Duration::days(UNREAD_WINDOW_DAYS)).timestamp_millis();
+            let unread = message_delivery::table
+                .filter(message_delivery::recipient.eq(recipient))
+                .filter(message_delivery::sent_ms.ge(since))
+                .filter(message_delivery::read_at.is_null());
+            let undelivered: i64 = unread
+                .clone()
+                .filter(message_delivery::delivered_at.is_null())
+                .count()
+                .get_result(conn)?;
+            let unread: i64 = unread.count().get_result(conn)?;
+
+            Ok(DeliveryStatus {
+                unread,
+                undelivered,
+            })
+        })
+    }
+
+    /// Delete rows older than `RETENTION_DAYS`
+    pub fn prune(&self) -> Result<usize> {
+        let cutoff = (Utc::now() - Duration::days(RETENTION_DAYS)).timestamp_millis();
+        self.conn.run(|conn| {
+            diesel::delete(message_delivery::table.filter(message_delivery::sent_ms.lt(cutoff)))
+                .execute(conn)
+                .context("Failed to prune delivery records")
+        })
+    }
+}
+
+// ============================================================================
+// Messenger Wrapper
+// ============================================================================
+
+/// Records every message sent through the inner messenger in
+/// `message_delivery`. Used for transports that report receipts.
+pub struct TrackedMessenger {
+    inner: Arc<dyn Messenger>,
+    db: Arc<DeliveryDb>,
+}
+
+impl TrackedMessenger {
+    pub fn new(inner: Arc<dyn Messenger>, db: Arc<DeliveryDb>) -> Self {
+        Self { inner, db }
+    }
+
+    fn record(&self, recipient: &str, sent_at: DateTime<Utc>) {
+        if let Err(e) = self.db.record_sent(recipient, sent_at) {
+            warn!("Failed to record delivery for {}: {}", recipient, e);
+        }
+    }
+}
+
+impl Messenger for TrackedMessenger {
+    fn capabilities(&self) -> MessengerCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
+        let sent_at = Utc::now();
+        self.inner.send_message(recipient, message)?;
+        self.record(recipient, sent_at);
+        Ok(())
+    }
+
+    fn send_quoted(&self, recipient: &str, message: &str, quote: &QuotedMessage) -> Result<()> {
+        let sent_at = Utc::now();
+        self.inner.send_quoted(recipient, message, quote)?;
+        self.record(recipient, sent_at);
+        Ok(())
+    }
+
+    fn send_attachment(&self, recipient: &str, path: &Path, caption: Option<&str>) -> Result<()> {
+        let sent_at = Utc::now();
+        self.inner.send_attachment(recipient, path, caption)?;
+        self.record(recipient, sent_at);
+        Ok(())
+    }
+
+    fn send_typing(&self, recipient: &str, stop: bool) -> Result<()> {
+        self.inner.send_typing(recipient, stop)
+    }
+
+    fn send_reaction(
+        &self,
+        recipient: &str,
+        target_author: &str,
+        target_timestamp: u64,
+        emoji: &str,
+    ) -> Result<()> {
+        self.inner
+            .send_reaction(recipient, target_author, target_timestamp, emoji)
+    }
+
+    fn end_turn(&self, recipient: &str) -> Result<()> {
+        self.inner.end_turn(recipient)
+    }
+
+    fn refresh(&self) -> Result<()> {
+        self.inner.refresh()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_note() {
+        assert_eq!(delivery_note(&DeliveryStatus::default()), None);
+        assert_eq!(
+            delivery_note(&DeliveryStatus {
+                unread: 1,
+                undelivered: 0
+            })
+            .unwrap(),
+            "[Delivery: the user hasn't read your last message. Avoid piling on more unprompted messages.]"
+        );
+        assert_eq!(
+            delivery_note(&DeliveryStatus {
+                unread: 3,
+                undelivered: 1
+            })
+            .unwrap(),
+            "[Delivery: the user hasn't read your last 3 messages (1 not delivered yet). Avoid piling on more unprompted messages.]"
+        );
+        assert!(delivery_note(&DeliveryStatus {
+            unread: 2,
+            undelivered: 2
+        })
+        .unwrap()
+        .contains("(not delivered to their phone yet)"));
+    }
+}
@@ -0,0 +1,276 @@
+//! Durable Inbox
+//!
+//! Write-ahead log for incoming messages. The main loop records each accepted
+//! message in `inbox_messages` before handing it to the agent's worker, and
+//! the worker deletes the row (acks) once the turn has completed. Anything
+//! still in the table at startup was received but never fully processed, and
+//! is replayed in arrival order.
+//!
+//! Every time processing starts, the row's attempt counter is bumped. A
+//! message that has already been attempted `MAX_ATTEMPTS` times (i.e. it keeps
+//! crashing the process) is marked `failed` instead of being replayed again.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::messenger::{IncomingAttachment, IncomingMessage, QuotedMessage};
+use crate::schema::inbox_messages;
+
+/// Processing attempts before a message is given up on
+pub const MAX_ATTEMPTS: i32 = 3;
+
+/// A persisted incoming message
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = inbox_messages)]
+#[allow(dead_code)]
+struct InboxRow {
+    id: Uuid,
+    source: String,
+    source_name: Option<String>,
+    message: String,
+    attachments: serde_json::Value,
+    sent_at: i64,
+    reply_to: String,
+    reply_context: Option<String>,
+    status: String,
+    attempts: i32,
+    received_at: DateTime<Utc>,
+    quote: Option<serde_json::Value>,
+    message_id: Option<String>,
+    mentions: serde_json::Value,
+    edited: bool,
+}
+
+/// Diesel model for inserting a new inbox message
+#[derive(Insertable)]
+#[diesel(table_name = inbox_messages)]
+struct NewInboxMessage {
+    id: Uuid,
+    source: String,
+    source_name: Option<String>,
+    message: String,
+    attachments: serde_json::Value,
+    sent_at: i64,
+    reply_to: String,
+    reply_context: Option<String>,
+    quote: Option<serde_json::Value>,
+    message_id: Option<String>,
+    mentions: serde_json::Value,
+    edited: bool,
+}
+
+impl NewInboxMessage {
+    fn from_message(msg: &IncomingMessage) -> Result<Self> {
+        Ok(Self {
+            id: Uuid::new_v4(),
+            source: msg.source.clone(),
+            source_name: msg.source_name.clone(),
+            message: msg.message.clone(),
+            attachments: serde_json::to_value(&msg.attachments)?,
+            sent_at: msg.timestamp as i64,
+            reply_to: msg.reply_to.clone(),
+            reply_context: msg.reply_context.clone(),
+            quote: msg.quote.as_ref().map(serde_json::to_value).transpose()?,
+            message_id: msg.message_id.clone(),
+            mentions: serde_json::to_value(&msg.mentions)?,
+            edited: msg.edited,
+        })
+    }
+}
+
+impl InboxRow {
+    fn into_message(self) -> IncomingMessage {
+        let attachments: Vec<IncomingAttachment> = serde_json::from_value(self.attachments)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Dropping unreadable attachments on inbox row {}: {}",
+                    self.id, e
+                );
+                Vec::new()
+            });
+
+        let quote = self
+            .quote
+            .and_then(|q| serde_json::from_value::<QuotedMessage>(q).ok());
+        let mentions: Vec<String> = serde_json::from_value(self.mentions).unwrap_or_default();
+
+        IncomingMessage {
+            source: self.source,
+            source_name: self.source_name,
+            message: self.message,
+            attachments,
+            timestamp: self.sent_at.max(0) as u64,
+            reply_to: self.reply_to,
+            reply_context: self.reply_context,
+            message_id: self.message_id,
+            quote,
+            mentions,
+            edited: self.edited,
+            reaction: None,
+            own_message: false,
+            inbox_ids: vec![self.id],
+        }
+    }
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct InboxDb {
+    conn: Arc<DbConn>,
+}
+
+impl InboxDb {
+    /// Create a new InboxDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Persist an incoming message before it is processed. Returns the row id
+    /// to ack once the turn completes.
+    pub fn enqueue(&self, msg: &IncomingMessage) -> Result<Uuid> {
+        self.conn.run(|conn| {
+            let row = NewInboxMessage::from_message(msg)?;
+            let id = row.id;
+            diesel::insert_into(inbox_messages::table)
+                .values(&row)
+                .execute(conn)
+                .context("Failed to persist incoming message")?;
+
+            Ok(id)
+        })
+    }
+
+    /// Record that processing of these messages is starting
+    pub fn begin_attempt(&self, ids: &[Uuid]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.conn.run(|conn| {
+            diesel::update(inbox_messages::table.filter(inbox_messages::id.eq_any(ids)))
+                .set(inbox_messages::attempts.eq(inbox_messages::attempts + 1))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Remove messages whose turn has completed
+    pub fn ack(&self, ids: &[Uuid]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.conn.run(|conn| {
+            diesel::delete(inbox_messages::table.filter(inbox_messages::id.eq_any(ids)))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Messages left over from a previous run, oldest first.
+    ///
+    /// Messages that already used up their attempts are marked `failed` and
+    /// not returned.
+    pub fn take_pending(&self) -> Result<Vec<IncomingMessage>> {
+        self.conn.run(|conn| {
+            let failed = diesel::update(
+                inbox_messages::table
+                    .filter(inbox_messages::status.eq("pending"))
+                    .filter(inbox_messages::attempts.ge(MAX_ATTEMPTS)),
+            )
+            .set(inbox_messages::status.eq("failed"))
+            .execute(conn)?;
+            if failed > 0 {
+                warn!(
+                    "Giving up on {} inbox message(s) after {} attempts",
+                    failed, MAX_ATTEMPTS
+                );
+            }
+
+            let rows = inbox_messages::table
+                .filter(inbox_messages::status.eq("pending"))
+                .order(inbox_messages::received_at.asc())
+                .select(InboxRow::as_select())
+                .load(conn)?;
+
+            Ok(rows.into_iter().map(InboxRow::into_message).collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inbox_row_round_trip() {
+        let msg = IncomingMessage {
+            source: "user-1".to_string(),
+            source_name: Some("Alice".to_string()),
+            message: "remind me later".to_string(),
+            attachments: vec![IncomingAttachment {
+                file: "abc123".to_string(),
+                content_type: "image/png".to_string(),
+                size: Some(2048),
+            }],
+            timestamp: 1_760_000_000_000,
+            reply_to: "user-1".to_string(),
+            reply_context: Some("group-9".to_string()),
+            quote: Some(QuotedMessage {
+                author: "sage".to_string(),
+                id: "1759999999000".to_string(),
+                text: Some("Want a reminder?".to_string()),
+                from_self: true,
+            }),
+            message_id: Some("1760000000000".to_string()),
+            mentions: vec!["bob-uuid".to_string()],
+            edited: true,
+            reaction: None,
+            own_message: false,
+            inbox_ids: Vec::new(),
+        };
+
+        let new = NewInboxMessage::from_message(&msg).unwrap();
+        let row = InboxRow {
+            id: new.id,
+            source: new.source,
+            source_name: new.source_name,
+            message: new.message,
+            attachments: new.attachments,
+            sent_at: new.sent_at,
+            reply_to: new.reply_to,
+            reply_context: new.reply_context,
+            status: "pending".to_string(),
+            attempts: 1,
+            received_at: Utc::now(),
+            quote: new.quote,
+            message_id: new.message_id,
+            mentions: new.mentions,
+            edited: new.edited,
+        };
+        let id = row.id;
+        let restored = row.into_message();
+
+        assert_eq!(restored.message, msg.message);
+        assert_eq!(restored.source_name, msg.source_name);
+        assert_eq!(restored.timestamp, msg.timestamp);
+        assert_eq!(restored.reply_context, msg.reply_context);
+        assert_eq!(restored.attachments.len(), 1);
+        assert_eq!(restored.quote, msg.quote);
+        assert_eq!(restored.message_id, msg.message_id);
+        assert_eq!(restored.mentions, msg.mentions);
+        assert!(restored.edited);
+        assert_eq!(restored.attachments[0].size, Some(2048));
+        assert_eq!(restored.inbox_ids, vec![id]);
+    }
+}
@@ -0,0 +1,395 @@
+//! Email Messenger
+//!
+//! Polls an IMAP inbox for unread mail, converts each message into an
+//! `IncomingMessage`, and replies over SMTP. Replies stay in the sender's
+//! thread (`Re:` subject plus `In-Reply-To`/`References` headers).
+//!
+//! Unlike chat transports, a whole turn's replies are sent as a single email
+//! (see `MessengerCapabilities::single_reply`).
+
+use anyhow::{anyhow, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use mail_parser::MessageParser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::messenger::{IncomingMessage, Messenger, MessengerCapabilities};
+
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub imap_host: String,
+    pub imap_port: u16,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub username: String,
+    pub password: String,
+    /// Address replies are sent from
+    pub from_address: String,
+    pub mailbox: String,
+    pub poll_interval: Duration,
+}
+
+/// Thread state needed to reply in-thread to a correspondent.
+///
+/// Persisted as the chat context's reply_context so threading survives restarts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmailThread {
+    pub subject: String,
+    pub message_id: Option<String>,
+}
+
+pub struct EmailClient {
+    config: EmailConfig,
+    mailer: SmtpTransport,
+    /// Maps sender address -> the thread of their latest email
+    threads: Arc<Mutex<HashMap<String, EmailThread>>>,
+}
+
+impl EmailClient {
+    pub fn new(config: &EmailConfig) -> Result<Self> {
+        if config.username.is_empty() || config.password.is_empty() {
+            return Err(anyhow!(
+                "EMAIL_USERNAME and EMAIL_PASSWORD must be set when MESSENGER=email"
+            ));
+        }
+
+        let mailer = SmtpTransport::starttls_relay(&config.smtp_host)
+            .with_context(|| format!("Invalid SMTP host {}", config.smtp_host))?
+            .port(config.smtp_port)
+            .credentials(Credentials::new(
+                config.username.clone(),
+                config.password.clone(),
+            ))
+            .build();
+
+        Ok(Self {
+            config: config.clone(),
+            mailer,
+            threads: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Shared thread map, updated by the receive loop
+    pub fn threads_handle(&self) -> Arc<Mutex<HashMap<String, EmailThread>>> {
+        self.threads.clone()
+    }
+
+    fn thread_for(&self, recipient: &str) -> EmailThread {
+        self.threads
+            .lock()
+            .ok()
+            .and_then(|threads| threads.get(recipient).cloned())
+            .unwrap_or_default()
+    }
+}
+
+impl Messenger for EmailClient {
+    fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
+        let thread = self.thread_for(recipient);
+
+        let mut builder = lettre::Message::builder()
+            .from(
+                self.config
+                    .from_address
+                    .parse()
+                    .context("Invalid EMAIL_ADDRESS")?,
+            )
+            .to(recipient
+                .parse()
+                .with_context(|| format!("Invalid recipient address {}", recipient))?)
+            .subject(reply_subject(&thread.subject))
+            .header(ContentType::TEXT_PLAIN);
+        if let Some(ref message_id) = thread.message_id {
+            builder = builder
+                .in_reply_to(message_id.clone())
+                .references(message_id.clone());
+        }
+        let email = builder
+            .body(message.to_string())
+            .context("Failed to build email")?;
+
+        self.mailer
+            .send(&email)
+            .with_context(|| format!("Failed to send email to {}", recipient))?;
+        info!("Sent email to {} ({} chars)", recipient, message.len());
+        Ok(())
+    }
+
+    fn send_typing(&self, _recipient: &str, _stop: bool) -> Result<()> {
+        // No typing indicators in email
+        Ok(())
+    }
+
+    fn capabilities(&self) -> MessengerCapabilities {
+        MessengerCapabilities {
+            single_reply: true,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn new_email_client(config: &EmailConfig) -> Result<EmailClient> {
+    EmailClient::new(config)
+}
+
+/// Poll the IMAP inbox forever, forwarding unread mail to `tx`.
+///
+/// Reconnects with exponential backoff on errors.
+pub async fn run_email_receive_loop(
+    config: EmailConfig,
+    threads: Arc<Mutex<HashMap<String, EmailThread>>>,
+    tx: mpsc::Sender<IncomingMessage>,
+) -> Result<()> {
+    let mut backoff = Duration::from_secs(5);
+    let backoff_max = Duration::from_secs(300);
+
+    loop {
+        let poll_config = config.clone();
+        let poll_threads = threads.clone();
+        let poll_tx = tx.clone();
+        let result =
+            tokio::task::spawn_blocking(move || poll_inbox(&poll_config, &poll_threads, &poll_tx))
+                .await
+                .map_err(|e| anyhow!("IMAP poll task failed: {}", e))
+                .and_then(|r| r);
+
+        match result {
+            Ok(count) => {
+                if count > 0 {
+                    info!("Received {} new email(s)", count);
+                }
+                backoff = Duration::from_secs(5);
+                tokio::time::sleep(config.poll_interval).await;
+            }
+            Err(e) => {
+                if tx.is_closed() {
+                    return Err(anyhow!("message channel closed"));
+                }
+                warn!("IMAP poll failed; retrying in {:?}: {}", backoff, e);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(backoff_max);
+            }
+        }
+    }
+}
+
+/// One IMAP session: fetch unread mail, mark it seen, and forward it
+fn poll_inbox(
+    config: &EmailConfig,
+    threads: &Mutex<HashMap<String, EmailThread>>,
+    tx: &mpsc::Sender<IncomingMessage>,
+) -> Result<usize> {
+    let mut session = imap_login(config)?;
+    session.select(&config.mailbox)?;
+    let mut uids: Vec<u32> = session.uid_search("UNSEEN")?.into_iter().collect();
+    uids.sort_unstable();
+    let mut forwarded = 0;
+
+    for uid in uids {
+        let fetches = session.uid_fetch(uid.to_string(), "RFC822")?;
+        // Mark seen before forwarding: the durable inbox owns it from here
+        session.uid_store(uid.to_string(), "+FLAGS (\\Seen)")?;
+
+        let Some(raw) = fetches.iter().next().and_then(|f| f.body()) else {
+            warn!("Email uid {} has no body", uid);
+            continue;
+        };
+        let Some(parsed) = parse_email(raw) else {
+            warn!("Could not parse email uid {}", uid);
+            continue;
+        };
+
+        debug!("Email uid {} from {}", uid, parsed.from);
+
+        let thread = EmailThread {
+            subject: parsed.subject.clone(),
+            message_id: parsed.message_id.clone(),
+        };
+        let reply_context = serde_json::to_string(&thread).ok();
+        if let Ok(mut threads) = threads.lock() {
+            threads.insert(parsed.from.clone(), thread);
+        }
+
+        let message = if parsed.subject.is_empty() {
+            parsed.body
+        } else {
+            format!("[Subject: {}]\n{}", parsed.subject, parsed.body)
+        };
+        let msg = IncomingMessage {
+            source: parsed.from.clone(),
+            source_name: parsed.from_name,
+            message,
+            attachments: vec![],
+            timestamp: parsed.timestamp,
+            reply_to: parsed.from,
+            reply_context,
+            message_id: parsed.message_id,
+            quote: None,
+            mentions: Vec::new(),
+            edited: false,
+            reaction: None,
+            own_message: false,
+            inbox_ids: Vec::new(),
+        };
+        if tx.blocking_send(msg).is_err() {
+            error!("Failed to send email to channel (receiver dropped)");
+            return Err(anyhow!("message channel closed"));
+        }
+        forwarded += 1;
+    }
+
+    let _ = session.logout();
+    Ok(forwarded)
+}
+
+type ImapSession = imap::Session<native_tls::TlsStream<std::net::TcpStream>>;
+
+/// Connect and log in to the configured IMAP server
+fn imap_login(config: &EmailConfig) -> Result<ImapSession> {
+    let tls = native_tls::TlsConnector::builder().build()?;
+    let client = imap::connect(
+        (config.imap_host.as_str(), config.imap_port),
+        config.imap_host.as_str(),
+        &tls,
+    )
+    .with_context(|| format!("Failed to connect to IMAP server {}", config.imap_host))?;
+    client
+        .login(&config.username, &config.password)
+        .map_err(|(e, _)| anyhow!("IMAP login failed: {}", e))
+}
+
+/// Fetch every unread message in `mailbox` as raw RFC 822 bytes, marking
+/// each one seen
+pub fn fetch_unseen(config: &EmailConfig, mailbox: &str) -> Result<Vec<Vec<u8>>> {
+    let mut session = imap_login(config)?;
+    session.select(mailbox)?;
+    let mut uids: Vec<u32> = session.uid_search("UNSEEN")?.into_iter().collect();
+    uids.sort_unstable();
+
+    let mut messages = Vec::new();
+    for uid in uids {
+        let fetches = session.uid_fetch(uid.to_string(), "RFC822")?;
+        session.uid_store(uid.to_string(), "+FLAGS (\\Seen)")?;
+        match fetches.iter().next().and_then(|f| f.body()) {
+            Some(raw) => messages.push(raw.to_vec()),
+            None => warn!("Email uid {} in {} has no body", uid, mailbox),
+        }
+    }
+
+    let _ = session.logout();
+    Ok(messages)
+}
+
+/// Fields extracted from a raw RFC 822 message
+#[derive(Debug, Clone, PartialEq)]
+struct ParsedEmail {
+    from: String,
+    from_name: Option<String>,
+    subject: String,
+    message_id: Option<String>,
+    body: String,
+    /// Milliseconds since epoch
+    timestamp: u64,
+}
+
+fn parse_email(raw: &[u8]) -> Option<ParsedEmail> {
+    let message = MessageParser::default().parse(raw)?;
+    let from = message.from()?.first()?;
+    let address = from.address()?.trim().to_lowercase();
+
+    let body = message
+        .body_text(0)
+        .map(|b| strip_quoted_reply(&b))
+        .unwrap_or_default();
+
+    Some(ParsedEmail {
+        from: address,
+        from_name: from.name().map(|n| n.to_string()),
+        subject: message.subject().unwrap_or_default().trim().to_string(),
+        message_id: message.message_id().map(|id| format!("<{}>", id)),
+        body,
+        timestamp: message
+            .date()
+            .map(|d| d.to_timestamp().max(0) as u64 * 1000)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp_millis() as u64),
+    })
+}
+
+/// Drop the quoted history below a reply ("On ... wrote:" and "> " lines)
+/// and any signature block.
+pub fn strip_quoted_reply(body: &str) -> String {
+    let mut kept = Vec::new();
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if trimmed == "--" || trimmed.starts_with("-----Original Message-----") {
+            break;
+        }
+        if trimmed.starts_with("On ") && trimmed.ends_with("wrote:") {
+            break;
+        }
+        if trimmed.starts_with('>') {
+            continue;
+        }
+        kept.push(line.trim_end());
+    }
+    kept.join("\n").trim().to_string()
+}
+
+/// Subject for a reply: "Re: <subject>", without stacking prefixes
+pub fn reply_subject(subject: &str) -> String {
+    let subject = subject.trim();
+    if subject.is_empty() {
+        return "Message from Sage".to_string();
+    }
+    if subject.len() >= 3 && subject[..3].eq_ignore_ascii_case("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {}", subject)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_quoted_reply() {
+        let body = "Sounds good, see you then!\n\nOn Tue, Oct 14, 2026 at 9:00 AM Sage <sage@example.com> wrote:\n> Want to meet at 10?\n";
+        assert_eq!(strip_quoted_reply(body), "Sounds good, see you then!");
+
+        let body = "Thanks\n> quoted\nMore text\n--\nAlice";
+        assert_eq!(strip_quoted_reply(body), "Thanks\nMore text");
+    }
+
+    #[test]
+    fn test_reply_subject() {
+        assert_eq!(reply_subject("Dinner plans"), "Re: Dinner plans");
+        assert_eq!(reply_subject("RE: Dinner plans"), "RE: Dinner plans");
+        assert_eq!(reply_subject("  "), "Message from Sage");
+    }
+
+    #[test]
+    fn test_parse_email() {
+        let raw = b"From: Alice Example <Alice@Example.com>\r\n\
+To: sage@example.com\r\n\
+Subject: Trip ideas\r\n\
+Message-ID: <abc123@example.com>\r\n\
+Date: Tue, 14 Oct 2026 09:00:00 +0000\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+Any ideas for a weekend trip?\r\n";
+        let parsed = parse_email(raw).unwrap();
+        assert_eq!(parsed.from, "alice@example.com");
+        assert_eq!(parsed.from_name.as_deref(), Some("Alice Example"));
+        assert_eq!(parsed.subject, "Trip ideas");
+        assert_eq!(parsed.message_id.as_deref(), Some("<abc123@example.com>"));
+        assert_eq!(parsed.body, "Any ideas for a weekend trip?");
+        assert_eq!(parsed.timestamp, 1_791_968_400_000);
+    }
+}
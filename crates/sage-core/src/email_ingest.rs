@@ -0,0 +1,399 @@
+//! Email-to-Memory Forwarding
+//!
+//! Users forward receipts, itineraries and newsletters to an ingestion
+//! mailbox (`INGEST_EMAIL_MAILBOX`, read with the `EMAIL_IMAP_*` account -
+//! typically a folder that a `sage+memory@...` address is filtered into).
+//! This works alongside any messenger. Each forwarder's address is mapped to
+//! the chat it belongs to (`INGEST_EMAIL_ROUTES`); mail from anyone else is
+//! ignored.
+//!
+//! Every forwarded email is digested by the LLM into a kind (receipt,
+//! itinerary, newsletter, other), a memory-ready summary and tags, stored as
+//! an archival passage tagged `email` plus the kind, and confirmed over the
+//! primary messenger. Itineraries are also parsed into flights and hotel
+//! stays with reminders (see `itinerary`).
+
+use anyhow::{anyhow, Result};
+use dspy_rs::{Predict, Signature};
+use mail_parser::MessageParser;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::agent_manager::{AgentManager, ContextType};
+use crate::email::{self, EmailConfig};
+use crate::itinerary;
+use crate::messenger::Messenger;
+
+/// Forwarded bodies are cut to this many characters before digesting
+const MAX_BODY_CHARS: usize = 12_000;
+
+/// Model-suggested tags kept per passage
+const MAX_TAGS: usize = 5;
+
+/// Kinds of forwarded email; anything else is stored as "other"
+pub const KINDS: &[&str] = &["receipt", "itinerary", "newsletter", "other"];
+
+const DIGEST_INSTRUCTION: &str = r#"You file emails the user forwarded into their long-term memory.
+
+Classify the email as one of: receipt, itinerary, newsletter, other.
+
+Write a summary that stands on its own when recalled months later. Keep every concrete detail the user may ask about:
+- receipt: merchant, date, items, total and currency, order or invoice number
+- itinerary: travel type, dates and times with time zones, places, carrier and flight/train numbers, confirmation codes, hotel address
+- newsletter: publication, date, and the main points in a few bullets
+- other: who it is from, what it is about, and any dates or actions
+
+Do not invent details. Leave out tracking links, unsubscribe text and legal footers.
+
+Tags: up to 5 short lowercase keywords (e.g. the merchant, destination, or topic)."#;
+
+/// DSRs signature for digesting a forwarded email
+#[derive(Signature, Clone, Debug)]
+pub struct DigestEmail {
+    #[input(desc = "Subject line of the forwarded email")]
+    pub subject: String,
+
+    #[input(desc = "Body of the forwarded email, including the forwarded headers")]
+    pub body: String,
+
+    #[input(desc = "Today's date (YYYY-MM-DD)")]
+    pub current_date: String,
+
+    #[output(desc = "One of: receipt, itinerary, newsletter, other")]
+    pub kind: String,
+
+    #[output(desc = "Self-contained summary keeping all concrete details")]
+    pub summary: String,
+
+    #[output(desc = "Up to 5 short lowercase keywords")]
+    pub tags: Vec<String>,
+}
+
+/// A forwarded email, as far as ingestion cares
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardedEmail {
+    /// Address of the user who forwarded it (lowercase)
+    pub from: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Parse `address=identifier` pairs separated by `;` or `,`. The identifier
+/// is the chat Sage uses for that user (Signal UUID, Marmot pubkey, ...).
+pub fn parse_routes(spec: &str) -> Result<HashMap<String, String>> {
+    let mut routes = HashMap::new();
+    for pair in spec
+        .split([';', ','])
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        let (address, identifier) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected address=identifier, got '{}'", pair))?;
+        let (address, identifier) = (address.trim().to_lowercase(), identifier.trim());
+        if address.is_empty() || identifier.is_empty() {
+            return Err(anyhow!("expected address=identifier, got '{}'", pair));
+        }
+        routes.insert(address, identifier.to_string());
+    }
+    Ok(routes)
+}
+
+/// Parse a raw forwarded email. Unlike chat email, the quoted part is the
+/// point, so the whole body is kept.
+pub fn parse_forwarded(raw: &[u8]) -> Option<ForwardedEmail> {
+    let message = MessageParser::default().parse(raw)?;
+    let from = message.from()?.first()?.address()?.trim().to_lowercase();
+    let body: String = message
+        .body_text(0)
+        .unwrap_or_default()
+        .trim()
+        .chars()
+        .take(MAX_BODY_CHARS)
+        .collect();
+    Some(ForwardedEmail {
+        from,
+        subject: strip_forward_prefix(message.subject().unwrap_or_default()),
+        body,
+    })
+}
+
+/// Drop "Fwd:"/"Fw:" prefixes (repeated ones too) from a subject
+pub fn strip_forward_prefix(subject: &str) -> String {
+    let mut subject = subject.trim();
+    loop {
+        let lower = subject.to_lowercase();
+        let rest = ["fwd:", "fw:"]
+            .iter()
+            .find(|p| lower.starts_with(*p))
+            .map(|p| &subject[p.len()..]);
+        match rest {
+            Some(rest) => subject = rest.trim_start(),
+            None => return subject.to_string(),
+        }
+    }
+}
+
+/// Tags for the archival passage: `email`, the kind, then the model's
+/// keywords (lowercased, deduplicated, at most `MAX_TAGS`)
+pub fn passage_tags(kind: &str, keywords: &[String]) -> Vec<String> {
+    let mut tags = vec!["email".to_string(), kind.to_string()];
+    for keyword in keywords {
+        let keyword = keyword.trim().to_lowercase();
+        if !keyword.is_empty() && !tags.contains(&keyword) && tags.len() < MAX_TAGS + 2 {
+            tags.push(keyword);
+        }
+    }
+    tags
+}
+
+/// One of `KINDS`, defaulting to "other"
+pub fn normalize_kind(kind: &str) -> &'static str {
+    let kind = kind.trim().to_lowercase();
+    KINDS
+        .iter()
+        .find(|k| **k == kind)
+        .copied()
+        .unwrap_or("other")
+}
+
+/// Digest a forwarded email with the LLM. Falls back to storing the raw text
+/// when the model call fails, so nothing forwarded is lost.
+async fn digest(email: &ForwardedEmail) -> (&'static str, String, Vec<String>) {
+    let predictor = Predict::<DigestEmail>::builder()
+        .instruction(DIGEST_INSTRUCTION)
+        .build();
+    let input = DigestEmailInput {
+        subject: email.subject.clone(),
+        body: email.body.clone(),
+        current_date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+    };
+    match predictor.call(input).await {
+        Ok(response) => (
+            normalize_kind(&response.kind),
+            response.summary.trim().to_string(),
+            response.tags,
+        ),
+        Err(e) => {
+            warn!("Failed to digest forwarded email, storing it as is: {}", e);
+            ("other", email.body.clone(), Vec::new())
+        }
+    }
+}
+
+/// Archival passage for a digested email
+pub fn passage(kind: &str, subject: &str, summary: &str, date: &str) -> String {
+    if subject.is_empty() {
+        format!("Forwarded email ({}), {}:\n{}", kind, date, summary)
+    } else {
+        format!(
+            "Forwarded email ({}) \"{}\", {}:\n{}",
+            kind, subject, date, summary
+        )
+    }
+}
+
+/// Message confirming what was saved
+pub fn confirmation(kind: &str, subject: &str) -> String {
+    let what = match kind {
+        "other" => "the email".to_string(),
+        kind => format!("the {}", kind),
+    };
+    if subject.is_empty() {
+        format!("📥 Saved {} you forwarded to my memory.", what)
+    } else {
+        format!(
+            "📥 Saved {} you forwarded (\"{}\") to my memory.",
+            what, subject
+        )
+    }
+}
+
+/// Store one forwarded email in its owner's archival memory and confirm
+async fn ingest(
+    email: ForwardedEmail,
+    routes: &HashMap<String, String>,
+    agent_manager: &AgentManager,
+    messenger: &Arc<dyn Messenger>,
+) -> Result<()> {
+    let Some(identifier) = routes.get(&email.from) else {
+        warn!(
+            "Ignoring forwarded email from unrouted address {}",
+            email.from
+        );
+        return Ok(());
+    };
+
+    let (agent_id, agent) = agent_manager
+        .get_or_create_agent(identifier, ContextType::for_identifier(identifier), None)
+        .await?;
+    let (archival, timezone) = {
+        let agent = agent.lock().await;
+        let memory = agent
+            .memory()
+            .ok_or_else(|| anyhow!("Agent for {} has no memory", identifier))?;
+        let timezone = memory
+            .get_preference("timezone")
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| "UTC".to_string());
+        (memory.archival().clone(), timezone)
+    };
+
+    let (kind, summary, keywords) = digest(&email).await;
+    let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    archival
+        .insert(
+            &passage(kind, &email.subject, &summary, &date),
+            Some(passage_tags(kind, &keywords)),
+        )
+        .await?;
+    info!(
+        "Saved forwarded {} from {} to archival memory",
+        kind, email.from
+    );
+
+    let mut reply = confirmation(kind, &email.subject);
+    if kind == "itinerary" {
+        match itinerary::record_itinerary(
+            &agent_manager.itinerary_db(),
+            &agent_manager.scheduler_db(),
+            agent_id,
+            &timezone,
+            &email.body,
+        )
+        .await
+        {
+            Ok(report) if !report.added.is_empty() => {
+                reply = format!(
+                    "{}
+
+{}",
+                    reply,
+                    report.summary(&timezone)
+                );
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to record forwarded itinerary: {:#}", e),
+        }
+    }
+
+    let client = messenger.as_ref();
+    client.send_message(identifier, &reply)?;
+    Ok(())
+}
+
+/// Poll the ingestion mailbox forever, storing each forwarded email.
+///
+/// Backs off exponentially on IMAP errors.
+pub async fn run_ingest_loop(
+    config: EmailConfig,
+    mailbox: String,
+    routes: HashMap<String, String>,
+    agent_manager: Arc<AgentManager>,
+    messenger: Arc<dyn Messenger>,
+) {
+    let mut backoff = Duration::from_secs(5);
+    let backoff_max = Duration::from_secs(300);
+
+    loop {
+        let poll_config = config.clone();
+        let poll_mailbox = mailbox.clone();
+        let fetched =
+            tokio::task::spawn_blocking(move || email::fetch_unseen(&poll_config, &poll_mailbox))
+                .await
+                .map_err(|e| anyhow!("IMAP poll task failed: {}", e))
+                .and_then(|r| r);
+
+        match fetched {
+            Ok(messages) => {
+                for raw in messages {
+                    let Some(email) = parse_forwarded(&raw) else {
+                        warn!("Could not parse forwarded email in {}", mailbox);
+                        continue;
+                    };
+                    let from = email.from.clone();
+                    if let Err(e) = ingest(email, &routes, &agent_manager, &messenger).await {
+                        warn!("Failed to ingest email forwarded by {}: {}", from, e);
+                    }
+                }
+                backoff = Duration::from_secs(5);
+                tokio::time::sleep(config.poll_interval).await;
+            }
+            Err(e) => {
+                warn!(
+                    "Ingestion mailbox poll failed; retrying in {:?}: {}",
+                    backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(backoff_max);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_routes() {
+        let routes =
+            parse_routes(" Alice@Example.com = abc-123 ; bob@example.com=npub1xyz,").unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes["alice@example.com"], "abc-123");
+        assert_eq!(routes["bob@example.com"], "npub1xyz");
+        assert!(parse_routes("").unwrap().is_empty());
+        assert!(parse_routes("alice@example.com").is_err());
+        assert!(parse_routes("alice@example.com=").is_err());
+    }
+
+    #[test]
+    fn test_parse_forwarded() {
+        let raw = b"From: Alice <Alice@Example.com>\r\n\
+To: sage+memory@example.com\r\n\
+Subject: Fwd: FW: Your order #1234\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+---------- Forwarded message ---------\r\n\
+From: Shop <orders@shop.example>\r\n\
+> Total: $42.00\r\n";
+        let email = parse_forwarded(raw).unwrap();
+        assert_eq!(email.from, "alice@example.com");
+        assert_eq!(email.subject, "Your order #1234");
+        assert!(email.body.contains("Forwarded message"));
+        assert!(email.body.ends_with("> Total: $42.00"));
+    }
+
+    #[test]
+    fn test_tags_and_messages() {
+        assert_eq!(normalize_kind(" Receipt "), "receipt");
+        assert_eq!(normalize_kind("invoice"), "other");
+
+        let keywords = vec![
+            "Shop".to_string(),
+            "receipt".to_string(),
+            " ".to_string(),
+            "shoes".to_string(),
+        ];
+        assert_eq!(
+            passage_tags("receipt", &keywords),
+            vec!["email", "receipt", "shop", "shoes"]
+        );
+
+        assert_eq!(
+            confirmation("itinerary", "Trip to Lisbon"),
+            "📥 Saved the itinerary you forwarded (\"Trip to Lisbon\") to my memory."
+        );
+        assert_eq!(
+            confirmation("other", ""),
+            "📥 Saved the email you forwarded to my memory."
+        );
+        assert_eq!(
+            passage("receipt", "Order", "Total $42", "2026-10-17"),
+            "Forwarded email (receipt) \"Order\", 2026-10-17:\nTotal $42"
+        );
+    }
+}
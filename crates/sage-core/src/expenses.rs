@@ -0,0 +1,464 @@
+//! Expense tracking
+//!
+//! Receipts and bills sent as images are parsed by the vision model into
+//! structured expenses (see `vision::extract_receipt`) and stored in the
+//! `expenses` table. The `spending_report` tool summarizes them over a period;
+//! a monthly summary can be set up by scheduling a recurring `spending_report`
+//! tool call (e.g. cron `0 9 1 * *` with period `last_month`).
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::sage_agent::{Tool, ToolResult};
+use crate::schema::expenses;
+use crate::vision::{ReceiptData, VisionConfig};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A stored expense
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = expenses)]
+#[allow(dead_code)]
+pub struct Expense {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub merchant: String,
+    pub total_cents: i64,
+    pub currency: String,
+    pub purchased_on: NaiveDate,
+    pub category: String,
+    pub line_items: serde_json::Value,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Diesel model for inserting a new expense
+#[derive(Insertable)]
+#[diesel(table_name = expenses)]
+struct NewExpense {
+    id: Uuid,
+    agent_id: Uuid,
+    merchant: String,
+    total_cents: i64,
+    currency: String,
+    purchased_on: NaiveDate,
+    category: String,
+    line_items: serde_json::Value,
+    source: String,
+}
+
+/// A receipt line item as stored in `expenses.line_items`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredLineItem {
+    pub description: String,
+    pub quantity: Option<f64>,
+    pub amount_cents: i64,
+}
+
+/// Categories the receipt parser is asked to choose from
+const CATEGORIES: &[&str] = &[
+    "groceries",
+    "dining",
+    "transport",
+    "utilities",
+    "shopping",
+    "health",
+    "entertainment",
+    "travel",
+    "housing",
+    "other",
+];
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct ExpenseDb {
+    conn: Arc<DbConn>,
+}
+
+impl ExpenseDb {
+    /// Create a new ExpenseDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Store an expense parsed from a receipt.
+    ///
+    /// `fallback_date` is used when the receipt has no (valid) printed date.
+    pub fn insert_receipt(
+        &self,
+        agent_id: Uuid,
+        receipt: &ReceiptData,
+        fallback_date: NaiveDate,
+    ) -> Result<Expense> {
+        self.conn.run(|conn| {
+            let line_items: Vec<StoredLineItem> = receipt
+                .line_items
+                .iter()
+                .map(|item| StoredLineItem {
+                    description: item.description.clone(),
+                    quantity: item.quantity,
+                    amount_cents: to_cents(item.amount),
+                })
+                .collect();
+
+            let new_expense = NewExpense {
+                id: Uuid::new_v4(),
+                agent_id,
+                merchant: receipt.merchant.trim().to_string(),
+                total_cents: to_cents(receipt.total),
+                currency: normalize_currency(receipt.currency.as_deref()),
+                purchased_on: receipt
+                    .date
+                    .as_deref()
+                    .and_then(|d| NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok())
+                    .unwrap_or(fallback_date),
+                category: normalize_category(receipt.category.as_deref()),
+                line_items: serde_json::to_value(&line_items)?,
+                source: "receipt".to_string(),
+            };
+
+            let expense = diesel::insert_into(expenses::table)
+                .values(&new_expense)
+                .returning(Expense::as_returning())
+                .get_result(conn)
+                .context("Failed to insert expense")?;
+
+            Ok(expense)
+        })
+    }
+
+    /// Expenses for an agent with `from <= purchased_on < to`, oldest first
+    pub fn list_between(
+        &self,
+        agent_id: Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Expense>> {
+        self.conn.run(|conn| {
+            let rows = expenses::table
+                .filter(expenses::agent_id.eq(agent_id))
+                .filter(expenses::purchased_on.ge(from))
+                .filter(expenses::purchased_on.lt(to))
+                .order((expenses::purchased_on.asc(), expenses::created_at.asc()))
+                .select(Expense::as_select())
+                .load(conn)
+                .context("Failed to query expenses")?;
+
+            Ok(rows)
+        })
+    }
+}
+
+/// Extract a receipt from an image and store it as an expense.
+///
+/// Returns a short note for the conversation (e.g. "[Receipt saved: ...]"),
+/// or `None` if the image turned out not to be a receipt.
+pub async fn record_receipt(
+    expense_db: &ExpenseDb,
+    agent_id: Uuid,
+    vision: &VisionConfig,
+    image_path: &str,
+    content_type: &str,
+) -> Result<Option<String>> {
+    let Some(receipt) = crate::vision::extract_receipt(vision, image_path, content_type).await?
+    else {
+        return Ok(None);
+    };
+
+    let expense = expense_db.insert_receipt(agent_id, &receipt, Utc::now().date_naive())?;
+    info!(
+        "Stored expense {} ({} {})",
+        expense.id, expense.merchant, expense.total_cents
+    );
+
+    Ok(Some(format!(
+        "[Receipt saved to expenses: {} - {} on {} ({}, {} line items)]",
+        expense.merchant,
+        format_amount(expense.total_cents, &expense.currency),
+        expense.purchased_on,
+        expense.category,
+        receipt.line_items.len()
+    )))
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn to_cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+fn normalize_currency(currency: Option<&str>) -> String {
+    match currency.map(|c| c.trim().to_uppercase()) {
+        Some(c) if c.len() == 3 && c.chars().all(|ch| ch.is_ascii_alphabetic()) => c,
+        _ => "USD".to_string(),
+    }
+}
+
+fn normalize_category(category: Option<&str>) -> String {
+    let category = category.unwrap_or("").trim().to_lowercase();
+    if CATEGORIES.contains(&category.as_str()) {
+        category
+    } else {
+        "other".to_string()
+    }
+}
+
+/// Format minor units as "12.34 USD"
+fn format_amount(cents: i64, currency: &str) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.abs();
+    format!("{}{}.{:02} {}", sign, cents / 100, cents % 100, currency)
+}
+
+/// Resolve a report period to a half-open date range `[from, to)` plus a label.
+///
+/// Accepts "this_month", "last_month", "this_year", "YYYY-MM", "YYYY", or
+/// "YYYY-MM-DD..YYYY-MM-DD" (inclusive end).
+fn parse_period(spec: &str, today: NaiveDate) -> Result<(NaiveDate, NaiveDate, String)> {
+    let month_start = |date: NaiveDate| date.with_day(1).unwrap_or(date);
+    let spec = spec.trim();
+
+    match spec {
+        "" | "this_month" => {
+            let from = month_start(today);
+            Ok((
+                from,
+                from + Months::new(1),
+                from.format("%B %Y").to_string(),
+            ))
+        }
+        "last_month" => {
+            let from = month_start(today) - Months::new(1);
+            Ok((
+                from,
+                from + Months::new(1),
+                from.format("%B %Y").to_string(),
+            ))
+        }
+        "this_year" => {
+            let from = NaiveDate::from_ymd_opt(today.year(), 1, 1)
+                .ok_or_else(|| anyhow::anyhow!("Invalid year"))?;
+            Ok((from, from + Months::new(12), today.year().to_string()))
+        }
+        _ => {
+            if let Some((start, end)) = spec.split_once("..") {
+                let from = NaiveDate::parse_from_str(start.trim(), "%Y-%m-%d")
+                    .with_context(|| format!("Invalid start date: {}", start))?;
+                let end = NaiveDate::parse_from_str(end.trim(), "%Y-%m-%d")
+                    .with_context(|| format!("Invalid end date: {}", end))?;
+                if end < from {
+                    anyhow::bail!("Period end is before its start");
+                }
+                let to = end
+                    .succ_opt()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid end date"))?;
+                Ok((from, to, format!("{} to {}", from, end)))
+            } else if let Ok(from) = NaiveDate::parse_from_str(&format!("{}-01", spec), "%Y-%m-%d")
+            {
+                Ok((
+                    from,
+                    from + Months::new(1),
+                    from.format("%B %Y").to_string(),
+                ))
+            } else if let Ok(year) = spec.parse::<i32>() {
+                let from = NaiveDate::from_ymd_opt(year, 1, 1)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid year: {}", year))?;
+                Ok((from, from + Months::new(12), year.to_string()))
+            } else {
+                anyhow::bail!(
+                    "Invalid period '{}'. Use this_month, last_month, this_year, YYYY-MM, YYYY, or YYYY-MM-DD..YYYY-MM-DD",
+                    spec
+                )
+            }
+        }
+    }
+}
+
+/// Render a spending report for a set of expenses
+fn format_report(expenses: &[Expense], label: &str, group_by: &str) -> String {
+    if expenses.is_empty() {
+        return format!("No expenses recorded for {}.", label);
+    }
+
+    // Totals per currency, and per (group, currency)
+    let mut totals: BTreeMap<&str, i64> = BTreeMap::new();
+    let mut groups: BTreeMap<(&str, &str), (i64, usize)> = BTreeMap::new();
+    for expense in expenses {
+        *totals.entry(expense.currency.as_str()).or_default() += expense.total_cents;
+        let key = match group_by {
+            "merchant" => expense.merchant.as_str(),
+            _ => expense.category.as_str(),
+        };
+        let entry = groups.entry((key, expense.currency.as_str())).or_default();
+        entry.0 += expense.total_cents;
+        entry.1 += 1;
+    }
+
+    let mut lines = vec![format!(
+        "Spending for {} ({} expenses):",
+        label,
+        expenses.len()
+    )];
+    for (currency, cents) in &totals {
+        lines.push(format!("  Total: {}", format_amount(*cents, currency)));
+    }
+
+    lines.push(String::new());
+    lines.push(format!("By {}:", group_by));
+    let mut grouped: Vec<_> = groups.into_iter().collect();
+    grouped.sort_by(|a, b| b.1 .0.cmp(&a.1 .0));
+    for ((key, currency), (cents, count)) in grouped {
+        lines.push(format!(
+            "  {}: {} ({} expense{})",
+            key,
+            format_amount(cents, currency),
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push("Largest:".to_string());
+    let mut largest: Vec<&Expense> = expenses.iter().collect();
+    largest.sort_by(|a, b| b.total_cents.cmp(&a.total_cents));
+    for expense in largest.into_iter().take(5) {
+        lines.push(format!(
+            "  {} {} - {} [{}]",
+            expense.purchased_on,
+            expense.merchant,
+            format_amount(expense.total_cents, &expense.currency),
+            expense.category
+        ));
+    }
+
+    lines.join("\n")
+}
+
+// ============================================================================
+// Spending Report Tool
+// ============================================================================
+
+pub struct SpendingReportTool {
+    expense_db: Arc<ExpenseDb>,
+    agent_id: Uuid,
+    default_timezone: String,
+}
+
+impl SpendingReportTool {
+    pub fn new(expense_db: Arc<ExpenseDb>, agent_id: Uuid, default_timezone: String) -> Self {
+        Self {
+            expense_db,
+            agent_id,
+            default_timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for SpendingReportTool {
+    fn name(&self) -> &str {
+        "spending_report"
+    }
+
+    fn description(&self) -> &str {
+        "Summarize expenses saved from receipt and bill photos: totals, breakdown by category or merchant, and the largest purchases. For a monthly summary, schedule a recurring tool_call of spending_report with period 'last_month'."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"period": "this_month (default), last_month, this_year, YYYY-MM, YYYY, or YYYY-MM-DD..YYYY-MM-DD", "group_by": "category (default) or merchant"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let group_by = match args.get("group_by").map(|s| s.trim()) {
+            None | Some("") | Some("category") => "category",
+            Some("merchant") => "merchant",
+            Some(other) => {
+                return Ok(ToolResult::error(format!(
+                    "Invalid group_by '{}'. Use 'category' or 'merchant'",
+                    other
+                )))
+            }
+        };
+
+        // "Today" in the user's timezone so month boundaries match theirs
+        let tz: Tz = self.default_timezone.parse().unwrap_or(chrono_tz::UTC);
+        let today = Utc::now().with_timezone(&tz).date_naive();
+
+        let (from, to, label) =
+            match parse_period(args.get("period").map(|s| s.as_str()).unwrap_or(""), today) {
+                Ok(period) => period,
+                Err(e) => return Ok(ToolResult::error(e.to_string())),
+            };
+
+        let expenses = self.expense_db.list_between(self.agent_id, from, to)?;
+        Ok(ToolResult::success(format_report(
+            &expenses, &label, group_by,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_parse_period_months() {
+        let today = date("2026-03-15");
+        let (from, to, label) = parse_period("this_month", today).unwrap();
+        assert_eq!((from, to), (date("2026-03-01"), date("2026-04-01")));
+        assert_eq!(label, "March 2026");
+
+        let (from, to, _) = parse_period("last_month", date("2026-01-10")).unwrap();
+        assert_eq!((from, to), (date("2025-12-01"), date("2026-01-01")));
+
+        let (from, to, _) = parse_period("2025-11", today).unwrap();
+        assert_eq!((from, to), (date("2025-11-01"), date("2025-12-01")));
+    }
+
+    #[test]
+    fn test_parse_period_ranges() {
+        let today = date("2026-03-15");
+        let (from, to, _) = parse_period("2025", today).unwrap();
+        assert_eq!((from, to), (date("2025-01-01"), date("2026-01-01")));
+
+        let (from, to, _) = parse_period("2026-02-03..2026-02-10", today).unwrap();
+        assert_eq!((from, to), (date("2026-02-03"), date("2026-02-11")));
+
+        assert!(parse_period("2026-02-10..2026-02-03", today).is_err());
+        assert!(parse_period("next week", today).is_err());
+    }
+
+    #[test]
+    fn test_amount_helpers() {
+        assert_eq!(to_cents(12.345), 1235);
+        assert_eq!(to_cents(0.1 + 0.2), 30);
+        assert_eq!(format_amount(1205, "USD"), "12.05 USD");
+        assert_eq!(format_amount(-50, "EUR"), "-0.50 EUR");
+        assert_eq!(normalize_currency(Some("eur")), "EUR");
+        assert_eq!(normalize_currency(Some("$")), "USD");
+        assert_eq!(normalize_category(Some("Dining")), "dining");
+        assert_eq!(normalize_category(Some("pets")), "other");
+    }
+}
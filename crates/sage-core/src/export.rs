@@ -0,0 +1,420 @@
+//! Encrypted Exports
+//!
+//! `export_conversation` writes a chat's conversation (and core memory) to a
+//! JSON file and sends it over the messenger. So the file isn't the weakest
+//! link in an end-to-end encrypted setup, the chat's owner can set an export
+//! passphrase with `/export-key <passphrase>` in a direct chat; every export
+//! is then encrypted before it touches the disk.
+//!
+//! `/export-key` is handled by the main loop and never reaches the agent or
+//! the message history. Only the Argon2id-derived key and its salt are
+//! stored, per main agent (a chat's `/topic` threads use it too).
+//!
+//! Encrypted files (`.sage-enc`) are `MAGIC | salt (16) | nonce (12) |
+//! AES-256-GCM ciphertext`; `sage-admin export decrypt` opens them.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Context, Result};
+use argon2::Argon2;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::messenger::{format_bytes, AttachmentOutbox, OutgoingAttachment};
+use crate::sage_agent::{Tool, ToolResult};
+use crate::schema::{blocks, export_keys, messages};
+
+/// Header of an encrypted export
+pub const MAGIC: &[u8; 8] = b"SAGEENC1";
+
+/// Extension of encrypted exports
+pub const ENCRYPTED_EXTENSION: &str = "sage-enc";
+
+/// Shortest passphrase `/export-key` accepts
+pub const MIN_PASSPHRASE_CHARS: usize = 12;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+// ============================================================================
+// Encryption
+// ============================================================================
+
+/// An export key derived from a passphrase
+#[derive(Clone, PartialEq)]
+pub struct ExportKey {
+    salt: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl ExportKey {
+    /// Derive a key from `passphrase` with a fresh random salt
+    pub fn generate(passphrase: &str) -> Result<Self> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, &salt)
+    }
+
+    /// Derive the key for `passphrase` and `salt` (Argon2id, default cost)
+    pub fn derive(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = vec![0u8; KEY_LEN];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("Failed to derive export key: {}", e))?;
+        Ok(Self {
+            salt: salt.to_vec(),
+            key,
+        })
+    }
+
+    /// Encrypt `plaintext` into the `.sage-enc` format
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let cipher = Aes256Gcm::new_from_slice(&self.key)
+            .map_err(|_| anyhow::anyhow!("Invalid export key"))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt export"))?;
+
+        let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+}
+
+/// Whether `data` is an encrypted export
+#[allow(dead_code)]
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Decrypt a `.sage-enc` export with its passphrase
+#[allow(dead_code)]
+pub fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if !is_encrypted(data) || data.len() < MAGIC.len() + SALT_LEN + NONCE_LEN {
+        anyhow::bail!("Not an encrypted Sage export");
+    }
+    let (salt, rest) = data[MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = ExportKey::derive(passphrase, salt)?;
+    let cipher =
+        Aes256Gcm::new_from_slice(&key.key).map_err(|_| anyhow::anyhow!("Invalid export key"))?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupted export"))
+}
+
+// ============================================================================
+// /export-key
+// ============================================================================
+
+/// An `/export-key` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportKeyCommand {
+    /// Say whether a passphrase is set
+    Show,
+    Set(String),
+    /// Stop encrypting exports
+    Clear,
+}
+
+/// Parse `/export-key`, `/export-key off` or `/export-key <passphrase>`
+pub fn parse_export_key_command(text: &str) -> Option<ExportKeyCommand> {
+    let rest = text.trim().strip_prefix("/export-key")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(match rest.trim() {
+        "" => ExportKeyCommand::Show,
+        "off" | "clear" | "none" => ExportKeyCommand::Clear,
+        passphrase => ExportKeyCommand::Set(passphrase.to_string()),
+    })
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+/// What an export contains
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportDocument {
+    pub exported_at: DateTime<Utc>,
+    pub agent_id: Uuid,
+    pub blocks: Vec<ExportedBlock>,
+    /// Oldest first
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct ExportedBlock {
+    pub label: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct ExportedMessage {
+    pub role: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ExportDb {
+    conn: Arc<DbConn>,
+}
+
+impl ExportDb {
+    /// Create a new ExportDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// The agent's export key, if a passphrase is set
+    pub fn key(&self, agent_id: Uuid) -> Result<Option<ExportKey>> {
+        self.conn.run(|conn| {
+            let row: Option<(Vec<u8>, Vec<u8>)> = export_keys::table
+                .filter(export_keys::agent_id.eq(agent_id))
+                .select((export_keys::salt, export_keys::key))
+                .first(conn)
+                .optional()
+                .context("Failed to load export key")?;
+            Ok(row.map(|(salt, key)| ExportKey { salt, key }))
+        })
+    }
+
+    /// Store (or replace) the agent's export key
+    pub fn set_key(&self, agent_id: Uuid, key: &ExportKey) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::insert_into(export_keys::table)
+                .values((
+                    export_keys::agent_id.eq(agent_id),
+                    export_keys::salt.eq(&key.salt),
+                    export_keys::key.eq(&key.key),
+                ))
+                .on_conflict(export_keys::agent_id)
+                .do_update()
+                .set((
+                    export_keys::salt.eq(&key.salt),
+                    export_keys::key.eq(&key.key),
+                    export_keys::updated_at.eq(Utc::now()),
+                ))
+                .execute(conn)
+                .context("Failed to store export key")?;
+            Ok(())
+        })
+    }
+
+    /// Remove the agent's export key. Returns whether one was set.
+    pub fn clear_key(&self, agent_id: Uuid) -> Result<bool> {
+        self.conn.run(|conn| {
+            let deleted =
+                diesel::delete(export_keys::table.filter(export_keys::agent_id.eq(agent_id)))
+                    .execute(conn)
+                    .context("Failed to remove export key")?;
+            Ok(deleted > 0)
+        })
+    }
+
+    /// The conversation of `agent_id` since `since`, with the core memory of
+    /// `core_agent_id`
+    fn document(
+        &self,
+        agent_id: Uuid,
+        core_agent_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<ExportDocument> {
+        self.conn.run(|conn| {
+            let blocks: Vec<ExportedBlock> = blocks::table
+                .filter(blocks::agent_id.eq(core_agent_id.to_string()))
+                .select((blocks::label, blocks::value))
+                .order(blocks::label.asc())
+                .load(conn)
+                .context("Failed to load blocks")?;
+
+            let mut query = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .filter(messages::role.ne("tool"))
+                .into_boxed();
+            if let Some(since) = since {
+                query = query.filter(messages::created_at.ge(since));
+            }
+            let messages: Vec<ExportedMessage> = query
+                .select((messages::role, messages::content, messages::created_at))
+                .order(messages::sequence_id.asc())
+                .load(conn)
+                .context("Failed to load messages")?;
+
+            Ok(ExportDocument {
+                exported_at: Utc::now(),
+                agent_id,
+                blocks,
+                messages,
+            })
+        })
+    }
+}
+
+// ============================================================================
+// Tools
+// ============================================================================
+
+pub struct ExportConversationTool {
+    export_db: Arc<ExportDb>,
+    agent_id: Uuid,
+    /// Main agent, owner of the core memory and export key
+    core_agent_id: Uuid,
+    workspace: PathBuf,
+    outbox: AttachmentOutbox,
+}
+
+impl ExportConversationTool {
+    pub fn new(
+        export_db: Arc<ExportDb>,
+        agent_id: Uuid,
+        core_agent_id: Uuid,
+        workspace: impl Into<PathBuf>,
+        outbox: AttachmentOutbox,
+    ) -> Self {
+        Self {
+            export_db,
+            agent_id,
+            core_agent_id,
+            workspace: workspace.into(),
+            outbox,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ExportConversationTool {
+    fn name(&self) -> &str {
+        "export_conversation"
+    }
+
+    fn description(&self) -> &str {
+        "Export this conversation and your core memory as a file sent to the user. If the user set an export passphrase (/export-key), the file is encrypted with it; otherwise tell them the export is unencrypted and that they can send '/export-key <passphrase>' in a direct chat first. Never ask the user to tell you their passphrase."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"days": "optional: only messages from the last N days (default: the whole conversation)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let since = match args.get("days").map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            Some(days) => match days.parse::<i64>() {
+                Ok(days) if days > 0 => Some(Utc::now() - Duration::days(days)),
+                _ => return Ok(ToolResult::error(format!("Invalid days: '{}'", days))),
+            },
+            None => None,
+        };
+
+        let document = self
+            .export_db
+            .document(self.agent_id, self.core_agent_id, since)?;
+        let json = serde_json::to_vec_pretty(&document)?;
+        let stamp = document.exported_at.format("%Y%m%d-%H%M%S");
+        let (path, bytes, encrypted) = match self.export_db.key(self.core_agent_id)? {
+            Some(key) => (
+                self.workspace.join(format!(
+                    "sage-export-{}.json.{}",
+                    stamp, ENCRYPTED_EXTENSION
+                )),
+                key.encrypt(&json)?,
+                true,
+            ),
+            None => (
+                self.workspace.join(format!("sage-export-{}.json", stamp)),
+                json,
+                false,
+            ),
+        };
+        std::fs::write(&path, &bytes)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        info!(
+            "Exported {} messages for agent {} (encrypted: {})",
+            document.messages.len(),
+            self.agent_id,
+            encrypted
+        );
+
+        let caption = if encrypted {
+            "Conversation export, encrypted with your export passphrase"
+        } else {
+            "Conversation export (unencrypted)"
+        };
+        self.outbox
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Outbox lock error: {}", e))?
+            .push(OutgoingAttachment {
+                path,
+                caption: Some(caption.to_string()),
+            });
+
+        Ok(ToolResult::success(format!(
+            "Queued export of {} messages and {} memory blocks ({}, {}).",
+            document.messages.len(),
+            document.blocks.len(),
+            format_bytes(bytes.len() as u64),
+            if encrypted {
+                "encrypted with the user's export passphrase"
+            } else {
+                "NOT encrypted: no export passphrase is set"
+            }
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = ExportKey::generate("correct horse battery staple").unwrap();
+        let sealed = key.encrypt(b"{\"messages\": []}").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(b"messages".len()).any(|w| w == b"messages"));
+        assert_eq!(
+            decrypt("correct horse battery staple", &sealed).unwrap(),
+            b"{\"messages\": []}"
+        );
+        assert!(decrypt("wrong passphrase", &sealed).is_err());
+        assert!(decrypt("correct horse battery staple", b"{}").is_err());
+
+        // A fresh nonce per export
+        assert_ne!(sealed, key.encrypt(b"{\"messages\": []}").unwrap());
+    }
+
+    #[test]
+    fn test_parse_export_key_command() {
+        assert_eq!(
+            parse_export_key_command("/export-key"),
+            Some(ExportKeyCommand::Show)
+        );
+        assert_eq!(
+            parse_export_key_command(" /export-key off "),
+            Some(ExportKeyCommand::Clear)
+        );
+        assert_eq!(
+            parse_export_key_command("/export-key correct horse battery"),
+            Some(ExportKeyCommand::Set("correct horse battery".to_string()))
+        );
+        assert_eq!(parse_export_key_command("/export-keys"), None);
+        assert_eq!(parse_export_key_command("export-key secret"), None);
+    }
+}
@@ -0,0 +1,320 @@
+//! Reaction Feedback
+//!
+//! Emoji reactions users put on Sage's messages are an implicit quality
+//! signal. Each one is stored in `message_reactions` next to the message it
+//! targets (matched by send time, since Signal identifies messages by the
+//! sender's timestamp), scored as positive, negative or neutral, and
+//! aggregated per agent. The aggregate is served at `GET /feedback/{agent_id}`
+//! for evals and GEPA, and - with `REACTION_STYLE_HINTS` - a user who keeps
+//! reacting badly to long replies gets shorter ones.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::schema::{message_reactions, messages};
+
+/// Replies longer than this many characters count as long
+pub const LONG_MESSAGE_CHARS: i32 = 600;
+
+/// How far a reaction's target timestamp may be from the stored message's
+/// creation time, in seconds
+const MATCH_WINDOW_SECS: i64 = 15;
+
+/// Reactions this recent make up the aggregate
+const SUMMARY_WINDOW_DAYS: i64 = 30;
+
+/// Negative reactions to long replies before Sage is told to keep it short
+const LONG_NEGATIVE_THRESHOLD: i64 = 3;
+
+const POSITIVE: &[&str] = &[
+    "👍", "❤️", "❤", "♥️", "😂", "🤣", "🙏", "🎉", "😍", "🥰", "🔥", "💯", "👏", "✅", "😊", "🙌",
+    "💪", "⭐", "🤩",
+];
+
+const NEGATIVE: &[&str] = &[
+    "👎",
+    "😡",
+    "😠",
+    "😕",
+    "🙄",
+    "😞",
+    "❌",
+    "🤦",
+    "🤦‍♂️",
+    "🤦‍♀️",
+    "😒",
+    "😬",
+    "🥱",
+    "😴",
+];
+
+/// Score of a reaction emoji: 1 positive, -1 negative, 0 neutral
+pub fn sentiment(emoji: &str) -> i16 {
+    // Ignore skin tone modifiers
+    let base: String = emoji
+        .chars()
+        .filter(|c| !matches!(*c as u32, 0x1F3FB..=0x1F3FF))
+        .collect();
+    if POSITIVE.contains(&base.as_str()) {
+        1
+    } else if NEGATIVE.contains(&base.as_str()) {
+        -1
+    } else {
+        0
+    }
+}
+
+/// Aggregated reactions for an agent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct FeedbackSummary {
+    pub positive: i64,
+    pub negative: i64,
+    pub neutral: i64,
+    /// Positive reactions to replies over `LONG_MESSAGE_CHARS`
+    pub long_positive: i64,
+    /// Negative reactions to replies over `LONG_MESSAGE_CHARS`
+    pub long_negative: i64,
+}
+
+impl FeedbackSummary {
+    /// Net score in [-1, 1] over scored reactions (None without any)
+    pub fn score(&self) -> Option<f32> {
+        let scored = self.positive + self.negative;
+        (scored > 0).then(|| (self.positive - self.negative) as f32 / scored as f32)
+    }
+}
+
+/// Line asking the agent to keep replies short, when the user keeps reacting
+/// negatively to long ones
+pub fn style_note(summary: &FeedbackSummary) -> Option<String> {
+    if summary.long_negative < LONG_NEGATIVE_THRESHOLD
+        || summary.long_negative <= summary.long_positive
+    {
+        return None;
+    }
+    Some(format!(
+        "[Feedback: the user reacted negatively to {} of your longer replies recently. Keep your messages short and to the point.]",
+        summary.long_negative
+    ))
+}
+
+/// A stored reaction with the message it targets
+#[derive(Debug, Clone, Serialize)]
+pub struct ReactionRecord {
+    pub emoji: String,
+    pub sentiment: i16,
+    pub reactor: String,
+    /// Content of the reacted-to message, if it was found
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+#[derive(Insertable)]
+#[diesel(table_name = message_reactions)]
+struct NewReaction<'a> {
+    id: Uuid,
+    agent_id: Uuid,
+    message_id: Option<Uuid>,
+    target_ms: i64,
+    reactor: &'a str,
+    emoji: &'a str,
+    sentiment: i16,
+    message_chars: Option<i32>,
+}
+
+pub struct FeedbackDb {
+    conn: Arc<DbConn>,
+}
+
+impl FeedbackDb {
+    /// Create a new FeedbackDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Record (or, with `removed`, take back) a reaction to the agent's message
+    /// sent at `target_ms`. A user's new reaction replaces their previous one.
+    pub fn record(
+        &self,
+        agent_id: Uuid,
+        reactor: &str,
+        target_ms: i64,
+        emoji: &str,
+        removed: bool,
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            if removed {
+                diesel::delete(
+                    message_reactions::table
+                        .filter(message_reactions::agent_id.eq(agent_id))
+                        .filter(message_reactions::target_ms.eq(target_ms))
+                        .filter(message_reactions::reactor.eq(reactor)),
+                )
+                .execute(conn)
+                .context("Failed to remove reaction")?;
+                return Ok(());
+            }
+
+            // The assistant message stored closest to the target's send time
+            let target = Utc
+                .timestamp_millis_opt(target_ms)
+                .single()
+                .context("Invalid reaction target timestamp")?;
+            let window = Duration::seconds(MATCH_WINDOW_SECS);
+            let candidates: Vec<(Uuid, String, DateTime<Utc>)> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .filter(messages::role.eq("assistant"))
+                .filter(messages::created_at.between(target - window, target + window))
+                .select((messages::id, messages::content, messages::created_at))
+                .load(conn)
+                .context("Failed to look up reacted message")?;
+            let message = candidates
+                .into_iter()
+                .min_by_key(|(_, _, created_at)| (*created_at - target).num_milliseconds().abs());
+
+            let new = NewReaction {
+                id: Uuid::new_v4(),
+                agent_id,
+                message_id: message.as_ref().map(|(id, _, _)| *id),
+                target_ms,
+                reactor,
+                emoji,
+                sentiment: sentiment(emoji),
+                message_chars: message
+                    .as_ref()
+                    .map(|(_, content, _)| content.chars().count() as i32),
+            };
+            diesel::insert_into(message_reactions::table)
+                .values(&new)
+                .on_conflict((
+                    message_reactions::agent_id,
+                    message_reactions::target_ms,
+                    message_reactions::reactor,
+                ))
+                .do_update()
+                .set((
+                    message_reactions::emoji.eq(emoji),
+                    message_reactions::sentiment.eq(new.sentiment),
+                    message_reactions::created_at.eq(Utc::now()),
+                ))
+                .execute(conn)
+                .context("Failed to record reaction")?;
+            Ok(())
+        })
+    }
+
+    /// Reactions to the agent's messages over the last `SUMMARY_WINDOW_DAYS`
+    pub fn summary(&self, agent_id: Uuid) -> Result<FeedbackSummary> {
+        let since = Utc::now() - Duration::days(SUMMARY_WINDOW_DAYS);
+        self.conn.run(|conn| {
+            let rows: Vec<(i16, Option<i32>)> = message_reactions::table
+                .filter(message_reactions::agent_id.eq(agent_id))
+                .filter(message_reactions::created_at.ge(since))
+                .select((
+                    message_reactions::sentiment,
+                    message_reactions::message_chars,
+                ))
+                .load(conn)
+                .context("Failed to load reactions")?;
+
+            let mut summary = FeedbackSummary::default();
+            for (sentiment, chars) in rows {
+                let long = chars.is_some_and(|c| c > LONG_MESSAGE_CHARS);
+                match sentiment.signum() {
+                    1 => {
+                        summary.positive += 1;
+                        summary.long_positive += long as i64;
+                    }
+                    -1 => {
+                        summary.negative += 1;
+                        summary.long_negative += long as i64;
+                    }
+                    _ => summary.neutral += 1,
+                }
+            }
+            Ok(summary)
+        })
+    }
+
+    /// Most recent reactions to the agent's messages, newest first
+    pub fn recent(&self, agent_id: Uuid, limit: i64) -> Result<Vec<ReactionRecord>> {
+        self.conn.run(|conn| {
+            let rows: Vec<(String, i16, String, Option<String>, DateTime<Utc>)> =
+                message_reactions::table
+                    .left_join(messages::table)
+                    .filter(message_reactions::agent_id.eq(agent_id))
+                    .order(message_reactions::created_at.desc())
+                    .limit(limit)
+                    .select((
+                        message_reactions::emoji,
+                        message_reactions::sentiment,
+                        message_reactions::reactor,
+                        messages::content.nullable(),
+                        message_reactions::created_at,
+                    ))
+                    .load(conn)
+                    .context("Failed to load reactions")?;
+
+            Ok(rows
+                .into_iter()
+                .map(
+                    |(emoji, sentiment, reactor, message, created_at)| ReactionRecord {
+                        emoji,
+                        sentiment,
+                        reactor,
+                        message,
+                        created_at,
+                    },
+                )
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentiment() {
+        assert_eq!(sentiment("👍"), 1);
+        assert_eq!(sentiment("👍🏽"), 1);
+        assert_eq!(sentiment("❤️"), 1);
+        assert_eq!(sentiment("👎"), -1);
+        assert_eq!(sentiment("🤦‍♀️"), -1);
+        assert_eq!(sentiment("🦀"), 0);
+    }
+
+    #[test]
+    fn test_summary_score_and_style_note() {
+        let mut summary = FeedbackSummary {
+            positive: 3,
+            negative: 1,
+            ..Default::default()
+        };
+        assert_eq!(summary.score(), Some(0.5));
+        assert_eq!(style_note(&summary), None);
+        assert_eq!(FeedbackSummary::default().score(), None);
+
+        summary.long_negative = 3;
+        summary.long_positive = 1;
+        assert!(style_note(&summary)
+            .unwrap()
+            .contains("reacted negatively to 3 of your longer replies"));
+
+        summary.long_positive = 3;
+        assert_eq!(style_note(&summary), None);
+    }
+}
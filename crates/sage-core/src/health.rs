@@ -0,0 +1,318 @@
+//! Readiness Checks
+//!
+//! `GET /health` only proves the process is up. `GET /health/ready` checks the
+//! subsystems Sage needs to actually answer people:
+//! - database: a `SELECT 1` round trip
+//! - messenger: the receive loop is still running, plus when a message last
+//!   came in
+//! - scheduler: how long the longest-waiting due task has been waiting
+//! - embeddings: a small embedding request (cached for `EMBEDDING_CHECK_TTL`
+//!   so frequent probes don't cost an API call each)
+//!
+//! Each component is `ok`, `degraded` (Sage still answers, something is off)
+//! or `down`. Any `down` component makes the endpoint return 503.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::AbortHandle;
+
+use crate::db::DbConn;
+use crate::memory::EmbeddingService;
+use crate::scheduler::SchedulerDb;
+
+/// Due tasks waiting longer than this mark the scheduler degraded
+/// (it polls every 30s, so a healthy one stays well under)
+const SCHEDULER_LAG_DEGRADED_SECS: i64 = 120;
+
+/// Due tasks waiting longer than this mark the scheduler down
+const SCHEDULER_LAG_DOWN_SECS: i64 = 900;
+
+/// How long an embedding check result is reused
+const EMBEDDING_CHECK_TTL: Duration = Duration::from_secs(60);
+
+/// Give up on the embedding API after this long
+const EMBEDDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State of one component
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Ok,
+    Degraded,
+    Down,
+}
+
+/// Result of checking one component
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub name: &'static str,
+    pub status: ComponentStatus,
+    /// What was found (error message, lag, last receive)
+    pub detail: String,
+    /// How long the check took
+    pub latency_ms: u64,
+}
+
+/// Response of `GET /health/ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub status: ComponentStatus,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl ReadinessReport {
+    /// Build a report; the overall status is the worst component's
+    pub fn new(components: Vec<ComponentHealth>) -> Self {
+        Self {
+            status: overall_status(&components),
+            components,
+        }
+    }
+
+    /// Ready unless a component is down
+    pub fn is_ready(&self) -> bool {
+        self.status != ComponentStatus::Down
+    }
+}
+
+/// Worst status among the components (`Ok` when there are none)
+pub fn overall_status(components: &[ComponentHealth]) -> ComponentStatus {
+    if components.iter().any(|c| c.status == ComponentStatus::Down) {
+        ComponentStatus::Down
+    } else if components
+        .iter()
+        .any(|c| c.status == ComponentStatus::Degraded)
+    {
+        ComponentStatus::Degraded
+    } else {
+        ComponentStatus::Ok
+    }
+}
+
+/// Scheduler status for the given lag of the oldest due task
+pub fn scheduler_status(lag_secs: Option<i64>) -> ComponentStatus {
+    match lag_secs {
+        Some(lag) if lag > SCHEDULER_LAG_DOWN_SECS => ComponentStatus::Down,
+        Some(lag) if lag > SCHEDULER_LAG_DEGRADED_SECS => ComponentStatus::Degraded,
+        _ => ComponentStatus::Ok,
+    }
+}
+
+/// Runs the readiness checks. Main records received messages on it and hands
+/// it the receive loop's task.
+pub struct HealthMonitor {
+    db: Arc<DbConn>,
+    scheduler_db: Arc<SchedulerDb>,
+    embedding: EmbeddingService,
+    receive_loop: Mutex<Option<AbortHandle>>,
+    last_receive: Mutex<Option<DateTime<Utc>>>,
+    embedding_check: tokio::sync::Mutex<Option<(Instant, ComponentHealth)>>,
+}
+
+impl HealthMonitor {
+    /// Create a monitor on the shared database pool
+    pub fn connect(
+        db_url: &str,
+        scheduler_db: Arc<SchedulerDb>,
+        embedding: EmbeddingService,
+    ) -> Result<Self> {
+        Ok(Self {
+            db: Arc::new(DbConn::connect(db_url)?),
+            scheduler_db,
+            embedding,
+            receive_loop: Mutex::new(None),
+            last_receive: Mutex::new(None),
+            embedding_check: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    /// Watch the messenger's receive loop task
+    pub fn set_receive_loop(&self, handle: AbortHandle) {
+        if let Ok(mut receive_loop) = self.receive_loop.lock() {
+            *receive_loop = Some(handle);
+        }
+    }
+
+    /// Note that the messenger delivered something
+    pub fn record_receive(&self) {
+        if let Ok(mut last) = self.last_receive.lock() {
+            *last = Some(Utc::now());
+        }
+    }
+
+    /// Check every component
+    pub async fn check(self: &Arc<Self>) -> ReadinessReport {
+        let database = {
+            let monitor = self.clone();
+            blocking_check("database", move || monitor.check_database())
+        };
+        let scheduler = {
+            let monitor = self.clone();
+            blocking_check("scheduler", move || monitor.check_scheduler())
+        };
+        let (database, scheduler, embeddings) =
+            tokio::join!(database, scheduler, self.check_embeddings());
+        ReadinessReport::new(vec![
+            database,
+            self.check_messenger(),
+            scheduler,
+            embeddings,
+        ])
+    }
+
+    fn check_database(&self) -> (ComponentStatus, String) {
+        let result = self.db.run(|conn| {
+            diesel::sql_query("SELECT 1")
+                .execute(conn)
+                .map_err(anyhow::Error::from)
+        });
+        match result {
+            Ok(_) => {
+                let (open, idle) = self.db.pool_state();
+                (
+                    ComponentStatus::Ok,
+                    format!("connected ({} pooled connections, {} idle)", open, idle),
+                )
+            }
+            Err(e) => (ComponentStatus::Down, e.to_string()),
+        }
+    }
+
+    fn check_scheduler(&self) -> (ComponentStatus, String) {
+        match self.scheduler_db.oldest_due_at() {
+            Ok(oldest) => {
+                let lag = oldest.map(|due| (Utc::now() - due).num_seconds().max(0));
+                let detail = match lag {
+                    Some(lag) => format!("oldest due task waiting {}s", lag),
+                    None => "no tasks due".to_string(),
+                };
+                (scheduler_status(lag), detail)
+            }
+            Err(e) => (ComponentStatus::Down, e.to_string()),
+        }
+    }
+
+    fn check_messenger(&self) -> ComponentHealth {
+        let running = self
+            .receive_loop
+            .lock()
+            .ok()
+            .and_then(|handle| handle.as_ref().map(|h| !h.is_finished()))
+            .unwrap_or(true);
+        let last_receive = self.last_receive.lock().ok().and_then(|last| *last);
+        let received = match last_receive {
+            Some(at) => format!(
+                "last message received {}s ago",
+                (Utc::now() - at).num_seconds().max(0)
+            ),
+            None => "no messages received since startup".to_string(),
+        };
+        let (status, detail) = if running {
+            (ComponentStatus::Ok, received)
+        } else {
+            (
+                ComponentStatus::Down,
+                format!("receive loop stopped; {}", received),
+            )
+        };
+        ComponentHealth {
+            name: "messenger",
+            status,
+            detail,
+            latency_ms: 0,
+        }
+    }
+
+    async fn check_embeddings(&self) -> ComponentHealth {
+        let mut cached = self.embedding_check.lock().await;
+        if let Some((checked_at, ref health)) = *cached {
+            if checked_at.elapsed() < EMBEDDING_CHECK_TTL {
+                return health.clone();
+            }
+        }
+
+        let started = Instant::now();
+        let (status, detail) =
+            match tokio::time::timeout(EMBEDDING_TIMEOUT, self.embedding.embed("health check"))
+                .await
+            {
+                Ok(Ok(_)) => (ComponentStatus::Ok, "reachable".to_string()),
+                // Memory search and inserts fail, but Sage can still reply
+                Ok(Err(e)) => (ComponentStatus::Degraded, e.to_string()),
+                Err(_) => (
+                    ComponentStatus::Degraded,
+                    format!("no response within {}s", EMBEDDING_TIMEOUT.as_secs()),
+                ),
+            };
+        let health = ComponentHealth {
+            name: "embeddings",
+            status,
+            detail,
+            latency_ms: started.elapsed().as_millis() as u64,
+        };
+        *cached = Some((Instant::now(), health.clone()));
+        health
+    }
+}
+
+/// Run a blocking check off the async runtime and time it
+async fn blocking_check<F>(name: &'static str, check: F) -> ComponentHealth
+where
+    F: FnOnce() -> (ComponentStatus, String) + Send + 'static,
+{
+    let started = Instant::now();
+    let (status, detail) = tokio::task::spawn_blocking(check)
+        .await
+        .unwrap_or_else(|e| (ComponentStatus::Down, format!("check failed: {}", e)));
+    ComponentHealth {
+        name,
+        status,
+        detail,
+        latency_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(status: ComponentStatus) -> ComponentHealth {
+        ComponentHealth {
+            name: "test",
+            status,
+            detail: String::new(),
+            latency_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_scheduler_status() {
+        assert_eq!(scheduler_status(None), ComponentStatus::Ok);
+        assert_eq!(scheduler_status(Some(30)), ComponentStatus::Ok);
+        assert_eq!(scheduler_status(Some(300)), ComponentStatus::Degraded);
+        assert_eq!(scheduler_status(Some(3600)), ComponentStatus::Down);
+    }
+
+    #[test]
+    fn test_overall_status() {
+        let report = ReadinessReport::new(vec![
+            component(ComponentStatus::Ok),
+            component(ComponentStatus::Degraded),
+        ]);
+        assert_eq!(report.status, ComponentStatus::Degraded);
+        assert!(report.is_ready());
+
+        let report = ReadinessReport::new(vec![
+            component(ComponentStatus::Degraded),
+            component(ComponentStatus::Down),
+        ]);
+        assert_eq!(report.status, ComponentStatus::Down);
+        assert!(!report.is_ready());
+
+        assert_eq!(overall_status(&[]), ComponentStatus::Ok);
+    }
+}
@@ -0,0 +1,696 @@
+//! HTTP server
+//!
+//! Serves the health checks (`GET /health`, and `GET /health/ready` with
+//! per-subsystem status, see `health`), reaction feedback per agent
+//! (`GET /feedback/{agent_id}`, for evals and GEPA), daily LLM token and
+//! cost aggregates (`GET /usage?days=&agent_id=`, see `usage`), and when
+//! `HTTP_AUTH_TOKEN` is set turn transcripts (`GET /turns/{turn_id}`),
+//! review of messages held by the output guardrails (`GET /held`,
+//! `POST /held/{id}/release`, `POST /held/{id}/discard`) and admin
+//! introspection (`GET /admin/agents`, and per agent `.../blocks`,
+//! `.../messages?limit=`, `.../schedules`, anonymized GEPA examples
+//! `.../gepa-examples?limit=`, `GET .../compact` to preview and
+//! `POST .../compact` to run a compaction; see `admin`), and - with
+//! `MESSENGER=webhook` - the chat endpoints `POST /message` and
+//! `GET /messages/{user_id}` (see `webhook`).
+//! The server binds to `HTTP_BIND_ADDRESS:HEALTH_PORT`. When `HTTP_AUTH_TOKEN`
+//! is set, every route requires an `Authorization: Bearer <token>` header.
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::admin::{self, AdminDb};
+use crate::agent_manager::{AgentManager, ContextType};
+use crate::feedback::{FeedbackDb, FeedbackSummary, ReactionRecord};
+use crate::guardrails::HeldMessageDb;
+use crate::health::HealthMonitor;
+use crate::memory::EditCause;
+use crate::messenger::Messenger;
+use crate::sage_agent::SageAgent;
+use crate::turn_journal::TurnJournal;
+use crate::usage::{self, UsageDb};
+use crate::webhook::{self, WebhookHub, WebhookRequest, WebhookResponse};
+
+/// Bind and auth settings for the HTTP server
+#[derive(Debug, Clone)]
+pub struct HttpServerConfig {
+    pub bind_address: String,
+    pub port: u16,
+    /// Required bearer token (None = no auth)
+    pub auth_token: Option<String>,
+}
+
+/// Shared handles for request handlers
+#[derive(Clone)]
+pub struct AppState {
+    pub turn_journal: Arc<TurnJournal>,
+    /// Set when running with the webhook messenger
+    pub webhook: Option<Arc<WebhookHub>>,
+    pub held_messages: Arc<HeldMessageDb>,
+    /// Unguarded messenger used to deliver released messages
+    pub outbound: Arc<dyn Messenger>,
+    pub feedback: Arc<FeedbackDb>,
+    pub health: Arc<HealthMonitor>,
+    pub usage: Arc<UsageDb>,
+    pub admin: Arc<AdminDb>,
+    /// Used by admin routes to reach live agents (compaction, schedules)
+    pub agents: Arc<AgentManager>,
+}
+
+/// Health check response
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    version: &'static str,
+}
+
+/// Health check endpoint - returns 200 OK when the service is running
+async fn health_check() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "healthy",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// Readiness: per-component status, 503 if any component is down
+async fn readiness_check(State(state): State<AppState>) -> Response {
+    let report = state.health.check().await;
+    let code = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        warn!("Readiness check failed: {:?}", report.components);
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(report)).into_response()
+}
+
+/// Full transcript of one agent turn as JSON
+async fn get_turn(State(state): State<AppState>, Path(turn_id): Path<Uuid>) -> Response {
+    let journal = state.turn_journal.clone();
+    match tokio::task::spawn_blocking(move || journal.transcript(turn_id)).await {
+        Ok(Ok(Some(transcript))) => Json(transcript).into_response(),
+        Ok(Ok(None)) => (StatusCode::NOT_FOUND, "Turn not found").into_response(),
+        Ok(Err(e)) => {
+            warn!("Failed to load transcript for turn {}: {}", turn_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load transcript",
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Transcript task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Outgoing messages awaiting review, oldest first
+async fn list_held(State(state): State<AppState>) -> Response {
+    let held = state.held_messages.clone();
+    match tokio::task::spawn_blocking(move || held.list_pending()).await {
+        Ok(Ok(messages)) => Json(messages).into_response(),
+        Ok(Err(e)) => {
+            warn!("Failed to list held messages: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("Held message task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Send a held message as originally written
+async fn release_held(State(state): State<AppState>, Path(id): Path<Uuid>) -> Response {
+    let held = state.held_messages.clone();
+    let outbound = state.outbound.clone();
+    let result = tokio::task::spawn_blocking(move || -> anyhow::Result<bool> {
+        let Some(message) = held.get_pending(id)? else {
+            return Ok(false);
+        };
+        outbound.send_message(&message.recipient, &message.content)?;
+        held.resolve(id, "released")?;
+        info!("Released held message {} to {}", id, message.recipient);
+        Ok(true)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(true)) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Ok(false)) => (StatusCode::NOT_FOUND, "No pending message with that id").into_response(),
+        Ok(Err(e)) => {
+            warn!("Failed to release held message {}: {}", id, e);
+            (StatusCode::BAD_GATEWAY, e.to_string()).into_response()
+        }
+        Err(e) => {
+            error!("Held message task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Drop a held message without sending it
+async fn discard_held(State(state): State<AppState>, Path(id): Path<Uuid>) -> Response {
+    let held = state.held_messages.clone();
+    match tokio::task::spawn_blocking(move || held.resolve(id, "discarded")).await {
+        Ok(Ok(true)) => {
+            info!("Discarded held message {}", id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Ok(false)) => (StatusCode::NOT_FOUND, "No pending message with that id").into_response(),
+        Ok(Err(e)) => {
+            warn!("Failed to discard held message {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("Held message task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Most recent reactions returned by `GET /feedback/{agent_id}`
+const FEEDBACK_RECENT_LIMIT: i64 = 50;
+
+/// Reaction feedback for an agent
+#[derive(Serialize)]
+struct FeedbackResponse {
+    summary: FeedbackSummary,
+    /// Net score in [-1, 1] (null without scored reactions)
+    score: Option<f32>,
+    recent: Vec<ReactionRecord>,
+}
+
+/// Aggregated reactions to an agent's messages, plus the latest ones
+async fn get_feedback(State(state): State<AppState>, Path(agent_id): Path<Uuid>) -> Response {
+    let feedback = state.feedback.clone();
+    let result = tokio::task::spawn_blocking(move || -> anyhow::Result<FeedbackResponse> {
+        let summary = feedback.summary(agent_id)?;
+        Ok(FeedbackResponse {
+            summary,
+            score: summary.score(),
+            recent: feedback.recent(agent_id, FEEDBACK_RECENT_LIMIT)?,
+        })
+    })
+    .await;
+
+    match result {
+        Ok(Ok(response)) => Json(response).into_response(),
+        Ok(Err(e)) => {
+            warn!("Failed to load feedback for agent {}: {}", agent_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("Feedback task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Query of `GET /usage`
+#[derive(Deserialize)]
+struct UsageQuery {
+    /// Days to cover (default `usage::DEFAULT_REPORT_DAYS`)
+    days: Option<i64>,
+    /// Only this agent (default all agents)
+    agent_id: Option<Uuid>,
+}
+
+/// Daily LLM token and cost aggregates per agent and call kind
+async fn usage_report(State(state): State<AppState>, Query(query): Query<UsageQuery>) -> Response {
+    let usage_db = state.usage.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        usage_db.report(
+            query.agent_id,
+            query.days.unwrap_or(usage::DEFAULT_REPORT_DAYS),
+        )
+    })
+    .await;
+
+    match result {
+        Ok(Ok(report)) => Json(report).into_response(),
+        Ok(Err(e)) => {
+            warn!("Failed to load usage report: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("Usage report task failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Run a blocking admin query and answer with its JSON (404 if it finds no
+/// agent)
+async fn admin_json<T, F>(what: &'static str, query: F) -> Response
+where
+    T: Serialize + Send + 'static,
+    F: FnOnce() -> anyhow::Result<Option<T>> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(query).await {
+        Ok(Ok(Some(value))) => Json(value).into_response(),
+        Ok(Ok(None)) => StatusCode::NOT_FOUND.into_response(),
+        Ok(Err(e)) => {
+            warn!("Admin {} query failed: {}", what, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            error!("Admin {} task failed: {}", what, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// All agents with their chat and message activity
+async fn admin_list_agents(State(state): State<AppState>) -> Response {
+    let admin = state.admin.clone();
+    admin_json("agents", move || admin.list_agents().map(Some)).await
+}
+
+/// An agent's core memory blocks
+async fn admin_blocks(State(state): State<AppState>, Path(agent_id): Path<Uuid>) -> Response {
+    let admin = state.admin.clone();
+    admin_json("blocks", move || {
+        if !admin.agent_exists(agent_id)? {
+            return Ok(None);
+        }
+        admin.blocks(agent_id).map(Some)
+    })
+    .await
+}
+
+/// Query of `GET /admin/agents/{agent_id}/messages` and `.../gepa-examples`
+#[derive(Deserialize)]
+struct TailQuery {
+    limit: Option<i64>,
+}
+
+/// The tail of an agent's conversation, oldest first
+async fn admin_messages(
+    State(state): State<AppState>,
+    Path(agent_id): Path<Uuid>,
+    Query(query): Query<TailQuery>,
+) -> Response {
+    let admin = state.admin.clone();
+    let limit = query.limit.unwrap_or(admin::DEFAULT_MESSAGE_LIMIT);
+    admin_json("messages", move || {
+        if !admin.agent_exists(agent_id)? {
+            return Ok(None);
+        }
+        admin.recent_messages(agent_id, limit).map(Some)
+    })
+    .await
+}
+
+/// An agent's recent turns as an anonymized GEPA trainset
+async fn admin_gepa_examples(
+    State(state): State<AppState>,
+    Path(agent_id): Path<Uuid>,
+    Query(query): Query<TailQuery>,
+) -> Response {
+    let admin = state.admin.clone();
+    let limit = query.limit.unwrap_or(admin::DEFAULT_EXAMPLE_LIMIT);
+    admin_json("gepa-examples", move || {
+        if !admin.agent_exists(agent_id)? {
+            return Ok(None);
+        }
+        let examples = admin.production_examples(agent_id, limit)?;
+        Ok(Some(admin::GepaTrainset::production(examples)))
+    })
+    .await
+}
+
+/// An agent's pending scheduled tasks, soonest first
+async fn admin_schedules(State(state): State<AppState>, Path(agent_id): Path<Uuid>) -> Response {
+    let admin = state.admin.clone();
+    let scheduler_db = state.agents.scheduler_db();
+    admin_json("schedules", move || {
+        if !admin.agent_exists(agent_id)? {
+            return Ok(None);
+        }
+        scheduler_db
+            .get_tasks_by_agent(agent_id, Some("pending"))
+            .map(Some)
+    })
+    .await
+}
+
+/// Result of `POST /admin/agents/{agent_id}/compact`
+#[derive(Serialize)]
+struct CompactResponse {
+    summary_id: Uuid,
+    from_sequence_id: i64,
+    to_sequence_id: i64,
+    summary: String,
+}
+
+/// The live agent with this id, loading it if needed (404 if there is none)
+async fn live_agent(state: &AppState, agent_id: Uuid) -> Result<Arc<Mutex<SageAgent>>, Response> {
+    let agents = state.agents.clone();
+    let identifier =
+        match tokio::task::spawn_blocking(move || agents.get_signal_identifier(agent_id)).await {
+            Ok(Ok(Some(identifier))) => identifier,
+            Ok(Ok(None)) => return Err(StatusCode::NOT_FOUND.into_response()),
+            Ok(Err(e)) => {
+                warn!("Failed to look up agent {}: {}", agent_id, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            Err(e) => {
+                error!("Agent lookup task failed: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+        };
+
+    match state
+        .agents
+        .get_or_create_agent(&identifier, ContextType::for_identifier(&identifier), None)
+        .await
+    {
+        Ok((_, agent)) => Ok(agent),
+        Err(e) => {
+            warn!("Failed to load agent {}: {}", agent_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Summarize the agent's older messages now. Waits for a running turn to
+/// finish; 409 when there is nothing to compact.
+async fn admin_compact(State(state): State<AppState>, Path(agent_id): Path<Uuid>) -> Response {
+    let agent = match live_agent(&state, agent_id).await {
+        Ok(agent) => agent,
+        Err(response) => return response,
+    };
+    let agent = agent.lock().await;
+    let Some(memory) = agent.memory() else {
+        return (StatusCode::CONFLICT, "agent has no memory").into_response();
+    };
+
+    info!("Admin-triggered compaction for agent {}", agent_id);
+    match usage::with_agent(agent_id, memory.run_compaction()).await {
+        Ok(result) => Json(CompactResponse {
+            summary_id: result.id,
+            from_sequence_id: result.from_sequence_id,
+            to_sequence_id: result.to_sequence_id,
+            summary: result.summary,
+        })
+        .into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
+/// What compacting the agent now would summarize and save, without doing
+/// it; 409 when there is nothing to compact. Waits for a running turn, like
+/// `POST`.
+async fn admin_preview_compaction(
+    State(state): State<AppState>,
+    Path(agent_id): Path<Uuid>,
+) -> Response {
+    let agent = match live_agent(&state, agent_id).await {
+        Ok(agent) => agent,
+        Err(response) => return response,
+    };
+    let agent = agent.lock().await;
+    let Some(memory) = agent.memory() else {
+        return (StatusCode::CONFLICT, "agent has no memory").into_response();
+    };
+    match memory.preview_compaction() {
+        Ok(plan) => Json(plan).into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
+/// Query of `GET /admin/agents/{agent_id}/blocks/{label}/revisions`
+#[derive(Deserialize)]
+struct RevisionQuery {
+    limit: Option<i64>,
+}
+
+/// A core memory block's recorded edits, newest first
+async fn admin_block_revisions(
+    State(state): State<AppState>,
+    Path((agent_id, label)): Path<(Uuid, String)>,
+    Query(query): Query<RevisionQuery>,
+) -> Response {
+    let admin = state.admin.clone();
+    let limit = query.limit.unwrap_or(admin::DEFAULT_REVISION_LIMIT);
+    admin_json("block revisions", move || {
+        if !admin.agent_exists(agent_id)? {
+            return Ok(None);
+        }
+        admin.block_revisions(agent_id, &label, limit).map(Some)
+    })
+    .await
+}
+
+/// Query of `POST /admin/agents/{agent_id}/blocks/{label}/rollback`
+#[derive(Deserialize)]
+struct RollbackQuery {
+    /// Version to restore (default: before the last edit)
+    version: Option<i32>,
+}
+
+/// Result of a block rollback
+#[derive(Serialize)]
+struct RollbackResponse {
+    label: String,
+    restored_version: i32,
+    value: String,
+}
+
+/// Roll a core memory block back through the live agent, so its cached
+/// blocks see the change. Waits for a running turn to finish; 409 when the
+/// block or revision doesn't exist or the block is read-only.
+async fn admin_rollback_block(
+    State(state): State<AppState>,
+    Path((agent_id, label)): Path<(Uuid, String)>,
+    Query(query): Query<RollbackQuery>,
+) -> Response {
+    let agent = match live_agent(&state, agent_id).await {
+        Ok(agent) => agent,
+        Err(response) => return response,
+    };
+    let agent = agent.lock().await;
+    let Some(memory) = agent.memory() else {
+        return (StatusCode::CONFLICT, "agent has no memory").into_response();
+    };
+
+    let blocks = memory.blocks();
+    match blocks.rollback(&label, query.version, &EditCause::other("admin")) {
+        Ok(restored_version) => Json(RollbackResponse {
+            value: blocks.get(&label).map(|b| b.value).unwrap_or_default(),
+            label,
+            restored_version,
+        })
+        .into_response(),
+        Err(e) => (StatusCode::CONFLICT, e.to_string()).into_response(),
+    }
+}
+
+/// Send a message as `user_id` and wait for the agent's replies
+async fn post_message(State(state): State<AppState>, Json(req): Json<WebhookRequest>) -> Response {
+    let Some(hub) = state.webhook else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let msg = match tokio::task::spawn_blocking(move || webhook::to_incoming_message(req)).await {
+        Ok(Ok(msg)) => msg,
+        Ok(Err(e)) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => {
+            error!("Webhook request task failed: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let user_id = msg.reply_to.clone();
+    let reply = hub.register(&user_id);
+    if let Err(e) = hub.submit(msg).await {
+        error!("Failed to queue webhook message: {}", e);
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    match tokio::time::timeout(hub.reply_timeout(), reply).await {
+        Ok(Ok(replies)) => Json(WebhookResponse {
+            status: "complete",
+            replies,
+        })
+        .into_response(),
+        // Turn still running: replies will be queued for GET /messages/{user_id}
+        _ => (
+            StatusCode::ACCEPTED,
+            Json(WebhookResponse {
+                status: "pending",
+                replies: Vec::new(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// Fetch replies queued for a user (e.g. scheduled reminders)
+async fn get_messages(State(state): State<AppState>, Path(user_id): Path<String>) -> Response {
+    let Some(hub) = state.webhook else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    Json(WebhookResponse {
+        status: "complete",
+        replies: hub.drain_replies(&user_id),
+    })
+    .into_response()
+}
+
+/// Build the router, wrapping every route in bearer auth if a token is set
+pub fn router(auth_token: Option<String>, state: AppState) -> Router {
+    let mut router = Router::new()
+        .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check))
+        .route("/feedback/{agent_id}", get(get_feedback))
+        .route("/usage", get(usage_report));
+    // Admin routes, turn transcripts and held messages expose
+    // conversations, so never without auth
+    if auth_token.is_some() {
+        router = router
+            .route("/turns/{turn_id}", get(get_turn))
+            .route("/held", get(list_held))
+            .route("/held/{id}/release", post(release_held))
+            .route("/held/{id}/discard", post(discard_held))
+            .route("/admin/agents", get(admin_list_agents))
+            .route("/admin/agents/{agent_id}/blocks", get(admin_blocks))
+            .route(
+                "/admin/agents/{agent_id}/blocks/{label}/revisions",
+                get(admin_block_revisions),
+            )
+            .route(
+                "/admin/agents/{agent_id}/blocks/{label}/rollback",
+                post(admin_rollback_block),
+            )
+            .route("/admin/agents/{agent_id}/messages", get(admin_messages))
+            .route("/admin/agents/{agent_id}/schedules", get(admin_schedules))
+            .route(
+                "/admin/agents/{agent_id}/gepa-examples",
+                get(admin_gepa_examples),
+            )
+            .route(
+                "/admin/agents/{agent_id}/compact",
+                get(admin_preview_compaction).post(admin_compact),
+            );
+    }
+    if state.webhook.is_some() {
+        router = router
+            .route("/message", post(post_message))
+            .route("/messages/{user_id}", get(get_messages));
+    }
+    let router = router.with_state(state);
+
+    match auth_token {
+        Some(token) => router.layer(middleware::from_fn_with_state(
+            Arc::new(token),
+            require_bearer,
+        )),
+        None => router,
+    }
+}
+
+/// Bind the listener and serve in a background task
+pub async fn spawn(config: &HttpServerConfig, state: AppState) -> Result<()> {
+    let addr = format!("{}:{}", config.bind_address, config.port);
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP server to {}", addr))?;
+
+    let app = router(config.auth_token.clone(), state);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("HTTP server error: {}", e);
+        }
+    });
+
+    if config.auth_token.is_some() {
+        info!("HTTP server listening on {} (bearer auth required)", addr);
+    } else {
+        if config.bind_address != "127.0.0.1" && config.bind_address != "localhost" {
+            warn!(
+                "HTTP server listening on {} without auth - set HTTP_AUTH_TOKEN to protect it",
+                addr
+            );
+        }
+        info!("HTTP server listening on {}", addr);
+        info!("Admin routes disabled - set HTTP_AUTH_TOKEN to enable /admin");
+    }
+
+    Ok(())
+}
+
+/// Middleware rejecting requests without the expected bearer token
+async fn require_bearer(State(token): State<Arc<String>>, req: Request, next: Next) -> Response {
+    let provided = bearer_token(
+        req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok()),
+    );
+
+    match provided {
+        Some(provided) if constant_time_eq(provided.as_bytes(), token.as_bytes()) => {
+            next.run(req).await
+        }
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Unauthorized",
+        )
+            .into_response(),
+    }
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header value
+fn bearer_token(header_value: Option<&str>) -> Option<&str> {
+    let value = header_value?.trim();
+    let (scheme, token) = value.split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token(Some("Bearer abc123")), Some("abc123"));
+        assert_eq!(bearer_token(Some("bearer  abc123 ")), Some("abc123"));
+        assert_eq!(bearer_token(Some("Basic abc123")), None);
+        assert_eq!(bearer_token(Some("Bearer ")), None);
+        assert_eq!(bearer_token(None), None);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
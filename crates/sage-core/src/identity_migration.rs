@@ -0,0 +1,525 @@
+//! Single-Agent Data Migration
+//!
+//! Sage started as a single agent: every conversation's messages, the core
+//! blocks and archival memory were stored under one agent id (the nil UUID
+//! in the earliest deployments), with the sender only recorded in each
+//! message's `user_id`. Since then each chat identity has its own agent
+//! (`chat_contexts`, see `AgentManager`), and data under an agent without a
+//! chat context is never loaded.
+//!
+//! `sage-admin migrate identities` moves such data over. `plan` works out
+//! which identity each message belongs to (its `user_id`, or the previous
+//! message's identity when it has none) and prints the plan; with `--apply`
+//! the agents are created through `AgentManager::get_or_create_agent` and
+//! `MigrationDb::apply` moves everything in one transaction:
+//!
+//! - messages (and their search chunks) go to their identity's agent
+//! - core blocks are copied to every identity, except `human`, which only
+//!   describes the primary identity (the one with most user messages unless
+//!   `--primary` says otherwise); the source's blocks are then removed
+//! - archival passages go to the primary identity
+//!
+//! Summaries stay with the source agent, since they mix conversations; the
+//! new agents compact their own history as it grows.
+
+// Only the sage-admin binary uses this module
+#![allow(dead_code)]
+
+use anyhow::{Context, Result};
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Text, Uuid as DieselUuid};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::schema::{blocks, chat_contexts, messages, passages, summaries};
+
+/// The block that describes one person, copied to the primary identity only
+const PERSONAL_BLOCK: &str = "human";
+
+/// A stored message of the source agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyMessage {
+    pub id: Uuid,
+    pub sequence_id: i64,
+    pub role: String,
+    pub user_id: String,
+}
+
+/// Identity each message belongs to, for messages in sequence order: its
+/// `user_id`, else the identity of the message before it
+pub fn assign_identities(messages: &[LegacyMessage]) -> Vec<Option<String>> {
+    let mut current: Option<String> = None;
+    messages
+        .iter()
+        .map(|m| {
+            let user_id = m.user_id.trim();
+            if !user_id.is_empty() {
+                current = Some(user_id.to_string());
+            }
+            current.clone()
+        })
+        .collect()
+}
+
+/// Where one identity's data goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentityPlan {
+    /// Chat identifier (Signal UUID, `group:<id>`, pubkey, ...)
+    pub identifier: String,
+    pub messages: Vec<Uuid>,
+    pub user_messages: usize,
+    /// The identity's agent, if it already has one
+    pub existing_agent: Option<Uuid>,
+}
+
+/// Everything a migration would move
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationPlan {
+    pub source: Uuid,
+    /// Most user messages first
+    pub identities: Vec<IdentityPlan>,
+    /// Identity that gets the `human` block and the archival passages
+    pub primary: Option<String>,
+    /// Messages before the first one with a `user_id`
+    pub unassigned: Vec<Uuid>,
+    pub block_labels: Vec<String>,
+    pub passages: i64,
+    pub summaries: i64,
+}
+
+impl MigrationPlan {
+    /// Plan the migration of `source`'s `messages` (in sequence order).
+    /// `existing` maps identifiers that already have an agent to it.
+    pub fn build(
+        source: Uuid,
+        messages: &[LegacyMessage],
+        existing: &HashMap<String, Uuid>,
+        block_labels: Vec<String>,
+        passages: i64,
+        summaries: i64,
+        primary: Option<&str>,
+    ) -> Result<Self> {
+        let mut identities: Vec<IdentityPlan> = Vec::new();
+        let mut unassigned = Vec::new();
+        for (message, identity) in messages.iter().zip(assign_identities(messages)) {
+            let Some(identifier) = identity else {
+                unassigned.push(message.id);
+                continue;
+            };
+            let index = match identities.iter().position(|i| i.identifier == identifier) {
+                Some(index) => index,
+                None => {
+                    identities.push(IdentityPlan {
+                        existing_agent: existing.get(&identifier).copied(),
+                        identifier,
+                        messages: Vec::new(),
+                        user_messages: 0,
+                    });
+                    identities.len() - 1
+                }
+            };
+            identities[index].messages.push(message.id);
+            if message.role == "user" {
+                identities[index].user_messages += 1;
+            }
+        }
+        // Stable, so ties keep the order of first appearance
+        identities.sort_by(|a, b| b.user_messages.cmp(&a.user_messages));
+
+        let primary = match primary {
+            Some(primary) => {
+                if !identities.iter().any(|i| i.identifier == primary) {
+                    anyhow::bail!("'{}' has no messages under agent {}", primary, source);
+                }
+                Some(primary.to_string())
+            }
+            None => identities.first().map(|i| i.identifier.clone()),
+        };
+
+        Ok(Self {
+            source,
+            identities,
+            primary,
+            unassigned,
+            block_labels,
+            passages,
+            summaries,
+        })
+    }
+
+    /// Labels of the blocks copied to `identifier`
+    pub fn blocks_for(&self, identifier: &str) -> Vec<String> {
+        let primary = self.primary.as_deref() == Some(identifier);
+        self.block_labels
+            .iter()
+            .filter(|label| primary || label.as_str() != PERSONAL_BLOCK)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether there is anything to move
+    pub fn is_empty(&self) -> bool {
+        self.identities.is_empty() && self.block_labels.is_empty() && self.passages == 0
+    }
+
+    /// The dry-run report
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("Source agent {}", self.source)];
+        if self.is_empty() {
+            lines.push("Nothing to migrate.".to_string());
+            return lines.join("\n");
+        }
+        lines.push(format!("{} identities:", self.identities.len()));
+        for identity in &self.identities {
+            let agent = match identity.existing_agent {
+                Some(id) => format!("existing agent {}", id),
+                None => "new agent".to_string(),
+            };
+            let blocks = self.blocks_for(&identity.identifier);
+            lines.push(format!(
+                "  {}{} -> {}: {} messages ({} from the user), blocks {}",
+                identity.identifier,
+                if self.primary.as_deref() == Some(identity.identifier.as_str()) {
+                    " (primary)"
+                } else {
+                    ""
+                },
+                agent,
+                identity.messages.len(),
+                identity.user_messages,
+                if blocks.is_empty() {
+                    "none".to_string()
+                } else {
+                    blocks.join(", ")
+                }
+            ));
+        }
+        match &self.primary {
+            Some(primary) if self.passages > 0 => lines.push(format!(
+                "{} archival passages -> {}",
+                self.passages, primary
+            )),
+            None if self.passages > 0 || !self.block_labels.is_empty() => lines.push(format!(
+                "No identity to receive {} archival passages and blocks {}; they stay",
+                self.passages,
+                self.block_labels.join(", ")
+            )),
+            _ => {}
+        }
+        if !self.unassigned.is_empty() {
+            lines.push(format!(
+                "{} messages before the first one with a sender stay with the source",
+                self.unassigned.len()
+            ));
+        }
+        if self.summaries > 0 {
+            lines.push(format!(
+                "{} summaries stay with the source (they mix conversations)",
+                self.summaries
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// What `apply` moved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    pub messages: usize,
+    pub blocks: usize,
+    pub passages: usize,
+}
+
+/// Agent with messages but no chat context
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrphanAgent {
+    pub id: Uuid,
+    pub messages: i64,
+}
+
+/// Database access for the migration
+pub struct MigrationDb {
+    conn: Arc<DbConn>,
+}
+
+impl MigrationDb {
+    pub fn connect(db_url: &str) -> Result<Self> {
+        Ok(Self {
+            conn: Arc::new(DbConn::connect(db_url)?),
+        })
+    }
+
+    /// Agents that have messages but no chat context, most messages first
+    pub fn orphan_agents(&self) -> Result<Vec<OrphanAgent>> {
+        let rows: Vec<(Uuid, i64)> = self.conn.run(|conn| {
+            let contexts: Vec<Uuid> = chat_contexts::table
+                .select(chat_contexts::id)
+                .load(conn)
+                .context("Failed to load chat contexts")?;
+            messages::table
+                .filter(messages::agent_id.ne_all(&contexts))
+                .group_by(messages::agent_id)
+                .select((messages::agent_id, diesel::dsl::count_star()))
+                .order(diesel::dsl::count_star().desc())
+                .load(conn)
+                .context("Failed to count messages")
+        })?;
+        Ok(rows
+            .into_iter()
+            .map(|(id, messages)| OrphanAgent { id, messages })
+            .collect())
+    }
+
+    /// Plan the migration of `source`'s data
+    pub fn plan(&self, source: Uuid, primary: Option<&str>) -> Result<MigrationPlan> {
+        self.conn.run(|conn| Self::plan_with(conn, source, primary))
+    }
+
+    fn plan_with(
+        conn: &mut PgConnection,
+        source: Uuid,
+        primary: Option<&str>,
+    ) -> Result<MigrationPlan> {
+        let messages: Vec<LegacyMessage> = messages::table
+            .filter(messages::agent_id.eq(source))
+            .order(messages::sequence_id.asc())
+            .select((
+                messages::id,
+                messages::sequence_id,
+                messages::role,
+                messages::user_id,
+            ))
+            .load::<(Uuid, i64, String, String)>(conn)
+            .context("Failed to load messages")?
+            .into_iter()
+            .map(|(id, sequence_id, role, user_id)| LegacyMessage {
+                id,
+                sequence_id,
+                role,
+                user_id,
+            })
+            .collect();
+
+        let identifiers: Vec<String> = assign_identities(&messages)
+            .into_iter()
+            .flatten()
+            .collect::<std::collections::HashSet<_>>()
+            .into_iter()
+            .collect();
+        let existing: HashMap<String, Uuid> = chat_contexts::table
+            .filter(chat_contexts::signal_identifier.eq_any(&identifiers))
+            .select((chat_contexts::signal_identifier, chat_contexts::id))
+            .load::<(String, Uuid)>(conn)
+            .context("Failed to load chat contexts")?
+            .into_iter()
+            .collect();
+
+        let source_key = source.to_string();
+        let block_labels: Vec<String> = blocks::table
+            .filter(blocks::agent_id.eq(&source_key))
+            .order(blocks::label.asc())
+            .select(blocks::label)
+            .load(conn)
+            .context("Failed to load blocks")?;
+        let passages: i64 = passages::table
+            .filter(passages::agent_id.eq(&source_key))
+            .count()
+            .get_result(conn)
+            .context("Failed to count passages")?;
+        let summaries: i64 = summaries::table
+            .filter(summaries::agent_id.eq(source))
+            .count()
+            .get_result(conn)
+            .context("Failed to count summaries")?;
+
+        MigrationPlan::build(
+            source,
+            &messages,
+            &existing,
+            block_labels,
+            passages,
+            summaries,
+            primary,
+        )
+    }
+
+    /// Move the planned data to `agents` (identifier -> agent id), in one
+    /// transaction
+    pub fn apply(
+        &self,
+        plan: &MigrationPlan,
+        agents: &HashMap<String, Uuid>,
+    ) -> Result<MigrationReport> {
+        let source_key = plan.source.to_string();
+        self.conn.run(|conn| {
+            conn.transaction(|conn| {
+            let mut report = MigrationReport::default();
+            for identity in &plan.identities {
+                let target = *agents.get(&identity.identifier).ok_or_else(|| {
+                    anyhow::anyhow!("No agent for {}", identity.identifier)
+                })?;
+                report.messages += diesel::update(
+                    messages::table.filter(messages::id.eq_any(&identity.messages)),
+                )
+                .set(messages::agent_id.eq(target))
+                .execute(conn)?;
+                diesel::sql_query(
+                    "UPDATE message_chunks SET agent_id = $1 WHERE message_id = ANY($2)",
+                )
+                .bind::<DieselUuid, _>(target)
+                .bind::<Array<DieselUuid>, _>(&identity.messages)
+                .execute(conn)?;
+
+                let labels = plan.blocks_for(&identity.identifier);
+                if !labels.is_empty() {
+                    report.blocks += diesel::sql_query(
+                        "INSERT INTO blocks (id, agent_id, label, description, value, char_limit, read_only) \
+                         SELECT gen_random_uuid(), $1, label, description, value, char_limit, read_only \
+                         FROM blocks WHERE agent_id = $2 AND label = ANY($3) \
+                         ON CONFLICT (agent_id, label) DO UPDATE SET \
+                         description = EXCLUDED.description, value = EXCLUDED.value, \
+                         char_limit = EXCLUDED.char_limit, read_only = EXCLUDED.read_only",
+                    )
+                    .bind::<Text, _>(target.to_string())
+                    .bind::<Text, _>(&source_key)
+                    .bind::<Array<Text>, _>(&labels)
+                    .execute(conn)?;
+                }
+            }
+
+            if let Some(primary) = plan.primary.as_ref().and_then(|p| agents.get(p)) {
+                report.passages = diesel::update(
+                    passages::table.filter(passages::agent_id.eq(&source_key)),
+                )
+                .set(passages::agent_id.eq(primary.to_string()))
+                .execute(conn)?;
+                diesel::delete(blocks::table.filter(blocks::agent_id.eq(&source_key)))
+                    .execute(conn)?;
+            }
+            Ok(report)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(sequence_id: i64, role: &str, user_id: &str) -> LegacyMessage {
+        LegacyMessage {
+            id: Uuid::new_v4(),
+            sequence_id,
+            role: role.to_string(),
+            user_id: user_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_assign_identities() {
+        let messages = vec![
+            message(1, "assistant", ""),
+            message(2, "user", "alice"),
+            message(3, "tool", ""),
+            message(4, "assistant", "alice"),
+            message(5, "user", "bob"),
+            message(6, "assistant", " "),
+        ];
+        assert_eq!(
+            assign_identities(&messages),
+            vec![
+                None,
+                Some("alice".to_string()),
+                Some("alice".to_string()),
+                Some("alice".to_string()),
+                Some("bob".to_string()),
+                Some("bob".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_plan() {
+        let messages = vec![
+            message(1, "user", "alice"),
+            message(2, "assistant", "alice"),
+            message(3, "user", "bob"),
+            message(4, "user", "bob"),
+            message(5, "assistant", "bob"),
+        ];
+        let existing_bob = Uuid::new_v4();
+        let existing = HashMap::from([("bob".to_string(), existing_bob)]);
+        let labels = vec!["human".to_string(), "persona".to_string()];
+        let plan = MigrationPlan::build(
+            Uuid::nil(),
+            &messages,
+            &existing,
+            labels.clone(),
+            3,
+            2,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(plan.identities[0].identifier, "bob");
+        assert_eq!(plan.identities[0].messages.len(), 3);
+        assert_eq!(plan.identities[0].existing_agent, Some(existing_bob));
+        assert_eq!(plan.identities[1].identifier, "alice");
+        assert_eq!(plan.identities[1].existing_agent, None);
+        assert_eq!(plan.primary.as_deref(), Some("bob"));
+        assert_eq!(plan.blocks_for("bob"), labels);
+        assert_eq!(plan.blocks_for("alice"), vec!["persona".to_string()]);
+
+        let plan = MigrationPlan::build(
+            Uuid::nil(),
+            &messages,
+            &existing,
+            labels.clone(),
+            3,
+            2,
+            Some("alice"),
+        )
+        .unwrap();
+        assert_eq!(plan.primary.as_deref(), Some("alice"));
+        assert!(MigrationPlan::build(
+            Uuid::nil(),
+            &messages,
+            &existing,
+            labels,
+            0,
+            0,
+            Some("carol")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let messages = vec![message(1, "assistant", ""), message(2, "user", "alice")];
+        let plan = MigrationPlan::build(
+            Uuid::nil(),
+            &messages,
+            &HashMap::new(),
+            vec!["human".to_string(), "persona".to_string()],
+            4,
+            1,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            plan.render(),
+            "Source agent 00000000-0000-0000-0000-000000000000\n\
+             1 identities:\n  \
+             alice (primary) -> new agent: 1 messages (1 from the user), blocks human, persona\n\
+             4 archival passages -> alice\n\
+             1 messages before the first one with a sender stay with the source\n\
+             1 summaries stay with the source (they mix conversations)"
+        );
+
+        let empty =
+            MigrationPlan::build(Uuid::nil(), &[], &HashMap::new(), vec![], 0, 0, None).unwrap();
+        assert!(empty.render().ends_with("Nothing to migrate."));
+    }
+}
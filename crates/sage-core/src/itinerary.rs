@@ -0,0 +1,698 @@
+//! Travel Itineraries
+//!
+//! Booking confirmations - pasted into the chat (`add_itinerary`) or
+//! forwarded to the ingestion mailbox (see `email_ingest`) - are parsed by the
+//! LLM into flights and hotel stays and stored in `travel_segments` as UTC
+//! instants plus the IANA timezone of each end. Everything after extraction
+//! is deterministic:
+//! - reminders are scheduled as one-off messages (flight check-in 24h before
+//!   departure, leave-for-the-airport 3h before, hotel check-in 3h before)
+//! - `travel_plans` answers "when does my flight land?" from the stored
+//!   times, in the local time of each airport and in the user's own timezone
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use dspy_rs::{BamlType, Predict, Signature};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::sage_agent::{Tool, ToolResult};
+use crate::scheduler::{MessagePayload, SchedulerDb, TaskPayload, TaskType};
+use crate::schema::travel_segments;
+
+/// Flight check-in reminder, before departure
+const CHECK_IN_REMINDER_HOURS: i64 = 24;
+
+/// Leave-for-the-airport reminder, before departure
+const DEPARTURE_REMINDER_HOURS: i64 = 3;
+
+/// Hotel check-in reminder, before check-in time
+const HOTEL_REMINDER_HOURS: i64 = 3;
+
+const EXTRACT_INSTRUCTION: &str = r#"Extract every flight and hotel stay from a travel booking confirmation.
+
+For each flight: title is the flight number with airline code (e.g. "UA 123"), origin and destination are the airports (IATA code and city, e.g. "EWR Newark"), start is the departure and end the arrival.
+For each hotel stay: title is the hotel name, destination its address, start is check-in and end check-out. Leave origin empty.
+
+Times are LOCAL times exactly as printed, formatted "YYYY-MM-DD HH:MM" (24h). Give each its IANA timezone (e.g. "America/New_York", "Europe/Lisbon") from the airport or hotel location - the departure and arrival timezones usually differ. If a hotel time is not printed, use 15:00 for check-in and 11:00 for check-out.
+
+Copy the confirmation / booking reference if present. Do not invent flights, times or places; return no segments if the text is not a booking."#;
+
+/// A flight or hotel stay as extracted by the LLM (empty string = unknown)
+#[derive(Clone, Debug, Default, BamlType)]
+pub struct ItinerarySegment {
+    /// "flight" or "hotel"
+    pub kind: String,
+    /// Flight number ("UA 123") or hotel name
+    pub title: String,
+    /// Confirmation or booking reference
+    pub confirmation: String,
+    /// Departure airport (flights only)
+    pub origin: String,
+    /// Arrival airport, or hotel address
+    pub destination: String,
+    /// Local departure / check-in time, "YYYY-MM-DD HH:MM"
+    pub start_local: String,
+    /// IANA timezone of the departure / hotel
+    pub start_timezone: String,
+    /// Local arrival / check-out time, "YYYY-MM-DD HH:MM"
+    pub end_local: String,
+    /// IANA timezone of the arrival / hotel
+    pub end_timezone: String,
+}
+
+/// DSRs signature for extracting an itinerary
+#[derive(Signature, Clone, Debug)]
+pub struct ExtractItinerary {
+    #[input(desc = "Booking confirmation text (email or pasted)")]
+    pub text: String,
+
+    #[input(desc = "Today's date (YYYY-MM-DD), for confirmations that omit the year")]
+    pub current_date: String,
+
+    #[output(desc = "Flights and hotel stays found in the text")]
+    pub segments: Vec<ItinerarySegment>,
+}
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A stored flight or hotel stay
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = travel_segments)]
+#[allow(dead_code)]
+pub struct TravelSegment {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub kind: String,
+    pub title: String,
+    pub confirmation: Option<String>,
+    pub origin: Option<String>,
+    pub destination: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub starts_tz: String,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub ends_tz: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Diesel model for inserting a new segment
+#[derive(Debug, Insertable)]
+#[diesel(table_name = travel_segments)]
+pub struct NewTravelSegment {
+    id: Uuid,
+    agent_id: Uuid,
+    kind: String,
+    title: String,
+    confirmation: Option<String>,
+    origin: Option<String>,
+    destination: Option<String>,
+    starts_at: DateTime<Utc>,
+    starts_tz: String,
+    ends_at: Option<DateTime<Utc>>,
+    ends_tz: Option<String>,
+}
+
+/// What recording an itinerary added
+#[derive(Debug, Default)]
+pub struct ItineraryReport {
+    /// Newly stored segments (bookings seen before are skipped)
+    pub added: Vec<TravelSegment>,
+    /// Reminders scheduled for them
+    pub reminders: usize,
+    /// Extracted segments that could not be stored (e.g. unknown timezone)
+    pub skipped: Vec<String>,
+}
+
+impl ItineraryReport {
+    /// Short note for the conversation
+    pub fn summary(&self, user_tz: &str) -> String {
+        if self.added.is_empty() && self.skipped.is_empty() {
+            return "No new flights or hotel stays found.".to_string();
+        }
+        let mut lines = Vec::new();
+        if !self.added.is_empty() {
+            lines.push(format!(
+                "Saved {} travel segment{} ({} reminder{} scheduled):",
+                self.added.len(),
+                if self.added.len() == 1 { "" } else { "s" },
+                self.reminders,
+                if self.reminders == 1 { "" } else { "s" }
+            ));
+            lines.extend(self.added.iter().map(|s| describe(s, user_tz)));
+        }
+        for reason in &self.skipped {
+            lines.push(format!("Skipped: {}", reason));
+        }
+        lines.join("\n")
+    }
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct ItineraryDb {
+    conn: Arc<DbConn>,
+}
+
+impl ItineraryDb {
+    /// Create a new ItineraryDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Store a segment. Returns None if the same booking is already stored.
+    pub fn insert(&self, segment: &NewTravelSegment) -> Result<Option<TravelSegment>> {
+        self.conn.run(|conn| {
+            diesel::insert_into(travel_segments::table)
+                .values(segment)
+                .on_conflict_do_nothing()
+                .returning(TravelSegment::as_returning())
+                .get_result(conn)
+                .optional()
+                .context("Failed to insert travel segment")
+        })
+    }
+
+    /// Segments that haven't ended before `since`, in time order
+    pub fn list_since(&self, agent_id: Uuid, since: DateTime<Utc>) -> Result<Vec<TravelSegment>> {
+        self.conn.run(|conn| {
+            travel_segments::table
+                .filter(travel_segments::agent_id.eq(agent_id))
+                .filter(
+                    travel_segments::ends_at
+                        .ge(since)
+                        .or(travel_segments::ends_at
+                            .is_null()
+                            .and(travel_segments::starts_at.ge(since))),
+                )
+                .order(travel_segments::starts_at.asc())
+                .select(TravelSegment::as_select())
+                .load(conn)
+                .context("Failed to query travel segments")
+        })
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Convert a local wall-clock time in an IANA timezone to UTC. Ambiguous
+/// times (DST fall-back) resolve to the earlier instant.
+pub fn local_to_utc(local: &str, timezone: &str) -> Result<DateTime<Utc>> {
+    let tz: Tz = timezone
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Unknown timezone '{}'", timezone))?;
+    let local = local.trim();
+    let naive = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(local, "%Y-%m-%dT%H:%M"))
+        .with_context(|| format!("Invalid local time '{}'", local))?;
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("{} does not exist in {} (DST change)", local, timezone))
+}
+
+/// "Tue Nov 3, 14:20 WET" in the given timezone (UTC if unknown)
+pub fn format_local(at: DateTime<Utc>, timezone: &str) -> String {
+    let tz: Tz = timezone.parse().unwrap_or(chrono_tz::UTC);
+    at.with_timezone(&tz)
+        .format("%a %b %-d, %H:%M %Z")
+        .to_string()
+}
+
+/// A local time, plus the user's own time when they're in another timezone
+fn format_both(at: DateTime<Utc>, timezone: &str, user_tz: &str) -> String {
+    let local = format!("{} ({})", format_local(at, timezone), timezone);
+    let tz: Option<Tz> = timezone.parse().ok();
+    let user: Option<Tz> = user_tz.parse().ok();
+    match (tz, user) {
+        (Some(tz), Some(user)) if tz.name() != user.name() => {
+            format!("{} - {} your time", local, format_local(at, user_tz))
+        }
+        _ => local,
+    }
+}
+
+/// "7h 10m"
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+/// Validate an extracted segment and convert its times to UTC
+pub fn to_new_segment(agent_id: Uuid, segment: &ItinerarySegment) -> Result<NewTravelSegment> {
+    let kind = segment.kind.trim().to_lowercase();
+    if kind != "flight" && kind != "hotel" {
+        anyhow::bail!("unknown segment kind '{}'", segment.kind);
+    }
+    let title = non_empty(&segment.title).ok_or_else(|| anyhow!("{} without a name", kind))?;
+    let starts_at = local_to_utc(&segment.start_local, &segment.start_timezone)
+        .with_context(|| format!("{}: start time", title))?;
+
+    // A missing end timezone means the same place (hotels, or a bad extraction)
+    let end_tz = non_empty(&segment.end_timezone).unwrap_or_else(|| segment.start_timezone.clone());
+    let (ends_at, ends_tz) = match non_empty(&segment.end_local) {
+        Some(end) => {
+            let ends_at =
+                local_to_utc(&end, &end_tz).with_context(|| format!("{}: end time", title))?;
+            if ends_at < starts_at {
+                anyhow::bail!("{} ends before it starts", title);
+            }
+            (Some(ends_at), Some(end_tz.trim().to_string()))
+        }
+        None => (None, None),
+    };
+
+    Ok(NewTravelSegment {
+        id: Uuid::new_v4(),
+        agent_id,
+        kind,
+        title,
+        confirmation: non_empty(&segment.confirmation),
+        origin: non_empty(&segment.origin),
+        destination: non_empty(&segment.destination),
+        starts_at,
+        starts_tz: segment.start_timezone.trim().to_string(),
+        ends_at,
+        ends_tz,
+    })
+}
+
+/// Route of a flight ("EWR → LIS") or a hotel's address
+fn place(segment: &TravelSegment) -> String {
+    match (&segment.origin, &segment.destination) {
+        (Some(origin), Some(destination)) => format!("{} → {}", origin, destination),
+        (None, Some(destination)) => destination.clone(),
+        (Some(origin), None) => format!("from {}", origin),
+        (None, None) => String::new(),
+    }
+}
+
+/// Reminder messages for a segment, skipping times already past
+pub fn reminders(segment: &TravelSegment, now: DateTime<Utc>) -> Vec<(DateTime<Utc>, String)> {
+    let departs = format_local(segment.starts_at, &segment.starts_tz);
+    let route = place(segment);
+    let candidates = match segment.kind.as_str() {
+        "flight" => vec![
+            (
+                segment.starts_at - Duration::hours(CHECK_IN_REMINDER_HOURS),
+                format!(
+                    "✈️ Check-in is open for {} ({}), departing {}.",
+                    segment.title, route, departs
+                ),
+            ),
+            (
+                segment.starts_at - Duration::hours(DEPARTURE_REMINDER_HOURS),
+                format!(
+                    "✈️ {} ({}) departs at {} - time to head to the airport.",
+                    segment.title, route, departs
+                ),
+            ),
+        ],
+        _ => vec![(
+            segment.starts_at - Duration::hours(HOTEL_REMINDER_HOURS),
+            format!(
+                "🏨 Check-in at {} today from {}{}.",
+                segment.title,
+                departs,
+                if route.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", route)
+                }
+            ),
+        )],
+    };
+    candidates.into_iter().filter(|(at, _)| *at > now).collect()
+}
+
+/// Deterministic description of a segment with exact local times
+pub fn describe(segment: &TravelSegment, user_tz: &str) -> String {
+    let mut header = match segment.kind.as_str() {
+        "flight" => format!("✈️ {} {}", segment.title, place(segment)),
+        _ => format!("🏨 {} {}", segment.title, place(segment)),
+    };
+    header = header.trim_end().to_string();
+    if let Some(ref confirmation) = segment.confirmation {
+        header.push_str(&format!(" (confirmation {})", confirmation));
+    }
+
+    let (start_label, end_label) = match segment.kind.as_str() {
+        "flight" => ("Departs", "Lands"),
+        _ => ("Check-in", "Check-out"),
+    };
+    let mut lines = vec![
+        header,
+        format!(
+            "   {}: {}",
+            start_label,
+            format_both(segment.starts_at, &segment.starts_tz, user_tz)
+        ),
+    ];
+    if let Some(ends_at) = segment.ends_at {
+        let ends_tz = segment.ends_tz.as_deref().unwrap_or(&segment.starts_tz);
+        lines.push(format!(
+            "   {}: {}",
+            end_label,
+            format_both(ends_at, ends_tz, user_tz)
+        ));
+        if segment.kind == "flight" {
+            lines.push(format!(
+                "   Duration: {}",
+                format_duration(ends_at - segment.starts_at)
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Whether a segment matches a free-text filter (flight number, place, hotel)
+fn segment_matches(segment: &TravelSegment, query: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    let squashed = |s: &str| s.to_lowercase().replace(' ', "");
+    [
+        Some(&segment.title),
+        segment.origin.as_ref(),
+        segment.destination.as_ref(),
+        segment.confirmation.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|field| {
+        field.to_lowercase().contains(&query) || squashed(field).contains(&squashed(&query))
+    })
+}
+
+// ============================================================================
+// Recording
+// ============================================================================
+
+/// Extract flights and hotels from a confirmation, store the new ones and
+/// schedule their reminders
+pub async fn record_itinerary(
+    itinerary_db: &ItineraryDb,
+    scheduler_db: &SchedulerDb,
+    agent_id: Uuid,
+    timezone: &str,
+    text: &str,
+) -> Result<ItineraryReport> {
+    let predictor = Predict::<ExtractItinerary>::builder()
+        .instruction(EXTRACT_INSTRUCTION)
+        .build();
+    let input = ExtractItineraryInput {
+        text: text.to_string(),
+        current_date: Utc::now().format("%Y-%m-%d").to_string(),
+    };
+    let segments = predictor
+        .call(input)
+        .await
+        .map_err(|e| anyhow!("Itinerary extraction failed: {}", e))?
+        .segments;
+
+    let mut report = ItineraryReport::default();
+    let now = Utc::now();
+    for segment in &segments {
+        let new_segment = match to_new_segment(agent_id, segment) {
+            Ok(new_segment) => new_segment,
+            Err(e) => {
+                warn!("Skipping itinerary segment: {:#}", e);
+                report.skipped.push(format!("{:#}", e));
+                continue;
+            }
+        };
+        let Some(stored) = itinerary_db.insert(&new_segment)? else {
+            continue;
+        };
+
+        for (at, message) in reminders(&stored, now) {
+            scheduler_db.create_task(
+                agent_id,
+                TaskType::Message,
+                TaskPayload::Message(MessagePayload { message }),
+                at,
+                None,
+                timezone.to_string(),
+                format!("Travel reminder: {}", stored.title),
+            )?;
+            report.reminders += 1;
+        }
+        report.added.push(stored);
+    }
+
+    info!(
+        "Recorded itinerary for agent {}: {} segment(s), {} reminder(s)",
+        agent_id,
+        report.added.len(),
+        report.reminders
+    );
+    Ok(report)
+}
+
+// ============================================================================
+// Tools
+// ============================================================================
+
+pub struct AddItineraryTool {
+    itinerary_db: Arc<ItineraryDb>,
+    scheduler_db: Arc<SchedulerDb>,
+    agent_id: Uuid,
+    default_timezone: String,
+}
+
+impl AddItineraryTool {
+    pub fn new(
+        itinerary_db: Arc<ItineraryDb>,
+        scheduler_db: Arc<SchedulerDb>,
+        agent_id: Uuid,
+        default_timezone: String,
+    ) -> Self {
+        Self {
+            itinerary_db,
+            scheduler_db,
+            agent_id,
+            default_timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for AddItineraryTool {
+    fn name(&self) -> &str {
+        "add_itinerary"
+    }
+
+    fn description(&self) -> &str {
+        "Save flights and hotel stays from a booking confirmation the user pasted or shared. Times are stored with each airport's timezone, and reminders are scheduled automatically (flight check-in 24h before, leave for the airport 3h before, hotel check-in). Don't schedule these reminders yourself."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"text": "the full confirmation text, including dates, times, flight numbers and booking references"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let text = args.get("text").map(|s| s.trim()).unwrap_or("");
+        if text.is_empty() {
+            return Ok(ToolResult::error("Missing required argument: text"));
+        }
+        match record_itinerary(
+            &self.itinerary_db,
+            &self.scheduler_db,
+            self.agent_id,
+            &self.default_timezone,
+            text,
+        )
+        .await
+        {
+            Ok(report) => Ok(ToolResult::success(report.summary(&self.default_timezone))),
+            Err(e) => Ok(ToolResult::error(format!("{:#}", e))),
+        }
+    }
+}
+
+pub struct TravelPlansTool {
+    itinerary_db: Arc<ItineraryDb>,
+    agent_id: Uuid,
+    default_timezone: String,
+}
+
+impl TravelPlansTool {
+    pub fn new(itinerary_db: Arc<ItineraryDb>, agent_id: Uuid, default_timezone: String) -> Self {
+        Self {
+            itinerary_db,
+            agent_id,
+            default_timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for TravelPlansTool {
+    fn name(&self) -> &str {
+        "travel_plans"
+    }
+
+    fn description(&self) -> &str {
+        "List the user's saved flights and hotel stays with exact departure, landing, check-in and check-out times, in local time at each place and in the user's timezone. Always use this to answer questions like 'when does my flight land?' - don't work out times yourself."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"query": "optional filter: flight number, airport, city, hotel or confirmation code", "include_past": "true to include trips that already ended (default false)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let include_past = args
+            .get("include_past")
+            .map(|s| s.trim() == "true")
+            .unwrap_or(false);
+        let since = if include_past {
+            Utc::now() - Duration::days(365 * 50)
+        } else {
+            Utc::now()
+        };
+
+        let mut segments = self.itinerary_db.list_since(self.agent_id, since)?;
+        if let Some(query) = args.get("query").filter(|q| !q.trim().is_empty()) {
+            segments.retain(|s| segment_matches(s, query));
+        }
+        if segments.is_empty() {
+            return Ok(ToolResult::success(
+                "No matching flights or hotel stays saved.",
+            ));
+        }
+        Ok(ToolResult::success(
+            segments
+                .iter()
+                .map(|s| describe(s, &self.default_timezone))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flight() -> ItinerarySegment {
+        ItinerarySegment {
+            kind: "Flight".to_string(),
+            title: "TP 202".to_string(),
+            confirmation: "ABC123".to_string(),
+            origin: "EWR Newark".to_string(),
+            destination: "LIS Lisbon".to_string(),
+            start_local: "2026-11-02 18:30".to_string(),
+            start_timezone: "America/New_York".to_string(),
+            end_local: "2026-11-03 06:40".to_string(),
+            end_timezone: "Europe/Lisbon".to_string(),
+        }
+    }
+
+    fn stored(segment: NewTravelSegment) -> TravelSegment {
+        TravelSegment {
+            id: segment.id,
+            agent_id: segment.agent_id,
+            kind: segment.kind,
+            title: segment.title,
+            confirmation: segment.confirmation,
+            origin: segment.origin,
+            destination: segment.destination,
+            starts_at: segment.starts_at,
+            starts_tz: segment.starts_tz,
+            ends_at: segment.ends_at,
+            ends_tz: segment.ends_tz,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_local_to_utc() {
+        // EST (UTC-5) after the November DST change, WET (UTC+0) in Lisbon
+        assert_eq!(
+            local_to_utc("2026-11-02 18:30", "America/New_York").unwrap(),
+            Utc.with_ymd_and_hms(2026, 11, 2, 23, 30, 0).unwrap()
+        );
+        assert_eq!(
+            local_to_utc("2026-07-01T08:00", "Europe/Lisbon").unwrap(),
+            Utc.with_ymd_and_hms(2026, 7, 1, 7, 0, 0).unwrap()
+        );
+        // Skipped hour on the March DST change
+        assert!(local_to_utc("2026-03-08 02:30", "America/New_York").is_err());
+        assert!(local_to_utc("2026-07-01 08:00", "Mars/Olympus").is_err());
+        assert!(local_to_utc("tomorrow", "UTC").is_err());
+    }
+
+    #[test]
+    fn test_to_new_segment() {
+        let agent_id = Uuid::new_v4();
+        let segment = to_new_segment(agent_id, &flight()).unwrap();
+        assert_eq!(segment.kind, "flight");
+        assert_eq!(segment.confirmation.as_deref(), Some("ABC123"));
+        assert_eq!(
+            segment.ends_at.unwrap() - segment.starts_at,
+            Duration::minutes(7 * 60 + 10)
+        );
+
+        let mut bad = flight();
+        bad.end_local = "2026-11-02 10:00".to_string();
+        assert!(to_new_segment(agent_id, &bad).is_err());
+        bad.kind = "train".to_string();
+        assert!(to_new_segment(agent_id, &bad).is_err());
+    }
+
+    #[test]
+    fn test_describe_and_reminders() {
+        let segment = stored(to_new_segment(Uuid::new_v4(), &flight()).unwrap());
+        let text = describe(&segment, "America/New_York");
+        assert_eq!(
+            text,
+            "✈️ TP 202 EWR Newark → LIS Lisbon (confirmation ABC123)\n   \
+             Departs: Mon Nov 2, 18:30 EST (America/New_York)\n   \
+             Lands: Tue Nov 3, 06:40 WET (Europe/Lisbon) - Tue Nov 3, 01:40 EST your time\n   \
+             Duration: 7h 10m"
+        );
+
+        let long_before = segment.starts_at - Duration::days(7);
+        let due: Vec<DateTime<Utc>> = reminders(&segment, long_before)
+            .into_iter()
+            .map(|(at, _)| at)
+            .collect();
+        assert_eq!(
+            due,
+            vec![
+                segment.starts_at - Duration::hours(24),
+                segment.starts_at - Duration::hours(3)
+            ]
+        );
+        // Only the departure reminder is still ahead 12h before the flight
+        let late = reminders(&segment, segment.starts_at - Duration::hours(12));
+        assert_eq!(late.len(), 1);
+        assert!(late[0].1.contains("head to the airport"));
+
+        assert!(segment_matches(&segment, "tp202"));
+        assert!(segment_matches(&segment, "lisbon"));
+        assert!(!segment_matches(&segment, "madrid"));
+    }
+}
@@ -2,20 +2,61 @@
 //!
 //! Shared types and modules for the Sage AI agent.
 
+pub mod admin;
 pub mod agent_manager;
+pub mod agent_worker;
+pub mod anonymize;
+pub mod capabilities;
+pub mod citations;
+pub mod commands;
+pub mod commitments;
 pub mod config;
+pub mod db;
+pub mod delivery;
+pub mod durable_inbox;
+pub mod email;
+pub mod email_ingest;
+pub mod expenses;
+pub mod export;
+pub mod feedback;
+pub mod guardrails;
+pub mod health;
+pub mod http_server;
+pub mod identity_migration;
+pub mod itinerary;
+pub mod maintenance;
 pub mod marmot;
 pub mod memory;
 pub mod messenger;
+pub mod model_limits;
+pub mod model_router;
+pub mod modes;
+pub mod occasions;
+pub mod polls;
+pub mod research;
+pub mod roles;
 pub mod sage_agent;
 pub mod scheduler;
 pub mod scheduler_tools;
 pub mod schema;
+pub mod shell_session;
 pub mod shell_tool;
 pub mod signal;
+pub mod signal_link;
+pub mod speech;
+pub mod status_report;
 pub mod storage;
+pub mod streaming;
+pub mod templates;
+pub mod threads;
+pub mod todos;
 pub mod tools;
+pub mod turn_journal;
+pub mod usage;
+pub mod vector_index;
 pub mod vision;
+pub mod webhook;
+pub mod workspace_snapshot;
 
 // Re-export key types for convenience
 pub use config::Config;
@@ -1,51 +1,84 @@
 use anyhow::Result;
-use axum::{routing::get, Json, Router};
-use serde::Serialize;
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
-use tracing::{error, info, warn};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use uuid::Uuid;
 
+mod admin;
 mod agent_manager;
+mod agent_worker;
+mod anonymize;
+mod capabilities;
+mod citations;
+mod commands;
+mod commitments;
 mod config;
+mod db;
+mod delivery;
+mod durable_inbox;
+mod email;
+mod email_ingest;
+mod expenses;
+mod export;
+mod feedback;
+mod guardrails;
+mod health;
+mod http_server;
+mod identity_migration;
+mod itinerary;
+mod maintenance;
 mod marmot;
 mod memory;
 mod messenger;
+mod model_limits;
+mod model_router;
+mod modes;
+mod occasions;
+mod polls;
+mod research;
+mod roles;
 mod sage_agent;
 mod scheduler;
 mod scheduler_tools;
 mod schema;
+mod shell_session;
 mod shell_tool;
 mod signal;
+mod signal_link;
+mod speech;
+mod status_report;
 mod storage;
+mod streaming;
+mod templates;
+mod threads;
+mod todos;
+mod turn_journal;
+mod usage;
+mod vector_index;
 mod vision;
+mod webhook;
+mod workspace_snapshot;
 
 use agent_manager::{AgentManager, ContextType};
+use agent_worker::{AgentWorkers, WorkerContext};
 use config::MessengerType;
 use messenger::{IncomingMessage, Messenger};
 use sage_agent::SageAgent;
 use signal::{run_receive_loop, run_receive_loop_tcp, SignalClient};
 
-/// Health check response
-#[derive(Serialize)]
-struct HealthResponse {
-    status: &'static str,
-    version: &'static str,
-}
-
-/// Health check endpoint - returns 200 OK when the service is running
-async fn health_check() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "healthy",
-        version: env!("CARGO_PKG_VERSION"),
-    })
-}
-
 // Tools are defined in tools.rs module
 mod tools;
 use tools::{DoneTool, WebSearchTool};
 
+/// Close out a message that won't get a turn, so request/response transports
+/// (webhook) answer right away instead of waiting for a timeout
+async fn end_turn(messenger: &Arc<dyn Messenger>, recipient: &str) {
+    let client = messenger.as_ref();
+    if let Err(e) = client.end_turn(recipient) {
+        warn!("Failed to end turn for {}: {}", recipient, e);
+    }
+}
+
 /// Check if a user is allowed to interact with Sage
 fn is_user_allowed(user_id: &str, allowed_users: &[String]) -> bool {
     // "*" means allow all users
@@ -60,8 +93,619 @@ fn is_user_allowed(user_id: &str, allowed_users: &[String]) -> bool {
     allowed_users.iter().any(|u| u == user_id)
 }
 
+/// Check if Sage takes part in a group conversation. Unlike users, an empty
+/// list allows no groups.
+fn is_group_allowed(group_id: &str, allowed_groups: &[String]) -> bool {
+    allowed_groups.iter().any(|g| g == "*" || g == group_id)
+}
+
+/// Deliver a due scheduled task (message, tool call, self-maintenance or
+/// status report) and record the outcome
+#[allow(clippy::too_many_arguments)]
+async fn handle_scheduled_task(
+    task: scheduler::ScheduledTask,
+    agent_manager: Arc<AgentManager>,
+    messenger: Arc<dyn Messenger>,
+    scheduler_db: Arc<scheduler::SchedulerDb>,
+    maintenance_db: Arc<maintenance::MaintenanceDb>,
+    status_db: Arc<status_report::StatusDb>,
+    consolidate: bool,
+    owner_alert_template: Option<String>,
+) {
+    info!(
+        "Processing scheduled task: {} ({})",
+        task.description,
+        task.task_type.as_str()
+    );
+
+    let signal_identifier = match agent_manager.get_signal_identifier(task.agent_id) {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            error!(
+                "No identifier found for agent_id {} - cannot deliver scheduled task",
+                task.agent_id
+            );
+            return;
+        }
+        Err(e) => {
+            error!(
+                "Failed to look up identifier for agent_id {}: {}",
+                task.agent_id, e
+            );
+            return;
+        }
+    };
+
+    // Tasks scheduled in a conversation thread are delivered to its chat
+    let recipient = threads::split_thread(&signal_identifier).0.to_string();
+
+    let task_result: Result<(), String> = match &task.payload {
+        scheduler::TaskPayload::Message(msg_payload) => {
+            let message = render_template(
+                &agent_manager,
+                &signal_identifier,
+                &msg_payload.message,
+                None,
+            )
+            .await;
+            info!("Sending scheduled message to {}: {}", recipient, message);
+            let client = messenger.as_ref();
+            if let Err(e) = client.send_message(&recipient, &message) {
+                Err(format!("Failed to send scheduled message: {}", e))
+            } else {
+                Ok(())
+            }
+        }
+        scheduler::TaskPayload::ToolCall(tool_payload) => {
+            // The chat context already exists, so the context type is not used here
+            match agent_manager
+                .get_or_create_agent(&signal_identifier, ContextType::Direct, None)
+                .await
+            {
+                Ok((_, agent)) => {
+                    info!(
+                        "Running scheduled tool call {} for {}",
+                        tool_payload.tool, signal_identifier
+                    );
+                    let agent_guard = agent.lock().await;
+                    let result = agent_guard
+                        .execute_tool(&tool_payload.tool, &tool_payload.args)
+                        .await;
+                    if result.success {
+                        let text = format!("{}\n\n{}", task.description, result.output);
+                        if let Err(e) =
+                            agent_guard.store_message_sync(&recipient, "assistant", &text)
+                        {
+                            warn!("Failed to store scheduled tool output: {}", e);
+                        }
+                        let client = messenger.as_ref();
+                        client
+                            .send_message(&recipient, &text)
+                            .map_err(|e| format!("Failed to send scheduled tool output: {}", e))
+                    } else {
+                        Err(format!(
+                            "Scheduled tool call {} failed: {}",
+                            tool_payload.tool,
+                            result.error.unwrap_or(result.output)
+                        ))
+                    }
+                }
+                Err(e) => Err(format!(
+                    "Failed to load agent for scheduled tool call: {}",
+                    e
+                )),
+            }
+        }
+        scheduler::TaskPayload::Occasion(payload) => {
+            if !agent_manager.role_policy(&recipient).proactive {
+                Ok(())
+            } else {
+                match agent_manager
+                    .get_or_create_agent(&signal_identifier, ContextType::Direct, None)
+                    .await
+                {
+                    Ok((_, agent)) => {
+                        let agent_guard = agent.lock().await;
+                        let composed = match agent_guard.memory() {
+                            Some(memory) => usage::with_agent(
+                                task.agent_id,
+                                occasions::compose(memory, payload),
+                            )
+                            .await
+                            .map_err(|e| format!("Failed to compose check-in: {}", e)),
+                            None => Err("Agent has no memory for a check-in".to_string()),
+                        };
+                        match composed {
+                            Ok(text) => {
+                                if let Err(e) =
+                                    agent_guard.store_message_sync(&recipient, "assistant", &text)
+                                {
+                                    warn!("Failed to store check-in: {}", e);
+                                }
+                                let client = messenger.as_ref();
+                                client
+                                    .send_message(&recipient, &text)
+                                    .map_err(|e| format!("Failed to send check-in: {}", e))
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
+                    Err(e) => Err(format!("Failed to load agent for check-in: {}", e)),
+                }
+            }
+        }
+        scheduler::TaskPayload::Commitment(payload) => {
+            if !agent_manager.role_policy(&recipient).proactive {
+                Ok(())
+            } else {
+                follow_up_commitment(
+                    &agent_manager,
+                    &messenger,
+                    &signal_identifier,
+                    &recipient,
+                    payload,
+                )
+                .await
+            }
+        }
+        scheduler::TaskPayload::Maintenance(payload)
+            if payload.routine == status_report::ROUTINE =>
+        {
+            match status_db.summary() {
+                Ok(summary) => {
+                    let summary = owner_alert(
+                        &agent_manager,
+                        &signal_identifier,
+                        owner_alert_template.as_deref(),
+                        summary,
+                    )
+                    .await;
+                    let client = messenger.as_ref();
+                    client
+                        .send_message(&recipient, &summary)
+                        .map_err(|e| format!("Failed to send status report: {}", e))
+                }
+                Err(e) => Err(format!("Status report failed: {}", e)),
+            }
+        }
+        scheduler::TaskPayload::Maintenance(_) => match maintenance_db.run(task.agent_id) {
+            Ok(mut report) => {
+                if consolidate {
+                    consolidate_archive(&agent_manager, &signal_identifier, &task, &mut report)
+                        .await;
+                }
+                // Only a direct chat has a single owner to report to, and
+                // roles without proactive messages don't get the summary
+                if messenger::group_id(&recipient).is_some()
+                    || !agent_manager.role_policy(&recipient).proactive
+                {
+                    Ok(())
+                } else {
+                    let summary = owner_alert(
+                        &agent_manager,
+                        &signal_identifier,
+                        owner_alert_template.as_deref(),
+                        report.summary(),
+                    )
+                    .await;
+                    let client = messenger.as_ref();
+                    client
+                        .send_message(&recipient, &summary)
+                        .map_err(|e| format!("Failed to send maintenance summary: {}", e))
+                }
+            }
+            Err(e) => Err(format!("Self-maintenance failed: {}", e)),
+        },
+    };
+
+    match task_result {
+        Ok(()) => {
+            if let Err(e) = scheduler::complete_task(&scheduler_db, &task) {
+                error!("Failed to mark task {} as completed: {}", task.id, e);
+            }
+        }
+        Err(err) => {
+            error!("{}", err);
+            if let Err(e) = scheduler::fail_task(&scheduler_db, &task, &err) {
+                error!("Failed to mark task {} as failed: {}", task.id, e);
+            }
+        }
+    }
+}
+
+/// Fill in a scheduled message's template variables (see `templates`) for
+/// this chat, with `report` as `{{report}}`. Text without placeholders is
+/// sent as is; if the variables can't be loaded the placeholders are dropped
+/// rather than sent raw.
+async fn render_template(
+    agent_manager: &AgentManager,
+    signal_identifier: &str,
+    template: &str,
+    report: Option<&str>,
+) -> String {
+    if templates::placeholders(template).is_empty() {
+        return template.to_string();
+    }
+    let vars = match agent_manager
+        .template_vars(signal_identifier, template)
+        .await
+    {
+        Ok(vars) => vars,
+        Err(e) => {
+            warn!("Failed to load template variables: {}", e);
+            templates::TemplateVars::default()
+        }
+    };
+    let vars = match report {
+        Some(report) => vars.with("report", report),
+        None => vars,
+    };
+    templates::render(template, &vars)
+}
+
+/// Wrap an owner alert in `OWNER_ALERT_TEMPLATE`, if one is set
+async fn owner_alert(
+    agent_manager: &AgentManager,
+    signal_identifier: &str,
+    template: Option<&str>,
+    report: String,
+) -> String {
+    match template {
+        Some(template) => {
+            render_template(agent_manager, signal_identifier, template, Some(&report)).await
+        }
+        None => report,
+    }
+}
+
+/// Merge near-duplicate archival passages (see `memory::consolidation`) and
+/// add the result to the maintenance report. Needs the agent's memory for
+/// embeddings and the chat model, which is why `MaintenanceDb` can't do it.
+/// A failure is logged and leaves the rest of the report standing.
+async fn consolidate_archive(
+    agent_manager: &AgentManager,
+    identifier: &str,
+    task: &scheduler::ScheduledTask,
+    report: &mut maintenance::MaintenanceReport,
+) {
+    let archival = match agent_manager
+        .get_or_create_agent(identifier, ContextType::Direct, None)
+        .await
+    {
+        Ok((_, agent)) => agent.lock().await.memory().map(|m| m.archival().clone()),
+        Err(e) => {
+            warn!("Failed to load agent for {}: {}", identifier, e);
+            return;
+        }
+    };
+    let Some(archival) = archival else {
+        return;
+    };
+    match usage::with_agent(task.agent_id, archival.consolidate()).await {
+        Ok(consolidated) => {
+            report.merged_passages = consolidated.replaced;
+            report.passages += consolidated.merges as i64 - consolidated.replaced as i64;
+        }
+        Err(e) => warn!("Archival consolidation failed for {}: {}", identifier, e),
+    }
+}
+
+/// Run the agent's follow-up turn on an open commitment (see `commitments`):
+/// it can use its tools to do what it promised, and its messages are sent
+/// and stored like a normal turn's. The commitment is kept afterwards.
+async fn follow_up_commitment(
+    agent_manager: &AgentManager,
+    messenger: &Arc<dyn Messenger>,
+    identifier: &str,
+    recipient: &str,
+    payload: &scheduler::CommitmentPayload,
+) -> Result<(), String> {
+    let commitment_db = agent_manager.commitment_db();
+    let commitment = match commitment_db.get(payload.commitment_id) {
+        Ok(Some(commitment)) if commitment.status == "open" => commitment,
+        Ok(_) => {
+            info!(
+                "Commitment {} is no longer open, skipping follow-up",
+                payload.commitment_id
+            );
+            return Ok(());
+        }
+        Err(e) => return Err(format!("Failed to load commitment: {}", e)),
+    };
+    let (agent_id, agent) = agent_manager
+        .get_or_create_agent(identifier, ContextType::Direct, None)
+        .await
+        .map_err(|e| format!("Failed to load agent for commitment follow-up: {}", e))?;
+
+    info!(
+        "Following up on commitment {} for {}: {}",
+        commitment.id, identifier, commitment.description
+    );
+    let prompt = commitments::follow_up_prompt(&commitment);
+    let mut agent_guard = agent.lock().await;
+    for step_num in 0..commitments::FOLLOW_UP_STEPS {
+        let result = usage::with_agent(agent_id, agent_guard.step(&prompt, step_num == 0))
+            .await
+            .map_err(|e| format!("Commitment follow-up failed: {}", e))?;
+        for text in &result.messages {
+            if let Err(e) = agent_guard.store_message_sync(recipient, "assistant", text) {
+                warn!("Failed to store commitment follow-up: {}", e);
+            }
+            let client = messenger.as_ref();
+            client
+                .send_message(recipient, text)
+                .map_err(|e| format!("Failed to send commitment follow-up: {}", e))?;
+        }
+        for executed in &result.executed_tools {
+            if let Err(e) = agent_guard
+                .store_tool_message(recipient, &executed.tool_call, &executed.result)
+                .await
+            {
+                warn!("Failed to store tool message: {}", e);
+            }
+        }
+        if result.done {
+            break;
+        }
+    }
+
+    if let Err(e) = commitment_db.resolve(commitment.id, "kept") {
+        warn!("Failed to close commitment {}: {}", commitment.id, e);
+    }
+    Ok(())
+}
+
+/// Store a message the account sent from another device (see
+/// `IncomingMessage::own_message`) as Sage's side of the conversation, so the
+/// history has no gaps. No turn runs.
+async fn store_own_message(
+    msg: IncomingMessage,
+    identifier: String,
+    agent_manager: Arc<AgentManager>,
+) {
+    if msg.message.trim().is_empty() {
+        return;
+    }
+    let agent = match agent_manager
+        .get_or_create_agent(
+            &identifier,
+            ContextType::for_identifier(&msg.reply_to),
+            None,
+        )
+        .await
+    {
+        Ok((_, agent)) => agent,
+        Err(e) => {
+            warn!("Failed to load agent for {}: {}", identifier, e);
+            return;
+        }
+    };
+    let content = format!("{} {}", messenger::OWN_MESSAGE_CONTEXT, msg.message);
+    let agent_guard = agent.lock().await;
+    if let Err(e) = agent_guard.store_message_sync(&msg.reply_to, "assistant", &content) {
+        warn!("Failed to store message sent from another device: {}", e);
+    }
+}
+
+/// Switch or show a direct chat's conversation thread (`/topic`)
+async fn handle_topic_command(
+    command: threads::TopicCommand,
+    chat: &str,
+    thread_db: &threads::ThreadDb,
+    messenger: &Arc<dyn Messenger>,
+) {
+    let reply = match command {
+        threads::TopicCommand::Show => thread_db.active_topic(chat).and_then(|active| {
+            let topics = thread_db.list_topics(chat)?;
+            Ok(threads::describe_topics(active.as_deref(), &topics))
+        }),
+        threads::TopicCommand::Switch(topic) => thread_db
+            .set_active_topic(chat, Some(&topic))
+            .map(|()| {
+                format!(
+                    "Switched to the \"{}\" topic. I still remember everything about you, but this thread has its own conversation. /topic main goes back.",
+                    topic
+                )
+            }),
+        threads::TopicCommand::Main => thread_db
+            .set_active_topic(chat, None)
+            .map(|()| "Back to the main conversation.".to_string()),
+    };
+
+    let reply = reply.unwrap_or_else(|e| {
+        error!("Topic command failed for {}: {}", chat, e);
+        "Sorry, I couldn't switch topics right now.".to_string()
+    });
+    let client = messenger.as_ref();
+    if let Err(e) = client.send_message(chat, &reply) {
+        warn!("Failed to send topic reply: {}", e);
+    }
+}
+
+/// Pin a note, list pins or unpin one (`/pin`, `/unpin`) in the chat's
+/// conversation (its active `/topic` thread in a direct chat)
+async fn handle_pin_command(
+    command: memory::PinCommand,
+    msg: &IncomingMessage,
+    identifier: &str,
+    agent_manager: &AgentManager,
+    pin_db: &memory::MemoryDb,
+    messenger: &Arc<dyn Messenger>,
+) {
+    let chat = &msg.reply_to;
+    let reply = agent_manager
+        .get_or_create_agent(
+            identifier,
+            ContextType::for_identifier(chat),
+            msg.source_name.as_deref(),
+        )
+        .await
+        .and_then(|(agent_id, _)| match command {
+            memory::PinCommand::Show => pin_db
+                .messages()
+                .pinned(agent_id)
+                .map(|pinned| memory::describe_pins(&pinned)),
+            memory::PinCommand::Pin(text) => memory::pin_note(pin_db, agent_id, &msg.source, &text),
+            memory::PinCommand::Unpin(number) => memory::unpin_number(pin_db, agent_id, number),
+        });
+
+    let reply = reply.unwrap_or_else(|e| {
+        error!("Pin command failed for {}: {}", chat, e);
+        "Sorry, I couldn't update the pinned messages right now.".to_string()
+    });
+    let client = messenger.as_ref();
+    if let Err(e) = client.send_message(chat, &reply) {
+        warn!("Failed to send pin reply: {}", e);
+    }
+}
+
+/// Switch or show the conversation's mode (`/mode`, see `modes`)
+async fn handle_mode_command(
+    command: modes::ModeCommand,
+    msg: &IncomingMessage,
+    identifier: &str,
+    agent_manager: &AgentManager,
+    mode_db: &memory::MemoryDb,
+    messenger: &Arc<dyn Messenger>,
+) {
+    let chat = &msg.reply_to;
+    let reply = agent_manager
+        .get_or_create_agent(
+            identifier,
+            ContextType::for_identifier(chat),
+            msg.source_name.as_deref(),
+        )
+        .await
+        .and_then(|(agent_id, _)| modes::run_command(mode_db, agent_id, command));
+
+    let reply = reply.unwrap_or_else(|e| {
+        error!("Mode command failed for {}: {}", chat, e);
+        "Sorry, I couldn't change the mode right now.".to_string()
+    });
+    let client = messenger.as_ref();
+    if let Err(e) = client.send_message(chat, &reply) {
+        warn!("Failed to send mode reply: {}", e);
+    }
+}
+
+/// Set, clear or show a chat's export passphrase (`/export-key`). Only in
+/// direct chats, where the passphrase isn't shown to anyone else.
+async fn handle_export_key_command(
+    command: export::ExportKeyCommand,
+    msg: &IncomingMessage,
+    agent_manager: &AgentManager,
+    messenger: &Arc<dyn Messenger>,
+) {
+    let chat = &msg.reply_to;
+    let reply = if msg.group_id().is_some() {
+        Ok(
+            "Export passphrases can only be set in a direct chat with me. Don't post yours here."
+                .to_string(),
+        )
+    } else {
+        let export_db = agent_manager.export_db();
+        agent_manager
+            .get_or_create_agent(chat, ContextType::Direct, msg.source_name.as_deref())
+            .await
+            .and_then(|(agent_id, _)| match command {
+                export::ExportKeyCommand::Show => export_db.key(agent_id).map(|key| match key {
+                    Some(_) => "Exports are encrypted with your export passphrase. /export-key off turns that off.".to_string(),
+                    None => "Exports aren't encrypted. Send /export-key <passphrase> to encrypt them.".to_string(),
+                }),
+                export::ExportKeyCommand::Set(passphrase)
+                    if passphrase.chars().count() < export::MIN_PASSPHRASE_CHARS =>
+                {
+                    Ok(format!(
+                        "That passphrase is too short - use at least {} characters.",
+                        export::MIN_PASSPHRASE_CHARS
+                    ))
+                }
+                export::ExportKeyCommand::Set(passphrase) => {
+                    let key = export::ExportKey::generate(&passphrase)?;
+                    export_db.set_key(agent_id, &key)?;
+                    Ok("Export passphrase set. Exports I send you are now encrypted with it - I don't keep the passphrase itself, so don't lose it. You may want to delete your message with it.".to_string())
+                }
+                export::ExportKeyCommand::Clear => export_db.clear_key(agent_id).map(|cleared| {
+                    if cleared {
+                        "Export passphrase removed. Exports will no longer be encrypted.".to_string()
+                    } else {
+                        "No export passphrase was set.".to_string()
+                    }
+                }),
+            })
+    };
+
+    let reply = reply.unwrap_or_else(|e| {
+        error!("Export key command failed for {}: {}", chat, e);
+        "Sorry, I couldn't update your export passphrase right now.".to_string()
+    });
+    let client = messenger.as_ref();
+    if let Err(e) = client.send_message(chat, &reply) {
+        warn!("Failed to send export key reply: {}", e);
+    }
+}
+
+/// Run an owner command (`/status`, `/reset`, ...) and reply with its result.
+/// Spawned, since `/status` and `/forget` wait for a running step.
+async fn handle_owner_command(
+    command: commands::OwnerCommand,
+    msg: IncomingMessage,
+    identifier: String,
+    agent_manager: Arc<AgentManager>,
+    inbox_db: Arc<durable_inbox::InboxDb>,
+    messenger: Arc<dyn Messenger>,
+) {
+    let reply = commands::run(command, &msg, &identifier, &agent_manager, &inbox_db)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Owner command failed for {}: {}", identifier, e);
+            format!("Command failed: {}", e)
+        });
+    {
+        let client = messenger.as_ref();
+        if let Err(e) = client.send_message(&msg.reply_to, &reply) {
+            warn!("Failed to send owner command reply: {}", e);
+        }
+    }
+    end_turn(&messenger, &msg.reply_to).await;
+}
+
+/// Let a voter know their poll vote counted: a 🗳️ reaction where the
+/// transport has reactions, otherwise a short reply. A reaction vote is its
+/// own acknowledgement.
+async fn acknowledge_vote(
+    vote: &polls::CountedVote,
+    msg: &IncomingMessage,
+    messenger: &Arc<dyn Messenger>,
+) {
+    if vote.via_reaction {
+        return;
+    }
+    let client = messenger.as_ref();
+    let acked = if client.capabilities().reactions {
+        let target = msg.target_id().parse().unwrap_or(msg.timestamp);
+        client.send_reaction(&msg.reply_to, &msg.source, target, "🗳️")
+    } else {
+        let voter = msg.source_name.as_deref().unwrap_or(&msg.source);
+        client.send_message(
+            &msg.reply_to,
+            &format!("🗳️ Counted {}'s vote for \"{}\"", voter, vote.option),
+        )
+    };
+    if let Err(e) = acked {
+        warn!("Failed to acknowledge vote: {}", e);
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    // `sage signal link|verify` provisioning commands run instead of the bot
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("signal") {
+        dotenvy::dotenv().ok();
+        return signal_link::run_cli(&args[1..]);
+    }
+
     // Initialize logging
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
@@ -80,6 +724,10 @@ async fn main() -> Result<()> {
     info!("  Maple API: {}", config.maple_api_url);
     info!("  Model: {}", config.maple_model);
 
+    // One connection pool shared by every DB struct
+    db::configure_pool(config.database_pool_size);
+    db::configure_vector_search(config.vector_ef_search);
+
     // Run database migrations first
     {
         use diesel::prelude::*;
@@ -97,9 +745,43 @@ async fn main() -> Result<()> {
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!("MAPLE_API_KEY not set"))?;
 
+    // Context window and output limits of the chat model (before the LM and
+    // memory use them)
+    model_limits::detect(
+        &config.maple_api_url,
+        api_key,
+        &config.maple_model,
+        (config.model_context_window, config.model_max_output_tokens),
+    )
+    .await;
+
     // Configure DSRs LM globally (required before creating agents)
     SageAgent::configure_lm(&config.maple_api_url, api_key, &config.maple_model).await?;
     info!("DSRs LM configured");
+    streaming::configure_timeouts(
+        std::time::Duration::from_secs(config.stream_connect_timeout_secs),
+        std::time::Duration::from_secs(config.stream_read_timeout_secs),
+    )?;
+
+    // Token and cost accounting of every model call
+    let usage_db = Arc::new(usage::UsageDb::connect(&config.database_url)?);
+    usage::install(
+        usage_db.clone(),
+        usage::Prices::from_config(&config),
+        &config.maple_model,
+    );
+    if let Some(model) = &config.router_cheap_model {
+        info!("Model routing: simple turns go to {}", model);
+        usage::set_model_prices(
+            model,
+            config
+                .router_cheap_prompt_price_per_mtok
+                .unwrap_or(config.llm_prompt_price_per_mtok),
+            config
+                .router_cheap_completion_price_per_mtok
+                .unwrap_or(config.llm_completion_price_per_mtok),
+        );
+    }
 
     // Check for Brave Search
     if config.brave_api_key.is_some() {
@@ -111,8 +793,56 @@ async fn main() -> Result<()> {
     // Initialize scheduler (shared across all agents)
     let scheduler_db = Arc::new(scheduler::SchedulerDb::connect(&config.database_url)?);
 
+    // Write-ahead log for incoming messages
+    let inbox_db = Arc::new(durable_inbox::InboxDb::connect(&config.database_url)?);
+
+    // Active conversation thread (`/topic`) of each direct chat
+    let thread_db = Arc::new(threads::ThreadDb::connect(&config.database_url)?);
+
+    // Delivery/read receipts for messages sent to direct chats
+    let delivery_db = Arc::new(delivery::DeliveryDb::connect(&config.database_url)?);
+
+    // Weekly self-maintenance of each agent's memory and schedules
+    let maintenance_db = Arc::new(
+        maintenance::MaintenanceDb::connect(&config.database_url)?
+            .with_retention(config.retention_policy()),
+    );
+
+    // Owners' status reports (STATUS_REPORT_CRON)
+    let status_db = Arc::new(status_report::StatusDb::connect(
+        &config.database_url,
+        config.status_budget_usd,
+    )?);
+
+    // Reactions to Sage's messages (implicit feedback)
+    let feedback_db = Arc::new(feedback::FeedbackDb::connect(&config.database_url)?);
+
+    // Subsystem checks behind GET /health/ready
+    let health = Arc::new(health::HealthMonitor::connect(
+        &config.database_url,
+        scheduler_db.clone(),
+        memory::EmbeddingService::new(
+            &config.maple_api_url,
+            api_key,
+            &config.maple_embedding_model,
+        ),
+    )?);
+
+    // Embeds stored messages in the background, retrying failures
+    tokio::spawn(memory::run_embedding_worker(
+        memory::MemoryDb::new(&config.database_url)?,
+        memory::EmbeddingService::new(
+            &config.maple_api_url,
+            api_key,
+            &config.maple_embedding_model,
+        ),
+    ));
+
     // Create agent manager
     let agent_manager = Arc::new(AgentManager::new(&config, scheduler_db.clone())?);
+    // `/pin`, `/unpin` and `/mode` (see `memory::pins`, `modes`)
+    let command_db = memory::MemoryDb::new(&config.database_url)?;
+    let poll_db = agent_manager.poll_db();
     info!(
         "Agent manager initialized (workspace: {})",
         config.workspace_path
@@ -121,14 +851,14 @@ async fn main() -> Result<()> {
     // Create channel for incoming messages
     let (tx, mut rx) = mpsc::channel::<IncomingMessage>(100);
 
-    // Agent keyed by identity (Signal UUID or Marmot pubkey).
-    // Both messengers currently use Direct (1:1 identity = 1 agent).
+    // Agent keyed by identity (Signal UUID or Marmot pubkey), or by group for
+    // Signal group chats (reply_to "group:<id>", ContextType::Group).
     // TODO: With multi-agent support, Marmot groups could each get their own
     // agent thread while sharing a parent identity for cross-thread memory.
-    let context_type = ContextType::Direct;
 
     // Start messenger based on config
-    let (messenger, receive_handle): (Arc<Mutex<dyn Messenger>>, _) = match config.messenger_type {
+    let mut webhook_hub: Option<Arc<webhook::WebhookHub>> = None;
+    let (outbound, receive_handle): (Arc<dyn Messenger>, _) = match config.messenger_type {
         MessengerType::Signal => {
             let signal_phone = match &config.signal_phone_number {
                 Some(phone) => phone.clone(),
@@ -148,7 +878,9 @@ async fn main() -> Result<()> {
 
                 let signal_client =
                     SignalClient::connect_tcp(&signal_phone, host, config.signal_cli_port)?;
-                let messenger: Arc<Mutex<dyn Messenger>> = Arc::new(Mutex::new(signal_client));
+                let gate = config.signal_group_gate();
+                let delivery = delivery_db.clone();
+                let messenger: Arc<dyn Messenger> = Arc::new(signal_client);
 
                 let host = host.clone();
                 let port = config.signal_cli_port;
@@ -158,7 +890,16 @@ async fn main() -> Result<()> {
                     let backoff_max = std::time::Duration::from_secs(60);
 
                     loop {
-                        match run_receive_loop_tcp(&host, port, &account, tx.clone()).await {
+                        match run_receive_loop_tcp(
+                            &host,
+                            port,
+                            &account,
+                            gate.clone(),
+                            delivery.clone(),
+                            tx.clone(),
+                        )
+                        .await
+                        {
                             Ok(()) => {
                                 warn!(
                                     "Signal TCP receive loop exited unexpectedly; restarting in {:?}",
@@ -184,14 +925,82 @@ async fn main() -> Result<()> {
 
                 let signal_client = SignalClient::spawn_subprocess(&signal_phone)?;
                 let reader = signal_client.take_reader()?;
-                let messenger: Arc<Mutex<dyn Messenger>> = Arc::new(Mutex::new(signal_client));
+                let messenger: Arc<dyn Messenger> = Arc::new(signal_client);
 
+                let gate = config.signal_group_gate();
+                let delivery = delivery_db.clone();
                 let receive_handle =
-                    tokio::spawn(async move { run_receive_loop(reader, tx).await });
+                    tokio::spawn(async move { run_receive_loop(reader, gate, delivery, tx).await });
 
                 (messenger, receive_handle)
             }
         }
+        MessengerType::Email => {
+            let email_config = config.email_config();
+
+            if email_config.imap_host.is_empty() || email_config.smtp_host.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "EMAIL_IMAP_HOST and EMAIL_SMTP_HOST must be set when MESSENGER=email"
+                ));
+            }
+
+            info!("Starting email interface...");
+            info!(
+                "  IMAP: {}:{} ({})",
+                email_config.imap_host, email_config.imap_port, email_config.mailbox
+            );
+            info!(
+                "  SMTP: {}:{}",
+                email_config.smtp_host, email_config.smtp_port
+            );
+
+            let client = email::new_email_client(&email_config)?;
+            let threads = client.threads_handle();
+
+            // Restore per-sender reply threads from DB
+            match agent_manager.load_reply_contexts() {
+                Ok(routes) => {
+                    if let Ok(mut map) = threads.lock() {
+                        for (address, thread) in routes {
+                            if let Ok(thread) = serde_json::from_str(&thread) {
+                                map.insert(address, thread);
+                            }
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to load reply contexts: {}", e),
+            }
+
+            let messenger: Arc<dyn Messenger> = Arc::new(client);
+
+            let receive_handle = tokio::spawn(async move {
+                email::run_email_receive_loop(email_config, threads, tx).await
+            });
+
+            (messenger, receive_handle)
+        }
+        MessengerType::Webhook => {
+            // Callers pick the user_id, so without a token anyone could
+            // chat as any user and read their replies
+            if config.http_auth_token.is_none() {
+                return Err(anyhow::anyhow!(
+                    "HTTP_AUTH_TOKEN must be set when MESSENGER=webhook"
+                ));
+            }
+            info!("Starting webhook interface (POST /message)...");
+
+            let hub = Arc::new(webhook::WebhookHub::new(
+                tx,
+                std::time::Duration::from_secs(config.webhook_reply_timeout_secs),
+            ));
+            webhook_hub = Some(hub.clone());
+            let messenger: Arc<dyn Messenger> = Arc::new(webhook::WebhookMessenger::new(hub));
+
+            // Messages arrive through the HTTP server; nothing to receive here
+            let receive_handle = tokio::spawn(std::future::pending::<Result<()>>());
+
+            (messenger, receive_handle)
+        }
         MessengerType::Marmot => {
             let marmot_config = config.marmot_config();
 
@@ -209,6 +1018,7 @@ async fn main() -> Result<()> {
             let writer = marmot::writer_handle(&client);
             let group_routes = marmot::group_routes_handle(&client);
             let child = marmot::child_handle(&client);
+            let pending = marmot::pending_handle(&client);
 
             // Restore persisted pubkey -> group_id routes from DB
             match agent_manager.load_reply_contexts() {
@@ -225,18 +1035,55 @@ async fn main() -> Result<()> {
                 Err(e) => warn!("Failed to load reply contexts: {}", e),
             }
 
-            let messenger: Arc<Mutex<dyn Messenger>> = Arc::new(Mutex::new(client));
+            let messenger: Arc<dyn Messenger> = Arc::new(client);
 
             // Supervisor loop: respawns marmotd on failure with exponential backoff
             let receive_handle = tokio::spawn(async move {
-                marmot::run_marmot_receive_loop(tx, marmot_config, group_routes, writer, child)
-                    .await
+                marmot::run_marmot_receive_loop(
+                    tx,
+                    marmot_config,
+                    group_routes,
+                    writer,
+                    child,
+                    pending,
+                )
+                .await
             });
 
             (messenger, receive_handle)
         }
     };
 
+    health.set_receive_loop(receive_handle.abort_handle());
+
+    // Record sent messages where the transport reports receipts
+    let outbound: Arc<dyn Messenger> = if outbound.capabilities().receipts {
+        Arc::new(delivery::TrackedMessenger::new(
+            outbound,
+            delivery_db.clone(),
+        ))
+    } else {
+        outbound
+    };
+
+    // Every outgoing message passes through the output guardrails
+    let held_db = Arc::new(guardrails::HeldMessageDb::connect(&config.database_url)?);
+    let output_guard = guardrails::OutputGuard::new(&config.output_guard_config())?;
+    info!(
+        "Output guardrails: {} rule(s), action {:?}",
+        output_guard.rule_count(),
+        output_guard.action()
+    );
+    if output_guard.action() == guardrails::GuardAction::Hold && config.http_auth_token.is_none() {
+        warn!("OUTPUT_GUARD_ACTION=hold without HTTP_AUTH_TOKEN - held messages can't be reviewed");
+    }
+    info!("Messenger capabilities: {:?}", outbound.capabilities());
+    let messenger: Arc<dyn Messenger> = Arc::new(guardrails::GuardedMessenger::new(
+        outbound.clone(),
+        output_guard,
+        held_db.clone(),
+    ));
+
     // Log allowed users configuration
     let allowed_users = config.allowed_users();
     if allowed_users.iter().any(|u| u == "*") {
@@ -246,25 +1093,79 @@ async fn main() -> Result<()> {
     } else {
         info!("Allowed users: {:?}", allowed_users);
     }
+    let roles = config.roles();
+    let owners = roles.owners();
+    if !owners.is_empty() {
+        info!("Owner commands enabled for: {:?}", owners);
+    }
+    if !config.user_roles.is_empty() {
+        info!(
+            "User roles: {} assigned, default {}",
+            config.user_roles.len(),
+            config.default_role.as_str()
+        );
+    }
+
+    let attachment_policy = config.attachment_policy();
+    info!(
+        "Attachment limits: max {}, types {:?}",
+        messenger::format_bytes(attachment_policy.max_bytes),
+        attachment_policy.allowed_types
+    );
 
     info!(
         "Sage is awake and listening via {:?}!",
         config.messenger_type
     );
 
-    // Start HTTP health check server
-    let health_port: u16 = std::env::var("HEALTH_PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(8080);
-    let health_router = Router::new().route("/health", get(health_check));
-    let health_listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", health_port)).await?;
-    tokio::spawn(async move {
-        if let Err(e) = axum::serve(health_listener, health_router).await {
-            error!("Health check server error: {}", e);
+    // Start HTTP server (health check, turn transcripts)
+    http_server::spawn(
+        &config.http_server_config(),
+        http_server::AppState {
+            turn_journal: agent_manager.turn_journal(),
+            webhook: webhook_hub,
+            held_messages: held_db,
+            outbound,
+            feedback: feedback_db.clone(),
+            health: health.clone(),
+            usage: usage_db.clone(),
+            admin: Arc::new(admin::AdminDb::connect(&config.database_url)?),
+            agents: agent_manager.clone(),
+        },
+    )
+    .await?;
+
+    // Forwarded receipts, itineraries and newsletters -> archival memory
+    if let Some(mailbox) = config.ingest_email_mailbox.clone() {
+        if config.email_imap_host.is_empty() {
+            warn!(
+                "INGEST_EMAIL_MAILBOX is set but EMAIL_IMAP_HOST is not - email ingestion disabled"
+            );
+        } else if config.ingest_email_routes.is_empty() {
+            warn!("INGEST_EMAIL_MAILBOX is set but INGEST_EMAIL_ROUTES is empty - email ingestion disabled");
+        } else {
+            info!(
+                "Email ingestion enabled ({} on {}, {} forwarder(s))",
+                mailbox,
+                config.email_imap_host,
+                config.ingest_email_routes.len()
+            );
+            tokio::spawn(email_ingest::run_ingest_loop(
+                config.email_config(),
+                mailbox,
+                config.ingest_email_routes.clone(),
+                agent_manager.clone(),
+                messenger.clone(),
+            ));
         }
-    });
-    info!("Health check server listening on port {}", health_port);
+    }
+
+    // HNSW indexes for embedding tables that outgrew exact scans
+    let vector_indexes = Arc::new(vector_index::VectorIndexes::connect(
+        &config.database_url,
+        config.vector_index_min_rows,
+    )?);
+    tokio::spawn(vector_index::run_index_loop(vector_indexes));
 
     // Start background scheduler
     let mut scheduler_rx = scheduler::spawn_scheduler(scheduler_db.clone(), 30);
@@ -276,291 +1177,264 @@ async fn main() -> Result<()> {
     health_interval.tick().await;
     info!("Messenger health check scheduled (every 60 minutes)");
 
+    // Per-agent workers: each agent processes its messages serially, agents run concurrently
+    let mut workers = AgentWorkers::new(Arc::new(WorkerContext {
+        config: Arc::new(config.clone()),
+        agent_manager: agent_manager.clone(),
+        messenger: messenger.clone(),
+        inbox_db: inbox_db.clone(),
+        delivery_db: delivery_db.clone(),
+        feedback_db: feedback_db.clone(),
+    }));
+
+    // Replay messages that were received but not fully processed before the last shutdown
+    match inbox_db.take_pending() {
+        Ok(pending) => {
+            if !pending.is_empty() {
+                info!(
+                    "Replaying {} unprocessed message(s) from the inbox",
+                    pending.len()
+                );
+            }
+            for msg in pending {
+                match agent_manager
+                    .get_or_create_agent(
+                        &thread_db.agent_identifier(&msg),
+                        ContextType::for_identifier(&msg.reply_to),
+                        msg.source_name.as_deref(),
+                    )
+                    .await
+                {
+                    Ok((agent_id, agent)) => workers.dispatch(agent_id, agent, msg),
+                    Err(e) => error!("Failed to get/create agent for {}: {}", msg.reply_to, e),
+                }
+            }
+        }
+        Err(e) => warn!("Failed to load pending inbox messages: {}", e),
+    }
+
     // Main event loop
     loop {
         tokio::select! {
             // Periodic messenger health check
             _ = health_interval.tick() => {
-                let client = messenger.lock().await;
-                if let Err(e) = client.refresh() {
-                    warn!("Messenger health check failed: {} - will retry next interval", e);
+                {
+                    let client = messenger.as_ref();
+                    if let Err(e) = client.refresh() {
+                        warn!("Messenger health check failed: {} - will retry next interval", e);
+                    }
+                }
+                if let Err(e) = delivery_db.prune() {
+                    warn!("Failed to prune delivery records: {}", e);
+                }
+                match maintenance_db.purge_guests(config.guest_retention_hours) {
+                    Ok(0) => {}
+                    Ok(n) => info!("Purged {} expired guest row(s)", n),
+                    Err(e) => warn!("Failed to purge guest conversations: {}", e),
                 }
             }
             // Handle scheduled task events
             Some(event) = scheduler_rx.recv() => {
-                let task = event.task;
-                info!("Processing scheduled task: {} ({})", task.description, task.task_type.as_str());
+                // Run in the background so a busy agent can't stall the main loop
+                tokio::spawn(handle_scheduled_task(
+                    event.task,
+                    agent_manager.clone(),
+                    messenger.clone(),
+                    scheduler_db.clone(),
+                    maintenance_db.clone(),
+                    status_db.clone(),
+                    config.archival_consolidation,
+                    config.owner_alert_template.clone(),
+                ));
+            }
 
-                let signal_identifier = match agent_manager.get_signal_identifier(task.agent_id) {
-                    Ok(Some(id)) => id,
-                    Ok(None) => {
-                        error!("No identifier found for agent_id {} - cannot deliver scheduled task", task.agent_id);
-                        continue;
-                    }
-                    Err(e) => {
-                        error!("Failed to look up identifier for agent_id {}: {}", task.agent_id, e);
-                        continue;
+            // Handle incoming messages
+            Some(msg) = rx.recv() => {
+                health.record_receive();
+
+                // What the account sent from its other devices goes into the
+                // conversation's history, if Sage takes part in it
+                if msg.own_message {
+                    let allowed = match msg.group_id() {
+                        Some(group_id) => {
+                            is_group_allowed(group_id, &config.signal_allowed_groups)
+                                || roles.is_listed(group_id)
+                        }
+                        None => {
+                            is_user_allowed(&msg.reply_to, config.allowed_users())
+                                || roles.is_listed(&msg.reply_to)
+                        }
+                    };
+                    if allowed {
+                        let identifier = thread_db.agent_identifier(&msg);
+                        tokio::spawn(store_own_message(msg, identifier, agent_manager.clone()));
+                    } else {
+                        debug!("Ignoring own message to a conversation Sage isn't in");
                     }
-                };
+                    continue;
+                }
 
-                let task_result: Result<(), String> = match &task.payload {
-                    scheduler::TaskPayload::Message(msg_payload) => {
-                        info!("Sending scheduled message to {}: {}", signal_identifier, msg_payload.message);
-                        let client = messenger.lock().await;
-                        if let Err(e) = client.send_message(&signal_identifier, &msg_payload.message) {
-                            Err(format!("Failed to send scheduled message: {}", e))
-                        } else {
-                            Ok(())
-                        }
+                // Check if sender (or, for group chats, the group) is allowed
+                let allowed = match messenger::group_id(&msg.reply_to) {
+                    Some(group_id) => {
+                        is_group_allowed(group_id, &config.signal_allowed_groups)
+                            || roles.is_listed(group_id)
                     }
-                    scheduler::TaskPayload::ToolCall(tool_payload) => {
-                        Err(format!("Tool call scheduled tasks not yet implemented: {:?}", tool_payload))
+                    None => {
+                        is_user_allowed(&msg.source, config.allowed_users())
+                            || roles.is_listed(&msg.source)
+                            || config.is_note_to_self(&msg.source)
                     }
                 };
+                if !allowed {
+                    warn!("Ignoring message from unauthorized user or group: {}", msg.reply_to);
+                    end_turn(&messenger, &msg.reply_to).await;
+                    continue;
+                }
 
-                match task_result {
-                    Ok(()) => {
-                        if let Err(e) = scheduler::complete_task(&scheduler_db, &task) {
-                            error!("Failed to mark task {} as completed: {}", task.id, e);
-                        }
-                    }
-                    Err(err) => {
-                        error!("{}", err);
-                        if let Err(e) = scheduler::fail_task(&scheduler_db, &task, &err) {
-                            error!("Failed to mark task {} as failed: {}", task.id, e);
-                        }
+                // `/topic` switches a direct chat's conversation thread
+                if msg.group_id().is_none() {
+                    if let Some(command) = threads::parse_topic_command(&msg.message) {
+                        handle_topic_command(command, &msg.reply_to, &thread_db, &messenger).await;
+                        end_turn(&messenger, &msg.reply_to).await;
+                        continue;
                     }
                 }
-            }
 
-            // Handle incoming messages
-            Some(msg) = rx.recv() => {
-                // Check if sender is allowed
-                if !is_user_allowed(&msg.source, config.allowed_users()) {
-                    warn!("Ignoring message from unauthorized user: {}", msg.source);
+                // `/export-key` sets the export passphrase; it never reaches the agent
+                if let Some(command) = export::parse_export_key_command(&msg.message) {
+                    handle_export_key_command(command, &msg, &agent_manager, &messenger).await;
+                    end_turn(&messenger, &msg.reply_to).await;
                     continue;
                 }
 
-                let user_name = msg.source_name.as_deref().unwrap_or(&msg.source);
-                info!("Processing message from {}...", user_name);
+                // `/pin <text>` pins a note without a reply from the agent
+                if let Some(command) = memory::parse_pin_command(&msg.message) {
+                    let identifier = thread_db.agent_identifier(&msg);
+                    handle_pin_command(command, &msg, &identifier, &agent_manager, &command_db, &messenger).await;
+                    end_turn(&messenger, &msg.reply_to).await;
+                    continue;
+                }
 
-                // Get or create agent for this conversation
-                // For Signal: keyed by user UUID (reply_to == source)
-                // For Marmot: keyed by sender pubkey (reply_to == from_pubkey)
-                let (agent_id, agent) = match agent_manager.get_or_create_agent(
-                    &msg.reply_to,
-                    context_type,
-                    msg.source_name.as_deref(),
-                ).await {
-                    Ok(result) => result,
-                    Err(e) => {
-                        error!("Failed to get/create agent for {}: {}", msg.reply_to, e);
+                // Conversation modes are preferences, switched without a reply from the agent
+                if let Some(command) = modes::parse_mode_command(&msg.message) {
+                    let identifier = thread_db.agent_identifier(&msg);
+                    handle_mode_command(command, &msg, &identifier, &agent_manager, &command_db, &messenger).await;
+                    end_turn(&messenger, &msg.reply_to).await;
+                    continue;
+                }
+
+                // Owner commands bypass the agent (see `commands`)
+                if commands::is_owner(&msg.source, &owners) {
+                    if let Some(command) = commands::parse_owner_command(&msg.message) {
+                        let identifier = thread_db.agent_identifier(&msg);
+                        tokio::spawn(handle_owner_command(
+                            command,
+                            msg,
+                            identifier,
+                            agent_manager.clone(),
+                            inbox_db.clone(),
+                            messenger.clone(),
+                        ));
                         continue;
                     }
-                };
-
-                info!("Using agent {} for user {}", agent_id, user_name);
+                }
 
-                // Persist reply context (e.g. Marmot group_id) for route restoration after restart
-                if let Some(ref ctx) = msg.reply_context {
-                    if let Err(e) = agent_manager.update_reply_context(&msg.reply_to, ctx) {
-                        warn!("Failed to persist reply context: {}", e);
+                // Poll votes (replies, "vote N", keycap reactions) are counted, not answered
+                match poll_db.try_vote(&msg) {
+                    Ok(Some(vote)) => {
+                        acknowledge_vote(&vote, &msg, &messenger).await;
+                        end_turn(&messenger, &msg.reply_to).await;
+                        continue;
                     }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to count poll vote from {}: {}", msg.source, e),
                 }
 
-                // Send typing indicator early
-                {
-                    let client = messenger.lock().await;
-                    let _ = client.send_typing(&msg.reply_to, false);
+                // Reactions to Sage's messages are feedback, not something to answer
+                if let Some(reaction) = &msg.reaction {
+                    let recorded = agent_manager
+                        .get_agent_id(&thread_db.agent_identifier(&msg))
+                        .and_then(|agent_id| match agent_id {
+                            Some(agent_id) => feedback_db.record(
+                                agent_id,
+                                &msg.source,
+                                reaction.target_id.parse().unwrap_or_default(),
+                                &reaction.emoji,
+                                reaction.removed,
+                            ),
+                            None => Ok(()),
+                        });
+                    if let Err(e) = recorded {
+                        warn!("Failed to record reaction from {}: {}", msg.source, e);
+                    }
+                    end_turn(&messenger, &msg.reply_to).await;
+                    continue;
                 }
 
-                // Check for image attachments and run vision pre-processing
-                let attachment_text = {
-                    let image_attachment = msg.attachments.iter().find(|a| vision::is_supported_image(&a.content_type));
-                    if let Some(attachment) = image_attachment {
-                        let attachment_path = format!(
-                            "/signal-cli-data/.local/share/signal-cli/attachments/{}",
-                            attachment.file
-                        );
-                        info!("Image attachment detected: {} ({}) at {}", attachment.file, attachment.content_type, attachment_path);
-
-                        let recent_context = {
-                            let agent_guard = agent.lock().await;
-                            match agent_guard.get_recent_messages_for_vision(6) {
-                                Ok(ctx) => ctx,
-                                Err(e) => {
-                                    warn!("Failed to get recent messages for vision context: {}", e);
-                                    String::new()
+                // Enforce attachment size/type limits before anything processes the files
+                let mut msg = msg;
+                let mut rejections = Vec::new();
+                msg.attachments.retain(|attachment| {
+                    let path = signal::attachment_path(&attachment.file);
+                    let size_on_disk = std::fs::metadata(&path).ok().map(|m| m.len());
+                    match attachment_policy.check(attachment, size_on_disk) {
+                        Ok(()) => true,
+                        Err(reason) => {
+                            warn!("Rejecting attachment {} ({}): {}", attachment.file, attachment.content_type, reason);
+                            // Don't let refused files pile up on disk
+                            if size_on_disk.is_some() {
+                                if let Err(e) = std::fs::remove_file(&path) {
+                                    warn!("Failed to remove rejected attachment {}: {}", path, e);
                                 }
                             }
-                        };
-
-                        match vision::describe_image(
-                            &config.maple_api_url,
-                            config.maple_api_key.as_deref().unwrap_or(""),
-                            &config.maple_vision_model,
-                            &attachment_path,
-                            &attachment.content_type,
-                            &msg.message,
-                            &recent_context,
-                        ).await {
-                            Ok(description) => {
-                                info!("Image described ({} chars)", description.len());
-                                Some(description)
-                            }
-                            Err(e) => {
-                                error!("Failed to describe image: {}", e);
-                                Some("[Image attached but could not be processed]".to_string())
-                            }
+                            rejections.push(reason);
+                            false
                         }
-                    } else {
-                        None
                     }
-                };
-
-                let user_message = if let Some(ref desc) = attachment_text {
-                    if msg.message.is_empty() {
-                        format!("[Uploaded Image: {}]", desc)
-                    } else {
-                        format!("{}\n\n[Uploaded Image: {}]", msg.message, desc)
-                    }
-                } else {
-                    msg.message.clone()
-                };
-
-                // Store incoming message
-                let user_msg_id = {
-                    let agent_guard = agent.lock().await;
-                    match agent_guard.store_message_sync_with_attachment(
-                        &msg.source,
-                        "user",
-                        &msg.message,
-                        attachment_text.as_deref(),
-                    ) {
-                        Ok(msg_id) => {
-                            tracing::debug!("Stored user message {}", msg_id);
-                            Some(msg_id)
-                        }
-                        Err(e) => {
-                            error!("Failed to store message: {}", e);
-                            None
+                });
+                if !rejections.is_empty() {
+                    {
+                        let client = messenger.as_ref();
+                        let refusal = format!("Sorry, I couldn't accept your attachment: {}.", rejections.join("; "));
+                        if let Err(e) = client.send_message(&msg.reply_to, &refusal) {
+                            warn!("Failed to send attachment refusal: {}", e);
                         }
                     }
-                };
-
-                if let Some(msg_id) = user_msg_id {
-                    let agent_clone = agent.clone();
-                    let embed_content = user_message.clone();
-                    tokio::spawn(async move {
-                        let agent_guard = agent_clone.lock().await;
-                        if let Err(e) = agent_guard.update_message_embedding(msg_id, &embed_content).await {
-                            tracing::warn!("Failed to update embedding for user message: {}", e);
-                        }
-                    });
+                    if msg.message.trim().is_empty() && msg.attachments.is_empty() {
+                        end_turn(&messenger, &msg.reply_to).await;
+                        continue;
+                    }
                 }
 
-                // Process message with agent
-                let recipient = msg.reply_to.clone();
-
-                let mut had_error = false;
-                let max_steps = 10;
-
-                for step_num in 0..max_steps {
-                    let step_result = {
-                        let mut agent_guard = agent.lock().await;
-                        agent_guard.step(&user_message, step_num == 0).await
-                    };
-
-                    match step_result {
-                        Ok(result) => {
-                            let msg_count = result.messages.len();
-                            let mut messages_to_store: Vec<String> = Vec::new();
-
-                            for (i, response) in result.messages.iter().enumerate() {
-                                let log_preview: String = response.chars().take(50).collect();
-                                info!("Sending response ({}/{}): {}...", i + 1, msg_count, log_preview);
-
-                                {
-                                    let client = messenger.lock().await;
-                                    if let Err(e) = client.send_message(&recipient, response) {
-                                        error!("Failed to send reply: {}", e);
-                                    }
-                                }
-
-                                messages_to_store.push(response.clone());
-
-                                if i < msg_count - 1 {
-                                    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
-                                    {
-                                        let client = messenger.lock().await;
-                                        let _ = client.send_typing(&recipient, false);
-                                    }
-                                    tokio::time::sleep(tokio::time::Duration::from_millis(1450)).await;
-                                }
-                            }
-
-                            if msg_count > 0 {
-                                let client = messenger.lock().await;
-                                let _ = client.send_typing(&recipient, true);
-                            }
-
-                            let mut msg_ids_for_embedding: Vec<(Uuid, String)> = Vec::new();
-                            for response in &messages_to_store {
-                                let msg_id = {
-                                    let agent_guard = agent.lock().await;
-                                    agent_guard.store_message_sync(&recipient, "assistant", response)
-                                };
-                                if let Ok(id) = msg_id {
-                                    msg_ids_for_embedding.push((id, response.clone()));
-                                }
-                            }
-
-                            if !msg_ids_for_embedding.is_empty() {
-                                let agent_clone = agent.clone();
-                                tokio::spawn(async move {
-                                    for (msg_id, content) in msg_ids_for_embedding {
-                                        let agent_guard = agent_clone.lock().await;
-                                        if let Err(e) = agent_guard.update_message_embedding(msg_id, &content).await {
-                                            tracing::warn!("Failed to update embedding: {}", e);
-                                        }
-                                    }
-                                });
-                            }
+                // Persist before processing so a crash mid-turn doesn't lose the message
+                match inbox_db.enqueue(&msg) {
+                    Ok(id) => msg.inbox_ids.push(id),
+                    Err(e) => warn!("Failed to persist incoming message (processing anyway): {}", e),
+                }
 
-                            if !result.executed_tools.is_empty() {
-                                let agent_clone = agent.clone();
-                                let recipient_clone = recipient.clone();
-                                let executed_tools = result.executed_tools.clone();
-                                tokio::spawn(async move {
-                                    let agent_guard = agent_clone.lock().await;
-                                    for executed in &executed_tools {
-                                        if let Err(e) = agent_guard.store_tool_message(&recipient_clone, &executed.tool_call, &executed.result).await {
-                                            error!("Failed to store tool message: {}", e);
-                                        }
-                                    }
-                                });
-                                info!("Queued {} tool calls for storage", result.executed_tools.len());
-                            }
+                let user_name = msg.source_name.as_deref().unwrap_or(&msg.source);
+                info!("Processing message from {}...", user_name);
 
-                            if result.done {
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            error!("Agent error at step {}: {}", step_num, e);
-                            had_error = true;
-                            break;
-                        }
+                // Get or create agent for this conversation
+                // For Signal: keyed by user UUID (reply_to == source), or by group
+                // For Marmot: keyed by sender pubkey (reply_to == from_pubkey)
+                // In a `/topic` thread: keyed by `<reply_to>#topic:<name>`
+                let (agent_id, agent) = match agent_manager.get_or_create_agent(
+                    &thread_db.agent_identifier(&msg),
+                    ContextType::for_identifier(&msg.reply_to),
+                    msg.source_name.as_deref(),
+                ).await {
+                    Ok(result) => result,
+                    Err(e) => {
+                        error!("Failed to get/create agent for {}: {}", msg.reply_to, e);
+                        end_turn(&messenger, &msg.reply_to).await;
+                        continue;
                     }
-                }
+                };
 
-                if had_error {
-                    let client = messenger.lock().await;
-                    let _ = client.send_message(
-                        &recipient,
-                        "Sorry, I encountered an error processing your message."
-                    );
-                }
+                // Hand off to the agent's worker: serial per agent, concurrent across agents
+                workers.dispatch(agent_id, agent, msg);
             }
 
             // Handle shutdown
@@ -0,0 +1,583 @@
+//! Agent Self-Maintenance
+//!
+//! Every main agent gets a recurring `maintenance` task (weekly by default,
+//! see `SELF_MAINTENANCE_CRON`). When it runs, Sage does the housekeeping that
+//! otherwise never happens:
+//! - checks core memory blocks against their character limits
+//! - prunes dead schedules (finished, failed or cancelled tasks that are old)
+//! - removes duplicate archival passages (near-duplicates are merged by the
+//!   caller, which has the agent's memory; see `memory::consolidation`)
+//! - applies the retention policy (see `memory::retention`): deletes expired
+//!   passages, decays and archives unused ones, and deletes old messages a
+//!   summary already covers
+//! - refreshes the chat's contact name from the `display_name` preference
+//!
+//! and sends a short health summary to the chat's owner (direct chats only).
+
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::memory::{RetentionPolicy, ARCHIVE_BELOW, DECAY_FACTOR};
+use crate::scheduler::{self, MaintenancePayload, SchedulerDb, TaskPayload, TaskType};
+use crate::schema::{
+    blocks, chat_contexts, messages, passages, scheduled_tasks, summaries, turn_events,
+    user_preferences,
+};
+
+/// Default schedule: Sundays at 9am in the user's timezone
+pub const DEFAULT_CRON: &str = "0 0 9 * * Sun";
+
+/// `MaintenancePayload::routine` of the self-maintenance task (see
+/// `status_report` for the other routine)
+pub const ROUTINE: &str = "weekly";
+
+/// Description of the maintenance task in `list_schedules`
+const TASK_DESCRIPTION: &str = "Weekly self-maintenance";
+
+/// Blocks filled beyond this fraction of their limit are reported
+const BLOCK_WARN_RATIO: f64 = 0.9;
+
+/// Finished, failed and cancelled tasks older than this are deleted
+const DEAD_TASK_DAYS: i64 = 30;
+
+/// A memory block close to (or over) its character limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockUsage {
+    pub label: String,
+    pub chars: usize,
+    pub limit: usize,
+}
+
+impl BlockUsage {
+    pub fn is_over(&self) -> bool {
+        self.chars > self.limit
+    }
+}
+
+/// What a maintenance run found and fixed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaintenanceReport {
+    /// Blocks near or over their character limit
+    pub full_blocks: Vec<BlockUsage>,
+    /// Dead scheduled tasks deleted
+    pub pruned_schedules: usize,
+    /// Duplicate archival passages deleted
+    pub duplicate_passages: usize,
+    /// Near-duplicate archival passages merged into others
+    pub merged_passages: usize,
+    /// Archival passages deleted because they expired
+    pub expired_passages: usize,
+    /// Unused archival passages archived by decay
+    pub archived_passages: usize,
+    /// Old summarized messages deleted
+    pub deleted_messages: usize,
+    /// Archival passages left
+    pub passages: i64,
+    /// New contact name, if it changed
+    pub renamed: Option<String>,
+}
+
+impl MaintenanceReport {
+    /// Short health summary for the owner
+    pub fn summary(&self) -> String {
+        let mut lines = vec!["Weekly check-up done.".to_string()];
+
+        if self.full_blocks.is_empty() {
+            lines.push("- Memory: all blocks have room.".to_string());
+        }
+        for block in &self.full_blocks {
+            let state = if block.is_over() {
+                "is over its limit"
+            } else {
+                "is nearly full"
+            };
+            lines.push(format!(
+                "- Memory: \"{}\" {} ({}/{} chars).",
+                block.label, state, block.chars, block.limit
+            ));
+        }
+
+        lines.push(format!(
+            "- Archive: {} entries{}{}.",
+            self.passages,
+            match self.duplicate_passages {
+                0 => String::new(),
+                n => format!(", {} duplicate{} removed", n, if n == 1 { "" } else { "s" }),
+            },
+            match self.merged_passages {
+                0 => String::new(),
+                n => format!(
+                    ", {} near-duplicate{} merged",
+                    n,
+                    if n == 1 { "" } else { "s" }
+                ),
+            }
+        ));
+        let mut retention = Vec::new();
+        if self.expired_passages > 0 {
+            retention.push(format!("{} expired", self.expired_passages));
+        }
+        if self.archived_passages > 0 {
+            retention.push(format!("{} unused archived", self.archived_passages));
+        }
+        if self.deleted_messages > 0 {
+            retention.push(format!(
+                "{} old message{} deleted",
+                self.deleted_messages,
+                if self.deleted_messages == 1 { "" } else { "s" }
+            ));
+        }
+        if !retention.is_empty() {
+            lines.push(format!("- Forgotten: {}.", retention.join(", ")));
+        }
+        if self.pruned_schedules > 0 {
+            lines.push(format!(
+                "- Schedules: cleared {} old finished task{}.",
+                self.pruned_schedules,
+                if self.pruned_schedules == 1 { "" } else { "s" }
+            ));
+        }
+        if let Some(ref name) = self.renamed {
+            lines.push(format!("- Contact name updated to {}.", name));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Blocks filled beyond `BLOCK_WARN_RATIO` of their limit, from
+/// `(label, value, char_limit)` rows
+pub fn full_blocks(rows: &[(String, String, i32)]) -> Vec<BlockUsage> {
+    rows.iter()
+        .filter(|(_, _, limit)| *limit > 0)
+        .map(|(label, value, limit)| BlockUsage {
+            label: label.clone(),
+            chars: value.chars().count(),
+            limit: *limit as usize,
+        })
+        .filter(|usage| usage.chars as f64 >= usage.limit as f64 * BLOCK_WARN_RATIO)
+        .collect()
+}
+
+/// Ids of passages that repeat an earlier one (case and whitespace
+/// insensitive). Expects `(id, content)` oldest first, so the oldest copy
+/// is kept.
+pub fn duplicate_passages(passages: &[(Uuid, String)]) -> Vec<Uuid> {
+    let mut seen = HashSet::new();
+    passages
+        .iter()
+        .filter(|(_, content)| {
+            let normalized = content
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase();
+            !seen.insert(normalized)
+        })
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// Create the agent's recurring maintenance task unless it already has one.
+/// A task the user cancelled counts, so cancelling opts out.
+pub fn ensure_scheduled(
+    scheduler_db: &SchedulerDb,
+    agent_id: Uuid,
+    cron: &str,
+    timezone: &str,
+) -> Result<bool> {
+    let existing = scheduler_db.get_tasks_by_agent(agent_id, None)?;
+    if existing.iter().any(|task| {
+        matches!(&task.payload, TaskPayload::Maintenance(p) if p.routine == ROUTINE)
+            && task.status != scheduler::TaskStatus::Completed
+            && task.status != scheduler::TaskStatus::Failed
+    }) {
+        return Ok(false);
+    }
+
+    let next_run_at = scheduler::next_cron_time(cron, timezone)?;
+    scheduler_db.create_task(
+        agent_id,
+        TaskType::Maintenance,
+        TaskPayload::Maintenance(MaintenancePayload {
+            routine: ROUTINE.to_string(),
+        }),
+        next_run_at,
+        Some(cron.to_string()),
+        timezone.to_string(),
+        TASK_DESCRIPTION.to_string(),
+    )?;
+    info!(
+        "Scheduled self-maintenance for agent {} ({})",
+        agent_id, cron
+    );
+    Ok(true)
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct MaintenanceDb {
+    conn: Arc<DbConn>,
+    retention: RetentionPolicy,
+}
+
+impl MaintenanceDb {
+    /// Create a new MaintenanceDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            retention: RetentionPolicy::default(),
+        })
+    }
+
+    /// Decay passages and delete messages as `policy` says
+    pub fn with_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = policy;
+        self
+    }
+
+    /// Run every maintenance step for an agent
+    pub fn run(&self, agent_id: Uuid) -> Result<MaintenanceReport> {
+        let report = MaintenanceReport {
+            full_blocks: self.check_blocks(agent_id)?,
+            pruned_schedules: self.prune_schedules(agent_id)?,
+            duplicate_passages: self.dedupe_passages(agent_id)?,
+            expired_passages: self.expire_passages(agent_id)?,
+            archived_passages: self.decay_passages(agent_id)?,
+            deleted_messages: self.prune_messages(agent_id)?,
+            passages: self.count_passages(agent_id)?,
+            renamed: self.refresh_display_name(agent_id)?,
+        };
+        info!(
+            "Self-maintenance for agent {}: {} full block(s), {} schedule(s) pruned, {} duplicate passage(s) removed, {} expired, {} archived, {} message(s) deleted",
+            agent_id,
+            report.full_blocks.len(),
+            report.pruned_schedules,
+            report.duplicate_passages,
+            report.expired_passages,
+            report.archived_passages,
+            report.deleted_messages
+        );
+        Ok(report)
+    }
+
+    /// Delete guest conversations' messages, turn events, passages and
+    /// summaries older than `hours`. Returns how many rows were deleted.
+    pub fn purge_guests(&self, hours: u64) -> Result<usize> {
+        let cutoff = Utc::now() - Duration::hours(hours as i64);
+        self.conn.run(|conn| {
+            let guests: Vec<Uuid> = chat_contexts::table
+                .filter(chat_contexts::context_type.eq("guest"))
+                .select(chat_contexts::id)
+                .load(conn)
+                .context("Failed to load guest contexts")?;
+            if guests.is_empty() {
+                return Ok(0);
+            }
+            let guest_ids: Vec<String> = guests.iter().map(Uuid::to_string).collect();
+
+            let mut deleted = diesel::delete(
+                messages::table
+                    .filter(messages::agent_id.eq_any(&guests))
+                    .filter(messages::created_at.lt(cutoff)),
+            )
+            .execute(conn)
+            .context("Failed to purge guest messages")?;
+            deleted += diesel::delete(
+                turn_events::table
+                    .filter(turn_events::agent_id.eq_any(&guests))
+                    .filter(turn_events::created_at.lt(cutoff)),
+            )
+            .execute(conn)
+            .context("Failed to purge guest turn events")?;
+            deleted += diesel::delete(
+                passages::table
+                    .filter(passages::agent_id.eq_any(&guest_ids))
+                    .filter(passages::created_at.lt(cutoff)),
+            )
+            .execute(conn)
+            .context("Failed to purge guest passages")?;
+            deleted += diesel::delete(
+                summaries::table
+                    .filter(summaries::agent_id.eq_any(&guests))
+                    .filter(summaries::created_at.lt(cutoff)),
+            )
+            .execute(conn)
+            .context("Failed to purge guest summaries")?;
+            Ok(deleted)
+        })
+    }
+
+    /// Blocks near or over their character limit
+    fn check_blocks(&self, agent_id: Uuid) -> Result<Vec<BlockUsage>> {
+        self.conn.run(|conn| {
+            let rows: Vec<(String, String, i32)> = blocks::table
+                .filter(blocks::agent_id.eq(agent_id.to_string()))
+                .order(blocks::label.asc())
+                .select((blocks::label, blocks::value, blocks::char_limit))
+                .load(conn)
+                .context("Failed to load blocks")?;
+            Ok(full_blocks(&rows))
+        })
+    }
+
+    /// Delete finished, failed and cancelled tasks that haven't run for
+    /// `DEAD_TASK_DAYS`
+    fn prune_schedules(&self, agent_id: Uuid) -> Result<usize> {
+        let cutoff = Utc::now() - Duration::days(DEAD_TASK_DAYS);
+        self.conn.run(|conn| {
+            diesel::delete(
+                scheduled_tasks::table
+                    .filter(scheduled_tasks::agent_id.eq(agent_id))
+                    .filter(scheduled_tasks::status.eq_any(["completed", "failed", "cancelled"]))
+                    // A cancelled check-in is how its occasion is opted out of
+                    .filter(
+                        scheduled_tasks::task_type
+                            .ne_all([TaskType::Maintenance.as_str(), TaskType::Occasion.as_str()]),
+                    )
+                    .filter(
+                        scheduled_tasks::last_run_at
+                            .lt(cutoff)
+                            .or(scheduled_tasks::last_run_at
+                                .is_null()
+                                .and(scheduled_tasks::created_at.lt(cutoff))),
+                    ),
+            )
+            .execute(conn)
+            .context("Failed to prune scheduled tasks")
+        })
+    }
+
+    /// Delete archival passages that repeat an older one
+    fn dedupe_passages(&self, agent_id: Uuid) -> Result<usize> {
+        self.conn.run(|conn| {
+            let rows: Vec<(Uuid, String)> = passages::table
+                .filter(passages::agent_id.eq(agent_id.to_string()))
+                .order(passages::created_at.asc())
+                .select((passages::id, passages::content))
+                .load(conn)
+                .context("Failed to load passages")?;
+
+            let duplicates = duplicate_passages(&rows);
+            if duplicates.is_empty() {
+                return Ok(0);
+            }
+            diesel::delete(passages::table.filter(passages::id.eq_any(&duplicates)))
+                .execute(conn)
+                .context("Failed to delete duplicate passages")
+        })
+    }
+
+    /// Delete passages past their expiry
+    fn expire_passages(&self, agent_id: Uuid) -> Result<usize> {
+        self.conn.run(|conn| {
+            diesel::delete(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id.to_string()))
+                    .filter(passages::expires_at.le(Utc::now())),
+            )
+            .execute(conn)
+            .context("Failed to delete expired passages")
+        })
+    }
+
+    /// Lower the importance of passages neither created nor retrieved within
+    /// the policy's `decay_days`, and archive those that fall below
+    /// `ARCHIVE_BELOW`. Returns how many were archived.
+    fn decay_passages(&self, agent_id: Uuid) -> Result<usize> {
+        if self.retention.decay_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = Utc::now() - Duration::days(self.retention.decay_days as i64);
+        self.conn.run(|conn| {
+            diesel::update(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id.to_string()))
+                    .filter(passages::archived_at.is_null())
+                    .filter(
+                        passages::last_retrieved_at
+                            .lt(cutoff)
+                            .or(passages::last_retrieved_at
+                                .is_null()
+                                .and(passages::created_at.lt(cutoff))),
+                    ),
+            )
+            .set(passages::importance.eq(passages::importance * DECAY_FACTOR))
+            .execute(conn)
+            .context("Failed to decay passages")?;
+
+            diesel::update(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id.to_string()))
+                    .filter(passages::archived_at.is_null())
+                    .filter(passages::importance.lt(ARCHIVE_BELOW)),
+            )
+            .set(passages::archived_at.eq(Utc::now()))
+            .execute(conn)
+            .context("Failed to archive passages")
+        })
+    }
+
+    /// Delete messages older than the policy's `message_retention_days` that
+    /// the latest summary already covers. Pinned messages are never
+    /// summarized, so they are kept.
+    fn prune_messages(&self, agent_id: Uuid) -> Result<usize> {
+        if self.retention.message_retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = Utc::now() - Duration::days(self.retention.message_retention_days as i64);
+        self.conn.run(|conn| {
+            let covered: Option<i64> = summaries::table
+                .filter(summaries::agent_id.eq(agent_id))
+                .select(diesel::dsl::max(summaries::to_sequence_id))
+                .first(conn)
+                .context("Failed to load summaries")?;
+            let Some(covered) = covered else {
+                return Ok(0);
+            };
+            diesel::delete(
+                messages::table
+                    .filter(messages::agent_id.eq(agent_id))
+                    .filter(messages::sequence_id.le(covered))
+                    .filter(messages::created_at.lt(cutoff))
+                    .filter(messages::pinned.eq(false)),
+            )
+            .execute(conn)
+            .context("Failed to delete old messages")
+        })
+    }
+
+    /// Live passages (not archived or expired)
+    fn count_passages(&self, agent_id: Uuid) -> Result<i64> {
+        self.conn.run(|conn| {
+            passages::table
+                .filter(passages::agent_id.eq(agent_id.to_string()))
+                .filter(passages::archived_at.is_null())
+                .count()
+                .get_result(conn)
+                .context("Failed to count passages")
+        })
+    }
+
+    /// Set the chat's contact name to the user's `display_name` preference.
+    /// Returns the new name if it changed.
+    fn refresh_display_name(&self, agent_id: Uuid) -> Result<Option<String>> {
+        self.conn.run(|conn| {
+            let preferred: Option<String> = user_preferences::table
+                .filter(user_preferences::agent_id.eq(agent_id))
+                .filter(user_preferences::key.eq("display_name"))
+                .select(user_preferences::value)
+                .first(conn)
+                .optional()
+                .context("Failed to load display_name preference")?;
+            let Some(preferred) = preferred.map(|name| name.trim().to_string()) else {
+                return Ok(None);
+            };
+            if preferred.is_empty() {
+                return Ok(None);
+            }
+
+            let updated = diesel::update(
+                chat_contexts::table
+                    .filter(chat_contexts::id.eq(agent_id))
+                    .filter(
+                        chat_contexts::display_name
+                            .is_null()
+                            .or(chat_contexts::display_name.ne(&preferred)),
+                    ),
+            )
+            .set(chat_contexts::display_name.eq(&preferred))
+            .execute(conn)
+            .context("Failed to update contact name")?;
+            Ok((updated > 0).then_some(preferred))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_blocks() {
+        let rows = vec![
+            ("human".to_string(), "x".repeat(95), 100),
+            ("persona".to_string(), "x".repeat(10), 100),
+            ("notes".to_string(), "x".repeat(120), 100),
+        ];
+        let full = full_blocks(&rows);
+        assert_eq!(full.len(), 2);
+        assert_eq!(full[0].label, "human");
+        assert!(!full[0].is_over());
+        assert!(full[1].is_over());
+    }
+
+    #[test]
+    fn test_duplicate_passages() {
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let passages = vec![
+            (ids[0], "Likes  hiking".to_string()),
+            (ids[1], "Allergic to peanuts".to_string()),
+            (ids[2], "likes hiking ".to_string()),
+            (ids[3], "Allergic to peanuts".to_string()),
+        ];
+        assert_eq!(duplicate_passages(&passages), vec![ids[2], ids[3]]);
+    }
+
+    #[test]
+    fn test_summary() {
+        let report = MaintenanceReport {
+            full_blocks: vec![BlockUsage {
+                label: "human".to_string(),
+                chars: 1900,
+                limit: 2000,
+            }],
+            duplicate_passages: 2,
+            passages: 40,
+            ..Default::default()
+        };
+        assert_eq!(
+            report.summary(),
+            "Weekly check-up done.\n- Memory: \"human\" is nearly full (1900/2000 chars).\n- Archive: 40 entries, 2 duplicates removed."
+        );
+    }
+
+    #[test]
+    fn test_summary_lists_what_was_forgotten() {
+        let report = MaintenanceReport {
+            expired_passages: 2,
+            deleted_messages: 1,
+            passages: 10,
+            ..Default::default()
+        };
+        assert!(report
+            .summary()
+            .ends_with("\n- Archive: 10 entries.\n- Forgotten: 2 expired, 1 old message deleted."));
+    }
+
+    #[test]
+    fn test_default_cron_parses() {
+        assert!(scheduler::parse_cron(DEFAULT_CRON).is_ok());
+    }
+
+    #[test]
+    fn test_summary_counts_merges() {
+        let report = MaintenanceReport {
+            duplicate_passages: 1,
+            merged_passages: 3,
+            passages: 12,
+            ..Default::default()
+        };
+        assert!(report
+            .summary()
+            .contains("- Archive: 12 entries, 1 duplicate removed, 3 near-duplicates merged."));
+    }
+}
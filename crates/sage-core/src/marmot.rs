@@ -2,16 +2,34 @@ use anyhow::{anyhow, Context, Result};
 use serde_json::json;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
-use crate::messenger::{IncomingMessage, Messenger};
+use crate::messenger::{
+    self, IncomingAttachment, IncomingMessage, Messenger, MessengerCapabilities, QuotedMessage,
+};
+use crate::signal;
 
 const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
 
+/// How long to wait for marmotd to confirm a message
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long to wait for marmotd to confirm a file (it encrypts and uploads it)
+const SEND_FILE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// One-shot channel resolving a command with marmotd's `ok` or `error` event
+type ResponseSender = std_mpsc::SyncSender<Result<(), String>>;
+
+/// Commands waiting for marmotd's answer, keyed by request_id
+pub type PendingRequests = Arc<Mutex<HashMap<String, ResponseSender>>>;
+
 /// Decode a bech32-encoded string (npub1...) into its raw bytes.
 fn bech32_decode_payload(s: &str) -> Option<Vec<u8>> {
     let pos = s.rfind('1')?;
@@ -84,6 +102,8 @@ pub struct MarmotClient {
     /// sharing a parent identity for cross-thread memory.
     group_routes: Arc<Mutex<HashMap<String, String>>>,
     child: Arc<Mutex<Child>>,
+    /// Commands awaiting confirmation, resolved by the receive loop
+    pending: PendingRequests,
 }
 
 impl Drop for MarmotClient {
@@ -110,6 +130,37 @@ impl MarmotClient {
     fn next_request_id(&self) -> String {
         self.request_id.fetch_add(1, Ordering::SeqCst).to_string()
     }
+
+    /// Send a command and wait for marmotd's `ok` or `error` event with the
+    /// same request_id, so failures reach the caller instead of the log
+    fn request(&self, id: &str, cmd: serde_json::Value, timeout: Duration) -> Result<()> {
+        let (tx, rx) = std_mpsc::sync_channel(1);
+        self.pending
+            .lock()
+            .map_err(|e| anyhow!("Lock error: {}", e))?
+            .insert(id.to_string(), tx);
+
+        let result = self
+            .send_cmd(cmd)
+            .and_then(|_| match rx.recv_timeout(timeout) {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(message)) => Err(anyhow!("marmotd rejected request #{}: {}", id, message)),
+                Err(std_mpsc::RecvTimeoutError::Timeout) => Err(anyhow!(
+                    "marmotd didn't answer request #{} within {:?}",
+                    id,
+                    timeout
+                )),
+                Err(std_mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!(
+                    "marmotd restarted before answering request #{}",
+                    id
+                )),
+            });
+
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(id);
+        }
+        result
+    }
 }
 
 impl MarmotClient {
@@ -126,6 +177,14 @@ impl MarmotClient {
 }
 
 impl Messenger for MarmotClient {
+    fn capabilities(&self) -> MessengerCapabilities {
+        MessengerCapabilities {
+            typing: true,
+            attachments: true,
+            ..Default::default()
+        }
+    }
+
     fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
         let group_id = self.resolve_group(recipient)?;
         let id = self.next_request_id();
@@ -144,12 +203,16 @@ impl Messenger for MarmotClient {
             group_id,
             &message[..preview_end]
         );
-        self.send_cmd(json!({
-            "cmd": "send_message",
-            "request_id": id,
-            "nostr_group_id": group_id,
-            "content": message
-        }))
+        self.request(
+            &id,
+            json!({
+                "cmd": "send_message",
+                "request_id": id,
+                "nostr_group_id": group_id,
+                "content": message
+            }),
+            SEND_TIMEOUT,
+        )
     }
 
     fn send_typing(&self, recipient: &str, stop: bool) -> Result<()> {
@@ -167,6 +230,46 @@ impl Messenger for MarmotClient {
             "nostr_group_id": group_id
         }))
     }
+
+    /// marmotd encrypts and uploads the file itself, so it gets the path
+    fn send_attachment(&self, recipient: &str, path: &Path, caption: Option<&str>) -> Result<()> {
+        let group_id = self.resolve_group(recipient)?;
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Attachment not found: {}", path.display()))?;
+        let id = self.next_request_id();
+        info!(
+            "Sending marmot file (req #{}) to {} via group {}: {}",
+            id,
+            recipient,
+            group_id,
+            path.display()
+        );
+        self.request(
+            &id,
+            json!({
+                "cmd": "send_file",
+                "request_id": id,
+                "nostr_group_id": group_id,
+                "file_path": path.to_string_lossy(),
+                "mime_type": messenger::content_type_for_path(&path),
+                "caption": caption.unwrap_or_default()
+            }),
+            SEND_FILE_TIMEOUT,
+        )
+    }
+
+    /// marmotd has no reaction command, so the emoji goes out as a short
+    /// message in the same group
+    fn send_reaction(
+        &self,
+        recipient: &str,
+        _target_author: &str,
+        _target_timestamp: u64,
+        emoji: &str,
+    ) -> Result<()> {
+        self.send_message(recipient, emoji)
+    }
 }
 
 /// Create a MarmotClient without spawning marmotd. The supervisor loop
@@ -202,9 +305,46 @@ pub fn new_marmot_client(config: &MarmotConfig) -> Result<MarmotClient> {
         request_id: AtomicU64::new(1),
         group_routes,
         child: Arc::new(Mutex::new(placeholder)),
+        pending: Arc::default(),
     })
 }
 
+/// The request a marmotd `ok` or `error` event answers, with the error
+/// message for `error`
+fn parse_response(event: &serde_json::Value) -> Option<(String, Result<(), String>)> {
+    let request_id = event.get("request_id")?.as_str()?.to_string();
+    match event.get("type")?.as_str()? {
+        "ok" => Some((request_id, Ok(()))),
+        "error" => {
+            let message = event
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown error");
+            Some((request_id, Err(message.to_string())))
+        }
+        _ => None,
+    }
+}
+
+/// Hand an `ok`/`error` event to the command waiting for it. Returns false
+/// if nothing was waiting (e.g. typing indicators, or a timed-out request).
+fn resolve_pending(pending: &PendingRequests, event: &serde_json::Value) -> bool {
+    let Some((request_id, result)) = parse_response(event) else {
+        return false;
+    };
+    let waiter = pending
+        .lock()
+        .ok()
+        .and_then(|mut pending| pending.remove(&request_id));
+    match waiter {
+        Some(tx) => {
+            let _ = tx.send(result);
+            true
+        }
+        None => false,
+    }
+}
+
 /// Single iteration of the marmot receive loop: spawn marmotd, run through
 /// all three phases (ready, keypackage, message loop), and return on any exit.
 /// The caller (supervisor) handles retry with backoff.
@@ -214,6 +354,7 @@ fn run_marmot_receive_once(
     group_routes: &Arc<Mutex<HashMap<String, String>>>,
     client_writer: &Arc<Mutex<BufWriter<std::process::ChildStdin>>>,
     client_child: &Mutex<Child>,
+    pending: &PendingRequests,
 ) -> Result<()> {
     // Spawn a fresh marmotd process
     let mut cmd = Command::new(&config.binary_path);
@@ -414,7 +555,7 @@ fn run_marmot_receive_once(
                             .unwrap_or("unknown");
                         info!("Marmot joined group: {}", group_id);
                     }
-                    "message_received" => {
+                    "message_received" | "file_received" => {
                         let from_pubkey = event
                             .get("from_pubkey")
                             .and_then(|x| x.as_str())
@@ -429,7 +570,20 @@ fn run_marmot_receive_once(
                             .and_then(|x| x.as_u64())
                             .unwrap_or(0);
 
-                        if content.is_empty() {
+                        // Media arrives already decrypted to marmotd's state dir;
+                        // copy it where attachments are processed
+                        let mut attachments = Vec::new();
+                        if event_type == "file_received" {
+                            match parse_received_file(&event).map(|f| store_received_file(&f)) {
+                                Some(Ok(attachment)) => attachments.push(attachment),
+                                Some(Err(e)) => {
+                                    warn!("Failed to store marmot file from {}: {}", from_pubkey, e)
+                                }
+                                None => warn!("marmotd file_received event without a file_path"),
+                            }
+                        }
+
+                        if content.is_empty() && attachments.is_empty() {
                             continue;
                         }
 
@@ -442,10 +596,11 @@ fn run_marmot_receive_once(
                             end
                         };
                         info!(
-                            "Marmot message from {} in group {}: {}",
+                            "Marmot message from {} in group {}: {} ({} attachment(s))",
                             from_pubkey,
                             group_id,
-                            &content[..preview_end]
+                            &content[..preview_end],
+                            attachments.len()
                         );
 
                         // Track pubkey -> latest group for reply routing.
@@ -462,10 +617,21 @@ fn run_marmot_receive_once(
                             source: from_pubkey.to_string(),
                             source_name: None,
                             message: content.to_string(),
-                            attachments: vec![],
+                            attachments,
                             timestamp: created_at,
                             reply_to: from_pubkey.to_string(),
                             reply_context: Some(group_id.to_string()),
+                            message_id: event
+                                .get("id")
+                                .or_else(|| event.get("event_id"))
+                                .and_then(|x| x.as_str())
+                                .map(|s| s.to_string()),
+                            quote: parse_reply_tag(&event),
+                            mentions: Vec::new(),
+                            edited: false,
+                            reaction: None,
+                            own_message: false,
+                            inbox_ids: Vec::new(),
                         };
 
                         if tx.blocking_send(msg).is_err() {
@@ -474,14 +640,17 @@ fn run_marmot_receive_once(
                         }
                     }
                     "ok" | "keypackage_published" => {
+                        resolve_pending(pending, &event);
                         debug!("marmotd: {}", line.trim());
                     }
                     "error" => {
-                        let msg = event
-                            .get("message")
-                            .and_then(|m| m.as_str())
-                            .unwrap_or("unknown");
-                        warn!("marmotd error: {}", msg);
+                        if !resolve_pending(pending, &event) {
+                            let msg = event
+                                .get("message")
+                                .and_then(|m| m.as_str())
+                                .unwrap_or("unknown");
+                            warn!("marmotd error: {}", msg);
+                        }
                     }
                     _ => {
                         debug!("marmotd event: {}", line.trim());
@@ -495,6 +664,84 @@ fn run_marmot_receive_once(
     }
 }
 
+/// The message a Marmot chat message replies to, from its NIP-10 style tags:
+/// an `e` tag marked "reply" (or a `q` quote tag) plus the replied-to author's
+/// `p` tag. marmotd doesn't include the quoted text.
+fn parse_reply_tag(event: &serde_json::Value) -> Option<QuotedMessage> {
+    let tags = event.get("tags")?.as_array()?;
+    let tag = |t: &serde_json::Value| -> Option<Vec<String>> {
+        t.as_array()?
+            .iter()
+            .map(|v| v.as_str().map(|s| s.to_string()))
+            .collect()
+    };
+    let tags: Vec<Vec<String>> = tags.iter().filter_map(tag).collect();
+
+    let id = tags
+        .iter()
+        .find(|t| t.len() >= 4 && t[0] == "e" && t[3] == "reply")
+        .or_else(|| tags.iter().find(|t| t.len() >= 2 && t[0] == "q"))
+        .map(|t| t[1].clone())?;
+    let author = tags
+        .iter()
+        .find(|t| t.len() >= 2 && t[0] == "p")
+        .map(|t| t[1].clone())
+        .unwrap_or_default();
+
+    Some(QuotedMessage {
+        author,
+        id,
+        text: None,
+        from_self: false,
+    })
+}
+
+/// A file marmotd received and decrypted
+#[derive(Debug, Clone, PartialEq)]
+struct ReceivedFile {
+    path: PathBuf,
+    content_type: String,
+    size: Option<u64>,
+}
+
+/// The file in a `file_received` event: `file_path` plus `mime_type` (guessed
+/// from the file name if missing) and `size`
+fn parse_received_file(event: &serde_json::Value) -> Option<ReceivedFile> {
+    let path = PathBuf::from(event.get("file_path")?.as_str()?);
+    let content_type = event
+        .get("mime_type")
+        .and_then(|x| x.as_str())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .unwrap_or_else(|| {
+            let name = event
+                .get("filename")
+                .and_then(|x| x.as_str())
+                .map(Path::new)
+                .unwrap_or(&path);
+            messenger::content_type_for_path(name).to_string()
+        });
+    Some(ReceivedFile {
+        size: event.get("size").and_then(|x| x.as_u64()),
+        path,
+        content_type,
+    })
+}
+
+/// Copy a received file into the attachments directory
+fn store_received_file(file: &ReceivedFile) -> Result<IncomingAttachment> {
+    std::fs::create_dir_all(signal::ATTACHMENTS_DIR)
+        .context("Failed to create attachments directory")?;
+    let name = format!("marmot-{}", Uuid::new_v4());
+    let size = std::fs::copy(&file.path, signal::attachment_path(&name))
+        .with_context(|| format!("Failed to copy {}", file.path.display()))?;
+    Ok(IncomingAttachment {
+        file: name,
+        content_type: file.content_type.clone(),
+        size: Some(file.size.unwrap_or(size)),
+    })
+}
+
 /// Supervised marmot receive loop with exponential backoff on failures.
 /// Respawns marmotd and re-initializes stdin/stdout handles on each retry.
 pub async fn run_marmot_receive_loop(
@@ -503,6 +750,7 @@ pub async fn run_marmot_receive_loop(
     group_routes: Arc<Mutex<HashMap<String, String>>>,
     client_writer: Arc<Mutex<BufWriter<std::process::ChildStdin>>>,
     client_child: Arc<Mutex<Child>>,
+    pending: PendingRequests,
 ) -> Result<()> {
     let mut backoff = std::time::Duration::from_millis(250);
     let backoff_max = std::time::Duration::from_secs(60);
@@ -513,12 +761,25 @@ pub async fn run_marmot_receive_loop(
         let group_routes = group_routes.clone();
         let client_writer = client_writer.clone();
         let client_child = client_child.clone();
+        let loop_pending = pending.clone();
 
         let result = tokio::task::spawn_blocking(move || {
-            run_marmot_receive_once(&config, &tx, &group_routes, &client_writer, &client_child)
+            run_marmot_receive_once(
+                &config,
+                &tx,
+                &group_routes,
+                &client_writer,
+                &client_child,
+                &loop_pending,
+            )
         })
         .await;
 
+        // The old process will never answer; fail whatever is still waiting
+        if let Ok(mut waiting) = pending.lock() {
+            waiting.clear();
+        }
+
         match result {
             Ok(Ok(())) => {
                 warn!(
@@ -565,6 +826,11 @@ pub fn child_handle(client: &MarmotClient) -> Arc<Mutex<Child>> {
     client.child.clone()
 }
 
+/// Get the shared pending-request map from a MarmotClient (for the receive loop).
+pub fn pending_handle(client: &MarmotClient) -> PendingRequests {
+    client.pending.clone()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -596,4 +862,47 @@ mod tests {
         assert!(normalize_pubkey("not_a_valid_key").is_err());
         assert!(normalize_pubkey("npub1invalid").is_err());
     }
+
+    #[test]
+    fn test_parse_reply_tag() {
+        let event = serde_json::json!({
+            "tags": [
+                ["e", "root-id", "", "root"],
+                ["e", "parent-id", "", "reply"],
+                ["p", "author-pubkey"]
+            ]
+        });
+        let quote = parse_reply_tag(&event).unwrap();
+        assert_eq!(quote.id, "parent-id");
+        assert_eq!(quote.author, "author-pubkey");
+
+        let event = serde_json::json!({ "tags": [["q", "quoted-id"]] });
+        assert_eq!(parse_reply_tag(&event).unwrap().id, "quoted-id");
+
+        assert!(parse_reply_tag(&serde_json::json!({ "content": "hi" })).is_none());
+    }
+
+    #[test]
+    fn test_resolve_pending() {
+        let pending: PendingRequests = Arc::default();
+        let (tx, rx) = std_mpsc::sync_channel(1);
+        pending.lock().unwrap().insert("7".to_string(), tx);
+
+        let ok = serde_json::json!({ "type": "ok", "request_id": "7" });
+        assert!(resolve_pending(&pending, &ok));
+        assert_eq!(rx.try_recv().unwrap(), Ok(()));
+        assert!(pending.lock().unwrap().is_empty());
+        // Already resolved
+        assert!(!resolve_pending(&pending, &ok));
+
+        let (tx, rx) = std_mpsc::sync_channel(1);
+        pending.lock().unwrap().insert("8".to_string(), tx);
+        let error =
+            serde_json::json!({ "type": "error", "request_id": "8", "message": "group not found" });
+        assert!(resolve_pending(&pending, &error));
+        assert_eq!(rx.try_recv().unwrap(), Err("group not found".to_string()));
+
+        let event = serde_json::json!({ "type": "message_received", "request_id": "9" });
+        assert!(parse_response(&event).is_none());
+    }
 }
@@ -1,16 +1,54 @@
 //! Archival Memory (Long-term Semantic Storage) - Database Backed
 //!
 //! Agent-created long-term memories stored with embeddings for semantic search.
-//! Uses PostgreSQL with pgvector for persistence and efficient similarity queries.
+//! Uses PostgreSQL with pgvector for persistence and efficient similarity queries,
+//! fused with full-text matches so exact names and numbers are found too
+//! (see `fusion`).
+//!
+//! Content longer than `MAX_PASSAGE_CHARS` is split at sentence boundaries
+//! into passages of up to `CHUNK_CHARS`, since one embedding of a long blob
+//! matches queries poorly. The parts share a `group:<id>` tag and each starts
+//! with a `[Part i/n, group:<id>]` reference, so a hit on one part leads to
+//! the rest.
+//!
+//! Search weighs results by passage importance and skips archived and
+//! expired passages (see `retention`). Near-duplicates are merged by
+//! `consolidate` (see `consolidation`).
+//!
+//! A passage can belong to a namespace ("work", "personal", ...); searches
+//! and listings in a namespace see its passages and those in none, so
+//! memories from one part of the user's life don't surface in another (see
+//! `namespaces`). `insert` and `search` are unscoped.
 
 #![allow(dead_code)]
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use uuid::Uuid;
 
+use super::consolidation::{self, Consolidated};
 use super::db::MemoryDb;
 use super::embedding::EmbeddingService;
+use super::freshness;
+use super::fusion::{reciprocal_rank_fusion, CANDIDATES_PER_RESULT};
+use super::rerank::coverage;
+use super::retention::{DEFAULT_IMPORTANCE, FORGET_MIN_SIMILARITY};
+use crate::messenger::split_message;
+
+/// Longest content stored as a single passage
+pub const MAX_PASSAGE_CHARS: usize = 2000;
+
+/// Target size of the parts longer content is split into
+pub const CHUNK_CHARS: usize = 1000;
+
+/// Passages stored by one insert
+#[derive(Debug, Clone)]
+pub struct InsertedPassages {
+    pub ids: Vec<Uuid>,
+    /// Tag shared by the parts of split content (None if it fit in one)
+    pub group: Option<String>,
+}
 
 /// A passage in archival memory
 #[derive(Debug, Clone)]
@@ -20,6 +58,7 @@ pub struct Passage {
     pub content: String,
     pub tags: Vec<String>,
     pub created_at: DateTime<Utc>,
+    pub namespace: Option<String>,
 }
 
 /// Search result from archival memory
@@ -32,17 +71,29 @@ pub struct ArchivalSearchResult {
 impl ArchivalSearchResult {
     /// Format the search result for display to the agent
     pub fn format(&self) -> String {
+        let now = Utc::now();
         let timestamp = self.passage.created_at.format("%Y-%m-%d %H:%M:%S UTC");
-        let time_ago = format_time_ago(self.passage.created_at, Utc::now());
+        let time_ago = format_time_ago(self.passage.created_at, now);
         let tags = if self.passage.tags.is_empty() {
             String::new()
         } else {
             format!(" [tags: {}]", self.passage.tags.join(", "))
         };
+        let namespace = match &self.passage.namespace {
+            Some(namespace) => format!(" [namespace: {}]", namespace),
+            None => String::new(),
+        };
 
         format!(
-            "[{}] ({}, score: {:.2}){}\n{}",
-            timestamp, time_ago, self.relevance_score, tags, self.passage.content
+            "[{}] (id: {}, {}, score: {:.2}){}{}{}\n{}",
+            timestamp,
+            self.passage.id,
+            time_ago,
+            self.relevance_score,
+            namespace,
+            tags,
+            freshness::stale_marker(self.passage.created_at, now),
+            self.passage.content
         )
     }
 }
@@ -73,62 +124,434 @@ impl ArchivalManager {
             .unwrap_or(0) as usize
     }
 
-    /// Insert a new passage into archival memory with embedding
-    pub async fn insert(&self, content: &str, tags: Option<Vec<String>>) -> Result<Uuid> {
-        // Generate embedding
-        let embedding = self.embedding.embed(content).await?;
+    /// Insert content into archival memory with embeddings, split into
+    /// several passages if it is too long (see module docs)
+    pub async fn insert(
+        &self,
+        content: &str,
+        tags: Option<Vec<String>>,
+    ) -> Result<InsertedPassages> {
+        self.insert_in(content, tags, None).await
+    }
+
+    /// `insert` into a namespace (None = no namespace)
+    pub async fn insert_in(
+        &self,
+        content: &str,
+        tags: Option<Vec<String>>,
+        namespace: Option<&str>,
+    ) -> Result<InsertedPassages> {
+        let mut tags = tags.unwrap_or_default();
+        let content = content.trim();
+
+        if content.chars().count() <= MAX_PASSAGE_CHARS {
+            let embedding = self.embedding.embed(content).await?;
+            let id = self.db.passages().insert_passage_with_embedding(
+                &self.agent_id.to_string(),
+                content,
+                &embedding,
+                &tags,
+                namespace,
+            )?;
+            tracing::debug!("Stored passage {} with embedding in archival memory", id);
+            return Ok(InsertedPassages {
+                ids: vec![id],
+                group: None,
+            });
+        }
+
+        let group = format!("group:{}", &Uuid::new_v4().simple().to_string()[..8]);
+        tags.push(group.clone());
+        let chunks = split_passage(content, CHUNK_CHARS);
+        let parts: Vec<String> = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| format!("[Part {}/{}, {}] {}", i + 1, chunks.len(), group, chunk))
+            .collect();
+
+        let texts: Vec<&str> = parts.iter().map(String::as_str).collect();
+        let embeddings = self.embedding.embed_batch(&texts).await?;
+        if embeddings.len() != parts.len() {
+            anyhow::bail!(
+                "Expected {} embeddings, got {}",
+                parts.len(),
+                embeddings.len()
+            );
+        }
 
-        let tags = tags.unwrap_or_default();
+        let mut ids = Vec::with_capacity(parts.len());
+        for (part, embedding) in parts.iter().zip(&embeddings) {
+            ids.push(self.db.passages().insert_passage_with_embedding(
+                &self.agent_id.to_string(),
+                part,
+                embedding,
+                &tags,
+                namespace,
+            )?);
+        }
+        tracing::debug!(
+            "Split {} chars into {} archival passages ({})",
+            content.chars().count(),
+            ids.len(),
+            group
+        );
+        Ok(InsertedPassages {
+            ids,
+            group: Some(group),
+        })
+    }
+
+    /// Get a passage by id
+    pub fn get(&self, id: Uuid) -> Result<Option<Passage>> {
+        Ok(self
+            .db
+            .passages()
+            .get_passage(&self.agent_id.to_string(), id)?
+            .map(|row| self.passage(row)))
+    }
+
+    /// Passages newest first, optionally only those with `tag` and those
+    /// in `namespace` (or in none)
+    pub fn list(
+        &self,
+        tag: Option<&str>,
+        namespace: Option<&str>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Passage>> {
+        Ok(self
+            .db
+            .passages()
+            .list_passages(
+                &self.agent_id.to_string(),
+                tag,
+                namespace,
+                limit as i64,
+                offset as i64,
+            )?
+            .into_iter()
+            .map(|row| self.passage(row))
+            .collect())
+    }
 
-        // Store in database with embedding
-        let id = self.db.passages().insert_passage_with_embedding(
+    /// Replace a passage's content (re-embedded) and, if given, its tags.
+    /// Returns false if there is no such passage.
+    pub async fn update(&self, id: Uuid, content: &str, tags: Option<Vec<String>>) -> Result<bool> {
+        let content = content.trim();
+        if content.chars().count() > MAX_PASSAGE_CHARS {
+            anyhow::bail!(
+                "Content is longer than {} characters; delete the passage and insert it again instead",
+                MAX_PASSAGE_CHARS
+            );
+        }
+        let embedding = self.embedding.embed(content).await?;
+        self.db.passages().update_passage(
             &self.agent_id.to_string(),
+            id,
             content,
             &embedding,
+            tags.as_deref(),
+        )
+    }
+
+    /// Delete a passage. Returns false if there is no such passage.
+    pub fn delete(&self, id: Uuid) -> Result<bool> {
+        self.db
+            .passages()
+            .delete_passage(&self.agent_id.to_string(), id)
+    }
+
+    /// Delete every part of split content (`group:<id>` tag). Returns how many.
+    pub fn delete_group(&self, group: &str) -> Result<usize> {
+        self.db
+            .passages()
+            .delete_passages_tagged(&self.agent_id.to_string(), group)
+    }
+
+    /// Move passages to a namespace (None = no namespace). Returns how many.
+    pub fn set_namespace(&self, ids: &[Uuid], namespace: Option<&str>) -> Result<usize> {
+        self.db
+            .passages()
+            .set_passage_namespace(&self.agent_id.to_string(), ids, namespace)
+    }
+
+    /// Namespaces in use, with their passage counts
+    pub fn namespaces(&self) -> Result<Vec<(String, i64)>> {
+        self.db
+            .passages()
+            .namespace_counts(&self.agent_id.to_string())
+    }
+
+    /// Set the importance and/or expiry of passages
+    pub fn set_retention(
+        &self,
+        ids: &[Uuid],
+        importance: Option<f32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        self.db.passages().set_passage_retention(
+            &self.agent_id.to_string(),
+            ids,
+            importance,
+            expires_at,
+        )
+    }
+
+    /// Delete the passages about something: those very similar to `about`
+    /// and those containing all of its words. Returns what was deleted.
+    pub async fn forget(&self, about: &str) -> Result<Vec<Passage>> {
+        let matches: Vec<Passage> = self
+            .search(about, 20, None)
+            .await?
+            .into_iter()
+            .filter(|r| {
+                r.relevance_score >= FORGET_MIN_SIMILARITY
+                    || coverage(about, &r.passage.content) >= 1.0
+            })
+            .map(|r| r.passage)
+            .collect();
+        if !matches.is_empty() {
+            let ids: Vec<Uuid> = matches.iter().map(|p| p.id).collect();
+            self.db
+                .passages()
+                .delete_passages(&self.agent_id.to_string(), &ids)?;
+        }
+        Ok(matches)
+    }
+
+    /// Merge clusters of near-duplicate passages, up to
+    /// `consolidation::MAX_MERGES` per run
+    pub async fn consolidate(&self) -> Result<Consolidated> {
+        let agent_id = self.agent_id.to_string();
+        let passages = self.db.passages();
+        let mut neighbours = Vec::new();
+        for seed in passages.mergeable_passage_ids(&agent_id, consolidation::SEEDS)? {
+            let similar =
+                passages.similar_passages(&agent_id, seed, consolidation::MAX_CLUSTER as i64)?;
+            neighbours.push((seed, similar));
+        }
+
+        let mut result = Consolidated::default();
+        for cluster in consolidation::clusters(&neighbours)
+            .into_iter()
+            .take(consolidation::MAX_MERGES)
+        {
+            match self.merge_passages(&cluster).await {
+                Ok(0) => {}
+                Ok(replaced) => {
+                    result.merges += 1;
+                    result.replaced += replaced;
+                }
+                Err(e) => tracing::warn!("Failed to merge passages {:?}: {}", cluster, e),
+            }
+        }
+        if result.merges > 0 {
+            tracing::info!(
+                "Consolidated {} archival passages into {} for agent {}",
+                result.replaced,
+                result.merges,
+                self.agent_id
+            );
+        }
+        Ok(result)
+    }
+
+    /// Replace passages with one merged by the chat model, moving their
+    /// provenance to it. Returns how many were replaced (0 if skipped).
+    async fn merge_passages(&self, ids: &[Uuid]) -> Result<usize> {
+        let agent_id = self.agent_id.to_string();
+        let passages = self.db.passages();
+        let mut rows = Vec::new();
+        for id in ids {
+            if let Some(row) = passages.get_passage(&agent_id, *id)? {
+                rows.push(row);
+            }
+        }
+        if rows.len() < 2 {
+            return Ok(0);
+        }
+        rows.sort_by_key(|row| row.created_at);
+
+        let contents: Vec<String> = rows.iter().map(|row| row.content.clone()).collect();
+        let merged = consolidation::merge(&contents).await?;
+        if merged.is_empty() || merged.chars().count() > MAX_PASSAGE_CHARS {
+            tracing::warn!(
+                "Skipping merge of {} passages: merged text is empty or too long",
+                rows.len()
+            );
+            return Ok(0);
+        }
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in rows.iter().flat_map(|row| &row.tags) {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        let old: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+        let importance = passages
+            .passage_importance(&agent_id, &old)?
+            .into_values()
+            .fold(DEFAULT_IMPORTANCE, f32::max);
+
+        // Only passages in the same namespace are merged
+        let namespace = rows[0].namespace.clone();
+        let embedding = self.embedding.embed(&merged).await?;
+        let id = passages.insert_passage_with_embedding(
+            &agent_id,
+            &merged,
+            &embedding,
             &tags,
+            namespace.as_deref(),
         )?;
+        passages.set_passage_retention(&agent_id, &[id], Some(importance), None)?;
+        let old_targets: Vec<String> = old.iter().map(Uuid::to_string).collect();
+        self.db
+            .sources()
+            .retarget(self.agent_id, "passage", &old_targets, &id.to_string())?;
+        passages.delete_passages(&agent_id, &old)
+    }
 
-        tracing::debug!("Stored passage {} with embedding in archival memory", id);
-        Ok(id)
+    fn passage(&self, row: super::db::PassageRow) -> Passage {
+        Passage {
+            id: row.id,
+            agent_id: self.agent_id,
+            content: row.content,
+            tags: row.tags,
+            created_at: row.created_at,
+            namespace: row.namespace,
+        }
     }
 
-    /// Search archival memory by semantic similarity
+    /// Search archival memory: semantic similarity and full text, merged by
+    /// reciprocal rank fusion (see `fusion`) and weighted by importance.
+    /// Returned passages are marked retrieved.
     pub async fn search(
         &self,
         query: &str,
         top_k: usize,
         tags_filter: Option<Vec<String>>,
     ) -> Result<Vec<ArchivalSearchResult>> {
-        // Generate query embedding
+        self.search_in(query, top_k, tags_filter, None).await
+    }
+
+    /// `search` among the passages in `namespace` and those in none (None =
+    /// all passages)
+    pub async fn search_in(
+        &self,
+        query: &str,
+        top_k: usize,
+        tags_filter: Option<Vec<String>>,
+        namespace: Option<&str>,
+    ) -> Result<Vec<ArchivalSearchResult>> {
         let query_embedding = self.embedding.embed(query).await?;
+        let candidates = (top_k * CANDIDATES_PER_RESULT) as i64;
+        let agent_id = self.agent_id.to_string();
 
-        // Search database with pgvector
-        let results = self.db.passages().search_passages_by_embedding(
-            &self.agent_id.to_string(),
+        let passages = self.db.passages();
+        let semantic = passages.search_passages_by_embedding(
+            &agent_id,
             &query_embedding,
-            top_k as i64,
+            candidates,
             tags_filter.as_deref(),
+            namespace,
         )?;
+        let full_text = passages.search_passages_full_text(
+            &agent_id,
+            query,
+            &query_embedding,
+            candidates,
+            tags_filter.as_deref(),
+            namespace,
+        )?;
+
+        let fused = reciprocal_rank_fusion(&[
+            semantic.iter().map(|(row, _)| row.id).collect(),
+            full_text.iter().map(|(row, _)| row.id).collect(),
+        ]);
+        let mut found: HashMap<Uuid, _> = semantic
+            .into_iter()
+            .chain(full_text)
+            .map(|(row, distance)| (row.id, (row, distance)))
+            .collect();
+
+        let ids: Vec<Uuid> = fused.iter().map(|(id, _)| *id).collect();
+        let importance = passages.passage_importance(&agent_id, &ids)?;
+        let mut weighted: Vec<(Uuid, f64)> = fused
+            .into_iter()
+            .map(|(id, score)| {
+                let weight = importance.get(&id).copied().unwrap_or(DEFAULT_IMPORTANCE);
+                (id, score * weight as f64)
+            })
+            .collect();
+        // Stable: equal scores keep the fused order
+        weighted.sort_by(|a, b| b.1.total_cmp(&a.1));
 
-        // Convert to ArchivalSearchResult
-        Ok(results
+        let results: Vec<ArchivalSearchResult> = weighted
             .into_iter()
-            .map(|(row, distance)| {
-                ArchivalSearchResult {
-                    passage: Passage {
-                        id: row.id,
-                        agent_id: self.agent_id,
-                        content: row.content,
-                        tags: row.tags,
-                        created_at: row.created_at,
-                    },
+            .take(top_k)
+            .filter_map(|(id, _)| {
+                let (row, distance) = found.remove(&id)?;
+                Some(ArchivalSearchResult {
+                    passage: self.passage(row),
                     relevance_score: 1.0 - distance as f32, // Convert distance to similarity
-                }
+                })
             })
-            .collect())
+            .collect();
+
+        let returned: Vec<Uuid> = results.iter().map(|r| r.passage.id).collect();
+        if let Err(e) = passages.mark_passages_retrieved(&agent_id, &returned) {
+            tracing::warn!("Failed to mark passages retrieved: {}", e);
+        }
+        Ok(results)
     }
 }
 
+/// Split text into chunks of at most `max_chars`, breaking between sentences.
+/// A sentence longer than that is broken at word boundaries.
+pub fn split_passage(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for sentence in sentences(text) {
+        let len = sentence.chars().count();
+        if current.chars().count() + len > max_chars && !current.trim().is_empty() {
+            chunks.push(current.trim().to_string());
+            current.clear();
+        }
+        if len > max_chars {
+            chunks.extend(split_message(sentence, max_chars));
+        } else {
+            current.push_str(sentence);
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current.trim().to_string());
+    }
+    chunks
+}
+
+/// Text cut after each sentence end (`.`, `!` or `?` before whitespace) and
+/// line break; the pieces concatenate back to `text`
+fn sentences(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let ends_sentence = c == '\n'
+            || (matches!(c, '.' | '!' | '?')
+                && chars.peek().is_some_and(|(_, next)| next.is_whitespace()));
+        if ends_sentence {
+            let end = i + c.len_utf8();
+            pieces.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
+
 /// Format a duration as human-readable "time ago"
 fn format_time_ago(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
     let duration = now.signed_duration_since(then);
@@ -143,3 +566,27 @@ fn format_time_ago(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
         "just now".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_passage() {
+        let text = "First sentence here. Second one! Third? Fourth sentence.";
+        assert_eq!(sentences(text).concat(), text);
+        assert_eq!(
+            split_passage(text, 40),
+            vec![
+                "First sentence here. Second one! Third?",
+                "Fourth sentence."
+            ]
+        );
+        assert_eq!(split_passage(text, 500), vec![text]);
+
+        // Version 1.2 isn't a sentence end; an overlong sentence breaks at words
+        let chunks = split_passage("Version 1.2 shipped with many many fixes", 20);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 20));
+        assert_eq!(chunks[0], "Version 1.2 shipped");
+    }
+}
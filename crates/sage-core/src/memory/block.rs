@@ -3,6 +3,16 @@
 //! Editable memory blocks that are always present in the system prompt.
 //! Default blocks: `persona` (who the agent is) and `human` (info about user).
 //!
+//! A group agent talks to several people, so besides the shared `human` block
+//! each participant can have their own, labeled `human:<sender id>`. They are
+//! shown after the shared block as `<human id="...">` sections
+//! (`compile_human`) and edited with the memory tools' `person` argument.
+//!
+//! The agent can also create its own blocks (`memory_create_block`) for
+//! things that deserve a standing place in the prompt, e.g. `project_context`
+//! or `health`. Each has a label, a description and a char limit, and every
+//! non-empty one is compiled into the prompt (`compile_custom`).
+//!
 //! Blocks are persisted to PostgreSQL and loaded on startup.
 
 #![allow(dead_code)]
@@ -14,12 +24,162 @@ use std::sync::{Arc, RwLock};
 use tracing::{debug, info};
 use uuid::Uuid;
 
-use super::db::{BlockDb, MemoryDb, NewBlock};
+use super::db::{BlockDb, BlockRevisionRow, BlockRow, MemoryDb, NewBlock, NewBlockRevision};
+use super::rethink;
 use super::{DEFAULT_HUMAN_DESCRIPTION, DEFAULT_PERSONA_DESCRIPTION};
 
 /// Default character limit per block (from Letta)
 pub const DEFAULT_BLOCK_CHAR_LIMIT: usize = 20_000;
 
+/// Label prefix of a group participant's human block
+pub const PERSON_PREFIX: &str = "human:";
+
+/// Description of a participant's human block
+pub const PERSON_DESCRIPTION: &str = "A participant's human block: Stores key details about one person in this group chat, identified by their sender id.";
+
+/// Most blocks the agent can create besides the built-in ones
+pub const MAX_CUSTOM_BLOCKS: usize = 10;
+
+/// Char limit of a created block when none is given
+pub const DEFAULT_CUSTOM_CHAR_LIMIT: usize = 5_000;
+
+/// Smallest char limit a created block can have
+pub const MIN_CUSTOM_CHAR_LIMIT: usize = 100;
+
+/// Longest label of a created block
+const MAX_LABEL_CHARS: usize = 40;
+
+/// Whether a block was created by the agent (not `persona`, `human` or a
+/// participant's human block)
+pub fn is_custom(label: &str) -> bool {
+    !matches!(label, "persona" | "human") && label_person(label).is_none()
+}
+
+/// Check the label of a block to create: lowercase letters, digits and
+/// underscores, starting with a letter
+pub fn validate_custom_label(label: &str) -> Result<()> {
+    if !is_custom(label) {
+        return Err(anyhow!("'{}' is a built-in block", label));
+    }
+    let valid = label.len() <= MAX_LABEL_CHARS
+        && label.starts_with(|c: char| c.is_ascii_lowercase())
+        && label
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid label '{}': use up to {} lowercase letters, digits and underscores, starting with a letter (e.g. 'project_context')",
+            label,
+            MAX_LABEL_CHARS
+        ));
+    }
+    Ok(())
+}
+
+/// Label of `person`'s human block
+pub fn person_label(person: &str) -> String {
+    format!("{}{}", PERSON_PREFIX, person.trim())
+}
+
+/// The participant a block belongs to, if it is a per-person human block
+pub fn label_person(label: &str) -> Option<&str> {
+    label.strip_prefix(PERSON_PREFIX)
+}
+
+/// Block a memory tool edits: `person`'s human block when a person is given
+/// (the block, if named, must be `human`), otherwise the named block
+pub fn target_label(block: Option<&str>, person: Option<&str>) -> Result<String> {
+    match (block, person.map(str::trim).filter(|p| !p.is_empty())) {
+        (None | Some("human"), Some(person)) => Ok(person_label(person)),
+        (Some(block), Some(_)) => Err(anyhow!(
+            "'person' only applies to the human block, not '{}'",
+            block
+        )),
+        (Some(block), None) => Ok(block.to_string()),
+        (None, None) => Err(anyhow!("'block' argument required")),
+    }
+}
+
+/// The shared human block followed by each participant's non-empty block
+/// as a `<human id="...">` section
+pub fn compile_human(shared: &str, people: &[Block]) -> String {
+    let mut sections: Vec<String> = Vec::new();
+    if !shared.trim().is_empty() {
+        sections.push(shared.to_string());
+    }
+    for block in people {
+        if let Some(person) = label_person(&block.label) {
+            if !block.value.trim().is_empty() {
+                sections.push(format!(
+                    "<human id=\"{}\">\n{}\n</human>",
+                    person, block.value
+                ));
+            }
+        }
+    }
+    sections.join("\n\n")
+}
+
+/// What made a block edit, recorded with its revision
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditCause {
+    /// Tool that made the edit, or `admin`, `import`, `/forget`, ...
+    pub source: String,
+    /// Arguments of the tool call
+    pub args: Option<serde_json::Value>,
+}
+
+impl EditCause {
+    /// An edit made by a tool call
+    pub fn tool(name: &str, args: &HashMap<String, String>) -> Self {
+        Self {
+            source: name.to_string(),
+            args: serde_json::to_value(args).ok(),
+        }
+    }
+
+    /// An edit made outside the agent's tools
+    pub fn other(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            args: None,
+        }
+    }
+
+    /// The same edit, made by rewriting a full block (`rethink`)
+    pub fn rewrite(&self) -> Self {
+        Self {
+            source: format!("{} (rewrite)", self.source),
+            args: self.args.clone(),
+        }
+    }
+}
+
+/// A block's value at `version`, from the revisions that made it (`value`)
+/// or followed it (`previous_value`)
+pub fn value_at(version: i32, revisions: &[BlockRevisionRow]) -> Option<String> {
+    revisions
+        .iter()
+        .find(|r| r.version == version)
+        .map(|r| r.value.clone())
+        .or_else(|| {
+            revisions
+                .iter()
+                .find(|r| r.version == version + 1)
+                .map(|r| r.previous_value.clone())
+        })
+}
+
+/// Order blocks are shown in: persona, human, participants, then the rest
+fn label_rank(label: &str) -> u8 {
+    match label {
+        "persona" => 0,
+        "human" => 1,
+        _ if label_person(label).is_some() => 2,
+        _ => 3,
+    }
+}
+
 /// A memory block that can be edited by the agent
 #[derive(Debug, Clone)]
 pub struct Block {
@@ -79,17 +239,17 @@ impl Block {
 
     /// Check if a new value would exceed the character limit
     pub fn would_exceed_limit(&self, new_value: &str) -> bool {
-        new_value.len() > self.char_limit
+        new_value.chars().count() > self.char_limit
     }
 
     /// Update the block's value, returning error if limit exceeded
     pub fn set_value(&mut self, new_value: impl Into<String>) -> Result<()> {
         let new_value = new_value.into();
-        if new_value.len() > self.char_limit {
+        if self.would_exceed_limit(&new_value) {
             return Err(anyhow!(
                 "Edit failed: Exceeds {} character limit (requested {})",
                 self.char_limit,
-                new_value.len()
+                new_value.chars().count()
             ));
         }
         self.value = new_value;
@@ -98,14 +258,18 @@ impl Block {
         Ok(())
     }
 
-    /// Append content to the block
-    pub fn append(&mut self, content: &str) -> Result<()> {
-        let new_value = if self.value.is_empty() {
+    /// The block's value with `content` appended
+    pub fn appended(&self, content: &str) -> String {
+        if self.value.is_empty() {
             content.to_string()
         } else {
             format!("{}\n{}", self.value, content)
-        };
-        self.set_value(new_value)
+        }
+    }
+
+    /// Append content to the block
+    pub fn append(&mut self, content: &str) -> Result<()> {
+        self.set_value(self.appended(content))
     }
 
     /// Replace text in the block
@@ -121,8 +285,8 @@ impl Block {
         self.set_value(new_value)
     }
 
-    /// Insert content at a specific line (-1 for end)
-    pub fn insert_at_line(&mut self, content: &str, line: i32) -> Result<()> {
+    /// The block's value with `content` inserted at a line (-1 for end)
+    pub fn inserted(&self, content: &str, line: i32) -> String {
         let lines: Vec<&str> = self.value.lines().collect();
         let mut new_lines: Vec<String> = lines.iter().map(|s| s.to_string()).collect();
 
@@ -133,18 +297,27 @@ impl Block {
         };
 
         new_lines.insert(insert_idx, content.to_string());
-        let new_value = new_lines.join("\n");
-        self.set_value(new_value)
+        new_lines.join("\n")
+    }
+
+    /// Insert content at a specific line (-1 for end)
+    pub fn insert_at_line(&mut self, content: &str, line: i32) -> Result<()> {
+        self.set_value(self.inserted(content, line))
     }
 
     /// Compile this block to XML format
     pub fn compile(&self) -> String {
         let label = &self.label;
         let desc = self.description.as_deref().unwrap_or("");
-        let chars_current = self.value.len();
+        let chars_current = self.value.chars().count();
         let chars_limit = self.char_limit;
 
-        let mut s = format!("<{}>\n", label);
+        // A participant's block opens as <human id="..."> and closes as </human>
+        let (open, close) = match label_person(label) {
+            Some(person) => (format!("human id=\"{}\"", person), "human"),
+            None => (label.clone(), label.as_str()),
+        };
+        let mut s = format!("<{}>\n", open);
         s.push_str("<description>\n");
         s.push_str(desc);
         s.push_str("\n</description>\n");
@@ -158,7 +331,7 @@ impl Block {
         s.push_str("<value>\n");
         s.push_str(&self.value);
         s.push_str("\n</value>\n");
-        s.push_str(&format!("</{}>\n", label));
+        s.push_str(&format!("</{}>\n", close));
 
         s
     }
@@ -237,7 +410,7 @@ impl BlockManager {
     }
 
     /// Persist a block to the database (used during initialization)
-    fn persist_block_to_db(db: &BlockDb, agent_id: &str, block: &Block) -> Result<()> {
+    fn persist_block_to_db(db: &BlockDb, agent_id: &str, block: &Block) -> Result<BlockRow> {
         db.upsert_block(NewBlock {
             id: block.id,
             agent_id,
@@ -246,22 +419,27 @@ impl BlockManager {
             value: &block.value,
             char_limit: block.char_limit as i32,
             read_only: block.read_only,
-        })?;
-        Ok(())
+        })
     }
 
-    /// Persist block value to database after modification
-    fn persist_block(&self, label: &str, value: &str) -> Result<()> {
+    /// Persist block value to database after modification, recording the
+    /// edit as a revision. Returns the block's new version.
+    fn persist_block(&self, label: &str, value: &str, cause: &EditCause) -> Result<i32> {
         let agent_id_str = self.agent_id.to_string();
-        self.db
-            .blocks()
-            .update_block_value(&agent_id_str, label, value)?;
+        let row = self.db.blocks().update_block_value_with_revision(
+            &agent_id_str,
+            label,
+            value,
+            &cause.source,
+            cause.args.as_ref(),
+        )?;
         debug!(
-            "Persisted block '{}' to database ({} chars)",
+            "Persisted block '{}' to database ({} chars, version {})",
             label,
-            value.len()
+            value.len(),
+            row.version
         );
-        Ok(())
+        Ok(row.version)
     }
 
     /// Get a block by label
@@ -287,39 +465,13 @@ impl BlockManager {
             .unwrap_or(false)
     }
 
-    /// Update a block's value
-    pub fn update(&self, label: &str, value: impl Into<String>) -> Result<()> {
-        let value = value.into();
-
-        let mut blocks = self
-            .blocks
-            .write()
-            .map_err(|_| anyhow!("Failed to acquire write lock"))?;
-
-        let block = blocks
-            .get_mut(label)
-            .ok_or_else(|| anyhow!("Block '{}' not found", label))?;
-
-        if block.read_only {
-            return Err(anyhow!("Block '{}' is read-only", label));
-        }
-
-        block.set_value(&value)?;
-
-        // Update last modified timestamp
-        if let Ok(mut last_mod) = self.last_modified.write() {
-            *last_mod = Some(Utc::now());
-        }
-
-        // Persist to database
-        drop(blocks); // Release lock before DB operation
-        self.persist_block(label, &value)?;
-
-        Ok(())
-    }
-
-    /// Replace text in a block
-    pub fn replace(&self, label: &str, old: &str, new: &str) -> Result<()> {
+    /// Apply `edit` to a writable block and persist the result
+    fn edit(
+        &self,
+        label: &str,
+        cause: &EditCause,
+        edit: impl FnOnce(&mut Block) -> Result<()>,
+    ) -> Result<()> {
         let new_value = {
             let mut blocks = self
                 .blocks
@@ -334,7 +486,7 @@ impl BlockManager {
                 return Err(anyhow!("Block '{}' is read-only", label));
             }
 
-            block.replace(old, new)?;
+            edit(block)?;
 
             if let Ok(mut last_mod) = self.last_modified.write() {
                 *last_mod = Some(Utc::now());
@@ -344,71 +496,135 @@ impl BlockManager {
         };
 
         // Persist to database (lock already released)
-        self.persist_block(label, &new_value)?;
+        let version = self.persist_block(label, &new_value, cause)?;
+        if let Ok(mut blocks) = self.blocks.write() {
+            if let Some(block) = blocks.get_mut(label) {
+                block.version = version;
+            }
+        }
 
         Ok(())
     }
 
-    /// Append to a block
-    pub fn append(&self, label: &str, content: &str) -> Result<()> {
-        let new_value = {
-            let mut blocks = self
-                .blocks
-                .write()
-                .map_err(|_| anyhow!("Failed to acquire write lock"))?;
-
-            let block = blocks
-                .get_mut(label)
-                .ok_or_else(|| anyhow!("Block '{}' not found", label))?;
-
-            if block.read_only {
-                return Err(anyhow!("Block '{}' is read-only", label));
-            }
+    /// Update a block's value
+    pub fn update(&self, label: &str, value: impl Into<String>, cause: &EditCause) -> Result<()> {
+        let value = value.into();
+        self.edit(label, cause, |block| block.set_value(value))
+    }
 
-            block.append(content)?;
+    /// Replace text in a block
+    pub fn replace(&self, label: &str, old: &str, new: &str, cause: &EditCause) -> Result<()> {
+        self.edit(label, cause, |block| block.replace(old, new))
+    }
 
-            if let Ok(mut last_mod) = self.last_modified.write() {
-                *last_mod = Some(Utc::now());
-            }
+    /// Append to a block
+    pub fn append(&self, label: &str, content: &str, cause: &EditCause) -> Result<()> {
+        self.edit(label, cause, |block| block.append(content))
+    }
 
-            block.value.clone()
-        };
+    /// Insert at a specific line in a block
+    pub fn insert_at_line(
+        &self,
+        label: &str,
+        content: &str,
+        line: i32,
+        cause: &EditCause,
+    ) -> Result<()> {
+        self.edit(label, cause, |block| block.insert_at_line(content, line))
+    }
 
-        // Persist to database (lock already released)
-        self.persist_block(label, &new_value)?;
+    /// Append to a block, or if that would go over its char limit, have the
+    /// chat model rewrite the block with `content` folded in. Returns whether
+    /// the block was rewritten.
+    pub async fn append_or_rewrite(
+        &self,
+        label: &str,
+        content: &str,
+        cause: &EditCause,
+    ) -> Result<bool> {
+        let block = self
+            .get(label)
+            .ok_or_else(|| anyhow!("Block '{}' not found", label))?;
+        if !block.would_exceed_limit(&block.appended(content)) {
+            self.append(label, content, cause)?;
+            return Ok(false);
+        }
+        self.rewrite(&block, content, cause).await?;
+        Ok(true)
+    }
 
-        Ok(())
+    /// Like `append_or_rewrite`, inserting at a line (-1 for end)
+    pub async fn insert_or_rewrite(
+        &self,
+        label: &str,
+        content: &str,
+        line: i32,
+        cause: &EditCause,
+    ) -> Result<bool> {
+        let block = self
+            .get(label)
+            .ok_or_else(|| anyhow!("Block '{}' not found", label))?;
+        if !block.would_exceed_limit(&block.inserted(content, line)) {
+            self.insert_at_line(label, content, line, cause)?;
+            return Ok(false);
+        }
+        self.rewrite(&block, content, cause).await?;
+        Ok(true)
     }
 
-    /// Insert at a specific line in a block
-    pub fn insert_at_line(&self, label: &str, content: &str, line: i32) -> Result<()> {
-        let new_value = {
-            let mut blocks = self
-                .blocks
-                .write()
-                .map_err(|_| anyhow!("Failed to acquire write lock"))?;
+    /// Replace a full block with a compressed rewrite that includes
+    /// `addition`
+    async fn rewrite(&self, block: &Block, addition: &str, cause: &EditCause) -> Result<()> {
+        if block.read_only {
+            return Err(anyhow!("Block '{}' is read-only", block.label));
+        }
+        info!(
+            "Block '{}' of agent {} is full ({} of {} chars), rewriting it",
+            block.label,
+            self.agent_id,
+            block.value.chars().count(),
+            block.char_limit
+        );
+        let rewritten = rethink::rewrite(block, addition).await?;
+        self.update(&block.label, rewritten, &cause.rewrite())
+    }
 
-            let block = blocks
-                .get_mut(label)
-                .ok_or_else(|| anyhow!("Block '{}' not found", label))?;
+    /// A block's recorded edits, newest first
+    pub fn revisions(&self, label: &str, limit: i64) -> Result<Vec<BlockRevisionRow>> {
+        self.db
+            .blocks()
+            .revisions(&self.agent_id.to_string(), label, limit)
+    }
 
-            if block.read_only {
-                return Err(anyhow!("Block '{}' is read-only", label));
+    /// Restore a block to how it was before its last edit, or as it was at
+    /// `version`. The rollback is recorded like any edit, so it can be undone
+    /// too. Returns the version restored.
+    pub fn rollback(&self, label: &str, version: Option<i32>, cause: &EditCause) -> Result<i32> {
+        let agent_id_str = self.agent_id.to_string();
+        let db = self.db.blocks();
+        let (version, value) = match version {
+            Some(version) => {
+                let revisions = db.revisions_around(&agent_id_str, label, version)?;
+                let value = value_at(version, &revisions).ok_or_else(|| {
+                    anyhow!("No recorded revision {} of block '{}'", version, label)
+                })?;
+                (version, value)
             }
-
-            block.insert_at_line(content, line)?;
-
-            if let Ok(mut last_mod) = self.last_modified.write() {
-                *last_mod = Some(Utc::now());
+            None => {
+                let last = db
+                    .revisions(&agent_id_str, label, 1)?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| anyhow!("Block '{}' has no recorded edits to undo", label))?;
+                (last.version - 1, last.previous_value)
             }
-
-            block.value.clone()
         };
-
-        // Persist to database (lock already released)
-        self.persist_block(label, &new_value)?;
-
-        Ok(())
+        self.edit(label, cause, |block| block.set_value(value))?;
+        info!(
+            "Rolled block '{}' of agent {} back to version {} ({})",
+            label, self.agent_id, version, cause.source
+        );
+        Ok(version)
     }
 
     /// Add a new block
@@ -433,6 +649,133 @@ impl BlockManager {
         Ok(())
     }
 
+    /// Insert or replace a block with its description, limit and read-only
+    /// flag (memory imports). Unlike `update`, read-only blocks are replaced.
+    pub fn put(&self, mut block: Block, cause: &EditCause) -> Result<()> {
+        if block.value.chars().count() > block.char_limit {
+            return Err(anyhow!(
+                "Block '{}' is longer than its limit of {} characters",
+                block.label,
+                block.char_limit
+            ));
+        }
+        let previous = self.get(&block.label).map(|b| b.value).unwrap_or_default();
+        let agent_id_str = self.agent_id.to_string();
+        let db = self.db.blocks();
+        let row = Self::persist_block_to_db(&db, &agent_id_str, &block)?;
+        block.version = row.version;
+        db.insert_revision(NewBlockRevision {
+            agent_id: &agent_id_str,
+            label: &block.label,
+            version: row.version,
+            previous_value: &previous,
+            value: &block.value,
+            source: &cause.source,
+            tool_args: cause.args.as_ref(),
+        })?;
+
+        self.blocks
+            .write()
+            .map_err(|_| anyhow!("Failed to acquire write lock"))?
+            .insert(block.label.clone(), block);
+        if let Ok(mut last_mod) = self.last_modified.write() {
+            *last_mod = Some(Utc::now());
+        }
+        Ok(())
+    }
+
+    /// Label of `person`'s human block, created empty if they don't have one
+    pub fn ensure_person(&self, person: &str) -> Result<String> {
+        let label = person_label(person);
+        if !self.has(&label) {
+            self.add(
+                Block::new(self.agent_id, label.clone()).with_description(PERSON_DESCRIPTION),
+            )?;
+            info!(
+                "Created human block '{}' for agent {}",
+                label, self.agent_id
+            );
+        }
+        Ok(label)
+    }
+
+    /// Participants' human blocks, by label
+    pub fn people(&self) -> Vec<Block> {
+        let mut people: Vec<Block> = self
+            .all()
+            .into_iter()
+            .filter(|b| label_person(&b.label).is_some())
+            .collect();
+        people.sort_by(|a, b| a.label.cmp(&b.label));
+        people
+    }
+
+    /// The human block as the prompt shows it, with participants' sections
+    pub fn compile_human(&self) -> String {
+        let shared = self.get("human").map(|b| b.value).unwrap_or_default();
+        compile_human(&shared, &self.people())
+    }
+
+    /// Blocks the agent created, by label
+    pub fn custom(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = self
+            .all()
+            .into_iter()
+            .filter(|b| is_custom(&b.label))
+            .collect();
+        blocks.sort_by(|a, b| a.label.cmp(&b.label));
+        blocks
+    }
+
+    /// Create a block with a description and char limit (and optionally a
+    /// first value). Fails if the label is taken or invalid, or the agent
+    /// already has `MAX_CUSTOM_BLOCKS`.
+    pub fn create(
+        &self,
+        label: &str,
+        description: &str,
+        char_limit: usize,
+        value: &str,
+        cause: &EditCause,
+    ) -> Result<Block> {
+        validate_custom_label(label)?;
+        if self.has(label) {
+            return Err(anyhow!("Block '{}' already exists", label));
+        }
+        if self.custom().len() >= MAX_CUSTOM_BLOCKS {
+            return Err(anyhow!(
+                "There are already {} custom blocks, the most allowed",
+                MAX_CUSTOM_BLOCKS
+            ));
+        }
+        if !(MIN_CUSTOM_CHAR_LIMIT..=DEFAULT_BLOCK_CHAR_LIMIT).contains(&char_limit) {
+            return Err(anyhow!(
+                "char_limit must be between {} and {}",
+                MIN_CUSTOM_CHAR_LIMIT,
+                DEFAULT_BLOCK_CHAR_LIMIT
+            ));
+        }
+
+        let block = Block::new(self.agent_id, label)
+            .with_description(description.trim())
+            .with_value(value)
+            .with_limit(char_limit);
+        self.put(block, cause)?;
+        info!("Created block '{}' for agent {}", label, self.agent_id);
+        self.get(label)
+            .ok_or_else(|| anyhow!("Block '{}' missing after create", label))
+    }
+
+    /// Non-empty blocks the agent created, as the prompt shows them
+    pub fn compile_custom(&self) -> String {
+        self.custom()
+            .iter()
+            .filter(|b| !b.value.trim().is_empty())
+            .map(|b| b.compile())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     /// Get the last modified timestamp
     pub fn last_modified(&self) -> Option<DateTime<Utc>> {
         self.last_modified.read().ok().and_then(|lm| *lm)
@@ -451,15 +794,10 @@ impl BlockManager {
 
         let mut s = String::from("<memory_blocks>\nThe following memory blocks are currently engaged in your core memory unit:\n\n");
 
-        // Sort by label for consistent ordering (persona first, then human, then others)
+        // Sort by label for consistent ordering (persona first, then human,
+        // then participants, then others)
         let mut labels: Vec<_> = blocks.keys().collect();
-        labels.sort_by(|a, b| match (a.as_str(), b.as_str()) {
-            ("persona", _) => std::cmp::Ordering::Less,
-            (_, "persona") => std::cmp::Ordering::Greater,
-            ("human", _) => std::cmp::Ordering::Less,
-            (_, "human") => std::cmp::Ordering::Greater,
-            _ => a.cmp(b),
-        });
+        labels.sort_by(|a, b| (label_rank(a), a).cmp(&(label_rank(b), b)));
 
         for (idx, label) in labels.iter().enumerate() {
             if let Some(block) = blocks.get(*label) {
@@ -546,4 +884,70 @@ mod tests {
         assert!(compiled.contains("<value>"));
         assert!(compiled.contains("Test value"));
     }
+
+    #[test]
+    fn test_custom_labels() {
+        assert!(is_custom("project_context"));
+        assert!(!is_custom("persona"));
+        assert!(!is_custom("human:+15551234567"));
+
+        assert!(validate_custom_label("health").is_ok());
+        assert!(validate_custom_label("project_2").is_ok());
+        assert!(validate_custom_label("human").is_err());
+        assert!(validate_custom_label("Project").is_err());
+        assert!(validate_custom_label("2fa").is_err());
+        assert!(validate_custom_label("my block").is_err());
+        assert!(validate_custom_label("").is_err());
+        assert!(validate_custom_label(&"a".repeat(41)).is_err());
+    }
+
+    #[test]
+    fn test_value_at() {
+        let revision = |version: i32, previous: &str, value: &str| BlockRevisionRow {
+            id: Uuid::new_v4(),
+            agent_id: "agent".to_string(),
+            label: "human".to_string(),
+            version,
+            previous_value: previous.to_string(),
+            value: value.to_string(),
+            source: "memory_append".to_string(),
+            tool_args: None,
+            created_at: Utc::now(),
+        };
+        let revisions = vec![revision(3, "a", "ab"), revision(2, "", "a")];
+        assert_eq!(value_at(3, &revisions).as_deref(), Some("ab"));
+        assert_eq!(value_at(2, &revisions).as_deref(), Some("a"));
+        // Before the first recorded edit: the value it replaced
+        assert_eq!(value_at(1, &revisions).as_deref(), Some(""));
+        assert_eq!(value_at(7, &revisions), None);
+    }
+
+    #[test]
+    fn test_person_blocks() {
+        assert_eq!(target_label(Some("persona"), None).unwrap(), "persona");
+        assert_eq!(
+            target_label(None, Some(" uuid-1 ")).unwrap(),
+            "human:uuid-1"
+        );
+        assert_eq!(
+            target_label(Some("human"), Some("uuid-1")).unwrap(),
+            "human:uuid-1"
+        );
+        assert_eq!(target_label(Some("human"), Some("")).unwrap(), "human");
+        assert!(target_label(Some("persona"), Some("uuid-1")).is_err());
+        assert!(target_label(None, None).is_err());
+
+        let agent_id = Uuid::new_v4();
+        let block = Block::new(agent_id, person_label("uuid-1")).with_value("Name: Ana");
+        let compiled = block.compile();
+        assert!(compiled.starts_with("<human id=\"uuid-1\">"));
+        assert!(compiled.ends_with("</human>\n"));
+
+        let people = vec![block, Block::new(agent_id, person_label("uuid-2"))];
+        assert_eq!(
+            compile_human("Group of friends", &people),
+            "Group of friends\n\n<human id=\"uuid-1\">\nName: Ana\n</human>"
+        );
+        assert_eq!(compile_human("Name: Bo", &[]), "Name: Bo");
+    }
 }
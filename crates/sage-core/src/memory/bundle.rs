@@ -0,0 +1,259 @@
+//! Memory Bundles
+//!
+//! A portable JSON copy of an agent's memory - core blocks, archival
+//! passages, summaries and preferences - for backups and moving an agent
+//! between deployments without `pg_dump` (`sage-admin memory export` /
+//! `memory import`, built on `MemoryManager::export` / `import`).
+//!
+//! Field names follow Letta's schemas: blocks have `label`, `value`,
+//! `limit`, `description` and `read_only`, passages have `text`, `tags` and
+//! `created_at`. Unknown fields are ignored and everything but a block's
+//! `label` and a passage's `text` is optional, so the `blocks` of a Letta
+//! agent file import as they are. Embeddings are not exported: they depend
+//! on the embedding model, and import computes them again.
+//!
+//! Import merges into the agent: blocks replace those with the same label,
+//! passages already stored word for word are skipped, and preferences are
+//! set. Summaries cover messages the target doesn't have, so they are stored
+//! before its own history (`summary_anchors`): the newest becomes the
+//! conversation's summary if the agent has none, and all stay searchable.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::block::DEFAULT_BLOCK_CHAR_LIMIT;
+
+/// `format` of a bundle
+pub const BUNDLE_FORMAT: &str = "sage-memory";
+
+/// Current bundle version
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Importance of imported passages that don't carry one
+const DEFAULT_IMPORTANCE: f32 = 0.5;
+
+/// A core memory block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleBlock {
+    pub label: String,
+    #[serde(default)]
+    pub value: String,
+    /// Character limit
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn default_limit() -> usize {
+    DEFAULT_BLOCK_CHAR_LIMIT
+}
+
+/// An archival passage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundlePassage {
+    pub text: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub importance: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Archival namespace (see `namespaces`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl BundlePassage {
+    pub fn importance(&self) -> f32 {
+        self.importance
+            .unwrap_or(DEFAULT_IMPORTANCE)
+            .clamp(0.0, 1.0)
+    }
+}
+
+/// A conversation summary, oldest first in a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleSummary {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// An agent's exported memory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryBundle {
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exported_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub blocks: Vec<BundleBlock>,
+    #[serde(default)]
+    pub passages: Vec<BundlePassage>,
+    #[serde(default)]
+    pub summaries: Vec<BundleSummary>,
+    #[serde(default)]
+    pub preferences: BTreeMap<String, String>,
+}
+
+fn default_format() -> String {
+    BUNDLE_FORMAT.to_string()
+}
+
+fn default_version() -> u32 {
+    BUNDLE_VERSION
+}
+
+impl MemoryBundle {
+    /// Parse a bundle, refusing other formats and newer versions
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let bundle: MemoryBundle = serde_json::from_str(json)?;
+        if bundle.format != BUNDLE_FORMAT {
+            anyhow::bail!("not a memory bundle (format '{}')", bundle.format);
+        }
+        if bundle.version > BUNDLE_VERSION {
+            anyhow::bail!(
+                "bundle version {} is newer than this Sage supports ({})",
+                bundle.version,
+                BUNDLE_VERSION
+            );
+        }
+        Ok(bundle)
+    }
+}
+
+/// What an import changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub blocks: usize,
+    pub passages: usize,
+    /// Passages the agent already had
+    pub duplicate_passages: usize,
+    pub summaries: usize,
+    pub preferences: usize,
+    /// Entries that couldn't be imported, with the reason
+    pub skipped: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "Imported {} block(s), {} passage(s), {} summary(ies), {} preference(s)",
+            self.blocks, self.passages, self.summaries, self.preferences
+        );
+        if self.duplicate_passages > 0 {
+            text.push_str(&format!(
+                "; {} passage(s) already present",
+                self.duplicate_passages
+            ));
+        }
+        if !self.skipped.is_empty() {
+            text.push_str(&format!("; skipped {}:", self.skipped.len()));
+            for reason in &self.skipped {
+                text.push_str(&format!("\n  - {}", reason));
+            }
+        }
+        text
+    }
+}
+
+/// Sequence ids for `count` imported summaries, oldest first, placed before
+/// the agent's own summaries and messages (which start at 1). The newest
+/// ends right below `first_own` (the lowest sequence id the agent's own
+/// summaries start at, if any), so an agent without summaries takes it as
+/// its current one and still sees all its messages after it.
+pub fn summary_anchors(count: usize, first_own: Option<i64>) -> Vec<i64> {
+    let newest = first_own.unwrap_or(1).min(1) - 1;
+    (0..count as i64)
+        .map(|i| newest - (count as i64 - 1 - i))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_letta_blocks_parse() {
+        let json = r#"{
+            "format": "sage-memory",
+            "blocks": [
+                {"id": "block-1", "label": "human", "value": "Name: Ana", "limit": 5000, "is_template": false},
+                {"label": "persona"}
+            ],
+            "passages": [{"text": "Allergic to peanuts", "tags": ["health"]}]
+        }"#;
+        let bundle = MemoryBundle::from_json(json).unwrap();
+        assert_eq!(bundle.version, BUNDLE_VERSION);
+        assert_eq!(bundle.blocks[0].limit, 5000);
+        assert_eq!(bundle.blocks[1].limit, DEFAULT_BLOCK_CHAR_LIMIT);
+        assert_eq!(bundle.blocks[1].value, "");
+        assert_eq!(bundle.passages[0].importance(), DEFAULT_IMPORTANCE);
+        assert!(bundle.summaries.is_empty());
+
+        assert!(MemoryBundle::from_json(r#"{"format": "other"}"#).is_err());
+        assert!(MemoryBundle::from_json(r#"{"version": 99}"#).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bundle = MemoryBundle {
+            format: default_format(),
+            version: BUNDLE_VERSION,
+            exported_at: Some(Utc::now()),
+            blocks: vec![BundleBlock {
+                label: "human".to_string(),
+                value: "Name: Ana".to_string(),
+                limit: 2000,
+                description: None,
+                read_only: false,
+            }],
+            passages: vec![BundlePassage {
+                text: "Allergic to peanuts".to_string(),
+                tags: vec!["health".to_string()],
+                created_at: None,
+                importance: Some(0.9),
+                expires_at: None,
+                namespace: Some("personal".to_string()),
+            }],
+            summaries: vec![BundleSummary {
+                content: "Talked about travel".to_string(),
+                created_at: None,
+            }],
+            preferences: BTreeMap::from([("timezone".to_string(), "Europe/Lisbon".to_string())]),
+        };
+        let json = serde_json::to_string(&bundle).unwrap();
+        assert_eq!(MemoryBundle::from_json(&json).unwrap(), bundle);
+    }
+
+    #[test]
+    fn test_summary_anchors() {
+        assert_eq!(summary_anchors(3, None), vec![-2, -1, 0]);
+        assert_eq!(summary_anchors(2, Some(40)), vec![-1, 0]);
+        assert_eq!(summary_anchors(2, Some(-5)), vec![-7, -6]);
+        assert!(summary_anchors(0, None).is_empty());
+    }
+
+    #[test]
+    fn test_report_summary() {
+        let report = ImportReport {
+            blocks: 2,
+            passages: 5,
+            duplicate_passages: 1,
+            skipped: vec!["preference timezone: invalid".to_string()],
+            ..Default::default()
+        };
+        let text = report.summary();
+        assert!(text.starts_with("Imported 2 block(s), 5 passage(s)"));
+        assert!(text.contains("1 passage(s) already present"));
+        assert!(text.contains("- preference timezone: invalid"));
+    }
+}
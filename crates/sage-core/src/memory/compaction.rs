@@ -2,15 +2,30 @@
 //!
 //! Summarizes old messages when context window approaches its limit.
 //! Uses DSRs signature for summarization to enable GEPA optimization.
+//!
+//! Summaries are hierarchical. Each compaction writes a level-1 summary of
+//! the messages it takes out of context. Once `ROLLUP_SIZE` summaries of one
+//! level are waiting, they are rolled into one summary a level up
+//! (`RollUpSummaries`) and marked `rolled_into` it, which can cascade. The
+//! prompt shows the summaries no rollup has absorbed yet, oldest first
+//! (`compile_summaries`): a few broad ones for the distant past and the
+//! recent level-1 detail, so it grows with the log of the history.
 
 #![allow(dead_code)]
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use uuid::Uuid;
 
 use dspy_rs::{Predict, Signature};
 
+use super::db::{MessageRow, SummaryRow};
+use crate::usage::{self, CallKind};
+
+/// Summaries of one level that are rolled into one a level up
+pub const ROLLUP_SIZE: usize = 5;
+
 /// Instruction for summarization DSRs signature
 pub const SUMMARY_INSTRUCTION: &str = r#"You are a conversation summarizer. Your job is to create a concise summary that allows an AI agent to resume a conversation without disruption, even after older messages are replaced with this summary.
 
@@ -21,6 +36,13 @@ Your summary should be structured and actionable. Include:
 
 Keep your summary under 100 words. Be specific and preserve key details like names, preferences, and decisions made."#;
 
+/// Instruction for rollup DSRs signature
+pub const ROLLUP_INSTRUCTION: &str = r#"You condense the memory of a long conversation. You get consecutive summaries of it, oldest first, and write one summary covering the whole span so an AI agent keeps the thread of the relationship after the individual summaries leave its context.
+
+Keep what still matters later: people and relationships, preferences, decisions, ongoing projects and plans, commitments, and how things turned out. Drop small talk and details that were resolved or superseded (later summaries win where they conflict). Mention roughly when things happened if the summaries say so.
+
+Keep your summary under 150 words."#;
+
 /// Instruction for correction DSRs signature
 pub const CORRECTION_INSTRUCTION: &str = r#"You are a correction agent. The summarizer produced a malformed response that couldn't be parsed. Your job is to extract the summary from the malformed response and return it in the correct format.
 
@@ -29,13 +51,27 @@ Preserve the original intent and content - do NOT generate new content. Just res
 /// DSRs signature for conversation summarization
 #[derive(Signature, Clone, Debug)]
 pub struct SummarizeConversation {
-    #[input(desc = "Previous summary to build upon (empty if first summarization)")]
+    #[input(
+        desc = "Summaries of the earlier conversation, for context (empty if first summarization)"
+    )]
     pub previous_summary: String,
 
-    #[input(desc = "New conversation messages to incorporate into the summary")]
+    #[input(desc = "New conversation messages to summarize")]
     pub new_messages: String,
 
-    #[output(desc = "Updated summary incorporating all context (100 word limit)")]
+    #[output(
+        desc = "Summary of the new messages in light of the earlier context (100 word limit)"
+    )]
+    pub summary: String,
+}
+
+/// DSRs signature for rolling summaries into one a level up
+#[derive(Signature, Clone, Debug)]
+pub struct RollUpSummaries {
+    #[input(desc = "Consecutive summaries of the conversation, oldest first")]
+    pub summaries: String,
+
+    #[output(desc = "One summary covering all of them (150 word limit)")]
     pub summary: String,
 }
 
@@ -116,8 +152,14 @@ impl CompactionManager {
         };
 
         // First attempt
-        match predictor.call(input.clone()).await {
-            Ok(response) => {
+        match predictor.call_with_meta(input.clone()).await {
+            Ok(result) => {
+                usage::record_chat(
+                    CallKind::Compaction,
+                    result.lm_usage.prompt_tokens as i64,
+                    result.lm_usage.completion_tokens as i64,
+                );
+                let response = result.output;
                 tracing::info!("Summarization succeeded on first attempt");
                 return Ok(SummaryResult::new(
                     response.summary,
@@ -154,8 +196,14 @@ impl CompactionManager {
                 self.max_retries
             );
 
-            match predictor.call(input.clone()).await {
-                Ok(response) => {
+            match predictor.call_with_meta(input.clone()).await {
+                Ok(result) => {
+                    usage::record_chat(
+                        CallKind::Compaction,
+                        result.lm_usage.prompt_tokens as i64,
+                        result.lm_usage.completion_tokens as i64,
+                    );
+                    let response = result.output;
                     tracing::info!("Summarization succeeded on retry {}", attempt);
                     return Ok(SummaryResult::new(
                         response.summary,
@@ -214,11 +262,61 @@ impl CompactionManager {
             error_message: error_message.to_string(),
         };
 
-        let corrected = correction_predictor.call(correction_input).await?;
+        let corrected = correction_predictor
+            .call_with_meta(correction_input)
+            .await?;
+        usage::record_chat(
+            CallKind::Correction,
+            corrected.lm_usage.prompt_tokens as i64,
+            corrected.lm_usage.completion_tokens as i64,
+        );
+        let corrected = corrected.output;
         tracing::info!("Summarization correction succeeded");
         Ok(corrected.summary)
     }
 
+    /// Condense consecutive summaries into one
+    pub async fn roll_up(&self, summaries: &[SummaryRow]) -> Result<String> {
+        let predictor = Predict::<RollUpSummaries>::builder()
+            .instruction(ROLLUP_INSTRUCTION)
+            .build();
+        let input = RollUpSummariesInput {
+            summaries: summaries
+                .iter()
+                .map(|s| s.content.trim())
+                .collect::<Vec<_>>()
+                .join("\n---\n"),
+        };
+
+        let mut last_error = None;
+        for attempt in 0..=self.max_retries {
+            match predictor.call_with_meta(input.clone()).await {
+                Ok(result) => {
+                    usage::record_chat(
+                        CallKind::Compaction,
+                        result.lm_usage.prompt_tokens as i64,
+                        result.lm_usage.completion_tokens as i64,
+                    );
+                    let summary = result.output.summary.trim().to_string();
+                    if !summary.is_empty() {
+                        return Ok(summary);
+                    }
+                    last_error = Some("empty rollup".to_string());
+                }
+                Err(e) => {
+                    tracing::warn!("Rollup attempt {} failed: {}", attempt + 1, e);
+                    last_error = Some(e.to_string());
+                }
+            }
+        }
+
+        anyhow::bail!(
+            "Rollup failed after {} retries: {}",
+            self.max_retries,
+            last_error.unwrap_or_default()
+        )
+    }
+
     /// Check if compaction is needed based on token count
     pub fn should_compact(&self, current_tokens: usize, max_tokens: usize, threshold: f32) -> bool {
         current_tokens > ((max_tokens as f32 * threshold) as usize)
@@ -231,6 +329,118 @@ impl Default for CompactionManager {
     }
 }
 
+/// The next rollup among an agent's unrolled summaries (oldest first): the
+/// oldest `ROLLUP_SIZE` of the lowest level that has that many, with the
+/// level their rollup gets
+pub fn next_rollup(unrolled: &[SummaryRow]) -> Option<(i32, Vec<SummaryRow>)> {
+    let mut levels: Vec<i32> = unrolled.iter().map(|s| s.level).collect();
+    levels.sort_unstable();
+    levels.dedup();
+    levels.into_iter().find_map(|level| {
+        let batch: Vec<SummaryRow> = unrolled
+            .iter()
+            .filter(|s| s.level == level)
+            .take(ROLLUP_SIZE)
+            .cloned()
+            .collect();
+        (batch.len() == ROLLUP_SIZE).then_some((level + 1, batch))
+    })
+}
+
+/// Context summary from an agent's unrolled summaries (oldest first): the
+/// broad ones for the distant past, then the recent detail
+pub fn compile_summaries(unrolled: &[SummaryRow]) -> String {
+    unrolled
+        .iter()
+        .map(|s| s.content.trim())
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Rough tokens of a summary written to its 100 word limit
+pub const SUMMARY_TOKENS: usize = 150;
+
+/// Characters a message takes in context, with its role and timestamp
+pub fn message_chars(message: &MessageRow) -> usize {
+    message.content.len() + message.role.len() + 10
+}
+
+/// Rough token count of `chars` characters (~4 per token)
+pub fn estimate_tokens(chars: usize) -> usize {
+    chars / 4
+}
+
+/// What a compaction would do, without doing it
+/// (`MemoryManager::preview_compaction`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompactionPlan {
+    pub from_sequence_id: i64,
+    pub to_sequence_id: i64,
+    /// Messages folded into the new summary
+    pub summarized: usize,
+    /// Pinned messages in that range, kept verbatim
+    pub pinned: usize,
+    /// Newer messages left in context
+    pub kept: usize,
+    /// Estimated context tokens now
+    pub tokens_before: usize,
+    /// Estimated context tokens after, with the new summary at its limit
+    pub tokens_after: usize,
+    /// Tokens at which compaction runs on its own
+    pub threshold_tokens: usize,
+}
+
+impl CompactionPlan {
+    /// Plan compacting `batch` (the oldest messages in context, in order),
+    /// leaving `kept` newer ones
+    pub fn new(
+        batch: &[MessageRow],
+        kept: usize,
+        tokens_before: usize,
+        threshold_tokens: usize,
+    ) -> Self {
+        let summarized: Vec<&MessageRow> = batch.iter().filter(|m| !m.pinned).collect();
+        let removed = estimate_tokens(summarized.iter().map(|m| message_chars(m)).sum());
+        Self {
+            from_sequence_id: batch.first().map_or(0, |m| m.sequence_id),
+            to_sequence_id: batch.last().map_or(0, |m| m.sequence_id),
+            summarized: summarized.len(),
+            pinned: batch.len() - summarized.len(),
+            kept,
+            tokens_before,
+            tokens_after: tokens_before.saturating_sub(removed) + SUMMARY_TOKENS,
+            threshold_tokens,
+        }
+    }
+
+    /// Estimated tokens the compaction frees (0 if the summary costs more)
+    pub fn tokens_saved(&self) -> usize {
+        self.tokens_before.saturating_sub(self.tokens_after)
+    }
+
+    /// One-paragraph description, for `sage-admin`
+    pub fn render(&self) -> String {
+        format!(
+            "Would summarize {} messages (sequence {} to {}){}, keeping {} newer ones in context.\n\
+             Context: ~{} tokens now, ~{} after (saves ~{}); compaction runs on its own above ~{}.",
+            self.summarized,
+            self.from_sequence_id,
+            self.to_sequence_id,
+            if self.pinned > 0 {
+                format!(", keeping {} pinned ones verbatim", self.pinned)
+            } else {
+                String::new()
+            },
+            self.kept,
+            self.tokens_before,
+            self.tokens_after,
+            self.tokens_saved(),
+            self.threshold_tokens
+        )
+    }
+}
+
 /// Extract malformed response from error if available
 fn extract_malformed_response<E: std::fmt::Display>(error: &E) -> Option<String> {
     let error_str = error.to_string();
@@ -267,6 +477,93 @@ mod tests {
         assert_eq!(summary.previous_summary_id, Some(prev_id));
     }
 
+    fn row(level: i32, from: i64, to: i64, content: &str) -> SummaryRow {
+        SummaryRow {
+            id: Uuid::new_v4(),
+            agent_id: Uuid::nil(),
+            from_sequence_id: from,
+            to_sequence_id: to,
+            content: content.to_string(),
+            previous_summary_id: None,
+            created_at: Utc::now(),
+            level,
+            rolled_into: None,
+        }
+    }
+
+    #[test]
+    fn test_next_rollup() {
+        let mut unrolled = vec![row(2, 1, 500, "broad")];
+        for i in 0..4 {
+            unrolled.push(row(1, 501 + i * 10, 510 + i * 10, "detail"));
+        }
+        assert!(next_rollup(&unrolled).is_none());
+
+        unrolled.push(row(1, 541, 550, "detail"));
+        unrolled.push(row(1, 551, 560, "newest"));
+        let (level, batch) = next_rollup(&unrolled).unwrap();
+        assert_eq!(level, 2);
+        assert_eq!(batch.len(), ROLLUP_SIZE);
+        assert_eq!(batch[0].from_sequence_id, 501);
+        assert_eq!(batch[4].to_sequence_id, 550);
+
+        // A full higher level waits until the lower levels are rolled up
+        let mut unrolled: Vec<SummaryRow> = (0..5).map(|i| row(2, i, i, "broad")).collect();
+        assert_eq!(next_rollup(&unrolled).unwrap().0, 3);
+        unrolled.extend((5..10).map(|i| row(1, i, i, "detail")));
+        assert_eq!(next_rollup(&unrolled).unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_compile_summaries() {
+        assert_eq!(compile_summaries(&[]), "");
+        let unrolled = vec![
+            row(3, 1, 900, "Years ago. "),
+            row(2, 901, 950, ""),
+            row(1, 951, 960, "Last week."),
+        ];
+        assert_eq!(compile_summaries(&unrolled), "Years ago.\n\nLast week.");
+    }
+
+    fn message(sequence_id: i64, content: &str, pinned: bool) -> MessageRow {
+        MessageRow {
+            id: Uuid::new_v4(),
+            agent_id: Uuid::nil(),
+            user_id: "alice".to_string(),
+            role: "user".to_string(),
+            content: content.to_string(),
+            sequence_id,
+            tool_calls: None,
+            tool_results: None,
+            created_at: Utc::now(),
+            attachment_text: None,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn test_compaction_plan() {
+        // 1986 + 4 + 10 chars = 500 tokens per message
+        let long = "x".repeat(1986);
+        let batch = vec![
+            message(10, &long, false),
+            message(11, "remember: always cc Bob", true),
+            message(12, &long, false),
+        ];
+        let plan = CompactionPlan::new(&batch, 20, 5_000, 100_000);
+        assert_eq!(plan.from_sequence_id, 10);
+        assert_eq!(plan.to_sequence_id, 12);
+        assert_eq!(plan.summarized, 2);
+        assert_eq!(plan.pinned, 1);
+        assert_eq!(plan.tokens_after, 5_000 - 1_000 + SUMMARY_TOKENS);
+        assert_eq!(plan.tokens_saved(), 1_000 - SUMMARY_TOKENS);
+        assert!(plan.render().contains("keeping 1 pinned ones verbatim"));
+
+        // A summary longer than what it replaces saves nothing
+        let plan = CompactionPlan::new(&[message(1, "hi", false)], 20, 100, 100_000);
+        assert_eq!(plan.tokens_saved(), 0);
+    }
+
     #[test]
     fn test_should_compact() {
         let manager = CompactionManager::new();
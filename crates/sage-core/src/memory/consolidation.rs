@@ -0,0 +1,130 @@
+//! Archival Consolidation
+//!
+//! The instruction has the agent store facts in core memory and archival
+//! memory both, and a fact is stored again whenever it comes up, so the
+//! archive fills with near-duplicates that crowd each other out of search.
+//! The weekly maintenance run (`ARCHIVAL_CONSOLIDATION`) clusters passages by
+//! embedding similarity and asks the chat model to merge each cluster into
+//! one passage that keeps every distinct fact (`MergePassages`).
+//!
+//! Seeds are the newest `SEEDS` mergeable passages; each one's neighbours
+//! come from a nearest-neighbour query over the whole archive, so an old
+//! fact written again is caught. The merged passage gets the union of the
+//! tags and the highest importance (at least the default), the originals
+//! are deleted, and their `memory_sources` rows point at it, so
+//! `memory_source` still finds where each fact was learned. Parts of split
+//! content (`group:` tags) and passages with an expiry are left alone.
+
+use anyhow::Result;
+use dspy_rs::{Predict, Signature};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::usage::{self, CallKind};
+
+/// Passages at least this similar to a seed join its cluster
+pub const MERGE_MIN_SIMILARITY: f64 = 0.9;
+
+/// Most passages merged into one
+pub const MAX_CLUSTER: usize = 5;
+
+/// Newest passages used as cluster seeds per run
+pub const SEEDS: i64 = 200;
+
+/// Most merges (model calls) per run
+pub const MAX_MERGES: usize = 10;
+
+/// Instruction for the merge DSRs signature
+pub const MERGE_INSTRUCTION: &str = r#"You maintain an assistant's long-term memory about a user. The numbered notes below say largely the same thing. Rewrite them as ONE note that keeps every distinct fact, name, number and date from all of them, in plain sentences. Where notes conflict, keep the more specific or more recent one (higher numbers are newer) and drop the other. Do not add anything that is not in the notes."#;
+
+/// DSRs signature for merging near-duplicate passages
+#[derive(Signature, Clone, Debug)]
+pub struct MergePassages {
+    #[input(desc = "Numbered notes, oldest first: [n] followed by the text")]
+    pub notes: String,
+
+    #[output(desc = "The single merged note")]
+    pub merged: String,
+}
+
+/// What a consolidation run merged
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Consolidated {
+    /// Merged passages written
+    pub merges: usize,
+    /// Passages they replaced
+    pub replaced: usize,
+}
+
+/// Group seeds with their similar neighbours, in seed order. A passage
+/// joins at most one cluster (the first seed it is similar enough to), and
+/// only clusters of two or more are returned.
+pub fn clusters(neighbours: &[(Uuid, Vec<(Uuid, f64)>)]) -> Vec<Vec<Uuid>> {
+    let mut taken: HashSet<Uuid> = HashSet::new();
+    let mut clusters = Vec::new();
+    for (seed, similar) in neighbours {
+        if taken.contains(seed) {
+            continue;
+        }
+        let mut cluster = vec![*seed];
+        for (id, similarity) in similar {
+            if cluster.len() >= MAX_CLUSTER {
+                break;
+            }
+            if *similarity >= MERGE_MIN_SIMILARITY && !taken.contains(id) && !cluster.contains(id) {
+                cluster.push(*id);
+            }
+        }
+        if cluster.len() > 1 {
+            taken.extend(cluster.iter().copied());
+            clusters.push(cluster);
+        }
+    }
+    clusters
+}
+
+/// Ask the chat model to merge passages (oldest first) into one
+pub async fn merge(contents: &[String]) -> Result<String> {
+    let notes: Vec<String> = contents
+        .iter()
+        .enumerate()
+        .map(|(i, text)| format!("[{}] {}", i + 1, text.replace('\n', " ")))
+        .collect();
+
+    let predictor = Predict::<MergePassages>::builder()
+        .instruction(MERGE_INSTRUCTION)
+        .build();
+    let result = predictor
+        .call_with_meta(MergePassagesInput {
+            notes: notes.join("\n"),
+        })
+        .await?;
+    usage::record_chat(
+        CallKind::Consolidation,
+        result.lm_usage.prompt_tokens as i64,
+        result.lm_usage.completion_tokens as i64,
+    );
+    Ok(result.output.merged.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusters() {
+        let ids: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+        let neighbours = vec![
+            (ids[0], vec![(ids[1], 0.95), (ids[2], 0.93), (ids[3], 0.7)]),
+            // Already merged with ids[0]
+            (ids[1], vec![(ids[0], 0.95), (ids[4], 0.99)]),
+            // Nothing similar enough
+            (ids[3], vec![(ids[4], 0.85)]),
+            (ids[4], vec![(ids[1], 0.99), (ids[3], 0.91)]),
+        ];
+        assert_eq!(
+            clusters(&neighbours),
+            vec![vec![ids[0], ids[1], ids[2]], vec![ids[4], ids[3]]]
+        );
+    }
+}
@@ -2,6 +2,17 @@
 //!
 //! Manages the in-context message buffer and token counting.
 //! The `message_ids` list represents which messages are visible to the LLM.
+//!
+//! Background compaction keeps the conversation under the compaction
+//! threshold, but a step can still outgrow the input budget (a huge block,
+//! a long summary, a burst of tool output before compaction runs).
+//! `ContextManager::fit` budgets each prompt section before the request is
+//! sent: the budget left after the fixed parts (instructions, tool
+//! descriptions) is split by `Section::share`, a section needing less than
+//! its share hands the rest on in `Section::ALL` order, and sections over
+//! their allocation are cut at a fixed end (blocks and tool results keep
+//! their start, the summary and recent conversation their most recent
+//! lines). The same inputs always trim the same way.
 
 #![allow(dead_code)]
 
@@ -88,6 +99,161 @@ impl ContextManager {
     pub fn clear(&mut self) {
         self.message_ids.clear();
     }
+
+    /// Trim `sections` to fit the input budget left after `fixed_tokens`
+    /// (instructions, tool descriptions, ...), each to its allocation (see
+    /// `allocate`). Returns the sections that were trimmed.
+    pub fn fit(&self, fixed_tokens: usize, sections: &mut [(Section, &mut String)]) -> Vec<Trim> {
+        let counter = TokenCounter::new();
+        let wants: Vec<(Section, usize)> = sections
+            .iter()
+            .map(|(section, text)| (*section, counter.count(text)))
+            .collect();
+        let budget = self.max_tokens.saturating_sub(fixed_tokens);
+        if wants.iter().map(|(_, want)| want).sum::<usize>() <= budget {
+            return Vec::new();
+        }
+
+        let granted = allocate(budget, &wants);
+        let mut trims = Vec::new();
+        for ((section, text), (&(_, before), &tokens)) in
+            sections.iter_mut().zip(wants.iter().zip(&granted))
+        {
+            if before <= tokens {
+                continue;
+            }
+            **text = trim_to_tokens(text, tokens, section.keeps_start());
+            trims.push(Trim {
+                section: *section,
+                before,
+                after: counter.count(text),
+            });
+        }
+        trims
+    }
+}
+
+/// Prompt sections with their own token budget, in the order budget left
+/// over by smaller sections is handed out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// Tool results shown in this step
+    ToolResults,
+    /// Recent conversation
+    Recent,
+    Human,
+    Persona,
+    /// Summary of the conversation before the recent messages
+    Summary,
+}
+
+impl Section {
+    pub const ALL: [Section; 5] = [
+        Section::ToolResults,
+        Section::Recent,
+        Section::Human,
+        Section::Persona,
+        Section::Summary,
+    ];
+
+    /// Share of the section budget
+    pub fn share(&self) -> f32 {
+        match self {
+            Section::ToolResults => 0.25,
+            Section::Recent => 0.40,
+            Section::Human => 0.10,
+            Section::Persona => 0.10,
+            Section::Summary => 0.15,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::ToolResults => "tool results",
+            Section::Recent => "recent conversation",
+            Section::Human => "human block",
+            Section::Persona => "persona block",
+            Section::Summary => "context summary",
+        }
+    }
+
+    /// Whether trimming keeps the start (else the most recent lines)
+    fn keeps_start(&self) -> bool {
+        matches!(
+            self,
+            Section::ToolResults | Section::Human | Section::Persona
+        )
+    }
+}
+
+/// A section `ContextManager::fit` cut down, with its tokens before and after
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trim {
+    pub section: Section,
+    pub before: usize,
+    pub after: usize,
+}
+
+/// Tokens each of `wants` (section, tokens needed) gets from `budget`: its
+/// share (normalized over the sections given), at most what it needs, plus
+/// what the others leave unused, handed out in `Section::ALL` order
+pub fn allocate(budget: usize, wants: &[(Section, usize)]) -> Vec<usize> {
+    let total_share: f32 = wants.iter().map(|(section, _)| section.share()).sum();
+    let mut granted: Vec<usize> = wants
+        .iter()
+        .map(|(section, want)| {
+            let share = if total_share > 0.0 {
+                section.share() / total_share
+            } else {
+                0.0
+            };
+            ((budget as f32 * share) as usize).min(*want)
+        })
+        .collect();
+
+    let mut spare = budget.saturating_sub(granted.iter().sum());
+    for section in Section::ALL {
+        for (i, (s, want)) in wants.iter().enumerate() {
+            if *s == section {
+                let extra = want.saturating_sub(granted[i]).min(spare);
+                granted[i] += extra;
+                spare -= extra;
+            }
+        }
+    }
+    granted
+}
+
+/// Marks where a section was cut
+const TRIM_MARKER: &str = "[... trimmed to fit the context budget]";
+
+/// `text` cut to about `tokens`: its start, or its last whole lines
+pub fn trim_to_tokens(text: &str, tokens: usize, keep_start: bool) -> String {
+    // Inverse of `TokenCounter::count`, less room for the marker
+    let max_bytes = (tokens * 4).saturating_sub(TRIM_MARKER.len() + 1);
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+
+    if keep_start {
+        let mut end = max_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}\n{}", &text[..end], TRIM_MARKER)
+    } else {
+        let mut start = text.len() - max_bytes;
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        // Drop the partial line the cut landed in
+        let kept = &text[start..];
+        let kept = match kept.find('\n') {
+            Some(newline) if start > 0 && !text[..start].ends_with('\n') => &kept[newline + 1..],
+            _ => kept,
+        };
+        format!("{}\n{}", TRIM_MARKER, kept)
+    }
 }
 
 /// Token counter using tiktoken (cl100k_base for GPT-4 compatible models)
@@ -156,6 +322,67 @@ mod tests {
         assert!(!ctx.message_ids().contains(&id2));
     }
 
+    #[test]
+    fn test_allocate() {
+        // Under budget: everyone gets what they need
+        let wants = [(Section::Persona, 100), (Section::Recent, 300)];
+        assert_eq!(allocate(1_000, &wants), vec![100, 300]);
+
+        // Over budget: the persona's unused share goes to the conversation
+        let wants = [
+            (Section::Persona, 50),
+            (Section::Summary, 400),
+            (Section::Recent, 2_000),
+        ];
+        let granted = allocate(1_000, &wants);
+        assert_eq!(granted[0], 50);
+        assert_eq!(granted.iter().sum::<usize>(), 1_000);
+        assert!(granted[2] > granted[1]);
+        assert_eq!(allocate(1_000, &wants), granted);
+    }
+
+    #[test]
+    fn test_trim_to_tokens() {
+        let text = "aaaa aaaa\n".repeat(100);
+        let start = trim_to_tokens(&text, 30, true);
+        assert!(start.starts_with("aaaa"));
+        assert!(start.ends_with(TRIM_MARKER));
+        assert!(TokenCounter::new().count(&start) <= 30);
+
+        let lines: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let end = trim_to_tokens(&lines, 30, false);
+        assert!(end.starts_with(TRIM_MARKER));
+        assert!(end.ends_with("line 99\n"));
+        // Whole lines only
+        assert!(end.lines().skip(1).all(|l| l.starts_with("line ")));
+
+        assert_eq!(trim_to_tokens("short", 30, true), "short");
+    }
+
+    #[test]
+    fn test_fit() {
+        let ctx = ContextManager::new(1_000);
+        let mut persona = "p".repeat(400);
+        let mut recent = "r\n".repeat(4_000);
+        let trims = ctx.fit(
+            200,
+            &mut [
+                (Section::Persona, &mut persona),
+                (Section::Recent, &mut recent),
+            ],
+        );
+        assert_eq!(trims.len(), 1);
+        assert_eq!(trims[0].section, Section::Recent);
+        assert_eq!(persona.len(), 400);
+        let counter = TokenCounter::new();
+        assert!(counter.count(&persona) + counter.count(&recent) <= 800);
+
+        let mut small = "fits".to_string();
+        assert!(ctx
+            .fit(200, &mut [(Section::Persona, &mut small)])
+            .is_empty());
+    }
+
     #[test]
     fn test_token_counter() {
         let counter = TokenCounter::new();
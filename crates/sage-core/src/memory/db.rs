@@ -1,19 +1,40 @@
 //! Database persistence layer for the memory system
 //!
-//! Provides Diesel-based CRUD operations for blocks, passages, and agents.
+//! Provides Diesel-based CRUD operations for blocks, passages, facts, and agents.
+//!
+//! Queries touching pgvector columns are raw SQL. Every value goes in as a
+//! bind parameter, never spliced into the statement; embeddings are bound
+//! natively as `pgvector::Vector` (binary, no text round trip).
 
 #![allow(dead_code)]
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use diesel::sql_types::{Array, Double, Text, Timestamptz, Uuid as DieselUuid};
-
-use std::sync::{Arc, Mutex};
+use diesel::sql_types::{
+    Array, Bool, Double, Float, Int4, Int8, Jsonb, Nullable, Text, Timestamptz, Uuid as DieselUuid,
+};
+use pgvector::sql_types::Vector as VectorType;
+use pgvector::Vector;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::schema::{agents, blocks, passages, summaries, user_preferences};
+use crate::db::DbConn;
+use crate::schema::{
+    agents, block_revisions, blocks, embedding_jobs, facts, memory_sources, open_threads, passages,
+    summaries, user_preferences,
+};
+
+/// Recent user messages the script of record is taken from
+const SCRIPT_OF_RECORD_MESSAGES: i64 = 50;
+
+/// An embedding as a bindable pgvector value
+fn vector(embedding: &[f32]) -> Vector {
+    Vector::from(embedding.to_vec())
+}
+
 // ============================================================================
 // Block Database Operations
 // ============================================================================
@@ -55,98 +76,201 @@ pub struct BlockUpdate<'a> {
     pub description: Option<Option<&'a str>>,
 }
 
+/// A recorded edit of a block: its value before and after, and what made it
+#[derive(Queryable, Selectable, Debug, Clone)]
+#[diesel(table_name = block_revisions)]
+pub struct BlockRevisionRow {
+    pub id: Uuid,
+    pub agent_id: String,
+    pub label: String,
+    /// The block's version after the edit
+    pub version: i32,
+    pub previous_value: String,
+    pub value: String,
+    /// Tool that made the edit, or `admin`, `import`, ...
+    pub source: String,
+    pub tool_args: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// New block revision to insert
+#[derive(Insertable)]
+#[diesel(table_name = block_revisions)]
+pub struct NewBlockRevision<'a> {
+    pub agent_id: &'a str,
+    pub label: &'a str,
+    pub version: i32,
+    pub previous_value: &'a str,
+    pub value: &'a str,
+    pub source: &'a str,
+    pub tool_args: Option<&'a serde_json::Value>,
+}
+
 /// Database operations for blocks
 pub struct BlockDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl BlockDb {
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
     /// Load all blocks for an agent
     pub fn load_blocks(&self, agent_id: &str) -> Result<Vec<BlockRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let results = blocks::table
-            .filter(blocks::agent_id.eq(agent_id))
-            .select(BlockRow::as_select())
-            .load(&mut *conn)?;
+        self.conn.run(|conn| {
+            let results = blocks::table
+                .filter(blocks::agent_id.eq(agent_id))
+                .select(BlockRow::as_select())
+                .load(conn)?;
 
-        Ok(results)
+            Ok(results)
+        })
     }
 
     /// Get a single block by agent and label
     pub fn get_block(&self, agent_id: &str, label: &str) -> Result<Option<BlockRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let result = blocks::table
-            .filter(blocks::agent_id.eq(agent_id))
-            .filter(blocks::label.eq(label))
-            .select(BlockRow::as_select())
-            .first(&mut *conn)
-            .optional()?;
-
-        Ok(result)
+        self.conn.run(|conn| {
+            let result = blocks::table
+                .filter(blocks::agent_id.eq(agent_id))
+                .filter(blocks::label.eq(label))
+                .select(BlockRow::as_select())
+                .first(conn)
+                .optional()?;
+
+            Ok(result)
+        })
     }
 
     /// Insert a new block
     pub fn insert_block(&self, block: NewBlock) -> Result<BlockRow> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
+        self.conn.run(|conn| {
+            let result = diesel::insert_into(blocks::table)
+                .values(&block)
+                .get_result(conn)?;
 
-        let result = diesel::insert_into(blocks::table)
-            .values(&block)
-            .get_result(&mut *conn)?;
-
-        Ok(result)
+            Ok(result)
+        })
     }
 
     /// Update a block's value
     pub fn update_block_value(&self, agent_id: &str, label: &str, value: &str) -> Result<BlockRow> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
+        self.conn.run(|conn| {
+            let result = diesel::update(blocks::table)
+                .filter(blocks::agent_id.eq(agent_id))
+                .filter(blocks::label.eq(label))
+                .set(blocks::value.eq(value))
+                .get_result(conn)?;
+
+            Ok(result)
+        })
+    }
+
+    /// Update a block's value and record the edit as a revision, in one
+    /// transaction
+    pub fn update_block_value_with_revision(
+        &self,
+        agent_id: &str,
+        label: &str,
+        value: &str,
+        source: &str,
+        tool_args: Option<&serde_json::Value>,
+    ) -> Result<BlockRow> {
+        self.conn.run(|conn| {
+            conn.transaction(|conn| {
+                let previous: String = blocks::table
+                    .filter(blocks::agent_id.eq(agent_id))
+                    .filter(blocks::label.eq(label))
+                    .select(blocks::value)
+                    .for_update()
+                    .first(conn)?;
+                let row: BlockRow = diesel::update(blocks::table)
+                    .filter(blocks::agent_id.eq(agent_id))
+                    .filter(blocks::label.eq(label))
+                    .set(blocks::value.eq(value))
+                    .get_result(conn)?;
+                diesel::insert_into(block_revisions::table)
+                    .values(NewBlockRevision {
+                        agent_id,
+                        label,
+                        version: row.version,
+                        previous_value: &previous,
+                        value,
+                        source,
+                        tool_args,
+                    })
+                    .execute(conn)?;
+                Ok(row)
+            })
+        })
+    }
 
-        let result = diesel::update(blocks::table)
-            .filter(blocks::agent_id.eq(agent_id))
-            .filter(blocks::label.eq(label))
-            .set(blocks::value.eq(value))
-            .get_result(&mut *conn)?;
+    /// Record a revision written outside `update_block_value_with_revision`
+    pub fn insert_revision(&self, revision: NewBlockRevision) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::insert_into(block_revisions::table)
+                .values(&revision)
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// A block's revisions, newest first
+    pub fn revisions(
+        &self,
+        agent_id: &str,
+        label: &str,
+        limit: i64,
+    ) -> Result<Vec<BlockRevisionRow>> {
+        self.conn.run(|conn| {
+            Ok(block_revisions::table
+                .filter(block_revisions::agent_id.eq(agent_id))
+                .filter(block_revisions::label.eq(label))
+                .order((
+                    block_revisions::version.desc(),
+                    block_revisions::created_at.desc(),
+                ))
+                .limit(limit)
+                .select(BlockRevisionRow::as_select())
+                .load(conn)?)
+        })
+    }
 
-        Ok(result)
+    /// The revision that made, or followed, `version` of a block
+    pub fn revisions_around(
+        &self,
+        agent_id: &str,
+        label: &str,
+        version: i32,
+    ) -> Result<Vec<BlockRevisionRow>> {
+        self.conn.run(|conn| {
+            Ok(block_revisions::table
+                .filter(block_revisions::agent_id.eq(agent_id))
+                .filter(block_revisions::label.eq(label))
+                .filter(block_revisions::version.eq_any([version, version + 1]))
+                .order(block_revisions::created_at.desc())
+                .select(BlockRevisionRow::as_select())
+                .load(conn)?)
+        })
     }
 
     /// Upsert a block (insert or update)
     pub fn upsert_block(&self, block: NewBlock) -> Result<BlockRow> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let result = diesel::insert_into(blocks::table)
-            .values(&block)
-            .on_conflict((blocks::agent_id, blocks::label))
-            .do_update()
-            .set((
-                blocks::value.eq(&block.value),
-                blocks::description.eq(&block.description),
-                blocks::char_limit.eq(&block.char_limit),
-                blocks::read_only.eq(&block.read_only),
-            ))
-            .get_result(&mut *conn)?;
-
-        Ok(result)
+        self.conn.run(|conn| {
+            let result = diesel::insert_into(blocks::table)
+                .values(&block)
+                .on_conflict((blocks::agent_id, blocks::label))
+                .do_update()
+                .set((
+                    blocks::value.eq(&block.value),
+                    blocks::description.eq(&block.description),
+                    blocks::char_limit.eq(&block.char_limit),
+                    blocks::read_only.eq(&block.read_only),
+                ))
+                .get_result(conn)?;
+
+            Ok(result)
+        })
     }
 }
 
@@ -163,31 +287,36 @@ pub struct PassageRow {
     pub content: String,
     pub tags: Vec<String>,
     pub created_at: DateTime<Utc>,
+    /// Archival namespace (None = visible in every namespace)
+    pub namespace: Option<String>,
 }
 
 /// Database operations for passages
 pub struct PassageDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl PassageDb {
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
-    /// Count passages for an agent
+    /// Count an agent's live passages (not archived or expired)
     pub fn count_passages(&self, agent_id: &str) -> Result<i64> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let count: i64 = passages::table
-            .filter(passages::agent_id.eq(agent_id))
-            .count()
-            .get_result(&mut *conn)?;
+        self.conn.run(|conn| {
+            let count: i64 = passages::table
+                .filter(passages::agent_id.eq(agent_id))
+                .filter(passages::archived_at.is_null())
+                .filter(
+                    passages::expires_at
+                        .is_null()
+                        .or(passages::expires_at.gt(Utc::now())),
+                )
+                .count()
+                .get_result(conn)?;
 
-        Ok(count)
+            Ok(count)
+        })
     }
 
     /// Insert a passage with embedding using raw SQL
@@ -197,126 +326,475 @@ impl PassageDb {
         content: &str,
         embedding: &[f32],
         tags: &[String],
+        namespace: Option<&str>,
     ) -> Result<Uuid> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let id = Uuid::new_v4();
-        let embedding_str = format!(
-            "[{}]",
-            embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-        let tags_array = tags
-            .iter()
-            .map(|t| format!("'{}'", t.replace('\'', "''")))
-            .collect::<Vec<_>>()
-            .join(",");
-
-        diesel::sql_query(format!(
-            "INSERT INTO passages (id, agent_id, content, embedding, tags) \
-             VALUES ('{}', '{}', '{}', '{}', ARRAY[{}]::text[])",
-            id,
-            agent_id.replace('\'', "''"),
-            content.replace('\'', "''"),
-            embedding_str,
-            tags_array
-        ))
-        .execute(&mut *conn)?;
+        self.conn.run(|conn| {
+            let id = Uuid::new_v4();
+            diesel::sql_query(
+                "INSERT INTO passages (id, agent_id, content, embedding, tags, namespace) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind::<DieselUuid, _>(id)
+            .bind::<Text, _>(agent_id)
+            .bind::<Text, _>(content)
+            .bind::<VectorType, _>(vector(embedding))
+            .bind::<Array<Text>, _>(tags)
+            .bind::<Nullable<Text>, _>(namespace)
+            .execute(conn)?;
+
+            Ok(id)
+        })
+    }
 
-        Ok(id)
+    /// Get one of an agent's passages
+    pub fn get_passage(&self, agent_id: &str, id: Uuid) -> Result<Option<PassageRow>> {
+        self.conn.run(|conn| {
+            let row = passages::table
+                .filter(passages::agent_id.eq(agent_id))
+                .filter(passages::id.eq(id))
+                .select((
+                    passages::id,
+                    passages::agent_id,
+                    passages::content,
+                    passages::tags,
+                    passages::created_at,
+                    passages::namespace,
+                ))
+                .first::<PassageTuple>(conn)
+                .optional()?;
+
+            Ok(row.map(PassageRow::from))
+        })
     }
 
-    /// Search passages by vector similarity using raw SQL
-    pub fn search_passages_by_embedding(
+    /// An agent's live passages, newest first, optionally only those with a
+    /// tag and those in a namespace (or in none)
+    pub fn list_passages(
         &self,
         agent_id: &str,
-        query_embedding: &[f32],
+        tag: Option<&str>,
+        namespace: Option<&str>,
         limit: i64,
-        tags_filter: Option<&[String]>,
-    ) -> Result<Vec<(PassageRow, f64)>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let embedding_str = format!(
-            "[{}]",
-            query_embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-
-        let tags_clause = if let Some(tags) = tags_filter {
-            if tags.is_empty() {
-                String::new()
-            } else {
-                let tags_array = tags
-                    .iter()
-                    .map(|t| format!("'{}'", t.replace('\'', "''")))
-                    .collect::<Vec<_>>()
-                    .join(",");
-                format!(" AND tags && ARRAY[{}]::text[]", tags_array)
+        offset: i64,
+    ) -> Result<Vec<PassageRow>> {
+        self.conn.run(|conn| {
+            let mut query = passages::table
+                .filter(passages::agent_id.eq(agent_id))
+                .filter(passages::archived_at.is_null())
+                .filter(
+                    passages::expires_at
+                        .is_null()
+                        .or(passages::expires_at.gt(Utc::now())),
+                )
+                .select((
+                    passages::id,
+                    passages::agent_id,
+                    passages::content,
+                    passages::tags,
+                    passages::created_at,
+                    passages::namespace,
+                ))
+                .order((passages::created_at.desc(), passages::id))
+                .limit(limit)
+                .offset(offset)
+                .into_boxed();
+            if let Some(tag) = tag {
+                query = query.filter(passages::tags.contains(vec![tag.to_string()]));
             }
-        } else {
-            String::new()
-        };
+            if let Some(namespace) = namespace {
+                query = query.filter(
+                    passages::namespace
+                        .is_null()
+                        .or(passages::namespace.eq(namespace)),
+                );
+            }
+            let rows = query.load::<PassageTuple>(conn)?;
 
-        // Use cosine distance (smaller is better, 0 = identical)
-        let query = format!(
-            "SELECT id, agent_id, content, tags, created_at, \
-                    (embedding <=> '{}') as distance \
-             FROM passages \
-             WHERE agent_id = '{}'{} \
-             ORDER BY distance \
-             LIMIT {}",
-            embedding_str,
-            agent_id.replace('\'', "''"),
-            tags_clause,
-            limit
-        );
-
-        // Execute raw query and parse results
-        #[allow(clippy::type_complexity)]
-        let results: Vec<(Uuid, String, String, Vec<String>, DateTime<Utc>, f64)> =
-            diesel::sql_query(&query)
-                .load::<PassageSearchRow>(&mut *conn)?
-                .into_iter()
-                .map(|row| {
-                    (
-                        row.id,
-                        row.agent_id,
-                        row.content,
-                        row.tags,
-                        row.created_at,
-                        row.distance,
-                    )
-                })
-                .collect();
+            Ok(rows.into_iter().map(PassageRow::from).collect())
+        })
+    }
 
-        Ok(results
-            .into_iter()
-            .map(|(id, agent_id, content, tags, created_at, distance)| {
-                (
-                    PassageRow {
+    /// An agent's live passages with their importance and expiry, oldest
+    /// first (for memory exports)
+    pub fn export_passages(
+        &self,
+        agent_id: &str,
+    ) -> Result<Vec<(PassageRow, f32, Option<DateTime<Utc>>)>> {
+        self.conn.run(|conn| {
+            let rows = passages::table
+                .filter(passages::agent_id.eq(agent_id))
+                .filter(passages::archived_at.is_null())
+                .filter(
+                    passages::expires_at
+                        .is_null()
+                        .or(passages::expires_at.gt(Utc::now())),
+                )
+                .select((
+                    passages::id,
+                    passages::agent_id,
+                    passages::content,
+                    passages::tags,
+                    passages::created_at,
+                    passages::namespace,
+                    passages::importance,
+                    passages::expires_at,
+                ))
+                .order((passages::created_at.asc(), passages::id))
+                .load::<(
+                    Uuid,
+                    String,
+                    String,
+                    Vec<String>,
+                    DateTime<Utc>,
+                    Option<String>,
+                    f32,
+                    Option<DateTime<Utc>>,
+                )>(conn)?;
+
+            Ok(rows
+                .into_iter()
+                .map(
+                    |(
                         id,
                         agent_id,
                         content,
                         tags,
                         created_at,
+                        namespace,
+                        importance,
+                        expires_at,
+                    )| {
+                        (
+                            PassageRow {
+                                id,
+                                agent_id,
+                                content,
+                                tags,
+                                created_at,
+                                namespace,
+                            },
+                            importance,
+                            expires_at,
+                        )
                     },
-                    distance,
                 )
-            })
-            .collect())
+                .collect())
+        })
+    }
+
+    /// Replace a passage's content and embedding (and its tags, if given).
+    /// Returns false if the agent has no such passage.
+    pub fn update_passage(
+        &self,
+        agent_id: &str,
+        id: Uuid,
+        content: &str,
+        embedding: &[f32],
+        tags: Option<&[String]>,
+    ) -> Result<bool> {
+        self.conn.run(|conn| {
+            let updated = diesel::sql_query(
+                "UPDATE passages SET content = $1, embedding = $2, tags = COALESCE($3, tags) \
+                 WHERE id = $4 AND agent_id = $5",
+            )
+            .bind::<Text, _>(content)
+            .bind::<VectorType, _>(vector(embedding))
+            .bind::<Nullable<Array<Text>>, _>(tags.map(|tags| tags.to_vec()))
+            .bind::<DieselUuid, _>(id)
+            .bind::<Text, _>(agent_id)
+            .execute(conn)?;
+
+            Ok(updated > 0)
+        })
+    }
+
+    /// Delete one of an agent's passages. Returns false if there was none.
+    pub fn delete_passage(&self, agent_id: &str, id: Uuid) -> Result<bool> {
+        self.conn.run(|conn| {
+            let deleted = diesel::delete(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id))
+                    .filter(passages::id.eq(id)),
+            )
+            .execute(conn)?;
+
+            Ok(deleted > 0)
+        })
+    }
+
+    /// Delete all of an agent's passages with a tag. Returns how many.
+    pub fn delete_passages_tagged(&self, agent_id: &str, tag: &str) -> Result<usize> {
+        self.conn.run(|conn| {
+            let deleted = diesel::delete(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id))
+                    .filter(passages::tags.contains(vec![tag.to_string()])),
+            )
+            .execute(conn)?;
+
+            Ok(deleted)
+        })
+    }
+
+    /// Delete some of an agent's passages. Returns how many.
+    pub fn delete_passages(&self, agent_id: &str, ids: &[Uuid]) -> Result<usize> {
+        self.conn.run(|conn| {
+            let deleted = diesel::delete(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id))
+                    .filter(passages::id.eq_any(ids)),
+            )
+            .execute(conn)?;
+
+            Ok(deleted)
+        })
+    }
+
+    /// Set the importance and/or expiry of some of an agent's passages
+    pub fn set_passage_retention(
+        &self,
+        agent_id: &str,
+        ids: &[Uuid],
+        importance: Option<f32>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::sql_query(
+                "UPDATE passages SET importance = COALESCE($1, importance), \
+                    expires_at = COALESCE($2, expires_at) \
+                 WHERE agent_id = $3 AND id = ANY($4)",
+            )
+            .bind::<Nullable<Float>, _>(importance)
+            .bind::<Nullable<Timestamptz>, _>(expires_at)
+            .bind::<Text, _>(agent_id)
+            .bind::<Array<DieselUuid>, _>(ids)
+            .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Move some of an agent's passages to a namespace (None = no
+    /// namespace). Returns how many were moved.
+    pub fn set_passage_namespace(
+        &self,
+        agent_id: &str,
+        ids: &[Uuid],
+        namespace: Option<&str>,
+    ) -> Result<usize> {
+        self.conn.run(|conn| {
+            let updated = diesel::update(
+                passages::table
+                    .filter(passages::agent_id.eq(agent_id))
+                    .filter(passages::id.eq_any(ids)),
+            )
+            .set(passages::namespace.eq(namespace))
+            .execute(conn)?;
+
+            Ok(updated)
+        })
+    }
+
+    /// An agent's namespaces with their live passage counts, by name
+    pub fn namespace_counts(&self, agent_id: &str) -> Result<Vec<(String, i64)>> {
+        self.conn.run(|conn| {
+            let rows: Vec<(Option<String>, i64)> = passages::table
+                .filter(passages::agent_id.eq(agent_id))
+                .filter(passages::namespace.is_not_null())
+                .filter(passages::archived_at.is_null())
+                .filter(
+                    passages::expires_at
+                        .is_null()
+                        .or(passages::expires_at.gt(Utc::now())),
+                )
+                .group_by(passages::namespace)
+                .select((passages::namespace, diesel::dsl::count_star()))
+                .order(passages::namespace)
+                .load(conn)?;
+
+            Ok(rows
+                .into_iter()
+                .filter_map(|(namespace, count)| namespace.map(|namespace| (namespace, count)))
+                .collect())
+        })
+    }
+
+    /// Importance of some of an agent's passages
+    pub fn passage_importance(&self, agent_id: &str, ids: &[Uuid]) -> Result<HashMap<Uuid, f32>> {
+        self.conn.run(|conn| {
+            let rows: Vec<(Uuid, f32)> = passages::table
+                .filter(passages::agent_id.eq(agent_id))
+                .filter(passages::id.eq_any(ids))
+                .select((passages::id, passages::importance))
+                .load(conn)?;
+
+            Ok(rows.into_iter().collect())
+        })
+    }
+
+    /// Note that search returned these passages: they count as used again,
+    /// and decayed ones get their default importance back
+    pub fn mark_passages_retrieved(&self, agent_id: &str, ids: &[Uuid]) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::sql_query(
+                "UPDATE passages SET last_retrieved_at = now(), \
+                    importance = GREATEST(importance, $1) \
+                 WHERE agent_id = $2 AND id = ANY($3)",
+            )
+            .bind::<Float, _>(super::retention::DEFAULT_IMPORTANCE)
+            .bind::<Text, _>(agent_id)
+            .bind::<Array<DieselUuid>, _>(ids)
+            .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Newest passages consolidation may merge: live, embedded, without an
+    /// expiry and not part of split content
+    pub fn mergeable_passage_ids(&self, agent_id: &str, limit: i64) -> Result<Vec<Uuid>> {
+        self.conn.run(|conn| {
+            let rows: Vec<IdRow> = diesel::sql_query(
+                "SELECT id FROM passages \
+                 WHERE agent_id = $1 AND archived_at IS NULL AND expires_at IS NULL \
+                    AND embedding IS NOT NULL AND vector_norm(embedding) > 0 \
+                    AND NOT EXISTS (SELECT 1 FROM unnest(tags) t WHERE t LIKE 'group:%') \
+                 ORDER BY created_at DESC \
+                 LIMIT $2",
+            )
+            .bind::<Text, _>(agent_id)
+            .bind::<Int8, _>(limit)
+            .load(conn)?;
+
+            Ok(rows.into_iter().map(|row| row.id).collect())
+        })
     }
+
+    /// Mergeable passages (see `mergeable_passage_ids`) in the same namespace
+    /// as passage `id` and closest to it, most similar first, with their
+    /// cosine similarity to it
+    pub fn similar_passages(
+        &self,
+        agent_id: &str,
+        id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, f64)>> {
+        self.conn.run(|conn| {
+            let rows: Vec<SimilarPassageRow> = diesel::sql_query(
+                "WITH s AS (SELECT embedding, namespace FROM passages WHERE id = $1 AND agent_id = $2) \
+                 SELECT p.id, 1 - (p.embedding <=> s.embedding) AS similarity \
+                 FROM passages p, s \
+                 WHERE p.agent_id = $2 AND p.id <> $1 \
+                    AND p.namespace IS NOT DISTINCT FROM s.namespace \
+                    AND p.archived_at IS NULL AND p.expires_at IS NULL \
+                    AND p.embedding IS NOT NULL AND vector_norm(p.embedding) > 0 \
+                    AND NOT EXISTS (SELECT 1 FROM unnest(p.tags) t WHERE t LIKE 'group:%') \
+                 ORDER BY p.embedding <=> s.embedding \
+                 LIMIT $3",
+            )
+            .bind::<DieselUuid, _>(id)
+            .bind::<Text, _>(agent_id)
+            .bind::<Int8, _>(limit)
+            .load(conn)?;
+
+            Ok(rows
+                .into_iter()
+                .map(|row| (row.id, row.similarity))
+                .collect())
+        })
+    }
+
+    /// Search passages by vector similarity using raw SQL, optionally only
+    /// those in a namespace (or in none)
+    pub fn search_passages_by_embedding(
+        &self,
+        agent_id: &str,
+        query_embedding: &[f32],
+        limit: i64,
+        tags_filter: Option<&[String]>,
+        namespace: Option<&str>,
+    ) -> Result<Vec<(PassageRow, f64)>> {
+        self.conn.run(|conn| {
+            // No tags (or an empty list) means no tag filter
+            let tags_filter = tags_filter
+                .filter(|tags| !tags.is_empty())
+                .map(|tags| tags.to_vec());
+
+            // Use cosine distance (smaller is better, 0 = identical)
+            let rows: Vec<PassageSearchRow> = diesel::sql_query(
+                "SELECT id, agent_id, content, tags, created_at, namespace, \
+                        (embedding <=> $1) as distance \
+                 FROM passages \
+                 WHERE agent_id = $2 AND ($3::text[] IS NULL OR tags && $3) \
+                    AND ($5::text IS NULL OR namespace IS NULL OR namespace = $5) \
+                    AND archived_at IS NULL AND (expires_at IS NULL OR expires_at > now()) \
+                 ORDER BY distance \
+                 LIMIT $4",
+            )
+            .bind::<VectorType, _>(vector(query_embedding))
+            .bind::<Text, _>(agent_id)
+            .bind::<Nullable<Array<Text>>, _>(tags_filter)
+            .bind::<Int8, _>(limit)
+            .bind::<Nullable<Text>, _>(namespace)
+            .load(conn)?;
+
+            Ok(rows.into_iter().map(PassageSearchRow::split).collect())
+        })
+    }
+
+    /// Search passages by full text (`search_tsv`), any word matching, best
+    /// `ts_rank` first, with their cosine distance to `query_embedding`
+    pub fn search_passages_full_text(
+        &self,
+        agent_id: &str,
+        query: &str,
+        query_embedding: &[f32],
+        limit: i64,
+        tags_filter: Option<&[String]>,
+        namespace: Option<&str>,
+    ) -> Result<Vec<(PassageRow, f64)>> {
+        self.conn.run(|conn| {
+            let tags_filter = tags_filter
+                .filter(|tags| !tags.is_empty())
+                .map(|tags| tags.to_vec());
+
+            let rows: Vec<PassageSearchRow> = diesel::sql_query(
+                "WITH q AS (SELECT NULLIF(replace(plainto_tsquery('simple', $1)::text, '&', '|'), '')::tsquery AS query) \
+                 SELECT p.id, p.agent_id, p.content, p.tags, p.created_at, p.namespace, \
+                    CASE WHEN vector_norm(p.embedding) > 0 THEN p.embedding <=> $2 ELSE 1 END AS distance \
+                 FROM passages p, q \
+                 WHERE p.agent_id = $3 AND ($4::text[] IS NULL OR p.tags && $4) \
+                    AND ($6::text IS NULL OR p.namespace IS NULL OR p.namespace = $6) \
+                    AND p.archived_at IS NULL AND (p.expires_at IS NULL OR p.expires_at > now()) \
+                    AND p.search_tsv @@ q.query \
+                 ORDER BY ts_rank(p.search_tsv, q.query) DESC, p.created_at DESC \
+                 LIMIT $5",
+            )
+            .bind::<Text, _>(query)
+            .bind::<VectorType, _>(vector(query_embedding))
+            .bind::<Text, _>(agent_id)
+            .bind::<Nullable<Array<Text>>, _>(tags_filter)
+            .bind::<Int8, _>(limit)
+            .bind::<Nullable<Text>, _>(namespace)
+            .load(conn)?;
+
+            Ok(rows.into_iter().map(PassageSearchRow::split).collect())
+        })
+    }
+}
+
+#[derive(QueryableByName, Debug)]
+struct IdRow {
+    #[diesel(sql_type = DieselUuid)]
+    id: Uuid,
+}
+
+#[derive(QueryableByName, Debug)]
+struct SimilarPassageRow {
+    #[diesel(sql_type = DieselUuid)]
+    id: Uuid,
+    #[diesel(sql_type = Double)]
+    similarity: f64,
 }
 
 /// Helper struct for passage search results with distance
@@ -332,10 +810,51 @@ struct PassageSearchRow {
     tags: Vec<String>,
     #[diesel(sql_type = Timestamptz)]
     created_at: DateTime<Utc>,
+    #[diesel(sql_type = Nullable<Text>)]
+    namespace: Option<String>,
     #[diesel(sql_type = Double)]
     distance: f64,
 }
 
+impl PassageSearchRow {
+    fn split(self) -> (PassageRow, f64) {
+        (
+            PassageRow {
+                id: self.id,
+                agent_id: self.agent_id,
+                content: self.content,
+                tags: self.tags,
+                created_at: self.created_at,
+                namespace: self.namespace,
+            },
+            self.distance,
+        )
+    }
+}
+
+/// Passage columns as selected by the query builder
+type PassageTuple = (
+    Uuid,
+    String,
+    String,
+    Vec<String>,
+    DateTime<Utc>,
+    Option<String>,
+);
+
+impl From<PassageTuple> for PassageRow {
+    fn from((id, agent_id, content, tags, created_at, namespace): PassageTuple) -> Self {
+        Self {
+            id,
+            agent_id,
+            content,
+            tags,
+            created_at,
+            namespace,
+        }
+    }
+}
+
 // ============================================================================
 // Agent Database Operations
 // ============================================================================
@@ -357,118 +876,96 @@ pub struct AgentRow {
 
 /// Database operations for agents
 pub struct AgentDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl AgentDb {
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
     /// Get an agent by ID using raw SQL
     #[allow(dead_code)]
     pub fn get_agent(&self, agent_id: Uuid) -> Result<Option<AgentRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        // Use raw SQL to avoid Array<Uuid> type issues
-        let exists: bool = diesel::dsl::select(diesel::dsl::exists(
-            agents::table.filter(agents::id.eq(agent_id)),
-        ))
-        .get_result(&mut *conn)?;
-
-        if !exists {
-            return Ok(None);
-        }
+        self.conn.run(|conn| {
+            // Use raw SQL to avoid Array<Uuid> type issues
+            let exists: bool = diesel::dsl::select(diesel::dsl::exists(
+                agents::table.filter(agents::id.eq(agent_id)),
+            ))
+            .get_result(conn)?;
 
-        // TODO: Full implementation with raw SQL to parse message_ids array
-        // For now, return a basic agent with empty message_ids
-        Ok(None)
+            if !exists {
+                return Ok(None);
+            }
+
+            // TODO: Full implementation with raw SQL to parse message_ids array
+            // For now, return a basic agent with empty message_ids
+            Ok(None)
+        })
     }
 
     /// Create a new agent using raw SQL
     pub fn create_agent(&self, id: Uuid, name: &str, system_prompt: &str) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        diesel::sql_query(format!(
-            "INSERT INTO agents (id, name, system_prompt, llm_config) \
-             VALUES ('{}', '{}', '{}', '{{}}')",
-            id,
-            name.replace('\'', "''"),
-            system_prompt.replace('\'', "''"),
-        ))
-        .execute(&mut *conn)?;
-
-        Ok(())
+        self.conn.run(|conn| {
+            diesel::sql_query(
+                "INSERT INTO agents (id, name, system_prompt, llm_config) \
+             VALUES ($1, $2, $3, '{}')",
+            )
+            .bind::<DieselUuid, _>(id)
+            .bind::<Text, _>(name)
+            .bind::<Text, _>(system_prompt)
+            .execute(conn)?;
+
+            Ok(())
+        })
     }
 
     /// Ensure an agent exists in the database, creating it if necessary
     pub fn ensure_agent_exists(&self, id: Uuid, name: &str) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        // Check if agent exists
-        let exists: bool =
-            diesel::dsl::select(diesel::dsl::exists(agents::table.filter(agents::id.eq(id))))
-                .get_result(&mut *conn)?;
-
-        if !exists {
-            // Create the agent with minimal data
-            diesel::sql_query(format!(
-                "INSERT INTO agents (id, name, system_prompt, llm_config) \
-                 VALUES ('{}', '{}', '', '{{}}')",
-                id,
-                name.replace('\'', "''"),
-            ))
-            .execute(&mut *conn)?;
-            tracing::info!("Created agent {} in database", id);
-        }
+        self.conn.run(|conn| {
+            // Check if agent exists
+            let exists: bool =
+                diesel::dsl::select(diesel::dsl::exists(agents::table.filter(agents::id.eq(id))))
+                    .get_result(conn)?;
+
+            if !exists {
+                // Create the agent with minimal data
+                diesel::sql_query(
+                    "INSERT INTO agents (id, name, system_prompt, llm_config) \
+                 VALUES ($1, $2, '', '{}')",
+                )
+                .bind::<DieselUuid, _>(id)
+                .bind::<Text, _>(name)
+                .execute(conn)?;
+                tracing::info!("Created agent {} in database", id);
+            }
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Update agent's message_ids using raw SQL
     pub fn update_message_ids(&self, agent_id: Uuid, message_ids: &[Uuid]) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let ids_str = message_ids
-            .iter()
-            .map(|id| format!("'{}'", id))
-            .collect::<Vec<_>>()
-            .join(",");
-
-        diesel::sql_query(format!(
-            "UPDATE agents SET message_ids = ARRAY[{}]::uuid[] WHERE id = '{}'",
-            ids_str, agent_id
-        ))
-        .execute(&mut *conn)?;
+        self.conn.run(|conn| {
+            diesel::sql_query("UPDATE agents SET message_ids = $1 WHERE id = $2")
+                .bind::<Array<DieselUuid>, _>(message_ids)
+                .bind::<DieselUuid, _>(agent_id)
+                .execute(conn)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Update agent's last memory update timestamp
     pub fn update_last_memory_update(&self, agent_id: Uuid) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        diesel::update(agents::table)
-            .filter(agents::id.eq(agent_id))
-            .set(agents::last_memory_update.eq(Some(Utc::now())))
-            .execute(&mut *conn)?;
+        self.conn.run(|conn| {
+            diesel::update(agents::table)
+                .filter(agents::id.eq(agent_id))
+                .set(agents::last_memory_update.eq(Some(Utc::now())))
+                .execute(conn)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 }
 
@@ -489,6 +986,8 @@ pub struct MessageRow {
     pub tool_results: Option<serde_json::Value>,
     pub created_at: DateTime<Utc>,
     pub attachment_text: Option<String>,
+    /// Kept out of compaction (`pin_message`, `/pin`)
+    pub pinned: bool,
 }
 
 /// Message search result with similarity score
@@ -498,13 +997,63 @@ pub struct MessageSearchResult {
     pub distance: f64, // Cosine distance (smaller = more similar)
 }
 
+/// Helper struct for message search results with distance
+#[derive(QueryableByName, Debug)]
+struct MessageSearchRow {
+    #[diesel(sql_type = DieselUuid)]
+    id: Uuid,
+    #[diesel(sql_type = DieselUuid)]
+    agent_id: Uuid,
+    #[diesel(sql_type = Text)]
+    user_id: String,
+    #[diesel(sql_type = Text)]
+    role: String,
+    #[diesel(sql_type = Text)]
+    content: String,
+    #[diesel(sql_type = Int8)]
+    sequence_id: i64,
+    #[diesel(sql_type = Nullable<Jsonb>)]
+    tool_calls: Option<serde_json::Value>,
+    #[diesel(sql_type = Nullable<Jsonb>)]
+    tool_results: Option<serde_json::Value>,
+    #[diesel(sql_type = Timestamptz)]
+    created_at: DateTime<Utc>,
+    #[diesel(sql_type = Nullable<Text>)]
+    attachment_text: Option<String>,
+    #[diesel(sql_type = Bool)]
+    pinned: bool,
+    #[diesel(sql_type = Double)]
+    distance: f64,
+}
+
+impl From<MessageSearchRow> for MessageSearchResult {
+    fn from(row: MessageSearchRow) -> Self {
+        Self {
+            message: MessageRow {
+                id: row.id,
+                agent_id: row.agent_id,
+                user_id: row.user_id,
+                role: row.role,
+                content: row.content,
+                sequence_id: row.sequence_id,
+                tool_calls: row.tool_calls,
+                tool_results: row.tool_results,
+                created_at: row.created_at,
+                attachment_text: row.attachment_text,
+                pinned: row.pinned,
+            },
+            distance: row.distance,
+        }
+    }
+}
+
 /// Database operations for messages (recall memory)
 pub struct MessageDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl MessageDb {
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
@@ -521,48 +1070,52 @@ impl MessageDb {
         tool_results: Option<&serde_json::Value>,
         attachment_text: Option<&str>,
     ) -> Result<Uuid> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let id = Uuid::new_v4();
-        let embedding_str = format!(
-            "[{}]",
-            embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-
-        let tool_calls_str = tool_calls
-            .map(|v| v.to_string())
-            .unwrap_or_else(|| "null".to_string());
-        let tool_results_str = tool_results
-            .map(|v| v.to_string())
-            .unwrap_or_else(|| "null".to_string());
-
-        let attachment_text_str = attachment_text
-            .map(|t| format!("'{}'", t.replace('\'', "''")))
-            .unwrap_or_else(|| "NULL".to_string());
-
-        diesel::sql_query(format!(
-            "INSERT INTO messages (id, agent_id, user_id, role, content, embedding, tool_calls, tool_results, attachment_text) \
-             VALUES ('{}', '{}', '{}', '{}', '{}', '{}', '{}', '{}', {})",
-            id,
-            agent_id,
-            user_id.replace('\'', "''"),
-            role.replace('\'', "''"),
-            content.replace('\'', "''"),
-            embedding_str,
-            tool_calls_str.replace('\'', "''"),
-            tool_results_str.replace('\'', "''"),
-            attachment_text_str,
-        ))
-        .execute(&mut *conn)?;
+        self.conn.run(|conn| {
+            let id = Uuid::new_v4();
+            diesel::sql_query(
+                "INSERT INTO messages (id, agent_id, user_id, role, content, embedding, tool_calls, tool_results, attachment_text, script) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .bind::<DieselUuid, _>(id)
+            .bind::<DieselUuid, _>(agent_id)
+            .bind::<Text, _>(user_id)
+            .bind::<Text, _>(role)
+            .bind::<Text, _>(content)
+            .bind::<VectorType, _>(vector(embedding))
+            .bind::<Nullable<Jsonb>, _>(tool_calls)
+            .bind::<Nullable<Jsonb>, _>(tool_results)
+            .bind::<Nullable<Text>, _>(attachment_text)
+            .bind::<Nullable<Text>, _>(super::language::detect(content))
+            .execute(conn)?;
+
+            Ok(id)
+        })
+    }
 
-        Ok(id)
+    /// The script most of the agent's recent user messages are in (see
+    /// `language`)
+    pub fn script_of_record(&self, agent_id: Uuid) -> Result<Option<String>> {
+        self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            let recent: Vec<String> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .filter(messages::role.eq("user"))
+                .filter(messages::script.is_not_null())
+                .order(messages::sequence_id.desc())
+                .limit(SCRIPT_OF_RECORD_MESSAGES)
+                .select(messages::script.assume_not_null())
+                .load(conn)?;
+
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            for script in recent {
+                *counts.entry(script).or_default() += 1;
+            }
+            Ok(counts
+                .into_iter()
+                .max_by_key(|(_, n)| *n)
+                .map(|(script, _)| script))
+        })
     }
 
     /// Get messages by IDs (for loading context window)
@@ -571,201 +1124,321 @@ impl MessageDb {
             return Ok(Vec::new());
         }
 
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        use crate::schema::messages;
-
-        #[derive(Queryable)]
-        struct RawMessage {
-            id: Uuid,
-            agent_id: Uuid,
-            user_id: String,
-            role: String,
-            content: String,
-            sequence_id: i64,
-            tool_calls: Option<serde_json::Value>,
-            tool_results: Option<serde_json::Value>,
-            created_at: DateTime<Utc>,
-            attachment_text: Option<String>,
-        }
+        self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            #[derive(Queryable)]
+            struct RawMessage {
+                id: Uuid,
+                agent_id: Uuid,
+                user_id: String,
+                role: String,
+                content: String,
+                sequence_id: i64,
+                tool_calls: Option<serde_json::Value>,
+                tool_results: Option<serde_json::Value>,
+                created_at: DateTime<Utc>,
+                attachment_text: Option<String>,
+                pinned: bool,
+            }
 
-        let results: Vec<RawMessage> = messages::table
-            .filter(messages::id.eq_any(ids))
-            .order(messages::sequence_id.asc())
-            .select((
-                messages::id,
-                messages::agent_id,
-                messages::user_id,
-                messages::role,
-                messages::content,
-                messages::sequence_id,
-                messages::tool_calls,
-                messages::tool_results,
-                messages::created_at,
-                messages::attachment_text,
-            ))
-            .load(&mut *conn)?;
-
-        Ok(results
-            .into_iter()
-            .map(|r| MessageRow {
-                id: r.id,
-                agent_id: r.agent_id,
-                user_id: r.user_id,
-                role: r.role,
-                content: r.content,
-                sequence_id: r.sequence_id,
-                tool_calls: r.tool_calls,
-                tool_results: r.tool_results,
-                created_at: r.created_at,
-                attachment_text: r.attachment_text,
+            let results: Vec<RawMessage> = messages::table
+                .filter(messages::id.eq_any(ids))
+                .order(messages::sequence_id.asc())
+                .select((
+                    messages::id,
+                    messages::agent_id,
+                    messages::user_id,
+                    messages::role,
+                    messages::content,
+                    messages::sequence_id,
+                    messages::tool_calls,
+                    messages::tool_results,
+                    messages::created_at,
+                    messages::attachment_text,
+                    messages::pinned,
+                ))
+                .load(conn)?;
+
+            Ok(results
+                .into_iter()
+                .map(|r| MessageRow {
+                    id: r.id,
+                    agent_id: r.agent_id,
+                    user_id: r.user_id,
+                    role: r.role,
+                    content: r.content,
+                    sequence_id: r.sequence_id,
+                    tool_calls: r.tool_calls,
+                    tool_results: r.tool_results,
+                    created_at: r.created_at,
+                    attachment_text: r.attachment_text,
+                    pinned: r.pinned,
+                })
+                .collect())
+        })
+    }
+
+    /// Store the embedded chunks of a long message, replacing any it had
+    pub fn replace_chunks(
+        &self,
+        message_id: Uuid,
+        agent_id: Uuid,
+        chunks: &[(String, Vec<f32>)],
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            conn.transaction(|conn| {
+                diesel::sql_query("DELETE FROM message_chunks WHERE message_id = $1")
+                    .bind::<DieselUuid, _>(message_id)
+                    .execute(conn)?;
+                for (index, (content, embedding)) in chunks.iter().enumerate() {
+                    diesel::sql_query(
+                        "INSERT INTO message_chunks (message_id, agent_id, chunk_index, content, embedding) \
+                         VALUES ($1, $2, $3, $4, $5)",
+                    )
+                    .bind::<DieselUuid, _>(message_id)
+                    .bind::<DieselUuid, _>(agent_id)
+                    .bind::<Int4, _>(index as i32)
+                    .bind::<Text, _>(content)
+                    .bind::<VectorType, _>(vector(embedding))
+                    .execute(conn)?;
+                }
+                Ok(())
             })
-            .collect())
+        })
     }
 
-    /// Search messages by vector similarity
+    /// Search messages by vector similarity. Long messages also match by
+    /// their chunks; a message ranks by its closest embedding.
     pub fn search_by_embedding(
         &self,
         agent_id: Uuid,
         query_embedding: &[f32],
         limit: i64,
     ) -> Result<Vec<MessageSearchResult>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let embedding_str = format!(
-            "[{}]",
-            query_embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-
-        // Raw SQL for pgvector cosine distance search
-        let query = format!(
-            "SELECT id, agent_id, user_id, role, content, sequence_id, \
-                    tool_calls, tool_results, created_at, \
-                    (embedding <=> '{}') as distance \
-             FROM messages \
-             WHERE agent_id = '{}' AND embedding IS NOT NULL \
-             ORDER BY distance \
-             LIMIT {}",
-            embedding_str, agent_id, limit
-        );
+        self.conn.run(|conn| {
+            // Zero vectors (embedding failed, backfill pending) have no
+            // cosine distance
+            let results: Vec<MessageSearchRow> = diesel::sql_query(
+                "WITH hits AS ( \
+                    SELECT id AS message_id, embedding <=> $1 AS distance \
+                    FROM messages \
+                    WHERE agent_id = $2 AND embedding IS NOT NULL AND vector_norm(embedding) > 0 \
+                    UNION ALL \
+                    SELECT message_id, embedding <=> $1 \
+                    FROM message_chunks \
+                    WHERE agent_id = $2 AND vector_norm(embedding) > 0 \
+                 ), best AS ( \
+                    SELECT message_id, MIN(distance) AS distance FROM hits GROUP BY message_id \
+                 ) \
+                 SELECT m.id, m.agent_id, m.user_id, m.role, m.content, m.sequence_id, \
+                    m.tool_calls, m.tool_results, m.created_at, m.attachment_text, m.pinned, \
+                    best.distance \
+                 FROM best JOIN messages m ON m.id = best.message_id \
+                 ORDER BY best.distance \
+                 LIMIT $3",
+            )
+            .bind::<VectorType, _>(vector(query_embedding))
+            .bind::<DieselUuid, _>(agent_id)
+            .bind::<Int8, _>(limit)
+            .load(conn)?;
+
+            Ok(results.into_iter().map(MessageSearchResult::from).collect())
+        })
+    }
+
+    /// Search messages by full text (`search_tsv`), any word matching,
+    /// best `ts_rank` first. `distance` is still the cosine distance to
+    /// `query_embedding` (1 for messages not embedded yet).
+    pub fn search_full_text(
+        &self,
+        agent_id: Uuid,
+        query: &str,
+        query_embedding: &[f32],
+        limit: i64,
+    ) -> Result<Vec<MessageSearchResult>> {
+        self.conn.run(|conn| {
+            let results: Vec<MessageSearchRow> = diesel::sql_query(
+                "WITH q AS (SELECT NULLIF(replace(plainto_tsquery('simple', $1)::text, '&', '|'), '')::tsquery AS query) \
+                 SELECT m.id, m.agent_id, m.user_id, m.role, m.content, m.sequence_id, \
+                    m.tool_calls, m.tool_results, m.created_at, m.attachment_text, m.pinned, \
+                    CASE WHEN vector_norm(m.embedding) > 0 THEN m.embedding <=> $2 ELSE 1 END AS distance \
+                 FROM messages m, q \
+                 WHERE m.agent_id = $3 AND m.search_tsv @@ q.query \
+                 ORDER BY ts_rank(m.search_tsv, q.query) DESC, m.sequence_id DESC \
+                 LIMIT $4",
+            )
+            .bind::<Text, _>(query)
+            .bind::<VectorType, _>(vector(query_embedding))
+            .bind::<DieselUuid, _>(agent_id)
+            .bind::<Int8, _>(limit)
+            .load(conn)?;
+
+            Ok(results.into_iter().map(MessageSearchResult::from).collect())
+        })
+    }
+
+    /// Delete an agent's user and assistant messages containing all the
+    /// words of `query`. Returns how many.
+    pub fn delete_messages_matching(&self, agent_id: Uuid, query: &str) -> Result<usize> {
+        self.conn.run(|conn| {
+            let deleted = diesel::sql_query(
+                "DELETE FROM messages \
+                 WHERE agent_id = $1 AND role IN ('user', 'assistant') \
+                    AND search_tsv @@ plainto_tsquery('simple', $2)",
+            )
+            .bind::<DieselUuid, _>(agent_id)
+            .bind::<Text, _>(query)
+            .execute(conn)?;
+
+            Ok(deleted)
+        })
+    }
+
+    /// Pin or unpin one of an agent's messages. Returns whether it exists.
+    pub fn set_pinned(&self, agent_id: Uuid, message_id: Uuid, pinned: bool) -> Result<bool> {
+        self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            let updated = diesel::update(
+                messages::table
+                    .filter(messages::id.eq(message_id))
+                    .filter(messages::agent_id.eq(agent_id)),
+            )
+            .set(messages::pinned.eq(pinned))
+            .execute(conn)?;
 
-        // TODO: Execute raw query and parse results
-        // For now, return empty - need custom result parsing for pgvector
-        let _ = query;
-        let _ = &mut *conn;
-        Ok(Vec::new())
+            Ok(updated > 0)
+        })
+    }
+
+    /// An agent's pinned messages, oldest first
+    pub fn pinned(&self, agent_id: Uuid) -> Result<Vec<MessageRow>> {
+        let ids: Vec<Uuid> = self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            Ok(messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .filter(messages::pinned.eq(true))
+                .select(messages::id)
+                .load(conn)?)
+        })?;
+        self.get_by_ids(&ids)
+    }
+
+    /// The latest of an agent's user and assistant messages containing
+    /// `text` (case-insensitive), or the latest user message when `text` is
+    /// None
+    pub fn find_latest(&self, agent_id: Uuid, text: Option<&str>) -> Result<Option<MessageRow>> {
+        let id: Option<Uuid> = self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            let mut query = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .into_boxed();
+            query = match text {
+                Some(text) => query
+                    .filter(messages::role.eq_any(["user", "assistant"]))
+                    .filter(messages::content.ilike(super::provenance::like_pattern(text))),
+                None => query.filter(messages::role.eq("user")),
+            };
+            Ok(query
+                .order(messages::sequence_id.desc())
+                .select(messages::id)
+                .first(conn)
+                .optional()?)
+        })?;
+        match id {
+            Some(id) => Ok(self.get_by_ids(&[id])?.pop()),
+            None => Ok(None),
+        }
     }
 
     /// Count messages for an agent
     pub fn count_messages(&self, agent_id: Uuid) -> Result<i64> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
+        self.conn.run(|conn| {
+            use crate::schema::messages;
 
-        use crate::schema::messages;
+            let count: i64 = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .count()
+                .get_result(conn)?;
 
-        let count: i64 = messages::table
-            .filter(messages::agent_id.eq(agent_id))
-            .count()
-            .get_result(&mut *conn)?;
-
-        Ok(count)
+            Ok(count)
+        })
     }
 
     /// Get recent messages for an agent
     pub fn get_recent(&self, agent_id: Uuid, limit: i64) -> Result<Vec<MessageRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        use crate::schema::messages;
-
-        #[derive(Queryable)]
-        struct RawMessage {
-            id: Uuid,
-            agent_id: Uuid,
-            user_id: String,
-            role: String,
-            content: String,
-            sequence_id: i64,
-            tool_calls: Option<serde_json::Value>,
-            tool_results: Option<serde_json::Value>,
-            created_at: DateTime<Utc>,
-            attachment_text: Option<String>,
-        }
+        self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            #[derive(Queryable)]
+            struct RawMessage {
+                id: Uuid,
+                agent_id: Uuid,
+                user_id: String,
+                role: String,
+                content: String,
+                sequence_id: i64,
+                tool_calls: Option<serde_json::Value>,
+                tool_results: Option<serde_json::Value>,
+                created_at: DateTime<Utc>,
+                attachment_text: Option<String>,
+                pinned: bool,
+            }
 
-        let mut results: Vec<RawMessage> = messages::table
-            .filter(messages::agent_id.eq(agent_id))
-            .order(messages::sequence_id.desc())
-            .limit(limit)
-            .select((
-                messages::id,
-                messages::agent_id,
-                messages::user_id,
-                messages::role,
-                messages::content,
-                messages::sequence_id,
-                messages::tool_calls,
-                messages::tool_results,
-                messages::created_at,
-                messages::attachment_text,
-            ))
-            .load(&mut *conn)?;
-
-        results.reverse(); // Chronological order
-
-        Ok(results
-            .into_iter()
-            .map(|r| MessageRow {
-                id: r.id,
-                agent_id: r.agent_id,
-                user_id: r.user_id,
-                role: r.role,
-                content: r.content,
-                sequence_id: r.sequence_id,
-                tool_calls: r.tool_calls,
-                tool_results: r.tool_results,
-                created_at: r.created_at,
-                attachment_text: r.attachment_text,
-            })
-            .collect())
+            let mut results: Vec<RawMessage> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .order(messages::sequence_id.desc())
+                .limit(limit)
+                .select((
+                    messages::id,
+                    messages::agent_id,
+                    messages::user_id,
+                    messages::role,
+                    messages::content,
+                    messages::sequence_id,
+                    messages::tool_calls,
+                    messages::tool_results,
+                    messages::created_at,
+                    messages::attachment_text,
+                    messages::pinned,
+                ))
+                .load(conn)?;
+
+            results.reverse(); // Chronological order
+
+            Ok(results
+                .into_iter()
+                .map(|r| MessageRow {
+                    id: r.id,
+                    agent_id: r.agent_id,
+                    user_id: r.user_id,
+                    role: r.role,
+                    content: r.content,
+                    sequence_id: r.sequence_id,
+                    tool_calls: r.tool_calls,
+                    tool_results: r.tool_results,
+                    created_at: r.created_at,
+                    attachment_text: r.attachment_text,
+                    pinned: r.pinned,
+                })
+                .collect())
+        })
     }
 
     /// Update embedding for an existing message (for background processing)
+    ///
+    /// Deferred while the database is down - a late backfill is harmless.
     pub fn update_embedding(&self, message_id: Uuid, embedding: &[f32]) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let embedding_str = format!(
-            "[{}]",
-            embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-
-        diesel::sql_query(format!(
-            "UPDATE messages SET embedding = '{}' WHERE id = '{}'",
-            embedding_str, message_id,
-        ))
-        .execute(&mut *conn)?;
-
-        Ok(())
+        let embedding = vector(embedding);
+        self.conn.execute_or_defer("update_embedding", move |conn| {
+            diesel::sql_query("UPDATE messages SET embedding = $1 WHERE id = $2")
+                .bind::<VectorType, _>(embedding)
+                .bind::<DieselUuid, _>(message_id)
+                .execute(conn)
+        })
     }
 }
 
@@ -783,6 +1456,73 @@ pub struct SummaryRow {
     pub content: String,
     pub previous_summary_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
+    /// 1 for compaction summaries, one more per rollup
+    pub level: i32,
+    /// The higher-level summary that absorbed this one
+    pub rolled_into: Option<Uuid>,
+}
+
+/// Columns selected for a `SummaryRow`
+type SummaryColumns = (
+    summaries::id,
+    summaries::agent_id,
+    summaries::from_sequence_id,
+    summaries::to_sequence_id,
+    summaries::content,
+    summaries::previous_summary_id,
+    summaries::created_at,
+    summaries::level,
+    summaries::rolled_into,
+);
+
+const SUMMARY_COLUMNS: SummaryColumns = (
+    summaries::id,
+    summaries::agent_id,
+    summaries::from_sequence_id,
+    summaries::to_sequence_id,
+    summaries::content,
+    summaries::previous_summary_id,
+    summaries::created_at,
+    summaries::level,
+    summaries::rolled_into,
+);
+
+type RawSummary = (
+    Uuid,
+    Uuid,
+    i64,
+    i64,
+    String,
+    Option<Uuid>,
+    DateTime<Utc>,
+    i32,
+    Option<Uuid>,
+);
+
+fn summary_row(
+    (
+        id,
+        agent_id,
+        from_sequence_id,
+        to_sequence_id,
+        content,
+        previous_summary_id,
+        created_at,
+        level,
+        rolled_into,
+    ): RawSummary,
+) -> SummaryRow {
+    SummaryRow {
+        id,
+        agent_id,
+        from_sequence_id,
+        to_sequence_id,
+        content,
+        previous_summary_id,
+        created_at,
+        level,
+        rolled_into,
+    }
 }
 
 /// Summary search result with similarity score
@@ -799,27 +1539,31 @@ struct SummarySearchRow {
     id: Uuid,
     #[diesel(sql_type = DieselUuid)]
     agent_id: Uuid,
-    #[diesel(sql_type = diesel::sql_types::Int8)]
+    #[diesel(sql_type = Int8)]
     from_sequence_id: i64,
-    #[diesel(sql_type = diesel::sql_types::Int8)]
+    #[diesel(sql_type = Int8)]
     to_sequence_id: i64,
     #[diesel(sql_type = Text)]
     content: String,
-    #[diesel(sql_type = diesel::sql_types::Nullable<DieselUuid>)]
+    #[diesel(sql_type = Nullable<DieselUuid>)]
     previous_summary_id: Option<Uuid>,
     #[diesel(sql_type = Timestamptz)]
     created_at: DateTime<Utc>,
+    #[diesel(sql_type = Int4)]
+    level: i32,
+    #[diesel(sql_type = Nullable<DieselUuid>)]
+    rolled_into: Option<Uuid>,
     #[diesel(sql_type = Double)]
     distance: f64,
 }
 
 /// Database operations for summaries
 pub struct SummaryDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl SummaryDb {
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
@@ -833,83 +1577,128 @@ impl SummaryDb {
         embedding: &[f32],
         previous_summary_id: Option<Uuid>,
     ) -> Result<Uuid> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let id = Uuid::new_v4();
-        let embedding_str = format!(
-            "[{}]",
-            embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-
-        let prev_id_str = previous_summary_id
-            .map(|id| format!("'{}'", id))
-            .unwrap_or_else(|| "NULL".to_string());
-
-        diesel::sql_query(format!(
-            "INSERT INTO summaries (id, agent_id, from_sequence_id, to_sequence_id, content, embedding, previous_summary_id) \
-             VALUES ('{}', '{}', {}, {}, '{}', '{}', {})",
-            id,
-            agent_id,
-            from_sequence_id,
-            to_sequence_id,
-            content.replace('\'', "''"),
-            embedding_str,
-            prev_id_str,
-        ))
-        .execute(&mut *conn)?;
-
-        Ok(id)
+        self.conn.run(|conn| {
+            let id = Uuid::new_v4();
+            diesel::sql_query(
+                "INSERT INTO summaries (id, agent_id, from_sequence_id, to_sequence_id, content, embedding, previous_summary_id) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind::<DieselUuid, _>(id)
+            .bind::<DieselUuid, _>(agent_id)
+            .bind::<Int8, _>(from_sequence_id)
+            .bind::<Int8, _>(to_sequence_id)
+            .bind::<Text, _>(content)
+            .bind::<VectorType, _>(vector(embedding))
+            .bind::<Nullable<DieselUuid>, _>(previous_summary_id)
+            .execute(conn)?;
+
+            Ok(id)
+        })
     }
 
     /// Get the latest summary for an agent (highest to_sequence_id)
     pub fn get_latest(&self, agent_id: Uuid) -> Result<Option<SummaryRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        #[derive(Queryable)]
-        struct RawSummary {
-            id: Uuid,
-            agent_id: Uuid,
-            from_sequence_id: i64,
-            to_sequence_id: i64,
-            content: String,
-            previous_summary_id: Option<Uuid>,
-            created_at: DateTime<Utc>,
-        }
+        self.conn.run(|conn| {
+            let result: Option<RawSummary> = summaries::table
+                .filter(summaries::agent_id.eq(agent_id))
+                .order((summaries::to_sequence_id.desc(), summaries::level.asc()))
+                .select(SUMMARY_COLUMNS)
+                .first(conn)
+                .optional()?;
+
+            Ok(result.map(summary_row))
+        })
+    }
 
-        let result: Option<RawSummary> = summaries::table
-            .filter(summaries::agent_id.eq(agent_id))
-            .order(summaries::to_sequence_id.desc())
-            .select((
-                summaries::id,
-                summaries::agent_id,
-                summaries::from_sequence_id,
-                summaries::to_sequence_id,
-                summaries::content,
-                summaries::previous_summary_id,
-                summaries::created_at,
-            ))
-            .first(&mut *conn)
-            .optional()?;
+    /// All of an agent's summaries, oldest first
+    pub fn all_summaries(&self, agent_id: Uuid) -> Result<Vec<SummaryRow>> {
+        self.conn.run(|conn| {
+            let rows = summaries::table
+                .filter(summaries::agent_id.eq(agent_id))
+                .order((summaries::to_sequence_id.asc(), summaries::created_at.asc()))
+                .select(SUMMARY_COLUMNS)
+                .load::<RawSummary>(conn)?;
+
+            Ok(rows.into_iter().map(summary_row).collect())
+        })
+    }
+
+    /// Summaries no rollup has absorbed yet, oldest first. Together they
+    /// cover the agent's summarized history.
+    pub fn unrolled(&self, agent_id: Uuid) -> Result<Vec<SummaryRow>> {
+        self.conn.run(|conn| {
+            let rows = summaries::table
+                .filter(summaries::agent_id.eq(agent_id))
+                .filter(summaries::rolled_into.is_null())
+                .order((
+                    summaries::to_sequence_id.asc(),
+                    summaries::level.desc(),
+                    summaries::created_at.asc(),
+                ))
+                .select(SUMMARY_COLUMNS)
+                .load::<RawSummary>(conn)?;
+
+            Ok(rows.into_iter().map(summary_row).collect())
+        })
+    }
+
+    /// Store a rollup of `children` at `level` and mark them as absorbed
+    /// by it, in one transaction
+    pub fn insert_rollup(
+        &self,
+        agent_id: Uuid,
+        level: i32,
+        content: &str,
+        embedding: &[f32],
+        children: &[SummaryRow],
+    ) -> Result<Uuid> {
+        let (Some(first), Some(last)) = (children.first(), children.last()) else {
+            anyhow::bail!("A rollup needs at least one summary");
+        };
+        let ids: Vec<Uuid> = children.iter().map(|c| c.id).collect();
+        self.conn.run(|conn| {
+            conn.transaction(|conn| {
+                let id = Uuid::new_v4();
+                diesel::sql_query(
+                    "INSERT INTO summaries (id, agent_id, from_sequence_id, to_sequence_id, content, embedding, level) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                )
+                .bind::<DieselUuid, _>(id)
+                .bind::<DieselUuid, _>(agent_id)
+                .bind::<Int8, _>(first.from_sequence_id)
+                .bind::<Int8, _>(last.to_sequence_id)
+                .bind::<Text, _>(content)
+                .bind::<VectorType, _>(vector(embedding))
+                .bind::<Int4, _>(level)
+                .execute(conn)?;
+                diesel::update(summaries::table.filter(summaries::id.eq_any(&ids)))
+                    .set(summaries::rolled_into.eq(id))
+                    .execute(conn)?;
+                Ok(id)
+            })
+        })
+    }
+
+    /// Mark summaries as absorbed by `into`
+    pub fn mark_rolled(&self, ids: &[Uuid], into: Uuid) -> Result<usize> {
+        self.conn.run(|conn| {
+            let updated = diesel::update(summaries::table.filter(summaries::id.eq_any(ids)))
+                .set(summaries::rolled_into.eq(into))
+                .execute(conn)?;
+            Ok(updated)
+        })
+    }
 
-        Ok(result.map(|r| SummaryRow {
-            id: r.id,
-            agent_id: r.agent_id,
-            from_sequence_id: r.from_sequence_id,
-            to_sequence_id: r.to_sequence_id,
-            content: r.content,
-            previous_summary_id: r.previous_summary_id,
-            created_at: r.created_at,
-        }))
+    /// Lowest sequence id any of an agent's summaries starts at
+    pub fn min_from_sequence_id(&self, agent_id: Uuid) -> Result<Option<i64>> {
+        self.conn.run(|conn| {
+            let min = summaries::table
+                .filter(summaries::agent_id.eq(agent_id))
+                .select(diesel::dsl::min(summaries::from_sequence_id))
+                .first::<Option<i64>>(conn)?;
+
+            Ok(min)
+        })
     }
 
     /// Search summaries by vector similarity
@@ -919,48 +1708,39 @@ impl SummaryDb {
         query_embedding: &[f32],
         limit: i64,
     ) -> Result<Vec<SummarySearchResult>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let embedding_str = format!(
-            "[{}]",
-            query_embedding
-                .iter()
-                .map(|f| f.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        );
-
-        let query = format!(
-            "SELECT id, agent_id, from_sequence_id, to_sequence_id, content, \
-                    previous_summary_id, created_at, \
-                    (embedding <=> '{}') as distance \
+        self.conn.run(|conn| {
+            let results: Vec<SummarySearchRow> = diesel::sql_query(
+                "SELECT id, agent_id, from_sequence_id, to_sequence_id, content, \
+                    previous_summary_id, created_at, level, rolled_into, \
+                    (embedding <=> $1) as distance \
              FROM summaries \
-             WHERE agent_id = '{}' AND embedding IS NOT NULL \
+             WHERE agent_id = $2 AND embedding IS NOT NULL \
              ORDER BY distance \
-             LIMIT {}",
-            embedding_str, agent_id, limit
-        );
-
-        let results: Vec<SummarySearchRow> = diesel::sql_query(&query).load(&mut *conn)?;
-
-        Ok(results
-            .into_iter()
-            .map(|row| SummarySearchResult {
-                summary: SummaryRow {
-                    id: row.id,
-                    agent_id: row.agent_id,
-                    from_sequence_id: row.from_sequence_id,
-                    to_sequence_id: row.to_sequence_id,
-                    content: row.content,
-                    previous_summary_id: row.previous_summary_id,
-                    created_at: row.created_at,
-                },
-                distance: row.distance,
-            })
-            .collect())
+             LIMIT $3",
+            )
+            .bind::<VectorType, _>(vector(query_embedding))
+            .bind::<DieselUuid, _>(agent_id)
+            .bind::<Int8, _>(limit)
+            .load(conn)?;
+
+            Ok(results
+                .into_iter()
+                .map(|row| SummarySearchResult {
+                    summary: SummaryRow {
+                        id: row.id,
+                        agent_id: row.agent_id,
+                        from_sequence_id: row.from_sequence_id,
+                        to_sequence_id: row.to_sequence_id,
+                        content: row.content,
+                        previous_summary_id: row.previous_summary_id,
+                        created_at: row.created_at,
+                        level: row.level,
+                        rolled_into: row.rolled_into,
+                    },
+                    distance: row.distance,
+                })
+                .collect())
+        })
     }
 
     /// Get messages after a specific sequence ID (for loading context after summary)
@@ -970,79 +1750,76 @@ impl SummaryDb {
         after_sequence_id: i64,
         limit: i64,
     ) -> Result<Vec<MessageRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        use crate::schema::messages;
-
-        #[derive(Queryable)]
-        struct RawMessage {
-            id: Uuid,
-            agent_id: Uuid,
-            user_id: String,
-            role: String,
-            content: String,
-            sequence_id: i64,
-            tool_calls: Option<serde_json::Value>,
-            tool_results: Option<serde_json::Value>,
-            created_at: DateTime<Utc>,
-            attachment_text: Option<String>,
-        }
+        self.conn.run(|conn| {
+            use crate::schema::messages;
+
+            #[derive(Queryable)]
+            struct RawMessage {
+                id: Uuid,
+                agent_id: Uuid,
+                user_id: String,
+                role: String,
+                content: String,
+                sequence_id: i64,
+                tool_calls: Option<serde_json::Value>,
+                tool_results: Option<serde_json::Value>,
+                created_at: DateTime<Utc>,
+                attachment_text: Option<String>,
+                pinned: bool,
+            }
 
-        let results: Vec<RawMessage> = messages::table
-            .filter(messages::agent_id.eq(agent_id))
-            .filter(messages::sequence_id.gt(after_sequence_id))
-            .order(messages::sequence_id.asc())
-            .limit(limit)
-            .select((
-                messages::id,
-                messages::agent_id,
-                messages::user_id,
-                messages::role,
-                messages::content,
-                messages::sequence_id,
-                messages::tool_calls,
-                messages::tool_results,
-                messages::created_at,
-                messages::attachment_text,
-            ))
-            .load(&mut *conn)?;
-
-        Ok(results
-            .into_iter()
-            .map(|r| MessageRow {
-                id: r.id,
-                agent_id: r.agent_id,
-                user_id: r.user_id,
-                role: r.role,
-                content: r.content,
-                sequence_id: r.sequence_id,
-                tool_calls: r.tool_calls,
-                tool_results: r.tool_results,
-                created_at: r.created_at,
-                attachment_text: r.attachment_text,
-            })
-            .collect())
+            let results: Vec<RawMessage> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .filter(messages::sequence_id.gt(after_sequence_id))
+                .order(messages::sequence_id.asc())
+                .limit(limit)
+                .select((
+                    messages::id,
+                    messages::agent_id,
+                    messages::user_id,
+                    messages::role,
+                    messages::content,
+                    messages::sequence_id,
+                    messages::tool_calls,
+                    messages::tool_results,
+                    messages::created_at,
+                    messages::attachment_text,
+                    messages::pinned,
+                ))
+                .load(conn)?;
+
+            Ok(results
+                .into_iter()
+                .map(|r| MessageRow {
+                    id: r.id,
+                    agent_id: r.agent_id,
+                    user_id: r.user_id,
+                    role: r.role,
+                    content: r.content,
+                    sequence_id: r.sequence_id,
+                    tool_calls: r.tool_calls,
+                    tool_results: r.tool_results,
+                    created_at: r.created_at,
+                    attachment_text: r.attachment_text,
+                    pinned: r.pinned,
+                })
+                .collect())
+        })
     }
 
     /// Get the maximum sequence_id for an agent's messages
     pub fn get_max_sequence_id(&self, agent_id: Uuid) -> Result<Option<i64>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        use crate::schema::messages;
-        use diesel::dsl::max;
+        self.conn.run(|conn| {
+            use crate::schema::messages;
+            use diesel::dsl::max;
 
-        let result: Option<i64> = messages::table
-            .filter(messages::agent_id.eq(agent_id))
-            .select(max(messages::sequence_id))
-            .first(&mut *conn)?;
+            let result: Option<i64> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .select(max(messages::sequence_id))
+                .first(conn)?;
 
-        Ok(result)
+            Ok(result)
+        })
     }
 }
 
@@ -1084,11 +1861,11 @@ pub struct NewPreference<'a> {
 
 /// Database operations for user preferences
 pub struct PreferenceDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl PreferenceDb {
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
@@ -1139,79 +1916,561 @@ impl PreferenceDb {
         // Validate known keys
         Self::validate(key, value)?;
 
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let now = Utc::now();
+        self.conn.run(|conn| {
+            let now = Utc::now();
 
-        // Upsert: insert or update on conflict
-        let result = diesel::insert_into(user_preferences::table)
-            .values(NewPreference {
-                id: Uuid::new_v4(),
-                agent_id,
-                key,
-                value,
-            })
-            .on_conflict((user_preferences::agent_id, user_preferences::key))
-            .do_update()
-            .set((
-                user_preferences::value.eq(value),
-                user_preferences::updated_at.eq(now),
-            ))
-            .get_result(&mut *conn)?;
-
-        Ok(result)
+            // Upsert: insert or update on conflict
+            let result = diesel::insert_into(user_preferences::table)
+                .values(NewPreference {
+                    id: Uuid::new_v4(),
+                    agent_id,
+                    key,
+                    value,
+                })
+                .on_conflict((user_preferences::agent_id, user_preferences::key))
+                .do_update()
+                .set((
+                    user_preferences::value.eq(value),
+                    user_preferences::updated_at.eq(now),
+                ))
+                .get_result(conn)?;
+
+            Ok(result)
+        })
     }
 
     /// Get a single preference by key
     pub fn get(&self, agent_id: Uuid, key: &str) -> Result<Option<PreferenceRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let result = user_preferences::table
-            .filter(user_preferences::agent_id.eq(agent_id))
-            .filter(user_preferences::key.eq(key))
-            .select(PreferenceRow::as_select())
-            .first(&mut *conn)
-            .optional()?;
+        self.conn.run(|conn| {
+            let result = user_preferences::table
+                .filter(user_preferences::agent_id.eq(agent_id))
+                .filter(user_preferences::key.eq(key))
+                .select(PreferenceRow::as_select())
+                .first(conn)
+                .optional()?;
 
-        Ok(result)
+            Ok(result)
+        })
     }
 
     /// Get all preferences for an agent
     pub fn get_all(&self, agent_id: Uuid) -> Result<Vec<PreferenceRow>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
-
-        let results = user_preferences::table
-            .filter(user_preferences::agent_id.eq(agent_id))
-            .select(PreferenceRow::as_select())
-            .load(&mut *conn)?;
+        self.conn.run(|conn| {
+            let results = user_preferences::table
+                .filter(user_preferences::agent_id.eq(agent_id))
+                .select(PreferenceRow::as_select())
+                .load(conn)?;
 
-        Ok(results)
+            Ok(results)
+        })
     }
 
     /// Delete a preference
     pub fn delete(&self, agent_id: Uuid, key: &str) -> Result<bool> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|_| anyhow::anyhow!("Failed to acquire database lock"))?;
+        self.conn.run(|conn| {
+            let deleted = diesel::delete(
+                user_preferences::table
+                    .filter(user_preferences::agent_id.eq(agent_id))
+                    .filter(user_preferences::key.eq(key)),
+            )
+            .execute(conn)?;
+
+            Ok(deleted > 0)
+        })
+    }
+}
 
-        let deleted = diesel::delete(
-            user_preferences::table
-                .filter(user_preferences::agent_id.eq(agent_id))
-                .filter(user_preferences::key.eq(key)),
-        )
-        .execute(&mut *conn)?;
+// ============================================================================
+// Memory Provenance Operations
+// ============================================================================
+
+/// Where a memory came from
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = memory_sources)]
+pub struct SourceRow {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub kind: String,
+    pub target: String,
+    pub excerpt: String,
+    pub message_ids: Vec<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = memory_sources)]
+struct NewSource<'a> {
+    agent_id: Uuid,
+    kind: &'a str,
+    target: &'a str,
+    excerpt: &'a str,
+    message_ids: &'a [Uuid],
+}
+
+/// Database operations for memory provenance
+pub struct SourceDb {
+    conn: Arc<DbConn>,
+}
+
+impl SourceDb {
+    pub fn new(conn: Arc<DbConn>) -> Self {
+        Self { conn }
+    }
+
+    /// Record that `excerpt` was written to `kind`/`target`, learned from
+    /// `message_ids`
+    pub fn record(
+        &self,
+        agent_id: Uuid,
+        kind: &str,
+        target: &str,
+        excerpt: &str,
+        message_ids: &[Uuid],
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::insert_into(memory_sources::table)
+                .values(NewSource {
+                    agent_id,
+                    kind,
+                    target,
+                    excerpt,
+                    message_ids,
+                })
+                .execute(conn)?;
+            Ok(())
+        })
+    }
 
-        Ok(deleted > 0)
+    /// Point sources of `kind` written to any of `from` at `to` instead
+    /// (after the targets were merged). Returns how many.
+    pub fn retarget(&self, agent_id: Uuid, kind: &str, from: &[String], to: &str) -> Result<usize> {
+        self.conn.run(|conn| {
+            let updated = diesel::update(
+                memory_sources::table
+                    .filter(memory_sources::agent_id.eq(agent_id))
+                    .filter(memory_sources::kind.eq(kind))
+                    .filter(memory_sources::target.eq_any(from)),
+            )
+            .set(memory_sources::target.eq(to))
+            .execute(conn)?;
+            Ok(updated)
+        })
+    }
+
+    /// Newest sources whose excerpt or target matches any `ILIKE` pattern
+    pub fn find(&self, agent_id: Uuid, patterns: &[String], limit: i64) -> Result<Vec<SourceRow>> {
+        if patterns.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.conn.run(|conn| {
+            let mut query = memory_sources::table
+                .select(SourceRow::as_select())
+                .into_boxed();
+            for pattern in patterns {
+                query = query.or_filter(
+                    memory_sources::agent_id.eq(agent_id).and(
+                        memory_sources::excerpt
+                            .ilike(pattern)
+                            .or(memory_sources::target.ilike(pattern)),
+                    ),
+                );
+            }
+            Ok(query
+                .order(memory_sources::created_at.desc())
+                .limit(limit)
+                .load(conn)?)
+        })
+    }
+}
+
+// ============================================================================
+// Fact Operations
+// ============================================================================
+
+/// A subject / predicate / object fact about the user
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = facts)]
+pub struct FactRow {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    pub confidence: f32,
+    pub source_message_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub superseded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = facts)]
+struct NewFact<'a> {
+    agent_id: Uuid,
+    subject: &'a str,
+    predicate: &'a str,
+    object: &'a str,
+    confidence: f32,
+    source_message_id: Option<Uuid>,
+}
+
+/// What storing a fact changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum FactChange {
+    /// Nothing was known for the subject and predicate
+    Added,
+    /// Same value as the current one (confidence kept at the higher)
+    Confirmed,
+    /// The current value differed and was superseded; holds the old value
+    Replaced(String),
+}
+
+/// Database operations for structured facts
+pub struct FactDb {
+    conn: Arc<DbConn>,
+}
+
+impl FactDb {
+    pub fn new(conn: Arc<DbConn>) -> Self {
+        Self { conn }
+    }
+
+    /// Store a fact. A different current value for the same subject and
+    /// predicate is superseded, not overwritten.
+    pub fn upsert(
+        &self,
+        agent_id: Uuid,
+        subject: &str,
+        predicate: &str,
+        object: &str,
+        confidence: f32,
+        source_message_id: Option<Uuid>,
+    ) -> Result<FactChange> {
+        self.conn.run(|conn| {
+            conn.transaction(|conn| {
+                let current: Option<FactRow> = facts::table
+                    .filter(facts::agent_id.eq(agent_id))
+                    .filter(facts::subject.eq(subject))
+                    .filter(facts::predicate.eq(predicate))
+                    .filter(facts::superseded_at.is_null())
+                    .select(FactRow::as_select())
+                    .for_update()
+                    .first(conn)
+                    .optional()?;
+
+                let change = match current {
+                    Some(row) if row.object.to_lowercase() == object.to_lowercase() => {
+                        diesel::update(facts::table.find(row.id))
+                            .set((
+                                facts::confidence.eq(row.confidence.max(confidence)),
+                                facts::updated_at.eq(diesel::dsl::now),
+                            ))
+                            .execute(conn)?;
+                        return Ok(FactChange::Confirmed);
+                    }
+                    Some(row) => {
+                        diesel::update(facts::table.find(row.id))
+                            .set(facts::superseded_at.eq(diesel::dsl::now))
+                            .execute(conn)?;
+                        FactChange::Replaced(row.object)
+                    }
+                    None => FactChange::Added,
+                };
+                diesel::insert_into(facts::table)
+                    .values(NewFact {
+                        agent_id,
+                        subject,
+                        predicate,
+                        object,
+                        confidence,
+                        source_message_id,
+                    })
+                    .execute(conn)?;
+                Ok(change)
+            })
+        })
+    }
+
+    /// Current facts, most recently updated first. `subject` and `predicate`
+    /// match exactly; every `ILIKE` pattern must match the subject, predicate
+    /// or object.
+    pub fn current(
+        &self,
+        agent_id: Uuid,
+        subject: Option<&str>,
+        predicate: Option<&str>,
+        patterns: &[String],
+        limit: i64,
+    ) -> Result<Vec<FactRow>> {
+        self.conn.run(|conn| {
+            let mut query = facts::table
+                .filter(facts::agent_id.eq(agent_id))
+                .filter(facts::superseded_at.is_null())
+                .select(FactRow::as_select())
+                .into_boxed();
+            if let Some(subject) = subject {
+                query = query.filter(facts::subject.eq(subject));
+            }
+            if let Some(predicate) = predicate {
+                query = query.filter(facts::predicate.eq(predicate));
+            }
+            for pattern in patterns {
+                query = query.filter(
+                    facts::subject
+                        .ilike(pattern)
+                        .or(facts::predicate.ilike(pattern))
+                        .or(facts::object.ilike(pattern)),
+                );
+            }
+            Ok(query
+                .order(facts::updated_at.desc())
+                .limit(limit)
+                .load(conn)?)
+        })
+    }
+
+    /// Every value a subject and predicate has had, newest first
+    pub fn history(
+        &self,
+        agent_id: Uuid,
+        subject: &str,
+        predicate: &str,
+        limit: i64,
+    ) -> Result<Vec<FactRow>> {
+        self.conn.run(|conn| {
+            Ok(facts::table
+                .filter(facts::agent_id.eq(agent_id))
+                .filter(facts::subject.eq(subject))
+                .filter(facts::predicate.eq(predicate))
+                .order(facts::created_at.desc())
+                .limit(limit)
+                .select(FactRow::as_select())
+                .load(conn)?)
+        })
+    }
+
+    /// Number of current facts
+    pub fn count(&self, agent_id: Uuid) -> Result<i64> {
+        self.conn.run(|conn| {
+            Ok(facts::table
+                .filter(facts::agent_id.eq(agent_id))
+                .filter(facts::superseded_at.is_null())
+                .count()
+                .get_result(conn)?)
+        })
+    }
+
+    /// Delete facts, current and superseded, matching every `ILIKE` pattern
+    /// in their subject, predicate or object. Returns how many.
+    pub fn delete_matching(&self, agent_id: Uuid, patterns: &[String]) -> Result<usize> {
+        if patterns.is_empty() {
+            return Ok(0);
+        }
+        self.conn.run(|conn| {
+            let mut query = diesel::delete(facts::table)
+                .filter(facts::agent_id.eq(agent_id))
+                .into_boxed();
+            for pattern in patterns {
+                query = query.filter(
+                    facts::subject
+                        .ilike(pattern)
+                        .or(facts::predicate.ilike(pattern))
+                        .or(facts::object.ilike(pattern)),
+                );
+            }
+            Ok(query.execute(conn)?)
+        })
+    }
+}
+
+// ============================================================================
+// Open Thread Operations
+// ============================================================================
+
+/// A question or topic left open in compacted messages
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = open_threads)]
+pub struct OpenThreadRow {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub content: String,
+    pub raised_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = open_threads)]
+struct NewOpenThread<'a> {
+    agent_id: Uuid,
+    content: &'a str,
+    raised_at: DateTime<Utc>,
+}
+
+/// Database operations for open threads
+pub struct OpenThreadDb {
+    conn: Arc<DbConn>,
+}
+
+impl OpenThreadDb {
+    pub fn new(conn: Arc<DbConn>) -> Self {
+        Self { conn }
+    }
+
+    pub fn insert(&self, agent_id: Uuid, content: &str, raised_at: DateTime<Utc>) -> Result<Uuid> {
+        self.conn.run(|conn| {
+            Ok(diesel::insert_into(open_threads::table)
+                .values(NewOpenThread {
+                    agent_id,
+                    content,
+                    raised_at,
+                })
+                .returning(open_threads::id)
+                .get_result(conn)?)
+        })
+    }
+
+    /// Unresolved threads, oldest first
+    pub fn open(&self, agent_id: Uuid, limit: i64) -> Result<Vec<OpenThreadRow>> {
+        self.conn.run(|conn| {
+            Ok(open_threads::table
+                .filter(open_threads::agent_id.eq(agent_id))
+                .filter(open_threads::resolved_at.is_null())
+                .order(open_threads::created_at.asc())
+                .limit(limit)
+                .select(OpenThreadRow::as_select())
+                .load(conn)?)
+        })
+    }
+
+    /// Mark threads resolved. Returns how many were still open.
+    pub fn resolve(&self, agent_id: Uuid, ids: &[Uuid]) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        self.conn.run(|conn| {
+            Ok(diesel::update(
+                open_threads::table
+                    .filter(open_threads::agent_id.eq(agent_id))
+                    .filter(open_threads::id.eq_any(ids))
+                    .filter(open_threads::resolved_at.is_null()),
+            )
+            .set(open_threads::resolved_at.eq(diesel::dsl::now))
+            .execute(conn)?)
+        })
+    }
+}
+
+// ============================================================================
+// Embedding Job Operations
+// ============================================================================
+
+/// A message waiting for its embedding
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = embedding_jobs)]
+pub struct EmbeddingJobRow {
+    pub message_id: Uuid,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Database operations for the embedding queue
+pub struct EmbeddingJobDb {
+    conn: Arc<DbConn>,
+}
+
+impl EmbeddingJobDb {
+    pub fn new(conn: Arc<DbConn>) -> Self {
+        Self { conn }
+    }
+
+    /// Queue a message for embedding (again, if it was queued before)
+    pub fn enqueue(&self, message_id: Uuid) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::insert_into(embedding_jobs::table)
+                .values(embedding_jobs::message_id.eq(message_id))
+                .on_conflict(embedding_jobs::message_id)
+                .do_update()
+                .set((
+                    embedding_jobs::attempts.eq(0),
+                    embedding_jobs::next_attempt_at.eq(diesel::dsl::now),
+                    embedding_jobs::last_error.eq(None::<String>),
+                ))
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Queue every message still without an embedding (zero vector or NULL),
+    /// restarting jobs that gave up. Returns how many were queued.
+    pub fn backfill(&self) -> Result<usize> {
+        self.conn.run(|conn| {
+            Ok(diesel::sql_query(
+                "INSERT INTO embedding_jobs (message_id) \
+                 SELECT id FROM messages \
+                 WHERE embedding IS NULL OR vector_norm(embedding) = 0 \
+                 ON CONFLICT (message_id) DO UPDATE \
+                 SET attempts = 0, next_attempt_at = NOW(), last_error = NULL",
+            )
+            .execute(conn)?)
+        })
+    }
+
+    /// Jobs due now that haven't used up `max_attempts`, oldest first
+    pub fn due(&self, max_attempts: i32, limit: i64) -> Result<Vec<EmbeddingJobRow>> {
+        self.conn.run(|conn| {
+            Ok(embedding_jobs::table
+                .filter(embedding_jobs::next_attempt_at.le(diesel::dsl::now))
+                .filter(embedding_jobs::attempts.lt(max_attempts))
+                .order(embedding_jobs::next_attempt_at.asc())
+                .limit(limit)
+                .select(EmbeddingJobRow::as_select())
+                .load(conn)?)
+        })
+    }
+
+    /// Which of `message_ids` are still waiting for an embedding
+    pub fn queued(&self, message_ids: &[Uuid]) -> Result<Vec<Uuid>> {
+        if message_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.conn.run(|conn| {
+            Ok(embedding_jobs::table
+                .filter(embedding_jobs::message_id.eq_any(message_ids))
+                .select(embedding_jobs::message_id)
+                .load(conn)?)
+        })
+    }
+
+    /// The message is embedded
+    pub fn complete(&self, message_id: Uuid) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::delete(embedding_jobs::table.find(message_id)).execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Record a failed attempt and when to try again
+    pub fn retry_at(
+        &self,
+        message_id: Uuid,
+        attempts: i32,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::update(embedding_jobs::table.find(message_id))
+                .set((
+                    embedding_jobs::attempts.eq(attempts),
+                    embedding_jobs::last_error.eq(error),
+                    embedding_jobs::next_attempt_at.eq(next_attempt_at),
+                ))
+                .execute(conn)?;
+            Ok(())
+        })
     }
 }
 
@@ -1222,16 +2481,14 @@ impl PreferenceDb {
 /// Shared database connection for the memory system
 #[derive(Clone)]
 pub struct MemoryDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 impl MemoryDb {
     /// Create a new memory database connection
     pub fn new(database_url: &str) -> Result<Self> {
-        let conn = PgConnection::establish(database_url)?;
-
         Ok(Self {
-            conn: Arc::new(Mutex::new(conn)),
+            conn: Arc::new(DbConn::connect(database_url)?),
         })
     }
 
@@ -1264,4 +2521,24 @@ impl MemoryDb {
     pub fn preferences(&self) -> PreferenceDb {
         PreferenceDb::new(Arc::clone(&self.conn))
     }
+
+    /// Get embedding queue operations
+    pub fn embedding_jobs(&self) -> EmbeddingJobDb {
+        EmbeddingJobDb::new(Arc::clone(&self.conn))
+    }
+
+    /// Get memory provenance operations
+    pub fn sources(&self) -> SourceDb {
+        SourceDb::new(Arc::clone(&self.conn))
+    }
+
+    /// Get structured fact operations
+    pub fn facts(&self) -> FactDb {
+        FactDb::new(Arc::clone(&self.conn))
+    }
+
+    /// Get open thread operations
+    pub fn open_threads(&self) -> OpenThreadDb {
+        OpenThreadDb::new(Arc::clone(&self.conn))
+    }
 }
@@ -1,16 +1,24 @@
 //! Embedding Service
 //!
 //! Shared embedding generation for all memory tiers.
-//! Uses Maple API with nomic-embed-text model (768 dimensions).
+//! Uses Maple API with nomic-embed-text model (768 dimensions). Many texts
+//! at once go through `embed_batch`, which sends them as one array `input`
+//! per `MAX_BATCH_INPUTS` instead of a request each.
 
 #![allow(dead_code)]
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use tracing::warn;
+use uuid::Uuid;
+
+use crate::usage::{self, CallKind};
 
 /// Embedding dimension for nomic-embed-text
 pub const EMBEDDING_DIM: usize = 768;
 
+/// Most texts sent in one embeddings request; larger batches are split
+pub const MAX_BATCH_INPUTS: usize = 64;
+
 /// Shared embedding service for generating vector embeddings
 #[derive(Clone)]
 pub struct EmbeddingService {
@@ -18,6 +26,8 @@ pub struct EmbeddingService {
     api_key: String,
     model: String,
     client: reqwest::Client,
+    /// Agent the usage of this service is recorded for
+    agent_id: Option<Uuid>,
 }
 
 impl EmbeddingService {
@@ -28,9 +38,24 @@ impl EmbeddingService {
             api_key: api_key.to_string(),
             model: model.to_string(),
             client: reqwest::Client::new(),
+            agent_id: None,
         }
     }
 
+    /// Record this service's usage for `agent_id`
+    pub fn for_agent(mut self, agent_id: Uuid) -> Self {
+        self.agent_id = Some(agent_id);
+        self
+    }
+
+    /// Record an embedding call, estimating tokens if the API reported none
+    fn record_usage(&self, json: &serde_json::Value, texts: &[&str]) {
+        let tokens = json["usage"]["prompt_tokens"]
+            .as_i64()
+            .unwrap_or_else(|| texts.iter().map(|t| usage::estimate_tokens(t)).sum());
+        usage::record(CallKind::Embedding, self.agent_id, &self.model, tokens, 0);
+    }
+
     /// Generate an embedding for a single text
     pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
         let response = self
@@ -49,6 +74,7 @@ impl EmbeddingService {
             Ok(resp) => {
                 if resp.status().is_success() {
                     let json: serde_json::Value = resp.json().await?;
+                    self.record_usage(&json, &[text]);
                     if let Some(embedding) = json["data"][0]["embedding"].as_array() {
                         let vec: Vec<f32> = embedding
                             .iter()
@@ -75,13 +101,32 @@ impl EmbeddingService {
         }
     }
 
-    /// Generate embeddings for multiple texts (batched)
+    /// Generate embeddings for multiple texts, one API request per
+    /// `MAX_BATCH_INPUTS`. Texts that fail get zero embeddings, like `embed`.
     pub async fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
-        if texts.is_empty() {
-            return Ok(Vec::new());
+        match self.try_embed_batch(texts).await {
+            Ok(embeddings) => Ok(embeddings),
+            Err(e) => {
+                warn!("Batch embedding failed, using zero embeddings: {}", e);
+                Ok(texts.iter().map(|_| zero_embedding()).collect())
+            }
         }
+    }
 
-        let response = self
+    /// Generate embeddings for multiple texts, failing instead of falling
+    /// back to zero embeddings (for callers that retry, e.g. the embedding
+    /// queue)
+    pub async fn try_embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(MAX_BATCH_INPUTS) {
+            embeddings.extend(self.request_batch(batch).await?);
+        }
+        Ok(embeddings)
+    }
+
+    /// One embeddings request with an array `input`
+    async fn request_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let resp = self
             .client
             .post(format!("{}/embeddings", self.api_url))
             .header("Authorization", format!("Bearer {}", self.api_key))
@@ -91,38 +136,53 @@ impl EmbeddingService {
                 "encoding_format": "float"
             }))
             .send()
-            .await;
+            .await
+            .context("Embedding request failed")?;
+        let status = resp.status();
+        if !status.is_success() {
+            anyhow::bail!("Embedding API returned {}", status);
+        }
 
-        match response {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    let json: serde_json::Value = resp.json().await?;
-                    if let Some(data) = json["data"].as_array() {
-                        let embeddings: Vec<Vec<f32>> = data
-                            .iter()
-                            .filter_map(|item| {
-                                item["embedding"].as_array().map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_f64().map(|f| f as f32))
-                                        .collect()
-                                })
-                            })
-                            .collect();
+        let json: serde_json::Value = resp.json().await?;
+        self.record_usage(&json, texts);
+        parse_batch(&json, texts.len())
+    }
+}
 
-                        if embeddings.len() == texts.len() {
-                            return Ok(embeddings);
-                        }
-                    }
-                }
-                warn!("Batch embedding API call failed, using zero embeddings");
-                Ok(texts.iter().map(|_| zero_embedding()).collect())
-            }
-            Err(e) => {
-                warn!("Failed to generate batch embeddings: {}", e);
-                Ok(texts.iter().map(|_| zero_embedding()).collect())
-            }
+/// Embeddings of a batch response, in input order (`data[].index`; the API
+/// doesn't promise to return them in order)
+fn parse_batch(json: &serde_json::Value, expected: usize) -> Result<Vec<Vec<f32>>> {
+    let data = json["data"]
+        .as_array()
+        .context("Embedding response has no data")?;
+    let mut embeddings: Vec<Option<Vec<f32>>> = vec![None; expected];
+    for (position, item) in data.iter().enumerate() {
+        let index = item["index"]
+            .as_u64()
+            .map(|i| i as usize)
+            .unwrap_or(position);
+        let vec: Vec<f32> = item["embedding"]
+            .as_array()
+            .context("Embedding response item has no embedding")?
+            .iter()
+            .filter_map(|v| v.as_f64().map(|f| f as f32))
+            .collect();
+        if vec.len() != EMBEDDING_DIM {
+            anyhow::bail!(
+                "Unexpected embedding dimension: {} (expected {})",
+                vec.len(),
+                EMBEDDING_DIM
+            );
+        }
+        match embeddings.get_mut(index) {
+            Some(slot) => *slot = Some(vec),
+            None => anyhow::bail!("Embedding index {} out of range", index),
         }
     }
+    embeddings
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .with_context(|| format!("Expected {} embeddings, got {}", expected, data.len()))
 }
 
 /// Return a zero embedding (fallback when API fails)
@@ -140,4 +200,18 @@ mod tests {
         assert_eq!(emb.len(), EMBEDDING_DIM);
         assert!(emb.iter().all(|&x| x == 0.0));
     }
+
+    #[test]
+    fn test_parse_batch_orders_by_index() {
+        let item = |index: usize, value: f64| serde_json::json!({"index": index, "embedding": vec![value; EMBEDDING_DIM]});
+        let json = serde_json::json!({"data": [item(1, 0.5), item(0, 0.25)]});
+        let embeddings = parse_batch(&json, 2).unwrap();
+        assert_eq!(embeddings[0][0], 0.25);
+        assert_eq!(embeddings[1][0], 0.5);
+
+        // A missing embedding fails the batch rather than shifting the rest
+        assert!(parse_batch(&json, 3).is_err());
+        let json = serde_json::json!({"data": [{"index": 0, "embedding": [0.1]}]});
+        assert!(parse_batch(&json, 1).is_err());
+    }
 }
@@ -0,0 +1,209 @@
+//! Embedding Queue
+//!
+//! Messages are stored right away with a zero vector, so a turn never waits
+//! for the embedding API. Each stored message also gets a row in
+//! `embedding_jobs`, and one background worker embeds them (plus the chunks
+//! of long messages) and deletes the job. Due jobs are embedded together,
+//! one batch request per agent. A failed attempt is retried with
+//! exponential backoff; after `MAX_ATTEMPTS` the job stays in the table with
+//! its last error.
+//!
+//! On startup the worker queues every message that still has no embedding,
+//! including ones whose jobs gave up or were never written (crashes, messages
+//! stored before the queue existed).
+
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use super::db::{EmbeddingJobRow, MemoryDb, MessageRow};
+use super::embedding::EmbeddingService;
+use super::recall_new::RecallManager;
+use crate::speech;
+
+/// Attempts before a job is given up (until the next startup)
+pub const MAX_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry; doubles per failed attempt
+const BASE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Upper bound on the delay between retries
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Jobs taken per round
+const BATCH_SIZE: i64 = 50;
+
+/// How often to look for due retries when nothing new was queued
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+static WAKE: OnceLock<Notify> = OnceLock::new();
+
+fn wake_signal() -> &'static Notify {
+    WAKE.get_or_init(Notify::new)
+}
+
+/// Tell the worker new jobs were queued
+pub fn wake() {
+    wake_signal().notify_one();
+}
+
+/// Delay before the next try after `attempts` failed attempts
+pub fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    BASE_BACKOFF.saturating_mul(1 << exponent).min(MAX_BACKOFF)
+}
+
+/// Text embedded for a message: its content plus any attachment description,
+/// as the agent saw it
+pub fn embed_text(message: &MessageRow) -> String {
+    match message.attachment_text.as_deref() {
+        Some(attachment) => {
+            let attachment = speech::display_attachment_text(attachment);
+            if message.content.is_empty() {
+                attachment
+            } else {
+                format!("{}\n\n{}", message.content, attachment)
+            }
+        }
+        None => message.content.clone(),
+    }
+}
+
+/// Embed the messages in `embedding_jobs`, for the life of the process
+pub async fn run_embedding_worker(db: MemoryDb, embedding: EmbeddingService) {
+    match db.embedding_jobs().backfill() {
+        Ok(0) => {}
+        Ok(queued) => info!("Queued {} message(s) without an embedding", queued),
+        Err(e) => warn!("Failed to queue messages without an embedding: {}", e),
+    }
+
+    loop {
+        let jobs = match db.embedding_jobs().due(MAX_ATTEMPTS, BATCH_SIZE) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                warn!("Failed to load embedding jobs: {}", e);
+                Vec::new()
+            }
+        };
+        if !jobs.is_empty() {
+            process(&db, &embedding, &jobs).await;
+        }
+        if (jobs.len() as i64) < BATCH_SIZE {
+            tokio::select! {
+                _ = wake_signal().notified() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+        }
+    }
+}
+
+/// Embed a round of jobs: one batch request per agent, so usage is still
+/// recorded per agent
+async fn process(db: &MemoryDb, embedding: &EmbeddingService, jobs: &[EmbeddingJobRow]) {
+    let ids: Vec<Uuid> = jobs.iter().map(|job| job.message_id).collect();
+    let messages = match db.messages().get_by_ids(&ids) {
+        Ok(messages) => messages,
+        Err(e) => {
+            warn!("Failed to load messages to embed: {}", e);
+            return;
+        }
+    };
+
+    // Deleted meanwhile; nothing to embed
+    for job in jobs {
+        if !messages.iter().any(|m| m.id == job.message_id) {
+            complete(db, job.message_id);
+        }
+    }
+
+    let mut by_agent: BTreeMap<Uuid, Vec<MessageRow>> = BTreeMap::new();
+    for message in messages {
+        by_agent.entry(message.agent_id).or_default().push(message);
+    }
+    for (agent_id, messages) in by_agent {
+        let embedding = embedding.clone().for_agent(agent_id);
+        let texts: Vec<String> = messages.iter().map(embed_text).collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        match embedding.try_embed_batch(&texts).await {
+            Ok(embeddings) => {
+                for (message, vector) in messages.iter().zip(embeddings) {
+                    store(db, &embedding, message, &vector).await;
+                }
+            }
+            Err(e) => {
+                for message in &messages {
+                    if let Some(job) = jobs.iter().find(|job| job.message_id == message.id) {
+                        retry(db, job, &e.to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Save the embedding of a queued message and finish its job. Also used by
+/// compaction, which embeds pending messages along with its summary.
+pub async fn store(
+    db: &MemoryDb,
+    embedding: &EmbeddingService,
+    message: &MessageRow,
+    vector: &[f32],
+) {
+    let recall = RecallManager::new(message.agent_id, db.clone(), embedding.clone());
+    match recall
+        .set_embedding(message.id, &embed_text(message), vector)
+        .await
+    {
+        Ok(()) => {
+            debug!("Embedded message {}", message.id);
+            complete(db, message.id);
+        }
+        Err(e) => warn!("Failed to store embedding of message {}: {}", message.id, e),
+    }
+}
+
+fn complete(db: &MemoryDb, message_id: Uuid) {
+    if let Err(e) = db.embedding_jobs().complete(message_id) {
+        warn!("Failed to update embedding job {}: {}", message_id, e);
+    }
+}
+
+fn retry(db: &MemoryDb, job: &EmbeddingJobRow, error: &str) {
+    let attempts = job.attempts + 1;
+    let delay = backoff(attempts);
+    if attempts >= MAX_ATTEMPTS {
+        warn!(
+            "Giving up embedding message {} after {} attempts: {}",
+            job.message_id, attempts, error
+        );
+    } else {
+        warn!(
+            "Failed to embed message {} (attempt {}), retrying in {:?}: {}",
+            job.message_id, attempts, delay, error
+        );
+    }
+    let next = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+    if let Err(e) = db
+        .embedding_jobs()
+        .retry_at(job.message_id, attempts, error, next)
+    {
+        warn!("Failed to update embedding job {}: {}", job.message_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        assert_eq!(backoff(1), BASE_BACKOFF);
+        assert_eq!(backoff(2), BASE_BACKOFF * 2);
+        assert_eq!(backoff(4), BASE_BACKOFF * 8);
+        assert_eq!(backoff(MAX_ATTEMPTS + 20), MAX_BACKOFF);
+    }
+}
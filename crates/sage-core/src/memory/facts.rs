@@ -0,0 +1,360 @@
+//! Structured Facts
+//!
+//! Next to free-text passages, Sage keeps facts about the user as subject /
+//! predicate / object rows (`facts` table), e.g. `user_dog` / `age` / `4`.
+//! After each user message in a direct chat, a background model pass
+//! (`ExtractFacts`, `FACT_EXTRACTION`) reads the message with the facts
+//! already known and returns the facts it states. Keys are normalized
+//! (`normalize_key`), and there is one current value per subject and
+//! predicate, so "my dog is 4, not 3" supersedes the old age instead of
+//! relying on the agent to find and replace the right words. Superseded
+//! values are kept as history.
+//!
+//! The agent reads facts with `fact_query`; `forget` deletes them.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dspy_rs::{BamlType, Predict, Signature};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::db::{FactChange, FactRow, MemoryDb};
+use super::provenance::like_pattern;
+use crate::sage_agent::{Tool, ToolResult};
+use crate::usage::{self, CallKind};
+
+/// Extracted facts below this confidence are dropped
+pub const MIN_CONFIDENCE: f32 = 0.5;
+
+/// Most facts stored from one message
+const MAX_FACTS_PER_MESSAGE: usize = 10;
+
+/// Current facts shown to the extraction model, so it reuses their keys
+const KNOWN_FACTS: i64 = 100;
+
+/// Earlier messages shown to the extraction model, for pronouns and context
+const RECENT_MESSAGES: i64 = 6;
+
+/// Messages shorter than this are not worth a model call ("ok", "thanks!")
+const MIN_MESSAGE_CHARS: usize = 12;
+
+/// Longest subject, predicate or object stored
+const MAX_PART_CHARS: usize = 200;
+
+/// Default and maximum number of facts `fact_query` returns
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+/// Instruction for the fact extraction DSRs signature
+pub const EXTRACT_INSTRUCTION: &str = r#"You maintain a structured memory of facts about the user of a personal assistant. Read the user's latest message and list the durable facts it states about the user or the people, pets, places and things in their life: names, ages, birthdays, relationships, jobs, locations, preferences, allergies, routines.
+
+Each fact is a subject, a predicate and an object. The subject is "user" for the user themself, or a short key for someone or something of theirs ("user_dog", "sister_anna"). The predicate is a short key ("name", "age", "birthday", "lives_in", "works_at", "likes"). The object is the value, as plain text.
+
+Reuse the subject and predicate of a known fact whenever the message is about the same thing, especially for corrections ("my dog is 4, not 3" gives user_dog / age / 4). Confidence is 0-1: 1 for something stated plainly, lower for hedged or implied facts. Skip small talk, questions, requests, opinions about the conversation, and anything only true for the moment. Return no facts if there are none."#;
+
+/// A fact as returned by the extraction model
+#[derive(Clone, Debug, Default, BamlType)]
+pub struct ExtractedFact {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    /// 0-1
+    pub confidence: f64,
+}
+
+/// DSRs signature for extracting facts from a user message
+#[derive(Signature, Clone, Debug)]
+pub struct ExtractFacts {
+    #[input(desc = "Facts already known, one per line: subject / predicate / object")]
+    pub known_facts: String,
+
+    #[input(desc = "Earlier messages of the conversation, for context")]
+    pub recent_messages: String,
+
+    #[input(desc = "The user's latest message")]
+    pub message: String,
+
+    #[output(desc = "Facts the latest message states (can be empty)")]
+    pub facts: Vec<ExtractedFact>,
+}
+
+/// Lowercase words joined by underscores, so keys written differently by
+/// different passes compare equal ("Sister Anna" and "sister_anna")
+pub fn normalize_key(key: &str) -> String {
+    key.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Check, normalize and clamp an extracted fact; None if it is unusable
+fn clean(fact: &ExtractedFact) -> Option<ExtractedFact> {
+    let subject = normalize_key(&fact.subject);
+    let predicate = normalize_key(&fact.predicate);
+    let object = fact.object.split_whitespace().collect::<Vec<_>>().join(" ");
+    let confidence = fact.confidence.clamp(0.0, 1.0);
+    if subject.is_empty()
+        || predicate.is_empty()
+        || object.is_empty()
+        || confidence < MIN_CONFIDENCE as f64
+        || [&subject, &predicate, &object]
+            .iter()
+            .any(|part| part.chars().count() > MAX_PART_CHARS)
+    {
+        return None;
+    }
+    Some(ExtractedFact {
+        subject,
+        predicate,
+        object,
+        confidence,
+    })
+}
+
+/// `ILIKE` patterns for each word of `text`; a fact matches when it
+/// contains all of them
+fn word_patterns(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| like_pattern(&word.to_lowercase()))
+        .collect()
+}
+
+/// One fact as a line for the agent
+pub fn format_fact(fact: &FactRow) -> String {
+    let mut line = format!("{} / {} / {}", fact.subject, fact.predicate, fact.object);
+    if fact.confidence < 1.0 {
+        line.push_str(&format!(" (confidence {:.1})", fact.confidence));
+    }
+    line.push_str(&format!(" [{}]", fact.updated_at.format("%Y-%m-%d")));
+    line
+}
+
+/// Extracts, stores and reads an agent's facts
+#[derive(Clone)]
+pub struct FactManager {
+    /// Agent whose messages are read (a thread, or the main agent)
+    agent_id: Uuid,
+    /// Owner of the facts: the main agent
+    core_agent_id: Uuid,
+    db: MemoryDb,
+}
+
+impl FactManager {
+    pub fn new(agent_id: Uuid, core_agent_id: Uuid, db: MemoryDb) -> Self {
+        Self {
+            agent_id,
+            core_agent_id,
+            db,
+        }
+    }
+
+    /// Extract the facts a stored user message states and store them.
+    /// Returns what changed.
+    pub async fn extract(
+        &self,
+        message_id: Uuid,
+        message: &str,
+    ) -> Result<Vec<(ExtractedFact, FactChange)>> {
+        if message.trim().chars().count() < MIN_MESSAGE_CHARS {
+            return Ok(Vec::new());
+        }
+
+        let facts = self.db.facts();
+        let known: Vec<String> = facts
+            .current(self.core_agent_id, None, None, &[], KNOWN_FACTS)?
+            .iter()
+            .map(|f| format!("{} / {} / {}", f.subject, f.predicate, f.object))
+            .collect();
+        let recent: Vec<String> = self
+            .db
+            .messages()
+            .get_recent(self.agent_id, RECENT_MESSAGES + 1)?
+            .into_iter()
+            .filter(|m| m.id != message_id && (m.role == "user" || m.role == "assistant"))
+            .map(|m| format!("[{}]: {}", m.role, m.content))
+            .collect();
+
+        let predictor = Predict::<ExtractFacts>::builder()
+            .instruction(EXTRACT_INSTRUCTION)
+            .build();
+        let result = predictor
+            .call_with_meta(ExtractFactsInput {
+                known_facts: known.join("\n"),
+                recent_messages: recent.join("\n"),
+                message: message.to_string(),
+            })
+            .await?;
+        usage::record_chat(
+            CallKind::FactExtraction,
+            result.lm_usage.prompt_tokens as i64,
+            result.lm_usage.completion_tokens as i64,
+        );
+
+        let mut changes = Vec::new();
+        for fact in result
+            .output
+            .facts
+            .iter()
+            .filter_map(clean)
+            .take(MAX_FACTS_PER_MESSAGE)
+        {
+            let change = facts.upsert(
+                self.core_agent_id,
+                &fact.subject,
+                &fact.predicate,
+                &fact.object,
+                fact.confidence as f32,
+                Some(message_id),
+            )?;
+            changes.push((fact, change));
+        }
+        Ok(changes)
+    }
+
+    /// The main agent that owns the facts
+    pub fn core_agent_id(&self) -> Uuid {
+        self.core_agent_id
+    }
+
+    /// Current facts, optionally about one subject
+    pub fn current(&self, subject: Option<&str>, limit: i64) -> Result<Vec<FactRow>> {
+        self.db
+            .facts()
+            .current(self.core_agent_id, subject, None, &[], limit)
+    }
+
+    /// Number of current facts
+    pub fn count(&self) -> usize {
+        self.db.facts().count(self.core_agent_id).unwrap_or(0) as usize
+    }
+
+    /// Delete the facts containing all the words of `about`. Returns how many.
+    pub fn forget(&self, about: &str) -> Result<usize> {
+        self.db
+            .facts()
+            .delete_matching(self.core_agent_id, &word_patterns(about))
+    }
+}
+
+/// Look up structured facts about the user
+pub struct FactQueryTool {
+    facts: FactManager,
+}
+
+impl FactQueryTool {
+    pub fn new(facts: FactManager) -> Self {
+        Self { facts }
+    }
+}
+
+#[async_trait]
+impl Tool for FactQueryTool {
+    fn name(&self) -> &str {
+        "fact_query"
+    }
+
+    fn description(&self) -> &str {
+        "Look up structured facts about the user and their people, pets and things (subject / predicate / object, e.g. user_dog / age / 4), learned automatically from their messages. The current value reflects their latest correction. Give subject and predicate with history=true to see earlier values."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"subject": "optional subject key, e.g. 'user' or 'user_dog'", "predicate": "optional predicate key, e.g. 'age'", "about": "optional words the fact must contain", "history": "optional 'true' to include superseded values (needs subject and predicate)", "limit": "max facts (default 20)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let key = |name: &str| {
+            args.get(name)
+                .map(|value| normalize_key(value))
+                .filter(|value| !value.is_empty())
+        };
+        let subject = key("subject");
+        let predicate = key("predicate");
+        let limit = args
+            .get("limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT) as i64;
+        let history = args.get("history").is_some_and(|h| h == "true");
+
+        let db = self.facts.db.facts();
+        let rows = if history {
+            let (Some(subject), Some(predicate)) = (&subject, &predicate) else {
+                return Ok(ToolResult::error(
+                    "history needs both 'subject' and 'predicate'",
+                ));
+            };
+            db.history(self.facts.core_agent_id, subject, predicate, limit)?
+        } else {
+            let patterns = args
+                .get("about")
+                .map(|about| word_patterns(about))
+                .unwrap_or_default();
+            db.current(
+                self.facts.core_agent_id,
+                subject.as_deref(),
+                predicate.as_deref(),
+                &patterns,
+                limit,
+            )?
+        };
+
+        if rows.is_empty() {
+            return Ok(ToolResult::success(
+                "No matching facts. Try archival_search or conversation_search.",
+            ));
+        }
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|row| match row.superseded_at {
+                Some(at) => format!(
+                    "- {} (superseded {})",
+                    format_fact(row),
+                    at.format("%Y-%m-%d")
+                ),
+                None => format!("- {}", format_fact(row)),
+            })
+            .collect();
+        Ok(ToolResult::success(format!(
+            "{} fact(s):\n{}",
+            rows.len(),
+            lines.join("\n")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fact(subject: &str, predicate: &str, object: &str, confidence: f64) -> ExtractedFact {
+        ExtractedFact {
+            subject: subject.to_string(),
+            predicate: predicate.to_string(),
+            object: object.to_string(),
+            confidence,
+        }
+    }
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("Sister Anna"), "sister_anna");
+        assert_eq!(normalize_key("user's dog"), "user_s_dog");
+        assert_eq!(normalize_key("  lives-in "), "lives_in");
+        assert_eq!(normalize_key("?!"), "");
+    }
+
+    #[test]
+    fn test_clean() {
+        let cleaned = clean(&fact("User Dog", "Age", " 4  years ", 1.2)).unwrap();
+        assert_eq!(cleaned.subject, "user_dog");
+        assert_eq!(cleaned.predicate, "age");
+        assert_eq!(cleaned.object, "4 years");
+        assert_eq!(cleaned.confidence, 1.0);
+
+        assert!(clean(&fact("user", "likes", "jazz", 0.3)).is_none());
+        assert!(clean(&fact("user", "", "jazz", 1.0)).is_none());
+        assert!(clean(&fact("user", "likes", "  ", 1.0)).is_none());
+    }
+}
@@ -0,0 +1,87 @@
+//! Memory Freshness
+//!
+//! Stored facts go stale: a job, an address or a plan recorded a year ago may
+//! no longer be true. Retrieval results and memory metadata carry the age of
+//! what they return, and anything older than `STALE_AFTER_DAYS` is flagged so
+//! the agent verifies it (web search or asking the user) instead of repeating
+//! it as current.
+
+use chrono::{DateTime, Utc};
+
+/// Memories older than this are flagged as possibly out of date
+pub const STALE_AFTER_DAYS: i64 = 180;
+
+/// Human-friendly age: "3d", "5mo", "1y 2mo"
+pub fn age(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let days = now.signed_duration_since(then).num_days().max(0);
+    match days {
+        0 => "today".to_string(),
+        1..=59 => format!("{}d", days),
+        60..=364 => format!("{}mo", days / 30),
+        _ => match (days % 365) / 30 {
+            0 => format!("{}y", days / 365),
+            months => format!("{}y {}mo", days / 365, months),
+        },
+    }
+}
+
+/// Whether a memory stored at `then` should be verified before relying on it
+pub fn is_stale(then: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    now.signed_duration_since(then).num_days() >= STALE_AFTER_DAYS
+}
+
+/// Marker appended to a stale search result (empty when fresh)
+pub fn stale_marker(then: DateTime<Utc>, now: DateTime<Utc>) -> &'static str {
+    if is_stale(then, now) {
+        " [possibly stale - verify before relying on time-sensitive details]"
+    } else {
+        ""
+    }
+}
+
+/// Metadata line giving a core memory block's age
+pub fn block_line(label: &str, updated_at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let mut line = format!(
+        "- {} block last updated {} ({} ago)",
+        label,
+        updated_at.format("%Y-%m-%d"),
+        age(updated_at, now)
+    );
+    if is_stale(updated_at, now) {
+        line.push_str(" - facts in it may be out of date");
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_age() {
+        let now = Utc::now();
+        assert_eq!(age(now, now), "today");
+        assert_eq!(age(now - Duration::days(3), now), "3d");
+        assert_eq!(age(now - Duration::days(150), now), "5mo");
+        assert_eq!(age(now - Duration::days(365), now), "1y");
+        assert_eq!(age(now - Duration::days(430), now), "1y 2mo");
+        // Clock skew never yields a negative age
+        assert_eq!(age(now + Duration::days(1), now), "today");
+    }
+
+    #[test]
+    fn test_staleness() {
+        let now = Utc::now();
+        let old = now - Duration::days(STALE_AFTER_DAYS);
+        assert!(is_stale(old, now));
+        assert!(!is_stale(now - Duration::days(10), now));
+        assert_eq!(stale_marker(now, now), "");
+        assert!(stale_marker(old, now).contains("possibly stale"));
+
+        let line = block_line("human", old, now);
+        assert!(line.starts_with("- human block last updated "));
+        assert!(line.ends_with("(6mo ago) - facts in it may be out of date"));
+        assert!(!block_line("persona", now, now).contains("out of date"));
+    }
+}
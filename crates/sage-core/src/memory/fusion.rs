@@ -0,0 +1,63 @@
+//! Hybrid Retrieval
+//!
+//! Embeddings find paraphrases but blur exact tokens: a name, a ticket
+//! number or a rare word can rank below loosely related text. Recall and
+//! archival search therefore also run a Postgres full-text query
+//! (`search_tsv`, ranked by `ts_rank`, Postgres' lexical ranking; there is no
+//! built-in BM25) and merge the ranked lists with reciprocal rank fusion:
+//! each result scores `1 / (RRF_K + rank)` in every list it appears in.
+//! Fusing ranks rather than raw scores needs no tuning between cosine
+//! distances and text ranks, and something near the top of either list
+//! makes the cut.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Rank offset damping the weight of the very first results (the usual 60)
+pub const RRF_K: f64 = 60.0;
+
+/// Candidates each list contributes per result asked for
+pub const CANDIDATES_PER_RESULT: usize = 3;
+
+/// Merge ranked lists (best first) into one, best first, with each key's
+/// fused score. Ties keep the order keys were first seen in.
+pub fn reciprocal_rank_fusion<K: Eq + Hash + Clone>(lists: &[Vec<K>]) -> Vec<(K, f64)> {
+    let mut scores: HashMap<K, (f64, usize)> = HashMap::new();
+    let mut seen = 0;
+    for list in lists {
+        for (rank, key) in list.iter().enumerate() {
+            let entry = scores.entry(key.clone()).or_insert_with(|| {
+                seen += 1;
+                (0.0, seen)
+            });
+            entry.0 += 1.0 / (RRF_K + rank as f64 + 1.0);
+        }
+    }
+    let mut fused: Vec<(K, f64, usize)> = scores
+        .into_iter()
+        .map(|(key, (score, order))| (key, score, order))
+        .collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.2.cmp(&b.2)));
+    fused
+        .into_iter()
+        .map(|(key, score, _)| (key, score))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        // "ticket" is second semantically but first lexically: it wins
+        let semantic = vec!["related", "ticket", "other"];
+        let lexical = vec!["ticket", "exact-only"];
+        let fused = reciprocal_rank_fusion(&[semantic, lexical]);
+        let keys: Vec<&str> = fused.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec!["ticket", "related", "exact-only", "other"]);
+        assert!((fused[0].1 - (1.0 / 62.0 + 1.0 / 61.0)).abs() < 1e-12);
+
+        assert!(reciprocal_rank_fusion::<u32>(&[]).is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+//! Message Scripts and Transliteration
+//!
+//! Each stored message records the writing system it is in (ISO 15924 code,
+//! e.g. `Latn`, `Cyrl`), detected from its letters. That is as far as
+//! language detection goes without a language model: it tells Russian from
+//! English, not French from English. The script most of the user's recent
+//! messages are in is the conversation's script of record, shown in memory
+//! metadata.
+//!
+//! Users who write one language in two scripts (Cyrillic and its
+//! romanization, Greek and Greeklish) shouldn't need to guess which one a
+//! message was stored in, so keyword search compares `fold`ed text: lowercased,
+//! Cyrillic and Greek transliterated to Latin, and accents dropped. Other
+//! scripts are compared as written.
+
+use std::collections::HashMap;
+
+/// Scripts told apart, with their display names
+const SCRIPTS: &[(&str, &str)] = &[
+    ("Latn", "Latin"),
+    ("Cyrl", "Cyrillic"),
+    ("Grek", "Greek"),
+    ("Arab", "Arabic"),
+    ("Hebr", "Hebrew"),
+    ("Deva", "Devanagari"),
+    ("Thai", "Thai"),
+    ("Hang", "Hangul"),
+    ("Jpan", "Japanese"),
+    ("Hani", "Han"),
+];
+
+/// Script of a letter, if it is one of `SCRIPTS`
+fn char_script(c: char) -> Option<&'static str> {
+    if !c.is_alphabetic() {
+        return None;
+    }
+    Some(match c as u32 {
+        0x0041..=0x024F | 0x1E00..=0x1EFF => "Latn",
+        0x0370..=0x03FF | 0x1F00..=0x1FFF => "Grek",
+        0x0400..=0x052F => "Cyrl",
+        0x0590..=0x05FF => "Hebr",
+        0x0600..=0x06FF | 0x0750..=0x077F => "Arab",
+        0x0900..=0x097F => "Deva",
+        0x0E00..=0x0E7F => "Thai",
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => "Hang",
+        0x3040..=0x30FF => "Jpan",
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF => "Hani",
+        _ => return None,
+    })
+}
+
+/// The script most of the text's letters are in, or None if it has no
+/// letters. Han characters next to kana are Japanese.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for script in text.chars().filter_map(char_script) {
+        *counts.entry(script).or_default() += 1;
+    }
+    if let (Some(han), Some(kana)) = (counts.get("Hani").copied(), counts.get_mut("Jpan")) {
+        *kana += han;
+        counts.remove("Hani");
+    }
+    SCRIPTS
+        .iter()
+        .filter_map(|(code, _)| counts.get(code).map(|n| (*code, *n)))
+        .max_by_key(|(_, n)| *n)
+        .map(|(code, _)| code)
+}
+
+/// Display name of a script code
+pub fn script_name(code: &str) -> &str {
+    SCRIPTS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map_or(code, |(_, name)| name)
+}
+
+/// Latin spelling of a lowercase Cyrillic, Greek or accented Latin letter
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        // Cyrillic (Russian, Ukrainian)
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' | 'ґ' => "g",
+        'д' => "d",
+        'е' | 'ё' | 'э' => "e",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' => "i",
+        'ї' => "yi",
+        'й' | 'ы' => "y",
+        'к' => "k",
+        'л' => "l",
+        'м' => "m",
+        'н' => "n",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+        // Greek
+        'α' | 'ά' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' | 'έ' => "e",
+        'ζ' => "z",
+        'η' | 'ή' | 'ι' | 'ί' | 'ϊ' | 'ΐ' => "i",
+        'θ' => "th",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' | 'ό' | 'ω' | 'ώ' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' | 'ύ' | 'ϋ' | 'ΰ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+        // Accented Latin
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ă' | 'ą' | 'ā' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'č' => "c",
+        'ď' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ę' | 'ě' | 'ē' => "e",
+        'ğ' => "g",
+        'ì' | 'í' | 'î' | 'ï' | 'ı' | 'ī' => "i",
+        'ł' => "l",
+        'ñ' | 'ń' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ő' | 'ō' => "o",
+        'œ' => "oe",
+        'ř' => "r",
+        'ś' | 'š' | 'ş' | 'ș' => "s",
+        'ß' => "ss",
+        'ť' | 'ţ' | 'ț' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ů' | 'ű' | 'ū' => "u",
+        'ý' | 'ÿ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+/// Text in the form keyword search compares: lowercase, Cyrillic and Greek
+/// in Latin letters, without accents
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match transliterate(c) {
+            Some(latin) => folded.push_str(latin),
+            None => folded.push(c),
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("See you at 6!"), Some("Latn"));
+        assert_eq!(detect("Привет, как дела? ok"), Some("Cyrl"));
+        assert_eq!(detect("Καλημέρα"), Some("Grek"));
+        assert_eq!(detect("東京に行きます"), Some("Jpan"));
+        assert_eq!(detect("我们明天见"), Some("Hani"));
+        assert_eq!(detect("12:30 :)"), None);
+        assert_eq!(script_name("Cyrl"), "Cyrillic");
+    }
+
+    #[test]
+    fn test_fold_matches_across_scripts() {
+        assert_eq!(fold("Привет"), "privet");
+        assert_eq!(fold("Щука"), "shchuka");
+        assert_eq!(fold("Καλημέρα"), "kalimera");
+        assert_eq!(fold("Café Zürich"), "cafe zurich");
+        // Scripts without a transliteration stay as they are
+        assert_eq!(fold("שלום"), "שלום");
+
+        assert!(fold("Встреча с Наташей в пятницу").contains(&fold("natashey")));
+    }
+}
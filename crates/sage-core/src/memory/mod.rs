@@ -11,24 +11,52 @@
 
 mod archival_new;
 mod block;
+mod bundle;
 mod compaction;
+mod consolidation;
 mod context;
 mod db;
 mod embedding;
+mod embedding_queue;
+mod facts;
+mod freshness;
+mod fusion;
+mod language;
+mod namespaces;
+mod open_threads;
+mod pins;
+mod provenance;
 mod recall_new;
+mod rerank;
+mod retention;
+mod rethink;
 mod tools;
 
-pub use block::BlockManager;
+pub use block::{BlockManager, EditCause};
+pub use bundle::{ImportReport, MemoryBundle};
 // Use new database-backed managers
 pub use archival_new::ArchivalManager;
-pub use compaction::{CompactionManager, SummaryResult};
-pub use context::ContextManager;
-pub use db::{preference_keys, MemoryDb};
+pub use compaction::{CompactionManager, CompactionPlan, SummaryResult};
+pub use consolidation::Consolidated;
+pub use context::{ContextManager, Section, TokenCounter, Trim};
+pub use db::{preference_keys, FactChange, FactRow, MemoryDb};
 pub use embedding::EmbeddingService;
+pub use embedding_queue::run_embedding_worker;
+pub use facts::{FactManager, FactQueryTool};
+pub use namespaces::{ActiveNamespace, ArchivalNamespaceTool};
+pub use open_threads::{OpenThreads, ResolveOpenThreadTool};
+pub use pins::{
+    describe_pins, parse_pin_command, pin_note, unpin_number, PinCommand, PinMessageTool,
+    MAX_PINNED,
+};
+pub use provenance::{MemorySourceTool, Provenance, ProvenanceTracker};
 pub use recall_new::RecallManager;
+pub use rerank::RerankMode;
+pub use retention::{RetentionPolicy, ARCHIVE_BELOW, DECAY_FACTOR};
 pub use tools::{
-    ArchivalInsertTool, ArchivalSearchTool, ConversationSearchTool, MemoryAppendTool,
-    MemoryInsertTool, MemoryReplaceTool, SetPreferenceTool,
+    ArchivalDeleteTool, ArchivalInsertTool, ArchivalListTool, ArchivalSearchTool,
+    ArchivalUpdateTool, ConversationSearchTool, ForgetTool, MemoryAppendTool,
+    MemoryCreateBlockTool, MemoryInsertTool, MemoryReplaceTool, MemoryUndoTool, SetPreferenceTool,
 };
 
 use anyhow::Result;
@@ -44,24 +72,37 @@ pub const DEFAULT_PERSONA_DESCRIPTION: &str = "The persona block: Stores details
 
 pub const DEFAULT_HUMAN_DESCRIPTION: &str = "The human block: Stores key details about the person you are conversing with, allowing for more personalized and friend-like conversation.";
 
-/// Constants for context management
-/// Note: Kimi K2 supports 256k tokens, but using 100k for faster compaction testing
-pub const DEFAULT_CONTEXT_WINDOW: usize = 100_000;
-#[allow(dead_code)]
-pub const COMPACTION_THRESHOLD: f32 = 0.80; // 80% threshold (80k tokens triggers compaction)
+/// Constants for context management. The context window and compaction
+/// threshold depend on the model (see `model_limits`).
 pub const MIN_MESSAGES_IN_CONTEXT: usize = 20; // Always show at least 20 messages after compaction
 
 /// Main memory manager that coordinates all memory tiers
 #[allow(dead_code)]
 pub struct MemoryManager {
     agent_id: Uuid,
+    /// Owner of core memory, archival memory and preferences: the agent
+    /// itself, or the main agent for a conversation thread
+    core_agent_id: Uuid,
     db: MemoryDb,
     embedding: EmbeddingService,
+    /// Which messages the memories written this turn come from
+    provenance: ProvenanceTracker,
     blocks: BlockManager,
     recall: RecallManager,
     archival: ArchivalManager,
+    facts: FactManager,
     compaction: CompactionManager,
+    /// Questions and topics compaction found left open
+    open_threads: OpenThreads,
     context: ContextManager,
+    /// How memory search results are re-ranked
+    rerank: RerankMode,
+    /// Guest memory: only this many recent messages in context, no
+    /// compaction and no tools that write long-term memory
+    ephemeral_window: Option<usize>,
+    /// Group memory: several people talk to the agent, each with their own
+    /// human block, and history shows who said what
+    group: bool,
     /// Mutex for compaction operations (prevents concurrent compaction)
     compaction_lock: Arc<TokioMutex<()>>,
 }
@@ -75,6 +116,46 @@ impl MemoryManager {
         embedding_api_url: &str,
         embedding_api_key: &str,
         embedding_model: &str,
+    ) -> Result<Self> {
+        Self::connect(
+            agent_id,
+            None,
+            db_url,
+            embedding_api_url,
+            embedding_api_key,
+            embedding_model,
+        )
+    }
+
+    /// Create a memory manager for a conversation thread (see `threads`): it
+    /// keeps its own history and summaries but shares the main agent's core
+    /// memory blocks, archival memory and preferences
+    pub async fn new_thread(
+        agent_id: Uuid,
+        core_agent_id: Uuid,
+        core_blocks: BlockManager,
+        db_url: &str,
+        embedding_api_url: &str,
+        embedding_api_key: &str,
+        embedding_model: &str,
+    ) -> Result<Self> {
+        Self::connect(
+            agent_id,
+            Some((core_agent_id, core_blocks)),
+            db_url,
+            embedding_api_url,
+            embedding_api_key,
+            embedding_model,
+        )
+    }
+
+    fn connect(
+        agent_id: Uuid,
+        core: Option<(Uuid, BlockManager)>,
+        db_url: &str,
+        embedding_api_url: &str,
+        embedding_api_key: &str,
+        embedding_model: &str,
     ) -> Result<Self> {
         // Create shared database connection
         let db = MemoryDb::new(db_url)?;
@@ -84,28 +165,74 @@ impl MemoryManager {
 
         // Create shared embedding service
         let embedding =
-            EmbeddingService::new(embedding_api_url, embedding_api_key, embedding_model);
+            EmbeddingService::new(embedding_api_url, embedding_api_key, embedding_model)
+                .for_agent(agent_id);
 
         // Initialize memory tiers - BlockManager now uses database
-        let blocks = BlockManager::new(agent_id, db.clone())?;
+        let (core_agent_id, blocks) = match core {
+            Some(core) => core,
+            None => (agent_id, BlockManager::new(agent_id, db.clone())?),
+        };
         let recall = RecallManager::new(agent_id, db.clone(), embedding.clone());
-        let archival = ArchivalManager::new(agent_id, db.clone(), embedding.clone());
+        let archival = ArchivalManager::new(core_agent_id, db.clone(), embedding.clone());
+        let facts = FactManager::new(agent_id, core_agent_id, db.clone());
         let compaction = CompactionManager::new();
-        let context = ContextManager::new(DEFAULT_CONTEXT_WINDOW);
+        let open_threads = OpenThreads::new(db.clone(), agent_id);
+        let limits = crate::model_limits::current();
+        let context = ContextManager::with_threshold(
+            limits.input_budget(),
+            crate::model_limits::COMPACTION_THRESHOLD,
+        );
 
         Ok(Self {
             agent_id,
+            core_agent_id,
             db,
             embedding,
+            provenance: ProvenanceTracker::default(),
             blocks,
             recall,
             archival,
+            facts,
             compaction,
+            open_threads,
             context,
+            rerank: RerankMode::Off,
+            ephemeral_window: None,
+            group: false,
             compaction_lock: Arc::new(TokioMutex::new(())),
         })
     }
 
+    /// Re-rank `archival_search` and `conversation_search` results
+    pub fn with_rerank(mut self, rerank: RerankMode) -> Self {
+        self.rerank = rerank;
+        self
+    }
+
+    /// Keep only the last `window` messages in context and write no
+    /// long-term memory (guest contexts)
+    pub fn with_ephemeral_window(mut self, window: usize) -> Self {
+        self.ephemeral_window = Some(window.max(1));
+        self
+    }
+
+    /// Whether this is a guest's ephemeral memory
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral_window.is_some()
+    }
+
+    /// Memory of a group chat (per-participant human blocks)
+    pub fn with_group(mut self) -> Self {
+        self.group = true;
+        self
+    }
+
+    /// Whether this is a group chat's memory
+    pub fn is_group(&self) -> bool {
+        self.group
+    }
+
     /// Get the agent ID
     pub fn agent_id(&self) -> Uuid {
         self.agent_id
@@ -113,13 +240,17 @@ impl MemoryManager {
 
     /// Store a message in recall memory with embedding
     pub async fn store_message(&self, user_id: &str, role: &str, content: &str) -> Result<Uuid> {
-        self.recall.add_message(user_id, role, content).await
+        let message_id = self.recall.add_message(user_id, role, content).await?;
+        self.provenance.note_message(message_id, role);
+        Ok(message_id)
     }
 
     /// Store a message WITHOUT embedding (fast, synchronous)
-    /// Use update_message_embedding() in background to add embedding later
+    /// The embedding queue adds the embedding in the background
     pub fn store_message_sync(&self, user_id: &str, role: &str, content: &str) -> Result<Uuid> {
-        self.recall.add_message_sync(user_id, role, content)
+        let message_id = self.recall.add_message_sync(user_id, role, content)?;
+        self.provenance.note_message(message_id, role);
+        Ok(message_id)
     }
 
     /// Store a message with optional image attachment description (fast, synchronous)
@@ -130,13 +261,14 @@ impl MemoryManager {
         content: &str,
         attachment_text: Option<&str>,
     ) -> Result<Uuid> {
-        self.recall
-            .add_message_sync_with_attachment(user_id, role, content, attachment_text)
-    }
-
-    /// Update embedding for a message (call in background after store_message_sync)
-    pub async fn update_message_embedding(&self, message_id: Uuid, content: &str) -> Result<()> {
-        self.recall.update_embedding(message_id, content).await
+        let message_id = self.recall.add_message_sync_with_attachment(
+            user_id,
+            role,
+            content,
+            attachment_text,
+        )?;
+        self.provenance.note_message(message_id, role);
+        Ok(message_id)
     }
 
     /// Get recent messages from recall memory with timestamps
@@ -172,28 +304,101 @@ impl MemoryManager {
             ));
         }
 
+        // How old each core block is, so stale facts get verified
+        let now = chrono::Utc::now();
+        let mut blocks = self.blocks.all();
+        blocks.sort_by(|a, b| a.label.cmp(&b.label));
+        for block in blocks.iter().filter(|b| !b.value.trim().is_empty()) {
+            s.push_str(&freshness::block_line(&block.label, block.updated_at, now));
+            s.push('\n');
+        }
+
         s.push_str(&format!(
             "- {} messages in recall memory (use conversation_search to access)\n",
             recall_count
         ));
+        if let Ok(Some(script)) = self.db.messages().script_of_record(self.agent_id) {
+            s.push_str(&format!(
+                "- The user mostly writes in {} script (keyword search also matches Latin transliterations)\n",
+                language::script_name(&script)
+            ));
+        }
         s.push_str(&format!(
             "- {} passages in archival memory (use archival_search to access)",
             archival_count
         ));
+        let fact_count = self.facts.count();
+        if fact_count > 0 {
+            s.push_str(&format!(
+                "\n- {} structured facts about the user (use fact_query to access)",
+                fact_count
+            ));
+        }
 
         s
     }
 
-    /// Get all memory tools for the agent
+    /// Get all memory tools for the agent. Ephemeral memory only gets
+    /// `conversation_search`.
     pub fn tools(&self) -> Vec<Arc<dyn Tool>> {
+        if self.is_ephemeral() {
+            return vec![Arc::new(
+                ConversationSearchTool::new(self.recall.clone()).with_rerank(self.rerank),
+            )];
+        }
+        let provenance =
+            Provenance::new(self.db.clone(), self.core_agent_id, self.provenance.clone());
+        // Per conversation, so a thread can work in its own namespace
+        let namespace = ActiveNamespace::new(self.db.clone(), self.agent_id);
         vec![
-            Arc::new(MemoryReplaceTool::new(self.blocks.clone())),
-            Arc::new(MemoryAppendTool::new(self.blocks.clone())),
-            Arc::new(MemoryInsertTool::new(self.blocks.clone())),
-            Arc::new(ConversationSearchTool::new(self.recall.clone())),
-            Arc::new(ArchivalInsertTool::new(self.archival.clone())),
-            Arc::new(ArchivalSearchTool::new(self.archival.clone())),
-            Arc::new(SetPreferenceTool::new(self.db.clone(), self.agent_id)),
+            Arc::new(MemoryReplaceTool::new(
+                self.blocks.clone(),
+                provenance.clone(),
+            )),
+            Arc::new(MemoryAppendTool::new(
+                self.blocks.clone(),
+                provenance.clone(),
+            )),
+            Arc::new(MemoryInsertTool::new(
+                self.blocks.clone(),
+                provenance.clone(),
+            )),
+            Arc::new(MemoryUndoTool::new(self.blocks.clone())),
+            Arc::new(MemoryCreateBlockTool::new(self.blocks.clone())),
+            Arc::new(ConversationSearchTool::new(self.recall.clone()).with_rerank(self.rerank)),
+            Arc::new(ArchivalInsertTool::new(
+                self.archival.clone(),
+                provenance.clone(),
+                namespace.clone(),
+            )),
+            Arc::new(
+                ArchivalSearchTool::new(self.archival.clone(), namespace.clone())
+                    .with_rerank(self.rerank),
+            ),
+            Arc::new(ArchivalUpdateTool::new(
+                self.archival.clone(),
+                provenance.clone(),
+            )),
+            Arc::new(ArchivalDeleteTool::new(self.archival.clone())),
+            Arc::new(ArchivalListTool::new(
+                self.archival.clone(),
+                namespace.clone(),
+            )),
+            Arc::new(ArchivalNamespaceTool::new(self.archival.clone(), namespace)),
+            Arc::new(FactQueryTool::new(self.facts.clone())),
+            Arc::new(ForgetTool::new(
+                self.archival.clone(),
+                self.recall.clone(),
+                self.facts.clone(),
+            )),
+            Arc::new(SetPreferenceTool::new(
+                self.db.clone(),
+                self.core_agent_id,
+                provenance,
+            )),
+            Arc::new(MemorySourceTool::new(self.db.clone(), self.core_agent_id)),
+            Arc::new(PinMessageTool::new(self.db.clone(), self.agent_id)),
+            Arc::new(ResolveOpenThreadTool::new(self.open_threads.clone())),
         ]
     }
 
@@ -202,7 +407,7 @@ impl MemoryManager {
         Ok(self
             .db
             .preferences()
-            .get(self.agent_id, key)?
+            .get(self.core_agent_id, key)?
             .map(|p| p.value))
     }
 
@@ -222,11 +427,38 @@ impl MemoryManager {
         self.db.summaries().get_latest(self.agent_id)
     }
 
+    /// Summary of the conversation before the context messages: the
+    /// unrolled summaries compiled oldest first (see `compaction`), with the
+    /// boundary and id of the newest
+    pub fn get_context_summary(&self) -> Result<Option<SummaryRow>> {
+        let unrolled = self.db.summaries().unrolled(self.agent_id)?;
+        let Some(newest) = unrolled.last() else {
+            return Ok(None);
+        };
+        Ok(Some(SummaryRow {
+            from_sequence_id: unrolled[0].from_sequence_id,
+            content: compaction::compile_summaries(&unrolled),
+            ..newest.clone()
+        }))
+    }
+
+    /// Open threads, listed after the context summary (see `open_threads`)
+    pub fn open_threads_section(&self) -> Result<Option<String>> {
+        Ok(open_threads::context_section(&self.open_threads.open()?))
+    }
+
     /// Get messages for context building
     /// - No summary yet: Load ALL messages (need to build up to hit compaction threshold)
     /// - Has summary: Load messages after summary boundary, with minimum of MIN_MESSAGES_IN_CONTEXT
     pub fn get_context_messages(&self) -> Result<(Option<SummaryRow>, Vec<MessageRow>)> {
-        let summary = self.get_latest_summary()?;
+        if let Some(window) = self.ephemeral_window {
+            let messages = self
+                .db
+                .messages()
+                .get_recent(self.agent_id, window as i64)?;
+            return Ok((None, messages));
+        }
+        let summary = self.get_context_summary()?;
 
         let messages = if let Some(ref s) = summary {
             // Has summary - get messages after summary boundary
@@ -237,13 +469,15 @@ impl MemoryManager {
             )?;
 
             // Ensure minimum messages for context continuity (some may overlap with summary)
-            if after_summary.len() < MIN_MESSAGES_IN_CONTEXT {
+            let recent = if after_summary.len() < MIN_MESSAGES_IN_CONTEXT {
                 self.db
                     .messages()
                     .get_recent(self.agent_id, MIN_MESSAGES_IN_CONTEXT as i64)?
             } else {
                 after_summary
-            }
+            };
+            // Pinned messages were left out of the summary (see `pins`)
+            pins::merge_pinned(self.db.messages().pinned(self.agent_id)?, recent)
         } else {
             // No summary yet - load ALL messages so we can build up to compaction threshold
             // Without this, we'd never accumulate enough context to trigger compaction
@@ -263,20 +497,17 @@ impl MemoryManager {
     ) -> Result<(Uuid, bool)> {
         // Store the message first
         let message_id = self.recall.add_message(user_id, role, content).await?;
+        self.provenance.note_message(message_id, role);
 
         // Check if compaction is needed (estimate tokens)
         let (summary, messages) = self.get_context_messages()?;
         let current_tokens = self.estimate_context_tokens(&summary, &messages);
 
-        let compacted = if self.compaction.should_compact(
-            current_tokens,
-            DEFAULT_CONTEXT_WINDOW,
-            COMPACTION_THRESHOLD,
-        ) {
+        let compacted = if !self.is_ephemeral() && self.context.needs_compaction(current_tokens) {
             tracing::info!(
                 "Context tokens ({}) exceed threshold ({}), triggering compaction",
                 current_tokens,
-                (DEFAULT_CONTEXT_WINDOW as f32 * COMPACTION_THRESHOLD) as usize
+                self.context.threshold_tokens()
             );
             self.run_compaction().await?;
             true
@@ -287,15 +518,11 @@ impl MemoryManager {
         Ok((message_id, compacted))
     }
 
-    /// Run compaction with mutex lock to prevent concurrent compaction
-    pub async fn run_compaction(&self) -> Result<SummaryResult> {
-        // Acquire compaction lock
-        let _lock = self.compaction_lock.lock().await;
-        tracing::info!("Acquired compaction lock, starting compaction");
-
-        // Get current state
-        let current_summary = self.get_latest_summary()?;
-        let summary_boundary = current_summary
+    /// What compaction would take out of context: the context summary, the
+    /// messages after it, and how many of the oldest of those it summarizes
+    fn compaction_batch(&self) -> Result<(Option<SummaryRow>, Vec<MessageRow>, usize)> {
+        let context_summary = self.get_context_summary()?;
+        let summary_boundary = context_summary
             .as_ref()
             .map(|s| s.to_sequence_id)
             .unwrap_or(0);
@@ -321,28 +548,67 @@ impl MemoryManager {
                 MIN_MESSAGES_IN_CONTEXT
             );
         }
+        if messages[..to_summarize_count].iter().all(|m| m.pinned) {
+            anyhow::bail!("Nothing to compact: the oldest messages are all pinned");
+        }
+
+        Ok((context_summary, messages, to_summarize_count))
+    }
+
+    /// What `run_compaction` would summarize now and the tokens it would
+    /// save, without calling the model. Errors like `run_compaction` when
+    /// there is nothing to compact.
+    pub fn preview_compaction(&self) -> Result<CompactionPlan> {
+        if self.is_ephemeral() {
+            anyhow::bail!("Guest memory is not compacted");
+        }
+        let (_, messages, to_summarize_count) = self.compaction_batch()?;
+        let (summary, context) = self.get_context_messages()?;
+        Ok(CompactionPlan::new(
+            &messages[..to_summarize_count],
+            messages.len() - to_summarize_count,
+            self.estimate_context_tokens(&summary, &context),
+            self.context.threshold_tokens(),
+        ))
+    }
 
+    /// Run compaction with mutex lock to prevent concurrent compaction
+    pub async fn run_compaction(&self) -> Result<SummaryResult> {
+        if self.is_ephemeral() {
+            anyhow::bail!("Guest memory is not compacted");
+        }
+        // Acquire compaction lock
+        let _lock = self.compaction_lock.lock().await;
+        tracing::info!("Acquired compaction lock, starting compaction");
+
+        // Get current state
+        let current_summary = self.get_latest_summary()?;
+        let (context_summary, messages, to_summarize_count) = self.compaction_batch()?;
         let messages_to_summarize = &messages[..to_summarize_count];
         let from_sequence_id = messages_to_summarize.first().unwrap().sequence_id;
         let to_sequence_id = messages_to_summarize.last().unwrap().sequence_id;
+        // Pinned messages stay in context verbatim instead (see `pins`)
+        let summarized: Vec<&MessageRow> =
+            messages_to_summarize.iter().filter(|m| !m.pinned).collect();
 
         tracing::info!(
-            "Compacting {} messages (sequence {} to {}), keeping {} in context",
-            to_summarize_count,
+            "Compacting {} messages (sequence {} to {}, {} pinned kept), keeping {} in context",
+            summarized.len(),
             from_sequence_id,
             to_sequence_id,
+            to_summarize_count - summarized.len(),
             messages.len() - to_summarize_count
         );
 
         // Format messages for summarization
-        let new_messages = messages_to_summarize
+        let new_messages = summarized
             .iter()
             .map(|m| format!("[{}]: {}", m.role, m.content))
             .collect::<Vec<_>>()
             .join("\n---\n");
 
-        // Get previous summary content
-        let previous_summary = current_summary
+        // Earlier context for the summarizer
+        let previous_summary = context_summary
             .as_ref()
             .map(|s| s.content.as_str())
             .unwrap_or("");
@@ -360,8 +626,40 @@ impl MemoryManager {
             )
             .await?;
 
-        // Generate embedding for the summary
-        let embedding = self.embedding.embed(&result.summary).await?;
+        // Embed the summary, and in the same request the compacted messages
+        // still waiting for the embedding queue: once out of context they
+        // are only found by search
+        let queued = self
+            .db
+            .embedding_jobs()
+            .queued(
+                &messages_to_summarize
+                    .iter()
+                    .map(|m| m.id)
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap_or_default();
+        let pending: Vec<&MessageRow> = messages_to_summarize
+            .iter()
+            .filter(|m| queued.contains(&m.id))
+            .collect();
+        let pending_texts: Vec<String> = pending
+            .iter()
+            .map(|m| embedding_queue::embed_text(m))
+            .collect();
+        let texts: Vec<&str> = std::iter::once(result.summary.as_str())
+            .chain(pending_texts.iter().map(String::as_str))
+            .collect();
+        let mut embeddings = self.embedding.embed_batch(&texts).await?.into_iter();
+        let embedding = embeddings
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No embedding for the summary"))?;
+        for (message, vector) in pending.into_iter().zip(embeddings) {
+            // Zero vectors mean the request failed; the queue retries those
+            if vector.iter().any(|&x| x != 0.0) {
+                embedding_queue::store(&self.db, &self.embedding, message, &vector).await;
+            }
+        }
 
         // Store the summary in the database
         self.db.summaries().insert_summary(
@@ -379,9 +677,55 @@ impl MemoryManager {
             result.to_sequence_id
         );
 
+        // The level-1 summary is stored either way; a failed rollup is
+        // retried after the next compaction
+        if let Err(e) = self.roll_up_summaries().await {
+            tracing::warn!("Summary rollup failed: {}", e);
+        }
+
+        // Questions the summary may lose; a failure costs only the threads
+        match self.open_threads.extract(&summarized).await {
+            Ok(changes) if changes.added + changes.resolved > 0 => tracing::info!(
+                "Open threads: {} added, {} resolved",
+                changes.added,
+                changes.resolved
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Open thread extraction failed: {}", e),
+        }
+
         Ok(result)
     }
 
+    /// Roll full levels of unrolled summaries into summaries a level up,
+    /// until no level has `ROLLUP_SIZE` waiting. Returns how many rollups
+    /// were stored.
+    async fn roll_up_summaries(&self) -> Result<usize> {
+        let mut stored = 0;
+        while let Some((level, batch)) =
+            compaction::next_rollup(&self.db.summaries().unrolled(self.agent_id)?)
+        {
+            let content = self.compaction.roll_up(&batch).await?;
+            let embedding = self.embedding.embed(&content).await?;
+            self.db.summaries().insert_rollup(
+                self.agent_id,
+                level,
+                &content,
+                &embedding,
+                &batch,
+            )?;
+            tracing::info!(
+                "Rolled {} summaries (sequence {} to {}) into a level-{} summary",
+                batch.len(),
+                batch[0].from_sequence_id,
+                batch[batch.len() - 1].to_sequence_id,
+                level
+            );
+            stored += 1;
+        }
+        Ok(stored)
+    }
+
     /// Estimate token count for context (summary + messages)
     fn estimate_context_tokens(
         &self,
@@ -390,11 +734,8 @@ impl MemoryManager {
     ) -> usize {
         // Rough estimate: ~4 chars per token
         let summary_chars = summary.as_ref().map(|s| s.content.len()).unwrap_or(0);
-        let message_chars: usize = messages
-            .iter()
-            .map(|m| m.content.len() + m.role.len() + 10)
-            .sum();
-        (summary_chars + message_chars) / 4
+        let message_chars: usize = messages.iter().map(compaction::message_chars).sum();
+        compaction::estimate_tokens(summary_chars + message_chars)
     }
 
     /// Search summaries by semantic similarity
@@ -409,6 +750,168 @@ impl MemoryManager {
             .search_by_embedding(self.agent_id, &embedding, limit as i64)
     }
 
+    /// Export core blocks, archival passages, summaries and preferences as a
+    /// portable bundle (see `bundle`)
+    pub fn export(&self) -> Result<MemoryBundle> {
+        let mut blocks: Vec<bundle::BundleBlock> = self
+            .blocks
+            .all()
+            .into_iter()
+            .map(|block| bundle::BundleBlock {
+                label: block.label,
+                value: block.value,
+                limit: block.char_limit,
+                description: block.description,
+                read_only: block.read_only,
+            })
+            .collect();
+        blocks.sort_by(|a, b| a.label.cmp(&b.label));
+
+        let passages = self
+            .db
+            .passages()
+            .export_passages(&self.core_agent_id.to_string())?
+            .into_iter()
+            .map(|(row, importance, expires_at)| bundle::BundlePassage {
+                text: row.content,
+                tags: row.tags,
+                created_at: Some(row.created_at),
+                importance: Some(importance),
+                expires_at,
+                namespace: row.namespace,
+            })
+            .collect();
+        let summaries = self
+            .db
+            .summaries()
+            .all_summaries(self.agent_id)?
+            .into_iter()
+            .map(|row| bundle::BundleSummary {
+                content: row.content,
+                created_at: Some(row.created_at),
+            })
+            .collect();
+        let preferences = self
+            .db
+            .preferences()
+            .get_all(self.core_agent_id)?
+            .into_iter()
+            .map(|p| (p.key, p.value))
+            .collect();
+
+        Ok(MemoryBundle {
+            format: bundle::BUNDLE_FORMAT.to_string(),
+            version: bundle::BUNDLE_VERSION,
+            exported_at: Some(chrono::Utc::now()),
+            blocks,
+            passages,
+            summaries,
+            preferences,
+        })
+    }
+
+    /// Merge a bundle into this agent's memory (see `bundle`). Entries that
+    /// can't be stored are skipped and listed in the report; a failing
+    /// embedding request aborts the import.
+    pub async fn import(&self, bundle: &MemoryBundle) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+
+        for entry in &bundle.blocks {
+            let label = entry.label.trim();
+            if label.is_empty() {
+                report.skipped.push("block without a label".to_string());
+                continue;
+            }
+            let mut block = self
+                .blocks
+                .get(label)
+                .unwrap_or_else(|| block::Block::new(self.core_agent_id, label));
+            block.value = entry.value.clone();
+            block.char_limit = entry.limit.max(1);
+            block.description = entry.description.clone().or(block.description.take());
+            block.read_only = entry.read_only;
+            match self.blocks.put(block, &block::EditCause::other("import")) {
+                Ok(()) => report.blocks += 1,
+                Err(e) => report.skipped.push(format!("block {}: {}", label, e)),
+            }
+        }
+
+        let existing: std::collections::HashSet<String> = self
+            .db
+            .passages()
+            .export_passages(&self.core_agent_id.to_string())?
+            .into_iter()
+            .map(|(row, _, _)| row.content)
+            .collect();
+        let now = chrono::Utc::now();
+        for passage in &bundle.passages {
+            let text = passage.text.trim();
+            if text.is_empty() || passage.expires_at.is_some_and(|at| at <= now) {
+                continue;
+            }
+            if existing.contains(text) {
+                report.duplicate_passages += 1;
+                continue;
+            }
+            let tags = (!passage.tags.is_empty()).then(|| passage.tags.clone());
+            let inserted = self
+                .archival
+                .insert_in(text, tags, passage.namespace.as_deref())
+                .await?;
+            self.archival.set_retention(
+                &inserted.ids,
+                Some(passage.importance()),
+                passage.expires_at,
+            )?;
+            report.passages += 1;
+        }
+
+        if !bundle.summaries.is_empty() {
+            let texts: Vec<&str> = bundle
+                .summaries
+                .iter()
+                .map(|s| s.content.as_str())
+                .collect();
+            let embeddings = self.embedding.embed_batch(&texts).await?;
+            let first_own = self.db.summaries().min_from_sequence_id(self.agent_id)?;
+            let anchors = bundle::summary_anchors(texts.len(), first_own);
+            let mut previous = None;
+            let mut imported = Vec::new();
+            for ((content, embedding), anchor) in texts.iter().zip(&embeddings).zip(anchors) {
+                let id = self.db.summaries().insert_summary(
+                    self.agent_id,
+                    anchor,
+                    anchor,
+                    content,
+                    embedding,
+                    previous,
+                )?;
+                previous = Some(id);
+                imported.push(id);
+                report.summaries += 1;
+            }
+            // Like a chain written before summary levels, the newest
+            // imported summary stands for the older ones
+            if let Some((newest, older)) = imported.split_last() {
+                self.db.summaries().mark_rolled(older, *newest)?;
+            }
+        }
+
+        for (key, value) in &bundle.preferences {
+            match self.db.preferences().set(self.core_agent_id, key, value) {
+                Ok(_) => report.preferences += 1,
+                Err(e) => report.skipped.push(format!("preference {}: {}", key, e)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Context window state and per-section budget (see `context`)
+    pub fn context(&self) -> &ContextManager {
+        &self.context
+    }
+
     /// Get a mutable reference to the block manager
     pub fn blocks_mut(&mut self) -> &mut BlockManager {
         &mut self.blocks
@@ -429,6 +932,11 @@ impl MemoryManager {
         &self.archival
     }
 
+    /// Get a reference to the fact manager
+    pub fn facts(&self) -> &FactManager {
+        &self.facts
+    }
+
     /// Get a reference to the database
     pub fn db(&self) -> &MemoryDb {
         &self.db
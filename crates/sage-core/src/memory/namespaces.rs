@@ -0,0 +1,233 @@
+//! Archival Namespaces
+//!
+//! Passages can be filed under a namespace ("work", "personal",
+//! "project-apollo") so work notes don't come up in a personal conversation
+//! and the other way around. Each conversation has an active namespace, its
+//! `archival_namespace` preference (on the conversation's own agent, so a
+//! `/topic` thread can work in another one than its chat). While one is
+//! active:
+//! - `archival_insert` files new passages under it
+//! - `archival_search` and `archival_list` see its passages and those in no
+//!   namespace, never another namespace's
+//!
+//! Their `namespace` argument overrides the active one for one call (`all`
+//! searches everything, `none` inserts without a namespace). With no active
+//! namespace, everything is visible as before. The agent switches with
+//! `archival_namespace`, which also lists the namespaces in use and moves
+//! passages between them. Passages stored by other features (forwarded
+//! email, deep research) have no namespace.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::archival_new::ArchivalManager;
+use super::db::MemoryDb;
+use crate::sage_agent::{Tool, ToolResult};
+
+/// Preference holding a conversation's active namespace
+pub const PREFERENCE: &str = "archival_namespace";
+
+/// Longest namespace name
+pub const MAX_NAME_CHARS: usize = 32;
+
+/// A namespace name as stored: lowercase words joined by hyphens
+/// ("Project Apollo" is `project-apollo`)
+pub fn normalize(name: &str) -> Result<String> {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let normalized = words.join("-");
+    if normalized.is_empty() {
+        anyhow::bail!("A namespace name needs letters or digits, like 'work' or 'project-apollo'");
+    }
+    if normalized.chars().count() > MAX_NAME_CHARS {
+        anyhow::bail!("Namespace names are at most {} characters", MAX_NAME_CHARS);
+    }
+    if matches!(normalized.as_str(), "all" | "none") {
+        anyhow::bail!("'{}' can't be a namespace name", normalized);
+    }
+    Ok(normalized)
+}
+
+/// Whether a `namespace` argument means every namespace (or none at all)
+fn is_unscoped(arg: &str) -> bool {
+    matches!(
+        arg.trim().to_lowercase().as_str(),
+        "all" | "none" | "off" | "clear"
+    )
+}
+
+/// The namespace a call works in: `arg` if given (`all`/`none` = no
+/// namespace), else the conversation's active one
+pub fn resolve(arg: Option<&str>, active: Option<String>) -> Result<Option<String>> {
+    match arg.map(str::trim).filter(|a| !a.is_empty()) {
+        Some(arg) if is_unscoped(arg) => Ok(None),
+        Some(arg) => normalize(arg).map(Some),
+        None => Ok(active),
+    }
+}
+
+/// A conversation's active namespace
+#[derive(Clone)]
+pub struct ActiveNamespace {
+    db: MemoryDb,
+    agent_id: Uuid,
+}
+
+impl ActiveNamespace {
+    pub fn new(db: MemoryDb, agent_id: Uuid) -> Self {
+        Self { db, agent_id }
+    }
+
+    pub fn get(&self) -> Result<Option<String>> {
+        Ok(self
+            .db
+            .preferences()
+            .get(self.agent_id, PREFERENCE)?
+            .map(|p| p.value))
+    }
+
+    /// Switch to `namespace` (None = no active namespace)
+    pub fn set(&self, namespace: Option<&str>) -> Result<()> {
+        match namespace {
+            Some(namespace) => {
+                self.db
+                    .preferences()
+                    .set(self.agent_id, PREFERENCE, namespace)?;
+            }
+            None => {
+                self.db.preferences().delete(self.agent_id, PREFERENCE)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The namespace a tool call works in (see `resolve`)
+    pub fn resolve(&self, args: &HashMap<String, String>) -> Result<Option<String>> {
+        resolve(args.get("namespace").map(String::as_str), self.get()?)
+    }
+}
+
+// ============================================================================
+// archival_namespace
+// ============================================================================
+
+/// Switch, list or move passages between archival namespaces
+pub struct ArchivalNamespaceTool {
+    archival: ArchivalManager,
+    active: ActiveNamespace,
+}
+
+impl ArchivalNamespaceTool {
+    pub fn new(archival: ArchivalManager, active: ActiveNamespace) -> Self {
+        Self { archival, active }
+    }
+
+    fn describe(&self) -> Result<String> {
+        let active = match self.active.get()? {
+            Some(namespace) => format!("Active namespace: {}.", namespace),
+            None => "No namespace is active: archival memory is searched as a whole.".to_string(),
+        };
+        let namespaces = self.archival.namespaces()?;
+        if namespaces.is_empty() {
+            return Ok(format!("{} No passages are in a namespace yet.", active));
+        }
+        let list: Vec<String> = namespaces
+            .iter()
+            .map(|(namespace, count)| format!("{} ({} passages)", namespace, count))
+            .collect();
+        Ok(format!("{} Namespaces: {}", active, list.join(", ")))
+    }
+}
+
+#[async_trait]
+impl Tool for ArchivalNamespaceTool {
+    fn name(&self) -> &str {
+        "archival_namespace"
+    }
+
+    fn description(&self) -> &str {
+        "Switch this conversation's archival namespace (e.g. 'work', 'personal', 'project-apollo') so work notes and personal memories stay apart. While one is active, archival_insert stores into it and archival_search/archival_list see only it plus memories in no namespace. Call without arguments to see the active namespace and those in use; with ids, move those passages into the namespace instead."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"namespace": "namespace to switch to, or 'none' to stop using one; omit to list", "ids": "optional comma-separated passage ids to move into the namespace instead of switching"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let Some(arg) = args
+            .get("namespace")
+            .map(|n| n.trim())
+            .filter(|n| !n.is_empty())
+        else {
+            return Ok(ToolResult::success(self.describe()?));
+        };
+        let namespace = match resolve(Some(arg), None) {
+            Ok(namespace) => namespace,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        if let Some(ids) = args.get("ids").filter(|ids| !ids.trim().is_empty()) {
+            let ids: Vec<Uuid> = match ids
+                .split(',')
+                .map(|id| Uuid::parse_str(id.trim()))
+                .collect()
+            {
+                Ok(ids) => ids,
+                Err(_) => {
+                    return Ok(ToolResult::error(format!(
+                        "'{}' is not a list of passage ids",
+                        ids
+                    )))
+                }
+            };
+            let moved = self.archival.set_namespace(&ids, namespace.as_deref())?;
+            return Ok(ToolResult::success(match namespace {
+                Some(namespace) => format!("Moved {} passages into '{}'.", moved, namespace),
+                None => format!("Moved {} passages out of their namespace.", moved),
+            }));
+        }
+
+        self.active.set(namespace.as_deref())?;
+        Ok(ToolResult::success(match namespace {
+            Some(namespace) => format!(
+                "Active namespace: {}. New archival memories go there, and searches see it plus memories in no namespace.",
+                namespace
+            ),
+            None => "No namespace is active now: archival memory is searched as a whole.".to_string(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("Work").unwrap(), "work");
+        assert_eq!(normalize("  Project Apollo! ").unwrap(), "project-apollo");
+        assert_eq!(normalize("side_project 2").unwrap(), "side-project-2");
+        assert!(normalize("--").is_err());
+        assert!(normalize("All").is_err());
+        assert!(normalize(&"x".repeat(MAX_NAME_CHARS + 1)).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let active = Some("work".to_string());
+        assert_eq!(resolve(None, active.clone()).unwrap(), active);
+        assert_eq!(resolve(Some(" "), active.clone()).unwrap(), active);
+        assert_eq!(
+            resolve(Some("Personal"), active.clone()).unwrap(),
+            Some("personal".to_string())
+        );
+        assert_eq!(resolve(Some("all"), active.clone()).unwrap(), None);
+        assert_eq!(resolve(Some("none"), active).unwrap(), None);
+        assert_eq!(resolve(None, None).unwrap(), None);
+    }
+}
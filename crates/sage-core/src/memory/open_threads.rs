@@ -0,0 +1,322 @@
+//! Open Threads
+//!
+//! A compaction summary keeps the gist of old messages, but a question the
+//! user asked three weeks ago that never got an answer ("can you find out
+//! whether the museum is open on Mondays?") tends to vanish into it. While
+//! compacting, `run_compaction` also asks a model for the questions and
+//! topics the summarized messages leave open (`ExtractOpenThreads`) and
+//! stores them in `open_threads`. The same call is shown the threads already
+//! open and says which of them the messages settled.
+//!
+//! Open threads are listed after the context summary, numbered, oldest
+//! first. The agent follows up when it fits and closes a thread with
+//! `resolve_open_thread` once it is answered or no longer matters. At most
+//! `MAX_OPEN` are open per conversation; new ones past that are dropped, so
+//! the old ones this exists for are not pushed out.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dspy_rs::{Predict, Signature};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::db::{MemoryDb, MessageRow, OpenThreadRow};
+use crate::sage_agent::{Tool, ToolResult};
+use crate::usage::{self, CallKind};
+
+/// Most open threads per conversation
+pub const MAX_OPEN: usize = 15;
+
+/// Longest thread stored
+const MAX_THREAD_CHARS: usize = 200;
+
+/// Instruction for open thread extraction
+pub const EXTRACT_INSTRUCTION: &str = r#"You keep track of loose ends in a conversation between a user and their personal assistant. The messages you get are about to be summarized away, so anything still open in them would be forgotten.
+
+List the questions the user asked that were not answered, requests that were not done, and topics left unresolved ("let me think about it and get back to you", "remind me to decide on the venue"). Write each as one short, self-contained line naming the people, places and things involved, like "Whether the Louvre is open on Mondays (user's Paris trip)". Skip anything answered or done within the messages, small talk, and things already in the open threads list.
+
+Also give the numbers of the open threads that these messages answer or settle. Return empty lists if there is nothing."#;
+
+/// DSRs signature for open thread extraction
+#[derive(Signature, Clone, Debug)]
+pub struct ExtractOpenThreads {
+    #[input(desc = "Threads already open, numbered (empty if none)")]
+    pub open_threads: String,
+
+    #[input(desc = "Conversation messages being summarized away")]
+    pub messages: String,
+
+    #[output(
+        desc = "New unanswered questions or unresolved topics, one short line each (can be empty)"
+    )]
+    pub new_threads: Vec<String>,
+
+    #[output(desc = "Numbers of the open threads these messages answer or settle (can be empty)")]
+    pub resolved: Vec<i64>,
+}
+
+/// One line, clipped; None if empty
+pub fn clean(thread: &str) -> Option<String> {
+    let line = thread.split_whitespace().collect::<Vec<_>>().join(" ");
+    let line = line.trim_start_matches(['-', '*', ' ']).trim();
+    if line.is_empty() {
+        return None;
+    }
+    Some(match line.char_indices().nth(MAX_THREAD_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    })
+}
+
+/// Threads as a numbered list, oldest first
+pub fn numbered(threads: &[OpenThreadRow]) -> String {
+    threads
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            format!(
+                "{}. {} (since {})",
+                i + 1,
+                t.content,
+                t.raised_at.format("%b %-d")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The context section listing `threads` (None if there are none)
+pub fn context_section(threads: &[OpenThreadRow]) -> Option<String> {
+    if threads.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "Open threads (questions and topics from earlier that were never settled; follow up when it fits, and call resolve_open_thread once one is answered or no longer matters):\n{}",
+        numbered(threads)
+    ))
+}
+
+/// Ids of the threads with these 1-based numbers; unknown numbers are
+/// skipped
+pub fn ids_for_numbers(threads: &[OpenThreadRow], numbers: &[i64]) -> Vec<Uuid> {
+    let mut ids: Vec<Uuid> = Vec::new();
+    for n in numbers {
+        let thread = usize::try_from(*n)
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| threads.get(i));
+        if let Some(thread) = thread.filter(|t| !ids.contains(&t.id)) {
+            ids.push(thread.id);
+        }
+    }
+    ids
+}
+
+/// What an extraction changed
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ThreadChanges {
+    pub added: usize,
+    pub resolved: usize,
+}
+
+/// Open threads of one conversation
+#[derive(Clone)]
+pub struct OpenThreads {
+    db: MemoryDb,
+    agent_id: Uuid,
+}
+
+impl OpenThreads {
+    pub fn new(db: MemoryDb, agent_id: Uuid) -> Self {
+        Self { db, agent_id }
+    }
+
+    pub fn open(&self) -> Result<Vec<OpenThreadRow>> {
+        self.db.open_threads().open(self.agent_id, MAX_OPEN as i64)
+    }
+
+    /// Resolve threads by their number in the list
+    pub fn resolve_numbers(&self, numbers: &[i64]) -> Result<usize> {
+        let ids = ids_for_numbers(&self.open()?, numbers);
+        self.db.open_threads().resolve(self.agent_id, &ids)
+    }
+
+    /// Record what the messages being compacted leave open, and resolve
+    /// the open threads they settle
+    pub async fn extract(&self, messages: &[&MessageRow]) -> Result<ThreadChanges> {
+        let Some(raised_at) = messages.first().map(|m| m.created_at) else {
+            return Ok(ThreadChanges::default());
+        };
+        let text = messages
+            .iter()
+            .filter(|m| m.role == "user" || m.role == "assistant")
+            .map(|m| format!("[{}]: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n---\n");
+        if text.is_empty() {
+            return Ok(ThreadChanges::default());
+        }
+
+        let open = self.open()?;
+        let predictor = Predict::<ExtractOpenThreads>::builder()
+            .instruction(EXTRACT_INSTRUCTION)
+            .build();
+        let result = predictor
+            .call_with_meta(ExtractOpenThreadsInput {
+                open_threads: numbered(&open),
+                messages: text,
+            })
+            .await?;
+        usage::record_chat(
+            CallKind::Compaction,
+            result.lm_usage.prompt_tokens as i64,
+            result.lm_usage.completion_tokens as i64,
+        );
+        self.apply(
+            &open,
+            &result.output.new_threads,
+            &result.output.resolved,
+            raised_at,
+        )
+    }
+
+    fn apply(
+        &self,
+        open: &[OpenThreadRow],
+        new_threads: &[String],
+        resolved: &[i64],
+        raised_at: DateTime<Utc>,
+    ) -> Result<ThreadChanges> {
+        let threads = self.db.open_threads();
+        let resolved = threads.resolve(self.agent_id, &ids_for_numbers(open, resolved))?;
+
+        let room = MAX_OPEN.saturating_sub(open.len() - resolved);
+        let mut added = 0;
+        for thread in new_threads.iter().filter_map(|t| clean(t)) {
+            if open.iter().any(|o| o.content.eq_ignore_ascii_case(&thread)) {
+                continue;
+            }
+            if added == room {
+                tracing::info!("Open threads are full; dropping \"{}\"", thread);
+                continue;
+            }
+            threads.insert(self.agent_id, &thread, raised_at)?;
+            added += 1;
+        }
+        Ok(ThreadChanges { added, resolved })
+    }
+}
+
+// ============================================================================
+// resolve_open_thread
+// ============================================================================
+
+/// Close open threads that are answered or no longer matter
+pub struct ResolveOpenThreadTool {
+    threads: OpenThreads,
+}
+
+impl ResolveOpenThreadTool {
+    pub fn new(threads: OpenThreads) -> Self {
+        Self { threads }
+    }
+}
+
+#[async_trait]
+impl Tool for ResolveOpenThreadTool {
+    fn name(&self) -> &str {
+        "resolve_open_thread"
+    }
+
+    fn description(&self) -> &str {
+        "Close open threads (listed after the conversation summary) once they are answered, done or no longer matter, so they stop showing up."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"numbers": "comma-separated numbers of the open threads, as listed"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let Some(arg) = args.get("numbers").filter(|n| !n.trim().is_empty()) else {
+            return Ok(ToolResult::error("Missing required argument: numbers"));
+        };
+        let numbers: Vec<i64> = match arg
+            .split(',')
+            .map(|n| n.trim().trim_start_matches('#').parse())
+            .collect()
+        {
+            Ok(numbers) => numbers,
+            Err(_) => {
+                return Ok(ToolResult::error(format!(
+                    "'{}' is not a list of thread numbers",
+                    arg
+                )))
+            }
+        };
+        let resolved = self.threads.resolve_numbers(&numbers)?;
+        let remaining = self.threads.open()?;
+        Ok(ToolResult::success(
+            match (resolved, remaining.is_empty()) {
+                (0, _) => "No open thread has those numbers.".to_string(),
+                (n, true) => format!("Resolved {} thread(s). No open threads left.", n),
+                (n, false) => format!(
+                    "Resolved {} thread(s). Still open:\n{}",
+                    n,
+                    numbered(&remaining)
+                ),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn thread(content: &str, day: u32) -> OpenThreadRow {
+        let raised_at = Utc.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap();
+        OpenThreadRow {
+            id: Uuid::new_v4(),
+            agent_id: Uuid::nil(),
+            content: content.to_string(),
+            raised_at,
+            created_at: raised_at,
+            resolved_at: None,
+        }
+    }
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(
+            clean("- Whether the  museum\nis open Mondays ").as_deref(),
+            Some("Whether the museum is open Mondays")
+        );
+        assert_eq!(clean("  \n "), None);
+        let long = clean(&"a".repeat(MAX_THREAD_CHARS + 10)).unwrap();
+        assert_eq!(long.chars().count(), MAX_THREAD_CHARS + 3);
+    }
+
+    #[test]
+    fn test_numbered_and_section() {
+        let threads = vec![thread("Venue for the party", 2), thread("Bike repair", 14)];
+        assert_eq!(
+            numbered(&threads),
+            "1. Venue for the party (since Oct 2)\n2. Bike repair (since Oct 14)"
+        );
+        assert!(context_section(&threads)
+            .unwrap()
+            .ends_with("2. Bike repair (since Oct 14)"));
+        assert_eq!(context_section(&[]), None);
+    }
+
+    #[test]
+    fn test_ids_for_numbers() {
+        let threads = vec![thread("a", 1), thread("b", 2), thread("c", 3)];
+        assert_eq!(
+            ids_for_numbers(&threads, &[3, 1, 0, -2, 9, 3]),
+            vec![threads[2].id, threads[0].id]
+        );
+    }
+}
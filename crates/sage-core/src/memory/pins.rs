@@ -0,0 +1,287 @@
+//! Pinned Messages
+//!
+//! Compaction folds the oldest messages into a summary, which can lose the
+//! exact wording of something that has to stay exact ("never book flights
+//! before 9am", a standing instruction for a group). Pinned messages
+//! (`messages.pinned`) are left out of summarization: `run_compaction` moves
+//! its boundary past them without summarizing them, and
+//! `get_context_messages` puts them back in context verbatim, in sequence
+//! order, shown as `(pinned)`. Retention never deletes them.
+//!
+//! The agent pins with `pin_message` (the user's latest message, or the
+//! latest message containing some text); users pin with `/pin <text>`, which
+//! stores the text as a pinned message without a reply from the agent.
+//! `/pin` alone lists pins and `/unpin <n>` removes one. At most
+//! `MAX_PINNED` messages are pinned per conversation, since they all take
+//! context space on every turn.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::db::{MemoryDb, MessageRow};
+use crate::sage_agent::{Tool, ToolResult};
+
+/// Most messages pinned per conversation
+pub const MAX_PINNED: usize = 10;
+
+/// Characters of a pinned message quoted in replies and listings
+const PREVIEW_CHARS: usize = 80;
+
+/// Pinned messages outside `messages` (compacted ones) merged into it, in
+/// sequence order
+pub fn merge_pinned(pinned: Vec<MessageRow>, mut messages: Vec<MessageRow>) -> Vec<MessageRow> {
+    let extra: Vec<MessageRow> = pinned
+        .into_iter()
+        .filter(|p| !messages.iter().any(|m| m.id == p.id))
+        .collect();
+    if extra.is_empty() {
+        return messages;
+    }
+    messages.extend(extra);
+    messages.sort_by_key(|m| m.sequence_id);
+    messages
+}
+
+fn preview(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+/// Numbered list of pinned messages, for `/pin`
+pub fn describe_pins(pinned: &[MessageRow]) -> String {
+    if pinned.is_empty() {
+        return "Nothing is pinned. /pin <text> pins something I should always keep in mind."
+            .to_string();
+    }
+    let mut reply = format!("Pinned ({}/{}):", pinned.len(), MAX_PINNED);
+    for (i, message) in pinned.iter().enumerate() {
+        reply.push_str(&format!("\n{}. {}", i + 1, preview(&message.content)));
+    }
+    reply.push_str("\n/unpin <number> removes one.");
+    reply
+}
+
+/// Store `text` from `user_id` as a pinned user message
+pub fn pin_note(db: &MemoryDb, agent_id: Uuid, user_id: &str, text: &str) -> Result<String> {
+    let pinned = db.messages().pinned(agent_id)?;
+    if pinned.len() >= MAX_PINNED {
+        return Ok(format!(
+            "{} messages are pinned already, the most I keep. /unpin <number> removes one (/pin lists them).",
+            MAX_PINNED
+        ));
+    }
+    let zero_embedding = vec![0.0f32; super::embedding::EMBEDDING_DIM];
+    let id = db.messages().insert_message(
+        agent_id,
+        user_id,
+        "user",
+        text,
+        &zero_embedding,
+        None,
+        None,
+        None,
+    )?;
+    db.messages().set_pinned(agent_id, id, true)?;
+    match db.embedding_jobs().enqueue(id) {
+        Ok(()) => super::embedding_queue::wake(),
+        Err(e) => tracing::warn!("Failed to queue embedding of message {}: {}", id, e),
+    }
+    Ok(
+        "Pinned. I'll keep this in mind word for word, however long our conversation gets."
+            .to_string(),
+    )
+}
+
+/// Unpin the `number`th pinned message (1-based, as `/pin` lists them)
+pub fn unpin_number(db: &MemoryDb, agent_id: Uuid, number: usize) -> Result<String> {
+    let pinned = db.messages().pinned(agent_id)?;
+    let Some(message) = number.checked_sub(1).and_then(|i| pinned.get(i)) else {
+        return Ok(format!("There is no pin {}. /pin lists them.", number));
+    };
+    db.messages().set_pinned(agent_id, message.id, false)?;
+    Ok(format!("Unpinned \"{}\".", preview(&message.content)))
+}
+
+// ============================================================================
+// /pin
+// ============================================================================
+
+/// A `/pin` or `/unpin` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinCommand {
+    /// `/pin`: list pinned messages
+    Show,
+    /// `/pin <text>`
+    Pin(String),
+    /// `/unpin <n>`
+    Unpin(usize),
+}
+
+/// Parse `/pin`, `/pin <text>` or `/unpin <n>`. Returns None for any other
+/// message.
+pub fn parse_pin_command(text: &str) -> Option<PinCommand> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix("/unpin") {
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        return rest.trim().parse().ok().map(PinCommand::Unpin);
+    }
+    let rest = text.strip_prefix("/pin")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(match rest.trim() {
+        "" => PinCommand::Show,
+        text => PinCommand::Pin(text.to_string()),
+    })
+}
+
+// ============================================================================
+// pin_message
+// ============================================================================
+
+/// Pin or unpin a message so compaction never summarizes it away
+pub struct PinMessageTool {
+    db: MemoryDb,
+    agent_id: Uuid,
+}
+
+impl PinMessageTool {
+    pub fn new(db: MemoryDb, agent_id: Uuid) -> Self {
+        Self { db, agent_id }
+    }
+}
+
+#[async_trait]
+impl Tool for PinMessageTool {
+    fn name(&self) -> &str {
+        "pin_message"
+    }
+
+    fn description(&self) -> &str {
+        "Pin a message so it stays in your context word for word instead of being summarized when the conversation is compacted. Use for standing instructions and critical details the user asks you to always remember, not for ordinary facts (use memory blocks or archival memory). Pins are limited, so unpin ones that no longer apply."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"text": "words from the message to pin (the latest message containing them); omit for the user's latest message", "unpin": "true to unpin instead"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let text = args.get("text").map(|t| t.trim()).filter(|t| !t.is_empty());
+        let unpin = args.get("unpin").is_some_and(|u| u == "true");
+
+        let messages = self.db.messages();
+        let pinned = messages.pinned(self.agent_id)?;
+        let message = if unpin {
+            // Only pinned messages can be unpinned; match among them
+            let found = match text {
+                Some(text) => {
+                    let text = text.to_lowercase();
+                    pinned
+                        .iter()
+                        .rev()
+                        .find(|m| m.content.to_lowercase().contains(&text))
+                }
+                None => pinned.last(),
+            };
+            match found {
+                Some(message) => message.clone(),
+                None => return Ok(ToolResult::error("No pinned message matches.".to_string())),
+            }
+        } else {
+            match messages.find_latest(self.agent_id, text)? {
+                Some(message) => message,
+                None => {
+                    return Ok(ToolResult::error(
+                        "No message contains that text.".to_string(),
+                    ))
+                }
+            }
+        };
+
+        if !unpin && message.pinned {
+            return Ok(ToolResult::success(format!(
+                "Already pinned: \"{}\"",
+                preview(&message.content)
+            )));
+        }
+        if !unpin && pinned.len() >= MAX_PINNED {
+            return Ok(ToolResult::error(format!(
+                "{} messages are pinned already, the most allowed. Unpin one that no longer applies first.",
+                MAX_PINNED
+            )));
+        }
+
+        messages.set_pinned(self.agent_id, message.id, !unpin)?;
+        Ok(ToolResult::success(format!(
+            "{} \"{}\"",
+            if unpin { "Unpinned" } else { "Pinned" },
+            preview(&message.content)
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn message(sequence_id: i64, pinned: bool) -> MessageRow {
+        MessageRow {
+            id: Uuid::new_v4(),
+            agent_id: Uuid::nil(),
+            user_id: "alice".to_string(),
+            role: "user".to_string(),
+            content: format!("message {}", sequence_id),
+            sequence_id,
+            tool_calls: None,
+            tool_results: None,
+            created_at: Utc::now(),
+            attachment_text: None,
+            pinned,
+        }
+    }
+
+    #[test]
+    fn test_merge_pinned() {
+        let old = message(2, true);
+        let recent = message(7, true);
+        let messages = vec![message(5, false), recent.clone(), message(9, false)];
+        let merged = merge_pinned(vec![old, recent], messages);
+        assert_eq!(
+            merged.iter().map(|m| m.sequence_id).collect::<Vec<_>>(),
+            vec![2, 5, 7, 9]
+        );
+
+        let messages = vec![message(5, false)];
+        assert_eq!(merge_pinned(Vec::new(), messages.clone()).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_pin_command() {
+        assert_eq!(parse_pin_command("/pin"), Some(PinCommand::Show));
+        assert_eq!(
+            parse_pin_command("/pin  Always answer in Spanish "),
+            Some(PinCommand::Pin("Always answer in Spanish".to_string()))
+        );
+        assert_eq!(parse_pin_command("/unpin 2"), Some(PinCommand::Unpin(2)));
+        assert_eq!(parse_pin_command("/unpin two"), None);
+        assert_eq!(parse_pin_command("/unpin"), None);
+        assert_eq!(parse_pin_command("/pineapple"), None);
+        assert_eq!(parse_pin_command("pin this"), None);
+    }
+
+    #[test]
+    fn test_describe_pins() {
+        assert!(describe_pins(&[]).starts_with("Nothing is pinned"));
+        let listing = describe_pins(&[message(1, true), message(4, true)]);
+        assert!(listing.starts_with(&format!("Pinned (2/{}):", MAX_PINNED)));
+        assert!(listing.contains("\n2. message 4"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Memory Provenance
+//!
+//! Every block edit, archival insert and preference records the user
+//! message(s) it was learned from (`memory_sources`): the user messages
+//! stored since the agent last replied, i.e. what the current turn answers.
+//! `memory_source` looks a memory up and quotes those messages, so the agent
+//! can answer "where did you learn that?".
+//!
+//! Memories written before provenance existed, or by turns without a user
+//! message (scheduled tasks), have no source to quote.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use super::db::MemoryDb;
+use crate::sage_agent::{Tool, ToolResult};
+
+/// Longest quote of a source message
+const QUOTE_CHARS: usize = 300;
+
+/// Default and maximum number of sources `memory_source` returns
+const DEFAULT_LIMIT: usize = 3;
+const MAX_LIMIT: usize = 10;
+
+/// Recent matching sources ranked for a query
+const CANDIDATES: i64 = 50;
+
+/// User messages the current turn is answering
+#[derive(Debug, Default)]
+struct TurnSources {
+    message_ids: Vec<Uuid>,
+    /// The agent replied since; the next user message starts a new turn
+    replied: bool,
+}
+
+/// Tracks which stored messages the memories written now come from
+#[derive(Clone, Default)]
+pub struct ProvenanceTracker {
+    inner: Arc<Mutex<TurnSources>>,
+}
+
+impl ProvenanceTracker {
+    /// Note a message stored in recall memory
+    pub fn note_message(&self, message_id: Uuid, role: &str) {
+        let Ok(mut sources) = self.inner.lock() else {
+            return;
+        };
+        match role {
+            "user" => {
+                if sources.replied {
+                    sources.message_ids.clear();
+                    sources.replied = false;
+                }
+                sources.message_ids.push(message_id);
+            }
+            "assistant" => sources.replied = true,
+            _ => {}
+        }
+    }
+
+    /// Messages memories written now were learned from
+    pub fn current(&self) -> Vec<Uuid> {
+        self.inner
+            .lock()
+            .map(|sources| sources.message_ids.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// What memory tools use to record where a write came from
+#[derive(Clone)]
+pub struct Provenance {
+    db: MemoryDb,
+    /// Owner of the memory (the main agent for threads)
+    agent_id: Uuid,
+    tracker: ProvenanceTracker,
+}
+
+impl Provenance {
+    pub fn new(db: MemoryDb, agent_id: Uuid, tracker: ProvenanceTracker) -> Self {
+        Self {
+            db,
+            agent_id,
+            tracker,
+        }
+    }
+
+    /// Record a memory write. Best effort: the write itself already happened.
+    pub fn record(&self, kind: &str, target: &str, excerpt: &str) {
+        let message_ids = self.tracker.current();
+        if let Err(e) =
+            self.db
+                .sources()
+                .record(self.agent_id, kind, target, excerpt.trim(), &message_ids)
+        {
+            tracing::warn!("Failed to record source of {} '{}': {}", kind, target, e);
+        }
+    }
+}
+
+/// Words of a memory_source query to look for: the significant ones (4+
+/// characters), or the whole query if it has none
+fn search_words(query: &str) -> Vec<String> {
+    let words: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| w.chars().count() >= 4)
+        .map(str::to_lowercase)
+        .collect();
+    if words.is_empty() && !query.trim().is_empty() {
+        vec![query.trim().to_lowercase()]
+    } else {
+        words
+    }
+}
+
+/// `ILIKE` pattern matching text that contains `word`
+pub(super) fn like_pattern(word: &str) -> String {
+    let escaped = word
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// How many of `words` `text` contains
+fn matches(text: &str, words: &[String]) -> usize {
+    let text = text.to_lowercase();
+    words.iter().filter(|w| text.contains(w.as_str())).count()
+}
+
+/// A message shortened for quoting
+fn quote(content: &str) -> String {
+    let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if content.chars().count() <= QUOTE_CHARS {
+        content
+    } else {
+        let cut: String = content.chars().take(QUOTE_CHARS).collect();
+        format!("{}...", cut.trim_end())
+    }
+}
+
+/// Tell where a memory came from
+pub struct MemorySourceTool {
+    db: MemoryDb,
+    agent_id: Uuid,
+}
+
+impl MemorySourceTool {
+    pub fn new(db: MemoryDb, agent_id: Uuid) -> Self {
+        Self { db, agent_id }
+    }
+}
+
+#[async_trait]
+impl Tool for MemorySourceTool {
+    fn name(&self) -> &str {
+        "memory_source"
+    }
+
+    fn description(&self) -> &str {
+        "Find where you learned something you remember: quotes the original messages behind a memory block edit, archival passage or preference. Use when asked 'where did you learn that?' or before relying on a surprising memory."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"memory": "the remembered fact, or words from it (e.g. 'dog named Smokey')", "limit": "max sources (default 3)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let memory = args
+            .get("memory")
+            .ok_or_else(|| anyhow::anyhow!("'memory' argument required"))?;
+        let limit = args
+            .get("limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT);
+
+        let words = search_words(memory);
+        if words.is_empty() {
+            return Ok(ToolResult::error("'memory' must not be empty"));
+        }
+        let patterns: Vec<String> = words.iter().map(|w| like_pattern(w)).collect();
+        // Best match first, the newest among equals
+        let mut sources = self
+            .db
+            .sources()
+            .find(self.agent_id, &patterns, CANDIDATES)?;
+        sources.sort_by_key(|s| {
+            std::cmp::Reverse(matches(&format!("{} {}", s.target, s.excerpt), &words))
+        });
+        sources.truncate(limit);
+        if sources.is_empty() {
+            return Ok(ToolResult::success(format!(
+                "No recorded source for '{}'. It may predate source tracking - try conversation_search.",
+                memory
+            )));
+        }
+
+        let mut output = String::new();
+        for source in sources {
+            output.push_str(&format!(
+                "{} '{}' on {}: \"{}\"\n",
+                source.kind,
+                source.target,
+                source.created_at.format("%Y-%m-%d %H:%M UTC"),
+                quote(&source.excerpt)
+            ));
+            let messages = self.db.messages().get_by_ids(&source.message_ids)?;
+            if messages.is_empty() {
+                output.push_str("  Learned from: no message on record (e.g. a scheduled task)\n\n");
+                continue;
+            }
+            output.push_str("  Learned from:\n");
+            for message in messages {
+                output.push_str(&format!(
+                    "  > [{}] {}: {}\n",
+                    message.created_at.format("%Y-%m-%d %H:%M UTC"),
+                    message.role,
+                    quote(&message.content)
+                ));
+            }
+            output.push('\n');
+        }
+        Ok(ToolResult::success(output.trim_end().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_follows_turns() {
+        let tracker = ProvenanceTracker::default();
+        let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        tracker.note_message(first, "user");
+        tracker.note_message(second, "user");
+        assert_eq!(tracker.current(), vec![first, second]);
+
+        // Replying doesn't drop the sources of the turn still running...
+        tracker.note_message(Uuid::new_v4(), "assistant");
+        assert_eq!(tracker.current(), vec![first, second]);
+
+        // ...the next user message starts over
+        tracker.note_message(third, "user");
+        assert_eq!(tracker.current(), vec![third]);
+    }
+
+    #[test]
+    fn test_search_words() {
+        let words = search_words("Dog named Smokey!");
+        assert_eq!(words, vec!["named", "smokey"]);
+        assert_eq!(matches("Has a dog called Smokey", &words), 1);
+        assert_eq!(matches("Her dog is named SMOKEY", &words), 2);
+
+        // Nothing significant: the whole query, escaped for ILIKE
+        assert_eq!(search_words(" 50% "), vec!["50%"]);
+        assert_eq!(like_pattern("50%"), "%50\\%%");
+        assert!(search_words("  ").is_empty());
+    }
+}
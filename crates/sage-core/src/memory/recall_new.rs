@@ -1,16 +1,32 @@
 //! Recall Memory (Conversation History with Embeddings)
 //!
 //! Full conversation history stored in PostgreSQL with embeddings.
-//! Supports both keyword and semantic search via pgvector.
+//! `search` is hybrid: pgvector similarity, Postgres full-text search and
+//! keyword matching, fused by rank (see `fusion`).
+//!
+//! The embedding model only sees the start of a long message, so messages over
+//! `CHUNK_CHARS` are also embedded as overlapping chunks (`message_chunks`).
+//! Semantic search ranks a message by its best match, which makes the middle
+//! of a pasted document findable.
 
 #![allow(dead_code)]
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 use super::db::{MemoryDb, MessageRow};
 use super::embedding::EmbeddingService;
+use super::freshness;
+use super::fusion::{reciprocal_rank_fusion, CANDIDATES_PER_RESULT};
+
+/// Messages longer than this (in characters) are also embedded in chunks
+pub const CHUNK_CHARS: usize = 1500;
+
+/// Characters shared by consecutive chunks, so a passage cut by a chunk
+/// boundary is whole in one of them
+pub const CHUNK_OVERLAP_CHARS: usize = 300;
 
 /// A message in recall memory
 #[derive(Debug, Clone)]
@@ -59,8 +75,9 @@ pub enum MatchType {
 impl RecallSearchResult {
     /// Format the search result for display to the agent
     pub fn format(&self) -> String {
+        let now = Utc::now();
         let timestamp = self.message.created_at.format("%Y-%m-%d %H:%M:%S UTC");
-        let time_ago = format_time_ago(self.message.created_at, Utc::now());
+        let time_ago = format_time_ago(self.message.created_at, now);
         let role = &self.message.role;
         let content = &self.message.content;
 
@@ -69,7 +86,14 @@ impl RecallSearchResult {
             .map(|s| format!(" (score: {:.2})", s))
             .unwrap_or_default();
 
-        let mut result = format!("[{}] ({}, {}){}\n", timestamp, time_ago, role, score_str);
+        let mut result = format!(
+            "[{}] ({}, {}){}{}\n",
+            timestamp,
+            time_ago,
+            role,
+            score_str,
+            freshness::stale_marker(self.message.created_at, now)
+        );
 
         // Truncate long content (handle UTF-8 boundaries safely)
         if content.len() > 500 {
@@ -154,13 +178,14 @@ impl RecallManager {
             None,
             attachment_text,
         )?;
+        self.embed_chunks(id, content).await;
 
         tracing::debug!("Stored message {} with embedding", id);
         Ok(id)
     }
 
     /// Add a message WITHOUT embedding (for fast insertion)
-    /// Use update_embedding() later to add the embedding in background
+    /// It is queued for the embedding worker (see `embedding_queue`)
     pub fn add_message_sync(&self, user_id: &str, role: &str, content: &str) -> Result<Uuid> {
         self.add_message_sync_with_attachment(user_id, role, content, None)
     }
@@ -186,20 +211,57 @@ impl RecallManager {
             attachment_text,
         )?;
 
+        // Without a job the startup backfill still finds the zero vector
+        match self.db.embedding_jobs().enqueue(id) {
+            Ok(()) => super::embedding_queue::wake(),
+            Err(e) => tracing::warn!("Failed to queue embedding of message {}: {}", id, e),
+        }
+
         tracing::debug!("Stored message {} (embedding pending)", id);
         Ok(id)
     }
 
-    /// Update embedding for a message (call in background after add_message_sync)
-    pub async fn update_embedding(&self, message_id: Uuid, content: &str) -> Result<()> {
-        let embedding = self.embedding.embed(content).await?;
-        self.db
-            .messages()
-            .update_embedding(message_id, &embedding)?;
+    /// Store the embedding of a message stored by add_message_sync, embedded
+    /// in a batch by the embedding queue
+    pub async fn set_embedding(
+        &self,
+        message_id: Uuid,
+        content: &str,
+        embedding: &[f32],
+    ) -> Result<()> {
+        self.db.messages().update_embedding(message_id, embedding)?;
+        self.embed_chunks(message_id, content).await;
         tracing::debug!("Updated embedding for message {}", message_id);
         Ok(())
     }
 
+    /// Embed a long message's chunks. Best effort: the message itself is
+    /// stored either way, it's just harder to find by its later parts.
+    async fn embed_chunks(&self, message_id: Uuid, content: &str) {
+        let chunks = overlapping_chunks(content, CHUNK_CHARS, CHUNK_OVERLAP_CHARS);
+        if chunks.len() < 2 {
+            return;
+        }
+        let texts: Vec<&str> = chunks.iter().map(String::as_str).collect();
+        let result = match self.embedding.embed_batch(&texts).await {
+            Ok(embeddings) if embeddings.len() == chunks.len() => {
+                let chunks: Vec<(String, Vec<f32>)> = chunks.into_iter().zip(embeddings).collect();
+                self.db
+                    .messages()
+                    .replace_chunks(message_id, self.agent_id, &chunks)
+            }
+            Ok(embeddings) => Err(anyhow::anyhow!(
+                "expected {} embeddings, got {}",
+                chunks.len(),
+                embeddings.len()
+            )),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to embed chunks of message {}: {}", message_id, e);
+        }
+    }
+
     /// Add a message with tool call information
     pub async fn add_tool_message(
         &self,
@@ -221,14 +283,23 @@ impl RecallManager {
             tool_results,
             None,
         )?;
+        self.embed_chunks(id, content).await;
 
         Ok(id)
     }
 
+    /// Delete the user and assistant messages containing all the words of
+    /// `about`. Returns how many.
+    pub fn forget(&self, about: &str) -> Result<usize> {
+        self.db
+            .messages()
+            .delete_messages_matching(self.agent_id, about)
+    }
+
     /// Search recall memory by keyword
     pub fn search_keyword(&self, query: &str, limit: usize) -> Result<Vec<RecallSearchResult>> {
         let messages = self.db.messages().get_recent(self.agent_id, 1000)?;
-        let query_lower = query.to_lowercase();
+        let query = super::language::fold(query);
 
         let mut results: Vec<RecallSearchResult> = messages
             .into_iter()
@@ -237,7 +308,7 @@ impl RecallManager {
                 if m.role == "tool" {
                     return false;
                 }
-                m.content.to_lowercase().contains(&query_lower)
+                super::language::fold(&m.content).contains(&query)
             })
             .map(|m| RecallSearchResult {
                 message: m.into(),
@@ -279,43 +350,58 @@ impl RecallManager {
             .collect())
     }
 
-    /// Hybrid search combining keyword and semantic
+    /// Hybrid search: semantic, full-text and (transliterated) keyword
+    /// matches, merged by reciprocal rank fusion (see `fusion`)
     pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<RecallSearchResult>> {
-        // Get keyword results
-        let keyword_results = self.search_keyword(query, limit)?;
-
-        // Get semantic results
-        let semantic_results = self.search_semantic(query, limit).await?;
-
-        // Merge and deduplicate by message ID
-        let mut seen = std::collections::HashSet::new();
-        let mut combined: Vec<RecallSearchResult> = Vec::new();
-
-        // Add semantic results first (they have scores)
-        for result in semantic_results {
-            if seen.insert(result.message.id) {
-                combined.push(result);
-            }
+        let query_embedding = self.embedding.embed(query).await?;
+        let candidates = limit * CANDIDATES_PER_RESULT;
+
+        let messages = self.db.messages();
+        let semantic =
+            messages.search_by_embedding(self.agent_id, &query_embedding, candidates as i64)?;
+        let full_text =
+            messages.search_full_text(self.agent_id, query, &query_embedding, candidates as i64)?;
+        let keyword = self.search_keyword(query, candidates)?;
+
+        let semantic_ids: Vec<Uuid> = semantic.iter().map(|r| r.message.id).collect();
+        let lexical_ids: HashSet<Uuid> = full_text
+            .iter()
+            .map(|r| r.message.id)
+            .chain(keyword.iter().map(|r| r.message.id))
+            .collect();
+        let fused = reciprocal_rank_fusion(&[
+            semantic_ids.clone(),
+            full_text.iter().map(|r| r.message.id).collect(),
+            keyword.iter().map(|r| r.message.id).collect(),
+        ]);
+
+        // Similarity where a distance is known (keyword hits have none)
+        let mut found: HashMap<Uuid, (RecallMessage, Option<f32>)> = HashMap::new();
+        for result in keyword {
+            found.insert(result.message.id, (result.message, None));
         }
-
-        // Add keyword results that weren't in semantic
-        for mut result in keyword_results {
-            if seen.insert(result.message.id) {
-                result.match_type = MatchType::Keyword;
-                combined.push(result);
-            }
+        for result in semantic.into_iter().chain(full_text) {
+            let similarity = Some(1.0 - result.distance as f32);
+            found.insert(result.message.id, (result.message.into(), similarity));
         }
 
-        // Sort by relevance score (semantic first), then by recency
-        combined.sort_by(|a, b| match (a.relevance_score, b.relevance_score) {
-            (Some(sa), Some(sb)) => sb.partial_cmp(&sa).unwrap_or(std::cmp::Ordering::Equal),
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => b.message.sequence_id.cmp(&a.message.sequence_id),
-        });
-
-        combined.truncate(limit);
-        Ok(combined)
+        Ok(fused
+            .into_iter()
+            .take(limit)
+            .filter_map(|(id, _)| {
+                let (message, relevance_score) = found.remove(&id)?;
+                let match_type = match (semantic_ids.contains(&id), lexical_ids.contains(&id)) {
+                    (true, true) => MatchType::Hybrid,
+                    (true, false) => MatchType::Semantic,
+                    _ => MatchType::Keyword,
+                };
+                Some(RecallSearchResult {
+                    message,
+                    relevance_score,
+                    match_type,
+                })
+            })
+            .collect())
     }
 
     /// Get messages by IDs (for loading context window)
@@ -345,3 +431,70 @@ fn format_time_ago(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
         "just now".to_string()
     }
 }
+
+/// Split text into chunks of at most `size` characters, each starting
+/// `overlap` characters before the previous one ended. Cuts fall on
+/// whitespace where possible. Text that fits is one chunk.
+pub fn overlapping_chunks(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let total = chars.len();
+    if total <= size {
+        return vec![text.to_string()];
+    }
+    let byte = |i: usize| chars.get(i).map_or(text.len(), |(b, _)| *b);
+    let size = size.max(1);
+    let overlap = overlap.min(size / 2);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let mut end = (start + size).min(total);
+        if end < total {
+            // Back up to a space in the last quarter of the window
+            if let Some(space) = (start + size * 3 / 4..end)
+                .rev()
+                .find(|&i| chars[i].1.is_whitespace())
+            {
+                end = space;
+            }
+        }
+        chunks.push(text[byte(start)..byte(end)].trim().to_string());
+        if end >= total {
+            break;
+        }
+        // Start the next chunk at a word boundary inside the overlap
+        let next = end.saturating_sub(overlap).max(start + 1);
+        start = (next..end)
+            .find(|&i| chars[i].1.is_whitespace())
+            .map_or(next, |i| i + 1);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_chunks() {
+        assert_eq!(overlapping_chunks("short", 10, 3), vec!["short"]);
+
+        let text = (0..100)
+            .map(|i| format!("w{:02}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let chunks = overlapping_chunks(&text, 40, 12);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 40));
+        // No word is cut, consecutive chunks share words, and the end is covered
+        assert!(chunks
+            .iter()
+            .flat_map(|c| c.split(' '))
+            .all(|w| w.len() == 3));
+        for pair in chunks.windows(2) {
+            let last_word = pair[0].rsplit(' ').next().unwrap();
+            assert!(pair[1].contains(last_word));
+        }
+        assert!(chunks.last().unwrap().ends_with("w99"));
+    }
+}
@@ -0,0 +1,228 @@
+//! Re-ranking of Memory Search Results
+//!
+//! With `MEMORY_RERANK` set, `archival_search` and `conversation_search` fetch
+//! `CANDIDATES` results from hybrid retrieval (see `fusion`), re-order them and
+//! return the number the agent asked for:
+//! - `score`: no model call. A candidate scores its similarity to the query
+//!   plus the share of query words it contains (compared `fold`ed, see
+//!   `language`), so a passage naming what was asked about beats one that is
+//!   merely on topic.
+//! - `llm`: one small call (`RerankResults` signature) orders the candidates
+//!   by how well they answer the query. If it fails, the score order is used.
+
+use anyhow::Result;
+use dspy_rs::{Predict, Signature};
+use std::collections::HashSet;
+use tracing::warn;
+
+use super::language::fold;
+use crate::usage::{self, CallKind};
+
+/// Results fetched for re-ranking
+pub const CANDIDATES: usize = 20;
+
+/// Characters of each candidate shown to the re-ranking model
+const CANDIDATE_CHARS: usize = 500;
+
+/// Weight of the retrieval similarity in the score
+const SIMILARITY_WEIGHT: f32 = 0.6;
+
+/// Weight of the share of query words a candidate contains
+const COVERAGE_WEIGHT: f32 = 0.4;
+
+/// Instruction for the re-ranking DSRs signature
+pub const RERANK_INSTRUCTION: &str = r#"You rank search results from an assistant's memory. Order the numbered candidates by how well each one answers or informs the query, most useful first. Prefer candidates that state the specific fact asked about over ones that only share the topic. Return candidate numbers only; leave out candidates that are irrelevant."#;
+
+/// DSRs signature for re-ranking memory search results
+#[derive(Signature, Clone, Debug)]
+pub struct RerankResults {
+    #[input(desc = "What the memory is being searched for")]
+    pub query: String,
+
+    #[input(desc = "Numbered candidates: [n] followed by the text")]
+    pub candidates: String,
+
+    #[output(desc = "Candidate numbers, most relevant first")]
+    pub ranking: Vec<i64>,
+}
+
+/// How search results are re-ranked (`MEMORY_RERANK`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RerankMode {
+    /// Keep the retrieval order
+    #[default]
+    Off,
+    /// Similarity plus query word coverage
+    Score,
+    /// A model call orders the candidates
+    Llm,
+}
+
+impl RerankMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "off" | "false" | "0" => Some(Self::Off),
+            "score" => Some(Self::Score),
+            "llm" => Some(Self::Llm),
+            _ => None,
+        }
+    }
+
+    /// Results to fetch for `limit` to be returned
+    pub fn candidates(&self, limit: usize) -> usize {
+        match self {
+            Self::Off => limit,
+            _ => limit.max(CANDIDATES),
+        }
+    }
+}
+
+/// What re-ranking needs to know about a search result
+pub struct Candidate<'a> {
+    pub text: &'a str,
+    /// Retrieval similarity (0-1), if the result has one
+    pub similarity: Option<f32>,
+}
+
+/// The best `limit` of `results`, re-ranked
+pub async fn top<T>(
+    mode: RerankMode,
+    query: &str,
+    results: Vec<T>,
+    candidate: impl Fn(&T) -> Candidate<'_>,
+    limit: usize,
+) -> Vec<T> {
+    let order = {
+        let candidates: Vec<Candidate> = results.iter().map(candidate).collect();
+        match mode {
+            RerankMode::Off => (0..candidates.len()).collect(),
+            RerankMode::Score => score_order(query, &candidates),
+            RerankMode::Llm => match llm_order(query, &candidates).await {
+                Ok(order) => order,
+                Err(e) => {
+                    warn!("Re-ranking failed, ordering by score: {}", e);
+                    score_order(query, &candidates)
+                }
+            },
+        }
+    };
+    let mut results: Vec<Option<T>> = results.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|i| results.get_mut(i)?.take())
+        .take(limit)
+        .collect()
+}
+
+/// Share of the query's distinct words that appear in the text
+pub fn coverage(query: &str, text: &str) -> f32 {
+    let text = fold(text);
+    let query = fold(query);
+    let words: HashSet<&str> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 2)
+        .collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+    let found = words.iter().filter(|w| text.contains(*w)).count();
+    found as f32 / words.len() as f32
+}
+
+fn score(query: &str, candidate: &Candidate) -> f32 {
+    SIMILARITY_WEIGHT * candidate.similarity.unwrap_or(0.0)
+        + COVERAGE_WEIGHT * coverage(query, candidate.text)
+}
+
+/// Candidates by score, best first; ties keep the retrieval order
+fn score_order(query: &str, candidates: &[Candidate]) -> Vec<usize> {
+    let scores: Vec<f32> = candidates.iter().map(|c| score(query, c)).collect();
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+    order
+}
+
+async fn llm_order(query: &str, candidates: &[Candidate<'_>]) -> Result<Vec<usize>> {
+    let listed: Vec<String> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let text: String = c.text.chars().take(CANDIDATE_CHARS).collect();
+            format!("[{}] {}", i + 1, text.replace('\n', " "))
+        })
+        .collect();
+
+    let predictor = Predict::<RerankResults>::builder()
+        .instruction(RERANK_INSTRUCTION)
+        .build();
+    let result = predictor
+        .call_with_meta(RerankResultsInput {
+            query: query.to_string(),
+            candidates: listed.join("\n"),
+        })
+        .await?;
+    usage::record_chat(
+        CallKind::Rerank,
+        result.lm_usage.prompt_tokens as i64,
+        result.lm_usage.completion_tokens as i64,
+    );
+    Ok(apply_ranking(candidates.len(), &result.output.ranking))
+}
+
+/// Indices in the model's order: numbers are 1-based, repeats and unknown
+/// numbers are dropped, and candidates it left out follow in retrieval order
+fn apply_ranking(len: usize, ranking: &[i64]) -> Vec<usize> {
+    let mut order: Vec<usize> = Vec::with_capacity(len);
+    for &n in ranking {
+        if n >= 1 && (n as usize) <= len && !order.contains(&(n as usize - 1)) {
+            order.push(n as usize - 1);
+        }
+    }
+    let rest: Vec<usize> = (0..len).filter(|i| !order.contains(i)).collect();
+    order.extend(rest);
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_order_prefers_named_facts() {
+        let candidates = [
+            Candidate {
+                text: "We talked about travel plans for the summer",
+                similarity: Some(0.82),
+            },
+            Candidate {
+                text: "Sister's flight to Lisbon lands at 9am",
+                similarity: Some(0.74),
+            },
+            Candidate {
+                text: "Lisbon flight booked",
+                similarity: None,
+            },
+        ];
+        assert_eq!(
+            score_order("sister flight Lisbon", &candidates),
+            vec![1, 0, 2]
+        );
+        assert_eq!(coverage("Привет Наташа", "privet natasha!"), 1.0);
+    }
+
+    #[test]
+    fn test_apply_ranking() {
+        assert_eq!(apply_ranking(4, &[3, 1]), vec![2, 0, 1, 3]);
+        assert_eq!(apply_ranking(3, &[2, 2, 0, 9, -1]), vec![1, 0, 2]);
+        assert_eq!(apply_ranking(2, &[]), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(RerankMode::parse("LLM"), Some(RerankMode::Llm));
+        assert_eq!(RerankMode::parse(""), Some(RerankMode::Off));
+        assert_eq!(RerankMode::parse("cross-encoder"), None);
+        assert_eq!(RerankMode::Score.candidates(5), CANDIDATES);
+        assert_eq!(RerankMode::Off.candidates(5), 5);
+    }
+}
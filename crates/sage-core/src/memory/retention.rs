@@ -0,0 +1,115 @@
+//! Memory Retention
+//!
+//! Archival memory only grows unless something takes facts out of it. Each
+//! passage has an importance (1.0 unless `archival_insert` says otherwise)
+//! and an optional expiry:
+//! - expired passages drop out of search at once; the weekly maintenance run
+//!   deletes them
+//! - search ranks by fused score times importance, and every passage it
+//!   returns is marked retrieved and brought back up to at least 1.0
+//! - with `MEMORY_DECAY_DAYS`, maintenance halves the importance of passages
+//!   neither created nor retrieved within that many days, and archives those
+//!   that fall below `ARCHIVE_BELOW` (kept in the table, but no longer
+//!   searched, listed or counted)
+//! - with `MESSAGE_RETENTION_DAYS`, maintenance deletes messages older than
+//!   that once a summary covers them
+//! - the `forget` tool deletes what the user asks to be forgotten
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+/// Importance of a passage nobody rated
+pub const DEFAULT_IMPORTANCE: f32 = 1.0;
+
+/// Factor applied to a stale passage's importance per maintenance run
+pub const DECAY_FACTOR: f32 = 0.5;
+
+/// Passages whose importance decays below this are archived
+pub const ARCHIVE_BELOW: f32 = 0.25;
+
+/// Passages at least this similar to what the user asks to forget are
+/// deleted, as are those containing all of its words
+pub const FORGET_MIN_SIMILARITY: f32 = 0.8;
+
+/// What the maintenance run removes (0 days = off)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RetentionPolicy {
+    /// Decay passages not created or retrieved within this many days
+    pub decay_days: u32,
+    /// Delete summarized messages older than this many days
+    pub message_retention_days: u32,
+}
+
+/// Importance from `low`, `normal` or `high`
+pub fn parse_importance(value: &str) -> Result<f32> {
+    match value.trim().to_lowercase().as_str() {
+        "low" => Ok(0.5),
+        "normal" | "" => Ok(DEFAULT_IMPORTANCE),
+        "high" => Ok(2.0),
+        other => anyhow::bail!("Unknown importance '{}' (use low, normal or high)", other),
+    }
+}
+
+/// Expiry from a duration (`30d`, `2w`, `6m`, `1y`) or a date
+/// (`2026-12-01`, expiring at the end of that day, UTC)
+pub fn parse_expiry(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    let expires = match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date
+            .succ_opt()
+            .and_then(|next| next.and_hms_opt(0, 0, 0))
+            .context("Date out of range")?
+            .and_utc(),
+        Err(_) => {
+            let split = value.char_indices().last().map_or(0, |(i, _)| i);
+            let (number, unit) = value.split_at(split);
+            let n: i64 = number.parse().ok().filter(|n| *n > 0).with_context(|| {
+                format!(
+                    "Can't read expiry '{}' (use e.g. 30d, 2w, 6m, 1y or 2026-12-01)",
+                    value
+                )
+            })?;
+            let days = match unit {
+                "d" => n,
+                "w" => n * 7,
+                "m" => n * 30,
+                "y" => n * 365,
+                _ => anyhow::bail!("Unknown expiry unit in '{}' (use d, w, m or y)", value),
+            };
+            now + Duration::days(days)
+        }
+    };
+    if expires <= now {
+        anyhow::bail!("Expiry {} is in the past", value);
+    }
+    Ok(expires)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_expiry() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        assert_eq!(parse_expiry("30d", now).unwrap(), now + Duration::days(30));
+        assert_eq!(parse_expiry("2w", now).unwrap(), now + Duration::days(14));
+        assert_eq!(
+            parse_expiry("2026-12-01", now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 12, 2, 0, 0, 0).unwrap()
+        );
+        assert!(parse_expiry("2026-01-01", now).is_err());
+        assert!(parse_expiry("soon", now).is_err());
+        assert!(parse_expiry("0d", now).is_err());
+        assert!(parse_expiry("3h", now).is_err());
+        assert!(parse_expiry("3天", now).is_err());
+    }
+
+    #[test]
+    fn test_parse_importance() {
+        assert_eq!(parse_importance("High").unwrap(), 2.0);
+        assert_eq!(parse_importance("low").unwrap(), 0.5);
+        assert!(parse_importance("urgent").is_err());
+    }
+}
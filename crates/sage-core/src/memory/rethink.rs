@@ -0,0 +1,94 @@
+//! Core Memory Rewrite
+//!
+//! A block's `char_limit` is enforced on every edit, so an append that
+//! would overflow a full block used to fail and the new fact was lost
+//! unless the agent thought to trim the block itself. Like Letta's core
+//! memory overflow handling, `memory_append` and `memory_insert` instead
+//! ask the chat model to rewrite the block with the new content folded in
+//! (`RewriteBlock`), compressed to fit in `TARGET_FILL` of the limit so the
+//! next few appends fit without another rewrite. The rewrite is recorded as
+//! a revision like any edit, so `memory_undo` can restore the old block.
+
+use anyhow::{anyhow, Result};
+use dspy_rs::{Predict, Signature};
+
+use super::block::Block;
+use crate::usage::{self, CallKind};
+
+/// Share of the char limit a rewritten block should fill
+pub const TARGET_FILL: f64 = 0.7;
+
+/// Instruction for the rewrite DSRs signature
+pub const REWRITE_INSTRUCTION: &str = r#"You maintain one block of an assistant's core memory, which is always in its context and has a hard character limit. The block is full and new content has to be added. Rewrite the block with the new content included, in at most the target number of characters. Keep every fact that is still useful: names, relationships, preferences, dates and ongoing plans come first. Merge duplicates, drop filler and outdated details (the new content wins where it conflicts), and prefer short lines. Keep the block's existing structure and voice. Output only the new block text."#;
+
+/// DSRs signature for compressing a full block
+#[derive(Signature, Clone, Debug)]
+pub struct RewriteBlock {
+    #[input(desc = "Block label and what the block is for")]
+    pub block: String,
+
+    #[input(desc = "The block's current text")]
+    pub current: String,
+
+    #[input(desc = "New content that must be added")]
+    pub addition: String,
+
+    #[input(desc = "Most characters the rewritten block may have")]
+    pub target_chars: String,
+
+    #[output(desc = "The rewritten block text")]
+    pub rewritten: String,
+}
+
+/// Characters a rewrite of a block with `char_limit` should aim for
+pub fn target_chars(char_limit: usize) -> usize {
+    (char_limit as f64 * TARGET_FILL) as usize
+}
+
+/// Ask the chat model to rewrite `block` with `addition` folded in. Fails
+/// if the result is empty or still over the block's limit.
+pub async fn rewrite(block: &Block, addition: &str) -> Result<String> {
+    let predictor = Predict::<RewriteBlock>::builder()
+        .instruction(REWRITE_INSTRUCTION)
+        .build();
+    let result = predictor
+        .call_with_meta(RewriteBlockInput {
+            block: format!(
+                "{}: {}",
+                block.label,
+                block.description.as_deref().unwrap_or("")
+            ),
+            current: block.value.clone(),
+            addition: addition.to_string(),
+            target_chars: target_chars(block.char_limit).to_string(),
+        })
+        .await?;
+    usage::record_chat(
+        CallKind::MemoryRewrite,
+        result.lm_usage.prompt_tokens as i64,
+        result.lm_usage.completion_tokens as i64,
+    );
+
+    let rewritten = result.output.rewritten.trim().to_string();
+    let chars = rewritten.chars().count();
+    if rewritten.is_empty() || chars > block.char_limit {
+        return Err(anyhow!(
+            "Rewrite of block '{}' came back with {} characters (limit {})",
+            block.label,
+            chars,
+            block.char_limit
+        ));
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_chars() {
+        assert_eq!(target_chars(20_000), 14_000);
+        assert_eq!(target_chars(100), 70);
+    }
+}
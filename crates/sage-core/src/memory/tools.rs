@@ -1,9 +1,15 @@
 //! Memory Tools
 //!
 //! Tools that allow the agent to manipulate its memory:
-//! - memory_replace, memory_append, memory_insert (core memory)
+//! - memory_replace, memory_append, memory_insert (core memory; `person`
+//!   edits a group participant's own human block), memory_undo (revisions),
+//!   memory_create_block (agent-defined blocks)
 //! - conversation_search (recall memory + summaries)
-//! - archival_insert, archival_search (archival memory)
+//! - archival_insert, archival_search, archival_update, archival_delete, archival_list (archival memory)
+//! - forget (archival, recall and fact memory)
+//!
+//! `fact_query` lives in `facts`, next to the extraction it reads,
+//! `pin_message` in `pins` and `archival_namespace` in `namespaces`.
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -11,9 +17,14 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 use super::archival_new::ArchivalManager;
-use super::block::BlockManager;
+use super::block::{self, BlockManager, EditCause};
 use super::db::MemoryDb;
+use super::facts::FactManager;
+use super::namespaces::ActiveNamespace;
+use super::provenance::Provenance;
 use super::recall_new::RecallManager;
+use super::rerank::{self, Candidate, RerankMode};
+use super::retention::{parse_expiry, parse_importance};
 use super::EmbeddingService;
 use crate::sage_agent::{Tool, ToolResult};
 
@@ -21,14 +32,52 @@ use crate::sage_agent::{Tool, ToolResult};
 // Core Memory Tools
 // ============================================================================
 
+/// Label of the block a memory tool's `block` and `person` arguments name
+fn target_block(args: &HashMap<String, String>) -> Result<String> {
+    block::target_label(
+        args.get("block").map(String::as_str),
+        args.get("person").map(String::as_str),
+    )
+}
+
+/// First `max` characters of `text` on one line
+fn preview(text: &str, max: usize) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+/// Result of an append or insert that rewrote a full block
+fn rewritten_message(blocks: &BlockManager, label: &str) -> String {
+    let block = blocks.get(label);
+    format!(
+        "The '{}' block was full, so it was rewritten more compactly with the new content included ({}/{} chars). memory_undo restores the previous version if something important was lost.",
+        label,
+        block.as_ref().map_or(0, |b| b.value.chars().count()),
+        block.as_ref().map_or(0, |b| b.char_limit)
+    )
+}
+
+/// Like `target_block`, creating a person's human block on first write
+fn writable_block(blocks: &BlockManager, args: &HashMap<String, String>) -> Result<String> {
+    let label = target_block(args)?;
+    match block::label_person(&label) {
+        Some(person) => blocks.ensure_person(person),
+        None => Ok(label),
+    }
+}
+
 /// Replace text in a memory block
 pub struct MemoryReplaceTool {
     blocks: BlockManager,
+    provenance: Provenance,
 }
 
 impl MemoryReplaceTool {
-    pub fn new(blocks: BlockManager) -> Self {
-        Self { blocks }
+    pub fn new(blocks: BlockManager, provenance: Provenance) -> Self {
+        Self { blocks, provenance }
     }
 }
 
@@ -43,13 +92,14 @@ impl Tool for MemoryReplaceTool {
     }
 
     fn args_schema(&self) -> &str {
-        r#"{"block": "block label (e.g., 'persona', 'human')", "old": "exact text to find", "new": "replacement text"}"#
+        r#"{"block": "block label (e.g., 'persona', 'human')", "old": "exact text to find", "new": "replacement text", "person": "optional sender id in a group chat: edit that person's own human block"}"#
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
-        let block = args
-            .get("block")
-            .ok_or_else(|| anyhow::anyhow!("'block' argument required"))?;
+        let block = match target_block(args) {
+            Ok(block) => block,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
         let old = args
             .get("old")
             .ok_or_else(|| anyhow::anyhow!("'old' argument required"))?;
@@ -57,11 +107,17 @@ impl Tool for MemoryReplaceTool {
             .get("new")
             .ok_or_else(|| anyhow::anyhow!("'new' argument required"))?;
 
-        match self.blocks.replace(block, old, new) {
-            Ok(()) => Ok(ToolResult::success(format!(
-                "Successfully replaced text in '{}' block.",
-                block
-            ))),
+        match self
+            .blocks
+            .replace(&block, old, new, &EditCause::tool(self.name(), args))
+        {
+            Ok(()) => {
+                self.provenance.record("block", &block, new);
+                Ok(ToolResult::success(format!(
+                    "Successfully replaced text in '{}' block.",
+                    block
+                )))
+            }
             Err(e) => Ok(ToolResult::error(e.to_string())),
         }
     }
@@ -70,11 +126,12 @@ impl Tool for MemoryReplaceTool {
 /// Append text to a memory block
 pub struct MemoryAppendTool {
     blocks: BlockManager,
+    provenance: Provenance,
 }
 
 impl MemoryAppendTool {
-    pub fn new(blocks: BlockManager) -> Self {
-        Self { blocks }
+    pub fn new(blocks: BlockManager, provenance: Provenance) -> Self {
+        Self { blocks, provenance }
     }
 }
 
@@ -89,22 +146,34 @@ impl Tool for MemoryAppendTool {
     }
 
     fn args_schema(&self) -> &str {
-        r#"{"block": "block label (e.g., 'persona', 'human')", "content": "text to append"}"#
+        r#"{"block": "block label (e.g., 'persona', 'human')", "content": "text to append", "person": "optional sender id in a group chat: append to that person's own human block"}"#
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
-        let block = args
-            .get("block")
-            .ok_or_else(|| anyhow::anyhow!("'block' argument required"))?;
         let content = args
             .get("content")
             .ok_or_else(|| anyhow::anyhow!("'content' argument required"))?;
+        let block = match writable_block(&self.blocks, args) {
+            Ok(block) => block,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
 
-        match self.blocks.append(block, content) {
-            Ok(()) => Ok(ToolResult::success(format!(
-                "Successfully appended to '{}' block.",
-                block
-            ))),
+        match self
+            .blocks
+            .append_or_rewrite(&block, content, &EditCause::tool(self.name(), args))
+            .await
+        {
+            Ok(true) => {
+                self.provenance.record("block", &block, content);
+                Ok(ToolResult::success(rewritten_message(&self.blocks, &block)))
+            }
+            Ok(false) => {
+                self.provenance.record("block", &block, content);
+                Ok(ToolResult::success(format!(
+                    "Successfully appended to '{}' block.",
+                    block
+                )))
+            }
             Err(e) => Ok(ToolResult::error(e.to_string())),
         }
     }
@@ -113,11 +182,12 @@ impl Tool for MemoryAppendTool {
 /// Insert text at a specific line in a memory block
 pub struct MemoryInsertTool {
     blocks: BlockManager,
+    provenance: Provenance,
 }
 
 impl MemoryInsertTool {
-    pub fn new(blocks: BlockManager) -> Self {
-        Self { blocks }
+    pub fn new(blocks: BlockManager, provenance: Provenance) -> Self {
+        Self { blocks, provenance }
     }
 }
 
@@ -132,27 +202,179 @@ impl Tool for MemoryInsertTool {
     }
 
     fn args_schema(&self) -> &str {
-        r#"{"block": "block label", "content": "text to insert", "line": "line number (0-indexed, -1 for end)"}"#
+        r#"{"block": "block label", "content": "text to insert", "line": "line number (0-indexed, -1 for end)", "person": "optional sender id in a group chat: insert into that person's own human block"}"#
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
-        let block = args
-            .get("block")
-            .ok_or_else(|| anyhow::anyhow!("'block' argument required"))?;
         let content = args
             .get("content")
             .ok_or_else(|| anyhow::anyhow!("'content' argument required"))?;
         let line: i32 = args.get("line").and_then(|l| l.parse().ok()).unwrap_or(-1);
+        let block = match writable_block(&self.blocks, args) {
+            Ok(block) => block,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        match self
+            .blocks
+            .insert_or_rewrite(&block, content, line, &EditCause::tool(self.name(), args))
+            .await
+        {
+            Ok(true) => {
+                self.provenance.record("block", &block, content);
+                Ok(ToolResult::success(rewritten_message(&self.blocks, &block)))
+            }
+            Ok(false) => {
+                self.provenance.record("block", &block, content);
+                Ok(ToolResult::success(format!(
+                    "Successfully inserted text into '{}' block at line {}.",
+                    block,
+                    if line < 0 {
+                        "end".to_string()
+                    } else {
+                        line.to_string()
+                    }
+                )))
+            }
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
 
-        match self.blocks.insert_at_line(block, content, line) {
-            Ok(()) => Ok(ToolResult::success(format!(
-                "Successfully inserted text into '{}' block at line {}.",
+/// Revisions `memory_undo` lists with `history=true`
+const UNDO_HISTORY: i64 = 10;
+
+/// Undo memory block edits, or roll a block back to an earlier version
+pub struct MemoryUndoTool {
+    blocks: BlockManager,
+}
+
+impl MemoryUndoTool {
+    pub fn new(blocks: BlockManager) -> Self {
+        Self { blocks }
+    }
+}
+
+#[async_trait]
+impl Tool for MemoryUndoTool {
+    fn name(&self) -> &str {
+        "memory_undo"
+    }
+
+    fn description(&self) -> &str {
+        "Undo the last change to a memory block, or restore it as it was at an earlier version. Use history=true to list its recent changes with their versions first. An undo can itself be undone."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"block": "block label (e.g., 'persona', 'human')", "version": "optional version to restore (default: before the last change)", "history": "optional 'true' to list recent changes instead", "person": "optional sender id in a group chat: that person's own human block"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let block = match target_block(args) {
+            Ok(block) => block,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        if args.get("history").is_some_and(|h| h == "true") {
+            let revisions = self.blocks.revisions(&block, UNDO_HISTORY)?;
+            if revisions.is_empty() {
+                return Ok(ToolResult::success(format!(
+                    "No recorded changes to '{}'.",
+                    block
+                )));
+            }
+            let lines: Vec<String> = revisions
+                .iter()
+                .map(|r| {
+                    format!(
+                        "- version {} ({}, {}): {}",
+                        r.version,
+                        r.source,
+                        r.created_at.format("%Y-%m-%d %H:%M UTC"),
+                        preview(&r.value, 120)
+                    )
+                })
+                .collect();
+            return Ok(ToolResult::success(format!(
+                "Recent changes to '{}', newest first (version {} was before the oldest):\n{}",
                 block,
-                if line < 0 {
-                    "end".to_string()
-                } else {
-                    line.to_string()
-                }
+                revisions.last().map(|r| r.version - 1).unwrap_or_default(),
+                lines.join("\n")
+            )));
+        }
+
+        let version = match args.get("version").map(|v| v.trim().parse::<i32>()) {
+            Some(Ok(version)) => Some(version),
+            Some(Err(_)) => return Ok(ToolResult::error("'version' must be a number")),
+            None => None,
+        };
+        match self
+            .blocks
+            .rollback(&block, version, &EditCause::tool(self.name(), args))
+        {
+            Ok(restored) => Ok(ToolResult::success(format!(
+                "Restored '{}' to version {}. Current value:\n{}",
+                block,
+                restored,
+                self.blocks.get(&block).map(|b| b.value).unwrap_or_default()
+            ))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
+
+/// Create a new labeled core memory block
+pub struct MemoryCreateBlockTool {
+    blocks: BlockManager,
+}
+
+impl MemoryCreateBlockTool {
+    pub fn new(blocks: BlockManager) -> Self {
+        Self { blocks }
+    }
+}
+
+#[async_trait]
+impl Tool for MemoryCreateBlockTool {
+    fn name(&self) -> &str {
+        "memory_create_block"
+    }
+
+    fn description(&self) -> &str {
+        "Create a new core memory block (always in your context, like persona and human) for a topic that deserves its own standing place, e.g. 'project_context' or 'health'. Edit it afterwards with memory_append/memory_replace/memory_insert."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"label": "lowercase label with underscores (e.g., 'project_context')", "description": "what the block is for", "char_limit": "optional max characters (default 5000)", "value": "optional initial content"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let label = args
+            .get("label")
+            .ok_or_else(|| anyhow::anyhow!("'label' argument required"))?
+            .trim();
+        let description = args
+            .get("description")
+            .ok_or_else(|| anyhow::anyhow!("'description' argument required"))?;
+        let char_limit = match args.get("char_limit").map(|l| l.trim().parse::<usize>()) {
+            Some(Ok(limit)) => limit,
+            Some(Err(_)) => return Ok(ToolResult::error("'char_limit' must be a number")),
+            None => block::DEFAULT_CUSTOM_CHAR_LIMIT,
+        };
+        let value = args.get("value").map(String::as_str).unwrap_or("");
+
+        match self.blocks.create(
+            label,
+            description,
+            char_limit,
+            value,
+            &EditCause::tool(self.name(), args),
+        ) {
+            Ok(created) => Ok(ToolResult::success(format!(
+                "Created '{}' block ({}/{} chars). It is now in your context.",
+                created.label,
+                created.value.chars().count(),
+                created.char_limit
             ))),
             Err(e) => Ok(ToolResult::error(e.to_string())),
         }
@@ -169,6 +391,7 @@ pub struct ConversationSearchTool {
     agent_id: Uuid,
     db: MemoryDb,
     embedding: EmbeddingService,
+    rerank: RerankMode,
 }
 
 impl ConversationSearchTool {
@@ -178,9 +401,16 @@ impl ConversationSearchTool {
             agent_id: recall.agent_id(),
             db: recall.db(),
             embedding: recall.embedding_service(),
+            rerank: RerankMode::Off,
         }
     }
 
+    /// Re-rank message results before returning them
+    pub fn with_rerank(mut self, rerank: RerankMode) -> Self {
+        self.rerank = rerank;
+        self
+    }
+
     /// Search summaries by semantic similarity
     async fn search_summaries(
         &self,
@@ -218,8 +448,23 @@ impl Tool for ConversationSearchTool {
         let mut total_results = 0;
 
         // Search messages
-        match self.recall.search(query, limit).await {
+        match self
+            .recall
+            .search(query, self.rerank.candidates(limit))
+            .await
+        {
             Ok(results) => {
+                let results = rerank::top(
+                    self.rerank,
+                    query,
+                    results,
+                    |r| Candidate {
+                        text: &r.message.content,
+                        similarity: r.relevance_score,
+                    },
+                    limit,
+                )
+                .await;
                 if !results.is_empty() {
                     total_results += results.len();
                     output.push_str(&format!("=== Messages ({}) ===\n\n", results.len()));
@@ -276,11 +521,21 @@ impl Tool for ConversationSearchTool {
 /// Insert content into archival memory
 pub struct ArchivalInsertTool {
     archival: ArchivalManager,
+    provenance: Provenance,
+    namespace: ActiveNamespace,
 }
 
 impl ArchivalInsertTool {
-    pub fn new(archival: ArchivalManager) -> Self {
-        Self { archival }
+    pub fn new(
+        archival: ArchivalManager,
+        provenance: Provenance,
+        namespace: ActiveNamespace,
+    ) -> Self {
+        Self {
+            archival,
+            provenance,
+            namespace,
+        }
     }
 }
 
@@ -291,11 +546,11 @@ impl Tool for ArchivalInsertTool {
     }
 
     fn description(&self) -> &str {
-        "Store information in long-term archival memory for future recall. Good for important facts, preferences, and details you want to remember."
+        "Store information in long-term archival memory for future recall. Good for important facts, preferences, and details you want to remember. Set expires for facts that stop mattering (a trip, a temporary address)."
     }
 
     fn args_schema(&self) -> &str {
-        r#"{"content": "text to store", "tags": "optional comma-separated tags"}"#
+        r#"{"content": "text to store", "tags": "optional comma-separated tags", "importance": "optional low, normal or high", "expires": "optional expiry: a duration (30d, 2w, 6m, 1y) or a date (YYYY-MM-DD)", "namespace": "optional namespace to store in (default: the active one; 'none' for no namespace)"}"#
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
@@ -306,12 +561,58 @@ impl Tool for ArchivalInsertTool {
         let tags = args
             .get("tags")
             .map(|t| t.split(',').map(|s| s.trim().to_string()).collect());
+        let importance = match args.get("importance").map(|i| parse_importance(i)) {
+            Some(Err(e)) => return Ok(ToolResult::error(e.to_string())),
+            importance => importance.and_then(Result::ok),
+        };
+        let expires_at = match args
+            .get("expires")
+            .filter(|e| !e.trim().is_empty())
+            .map(|e| parse_expiry(e, chrono::Utc::now()))
+        {
+            Some(Err(e)) => return Ok(ToolResult::error(e.to_string())),
+            expires_at => expires_at.and_then(Result::ok),
+        };
+        let namespace = match self.namespace.resolve(args) {
+            Ok(namespace) => namespace,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
 
-        match self.archival.insert(content, tags).await {
-            Ok(id) => Ok(ToolResult::success(format!(
-                "Successfully stored in archival memory (id: {}).",
-                id
-            ))),
+        match self
+            .archival
+            .insert_in(content, tags, namespace.as_deref())
+            .await
+        {
+            Ok(inserted) => {
+                if importance.is_some() || expires_at.is_some() {
+                    if let Err(e) =
+                        self.archival
+                            .set_retention(&inserted.ids, importance, expires_at)
+                    {
+                        tracing::warn!("Failed to set passage retention: {}", e);
+                    }
+                }
+                let target = match &inserted.group {
+                    None => inserted.ids[0].to_string(),
+                    Some(group) => group.clone(),
+                };
+                self.provenance.record("passage", &target, content);
+                let stored = match inserted.group {
+                    None => format!(
+                        "Successfully stored in archival memory (id: {}).",
+                        inserted.ids[0]
+                    ),
+                    Some(group) => format!(
+                        "Content was long, so it was stored as {} archival passages tagged '{}' (search with that tag to get all parts).",
+                        inserted.ids.len(),
+                        group
+                    ),
+                };
+                Ok(ToolResult::success(match namespace {
+                    Some(namespace) => format!("{} Namespace: {}.", stored, namespace),
+                    None => stored,
+                }))
+            }
             Err(e) => Ok(ToolResult::error(e.to_string())),
         }
     }
@@ -320,11 +621,23 @@ impl Tool for ArchivalInsertTool {
 /// Search archival memory
 pub struct ArchivalSearchTool {
     archival: ArchivalManager,
+    rerank: RerankMode,
+    namespace: ActiveNamespace,
 }
 
 impl ArchivalSearchTool {
-    pub fn new(archival: ArchivalManager) -> Self {
-        Self { archival }
+    pub fn new(archival: ArchivalManager, namespace: ActiveNamespace) -> Self {
+        Self {
+            archival,
+            rerank: RerankMode::Off,
+            namespace,
+        }
+    }
+
+    /// Re-rank results before returning them
+    pub fn with_rerank(mut self, rerank: RerankMode) -> Self {
+        self.rerank = rerank;
+        self
     }
 }
 
@@ -335,11 +648,11 @@ impl Tool for ArchivalSearchTool {
     }
 
     fn description(&self) -> &str {
-        "Search long-term archival memory using semantic similarity. Returns most relevant stored memories."
+        "Search long-term archival memory by meaning and exact words (names, numbers). Returns most relevant stored memories."
     }
 
     fn args_schema(&self) -> &str {
-        r#"{"query": "search query", "top_k": "max results (default 5)", "tags": "optional comma-separated tags to filter by"}"#
+        r#"{"query": "search query", "top_k": "max results (default 5)", "tags": "optional comma-separated tags to filter by", "namespace": "optional namespace to search (default: the active one; 'all' for every namespace)"}"#
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
@@ -350,9 +663,33 @@ impl Tool for ArchivalSearchTool {
         let tags = args
             .get("tags")
             .map(|t| t.split(',').map(|s| s.trim().to_string()).collect());
+        let namespace = match self.namespace.resolve(args) {
+            Ok(namespace) => namespace,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
 
-        match self.archival.search(query, top_k, tags).await {
+        match self
+            .archival
+            .search_in(
+                query,
+                self.rerank.candidates(top_k),
+                tags,
+                namespace.as_deref(),
+            )
+            .await
+        {
             Ok(results) => {
+                let results = rerank::top(
+                    self.rerank,
+                    query,
+                    results,
+                    |r| Candidate {
+                        text: &r.passage.content,
+                        similarity: Some(r.relevance_score),
+                    },
+                    top_k,
+                )
+                .await;
                 if results.is_empty() {
                     return Ok(ToolResult::success(
                         "No matching memories found.".to_string(),
@@ -370,6 +707,312 @@ impl Tool for ArchivalSearchTool {
     }
 }
 
+/// Parse a passage id argument
+fn passage_id(args: &HashMap<String, String>) -> Result<Uuid> {
+    let id = args
+        .get("id")
+        .ok_or_else(|| anyhow::anyhow!("'id' argument required"))?;
+    Uuid::parse_str(id.trim()).map_err(|_| anyhow::anyhow!("'{}' is not a passage id", id))
+}
+
+/// Correct an archival passage
+pub struct ArchivalUpdateTool {
+    archival: ArchivalManager,
+    provenance: Provenance,
+}
+
+impl ArchivalUpdateTool {
+    pub fn new(archival: ArchivalManager, provenance: Provenance) -> Self {
+        Self {
+            archival,
+            provenance,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ArchivalUpdateTool {
+    fn name(&self) -> &str {
+        "archival_update"
+    }
+
+    fn description(&self) -> &str {
+        "Rewrite an archival memory that is wrong or out of date. Get its id from archival_search or archival_list. The new content replaces the old entirely."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"id": "passage id", "content": "corrected text", "tags": "optional comma-separated tags (replace the old tags)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let id = match passage_id(args) {
+            Ok(id) => id,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        let content = args
+            .get("content")
+            .ok_or_else(|| anyhow::anyhow!("'content' argument required"))?;
+        let tags = args
+            .get("tags")
+            .map(|t| t.split(',').map(|s| s.trim().to_string()).collect());
+
+        match self.archival.update(id, content, tags).await {
+            Ok(true) => {
+                self.provenance.record("passage", &id.to_string(), content);
+                Ok(ToolResult::success(format!(
+                    "Updated archival memory {}.",
+                    id
+                )))
+            }
+            Ok(false) => Ok(ToolResult::error(format!(
+                "No archival memory with id {}",
+                id
+            ))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
+
+/// Delete archival passages
+pub struct ArchivalDeleteTool {
+    archival: ArchivalManager,
+}
+
+impl ArchivalDeleteTool {
+    pub fn new(archival: ArchivalManager) -> Self {
+        Self { archival }
+    }
+}
+
+#[async_trait]
+impl Tool for ArchivalDeleteTool {
+    fn name(&self) -> &str {
+        "archival_delete"
+    }
+
+    fn description(&self) -> &str {
+        "Delete an archival memory that is wrong or no longer true, by id (from archival_search or archival_list). A group tag (group:...) deletes every part of long content."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"id": "passage id, or a group:... tag"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        if let Some(group) = args
+            .get("id")
+            .map(|id| id.trim())
+            .filter(|id| id.starts_with("group:"))
+        {
+            return match self.archival.delete_group(group) {
+                Ok(0) => Ok(ToolResult::error(format!(
+                    "No archival memories tagged {}",
+                    group
+                ))),
+                Ok(n) => Ok(ToolResult::success(format!(
+                    "Deleted {} archival memories tagged {}.",
+                    n, group
+                ))),
+                Err(e) => Ok(ToolResult::error(e.to_string())),
+            };
+        }
+
+        let id = match passage_id(args) {
+            Ok(id) => id,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        match self.archival.delete(id) {
+            Ok(true) => Ok(ToolResult::success(format!(
+                "Deleted archival memory {}.",
+                id
+            ))),
+            Ok(false) => Ok(ToolResult::error(format!(
+                "No archival memory with id {}",
+                id
+            ))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
+
+/// Browse archival memory without a query
+pub struct ArchivalListTool {
+    archival: ArchivalManager,
+    namespace: ActiveNamespace,
+}
+
+impl ArchivalListTool {
+    pub fn new(archival: ArchivalManager, namespace: ActiveNamespace) -> Self {
+        Self {
+            archival,
+            namespace,
+        }
+    }
+}
+
+/// Characters of each passage `archival_list` shows
+const LIST_PREVIEW_CHARS: usize = 200;
+
+/// Most passages `archival_list` returns at once
+const MAX_LIST_LIMIT: usize = 50;
+
+#[async_trait]
+impl Tool for ArchivalListTool {
+    fn name(&self) -> &str {
+        "archival_list"
+    }
+
+    fn description(&self) -> &str {
+        "List archival memories newest first, optionally only those with a tag. Use to review or clean up what you have stored; use archival_search to find something specific."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"tag": "optional tag to filter by", "limit": "max results (default 20, max 50)", "offset": "optional number to skip, for the next page", "namespace": "optional namespace to list (default: the active one; 'all' for every namespace)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let tag = args.get("tag").map(|t| t.trim()).filter(|t| !t.is_empty());
+        let limit: usize = args
+            .get("limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(20)
+            .clamp(1, MAX_LIST_LIMIT);
+        let offset: usize = args.get("offset").and_then(|o| o.parse().ok()).unwrap_or(0);
+        let namespace = match self.namespace.resolve(args) {
+            Ok(namespace) => namespace,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        match self.archival.list(tag, namespace.as_deref(), limit, offset) {
+            Ok(passages) if passages.is_empty() => Ok(ToolResult::success(
+                "No archival memories found.".to_string(),
+            )),
+            Ok(passages) => {
+                let mut output = format!(
+                    "Archival memories {}-{}:\n\n",
+                    offset + 1,
+                    offset + passages.len()
+                );
+                for passage in &passages {
+                    let mut preview: String =
+                        passage.content.chars().take(LIST_PREVIEW_CHARS).collect();
+                    if passage.content.chars().count() > LIST_PREVIEW_CHARS {
+                        preview.push_str("...");
+                    }
+                    let tags = if passage.tags.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" [tags: {}]", passage.tags.join(", "))
+                    };
+                    let namespace = match &passage.namespace {
+                        Some(namespace) => format!(" [namespace: {}]", namespace),
+                        None => String::new(),
+                    };
+                    output.push_str(&format!(
+                        "- {} ({}){}{}\n  {}\n",
+                        passage.id,
+                        passage.created_at.format("%Y-%m-%d"),
+                        namespace,
+                        tags,
+                        preview
+                    ));
+                }
+                if passages.len() == limit {
+                    output.push_str(&format!(
+                        "\nMore may follow: call again with offset {}.",
+                        offset + limit
+                    ));
+                }
+                Ok(ToolResult::success(output))
+            }
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
+
+/// Delete memories on the user's request
+pub struct ForgetTool {
+    archival: ArchivalManager,
+    recall: RecallManager,
+    facts: FactManager,
+}
+
+impl ForgetTool {
+    pub fn new(archival: ArchivalManager, recall: RecallManager, facts: FactManager) -> Self {
+        Self {
+            archival,
+            recall,
+            facts,
+        }
+    }
+}
+
+/// Passages `forget` quotes back
+const FORGET_PREVIEWS: usize = 5;
+
+#[async_trait]
+impl Tool for ForgetTool {
+    fn name(&self) -> &str {
+        "forget"
+    }
+
+    fn description(&self) -> &str {
+        "Permanently delete what the user asks you to forget: archival memories about it, and facts and past messages containing all of its words. Only use when the user explicitly asks. Also remove it from your memory blocks with memory_replace."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"about": "the key words of what to forget, e.g. a name or topic"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let about = args
+            .get("about")
+            .map(|a| a.trim())
+            .filter(|a| a.chars().count() >= 2)
+            .ok_or_else(|| anyhow::anyhow!("'about' argument required"))?;
+
+        let passages = match self.archival.forget(about).await {
+            Ok(passages) => passages,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        let messages = match self.recall.forget(about) {
+            Ok(count) => count,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        let facts = match self.facts.forget(about) {
+            Ok(count) => count,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        tracing::info!(
+            "Forgot {} passage(s), {} fact(s) and {} message(s) about '{}'",
+            passages.len(),
+            facts,
+            messages,
+            about
+        );
+
+        let mut output = format!(
+            "Deleted {} archival memories, {} facts and {} messages about '{}'.",
+            passages.len(),
+            facts,
+            messages,
+            about
+        );
+        for passage in passages.iter().take(FORGET_PREVIEWS) {
+            let preview: String = passage.content.chars().take(LIST_PREVIEW_CHARS).collect();
+            output.push_str(&format!("\n- {}", preview));
+        }
+        if passages.len() > FORGET_PREVIEWS {
+            output.push_str(&format!(
+                "\n- ...and {} more",
+                passages.len() - FORGET_PREVIEWS
+            ));
+        }
+        output.push_str("\nSummaries of older conversations may still mention it.");
+        Ok(ToolResult::success(output))
+    }
+}
+
 // ============================================================================
 // User Preference Tools
 // ============================================================================
@@ -378,11 +1021,16 @@ impl Tool for ArchivalSearchTool {
 pub struct SetPreferenceTool {
     db: MemoryDb,
     agent_id: Uuid,
+    provenance: Provenance,
 }
 
 impl SetPreferenceTool {
-    pub fn new(db: MemoryDb, agent_id: Uuid) -> Self {
-        Self { db, agent_id }
+    pub fn new(db: MemoryDb, agent_id: Uuid, provenance: Provenance) -> Self {
+        Self {
+            db,
+            agent_id,
+            provenance,
+        }
     }
 }
 
@@ -393,7 +1041,7 @@ impl Tool for SetPreferenceTool {
     }
 
     fn description(&self) -> &str {
-        "Set a user preference. Known keys: 'timezone' (IANA format like 'America/Chicago'), 'language' (ISO code like 'en'), 'display_name'. Other keys are also allowed."
+        "Set a user preference. Known keys: 'timezone' (IANA format like 'America/Chicago'), 'language' (ISO code like 'en'), 'display_name', 'occasions' ('off' stops birthday and anniversary check-ins). Other keys are also allowed."
     }
 
     fn args_schema(&self) -> &str {
@@ -409,12 +1057,15 @@ impl Tool for SetPreferenceTool {
             .ok_or_else(|| anyhow::anyhow!("'value' argument required"))?;
 
         match self.db.preferences().set(self.agent_id, key, value) {
-            Ok(pref) => Ok(ToolResult::success(format!(
-                "Preference '{}' set to '{}' (updated: {})",
-                pref.key,
-                pref.value,
-                pref.updated_at.format("%Y-%m-%d %H:%M UTC")
-            ))),
+            Ok(pref) => {
+                self.provenance.record("preference", &pref.key, &pref.value);
+                Ok(ToolResult::success(format!(
+                    "Preference '{}' set to '{}' (updated: {})",
+                    pref.key,
+                    pref.value,
+                    pref.updated_at.format("%Y-%m-%d %H:%M UTC")
+                )))
+            }
             Err(e) => Ok(ToolResult::error(e.to_string())),
         }
     }
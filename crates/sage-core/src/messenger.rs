@@ -1,7 +1,11 @@
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
 
 /// An attachment received from a messaging provider
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct IncomingAttachment {
     pub file: String,
@@ -9,8 +13,184 @@ pub struct IncomingAttachment {
     pub size: Option<u64>,
 }
 
-/// A message received from a messaging provider
+/// Limits applied to incoming attachments before they are processed
 #[derive(Debug, Clone)]
+pub struct AttachmentPolicy {
+    /// Maximum attachment size in bytes
+    pub max_bytes: u64,
+    /// Allowed MIME types; entries may use a wildcard subtype (e.g. "image/*")
+    pub allowed_types: Vec<String>,
+}
+
+impl AttachmentPolicy {
+    /// Check an attachment against the policy.
+    ///
+    /// `size_on_disk` is used when the provider didn't report a size.
+    /// Returns a user-facing reason if the attachment is refused.
+    pub fn check(
+        &self,
+        attachment: &IncomingAttachment,
+        size_on_disk: Option<u64>,
+    ) -> std::result::Result<(), String> {
+        let content_type = attachment.content_type.to_lowercase();
+        if !self.is_type_allowed(&content_type) {
+            return Err(format!(
+                "{} files aren't supported (I can accept: {})",
+                content_type,
+                self.allowed_types.join(", ")
+            ));
+        }
+
+        if let Some(size) = attachment.size.or(size_on_disk) {
+            if size > self.max_bytes {
+                return Err(format!(
+                    "that {} is {}, over the {} limit",
+                    content_type,
+                    format_bytes(size),
+                    format_bytes(self.max_bytes)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_type_allowed(&self, content_type: &str) -> bool {
+        self.allowed_types.iter().any(|allowed| {
+            let allowed = allowed.to_lowercase();
+            match allowed.strip_suffix("/*") {
+                Some(prefix) => content_type
+                    .split_once('/')
+                    .is_some_and(|(top, _)| top == prefix),
+                None => allowed == "*" || allowed == content_type,
+            }
+        })
+    }
+}
+
+/// Human-readable byte size ("512 B", "3.4 MB")
+pub fn format_bytes(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = KB * 1024.0;
+    const GB: f64 = MB * 1024.0;
+
+    let b = bytes as f64;
+    if b >= GB {
+        format!("{:.1} GB", b / GB)
+    } else if b >= MB {
+        format!("{:.1} MB", b / MB)
+    } else if b >= KB {
+        format!("{:.1} KB", b / KB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// A file the agent wants delivered to the conversation
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingAttachment {
+    pub path: PathBuf,
+    pub caption: Option<String>,
+}
+
+/// Files queued by tools during a step; the worker delivers them once the
+/// step's messages have been sent
+pub type AttachmentOutbox = Arc<Mutex<Vec<OutgoingAttachment>>>;
+
+/// Emoji reactions to the user's message queued by tools during a step
+/// (see `ReactTool`); the worker sends them after the step's messages
+pub type ReactionOutbox = Arc<Mutex<Vec<String>>>;
+
+/// MIME type for a file, guessed from its extension
+pub fn content_type_for_path(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" | "log" | "md" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Prefix marking a group conversation identifier (`group:<id>`); such
+/// conversations get their own agent with `ContextType::Group`
+pub const GROUP_PREFIX: &str = "group:";
+
+/// The group id of a group conversation identifier
+pub fn group_id(identifier: &str) -> Option<&str> {
+    identifier.strip_prefix(GROUP_PREFIX)
+}
+
+/// An earlier message that a message replies to (Signal quote, Marmot reply tag)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuotedMessage {
+    /// Author of the quoted message (Signal UUID/number, Nostr pubkey)
+    pub author: String,
+    /// Transport id of the quoted message (Signal timestamp, Nostr event id)
+    pub id: String,
+    /// Text of the quoted message, if the transport includes it
+    pub text: Option<String>,
+    /// Whether the quoted message is one of Sage's own
+    pub from_self: bool,
+}
+
+/// An emoji reaction a user put on one of Sage's messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingReaction {
+    pub emoji: String,
+    /// Transport id of the message reacted to (Signal timestamp)
+    pub target_id: String,
+    /// The user took the reaction back
+    pub removed: bool,
+}
+
+/// Line telling the agent what the user is replying to
+pub fn quote_context(quote: &QuotedMessage) -> String {
+    let whose = if quote.from_self {
+        "your earlier message"
+    } else {
+        "an earlier message"
+    };
+    match quote
+        .text
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    {
+        Some(text) => {
+            let text: String = if text.chars().count() > 300 {
+                format!("{}...", text.chars().take(300).collect::<String>())
+            } else {
+                text.to_string()
+            };
+            format!("[Replying to {}: \"{}\"]", whose, text)
+        }
+        None => format!("[Replying to {}]", whose),
+    }
+}
+
+/// A message received from a messaging provider. Every backend produces this
+/// one envelope, so reply, quote and edit handling lives in the worker rather
+/// than in each messenger.
+#[derive(Debug, Clone, Default)]
 pub struct IncomingMessage {
     /// Unique identifier of the sender (Signal UUID, Nostr pubkey, etc.)
     pub source: String,
@@ -19,20 +199,303 @@ pub struct IncomingMessage {
     pub attachments: Vec<IncomingAttachment>,
     #[allow(dead_code)]
     pub timestamp: u64,
-    /// Identity key for agent lookup and reply routing (Signal UUID or Marmot pubkey)
+    /// Identity key for agent lookup and reply routing (Signal UUID, Marmot
+    /// pubkey, or `group:<id>` for a group conversation)
     pub reply_to: String,
     /// Transport-specific routing context to persist (e.g. Marmot nostr_group_id).
     /// Used to restore reply routing after restarts.
     pub reply_context: Option<String>,
+    /// Transport id of the message (Signal timestamp, Nostr event id, email
+    /// Message-ID). For an edit this is the id of the message being edited.
+    pub message_id: Option<String>,
+    /// The message this one replies to, if any
+    pub quote: Option<QuotedMessage>,
+    /// Users mentioned in the message (Signal UUIDs/numbers, Nostr pubkeys)
+    pub mentions: Vec<String>,
+    /// The message replaces an earlier one (`message_id`)
+    pub edited: bool,
+    /// Set when the message is a reaction to one of Sage's messages rather
+    /// than something to reply to (`message` is then empty)
+    pub reaction: Option<IncomingReaction>,
+    /// Sent by the account itself from another of its devices (a Signal sync
+    /// transcript): stored in recall memory as Sage's side of the
+    /// conversation, never answered
+    pub own_message: bool,
+    /// Durable inbox rows backing this message; acked once its turn completes
+    pub inbox_ids: Vec<Uuid>,
+}
+
+impl IncomingMessage {
+    /// Group the message was sent in, or None for a direct message
+    pub fn group_id(&self) -> Option<&str> {
+        group_id(&self.reply_to)
+    }
+
+    /// Id replies and reactions should target: the transport message id,
+    /// falling back to the timestamp
+    pub fn target_id(&self) -> String {
+        self.message_id
+            .clone()
+            .unwrap_or_else(|| self.timestamp.to_string())
+    }
+
+    /// Message text with what the agent needs to know about it: the message
+    /// it replies to and whether it is an edit
+    pub fn context_text(&self) -> String {
+        let mut text = self.message.clone();
+        if let Some(ref quote) = self.quote {
+            text = format!("{}\n{}", quote_context(quote), text);
+        }
+        if self.edited {
+            text = format!("{}\n{}", EDIT_CONTEXT, text);
+        }
+        text
+    }
+}
+
+/// Line telling the agent that a message replaces an earlier one
+pub const EDIT_CONTEXT: &str = "[Edited: this replaces the user's previous message]";
+
+/// Marks a stored message the account sent from another device, not Sage
+pub const OWN_MESSAGE_CONTEXT: &str = "[Sent from another device]";
+
+/// What a messaging provider supports. The worker consults these instead of
+/// special-casing backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MessengerCapabilities {
+    /// Typing indicators are shown to the recipient
+    pub typing: bool,
+    /// Emoji reactions to a specific message
+    pub reactions: bool,
+    /// Files can be sent (`send_attachment`)
+    pub attachments: bool,
+    /// Sent messages can be edited afterwards
+    pub edits: bool,
+    /// Replies can quote the message they answer (`send_quoted`)
+    pub quotes: bool,
+    /// Longest message the transport accepts, in characters (None = no limit)
+    pub max_message_length: Option<usize>,
+    /// A turn's replies are combined into one message instead of being sent
+    /// as they are produced (e.g. email)
+    pub single_reply: bool,
+    /// Delivery and read receipts are reported for sent messages
+    pub receipts: bool,
+}
+
+/// Split a message into pieces of at most `max_chars` characters, breaking at
+/// paragraph, then line, then word boundaries where possible.
+pub fn split_message(message: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut parts = Vec::new();
+    let mut rest = message.trim();
+
+    while rest.chars().count() > max_chars {
+        // Byte index just past the first `max_chars` characters
+        let limit = rest
+            .char_indices()
+            .nth(max_chars)
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let window = &rest[..limit];
+        let cut = if rest[limit..].starts_with(char::is_whitespace) {
+            limit
+        } else {
+            ["\n\n", "\n", " "]
+                .iter()
+                .find_map(|sep| window.rfind(sep).filter(|&i| i > 0))
+                .unwrap_or(limit)
+        };
+
+        parts.push(rest[..cut].trim_end().to_string());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
 }
 
 /// Trait for sending messages via a messaging provider
 pub trait Messenger: Send + Sync {
+    /// What this provider supports (nothing beyond plain text by default)
+    fn capabilities(&self) -> MessengerCapabilities {
+        MessengerCapabilities::default()
+    }
+
     fn send_message(&self, recipient: &str, message: &str) -> Result<()>;
     fn send_typing(&self, recipient: &str, stop: bool) -> Result<()>;
 
+    /// Send a message quoting the one it replies to. Transports without
+    /// quoting send it as a plain message.
+    fn send_quoted(&self, recipient: &str, message: &str, _quote: &QuotedMessage) -> Result<()> {
+        self.send_message(recipient, message)
+    }
+
+    /// Send a file with an optional caption
+    fn send_attachment(
+        &self,
+        _recipient: &str,
+        _path: &Path,
+        _caption: Option<&str>,
+    ) -> Result<()> {
+        anyhow::bail!("this messenger can't send attachments")
+    }
+
+    /// React to a message with an emoji. The target message is identified by
+    /// its author and timestamp (Signal needs both).
+    fn send_reaction(
+        &self,
+        _recipient: &str,
+        _target_author: &str,
+        _target_timestamp: u64,
+        _emoji: &str,
+    ) -> Result<()> {
+        anyhow::bail!("this messenger can't send reactions")
+    }
+
+    /// Called once a turn for `recipient` has finished sending replies
+    /// (no-op by default; request/response transports resolve here)
+    fn end_turn(&self, _recipient: &str) -> Result<()> {
+        Ok(())
+    }
+
     /// Periodic health/refresh check (no-op by default)
     fn refresh(&self) -> Result<()> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> AttachmentPolicy {
+        AttachmentPolicy {
+            max_bytes: 10 * 1024 * 1024,
+            allowed_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+        }
+    }
+
+    fn attachment(content_type: &str, size: Option<u64>) -> IncomingAttachment {
+        IncomingAttachment {
+            file: "abc123".to_string(),
+            content_type: content_type.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_attachment_type_guard() {
+        let policy = policy();
+        assert!(policy
+            .check(&attachment("image/png", Some(1000)), None)
+            .is_ok());
+        assert!(policy.check(&attachment("IMAGE/JPEG", None), None).is_ok());
+        assert!(policy
+            .check(&attachment("application/pdf", Some(1000)), None)
+            .is_ok());
+        assert!(policy
+            .check(&attachment("video/mp4", Some(1000)), None)
+            .is_err());
+        assert!(policy
+            .check(&attachment("application/pdfx", Some(1000)), None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_attachment_size_guard() {
+        let policy = policy();
+        let big = 500 * 1024 * 1024;
+        let err = policy
+            .check(&attachment("image/png", Some(big)), None)
+            .unwrap_err();
+        assert!(err.contains("500.0 MB"));
+        // Falls back to the size on disk when the provider didn't report one
+        assert!(policy
+            .check(&attachment("image/png", None), Some(big))
+            .is_err());
+        assert!(policy.check(&attachment("image/png", None), None).is_ok());
+    }
+
+    #[test]
+    fn test_content_type_for_path() {
+        assert_eq!(content_type_for_path(Path::new("chart.PNG")), "image/png");
+        assert_eq!(
+            content_type_for_path(Path::new("/workspace/report.pdf")),
+            "application/pdf"
+        );
+        assert_eq!(
+            content_type_for_path(Path::new("data")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KB");
+        assert_eq!(format_bytes(25 * 1024 * 1024), "25.0 MB");
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("short", 100), vec!["short"]);
+
+        let text = "First paragraph here.\n\nSecond paragraph is a bit longer.";
+        assert_eq!(
+            split_message(text, 40),
+            vec!["First paragraph here.", "Second paragraph is a bit longer."]
+        );
+
+        let parts = split_message("one two three four five", 9);
+        assert_eq!(parts, vec!["one two", "three", "four five"]);
+        assert_eq!(split_message("one two three", 7), vec!["one two", "three"]);
+
+        // No break point: hard cut on a char boundary
+        let parts = split_message("ééééé", 2);
+        assert_eq!(parts, vec!["éé", "éé", "é"]);
+    }
+    #[test]
+    fn test_quote_context() {
+        let mut quote = QuotedMessage {
+            author: "sage".to_string(),
+            id: "1760000000000".to_string(),
+            text: Some("Dentist is at 3pm".to_string()),
+            from_self: true,
+        };
+        assert_eq!(
+            quote_context(&quote),
+            "[Replying to your earlier message: \"Dentist is at 3pm\"]"
+        );
+
+        quote.from_self = false;
+        quote.text = None;
+        assert_eq!(quote_context(&quote), "[Replying to an earlier message]");
+    }
+
+    #[test]
+    fn test_incoming_message_envelope() {
+        let mut msg = IncomingMessage {
+            source: "user-1".to_string(),
+            message: "actually 4pm".to_string(),
+            timestamp: 1760000000500,
+            reply_to: "group:abc".to_string(),
+            message_id: Some("1760000000000".to_string()),
+            edited: true,
+            ..Default::default()
+        };
+        assert_eq!(msg.group_id(), Some("abc"));
+        assert_eq!(msg.target_id(), "1760000000000");
+        assert_eq!(
+            msg.context_text(),
+            format!("{}\nactually 4pm", EDIT_CONTEXT)
+        );
+
+        msg.edited = false;
+        msg.message_id = None;
+        msg.reply_to = "user-1".to_string();
+        assert_eq!(msg.group_id(), None);
+        assert_eq!(msg.target_id(), "1760000000500");
+        assert_eq!(msg.context_text(), "actually 4pm");
+    }
+}
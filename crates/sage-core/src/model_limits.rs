@@ -0,0 +1,293 @@
+//! Model Limits
+//!
+//! The chat model's context window and maximum output tokens, worked out once
+//! at startup instead of hard-coded, so smaller models don't overflow. Each
+//! limit comes from, in order:
+//!
+//! 1. `MODEL_CONTEXT_WINDOW` / `MODEL_MAX_OUTPUT_TOKENS`
+//! 2. the provider's OpenAI-compatible `GET /models` metadata (the field
+//!    names vLLM, OpenRouter, Groq and others use)
+//! 3. a table of known models (`KNOWN_MODELS`)
+//! 4. conservative defaults
+//!
+//! From them follow the LLM's `max_tokens` and the compaction threshold: a
+//! share (`COMPACTION_THRESHOLD`) of the context left once room for the reply
+//! is reserved.
+
+use serde_json::Value;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Context window assumed for an unknown model
+pub const DEFAULT_CONTEXT_WINDOW: usize = 32_768;
+
+/// Output tokens assumed for an unknown model
+pub const DEFAULT_MAX_OUTPUT_TOKENS: usize = 4_096;
+
+/// Share of the input budget at which history is compacted
+pub const COMPACTION_THRESHOLD: f32 = 0.80;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Known models (matched by substring of the lowercased model id, first match
+/// wins): context window, max output tokens
+const KNOWN_MODELS: &[(&str, usize, usize)] = &[
+    // Kimi K2 uses output tokens for reasoning, so give it plenty
+    ("kimi-k2", 256_000, 32_768),
+    ("deepseek-r1", 128_000, 32_768),
+    ("gpt-oss", 128_000, 32_768),
+    ("llama-3.3", 128_000, 8_192),
+    ("llama-3.1", 128_000, 8_192),
+    ("gemma-3", 128_000, 8_192),
+    ("qwen3", 32_768, 8_192),
+    ("mistral-small", 32_768, 8_192),
+];
+
+/// Where a limit came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitSource {
+    Config,
+    Provider,
+    KnownModel,
+    Default,
+}
+
+impl LimitSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitSource::Config => "config",
+            LimitSource::Provider => "provider metadata",
+            LimitSource::KnownModel => "known model table",
+            LimitSource::Default => "default",
+        }
+    }
+}
+
+/// Token limits of the chat model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelLimits {
+    pub context_window: usize,
+    pub max_output_tokens: usize,
+    pub context_source: LimitSource,
+    pub output_source: LimitSource,
+}
+
+impl Default for ModelLimits {
+    fn default() -> Self {
+        Self {
+            context_window: DEFAULT_CONTEXT_WINDOW,
+            max_output_tokens: DEFAULT_MAX_OUTPUT_TOKENS,
+            context_source: LimitSource::Default,
+            output_source: LimitSource::Default,
+        }
+    }
+}
+
+impl ModelLimits {
+    /// Tokens left for the prompt once room for the reply is reserved
+    pub fn input_budget(&self) -> usize {
+        self.context_window.saturating_sub(self.max_output_tokens)
+    }
+
+    /// Estimated history tokens above which compaction runs
+    pub fn compaction_threshold_tokens(&self) -> usize {
+        (self.input_budget() as f32 * COMPACTION_THRESHOLD) as usize
+    }
+}
+
+/// Pick each limit from the first source that has it (see module docs). The
+/// reply may use at most half the window, so there is always room for the
+/// prompt.
+pub fn resolve(
+    model: &str,
+    configured: (Option<usize>, Option<usize>),
+    provider: (Option<usize>, Option<usize>),
+) -> ModelLimits {
+    let known = known_limits(model);
+    let pick =
+        |configured: Option<usize>, provider: Option<usize>, known: Option<usize>, default| {
+            configured
+                .map(|v| (v, LimitSource::Config))
+                .or(provider.map(|v| (v, LimitSource::Provider)))
+                .or(known.map(|v| (v, LimitSource::KnownModel)))
+                .unwrap_or((default, LimitSource::Default))
+        };
+    let (context_window, context_source) = pick(
+        configured.0,
+        provider.0,
+        known.map(|k| k.0),
+        DEFAULT_CONTEXT_WINDOW,
+    );
+    let (max_output_tokens, output_source) = pick(
+        configured.1,
+        provider.1,
+        known.map(|k| k.1),
+        DEFAULT_MAX_OUTPUT_TOKENS,
+    );
+    ModelLimits {
+        context_window,
+        max_output_tokens: max_output_tokens.min(context_window / 2),
+        context_source,
+        output_source,
+    }
+}
+
+/// Limits of a model in `KNOWN_MODELS`
+fn known_limits(model: &str) -> Option<(usize, usize)> {
+    let model = model.to_lowercase();
+    KNOWN_MODELS
+        .iter()
+        .find(|(pattern, ..)| model.contains(pattern))
+        .map(|(_, context, output)| (*context, *output))
+}
+
+/// Context window and max output tokens of `model` in a `GET /models`
+/// response, if listed
+pub fn limits_from_metadata(model: &str, response: &Value) -> (Option<usize>, Option<usize>) {
+    let entries = response["data"].as_array().or(response.as_array());
+    let Some(entry) = entries
+        .into_iter()
+        .flatten()
+        .find(|entry| entry["id"].as_str() == Some(model))
+    else {
+        return (None, None);
+    };
+    let first = |paths: &[&[&str]]| {
+        paths.iter().find_map(|path| {
+            let value = path.iter().fold(entry, |value, key| &value[*key]);
+            value.as_u64().filter(|v| *v > 0).map(|v| v as usize)
+        })
+    };
+    let context = first(&[
+        &["context_length"],
+        &["context_window"],
+        &["max_context_length"],
+        &["max_model_len"],
+        &["top_provider", "context_length"],
+    ]);
+    let output = first(&[
+        &["max_output_tokens"],
+        &["max_completion_tokens"],
+        &["top_provider", "max_completion_tokens"],
+    ]);
+    (context, output)
+}
+
+/// Ask the provider for `model`'s limits. Failures only mean falling back.
+async fn probe(api_base: &str, api_key: &str, model: &str) -> (Option<usize>, Option<usize>) {
+    let url = format!("{}/models", api_base.trim_end_matches('/'));
+    let response = async {
+        reqwest::Client::builder()
+            .timeout(PROBE_TIMEOUT)
+            .build()?
+            .get(&url)
+            .bearer_auth(api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await
+    };
+    match response.await {
+        Ok(body) => limits_from_metadata(model, &body),
+        Err(e) => {
+            warn!("Couldn't read model metadata from {}: {}", url, e);
+            (None, None)
+        }
+    }
+}
+
+static LIMITS: OnceLock<ModelLimits> = OnceLock::new();
+
+/// Work out the chat model's limits and install them for `current` (once, at
+/// startup)
+pub async fn detect(
+    api_base: &str,
+    api_key: &str,
+    model: &str,
+    configured: (Option<usize>, Option<usize>),
+) -> ModelLimits {
+    let provider = match configured {
+        (Some(_), Some(_)) => (None, None),
+        _ => probe(api_base, api_key, model).await,
+    };
+    let limits = resolve(model, configured, provider);
+    info!(
+        "Model {}: context window {} ({}), max output {} ({}), compaction above {} tokens",
+        model,
+        limits.context_window,
+        limits.context_source.as_str(),
+        limits.max_output_tokens,
+        limits.output_source.as_str(),
+        limits.compaction_threshold_tokens()
+    );
+    if limits.context_source == LimitSource::Default {
+        warn!(
+            "Unknown context window for {} - assuming {} tokens; set MODEL_CONTEXT_WINDOW if it is larger",
+            model, DEFAULT_CONTEXT_WINDOW
+        );
+    }
+    let _ = LIMITS.set(limits);
+    limits
+}
+
+/// The chat model's limits (defaults until `detect` has run)
+pub fn current() -> ModelLimits {
+    LIMITS.get().copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let limits = resolve("maple/kimi-k2-5", (None, None), (None, None));
+        assert_eq!(limits.context_window, 256_000);
+        assert_eq!(limits.max_output_tokens, 32_768);
+        assert_eq!(limits.context_source, LimitSource::KnownModel);
+        assert_eq!(limits.compaction_threshold_tokens(), 178_585);
+
+        // Config beats the provider, which beats the table
+        let limits = resolve(
+            "kimi-k2",
+            (Some(100_000), None),
+            (Some(128_000), Some(16_384)),
+        );
+        assert_eq!(limits.context_window, 100_000);
+        assert_eq!(limits.context_source, LimitSource::Config);
+        assert_eq!(limits.max_output_tokens, 16_384);
+        assert_eq!(limits.output_source, LimitSource::Provider);
+
+        // Unknown and small: the reply can't take more than half the window
+        let limits = resolve("tiny-model", (Some(8_192), None), (None, None));
+        assert_eq!(limits.max_output_tokens, 4_096);
+        assert_eq!(limits.output_source, LimitSource::Default);
+        assert_eq!(
+            resolve("tiny-model", (None, None), (None, None)),
+            ModelLimits::default()
+        );
+    }
+
+    #[test]
+    fn test_limits_from_metadata() {
+        let response = serde_json::json!({
+            "data": [
+                {"id": "other", "context_length": 4096},
+                {"id": "llama-3.3-70b", "max_model_len": 65536},
+                {"id": "router/model", "context_length": 200000,
+                 "top_provider": {"max_completion_tokens": 8192}}
+            ]
+        });
+        assert_eq!(
+            limits_from_metadata("llama-3.3-70b", &response),
+            (Some(65_536), None)
+        );
+        assert_eq!(
+            limits_from_metadata("router/model", &response),
+            (Some(200_000), Some(8_192))
+        );
+        assert_eq!(limits_from_metadata("missing", &response), (None, None));
+    }
+}
@@ -0,0 +1,162 @@
+//! Model Routing
+//!
+//! Most turns don't need the flagship model: "thanks!", "haha" or "good
+//! night" get a short friendly reply whichever model writes it. With
+//! `ROUTER_CHEAP_MODEL` set, the first step of each turn is classified with
+//! cheap heuristics (`is_simple`, no model call): short messages without a
+//! question, a link, a number, an attachment or anything that reads like a
+//! request go to the cheap model, everything else to `MAPLE_MODEL`.
+//!
+//! Only the first step is routed. If the cheap model calls tools, the steps
+//! that process their results use the flagship, and a cheap call that fails
+//! before sending anything is redone with the flagship. First-time users
+//! always get the flagship, since onboarding matters more than the cost.
+//!
+//! The DSRs LM is process-wide, so cheap steps go through the raw
+//! chat-completions call of `streaming` with the model overridden. Their
+//! usage is recorded under the cheap model, priced with
+//! `ROUTER_CHEAP_PROMPT_PRICE_PER_MTOK` / `ROUTER_CHEAP_COMPLETION_PRICE_PER_MTOK`
+//! when set.
+
+use crate::config::Config;
+use crate::streaming::ModelOverride;
+
+/// Output tokens a cheap step may use; simple turns get short replies
+pub const CHEAP_MAX_OUTPUT_TOKENS: usize = 2_048;
+
+/// Most words in a message the cheap model takes
+const MAX_SIMPLE_WORDS: usize = 12;
+
+/// Words that make a message a request or a question, not small talk
+const REQUEST_WORDS: &[&str] = &[
+    "add", "book", "can", "cancel", "change", "check", "could", "create", "delete", "explain",
+    "find", "forget", "help", "how", "list", "look", "make", "need", "note", "plan", "please",
+    "remember", "remind", "schedule", "search", "send", "should", "show", "tell", "todo", "update",
+    "want", "what", "when", "where", "which", "who", "why", "would", "write",
+];
+
+/// Which model a step uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Cheap,
+    Flagship,
+}
+
+/// Whether a user message is small talk the cheap model can answer
+pub fn is_simple(message: &str, max_chars: usize) -> bool {
+    let message = message.trim();
+    if message.is_empty() || message.chars().count() > max_chars {
+        return false;
+    }
+    // Questions, numbers (times, dates, amounts), links, and the notes the
+    // worker appends for attachments, email and delivery
+    if message
+        .chars()
+        .any(|c| c == '?' || c == '[' || c.is_ascii_digit())
+        || message.contains("://")
+    {
+        return false;
+    }
+    let words: Vec<String> = message
+        .split_whitespace()
+        .map(|w| {
+            w.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'')
+                .to_lowercase()
+        })
+        .collect();
+    words.len() <= MAX_SIMPLE_WORDS
+        && !words
+            .iter()
+            .any(|w| REQUEST_WORDS.contains(&w.as_str()) || w.ends_with("n't"))
+}
+
+/// Picks the model for a turn's first step
+#[derive(Debug, Clone)]
+pub struct ModelRouter {
+    cheap_model: String,
+    max_chars: usize,
+}
+
+impl ModelRouter {
+    pub fn new(cheap_model: impl Into<String>, max_chars: usize) -> Self {
+        Self {
+            cheap_model: cheap_model.into(),
+            max_chars,
+        }
+    }
+
+    /// The router, if `ROUTER_CHEAP_MODEL` is set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .router_cheap_model
+            .as_ref()
+            .map(|model| Self::new(model.clone(), config.router_max_chars))
+    }
+
+    /// Tier for a turn starting with `message`
+    pub fn route(&self, message: &str, is_first_time_user: bool) -> Tier {
+        if !is_first_time_user && is_simple(message, self.max_chars) {
+            Tier::Cheap
+        } else {
+            Tier::Flagship
+        }
+    }
+
+    /// The cheap model, for `streaming::complete`
+    pub fn cheap(&self) -> ModelOverride {
+        ModelOverride {
+            model: self.cheap_model.clone(),
+            max_tokens: CHEAP_MAX_OUTPUT_TOKENS
+                .min(crate::model_limits::current().max_output_tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_talk_is_simple() {
+        for message in [
+            "thanks!",
+            "Thank you so much 🙏",
+            "haha",
+            "ok cool",
+            "good night!",
+            "love it",
+            "That's great news",
+        ] {
+            assert!(is_simple(message, 80), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_requests_and_questions_are_not_simple() {
+        for message in [
+            "",
+            "thanks?",
+            "remind me tomorrow",
+            "Can you look that up",
+            "meet at 5",
+            "see https://example.com",
+            "what do you think",
+            "I don't like that",
+            "nice\n\n[Uploaded Image: a cat]",
+            "this is a much longer message that goes on about a lot of things and keeps going",
+        ] {
+            assert!(!is_simple(message, 80), "{}", message);
+        }
+        assert!(!is_simple("thanks a lot", 5));
+    }
+
+    #[test]
+    fn test_route() {
+        let router = ModelRouter::new("small-model", 80);
+        assert_eq!(router.route("thanks!", false), Tier::Cheap);
+        assert_eq!(router.route("thanks!", true), Tier::Flagship);
+        assert_eq!(router.route("plan my week", false), Tier::Flagship);
+        assert_eq!(router.cheap().model, "small-model");
+        assert!(router.cheap().max_tokens <= CHEAP_MAX_OUTPUT_TOKENS);
+    }
+}
@@ -0,0 +1,259 @@
+//! Conversation Modes
+//!
+//! A mode changes how Sage talks in one conversation without touching what
+//! it remembers: `focus` keeps replies on task, `coach` guides instead of
+//! answering, `terse` keeps replies as short as possible. Users switch with
+//! `/mode <name>`, `/mode off` goes back to normal and `/mode` alone shows
+//! the current one.
+//!
+//! The mode is the conversation's `mode` preference, stored on the
+//! conversation's own agent, so a `/topic` thread can be in a different mode
+//! than its chat. Memory blocks, archival memory and facts stay shared.
+//! While a mode is on, its style section is added after the persona block
+//! and its tools are left out of the prompt; the agent can't call them
+//! either (see `SageAgent::step`).
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::memory::MemoryDb;
+
+/// Preference holding the conversation's mode (unset = normal)
+pub const PREFERENCE: &str = "mode";
+
+/// How Sage replies in a conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Focus,
+    Coach,
+    Terse,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Focus, Mode::Coach, Mode::Terse];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "focus" => Some(Mode::Focus),
+            "coach" => Some(Mode::Coach),
+            "terse" => Some(Mode::Terse),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Focus => "focus",
+            Mode::Coach => "coach",
+            Mode::Terse => "terse",
+        }
+    }
+
+    /// One line for `/mode`
+    pub fn summary(&self) -> &'static str {
+        match self {
+            Mode::Focus => "straight to the point, no small talk",
+            Mode::Coach => "questions and nudges that help you work it out yourself",
+            Mode::Terse => "as few words as possible",
+        }
+    }
+
+    /// Style section added to the persona block
+    pub fn style(&self) -> &'static str {
+        match self {
+            Mode::Focus => "The user wants to get something done. Answer what they ask directly and stay on that task: no small talk, tangents or emoji reactions, and keep non-urgent reminders and asides for later unless they bear on the task.",
+            Mode::Coach => "Act as a coach. Help the user reach answers themselves: ask one guiding question at a time, suggest the next step rather than doing it, point out progress and hold them to the goals they set. Give a full answer only when they ask for it outright.",
+            Mode::Terse => "Reply in as few words as possible, in a single short message: no greetings, preamble, recaps or follow-up questions unless something is ambiguous. A word or a number is a fine answer.",
+        }
+    }
+
+    /// Tools the agent doesn't get in this mode
+    pub fn denied_tools(&self) -> &'static [&'static str] {
+        match self {
+            Mode::Focus => &["react", "create_poll"],
+            // A coach suggests what to run instead of running it
+            Mode::Coach => &[
+                "shell",
+                "shell_session_start",
+                "shell_job_status",
+                "shell_job_kill",
+                "workspace_rollback",
+            ],
+            Mode::Terse => &["react", "deep_research"],
+        }
+    }
+
+    pub fn allows_tool(&self, name: &str) -> bool {
+        !self.denied_tools().contains(&name)
+    }
+
+    /// Persona block with this mode's style section after it
+    pub fn apply_style(&self, persona: &str) -> String {
+        let section = format!("Conversation mode: {}. {}", self.as_str(), self.style());
+        if persona.trim().is_empty() {
+            section
+        } else {
+            format!("{}\n\n{}", persona, section)
+        }
+    }
+}
+
+/// The conversation's mode. A stored name that isn't a mode reads as normal.
+pub fn current(db: &MemoryDb, agent_id: Uuid) -> Result<Option<Mode>> {
+    Ok(db
+        .preferences()
+        .get(agent_id, PREFERENCE)?
+        .and_then(|p| Mode::parse(&p.value)))
+}
+
+/// Switch the conversation's mode (None = back to normal)
+pub fn set(db: &MemoryDb, agent_id: Uuid, mode: Option<Mode>) -> Result<()> {
+    match mode {
+        Some(mode) => {
+            db.preferences().set(agent_id, PREFERENCE, mode.as_str())?;
+        }
+        None => {
+            db.preferences().delete(agent_id, PREFERENCE)?;
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// /mode
+// ============================================================================
+
+/// A `/mode` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModeCommand {
+    /// `/mode`: show the current mode and the choices
+    Show,
+    /// `/mode <name>`
+    Set(Mode),
+    /// `/mode off`
+    Off,
+    /// `/mode <something else>`
+    Unknown(String),
+}
+
+/// Parse `/mode`, `/mode <name>` or `/mode off`. Returns None for any other
+/// message.
+pub fn parse_mode_command(text: &str) -> Option<ModeCommand> {
+    let rest = text.trim().strip_prefix("/mode")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(match rest.trim() {
+        "" => ModeCommand::Show,
+        name if matches!(name.to_lowercase().as_str(), "off" | "normal" | "none") => {
+            ModeCommand::Off
+        }
+        name => match Mode::parse(name) {
+            Some(mode) => ModeCommand::Set(mode),
+            None => ModeCommand::Unknown(name.to_string()),
+        },
+    })
+}
+
+fn choices() -> String {
+    Mode::ALL
+        .iter()
+        .map(|mode| format!("\n- {}: {}", mode.as_str(), mode.summary()))
+        .collect()
+}
+
+/// Reply to `/mode` for a conversation in `mode`
+pub fn describe(mode: Option<Mode>) -> String {
+    let current = match mode {
+        Some(mode) => format!("This conversation is in {} mode.", mode.as_str()),
+        None => "No mode is on.".to_string(),
+    };
+    format!(
+        "{} Modes:{}\n/mode <name> switches, /mode off goes back to normal. Memory is the same in every mode.",
+        current,
+        choices()
+    )
+}
+
+/// Run a `/mode` command for the conversation of `agent_id`
+pub fn run_command(db: &MemoryDb, agent_id: Uuid, command: ModeCommand) -> Result<String> {
+    Ok(match command {
+        ModeCommand::Show => describe(current(db, agent_id)?),
+        ModeCommand::Set(mode) => {
+            set(db, agent_id, Some(mode))?;
+            format!(
+                "Switched to {} mode: {}. /mode off goes back to normal.",
+                mode.as_str(),
+                mode.summary()
+            )
+        }
+        ModeCommand::Off => {
+            let was = current(db, agent_id)?;
+            set(db, agent_id, None)?;
+            match was {
+                Some(mode) => format!("Left {} mode.", mode.as_str()),
+                None => "No mode was on.".to_string(),
+            }
+        }
+        ModeCommand::Unknown(name) => {
+            format!("There's no \"{}\" mode. Modes:{}", name, choices())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sage_agent::ToolRegistry;
+
+    #[test]
+    fn test_parse_mode_command() {
+        assert_eq!(parse_mode_command("/mode"), Some(ModeCommand::Show));
+        assert_eq!(
+            parse_mode_command(" /mode Focus "),
+            Some(ModeCommand::Set(Mode::Focus))
+        );
+        assert_eq!(parse_mode_command("/mode off"), Some(ModeCommand::Off));
+        assert_eq!(parse_mode_command("/mode normal"), Some(ModeCommand::Off));
+        assert_eq!(
+            parse_mode_command("/mode pirate"),
+            Some(ModeCommand::Unknown("pirate".to_string()))
+        );
+        assert_eq!(parse_mode_command("/modes"), None);
+        assert_eq!(parse_mode_command("mode focus"), None);
+    }
+
+    #[test]
+    fn test_mode_names_round_trip() {
+        for mode in Mode::ALL {
+            assert_eq!(Mode::parse(mode.as_str()), Some(mode));
+        }
+        assert_eq!(Mode::parse("off"), None);
+    }
+
+    #[test]
+    fn test_denied_tools_exist() {
+        let registry = ToolRegistry::all_tools_description_only();
+        for mode in Mode::ALL {
+            for tool in mode.denied_tools() {
+                assert!(
+                    registry.has(tool),
+                    "{} denies unknown tool {}",
+                    mode.as_str(),
+                    tool
+                );
+            }
+        }
+        assert!(!Mode::Focus.allows_tool("react"));
+        assert!(Mode::Focus.allows_tool("web_search"));
+    }
+
+    #[test]
+    fn test_apply_style() {
+        let persona = Mode::Terse.apply_style("I am Sage.");
+        assert!(persona.starts_with("I am Sage.\n\nConversation mode: terse."));
+        assert!(Mode::Coach
+            .apply_style("")
+            .starts_with("Conversation mode: coach."));
+    }
+}
@@ -0,0 +1,531 @@
+//! Birthday and Anniversary Check-ins
+//!
+//! Dates of personal significance are picked up from the structured facts
+//! (see `memory::facts`): a current fact with a birthday or anniversary
+//! predicate (`birthday`, `date_of_birth`, `anniversary`,
+//! `wedding_anniversary`, ...) whose object reads as a month and day
+//! ("March 3", "3rd of March", "1990-03-03", "3/3", month first).
+//!
+//! Each one gets a yearly `occasion` task on the main agent, at
+//! `OCCASION_CHECKIN_HOUR` in the user's timezone. When it fires, the chat
+//! model composes a short personal check-in from what memory holds about the
+//! person and the recent conversation; it is sent and stored like any of
+//! Sage's messages.
+//!
+//! Tasks are synced when the agent is loaded and whenever fact extraction
+//! stores an occasion fact: a corrected date replaces the task and a
+//! forgotten fact retires it. Opting out:
+//! - one occasion: cancel its task (`cancel_schedule`, `/tasks cancel`);
+//!   the cancelled task is kept so sync doesn't bring it back
+//! - all of them: the `occasions` preference set to `off` (`/occasions off`)
+//!
+//! Owners see what was detected with `/occasions`.
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate, Utc};
+use dspy_rs::{Predict, Signature};
+use std::collections::HashSet;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::memory::{preference_keys, FactManager, FactRow, MemoryManager};
+use crate::scheduler::{self, OccasionPayload, SchedulerDb, TaskPayload, TaskStatus, TaskType};
+use crate::usage::{self, CallKind};
+
+/// Preference that turns check-ins off (`off`)
+pub const PREFERENCE: &str = "occasions";
+
+/// Default local hour of a check-in
+pub const DEFAULT_HOUR: u32 = 9;
+
+/// Facts read to find occasions
+const MAX_FACTS: i64 = 500;
+
+/// Facts about the person (and the user) shown to the model
+const PERSON_FACTS: i64 = 20;
+
+/// Earlier messages shown to the model
+const RECENT_MESSAGES: usize = 10;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Instruction for the check-in DSRs signature
+pub const COMPOSE_INSTRUCTION: &str = r#"You are Sage, a personal assistant, writing a short, warm message to your user because today is an occasion they told you about: their own birthday or anniversary, or that of someone in their life. Use what you know about the person and the recent conversation to make it personal (a shared plan, a gift idea they mentioned, how old someone turns if the year is known), but never invent details. If it is someone else's day, remind the user and offer help (a message to send, a gift, a reservation). One to three sentences, plain text, no hashtags."#;
+
+/// What kind of day an occasion is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OccasionKind {
+    Birthday,
+    Anniversary,
+}
+
+impl OccasionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OccasionKind::Birthday => "birthday",
+            OccasionKind::Anniversary => "anniversary",
+        }
+    }
+
+    /// The kind a fact predicate records, if any
+    pub fn from_predicate(predicate: &str) -> Option<Self> {
+        let predicate = predicate.to_lowercase();
+        if predicate.contains("anniversary") {
+            Some(OccasionKind::Anniversary)
+        } else if predicate.contains("birthday")
+            || predicate.contains("birth_date")
+            || predicate.contains("date_of_birth")
+            || predicate == "born" // "born_in" is usually a place
+            || predicate == "born_on"
+        {
+            Some(OccasionKind::Birthday)
+        } else {
+            None
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "birthday" => Some(OccasionKind::Birthday),
+            "anniversary" => Some(OccasionKind::Anniversary),
+            _ => None,
+        }
+    }
+}
+
+/// A yearly date of personal significance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occasion {
+    /// Fact subject ("user", "sister_anna")
+    pub subject: String,
+    pub kind: OccasionKind,
+    pub month: u32,
+    pub day: u32,
+    /// Year of birth or of the wedding, when the fact gives one
+    pub year: Option<i32>,
+}
+
+impl Occasion {
+    /// The occasion a fact records, if it is one with a readable date
+    pub fn from_fact(subject: &str, predicate: &str, object: &str) -> Option<Self> {
+        let kind = OccasionKind::from_predicate(predicate)?;
+        let (month, day, year) = parse_month_day(object)?;
+        Some(Self {
+            subject: subject.to_string(),
+            kind,
+            month,
+            day,
+            year,
+        })
+    }
+
+    /// The occasion a check-in task is for
+    pub fn from_payload(payload: &OccasionPayload) -> Option<Self> {
+        Some(Self {
+            subject: payload.subject.clone(),
+            kind: OccasionKind::parse(&payload.occasion)?,
+            month: payload.month,
+            day: payload.day,
+            year: payload.year,
+        })
+    }
+
+    pub fn payload(&self) -> OccasionPayload {
+        OccasionPayload {
+            subject: self.subject.clone(),
+            occasion: self.kind.as_str().to_string(),
+            month: self.month,
+            day: self.day,
+            year: self.year,
+        }
+    }
+
+    /// Yearly cron at `hour`. A February 29 date is marked on the 28th, so
+    /// it comes up every year.
+    pub fn cron(&self, hour: u32) -> String {
+        let day = if (self.month, self.day) == (2, 29) {
+            28
+        } else {
+            self.day
+        };
+        format!("0 0 {} {} {} *", hour.min(23), day, self.month)
+    }
+
+    /// "March 3"
+    pub fn date_label(&self) -> String {
+        format!("{} {}", MONTHS[self.month as usize - 1], self.day)
+    }
+
+    /// Task description, e.g. "Birthday check-in: sister_anna (March 3)"
+    pub fn describe(&self) -> String {
+        let kind = match self.kind {
+            OccasionKind::Birthday => "Birthday",
+            OccasionKind::Anniversary => "Anniversary",
+        };
+        format!(
+            "{} check-in: {} ({})",
+            kind,
+            self.subject,
+            self.date_label()
+        )
+    }
+
+    fn key(&self) -> (&str, OccasionKind) {
+        (&self.subject, self.kind)
+    }
+}
+
+/// Month, day and year (if given) of a date written the way people do:
+/// "March 3", "3rd of March", "Mar 3, 1990", "1990-03-03", "3/3" (month
+/// first). None if it isn't a valid day of the year.
+pub fn parse_month_day(text: &str) -> Option<(u32, u32, Option<i32>)> {
+    let text = text.trim().to_lowercase();
+    let valid = |month: u32, day: u32| NaiveDate::from_ymd_opt(2024, month, day).is_some();
+
+    // Numeric: 1990-03-03, 03-03, 3/3/1990
+    let parts: Vec<&str> = text.split(['-', '/', '.']).map(str::trim).collect();
+    if parts.len() >= 2 && parts.iter().all(|p| p.chars().all(|c| c.is_ascii_digit())) {
+        let n: Vec<u32> = parts.iter().filter_map(|p| p.parse().ok()).collect();
+        let (month, day, year) = match (parts[0].len(), n.as_slice()) {
+            (4, [y, m, d]) => (*m, *d, Some(*y as i32)),
+            (_, [m, d]) => (*m, *d, None),
+            (_, [m, d, y]) if parts[2].len() == 4 => (*m, *d, Some(*y as i32)),
+            _ => return None,
+        };
+        return valid(month, day).then_some((month, day, year));
+    }
+
+    // Words: a month name, a day and maybe a year
+    let mut month = None;
+    let mut day = None;
+    let mut year = None;
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            match (digits.len(), digits.parse::<u32>().ok()) {
+                (4, Some(y)) => year = Some(y as i32),
+                (1 | 2, Some(d)) if day.is_none() => day = Some(d),
+                _ => {}
+            }
+        } else if word.len() >= 3 && month.is_none() {
+            month = MONTHS
+                .iter()
+                .position(|m| m.to_lowercase().starts_with(word))
+                .map(|i| i as u32 + 1);
+        }
+    }
+    let (month, day) = (month?, day?);
+    valid(month, day).then_some((month, day, year))
+}
+
+/// Occasions recorded in a set of facts, one per subject and kind
+pub fn from_facts(facts: &[FactRow]) -> Vec<Occasion> {
+    let mut seen = HashSet::new();
+    facts
+        .iter()
+        .filter_map(|f| Occasion::from_fact(&f.subject, &f.predicate, &f.object))
+        .filter(|o| seen.insert((o.subject.clone(), o.kind)))
+        .collect()
+}
+
+/// Whether check-ins are on for this memory (the `occasions` preference)
+pub fn enabled(memory: &MemoryManager) -> bool {
+    !matches!(
+        memory.get_preference(PREFERENCE).ok().flatten().as_deref(),
+        Some("off" | "false" | "0")
+    )
+}
+
+/// What a sync changed
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncReport {
+    pub added: usize,
+    pub retired: usize,
+}
+
+/// Check-in tasks of an agent with their occasions
+fn occasion_tasks(
+    scheduler_db: &SchedulerDb,
+    agent_id: Uuid,
+) -> Result<Vec<(scheduler::ScheduledTask, Occasion)>> {
+    Ok(scheduler_db
+        .get_tasks_by_agent(agent_id, None)?
+        .into_iter()
+        .filter_map(|task| match &task.payload {
+            TaskPayload::Occasion(p) => Occasion::from_payload(p).map(|o| (task, o)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Make the agent's check-in tasks match `occasions`: create missing ones,
+/// replace those whose date changed and retire (complete) those no longer
+/// wanted. An occasion whose task was cancelled is left alone.
+pub fn sync(
+    scheduler_db: &SchedulerDb,
+    agent_id: Uuid,
+    occasions: &[Occasion],
+    timezone: &str,
+    hour: u32,
+) -> Result<SyncReport> {
+    let tasks = occasion_tasks(scheduler_db, agent_id)?;
+    let active = |status: &TaskStatus| matches!(status, TaskStatus::Pending | TaskStatus::Running);
+    let mut report = SyncReport::default();
+
+    for occasion in occasions {
+        let same: Vec<_> = tasks
+            .iter()
+            .filter(|(_, o)| o.key() == occasion.key())
+            .collect();
+        if same.iter().any(|(t, _)| t.status == TaskStatus::Cancelled) {
+            continue;
+        }
+        let mut current = false;
+        for (task, existing) in same.iter().filter(|(t, _)| active(&t.status)) {
+            if (existing.month, existing.day, existing.year)
+                == (occasion.month, occasion.day, occasion.year)
+            {
+                current = true;
+            } else {
+                scheduler_db.mark_completed(task.id)?;
+                report.retired += 1;
+            }
+        }
+        if current {
+            continue;
+        }
+
+        let cron = occasion.cron(hour);
+        scheduler_db.create_task(
+            agent_id,
+            TaskType::Occasion,
+            TaskPayload::Occasion(occasion.payload()),
+            scheduler::next_cron_time(&cron, timezone)?,
+            Some(cron),
+            timezone.to_string(),
+            occasion.describe(),
+        )?;
+        report.added += 1;
+    }
+
+    for (task, existing) in &tasks {
+        if active(&task.status) && !occasions.iter().any(|o| o.key() == existing.key()) {
+            scheduler_db.mark_completed(task.id)?;
+            report.retired += 1;
+        }
+    }
+
+    if report != SyncReport::default() {
+        info!(
+            "Occasion check-ins for agent {}: {} added, {} retired",
+            agent_id, report.added, report.retired
+        );
+    }
+    Ok(report)
+}
+
+/// Sync the check-ins of the main agent `facts` belong to with them (or
+/// retire them all when check-ins are off)
+pub fn sync_facts(
+    scheduler_db: &SchedulerDb,
+    facts: &FactManager,
+    enabled: bool,
+    timezone: &str,
+    hour: u32,
+) -> Result<SyncReport> {
+    let occasions = if enabled {
+        from_facts(&facts.current(None, MAX_FACTS)?)
+    } else {
+        Vec::new()
+    };
+    sync(
+        scheduler_db,
+        facts.core_agent_id(),
+        &occasions,
+        timezone,
+        hour,
+    )
+}
+
+/// `sync_facts` with the memory's `occasions` and timezone preferences
+pub fn sync_memory(
+    scheduler_db: &SchedulerDb,
+    memory: &MemoryManager,
+    hour: u32,
+) -> Result<SyncReport> {
+    let timezone = memory
+        .get_preference(preference_keys::TIMEZONE)?
+        .unwrap_or_else(|| "UTC".to_string());
+    sync_facts(
+        scheduler_db,
+        memory.facts(),
+        enabled(memory),
+        &timezone,
+        hour,
+    )
+}
+
+/// One line per check-in task of the agent, for `/occasions`
+pub fn list(scheduler_db: &SchedulerDb, agent_id: Uuid) -> Result<Vec<String>> {
+    let mut tasks = occasion_tasks(scheduler_db, agent_id)?;
+    tasks.retain(|(t, _)| t.status != TaskStatus::Completed);
+    tasks.sort_by_key(|(_, o)| (o.month, o.day));
+    Ok(tasks
+        .into_iter()
+        .map(|(task, occasion)| {
+            let status = match task.status {
+                TaskStatus::Cancelled => " - opted out".to_string(),
+                TaskStatus::Failed => " - failed, recreated on the next sync".to_string(),
+                _ => String::new(),
+            };
+            format!(
+                "{}  {}{}",
+                &task.id.to_string()[..8],
+                occasion.describe(),
+                status
+            )
+        })
+        .collect())
+}
+
+/// DSRs signature for composing a check-in
+#[derive(Signature, Clone, Debug)]
+pub struct ComposeCheckIn {
+    #[input(desc = "Today's occasion, e.g. 'Birthday of sister_anna (March 3)'")]
+    pub occasion: String,
+
+    #[input(desc = "Facts about the person and the user, one per line")]
+    pub facts: String,
+
+    #[input(desc = "Recent messages of the conversation")]
+    pub recent_messages: String,
+
+    #[output(desc = "The message to send the user")]
+    pub message: String,
+}
+
+/// Compose the check-in for an occasion task from the agent's memory
+pub async fn compose(memory: &MemoryManager, payload: &OccasionPayload) -> Result<String> {
+    let occasion = Occasion::from_payload(payload)
+        .ok_or_else(|| anyhow::anyhow!("Unknown occasion '{}'", payload.occasion))?;
+    let facts = memory.facts();
+    let mut rows = facts.current(Some(&occasion.subject), PERSON_FACTS)?;
+    if occasion.subject != "user" {
+        rows.extend(facts.current(Some("user"), PERSON_FACTS)?);
+    }
+    let facts: Vec<String> = rows
+        .iter()
+        .map(|f| format!("{} / {} / {}", f.subject, f.predicate, f.object))
+        .collect();
+    let recent: Vec<String> = memory
+        .get_recent_messages(RECENT_MESSAGES)?
+        .into_iter()
+        .filter(|(role, _, _)| role == "user" || role == "assistant")
+        .map(|(role, content, _)| format!("[{}]: {}", role, content))
+        .collect();
+    let kind = match occasion.kind {
+        OccasionKind::Birthday => "Birthday",
+        OccasionKind::Anniversary => "Anniversary",
+    };
+
+    let predictor = Predict::<ComposeCheckIn>::builder()
+        .instruction(COMPOSE_INSTRUCTION)
+        .build();
+    let result = predictor
+        .call_with_meta(ComposeCheckInInput {
+            occasion: format!(
+                "{} of {} ({}{})",
+                kind,
+                occasion.subject,
+                occasion.date_label(),
+                occasion
+                    .year
+                    .map(|year| format!(", {} years", Utc::now().year() - year))
+                    .unwrap_or_default()
+            ),
+            facts: facts.join("\n"),
+            recent_messages: recent.join("\n"),
+        })
+        .await?;
+    usage::record_chat(
+        CallKind::CheckIn,
+        result.lm_usage.prompt_tokens as i64,
+        result.lm_usage.completion_tokens as i64,
+    );
+
+    let message = result.output.message.trim().to_string();
+    if message.is_empty() {
+        anyhow::bail!("The model returned an empty check-in");
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_month_day() {
+        assert_eq!(parse_month_day("March 3"), Some((3, 3, None)));
+        assert_eq!(parse_month_day("3rd of March"), Some((3, 3, None)));
+        assert_eq!(parse_month_day("Mar 3, 1990"), Some((3, 3, Some(1990))));
+        assert_eq!(parse_month_day("1990-03-03"), Some((3, 3, Some(1990))));
+        assert_eq!(parse_month_day("12/25"), Some((12, 25, None)));
+        assert_eq!(parse_month_day("2/29/1996"), Some((2, 29, Some(1996))));
+        assert_eq!(parse_month_day("February 30"), None);
+        assert_eq!(parse_month_day("in the spring"), None);
+        assert_eq!(parse_month_day("1990"), None);
+    }
+
+    #[test]
+    fn test_occasion_from_fact() {
+        let occasion = Occasion::from_fact("sister_anna", "birthday", "March 3rd").unwrap();
+        assert_eq!(occasion.kind, OccasionKind::Birthday);
+        assert_eq!(occasion.cron(9), "0 0 9 3 3 *");
+        assert_eq!(
+            occasion.describe(),
+            "Birthday check-in: sister_anna (March 3)"
+        );
+        assert_eq!(
+            Occasion::from_fact("user", "wedding_anniversary", "2015-06-20")
+                .unwrap()
+                .kind,
+            OccasionKind::Anniversary
+        );
+        assert!(Occasion::from_fact("user", "born_in", "March 3").is_none());
+        assert!(Occasion::from_fact("user", "likes", "March 3").is_none());
+
+        let leap = Occasion::from_fact("user", "date_of_birth", "1996-02-29").unwrap();
+        assert_eq!(leap.cron(9), "0 0 9 28 2 *");
+        assert!(scheduler::parse_cron(&leap.cron(9)).is_ok());
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let occasion = Occasion::from_fact("user", "anniversary", "June 20").unwrap();
+        let payload = TaskPayload::Occasion(occasion.payload());
+        let json = serde_json::to_value(&payload).unwrap();
+        match serde_json::from_value::<TaskPayload>(json).unwrap() {
+            TaskPayload::Occasion(p) => {
+                assert_eq!(Occasion::from_payload(&p), Some(occasion))
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+    }
+}
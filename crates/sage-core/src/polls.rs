@@ -0,0 +1,717 @@
+//! Group Polls
+//!
+//! `create_poll` starts a poll in a group chat (a Signal group or a Marmot
+//! group) and schedules a `close_poll` tool call at its deadline, which
+//! tallies the votes and announces the result in the chat.
+//!
+//! Votes never reach the agent: the main loop hands every incoming message to
+//! `PollDb::try_vote` first, which counts
+//! - a reply quoting the poll (its text carries `Poll [<id>]`) with a number,
+//!   a keycap emoji or an option's text,
+//! - "vote <number or option>" sent in the chat (how Marmot members vote,
+//!   since Marmot has no quotes or reactions),
+//! - a keycap reaction (1️⃣, 2️⃣, ...) to a Sage message, for the newest open
+//!   poll in the chat.
+//!
+//! Each member has one vote per poll; voting again changes it. Like todos,
+//! polls are scoped to the chat (see `todos::scope_key`), so in Marmot every
+//! member's vote counts towards the same poll.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::itinerary;
+use crate::messenger::{self, IncomingMessage};
+use crate::sage_agent::{Tool, ToolResult};
+use crate::scheduler::{SchedulerDb, TaskPayload, TaskType, ToolCallPayload};
+use crate::schema::{chat_contexts, poll_votes, polls};
+use crate::todos::{self, MARMOT_PREFIX};
+
+/// Most options a poll can have (one keycap emoji each)
+pub const MAX_OPTIONS: usize = 9;
+
+/// Deadline when `create_poll` is given none
+const DEFAULT_DURATION_HOURS: i64 = 24;
+
+const KEYCAPS: [&str; MAX_OPTIONS] = ["1️⃣", "2️⃣", "3️⃣", "4️⃣", "5️⃣", "6️⃣", "7️⃣", "8️⃣", "9️⃣"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A stored poll
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = polls)]
+#[allow(dead_code)]
+pub struct Poll {
+    pub id: Uuid,
+    pub scope: String,
+    pub agent_id: Uuid,
+    pub question: String,
+    pub options: Vec<String>,
+    pub closes_at: DateTime<Utc>,
+    pub close_task_id: Option<Uuid>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = polls)]
+struct NewPoll {
+    id: Uuid,
+    scope: String,
+    agent_id: Uuid,
+    question: String,
+    options: Vec<String>,
+    closes_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = poll_votes)]
+struct NewVote<'a> {
+    poll_id: Uuid,
+    voter: &'a str,
+    voter_name: Option<&'a str>,
+    option_index: i32,
+}
+
+/// A vote `try_vote` counted
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountedVote {
+    pub option: String,
+    /// The vote came from a reaction (the reaction is its own acknowledgement)
+    pub via_reaction: bool,
+}
+
+/// Votes per option, in option order: (option, voter names)
+pub type Tally = Vec<(String, Vec<String>)>;
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Short id shown in the poll text and accepted by `close_poll`
+pub fn short_id(id: Uuid) -> String {
+    id.to_string()[..8].to_string()
+}
+
+/// Poll scope of a chat, or None outside groups
+pub fn group_scope(
+    identifier: &str,
+    context_type: &str,
+    reply_context: Option<&str>,
+    marmot: bool,
+) -> Option<String> {
+    let scope = todos::scope_key(identifier, context_type, reply_context, marmot);
+    (messenger::group_id(&scope).is_some() || scope.starts_with(MARMOT_PREFIX)).then_some(scope)
+}
+
+/// Option index of a keycap emoji ("2️⃣" -> 1)
+pub fn keycap_index(emoji: &str) -> Option<usize> {
+    let emoji = emoji.trim();
+    KEYCAPS.iter().position(|k| *k == emoji).or_else(|| {
+        // Some clients drop the variation selector
+        let digit = emoji.strip_suffix('\u{20E3}')?.trim_end_matches('\u{FE0F}');
+        digit.parse::<usize>().ok()?.checked_sub(1)
+    })
+}
+
+/// Option a vote names: a number, a keycap emoji, or an option's text
+/// (case-insensitive)
+pub fn parse_choice(text: &str, options: &[String]) -> Option<usize> {
+    let text = text
+        .trim()
+        .trim_start_matches('#')
+        .trim_end_matches(['.', '!'])
+        .trim();
+    let index = match text.parse::<usize>() {
+        Ok(n) => n.checked_sub(1),
+        Err(_) => keycap_index(text).or_else(|| {
+            options
+                .iter()
+                .position(|option| option.trim().eq_ignore_ascii_case(text))
+        }),
+    }?;
+    (index < options.len()).then_some(index)
+}
+
+/// The poll id in a poll message ("📊 Poll [1a2b3c4d]: ...")
+pub fn quoted_poll_id(text: &str) -> Option<&str> {
+    let start = text.find("Poll [")? + "Poll [".len();
+    let id = text.get(start..start + 8)?;
+    id.chars()
+        .all(|c| c.is_ascii_hexdigit())
+        .then_some(id)
+        .filter(|_| text[start + 8..].starts_with(']'))
+}
+
+/// Text of "vote <choice>", or None for other messages
+pub fn strip_vote_command(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let (word, rest) = text.split_at(text.find(char::is_whitespace)?);
+    word.eq_ignore_ascii_case("vote").then(|| rest.trim())
+}
+
+/// Message text without the "Name: " prefix group messages get
+fn without_sender<'a>(msg: &'a IncomingMessage) -> &'a str {
+    let sender = msg.source_name.as_deref().unwrap_or(&msg.source);
+    msg.message
+        .strip_prefix(sender)
+        .and_then(|rest| rest.strip_prefix(": "))
+        .unwrap_or(&msg.message)
+}
+
+/// The message members see
+pub fn poll_text(poll: &Poll, timezone: &str, marmot: bool) -> String {
+    let mut text = format!("📊 Poll [{}]: {}", short_id(poll.id), poll.question);
+    for (i, option) in poll.options.iter().enumerate() {
+        text.push_str(&format!("\n{} {}", KEYCAPS[i], option));
+    }
+    let how = if marmot {
+        "Vote by sending \"vote 1\" (or the option's name)."
+    } else {
+        "Vote by replying to this message with a number, or react with the number."
+    };
+    text.push_str(&format!(
+        "\n{} Closes {}.",
+        how,
+        itinerary::format_local(poll.closes_at, timezone)
+    ));
+    text
+}
+
+/// Voter names per option
+pub fn tally(options: &[String], votes: &[(i32, String)]) -> Tally {
+    let mut tally: Tally = options.iter().map(|o| (o.clone(), Vec::new())).collect();
+    for (index, voter) in votes {
+        if let Some((_, voters)) = tally.get_mut(*index as usize) {
+            voters.push(voter.clone());
+        }
+    }
+    tally
+}
+
+/// Result announcement: counts per option, most votes first, and the winner
+pub fn format_result(question: &str, tally: &Tally) -> String {
+    let total: usize = tally.iter().map(|(_, voters)| voters.len()).sum();
+    if total == 0 {
+        return format!("{}\nNo votes were cast.", question);
+    }
+    let mut ranked: Vec<&(String, Vec<String>)> = tally.iter().collect();
+    ranked.sort_by_key(|(_, voters)| std::cmp::Reverse(voters.len()));
+
+    let mut text = question.to_string();
+    for (option, voters) in &ranked {
+        text.push_str(&format!(
+            "\n{}: {} vote{}",
+            option,
+            voters.len(),
+            if voters.len() == 1 { "" } else { "s" }
+        ));
+        if !voters.is_empty() {
+            text.push_str(&format!(" ({})", voters.join(", ")));
+        }
+    }
+    let top = ranked[0].1.len();
+    let winners: Vec<&str> = ranked
+        .iter()
+        .filter(|(_, voters)| voters.len() == top)
+        .map(|(option, _)| option.as_str())
+        .collect();
+    match winners.as_slice() {
+        [winner] => text.push_str(&format!("\nWinner: {}", winner)),
+        _ => text.push_str(&format!("\nTie: {}", winners.join(", "))),
+    }
+    text
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct PollDb {
+    conn: Arc<DbConn>,
+    /// Whether chats are Marmot chats, whose reply_context is their group
+    marmot: bool,
+}
+
+impl PollDb {
+    /// Create a new PollDb with its own connection
+    pub fn connect(db_url: &str, marmot: bool) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            marmot,
+        })
+    }
+
+    /// Group scope of an agent's chat (None for direct chats)
+    fn scope_for(&self, agent_id: Uuid) -> Result<Option<String>> {
+        self.conn.run(|conn| {
+            let (identifier, context_type, reply_context): (String, String, Option<String>) =
+                chat_contexts::table
+                    .filter(chat_contexts::id.eq(agent_id))
+                    .select((
+                        chat_contexts::signal_identifier,
+                        chat_contexts::context_type,
+                        chat_contexts::reply_context,
+                    ))
+                    .first(conn)
+                    .context("Failed to load chat context")?;
+            Ok(group_scope(
+                &identifier,
+                &context_type,
+                reply_context.as_deref(),
+                self.marmot,
+            ))
+        })
+    }
+
+    fn insert(&self, poll: &NewPoll) -> Result<Poll> {
+        self.conn.run(|conn| {
+            diesel::insert_into(polls::table)
+                .values(poll)
+                .returning(Poll::as_returning())
+                .get_result(conn)
+                .context("Failed to insert poll")
+        })
+    }
+
+    fn set_close_task(&self, id: Uuid, task_id: Uuid) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::update(polls::table.filter(polls::id.eq(id)))
+                .set(polls::close_task_id.eq(task_id))
+                .execute(conn)
+                .context("Failed to link poll close task")?;
+            Ok(())
+        })
+    }
+
+    /// Open polls in a scope, newest first
+    fn open_polls(&self, scope: &str) -> Result<Vec<Poll>> {
+        self.conn.run(|conn| {
+            polls::table
+                .filter(polls::scope.eq(scope))
+                .filter(polls::status.eq("open"))
+                .select(Poll::as_select())
+                .order(polls::created_at.desc())
+                .load(conn)
+                .context("Failed to query open polls")
+        })
+    }
+
+    /// A poll by full id (any scope, for the scheduled close) or by id
+    /// prefix among the scope's open polls
+    fn find(&self, scope: Option<&str>, id: &str) -> Result<Option<Poll>> {
+        let id = id.trim().trim_matches(['[', ']']).to_lowercase();
+        if let Ok(uuid) = Uuid::parse_str(&id) {
+            return self.conn.run(|conn| {
+                polls::table
+                    .filter(polls::id.eq(uuid))
+                    .select(Poll::as_select())
+                    .first(conn)
+                    .optional()
+                    .context("Failed to load poll")
+            });
+        }
+        let Some(scope) = scope else {
+            return Ok(None);
+        };
+        Ok(self
+            .open_polls(scope)?
+            .into_iter()
+            .find(|poll| !id.is_empty() && poll.id.to_string().starts_with(&id)))
+    }
+
+    /// Record `voter`'s vote, replacing an earlier one
+    fn vote(
+        &self,
+        poll_id: Uuid,
+        voter: &str,
+        voter_name: Option<&str>,
+        index: usize,
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            let vote = NewVote {
+                poll_id,
+                voter,
+                voter_name,
+                option_index: index as i32,
+            };
+            diesel::insert_into(poll_votes::table)
+                .values(&vote)
+                .on_conflict((poll_votes::poll_id, poll_votes::voter))
+                .do_update()
+                .set((
+                    poll_votes::option_index.eq(vote.option_index),
+                    poll_votes::voter_name.eq(voter_name),
+                ))
+                .execute(conn)
+                .context("Failed to record vote")?;
+            Ok(())
+        })
+    }
+
+    /// Withdraw `voter`'s vote if it is still for `index`
+    fn unvote(&self, poll_id: Uuid, voter: &str, index: usize) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::delete(
+                poll_votes::table
+                    .filter(poll_votes::poll_id.eq(poll_id))
+                    .filter(poll_votes::voter.eq(voter))
+                    .filter(poll_votes::option_index.eq(index as i32)),
+            )
+            .execute(conn)
+            .context("Failed to withdraw vote")?;
+            Ok(())
+        })
+    }
+
+    /// Count `msg` as a vote if it is one (see the module docs). Returns None
+    /// for anything else, which then goes to the agent as usual.
+    pub fn try_vote(&self, msg: &IncomingMessage) -> Result<Option<CountedVote>> {
+        let context_type = if msg.group_id().is_some() {
+            "group"
+        } else {
+            "direct"
+        };
+        let Some(scope) = group_scope(
+            &msg.reply_to,
+            context_type,
+            msg.reply_context.as_deref(),
+            self.marmot,
+        ) else {
+            return Ok(None);
+        };
+
+        if let Some(reaction) = &msg.reaction {
+            let Some(index) = keycap_index(&reaction.emoji) else {
+                return Ok(None);
+            };
+            let Some(poll) = self.open_polls(&scope)?.into_iter().next() else {
+                return Ok(None);
+            };
+            let Some(option) = poll.options.get(index).cloned() else {
+                return Ok(None);
+            };
+            if reaction.removed {
+                self.unvote(poll.id, &msg.source, index)?;
+            } else {
+                self.vote(poll.id, &msg.source, msg.source_name.as_deref(), index)?;
+            }
+            info!(
+                "Counted reaction vote from {} in poll {}",
+                msg.source, poll.id
+            );
+            return Ok(Some(CountedVote {
+                option,
+                via_reaction: true,
+            }));
+        }
+
+        let text = without_sender(msg);
+        let quoted = msg
+            .quote
+            .as_ref()
+            .filter(|q| q.from_self)
+            .and_then(|q| q.text.as_deref())
+            .and_then(quoted_poll_id);
+        let (poll, choice) = match (quoted, strip_vote_command(text)) {
+            (Some(id), command) => (self.find(Some(&scope), id)?, command.unwrap_or(text)),
+            (None, Some(choice)) => (self.open_polls(&scope)?.into_iter().next(), choice),
+            (None, None) => return Ok(None),
+        };
+        let Some(poll) = poll.filter(|p| p.status == "open") else {
+            return Ok(None);
+        };
+        let Some(index) = parse_choice(choice, &poll.options) else {
+            return Ok(None);
+        };
+        self.vote(poll.id, &msg.source, msg.source_name.as_deref(), index)?;
+        info!("Counted vote from {} in poll {}", msg.source, poll.id);
+        Ok(Some(CountedVote {
+            option: poll.options[index].clone(),
+            via_reaction: false,
+        }))
+    }
+
+    /// Close a poll and tally its votes
+    fn close(&self, poll: &Poll) -> Result<Tally> {
+        self.conn.run(|conn| {
+            diesel::update(polls::table.filter(polls::id.eq(poll.id)))
+                .set(polls::status.eq("closed"))
+                .execute(conn)
+                .context("Failed to close poll")?;
+            let votes: Vec<(i32, String, Option<String>)> = poll_votes::table
+                .filter(poll_votes::poll_id.eq(poll.id))
+                .select((
+                    poll_votes::option_index,
+                    poll_votes::voter,
+                    poll_votes::voter_name,
+                ))
+                .order(poll_votes::created_at.asc())
+                .load(conn)
+                .context("Failed to load votes")?;
+            let votes: Vec<(i32, String)> = votes
+                .into_iter()
+                .map(|(index, voter, name)| (index, name.unwrap_or(voter)))
+                .collect();
+            Ok(tally(&poll.options, &votes))
+        })
+    }
+}
+
+// ============================================================================
+// Tools
+// ============================================================================
+
+pub struct CreatePollTool {
+    poll_db: Arc<PollDb>,
+    scheduler_db: Arc<SchedulerDb>,
+    agent_id: Uuid,
+    default_timezone: String,
+}
+
+impl CreatePollTool {
+    pub fn new(
+        poll_db: Arc<PollDb>,
+        scheduler_db: Arc<SchedulerDb>,
+        agent_id: Uuid,
+        default_timezone: String,
+    ) -> Self {
+        Self {
+            poll_db,
+            scheduler_db,
+            agent_id,
+            default_timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for CreatePollTool {
+    fn name(&self) -> &str {
+        "create_poll"
+    }
+
+    fn description(&self) -> &str {
+        "Start a poll in this group chat. Votes are counted automatically and the result is announced when the poll closes. Post the poll text the tool returns exactly as given, as its own message. Only works in group chats."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"question": "what to vote on", "options": "2-9 options separated by |, e.g. 'Pizza | Sushi | Tacos'", "closes": "optional: 'YYYY-MM-DD HH:MM' in the user's timezone or ISO 8601 (default: in 24 hours)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let question = args.get("question").map(|s| s.trim()).unwrap_or("");
+        if question.is_empty() {
+            return Ok(ToolResult::error("Missing required argument: question"));
+        }
+        let options: Vec<String> = args
+            .get("options")
+            .map(|s| {
+                s.split('|')
+                    .map(|o| o.trim().to_string())
+                    .filter(|o| !o.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if options.len() < 2 || options.len() > MAX_OPTIONS {
+            return Ok(ToolResult::error(format!(
+                "A poll needs 2 to {} options separated by |",
+                MAX_OPTIONS
+            )));
+        }
+        let closes_at = match args
+            .get("closes")
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+        {
+            Some(closes) => match todos::parse_due(closes, &self.default_timezone) {
+                Ok(at) if at > Utc::now() => at,
+                Ok(_) => return Ok(ToolResult::error("The closing time is in the past")),
+                Err(e) => return Ok(ToolResult::error(e.to_string())),
+            },
+            None => Utc::now() + Duration::hours(DEFAULT_DURATION_HOURS),
+        };
+        let Some(scope) = self.poll_db.scope_for(self.agent_id)? else {
+            return Ok(ToolResult::error("Polls only work in group chats"));
+        };
+
+        let poll = self.poll_db.insert(&NewPoll {
+            id: Uuid::new_v4(),
+            scope,
+            agent_id: self.agent_id,
+            question: question.to_string(),
+            options,
+            closes_at,
+        })?;
+        let task = self.scheduler_db.create_task(
+            self.agent_id,
+            TaskType::ToolCall,
+            TaskPayload::ToolCall(ToolCallPayload {
+                tool: "close_poll".to_string(),
+                args: HashMap::from([("id".to_string(), poll.id.to_string())]),
+            }),
+            closes_at,
+            None,
+            self.default_timezone.clone(),
+            "📊 Poll closed".to_string(),
+        )?;
+        self.poll_db.set_close_task(poll.id, task.id)?;
+        info!("Created poll {} in {}", poll.id, poll.scope);
+
+        Ok(ToolResult::success(format!(
+            "Poll created. Post this exactly, as its own message:\n\n{}",
+            poll_text(&poll, &self.default_timezone, self.poll_db.marmot)
+        )))
+    }
+}
+
+pub struct ClosePollTool {
+    poll_db: Arc<PollDb>,
+    scheduler_db: Arc<SchedulerDb>,
+    agent_id: Uuid,
+}
+
+impl ClosePollTool {
+    pub fn new(poll_db: Arc<PollDb>, scheduler_db: Arc<SchedulerDb>, agent_id: Uuid) -> Self {
+        Self {
+            poll_db,
+            scheduler_db,
+            agent_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ClosePollTool {
+    fn name(&self) -> &str {
+        "close_poll"
+    }
+
+    fn description(&self) -> &str {
+        "Close a poll now and get its result (polls also close on their own at their deadline, announcing the result)."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"id": "poll id (the 8 characters in 'Poll [...]')"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let Some(id) = args.get("id").filter(|s| !s.trim().is_empty()) else {
+            return Ok(ToolResult::error("Missing required argument: id"));
+        };
+        let scope = self.poll_db.scope_for(self.agent_id)?;
+        let Some(poll) = self.poll_db.find(scope.as_deref(), id)? else {
+            return Ok(ToolResult::error(format!(
+                "No open poll with id '{}'",
+                id.trim()
+            )));
+        };
+        if poll.status != "open" {
+            return Ok(ToolResult::error("That poll is already closed"));
+        }
+        // Closed early: drop the scheduled close (a no-op when this is it)
+        if let Some(task_id) = poll.close_task_id {
+            self.scheduler_db.cancel_task(task_id)?;
+        }
+        let tally = self.poll_db.close(&poll)?;
+        Ok(ToolResult::success(format_result(&poll.question, &tally)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Vec<String> {
+        vec![
+            "Pizza".to_string(),
+            "Sushi".to_string(),
+            "Tacos".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("2", &options()), Some(1));
+        assert_eq!(parse_choice(" #3. ", &options()), Some(2));
+        assert_eq!(parse_choice("1️⃣", &options()), Some(0));
+        assert_eq!(parse_choice("sushi", &options()), Some(1));
+        assert_eq!(parse_choice("4", &options()), None);
+        assert_eq!(parse_choice("0", &options()), None);
+        assert_eq!(parse_choice("burgers", &options()), None);
+        assert_eq!(keycap_index("3\u{20E3}"), Some(2));
+        assert_eq!(keycap_index("👍"), None);
+    }
+
+    #[test]
+    fn test_vote_text() {
+        assert_eq!(strip_vote_command("vote 2"), Some("2"));
+        assert_eq!(strip_vote_command("Vote  Sushi "), Some("Sushi"));
+        assert_eq!(strip_vote_command("voter turnout"), None);
+        assert_eq!(strip_vote_command("vote"), None);
+        assert_eq!(
+            quoted_poll_id("📊 Poll [1a2b3c4d]: Dinner?\n1️⃣ Pizza"),
+            Some("1a2b3c4d")
+        );
+        assert_eq!(quoted_poll_id("Poll [xyz]: nope"), None);
+        assert_eq!(quoted_poll_id("no poll here"), None);
+    }
+
+    #[test]
+    fn test_group_scope() {
+        assert_eq!(
+            group_scope("group:abc", "group", None, false),
+            Some("group:abc".to_string())
+        );
+        assert_eq!(
+            group_scope("npub1alice", "direct", Some("g1"), true),
+            Some("marmot:g1".to_string())
+        );
+        assert_eq!(group_scope("uuid-1", "direct", None, false), None);
+    }
+
+    #[test]
+    fn test_poll_text_and_result() {
+        let poll = Poll {
+            id: Uuid::parse_str("1a2b3c4d-0000-0000-0000-000000000000").unwrap(),
+            scope: "group:abc".to_string(),
+            agent_id: Uuid::new_v4(),
+            question: "Dinner Friday?".to_string(),
+            options: options(),
+            closes_at: todos::parse_due("2026-10-23 19:00", "UTC").unwrap(),
+            close_task_id: None,
+            status: "open".to_string(),
+            created_at: Utc::now(),
+        };
+        let text = poll_text(&poll, "UTC", false);
+        assert!(text.starts_with("📊 Poll [1a2b3c4d]: Dinner Friday?\n1️⃣ Pizza\n2️⃣ Sushi"));
+        assert!(text.ends_with("Closes Fri Oct 23, 19:00 UTC."));
+        assert_eq!(quoted_poll_id(&text), Some("1a2b3c4d"));
+
+        let votes = vec![
+            (1, "Alice".to_string()),
+            (0, "Bob".to_string()),
+            (1, "Carol".to_string()),
+        ];
+        assert_eq!(
+            format_result("Dinner Friday?", &tally(&options(), &votes)),
+            "Dinner Friday?\nSushi: 2 votes (Alice, Carol)\nPizza: 1 vote (Bob)\nTacos: 0 votes\nWinner: Sushi"
+        );
+        let tie = vec![(0, "Alice".to_string()), (2, "Bob".to_string())];
+        assert!(format_result("Q", &tally(&options(), &tie)).ends_with("Tie: Pizza, Tacos"));
+        assert_eq!(
+            format_result("Q", &tally(&options(), &[])),
+            "Q\nNo votes were cast."
+        );
+    }
+}
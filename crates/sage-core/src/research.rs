@@ -0,0 +1,542 @@
+//! Web Research
+//!
+//! `web_fetch` reads a web page as plain text. `deep_research` runs a bounded
+//! research loop inside a single tool call: plan a few search queries, search
+//! each one, read the top pages, and synthesize an answer that cites its
+//! sources. The answer is stored as an archival passage (tagged `research`)
+//! so it can be recalled later without redoing the work. Both LLM steps are
+//! DSRs signatures, so GEPA can optimize them like the rest of Sage.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use dspy_rs::{Predict, Signature};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::memory::ArchivalManager;
+use crate::sage_agent::{Tool, ToolResult};
+
+/// Search queries planned per research run
+const MAX_QUERIES: usize = 4;
+
+/// Search results considered per query
+const RESULTS_PER_QUERY: u32 = 5;
+
+/// Pages read per research run
+const MAX_PAGES: usize = 6;
+
+/// Characters of each page given to the synthesizer
+const SOURCE_CHARS: usize = 6_000;
+
+/// Default characters returned by web_fetch
+const DEFAULT_FETCH_CHARS: usize = 8_000;
+
+/// Largest response body read from a page
+const MAX_FETCH_BYTES: usize = 2 * 1024 * 1024;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Instruction for the query planning DSRs signature
+pub const PLAN_INSTRUCTION: &str = r#"You are planning web research. Given a question, write 2-4 distinct web search queries that together cover it: the core fact, supporting or opposing evidence, and recent developments if the topic is time-sensitive.
+
+Queries should be short and specific, like a person would type into a search engine. Do not repeat the same query with minor wording changes."#;
+
+/// Instruction for the synthesis DSRs signature
+pub const SYNTHESIZE_INSTRUCTION: &str = r#"You are a research assistant. Answer the question using ONLY the numbered sources provided.
+
+Cite every claim with the source number in brackets, e.g. [1] or [2][3]. If sources disagree, say so and cite both. If the sources do not answer the question, say what is missing instead of guessing.
+
+Be concise: a few short paragraphs or a bullet list. Do not include a sources list - it is appended automatically."#;
+
+/// DSRs signature for planning research queries
+#[derive(Signature, Clone, Debug)]
+pub struct PlanResearch {
+    #[input(desc = "The question to research")]
+    pub question: String,
+
+    #[input(desc = "Today's date (YYYY-MM-DD)")]
+    pub current_date: String,
+
+    #[output(desc = "2-4 distinct web search queries")]
+    pub queries: Vec<String>,
+}
+
+/// DSRs signature for synthesizing a cited answer
+#[derive(Signature, Clone, Debug)]
+pub struct SynthesizeResearch {
+    #[input(desc = "The question to answer")]
+    pub question: String,
+
+    #[input(desc = "Numbered sources: [n] title (url) followed by the page text")]
+    pub sources: String,
+
+    #[output(desc = "Answer citing sources as [n]")]
+    pub answer: String,
+}
+
+/// A fetched page as plain text
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub url: String,
+    pub title: String,
+    pub text: String,
+}
+
+static DROP_BLOCKS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        "script", "style", "noscript", "svg", "head", "nav", "footer",
+    ]
+    .iter()
+    .map(|tag| Regex::new(&format!(r"(?is)<{tag}\b.*?</{tag}\s*>")).unwrap())
+    .collect()
+});
+static COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+static BLOCK_BREAK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)<br\s*/?>|</(p|div|h[1-6]|li|tr|section|article|blockquote|pre)\s*>").unwrap()
+});
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title\s*>").unwrap());
+
+/// Decode the handful of HTML entities that matter for readability
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Readable text of an HTML page: scripts, styles and navigation dropped,
+/// tags stripped, blank runs collapsed
+pub fn html_to_text(html: &str) -> String {
+    let mut text = COMMENT.replace_all(html, "").into_owned();
+    for block in DROP_BLOCKS.iter() {
+        text = block.replace_all(&text, "").into_owned();
+    }
+    let text = BLOCK_BREAK.replace_all(&text, "\n");
+    let text = decode_entities(&TAG.replace_all(&text, " "));
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Contents of the page's `<title>`, if any
+pub fn html_title(html: &str) -> Option<String> {
+    TITLE
+        .captures(html)
+        .map(|c| {
+            decode_entities(
+                c[1].split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .as_str(),
+            )
+        })
+        .filter(|t| !t.is_empty())
+}
+
+/// First `max` characters of `text`, marking the cut
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}\n[... truncated]", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+/// Build the HTTP client used for page fetches
+pub fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent("Mozilla/5.0 (compatible; Sage/0.1.0)")
+        .build()
+        .context("Failed to build HTTP client")
+}
+
+/// Fetch a web page and return it as plain text
+pub async fn fetch_page(client: &reqwest::Client, url: &str) -> Result<Page> {
+    let parsed = reqwest::Url::parse(url).context("Invalid URL")?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        anyhow::bail!("Only http and https URLs can be fetched");
+    }
+
+    let mut response = client.get(parsed).send().await.context("Request failed")?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("HTTP {}", status);
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("text/html")
+        .to_lowercase();
+    let is_html = content_type.contains("html");
+    if !is_html && !content_type.starts_with("text/") && !content_type.contains("json") {
+        anyhow::bail!("Unsupported content type: {}", content_type);
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.context("Failed to read body")? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_FETCH_BYTES {
+            body.truncate(MAX_FETCH_BYTES);
+            break;
+        }
+    }
+    let raw = String::from_utf8_lossy(&body);
+
+    let (title, text) = if is_html {
+        (html_title(&raw), html_to_text(&raw))
+    } else {
+        (None, raw.trim().to_string())
+    };
+    Ok(Page {
+        url: url.to_string(),
+        title: title.unwrap_or_else(|| url.to_string()),
+        text,
+    })
+}
+
+/// Clean up planned queries: trimmed, deduplicated, capped at `MAX_QUERIES`,
+/// falling back to the question itself
+pub fn normalize_queries(question: &str, queries: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let queries: Vec<String> = queries
+        .into_iter()
+        .map(|q| q.trim().trim_matches('"').trim().to_string())
+        .filter(|q| !q.is_empty() && seen.insert(q.to_lowercase()))
+        .take(MAX_QUERIES)
+        .collect();
+    if queries.is_empty() {
+        vec![question.trim().to_string()]
+    } else {
+        queries
+    }
+}
+
+/// Pick up to `MAX_PAGES` distinct URLs, taking each query's results in turn
+/// so every query contributes its best hits
+pub fn pick_urls(results: Vec<Vec<(String, String)>>) -> Vec<(String, String)> {
+    let mut seen = HashSet::new();
+    let mut picked = Vec::new();
+    let depth = results.iter().map(Vec::len).max().unwrap_or(0);
+    for rank in 0..depth {
+        for hits in &results {
+            if picked.len() >= MAX_PAGES {
+                return picked;
+            }
+            if let Some((title, url)) = hits.get(rank) {
+                if seen.insert(url.trim_end_matches('/').to_string()) {
+                    picked.push((title.clone(), url.clone()));
+                }
+            }
+        }
+    }
+    picked
+}
+
+/// Numbered sources for the synthesizer
+fn format_sources(pages: &[Page]) -> String {
+    pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| {
+            format!(
+                "[{}] {} ({})\n{}",
+                i + 1,
+                page.title,
+                page.url,
+                truncate_chars(&page.text, SOURCE_CHARS)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Sources list appended to the answer
+pub fn sources_list(pages: &[Page]) -> String {
+    let mut out = "Sources:".to_string();
+    for (i, page) in pages.iter().enumerate() {
+        out.push_str(&format!("\n[{}] {} - {}", i + 1, page.title, page.url));
+    }
+    out
+}
+
+// ============================================================================
+// Tools
+// ============================================================================
+
+/// Web fetch tool - reads a page as plain text
+pub struct WebFetchTool {
+    client: reqwest::Client,
+}
+
+impl WebFetchTool {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Tool for WebFetchTool {
+    fn name(&self) -> &str {
+        "web_fetch"
+    }
+
+    fn description(&self) -> &str {
+        "Read a web page as plain text (e.g. a result from web_search). Long pages are truncated."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"url": "http(s) URL to read", "max_chars": "characters to return (default 8000)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let url = args
+            .get("url")
+            .ok_or_else(|| anyhow::anyhow!("url argument required"))?;
+        let max_chars = args
+            .get("max_chars")
+            .and_then(|m| m.parse().ok())
+            .unwrap_or(DEFAULT_FETCH_CHARS);
+
+        match fetch_page(&self.client, url.trim()).await {
+            Ok(page) if page.text.is_empty() => Ok(ToolResult::error(format!(
+                "No readable text at {}",
+                page.url
+            ))),
+            Ok(page) => Ok(ToolResult::success(format!(
+                "{} ({})\n\n{}",
+                page.title,
+                page.url,
+                truncate_chars(&page.text, max_chars)
+            ))),
+            Err(e) => Ok(ToolResult::error(format!("Fetch failed: {:#}", e))),
+        }
+    }
+}
+
+/// Deep research tool - plans queries, reads pages and writes a cited answer
+pub struct DeepResearchTool {
+    brave: Arc<sage_tools::BraveClient>,
+    client: reqwest::Client,
+    archival: ArchivalManager,
+}
+
+impl DeepResearchTool {
+    pub fn new(api_key: &str, archival: ArchivalManager) -> Result<Self> {
+        Ok(Self {
+            brave: Arc::new(sage_tools::BraveClient::new(api_key.to_string())?),
+            client: http_client()?,
+            archival,
+        })
+    }
+
+    /// Ask the LLM for search queries, falling back to the question itself
+    async fn plan(&self, question: &str) -> Vec<String> {
+        let predictor = Predict::<PlanResearch>::builder()
+            .instruction(PLAN_INSTRUCTION)
+            .build();
+        let input = PlanResearchInput {
+            question: question.to_string(),
+            current_date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+        };
+        let queries = match predictor.call(input).await {
+            Ok(response) => response.queries,
+            Err(e) => {
+                warn!("Research planning failed, searching the question: {}", e);
+                Vec::new()
+            }
+        };
+        normalize_queries(question, queries)
+    }
+
+    /// Top (title, url) hits for each query
+    async fn search(&self, queries: &[String]) -> Vec<Vec<(String, String)>> {
+        let mut results = Vec::new();
+        for query in queries {
+            let options = sage_tools::SearchOptions {
+                count: Some(RESULTS_PER_QUERY),
+                ..Default::default()
+            };
+            match self.brave.search(query, Some(options)).await {
+                Ok(response) => results.push(
+                    response
+                        .web
+                        .and_then(|web| web.results)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|r| (r.title, r.url))
+                        .collect(),
+                ),
+                Err(e) => warn!("Research search '{}' failed: {}", query, e),
+            }
+        }
+        results
+    }
+
+    /// Fetch pages concurrently, keeping search order and dropping failures
+    async fn read(&self, urls: Vec<(String, String)>) -> Vec<Page> {
+        let mut set = JoinSet::new();
+        for (i, (title, url)) in urls.into_iter().enumerate() {
+            let client = self.client.clone();
+            set.spawn(async move {
+                match fetch_page(&client, &url).await {
+                    Ok(mut page) if !page.text.is_empty() => {
+                        if page.title == page.url && !title.is_empty() {
+                            page.title = title;
+                        }
+                        Some((i, page))
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        warn!("Research fetch {} failed: {:#}", url, e);
+                        None
+                    }
+                }
+            });
+        }
+
+        let mut pages = Vec::new();
+        while let Some(joined) = set.join_next().await {
+            if let Ok(Some(page)) = joined {
+                pages.push(page);
+            }
+        }
+        pages.sort_by_key(|(i, _)| *i);
+        pages.into_iter().map(|(_, page)| page).collect()
+    }
+}
+
+#[async_trait]
+impl Tool for DeepResearchTool {
+    fn name(&self) -> &str {
+        "deep_research"
+    }
+
+    fn description(&self) -> &str {
+        "Research a question thoroughly: runs several web searches, reads the top pages and returns a cited answer (also saved to archival memory, tag 'research'). Slower than web_search - use for questions that need more than one search."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"question": "the question to research"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let question = args
+            .get("question")
+            .map(|q| q.trim())
+            .filter(|q| !q.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("question argument required"))?;
+
+        let queries = self.plan(question).await;
+        info!("Researching '{}' with {} queries", question, queries.len());
+
+        let urls = pick_urls(self.search(&queries).await);
+        if urls.is_empty() {
+            return Ok(ToolResult::error("No search results found.".to_string()));
+        }
+        let pages = self.read(urls).await;
+        if pages.is_empty() {
+            return Ok(ToolResult::error(
+                "Found results but couldn't read any of the pages.".to_string(),
+            ));
+        }
+
+        let predictor = Predict::<SynthesizeResearch>::builder()
+            .instruction(SYNTHESIZE_INSTRUCTION)
+            .build();
+        let input = SynthesizeResearchInput {
+            question: question.to_string(),
+            sources: format_sources(&pages),
+        };
+        let answer = match predictor.call(input).await {
+            Ok(response) => response.answer,
+            Err(e) => {
+                return Ok(ToolResult::error(format!(
+                    "Research synthesis failed: {}\n\n{}",
+                    e,
+                    sources_list(&pages)
+                )))
+            }
+        };
+        let report = format!("{}\n\n{}", answer.trim(), sources_list(&pages));
+
+        let passage = format!("Research: {}\n\n{}", question, report);
+        if let Err(e) = self
+            .archival
+            .insert(&passage, Some(vec!["research".to_string()]))
+            .await
+        {
+            warn!("Failed to store research in archival memory: {}", e);
+        }
+
+        Ok(ToolResult::success(format!(
+            "Searched: {}\n\n{}",
+            queries.join("; "),
+            report
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<html><head><title>Rust &amp; You</title><style>p{}</style></head>
+<body><nav>Home | About</nav><script>var x = "<p>";</script>
+<h1>Hello</h1><p>Rust is <b>fast</b>&nbsp;and   safe.</p><!-- hidden --><ul><li>One</li><li>Two</li></ul>
+</body></html>"#;
+        assert_eq!(html_title(html).as_deref(), Some("Rust & You"));
+        assert_eq!(
+            html_to_text(html),
+            "Hello\nRust is fast and safe.\nOne\nTwo"
+        );
+    }
+
+    #[test]
+    fn test_normalize_queries() {
+        let queries = vec![
+            " rust async ".to_string(),
+            "\"Rust async\"".to_string(),
+            String::new(),
+            "tokio".to_string(),
+            "a".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+        ];
+        assert_eq!(
+            normalize_queries("q", queries),
+            vec!["rust async", "tokio", "a", "b"]
+        );
+        assert_eq!(normalize_queries(" why? ", Vec::new()), vec!["why?"]);
+    }
+
+    #[test]
+    fn test_pick_urls() {
+        let hit = |u: &str| (u.to_string(), format!("https://{}/", u));
+        let results = vec![
+            vec![hit("a"), hit("b"), hit("c"), hit("d")],
+            vec![hit("a"), hit("e")],
+            vec![hit("f"), hit("g")],
+        ];
+        let urls: Vec<String> = pick_urls(results).into_iter().map(|(t, _)| t).collect();
+        assert_eq!(urls, vec!["a", "f", "b", "e", "g", "c"]);
+    }
+}
@@ -0,0 +1,289 @@
+//! User Roles
+//!
+//! Each conversation has a role - owner, trusted or guest - that decides
+//! which tools its agent gets, how much it may spend on model calls per day
+//! and whether it gets proactive messages (the weekly maintenance summary).
+//! Roles are assigned in `USER_ROLES` (`<id>:<role>,...`, user or group
+//! ids); anyone else has `DEFAULT_ROLE` (trusted). `OWNER_USERS` are owners.
+//! Listing someone in `USER_ROLES` also lets them talk to Sage, so adding a
+//! guest is one entry instead of an allowlist change plus per-tool settings.
+//!
+//! Owners and trusted users keep every tool with no budget. Guests lose the
+//! shell, file and export tools and deep research, get a small daily budget
+//! and no proactive messages, and their direct chats are guest contexts
+//! with ephemeral memory (see `agent_manager::ContextType::Guest`). Each
+//! default can be changed per role with `ROLE_<ROLE>_DENY_TOOLS`,
+//! `ROLE_<ROLE>_DAILY_BUDGET_USD`, `ROLE_<ROLE>_PROACTIVE` and
+//! `ROLE_<ROLE>_EPHEMERAL`.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::threads;
+
+/// Tools guests don't get by default: they act on the host or leave the chat
+pub const GUEST_DENIED_TOOLS: &[&str] = &[
+    "shell",
+    "shell_session_start",
+    "shell_job_status",
+    "shell_job_kill",
+    "workspace_rollback",
+    "send_file",
+    "export_conversation",
+    "deep_research",
+];
+
+/// Guests' default model spend per conversation per day (USD)
+pub const GUEST_DAILY_BUDGET_USD: f64 = 0.50;
+
+/// Reply when a conversation has used up its daily budget
+pub const BUDGET_REPLY: &str =
+    "I've reached my usage limit for this conversation today. Let's pick this up again tomorrow.";
+
+/// What a conversation's user may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Owner,
+    Trusted,
+    Guest,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Trusted, Role::Guest];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "owner" => Some(Role::Owner),
+            "trusted" => Some(Role::Trusted),
+            "guest" => Some(Role::Guest),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Trusted => "trusted",
+            Role::Guest => "guest",
+        }
+    }
+}
+
+/// Tool permissions, budget and proactivity of a role
+#[derive(Debug, Clone, PartialEq)]
+pub struct RolePolicy {
+    /// Tools the role's agents don't get
+    pub denied_tools: Vec<String>,
+    /// Model spend allowed per conversation per UTC day (None = unlimited)
+    pub daily_budget_usd: Option<f64>,
+    /// Whether the role gets proactive messages
+    pub proactive: bool,
+    /// Whether the role's direct chats are guest contexts: a short rolling
+    /// window, no long-term memory, purged after `GUEST_RETENTION_HOURS`
+    pub ephemeral: bool,
+}
+
+impl RolePolicy {
+    /// Built-in policy of a role
+    pub fn default_for(role: Role) -> Self {
+        match role {
+            Role::Owner | Role::Trusted => Self {
+                denied_tools: Vec::new(),
+                daily_budget_usd: None,
+                proactive: true,
+                ephemeral: false,
+            },
+            Role::Guest => Self {
+                denied_tools: GUEST_DENIED_TOOLS.iter().map(|t| t.to_string()).collect(),
+                daily_budget_usd: Some(GUEST_DAILY_BUDGET_USD),
+                proactive: false,
+                ephemeral: true,
+            },
+        }
+    }
+
+    pub fn allows_tool(&self, name: &str) -> bool {
+        !self.denied_tools.iter().any(|t| t == name)
+    }
+
+    /// Whether `spent` USD today leaves any budget
+    pub fn within_budget(&self, spent: f64) -> bool {
+        self.daily_budget_usd.is_none_or(|budget| spent < budget)
+    }
+}
+
+/// Parse `USER_ROLES`: comma-separated `<id>:<role>` entries. The role is
+/// after the last colon, so ids may contain colons.
+pub fn parse_assignments(spec: &str) -> Result<HashMap<String, Role>> {
+    let mut roles = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (id, role) = entry
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("expected id:role, got '{}'", entry))?;
+        let role = Role::parse(role)
+            .ok_or_else(|| anyhow::anyhow!("unknown role '{}' for '{}'", role.trim(), id))?;
+        roles.insert(id.trim().to_string(), role);
+    }
+    Ok(roles)
+}
+
+/// Parse a `ROLE_<ROLE>_DENY_TOOLS` list ("none" = no tools denied)
+pub fn parse_tool_list(spec: &str) -> Vec<String> {
+    if spec.trim().eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    spec.split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Role assignments and policies
+#[derive(Debug, Clone)]
+pub struct Roles {
+    assignments: HashMap<String, Role>,
+    owners: Vec<String>,
+    default: Role,
+    policies: HashMap<Role, RolePolicy>,
+}
+
+impl Roles {
+    pub fn new(assignments: HashMap<String, Role>, owners: Vec<String>, default: Role) -> Self {
+        Self {
+            assignments,
+            owners,
+            default,
+            policies: Role::ALL
+                .iter()
+                .map(|role| (*role, RolePolicy::default_for(*role)))
+                .collect(),
+        }
+    }
+
+    /// Replace a role's built-in policy
+    pub fn with_policy(mut self, role: Role, policy: RolePolicy) -> Self {
+        self.policies.insert(role, policy);
+        self
+    }
+
+    /// Role of a user, group or conversation thread identifier
+    pub fn role(&self, identifier: &str) -> Role {
+        let (chat, _) = threads::split_thread(identifier);
+        if self.owners.iter().any(|owner| owner == chat) {
+            return Role::Owner;
+        }
+        self.assignments.get(chat).copied().unwrap_or(self.default)
+    }
+
+    pub fn policy(&self, role: Role) -> RolePolicy {
+        self.policies
+            .get(&role)
+            .cloned()
+            .unwrap_or_else(|| RolePolicy::default_for(role))
+    }
+
+    /// Policy of the role of `identifier`
+    pub fn policy_for(&self, identifier: &str) -> RolePolicy {
+        self.policy(self.role(identifier))
+    }
+
+    /// Whether `identifier` is listed as an owner. `DEFAULT_ROLE` never
+    /// makes anyone an owner here: like `OWNER_USERS`, owners are listed by id.
+    pub fn is_owner(&self, identifier: &str) -> bool {
+        let (chat, _) = threads::split_thread(identifier);
+        self.owners.iter().any(|owner| owner == chat)
+            || self.assignments.get(chat) == Some(&Role::Owner)
+    }
+
+    /// Whether `identifier` has a role in `USER_ROLES` (and so is allowed in)
+    pub fn is_listed(&self, identifier: &str) -> bool {
+        self.assignments.contains_key(identifier)
+    }
+
+    /// Owners, from `OWNER_USERS` and `USER_ROLES`
+    pub fn owners(&self) -> Vec<String> {
+        let mut owners = self.owners.clone();
+        for (id, role) in &self.assignments {
+            if *role == Role::Owner && !owners.contains(id) {
+                owners.push(id.clone());
+            }
+        }
+        owners
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_assignments() {
+        let roles = parse_assignments(" alice:guest, group.abc=:Trusted,npub1:x:owner ,").unwrap();
+        assert_eq!(roles.len(), 3);
+        assert_eq!(roles["alice"], Role::Guest);
+        assert_eq!(roles["group.abc="], Role::Trusted);
+        assert_eq!(roles["npub1:x"], Role::Owner);
+        assert!(parse_assignments("").unwrap().is_empty());
+        assert!(parse_assignments("alice").is_err());
+        assert!(parse_assignments("alice:admin").is_err());
+    }
+
+    #[test]
+    fn test_parse_tool_list() {
+        assert_eq!(
+            parse_tool_list("shell, send_file,"),
+            vec!["shell", "send_file"]
+        );
+        assert!(parse_tool_list("none").is_empty());
+        assert!(parse_tool_list("").is_empty());
+    }
+
+    #[test]
+    fn test_role_lookup() {
+        let roles = Roles::new(
+            parse_assignments("alice:guest,bob:owner").unwrap(),
+            vec!["carol".to_string()],
+            Role::Trusted,
+        );
+        assert_eq!(roles.role("alice"), Role::Guest);
+        assert_eq!(roles.role("alice#topic:travel"), Role::Guest);
+        assert_eq!(roles.role("carol"), Role::Owner);
+        assert_eq!(roles.role("dave"), Role::Trusted);
+        assert!(roles.is_owner("bob"));
+        assert!(!Roles::new(HashMap::new(), Vec::new(), Role::Owner).is_owner("dave"));
+        assert!(roles.is_listed("alice"));
+        assert!(!roles.is_listed("dave"));
+        let mut owners = roles.owners();
+        owners.sort();
+        assert_eq!(owners, vec!["bob", "carol"]);
+    }
+
+    #[test]
+    fn test_default_policies() {
+        let roles = Roles::new(HashMap::new(), Vec::new(), Role::Guest);
+        let guest = roles.policy_for("stranger");
+        assert!(!guest.allows_tool("shell"));
+        assert!(guest.allows_tool("archival_search"));
+        assert!(!guest.proactive);
+        assert!(guest.ephemeral);
+        assert!(guest.within_budget(0.10));
+        assert!(!guest.within_budget(GUEST_DAILY_BUDGET_USD));
+
+        let trusted = roles.policy(Role::Trusted);
+        assert!(trusted.allows_tool("shell"));
+        assert!(trusted.within_budget(1000.0));
+        assert!(trusted.proactive);
+        assert!(!trusted.ephemeral);
+
+        let roles = roles.with_policy(
+            Role::Guest,
+            RolePolicy {
+                denied_tools: Vec::new(),
+                daily_budget_usd: None,
+                proactive: true,
+                ephemeral: false,
+            },
+        );
+        assert!(roles.policy(Role::Guest).allows_tool("shell"));
+    }
+}
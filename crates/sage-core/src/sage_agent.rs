@@ -7,11 +7,20 @@
 
 use anyhow::Result;
 use dspy_rs::{configure, BamlType, ChatAdapter, Predict, LM};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::sync::Arc;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 use uuid::Uuid;
 
-use crate::memory::MemoryManager;
+use crate::citations::{SourceLedger, SOURCE_TOOLS};
+use crate::guardrails::SecretScanner;
+use crate::memory::{MemoryManager, Section, TokenCounter};
+use crate::messenger::{AttachmentOutbox, OutgoingAttachment, ReactionOutbox};
+use crate::model_router::{ModelRouter, Tier};
+use crate::modes::{self, Mode};
+use crate::streaming::{self, ModelOverride};
+use crate::usage::{self, CallKind};
 
 /// A tool call requested by the agent
 #[derive(Clone, Debug, Default, BamlType)]
@@ -31,22 +40,30 @@ pub struct ToolCall {
 /// - Each field has a distinct purpose
 /// - GEPA can optimize field descriptions independently
 /// - No XML parsing needed - clean structured data
+///
+/// Inputs are rendered in declaration order, so they are ordered from most to
+/// least stable: the prompt up to the conversation stays byte-identical
+/// between the steps of a turn (and mostly between turns), which lets the
+/// provider serve it from its prompt cache. Only the conversation tail,
+/// metadata counts, clock and step input change from step to step.
 #[derive(dspy_rs::Signature, Clone, Debug)]
 pub struct AgentResponse {
-    #[input(desc = "The user message or tool result to respond to")]
-    pub input: String,
-
-    #[input(desc = "Current date and time in user's timezone")]
-    pub current_time: String,
-
     #[input(desc = "Your persona - who you are, your personality and style")]
     pub persona_block: String,
 
+    #[input(desc = "Available tools and their descriptions")]
+    pub available_tools: String,
+
     #[input(desc = "What you know about this human - name, preferences, facts")]
     pub human_block: String,
 
-    #[input(desc = "Memory stats: message count in recall, archival count, last modified")]
-    pub memory_metadata: String,
+    #[input(
+        desc = "Other core memory blocks you created (memory_create_block), with their limits. Ignore if empty."
+    )]
+    pub memory_blocks: String,
+
+    #[input(desc = "Is this the first conversation with this user?")]
+    pub is_first_time_user: bool,
 
     #[input(desc = "Summary of older conversation if context was compacted. Ignore if empty.")]
     pub previous_context_summary: String,
@@ -54,11 +71,16 @@ pub struct AgentResponse {
     #[input(desc = "Recent messages between you and the user")]
     pub recent_conversation: String,
 
-    #[input(desc = "Available tools and their descriptions")]
-    pub available_tools: String,
+    #[input(
+        desc = "Memory stats: message count in recall, archival count, last modified, age of each memory block"
+    )]
+    pub memory_metadata: String,
 
-    #[input(desc = "Is this the first conversation with this user?")]
-    pub is_first_time_user: bool,
+    #[input(desc = "Current date and time in user's timezone")]
+    pub current_time: String,
+
+    #[input(desc = "The user message or tool result to respond to")]
+    pub input: String,
 
     // NOTE: No reasoning output field - Kimi K2.5 is a thinking model that puts
     // its reasoning in reasoning_content. Having a separate reasoning field
@@ -78,6 +100,9 @@ pub struct AgentResponse {
 /// It should preserve the intent/content of the original response, not generate new content.
 #[derive(dspy_rs::Signature, Clone, Debug)]
 pub struct CorrectionResponse {
+    #[input(desc = "Available tools for reference")]
+    pub available_tools: String,
+
     #[input(desc = "The original input that was given to the agent")]
     pub original_input: String,
 
@@ -87,9 +112,6 @@ pub struct CorrectionResponse {
     #[input(desc = "The error message explaining what went wrong with parsing")]
     pub error_message: String,
 
-    #[input(desc = "Available tools for reference")]
-    pub available_tools: String,
-
     // NOTE: No reasoning output - Kimi K2.5 thinks in reasoning_content
     #[output(desc = "Array of messages extracted/fixed from the original response")]
     pub messages: Vec<String>,
@@ -138,13 +160,16 @@ You have two types of memory. Use them proactively:
 **Core Memory** (always visible to you):
 - The <persona> and <human> blocks are ALWAYS in your context
 - Use for essential, frequently-needed info: name, job, key preferences, current projects
-- Tools: `memory_append`, `memory_replace`, `memory_insert`
+- Tools: `memory_append`, `memory_replace`, `memory_insert`; `memory_undo` reverts a block edit that went wrong
+- A topic that needs its own standing place (an ongoing project, health) can get its own block with `memory_create_block`; non-empty blocks you create appear alongside <human>
+- Group chats: user messages show the sender as `[user id=...]`. Keep what you learn about one person in their own human block by passing `person` (their id) to the memory tools; it appears as `<human id="...">`. The shared human block is for the group as a whole
 - Rule: "Will I need this in EVERY conversation?" → Core Memory
 
 **Archival Memory** (searchable long-term storage):
 - NOT visible until you search - unlimited storage for details
 - Use for: life events, stories, specific preferences, things worth remembering later
-- Tools: `archival_insert` (store), `archival_search` (retrieve)
+- Tools: `archival_insert` (store), `archival_search` (retrieve), `archival_update`/`archival_delete` (fix or remove a wrong or outdated passage by id), `archival_list` (browse), `archival_namespace` (keep e.g. work and personal memories apart when the user asks)
+- "Forget X" requests: call `forget` with X's key words, remove X from your memory blocks with `memory_replace`, and confirm briefly
 - Rule: "Might I want to recall this detail someday?" → Archival Memory
 
 **Common Storage Patterns:**
@@ -156,6 +181,9 @@ You have two types of memory. Use them proactively:
 **Conversation History**:
 - `conversation_search`: Find past discussions by keyword/topic
 
+**Structured Facts** (learned automatically from the user's messages):
+- `fact_query`: exact values like ages, birthdays, names and places, as subject / predicate / object; the current value reflects the user's latest correction
+
 MEMORY PROTOCOLS - CRITICAL DISTINCTIONS:
 
 **LIFE EVENTS vs CORRECTIONS:**
@@ -170,7 +198,7 @@ MEMORY PROTOCOLS - CRITICAL DISTINCTIONS:
   → Store silently to memory while engaging with genuine interest
   
 - **CORRECTIONS** (fixing existing data): Trigger phrases include "Actually...", "I meant...", "Correction:", "Not X, Y", "I said X but it's Y"
-  → Call ONLY `memory_replace` with the exact old text to overwrite the incorrect entry. Do NOT call `archival_insert` for corrections.
+  → Call ONLY `memory_replace` with the exact old text to overwrite the incorrect entry. Do NOT call `archival_insert` for corrections. If the wrong fact is also in archival memory, fix that passage with `archival_update` (id from `archival_search`).
 
 **SEARCH SELECTION RULES:**
 - Use `archival_search` when users ask "what do you remember", "tell me about [past event]", or query specific past experiences and personal history
@@ -182,6 +210,7 @@ MEMORY TIPS:
 - Archival = rich & detailed (birthday, pet's name, trip stories, food preferences)
 - Update memory proactively whenever you learn something worth remembering
 - When using `memory_replace`, specify the exact old text to be replaced
+- Memories age: memory_metadata shows when each block was last updated, and search results older than 6 months are marked "possibly stale". Before stating an old time-sensitive fact (job, address, plans, prices) as current, verify it with web_search or ask the user
 
 COMMUNICATION STYLE:
 You communicate via Signal chat like you're texting a friend.
@@ -202,6 +231,7 @@ Guidelines:
 - Short casual exchanges = quick, warm messages
 - Technical explanations = longer structured messages with newlines OK
 - Always feel like chatting with a friend, not talking to a service
+- Asked what you can do, call `capabilities` and answer from it: only offer features it lists
 
 RESPONSE RULES:
 1. Respond naturally and conversationally
@@ -219,7 +249,7 @@ When you see "[Tool Result: X]", decide what to do next:
 
 - **web_search/archival_search/conversation_search**: Summarize findings in messages
 
-- **memory_append/memory_replace/archival_insert/memory_insert**: These operations complete without user-facing messages. Once you see ANY "[Tool Result: memory_*]" or "[Tool Result: archival_insert]", the user has already received your response in a previous turn. Immediately return:
+- **memory_append/memory_replace/archival_insert/archival_update/archival_delete/memory_insert**: These operations complete without user-facing messages. Once you see ANY "[Tool Result: memory_*]" or "[Tool Result: archival_insert/archival_update/archival_delete]", the user has already received your response in a previous turn. Immediately return:
   messages: []
   tool_calls: [{"name": "done", "args": {}}]
   
@@ -252,12 +282,79 @@ pub struct AgentContext {
     pub current_time: String,
     pub persona_block: String,
     pub human_block: String,
+    pub memory_blocks: String,
     pub memory_metadata: String,
     pub previous_context_summary: String,
     pub recent_conversation: String,
     pub is_first_time_user: bool,
 }
 
+impl AgentContext {
+    /// The inputs rendered before the volatile ones (see `AgentResponse`),
+    /// which a provider prompt cache can reuse across steps
+    pub fn cacheable_prefix(&self, available_tools: &str) -> String {
+        [
+            self.persona_block.as_str(),
+            available_tools,
+            self.human_block.as_str(),
+            self.memory_blocks.as_str(),
+            if self.is_first_time_user {
+                "true"
+            } else {
+                "false"
+            },
+            self.previous_context_summary.as_str(),
+            self.recent_conversation.as_str(),
+        ]
+        .join("\n")
+    }
+
+    /// Append what a step added to the turn: the tool results it was shown
+    /// and the messages it sent, rendered like the other conversation lines
+    pub fn append_step(&mut self, tool_results: &[String], messages: &[String]) {
+        if tool_results.is_empty() && messages.is_empty() {
+            return;
+        }
+        if self.recent_conversation == NO_CONVERSATION {
+            self.recent_conversation.clear();
+        }
+        for result in tool_results {
+            self.recent_conversation
+                .push_str(&format!("[tool]: {}\n", clip_tool_message(result)));
+        }
+        for message in messages {
+            self.recent_conversation
+                .push_str(&format!("[assistant]: {}\n", message));
+        }
+    }
+}
+
+/// `recent_conversation` when there is none
+const NO_CONVERSATION: &str = "No previous conversation.";
+
+/// Longest tool message shown in `recent_conversation`
+const MAX_TOOL_MESSAGE_CHARS: usize = 2000;
+
+/// Truncate a tool message for `recent_conversation`
+fn clip_tool_message(content: &str) -> String {
+    if content.len() <= MAX_TOOL_MESSAGE_CHARS {
+        return content.to_string();
+    }
+    let mut end = MAX_TOOL_MESSAGE_CHARS;
+    while !content.is_char_boundary(end) && end > 0 {
+        end -= 1;
+    }
+    format!("{}...", &content[..end])
+}
+
+/// Length in bytes of the prefix two prompts share
+fn shared_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
 /// Result of executing a tool
 #[derive(Clone, Debug)]
 pub struct ToolResult {
@@ -316,18 +413,83 @@ impl Tool for ToolDescriptor {
     }
 }
 
+/// Process-wide caps on concurrent executions of individual tools.
+///
+/// One instance is shared by every agent's `ToolRegistry`, so e.g. a limit of
+/// 1 on `shell` means only one shell command runs at a time across all agents.
+/// Tools without a limit run unrestricted.
+#[derive(Default)]
+pub struct ToolConcurrencyLimits {
+    semaphores: HashMap<String, Arc<Semaphore>>,
+}
+
+impl ToolConcurrencyLimits {
+    pub fn new(limits: &HashMap<String, usize>) -> Self {
+        Self {
+            semaphores: limits
+                .iter()
+                .map(|(name, limit)| (name.clone(), Arc::new(Semaphore::new((*limit).max(1)))))
+                .collect(),
+        }
+    }
+
+    /// Parse a limit spec like "shell=1,web_search=3"
+    pub fn parse(spec: &str) -> Result<HashMap<String, usize>> {
+        let mut limits = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, limit) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("expected tool=limit, got '{}'", entry))?;
+            let limit: usize = limit
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("invalid limit for tool '{}'", name.trim()))?;
+            if limit == 0 {
+                anyhow::bail!("limit for tool '{}' must be at least 1", name.trim());
+            }
+            limits.insert(name.trim().to_string(), limit);
+        }
+        Ok(limits)
+    }
+
+    /// Wait for an execution slot. Returns None for tools without a limit;
+    /// the slot is released when the permit is dropped.
+    pub async fn acquire(&self, name: &str) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.semaphores.get(name)?.clone();
+        match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                tracing::debug!("Tool {} at its concurrency limit, waiting", name);
+                semaphore.acquire_owned().await.ok()
+            }
+        }
+    }
+}
+
 /// Registry of available tools
 pub struct ToolRegistry {
     tools: BTreeMap<String, Arc<dyn Tool>>,
+    limits: Arc<ToolConcurrencyLimits>,
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
         Self {
             tools: BTreeMap::new(),
+            limits: Arc::new(ToolConcurrencyLimits::default()),
         }
     }
 
+    /// Share process-wide concurrency limits with this registry
+    pub fn set_concurrency_limits(&mut self, limits: Arc<ToolConcurrencyLimits>) {
+        self.limits = limits;
+    }
+
+    /// Wait for an execution slot for `name` (see `ToolConcurrencyLimits`)
+    pub async fn acquire(&self, name: &str) -> Option<OwnedSemaphorePermit> {
+        self.limits.acquire(name).await
+    }
+
     pub fn register(&mut self, tool: Arc<dyn Tool>) {
         self.tools.insert(tool.name().to_string(), tool);
     }
@@ -336,19 +498,42 @@ impl ToolRegistry {
         self.tools.get(name)
     }
 
+    /// Keep only the tools `keep` accepts
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.tools.retain(|name, _| keep(name));
+    }
+
     #[allow(dead_code)]
     pub fn has(&self, name: &str) -> bool {
         self.tools.contains_key(name)
     }
 
+    /// Name and description of each tool, by name
+    pub fn summaries(&self) -> Vec<(String, String)> {
+        self.tools
+            .values()
+            .map(|tool| (tool.name().to_string(), tool.description().to_string()))
+            .collect()
+    }
+
     /// Generate tool descriptions for the prompt
     pub fn generate_description(&self) -> String {
-        if self.tools.is_empty() {
+        self.generate_description_except(&[])
+    }
+
+    /// Tool descriptions for the prompt, leaving out `hidden` tools
+    pub fn generate_description_except(&self, hidden: &[&str]) -> String {
+        let mut tools = self
+            .tools
+            .values()
+            .filter(|tool| !hidden.contains(&tool.name()))
+            .peekable();
+        if tools.peek().is_none() {
             return "No tools available.".to_string();
         }
 
         let mut desc = String::from("Available tools (add to tool_calls array to use):\n\n");
-        for tool in self.tools.values() {
+        for tool in tools {
             desc.push_str(&format!(
                 "{}:\n  Description: {}\n  Args: {}\n\n",
                 tool.name(),
@@ -370,17 +555,32 @@ impl ToolRegistry {
         registry.register_descriptor(
             "memory_replace",
             "Replace text in a memory block. Requires exact match of old text.",
-            r#"{"block": "block label (e.g., 'persona', 'human')", "old": "exact text to find", "new": "replacement text"}"#,
+            r#"{"block": "block label (e.g., 'persona', 'human')", "old": "exact text to find", "new": "replacement text", "person": "optional sender id in a group chat: edit that person's own human block"}"#,
         );
         registry.register_descriptor(
             "memory_append",
             "Append text to the end of a memory block.",
-            r#"{"block": "block label (e.g., 'persona', 'human')", "content": "text to append"}"#,
+            r#"{"block": "block label (e.g., 'persona', 'human')", "content": "text to append", "person": "optional sender id in a group chat: append to that person's own human block"}"#,
         );
         registry.register_descriptor(
             "memory_insert",
             "Insert text at a specific line in a memory block. Use line=-1 for end.",
-            r#"{"block": "block label", "content": "text to insert", "line": "line number (0-indexed, -1 for end)"}"#,
+            r#"{"block": "block label", "content": "text to insert", "line": "line number (0-indexed, -1 for end)", "person": "optional sender id in a group chat: insert into that person's own human block"}"#,
+        );
+        registry.register_descriptor(
+            "memory_undo",
+            "Undo the last change to a memory block, or restore it as it was at an earlier version. Use history=true to list its recent changes with their versions first. An undo can itself be undone.",
+            r#"{"block": "block label (e.g., 'persona', 'human')", "version": "optional version to restore (default: before the last change)", "history": "optional 'true' to list recent changes instead", "person": "optional sender id in a group chat: that person's own human block"}"#,
+        );
+        registry.register_descriptor(
+            "memory_create_block",
+            "Create a new core memory block (always in your context, like persona and human) for a topic that deserves its own standing place, e.g. 'project_context' or 'health'. Edit it afterwards with memory_append/memory_replace/memory_insert.",
+            r#"{"label": "lowercase label with underscores (e.g., 'project_context')", "description": "what the block is for", "char_limit": "optional max characters (default 5000)", "value": "optional initial content"}"#,
+        );
+        registry.register_descriptor(
+            "capabilities",
+            "List what you can actually do in this deployment: messenger, enabled features, this conversation's role and budget, your persona, and every tool you have. Call this before answering 'what can you do?' instead of guessing.",
+            "{}",
         );
         registry.register_descriptor(
             "conversation_search",
@@ -389,25 +589,70 @@ impl ToolRegistry {
         );
         registry.register_descriptor(
             "archival_insert",
-            "Store information in long-term archival memory for future recall. Good for important facts, preferences, and details you want to remember.",
-            r#"{"content": "text to store", "tags": "optional comma-separated tags"}"#,
+            "Store information in long-term archival memory for future recall. Good for important facts, preferences, and details you want to remember. Set expires for facts that stop mattering (a trip, a temporary address).",
+            r#"{"content": "text to store", "tags": "optional comma-separated tags", "importance": "optional low, normal or high", "expires": "optional expiry: a duration (30d, 2w, 6m, 1y) or a date (YYYY-MM-DD)", "namespace": "optional namespace to store in (default: the active one; 'none' for no namespace)"}"#,
         );
         registry.register_descriptor(
             "archival_search",
-            "Search long-term archival memory using semantic similarity. Returns most relevant stored memories.",
-            r#"{"query": "search query", "top_k": "max results (default 5)", "tags": "optional comma-separated tags to filter by"}"#,
+            "Search long-term archival memory by meaning and exact words (names, numbers). Returns most relevant stored memories.",
+            r#"{"query": "search query", "top_k": "max results (default 5)", "tags": "optional comma-separated tags to filter by", "namespace": "optional namespace to search (default: the active one; 'all' for every namespace)"}"#,
+        );
+        registry.register_descriptor(
+            "archival_update",
+            "Rewrite an archival memory that is wrong or out of date. Get its id from archival_search or archival_list. The new content replaces the old entirely.",
+            r#"{"id": "passage id", "content": "corrected text", "tags": "optional comma-separated tags (replace the old tags)"}"#,
+        );
+        registry.register_descriptor(
+            "archival_delete",
+            "Delete an archival memory that is wrong or no longer true, by id (from archival_search or archival_list). A group tag (group:...) deletes every part of long content.",
+            r#"{"id": "passage id, or a group:... tag"}"#,
+        );
+        registry.register_descriptor(
+            "archival_list",
+            "List archival memories newest first, optionally only those with a tag. Use to review or clean up what you have stored; use archival_search to find something specific.",
+            r#"{"tag": "optional tag to filter by", "limit": "max results (default 20, max 50)", "offset": "optional number to skip, for the next page", "namespace": "optional namespace to list (default: the active one; 'all' for every namespace)"}"#,
+        );
+        registry.register_descriptor(
+            "archival_namespace",
+            "Switch this conversation's archival namespace (e.g. 'work', 'personal', 'project-apollo') so work notes and personal memories stay apart. While one is active, archival_insert stores into it and archival_search/archival_list see only it plus memories in no namespace. Call without arguments to see the active namespace and those in use; with ids, move those passages into the namespace instead.",
+            r#"{"namespace": "namespace to switch to, or 'none' to stop using one; omit to list", "ids": "optional comma-separated passage ids to move into the namespace instead of switching"}"#,
+        );
+        registry.register_descriptor(
+            "forget",
+            "Permanently delete what the user asks you to forget: archival memories about it, and facts and past messages containing all of its words. Only use when the user explicitly asks. Also remove it from your memory blocks with memory_replace.",
+            r#"{"about": "the key words of what to forget, e.g. a name or topic"}"#,
+        );
+        registry.register_descriptor(
+            "fact_query",
+            "Look up structured facts about the user and their people, pets and things (subject / predicate / object, e.g. user_dog / age / 4), learned automatically from their messages. The current value reflects their latest correction. Give subject and predicate with history=true to see earlier values.",
+            r#"{"subject": "optional subject key, e.g. 'user' or 'user_dog'", "predicate": "optional predicate key, e.g. 'age'", "about": "optional words the fact must contain", "history": "optional 'true' to include superseded values (needs subject and predicate)", "limit": "max facts (default 20)"}"#,
         );
         registry.register_descriptor(
             "set_preference",
-            "Set a user preference. Known keys: 'timezone' (IANA format like 'America/Chicago'), 'language' (ISO code like 'en'), 'display_name'. Other keys are also allowed.",
+            "Set a user preference. Known keys: 'timezone' (IANA format like 'America/Chicago'), 'language' (ISO code like 'en'), 'display_name', 'occasions' ('off' stops birthday and anniversary check-ins). Other keys are also allowed.",
             r#"{"key": "preference key (e.g., 'timezone', 'language', 'display_name')", "value": "preference value"}"#,
         );
+        registry.register_descriptor(
+            "memory_source",
+            "Find where you learned something you remember: quotes the original messages behind a memory block edit, archival passage or preference. Use when asked 'where did you learn that?' or before relying on a surprising memory.",
+            r#"{"memory": "the remembered fact, or words from it (e.g. 'dog named Smokey')", "limit": "max sources (default 3)"}"#,
+        );
+        registry.register_descriptor(
+            "pin_message",
+            "Pin a message so it stays in your context word for word instead of being summarized when the conversation is compacted. Use for standing instructions and critical details the user asks you to always remember, not for ordinary facts (use memory blocks or archival memory). Pins are limited, so unpin ones that no longer apply.",
+            r#"{"text": "words from the message to pin (the latest message containing them); omit for the user's latest message", "unpin": "true to unpin instead"}"#,
+        );
+        registry.register_descriptor(
+            "resolve_open_thread",
+            "Close open threads (listed after the conversation summary) once they are answered, done or no longer matter, so they stop showing up.",
+            r#"{"numbers": "comma-separated numbers of the open threads, as listed"}"#,
+        );
 
         // -- Scheduler tools (from scheduler_tools) --
         registry.register_descriptor(
             "schedule_task",
             "Schedule a future message or tool execution. Supports one-off (ISO datetime) or recurring (cron expression).",
-            r#"{"task_type": "message|tool_call", "description": "human-readable description", "run_at": "ISO datetime (2026-01-26T15:30:00Z) or cron (0 9 * * MON-FRI)", "payload": "JSON: {\"message\": \"...\"} for message, {\"tool\": \"name\", \"args\": {...}} for tool_call", "timezone": "optional IANA timezone for cron (default: user preference or UTC)"}"#,
+            crate::scheduler_tools::SCHEDULE_TASK_ARGS,
         );
         registry.register_descriptor(
             "list_schedules",
@@ -420,6 +665,66 @@ impl ToolRegistry {
             r#"{"id": "UUID of the task to cancel"}"#,
         );
 
+        // -- Expense tools (from expenses) --
+        registry.register_descriptor(
+            "spending_report",
+            "Summarize expenses saved from receipt and bill photos: totals, breakdown by category or merchant, and the largest purchases. For a monthly summary, schedule a recurring tool_call of spending_report with period 'last_month'.",
+            r#"{"period": "this_month (default), last_month, this_year, YYYY-MM, YYYY, or YYYY-MM-DD..YYYY-MM-DD", "group_by": "category (default) or merchant"}"#,
+        );
+
+        // -- Travel tools (from itinerary) --
+        registry.register_descriptor(
+            "add_itinerary",
+            "Save flights and hotel stays from a booking confirmation the user pasted or shared. Times are stored with each airport's timezone, and reminders are scheduled automatically (flight check-in 24h before, leave for the airport 3h before, hotel check-in). Don't schedule these reminders yourself.",
+            r#"{"text": "the full confirmation text, including dates, times, flight numbers and booking references"}"#,
+        );
+        registry.register_descriptor(
+            "travel_plans",
+            "List the user's saved flights and hotel stays with exact departure, landing, check-in and check-out times, in local time at each place and in the user's timezone. Always use this to answer questions like 'when does my flight land?' - don't work out times yourself.",
+            r#"{"query": "optional filter: flight number, airport, city, hotel or confirmation code", "include_past": "true to include trips that already ended (default false)"}"#,
+        );
+
+        // -- Shared todos (from todos) --
+        registry.register_descriptor(
+            "add_todo",
+            "Add a todo to this chat's shared list, optionally assigned to someone (e.g. '@bob takes trash duty') and with a due time. In a group everyone shares the list. With a due time, a reminder is sent automatically - to the assignee when they are a known group member, otherwise in this chat.",
+            r#"{"title": "what needs doing", "assignee": "optional: who it is assigned to (name or @name)", "due": "optional: 'YYYY-MM-DD HH:MM' in the user's timezone, or ISO 8601"}"#,
+        );
+        registry.register_descriptor(
+            "list_todos",
+            "List this chat's shared todos with their ids, assignees and due times. Open todos by default.",
+            r#"{"assignee": "optional: only todos assigned to this name", "status": "open (default), done, or all"}"#,
+        );
+        registry.register_descriptor(
+            "complete_todo",
+            "Mark a shared todo as done (its pending reminder is cancelled). Use the id from list_todos.",
+            r#"{"id": "todo id (the 8 characters shown by list_todos)"}"#,
+        );
+
+        // -- Group polls (from polls) --
+        registry.register_descriptor(
+            "create_poll",
+            "Start a poll in this group chat. Votes are counted automatically and the result is announced when the poll closes. Post the poll text the tool returns exactly as given, as its own message. Only works in group chats.",
+            r#"{"question": "what to vote on", "options": "2-9 options separated by |, e.g. 'Pizza | Sushi | Tacos'", "closes": "optional: 'YYYY-MM-DD HH:MM' in the user's timezone or ISO 8601 (default: in 24 hours)"}"#,
+        );
+        registry.register_descriptor(
+            "close_poll",
+            "Close a poll now and get its result (polls also close on their own at their deadline, announcing the result).",
+            r#"{"id": "poll id (the 8 characters in 'Poll [...]')"}"#,
+        );
+
+        // -- Turn journal (from turn_journal) --
+        registry.register_descriptor(
+            "turn_transcript",
+            "Show the full transcript of one of your past turns: the input, what you saw at each step, every tool call with its result, the messages you sent, and timings. Use it when the user asks why you did something.",
+            r#"{"turn_id": "turn UUID (optional, defaults to your previous turn)"}"#,
+        );
+        registry.register_descriptor(
+            "explain_last_action",
+            "Retrieve the factual record of your recent turns (what the user said, which tools you called with what arguments and results, what you replied) so you can honestly explain why you did something. Use this whenever the user asks why you did or said something - never guess from memory.",
+            r#"{"turns": "how many recent turns to include (default 1, max 5)"}"#,
+        );
+
         // -- Shell tool --
         registry.register_descriptor(
             "shell",
@@ -427,12 +732,67 @@ impl ToolRegistry {
             r#"{"command": "shell command to execute (supports pipes, redirects)", "timeout": "optional timeout in seconds (default 60, set appropriately for long-running commands)"}"#,
         );
 
+        // -- Shell session tools (from shell_session) --
+        registry.register_descriptor(
+            "shell_session_start",
+            "Run a command as a background job in a named, persistent shell session (created on first use). Use for long-running processes (servers, sync daemons, big downloads) that must not block. Waits briefly and returns the output if the job finishes quickly; otherwise returns the job ID to poll with shell_job_status.",
+            r#"{"session": "session name (letters, digits, - and _)", "command": "shell command to run as a background job", "cwd": "optional working directory relative to the workspace (only used when creating the session)", "wait": "optional seconds to wait for the job before returning (default 5, max 60)"}"#,
+        );
+        registry.register_descriptor(
+            "shell_job_status",
+            "Check background shell jobs. With session and job, shows the job's state and recent output. Without a job, lists sessions and their jobs.",
+            r#"{"session": "optional session name", "job": "optional job ID (requires session)", "tail": "optional bytes of output to show (default 4000, max 20000)"}"#,
+        );
+        registry.register_descriptor(
+            "shell_job_kill",
+            "Stop a background shell job (SIGTERM, then SIGKILL after a few seconds), including any processes it spawned. Omit the job to kill every job in the session and close it.",
+            r#"{"session": "session name", "job": "optional job ID (omit to kill the whole session)"}"#,
+        );
+
+        // -- Workspace rollback tool --
+        registry.register_descriptor(
+            "workspace_rollback",
+            "Undo changes to your workspace. A snapshot is taken automatically before shell commands that delete, move or overwrite files or run scripts. Use action 'list' to see snapshots, or 'restore' to roll the workspace back (the current state is snapshotted first, so a rollback can be undone too).",
+            r#"{"action": "list or restore (default list)", "snapshot": "snapshot id to restore (default: most recent)"}"#,
+        );
+
+        // -- Send file tool --
+        registry.register_descriptor(
+            "send_file",
+            "Send a file from your workspace to the user (an image, PDF, CSV, etc. you created or downloaded). Delivered right after this step's messages.",
+            r#"{"path": "file path, relative to the workspace", "caption": "optional text sent with the file"}"#,
+        );
+
+        // -- Encrypted exports (from export) --
+        registry.register_descriptor(
+            "export_conversation",
+            "Export this conversation and your core memory as a file sent to the user. If the user set an export passphrase (/export-key), the file is encrypted with it; otherwise tell them the export is unencrypted and that they can send '/export-key <passphrase>' in a direct chat first. Never ask the user to tell you their passphrase.",
+            r#"{"days": "optional: only messages from the last N days (default: the whole conversation)"}"#,
+        );
+
+        // -- React tool --
+        registry.register_descriptor(
+            "react",
+            "React to the user's latest message with an emoji (e.g. 👍 for \"thanks!\" or \"ok\"). Use instead of a reply for simple acknowledgements; combine with done if nothing else needs saying.",
+            r#"{"emoji": "a single emoji, e.g. 👍 ❤️ 😂"}"#,
+        );
+
         // -- Web search tool --
         registry.register_descriptor(
             "web_search",
             "Search the web with AI summaries, real-time data (weather, stocks, sports), and rich results. Use 'freshness' for time-sensitive queries, 'location' for local results.",
             r#"{ "query": "search query", "count": "results (default 10)", "freshness": "pd=24h, pw=week, pm=month (optional)", "location": "city or 'city, state' for local results (optional)" }"#,
         );
+        registry.register_descriptor(
+            "web_fetch",
+            "Read a web page as plain text (e.g. a result from web_search). Long pages are truncated.",
+            r#"{"url": "http(s) URL to read", "max_chars": "characters to return (default 8000)"}"#,
+        );
+        registry.register_descriptor(
+            "deep_research",
+            "Research a question thoroughly: runs several web searches, reads the top pages and returns a cited answer (also saved to archival memory, tag 'research'). Slower than web_search - use for questions that need more than one search.",
+            r#"{"question": "the question to research"}"#,
+        );
 
         // -- Done tool --
         registry.register_descriptor(
@@ -472,18 +832,79 @@ pub struct Message {
 pub struct ExecutedTool {
     pub tool_call: ToolCall,
     pub result: ToolResult,
+    /// Wall-clock execution time
+    pub duration_ms: u64,
 }
 
 /// Result of a single agent step
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct StepResult {
+    /// Input sent to the LLM this step (user message or tool results)
+    pub input: String,
+    /// Time spent waiting on the LLM, including retries
+    pub llm_duration_ms: u64,
     pub messages: Vec<String>,
     pub tool_calls: Vec<ToolCall>,
     pub executed_tools: Vec<ExecutedTool>, // Tool calls with their results for storage
+    /// Files queued for delivery by tools this step (e.g. send_file)
+    pub attachments: Vec<OutgoingAttachment>,
+    /// Emoji reactions to the user's message queued this step (react tool)
+    pub reactions: Vec<String>,
+    /// Leading `messages` already sent while the answer streamed
+    pub streamed: usize,
     pub done: bool,
 }
 
+/// Non-empty messages of an answer. Sometimes the LLM double-encodes
+/// (`["[\"msg1\", \"msg2\"]"]` instead of `["msg1", "msg2"]`); nested
+/// arrays are unwrapped.
+fn flatten_messages(messages: &[String]) -> Vec<String> {
+    messages
+        .iter()
+        .flat_map(|m| {
+            let trimmed = m.trim();
+            // Check if this message is itself a JSON array
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                // Try to parse as JSON array of strings
+                if let Ok(inner_messages) = serde_json::from_str::<Vec<String>>(trimmed) {
+                    tracing::debug!(
+                        "Unwrapped nested JSON array with {} messages",
+                        inner_messages.len()
+                    );
+                    return inner_messages;
+                }
+            }
+            // Not a nested array, return as-is
+            vec![m.clone()]
+        })
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// An `AgentResponse` made from its outputs (the inputs aren't read back)
+fn response_from_parts(
+    input: &str,
+    available_tools: &str,
+    messages: Vec<String>,
+    tool_calls: Vec<ToolCall>,
+) -> AgentResponse {
+    AgentResponse {
+        input: input.to_string(),
+        current_time: String::new(),
+        persona_block: String::new(),
+        human_block: String::new(),
+        memory_blocks: String::new(),
+        memory_metadata: String::new(),
+        previous_context_summary: String::new(),
+        recent_conversation: String::new(),
+        available_tools: available_tools.to_string(),
+        is_first_time_user: false,
+        messages,
+        tool_calls,
+    }
+}
+
 #[allow(dead_code)]
 impl Message {
     pub fn user(content: impl Into<String>) -> Self {
@@ -508,6 +929,25 @@ impl Message {
     }
 }
 
+/// Tools whose exact repeat within a turn is legitimate (polling, commands
+/// whose effect depends on state that changed in between)
+const REPEATABLE_TOOLS: &[&str] = &["done", "shell", "shell_job_status"];
+
+/// Result given instead of re-running a call that already succeeded this turn
+const ALREADY_EXECUTED: &str = "Already executed this turn with these exact arguments - not run again. The earlier result stands; don't call it again.";
+
+/// Identity of a tool call: its name and arguments (in any order)
+fn call_fingerprint(tool_call: &ToolCall) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tool_call.name.hash(&mut hasher);
+    tool_call
+        .args
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
 /// The Sage agent using DSRs
 #[allow(dead_code)]
 pub struct SageAgent {
@@ -519,7 +959,31 @@ pub struct SageAgent {
     /// Track what was sent in previous step (messages + tool names) for context
     /// The messages Vec contains the actual message content sent
     previous_step_summary: Option<(Vec<String>, Vec<String>)>,
+    /// Fingerprints of tool calls that succeeded this turn; exact repeats are
+    /// refused so the model can't loop on e.g. the same archival_insert
+    executed_calls: HashSet<u64>,
     max_steps: usize,
+    /// Scrubs secrets from tool output before it reaches context or memory
+    secret_scanner: Option<Arc<SecretScanner>>,
+    /// Files queued by tools, handed to the caller with each step's result
+    outbox: Option<AttachmentOutbox>,
+    /// Reactions queued by the react tool, handed over the same way
+    reactions: Option<ReactionOutbox>,
+    /// Web sources seen this turn, for citations (None = citations off)
+    sources: Option<SourceLedger>,
+    /// Cacheable prompt prefix of the previous step, to log how much of it
+    /// the next step reuses
+    last_prompt_prefix: Option<String>,
+    /// Reuse the turn's context between steps instead of rebuilding it
+    partial_refresh: bool,
+    /// Context of the turn so far, when `partial_refresh` is on
+    turn_context: Option<AgentContext>,
+    /// Conversation mode, read at the start of each turn
+    mode: Option<Mode>,
+    /// Where the next step sends messages as they stream (see `streaming`)
+    message_stream: Option<mpsc::UnboundedSender<String>>,
+    /// Sends simple turns to a cheaper model (see `model_router`)
+    router: Option<ModelRouter>,
 }
 
 #[allow(dead_code)]
@@ -532,8 +996,122 @@ impl SageAgent {
             memory: Some(memory),
             current_tool_results: Vec::new(),
             previous_step_summary: None,
+            executed_calls: HashSet::new(),
             max_steps: 10,
+            secret_scanner: None,
+            outbox: None,
+            reactions: None,
+            sources: None,
+            last_prompt_prefix: None,
+            partial_refresh: false,
+            turn_context: None,
+            mode: None,
+            message_stream: None,
+            router: None,
+        }
+    }
+
+    /// Collect files queued by tools (shared with e.g. `SendFileTool`)
+    pub fn with_attachment_outbox(mut self, outbox: AttachmentOutbox) -> Self {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    /// Collect reactions queued by `ReactTool`
+    pub fn with_reaction_outbox(mut self, reactions: ReactionOutbox) -> Self {
+        self.reactions = Some(reactions);
+        self
+    }
+
+    /// Number web sources in tool results and append the ones each message
+    /// cites
+    pub fn with_citations(mut self) -> Self {
+        self.sources = Some(SourceLedger::default());
+        self
+    }
+
+    /// Build the conversation history once per turn. Later steps refresh
+    /// the time, blocks and metadata and append only what the previous step
+    /// added, so the prompt grows at its end and resends less
+    pub fn with_partial_context_refresh(mut self) -> Self {
+        self.partial_refresh = true;
+        self
+    }
+
+    /// Redact secrets from every tool result
+    pub fn with_secret_scanner(mut self, scanner: Arc<SecretScanner>) -> Self {
+        self.secret_scanner = Some(scanner);
+        self
+    }
+
+    /// Answer simple turns with the router's cheap model
+    pub fn with_model_router(mut self, router: ModelRouter) -> Self {
+        self.router = Some(router);
+        self
+    }
+
+    /// Stream the next step's answer, sending each message to `tx` as soon
+    /// as it is complete. Applies to one step; the sender is dropped after it.
+    pub fn stream_messages_to(&mut self, tx: mpsc::UnboundedSender<String>) {
+        self.message_stream = Some(tx);
+    }
+
+    /// Execute a registered tool by name (errors are returned as failed results)
+    pub async fn execute_tool(&self, name: &str, args: &HashMap<String, String>) -> ToolResult {
+        let result = if let Some(mode) = self.mode.filter(|mode| !mode.allows_tool(name)) {
+            ToolResult::error(format!(
+                "{} is not available in {} mode",
+                name,
+                mode.as_str()
+            ))
+        } else if let Some(tool) = self.tools.get(name) {
+            let _permit = self.tools.acquire(name).await;
+            match tool.execute(args).await {
+                Ok(result) => {
+                    tracing::debug!("Tool {} result: {:?}", name, result);
+                    result
+                }
+                Err(e) => {
+                    tracing::error!("Tool {} error: {}", name, e);
+                    ToolResult::error(e.to_string())
+                }
+            }
+        } else {
+            tracing::warn!("Unknown tool: {}", name);
+            ToolResult::error(format!("Unknown tool: {}", name))
+        };
+
+        self.scrub_secrets(name, result)
+    }
+
+    /// Redact secrets from a tool result before it is injected into context
+    /// or stored in recall memory
+    fn scrub_secrets(&self, name: &str, mut result: ToolResult) -> ToolResult {
+        let Some(ref scanner) = self.secret_scanner else {
+            return result;
+        };
+
+        let (output, mut found) = scanner.redact(&result.output);
+        result.output = output;
+        if let Some(error) = result.error.take() {
+            let (error, error_found) = scanner.redact(&error);
+            found.extend(error_found);
+            result.error = Some(error);
+        }
+
+        if !found.is_empty() {
+            tracing::warn!(
+                "Redacted secrets from {} output: {}",
+                name,
+                found.join(", ")
+            );
         }
+        result
+    }
+
+    /// The agent's memory manager, if configured
+    pub fn memory(&self) -> Option<&MemoryManager> {
+        self.memory.as_ref()
     }
 
     /// Store a message in memory (for persistence)
@@ -545,8 +1123,8 @@ impl SageAgent {
         }
     }
 
-    /// Store a message WITHOUT embedding (fast, synchronous)
-    /// Returns message ID for later embedding update
+    /// Store a message WITHOUT embedding (fast, synchronous); it is queued
+    /// for embedding
     pub fn store_message_sync(&self, user_id: &str, role: &str, content: &str) -> Result<Uuid> {
         if let Some(memory) = &self.memory {
             memory.store_message_sync(user_id, role, content)
@@ -570,15 +1148,6 @@ impl SageAgent {
         }
     }
 
-    /// Update embedding for a message (call in background)
-    pub async fn update_message_embedding(&self, message_id: Uuid, content: &str) -> Result<()> {
-        if let Some(memory) = &self.memory {
-            memory.update_message_embedding(message_id, content).await
-        } else {
-            Err(anyhow::anyhow!("No memory system configured"))
-        }
-    }
-
     /// Store a tool call and its result in memory
     pub async fn store_tool_message(
         &self,
@@ -637,26 +1206,28 @@ impl SageAgent {
         }
     }
 
-    /// Configure the global LM settings for DSRs
+    /// Configure the global LM settings for DSRs. Replies may use the model's
+    /// max output tokens (see `model_limits`).
     pub async fn configure_lm(api_base: &str, api_key: &str, model: &str) -> Result<()> {
         let lm = LM::builder()
             .base_url(api_base.to_string())
             .api_key(api_key.to_string())
             .model(model.to_string())
             .temperature(0.7)
-            .max_tokens(32768) // High limit for thinking models (Kimi K2 uses tokens for reasoning)
+            .max_tokens(crate::model_limits::current().max_output_tokens as u32)
             .build()
             .await?;
 
         configure(lm, ChatAdapter);
+        streaming::configure(api_base, api_key, model);
         Ok(())
     }
 
     /// Build conversation context from database + current tool results
     /// Returns AgentContext with all fields separated for the signature
-    fn build_context(&self) -> AgentContext {
-        let mut ctx = AgentContext::default();
-
+    /// Fill the fields that can change between steps of a turn: the time,
+    /// the core blocks and the memory metadata
+    fn refresh_memory_fields(&self, ctx: &mut AgentContext) {
         // Current time in user's timezone
         let now = chrono::Utc::now();
         if let Some(memory) = &self.memory {
@@ -680,13 +1251,22 @@ impl SageAgent {
             if let Some(persona) = memory.blocks().get("persona") {
                 ctx.persona_block = persona.value.clone();
             }
-            if let Some(human) = memory.blocks().get("human") {
-                ctx.human_block = human.value.clone();
+            if let Some(mode) = self.mode {
+                ctx.persona_block = mode.apply_style(&ctx.persona_block);
             }
+            // Shared human block, then group participants' own blocks
+            ctx.human_block = memory.blocks().compile_human();
+            // Blocks the agent created, when they hold something
+            ctx.memory_blocks = memory.blocks().compile_custom();
 
             // Memory metadata (counts and timestamps)
             ctx.memory_metadata = memory.compile_metadata();
         }
+    }
+
+    fn build_context(&self) -> AgentContext {
+        let mut ctx = AgentContext::default();
+        self.refresh_memory_fields(&mut ctx);
 
         // Load conversation history
         let mut conversation = String::new();
@@ -694,6 +1274,9 @@ impl SageAgent {
 
         if let Some(memory) = &self.memory {
             let user_tz = memory.get_timezone().ok().flatten();
+            // In groups, user messages show their sender id, which is how
+            // participants' human blocks are keyed
+            let group = memory.is_group();
 
             if let Ok((summary, messages)) = memory.get_context_messages() {
                 // First-time user check (before moving values)
@@ -707,6 +1290,18 @@ impl SageAgent {
                 if let Some(s) = summary {
                     ctx.previous_context_summary = s.content;
                 }
+                // Followed by what compaction found left open
+                match memory.open_threads_section() {
+                    Ok(Some(section)) if ctx.previous_context_summary.is_empty() => {
+                        ctx.previous_context_summary = section;
+                    }
+                    Ok(Some(section)) => {
+                        ctx.previous_context_summary.push_str("\n\n");
+                        ctx.previous_context_summary.push_str(&section);
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Failed to load open threads: {}", e),
+                }
 
                 // Recent messages
                 if !messages.is_empty() {
@@ -719,28 +1314,33 @@ impl SageAgent {
                             format!("{} UTC", msg.created_at.format("%m/%d/%Y %H:%M:%S"))
                         };
                         // Truncate tool messages to 2k chars
-                        let content = if msg.role == "tool" && msg.content.len() > 2000 {
-                            let mut end = 2000;
-                            while !msg.content.is_char_boundary(end) && end > 0 {
-                                end -= 1;
-                            }
-                            format!("{}...", &msg.content[..end])
+                        let content = if msg.role == "tool" {
+                            clip_tool_message(&msg.content)
                         } else {
                             msg.content.clone()
                         };
                         // Render attachment_text alongside user messages
                         let display_content = if let Some(ref att) = msg.attachment_text {
+                            let att = crate::speech::display_attachment_text(att);
                             if content.is_empty() {
-                                format!("[Uploaded Image: {}]", att)
+                                att
                             } else {
-                                format!("{}\n[Uploaded Image: {}]", content, att)
+                                format!("{}\n{}", content, att)
                             }
                         } else {
                             content
                         };
+                        let mut speaker = if group && msg.role == "user" {
+                            format!("user id={}", msg.user_id)
+                        } else {
+                            msg.role.clone()
+                        };
+                        if msg.pinned {
+                            speaker.push_str(" (pinned)");
+                        }
                         conversation.push_str(&format!(
                             "[{} @ {}]: {}\n",
-                            msg.role, timestamp, display_content
+                            speaker, timestamp, display_content
                         ));
                     }
                 }
@@ -756,7 +1356,7 @@ impl SageAgent {
         }
 
         if conversation.is_empty() {
-            ctx.recent_conversation = "No previous conversation.".to_string();
+            ctx.recent_conversation = NO_CONVERSATION.to_string();
         } else {
             ctx.recent_conversation = conversation;
         }
@@ -797,6 +1397,8 @@ impl SageAgent {
     pub fn clear_tool_results(&mut self) {
         self.current_tool_results.clear();
         self.previous_step_summary = None;
+        self.executed_calls.clear();
+        self.turn_context = None;
     }
 
     /// Attempt to correct a malformed LLM response using the correction agent
@@ -832,34 +1434,115 @@ impl SageAgent {
         };
 
         // Call correction agent (no retry on correction - avoid infinite loops)
-        let corrected = correction_predictor.call(correction_input).await?;
+        let corrected = correction_predictor
+            .call_with_meta(correction_input)
+            .await?;
+        usage::record_chat(
+            CallKind::Correction,
+            corrected.lm_usage.prompt_tokens as i64,
+            corrected.lm_usage.completion_tokens as i64,
+        );
+        let corrected = corrected.output;
 
         tracing::info!("=== CORRECTION RESULT ===");
         tracing::info!("Corrected messages: {:?}", corrected.messages);
         tracing::info!("Corrected tool_calls: {:?}", corrected.tool_calls);
 
         // Convert CorrectionResponse to AgentResponse
-        Ok(AgentResponse {
-            input: original_input.to_string(),
-            current_time: String::new(),
-            persona_block: String::new(),
-            human_block: String::new(),
-            memory_metadata: String::new(),
-            previous_context_summary: String::new(),
-            recent_conversation: String::new(),
-            available_tools: available_tools.to_string(),
-            is_first_time_user: false,
-            messages: corrected.messages,
-            tool_calls: corrected.tool_calls,
+        Ok(response_from_parts(
+            original_input,
+            available_tools,
+            corrected.messages,
+            corrected.tool_calls,
+        ))
+    }
+
+    /// Get the step's answer through `streaming`, sending each message to
+    /// `tx` as it completes, from `model` if given. Returns the response, if
+    /// one could be made out, and the messages already sent, cited as they
+    /// will be stored.
+    async fn stream_response(
+        &self,
+        input: &AgentResponseInput,
+        tx: Option<&mpsc::UnboundedSender<String>>,
+        model: Option<&ModelOverride>,
+    ) -> (Option<AgentResponse>, Vec<String>) {
+        let mut streamed = Vec::new();
+        let sources = self.sources.as_ref();
+        let raw = streaming::complete(input, model, |message| {
+            let Some(tx) = tx else {
+                return;
+            };
+            for message in flatten_messages(&[message]) {
+                let message = match sources {
+                    Some(sources) => sources.cite(&message),
+                    None => message,
+                };
+                if tx.send(message.clone()).is_ok() {
+                    streamed.push(message);
+                }
+            }
         })
+        .await;
+
+        let raw = match raw {
+            Ok(raw) => raw,
+            Err(e) => {
+                tracing::warn!(
+                    "Streaming failed after {} messages: {:?}",
+                    streamed.len(),
+                    e
+                );
+                return (None, streamed);
+            }
+        };
+        match streaming::parse_response(&raw) {
+            Ok((messages, tool_calls)) => (
+                Some(response_from_parts(
+                    &input.input,
+                    &input.available_tools,
+                    messages,
+                    tool_calls,
+                )),
+                streamed,
+            ),
+            Err(e) => {
+                let error_message = format!("Parse error: {:#}", e);
+                match self
+                    .attempt_correction(&input.input, &input.available_tools, &raw, &error_message)
+                    .await
+                {
+                    Ok(corrected) => (Some(corrected), streamed),
+                    Err(correction_err) => {
+                        tracing::warn!(
+                            "Correction of streamed answer failed: {:?}",
+                            correction_err
+                        );
+                        (None, streamed)
+                    }
+                }
+            }
+        }
     }
 
     /// Execute a single step of the agent loop
     /// Returns messages to send and whether we're done
     pub async fn step(&mut self, user_message: &str, is_first_step: bool) -> Result<StepResult> {
+        // Taken up front so the caller's receiver closes however the step ends
+        let message_stream = self.message_stream.take();
         // Clear tool results at start of new request
         if is_first_step {
             self.current_tool_results.clear();
+            self.executed_calls.clear();
+            if let Some(ref mut sources) = self.sources {
+                sources.clear();
+            }
+            self.mode = self.memory.as_ref().and_then(|memory| {
+                modes::current(memory.db(), memory.agent_id()).unwrap_or_else(|e| {
+                    tracing::warn!("Failed to read conversation mode: {}", e);
+                    None
+                })
+            });
         }
 
         tracing::debug!("Agent step (first={})", is_first_step);
@@ -869,11 +1552,20 @@ impl SageAgent {
             .instruction(AGENT_INSTRUCTION)
             .build();
 
-        // Build context - separate fields for each input
-        let ctx = self.build_context();
+        // Build context - separate fields for each input. With partial
+        // refresh, later steps extend the turn's context instead
+        let mut ctx = match self.turn_context.take() {
+            Some(mut ctx) if !is_first_step => {
+                self.refresh_memory_fields(&mut ctx);
+                ctx
+            }
+            _ => self.build_context(),
+        };
+        // Tool results shown in this step's input
+        let mut presented_results = Vec::new();
 
         // Input is either the user message (first step) or ALL tool results from this cycle
-        let input_content = if is_first_step {
+        let mut input_content = if is_first_step {
             user_message.to_string()
         } else {
             // Collect ALL tool results from current cycle
@@ -945,6 +1637,8 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
                     )
                 };
 
+                presented_results = tool_results.iter().map(|r| r.to_string()).collect();
+
                 // Clear tool results after presenting them
                 self.current_tool_results.clear();
 
@@ -958,12 +1652,58 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
         tracing::info!("Input: {}", input_content);
         tracing::info!("Recent conversation:\n{}", ctx.recent_conversation);
 
-        let available_tools = self.tools.generate_description();
+        let available_tools = match self.mode {
+            Some(mode) => self.tools.generate_description_except(mode.denied_tools()),
+            None => self.tools.generate_description(),
+        };
+        // Keep each prompt section within its share of the input budget;
+        // compaction normally keeps the prompt well under it
+        if let Some(memory) = &self.memory {
+            let mut fixed = vec![
+                AGENT_INSTRUCTION,
+                available_tools.as_str(),
+                ctx.memory_blocks.as_str(),
+                ctx.memory_metadata.as_str(),
+                ctx.current_time.as_str(),
+            ];
+            if is_first_step {
+                fixed.push(input_content.as_str());
+            }
+            let fixed_tokens = TokenCounter::new().count_many(&fixed);
+            let mut sections = vec![
+                (Section::Persona, &mut ctx.persona_block),
+                (Section::Human, &mut ctx.human_block),
+                (Section::Summary, &mut ctx.previous_context_summary),
+                (Section::Recent, &mut ctx.recent_conversation),
+            ];
+            if !is_first_step {
+                sections.push((Section::ToolResults, &mut input_content));
+            }
+            for trim in memory.context().fit(fixed_tokens, &mut sections) {
+                tracing::warn!(
+                    "Trimmed the {} from {} to {} tokens to fit the context budget",
+                    trim.section.name(),
+                    trim.before,
+                    trim.after
+                );
+            }
+        }
+        let prefix = ctx.cacheable_prefix(&available_tools);
+        if let Some(last) = &self.last_prompt_prefix {
+            tracing::debug!(
+                "Prompt prefix: {} of {} bytes unchanged since the last step",
+                shared_prefix_len(last, &prefix),
+                prefix.len()
+            );
+        }
+        self.last_prompt_prefix = Some(prefix);
+        let turn_context = self.partial_refresh.then(|| ctx.clone());
         let input = AgentResponseInput {
             input: input_content.clone(),
             current_time: ctx.current_time,
             persona_block: ctx.persona_block,
             human_block: ctx.human_block,
+            memory_blocks: ctx.memory_blocks,
             memory_metadata: ctx.memory_metadata,
             previous_context_summary: ctx.previous_context_summary,
             recent_conversation: ctx.recent_conversation,
@@ -972,62 +1712,113 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
         };
 
         // Get typed response from LLM with retry logic (up to 3 attempts)
+        let llm_started = std::time::Instant::now();
         const MAX_LLM_RETRIES: u32 = 3;
         let mut last_error: Option<dspy_rs::PredictError> = None;
         let mut response: Option<AgentResponse> = None;
 
-        for attempt in 1..=MAX_LLM_RETRIES {
-            match predictor.call(input.clone()).await {
-                Ok(r) => {
-                    response = Some(r);
+        // Simple turns start on the cheap model (see `model_router`)
+        let cheap = self
+            .router
+            .as_ref()
+            .filter(|router| {
+                is_first_step && router.route(user_message, input.is_first_time_user) == Tier::Cheap
+            })
+            .map(ModelRouter::cheap);
+        if let Some(cheap) = &cheap {
+            tracing::info!("Routing this turn to the cheap model {}", cheap.model);
+        }
+
+        // Streamed answers send messages as they complete. Until one is
+        // sent, a failed stream or cheap call falls back to the flagship;
+        // after that, to the DSRs call below, which is told what already
+        // went out (`retry_input`) and whose answer `merge_streamed` joins
+        // to those messages.
+        let mut streamed: Vec<String> = Vec::new();
+        if streaming::is_configured() {
+            let mut models = Vec::new();
+            if cheap.is_some() {
+                models.push(cheap.as_ref());
+            }
+            if message_stream.is_some() {
+                models.push(None);
+            }
+            for model in models {
+                (response, streamed) = self
+                    .stream_response(&input, message_stream.as_ref(), model)
+                    .await;
+                if response.is_some() || !streamed.is_empty() {
                     break;
                 }
-                Err(e) => {
-                    tracing::warn!(
-                        "LLM call failed (attempt {}/{}): {:?}",
-                        attempt,
-                        MAX_LLM_RETRIES,
-                        e
-                    );
+            }
+            if response.is_none() && !streamed.is_empty() {
+                tracing::warn!(
+                    "Stream failed after {} messages - continuing the step without streaming",
+                    streamed.len()
+                );
+            }
+        }
+
+        if response.is_none() {
+            let input = streaming::retry_input(&input, &streamed);
+            for attempt in 1..=MAX_LLM_RETRIES {
+                match predictor.call_with_meta(input.clone()).await {
+                    Ok(r) => {
+                        usage::record_chat(
+                            CallKind::Agent,
+                            r.lm_usage.prompt_tokens as i64,
+                            r.lm_usage.completion_tokens as i64,
+                        );
+                        response = Some(r.output);
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            "LLM call failed (attempt {}/{}): {:?}",
+                            attempt,
+                            MAX_LLM_RETRIES,
+                            e
+                        );
 
-                    // For parse errors, try correction instead of simple retry
-                    if let dspy_rs::PredictError::Parse {
-                        raw_response,
-                        source,
-                        ..
-                    } = &e
-                    {
-                        let error_message = format!("Parse error: {}", source);
-                        match self
-                            .attempt_correction(
-                                &input_content,
-                                &available_tools,
-                                raw_response,
-                                &error_message,
-                            )
-                            .await
+                        // For parse errors, try correction instead of simple retry
+                        if let dspy_rs::PredictError::Parse {
+                            raw_response,
+                            source,
+                            ..
+                        } = &e
                         {
-                            Ok(corrected) => {
-                                response = Some(corrected);
-                                break;
-                            }
-                            Err(correction_err) => {
-                                tracing::warn!(
-                                    "Correction failed (attempt {}/{}): {:?}",
-                                    attempt,
-                                    MAX_LLM_RETRIES,
-                                    correction_err
-                                );
+                            let error_message = format!("Parse error: {}", source);
+                            match self
+                                .attempt_correction(
+                                    &input_content,
+                                    &available_tools,
+                                    raw_response,
+                                    &error_message,
+                                )
+                                .await
+                            {
+                                Ok(corrected) => {
+                                    response = Some(corrected);
+                                    break;
+                                }
+                                Err(correction_err) => {
+                                    tracing::warn!(
+                                        "Correction failed (attempt {}/{}): {:?}",
+                                        attempt,
+                                        MAX_LLM_RETRIES,
+                                        correction_err
+                                    );
+                                }
                             }
                         }
-                    }
 
-                    last_error = Some(e);
+                        last_error = Some(e);
 
-                    // Add a small delay before retry (except on last attempt)
-                    if attempt < MAX_LLM_RETRIES {
-                        tracing::info!("Retrying LLM call in 1 second...");
-                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        // Add a small delay before retry (except on last attempt)
+                        if attempt < MAX_LLM_RETRIES {
+                            tracing::info!("Retrying LLM call in 1 second...");
+                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        }
                     }
                 }
             }
@@ -1050,36 +1841,28 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
             }
         };
 
+        let llm_duration_ms = llm_started.elapsed().as_millis() as u64;
+
         tracing::info!("=== LLM RESPONSE ===");
         tracing::info!("Messages (raw): {:?}", response.messages);
         tracing::info!("Tool calls: {:?}", response.tool_calls);
 
-        // Unwrap nested JSON arrays and collect non-empty messages
-        // Sometimes the LLM double-encodes: ["[\"msg1\", \"msg2\"]"] instead of ["msg1", "msg2"]
-        let messages: Vec<String> = response
-            .messages
-            .iter()
-            .flat_map(|m| {
-                let trimmed = m.trim();
-                // Check if this message is itself a JSON array
-                if trimmed.starts_with('[') && trimmed.ends_with(']') {
-                    // Try to parse as JSON array of strings
-                    if let Ok(inner_messages) = serde_json::from_str::<Vec<String>>(trimmed) {
-                        tracing::debug!(
-                            "Unwrapped nested JSON array with {} messages",
-                            inner_messages.len()
-                        );
-                        return inner_messages;
-                    }
-                }
-                // Not a nested array, return as-is
-                vec![m.clone()]
-            })
-            .filter(|m| !m.is_empty())
-            .collect();
+        let messages = flatten_messages(&response.messages);
+
+        // Sources cited from earlier steps' web results
+        let messages: Vec<String> = match self.sources {
+            Some(ref sources) => messages.iter().map(|m| sources.cite(m)).collect(),
+            None => messages,
+        };
+        let messages = streaming::merge_streamed(&streamed, messages);
 
         tracing::info!("Messages (processed): {:?}", messages);
 
+        self.turn_context = turn_context.map(|mut ctx| {
+            ctx.append_step(&presented_results, &messages);
+            ctx
+        });
+
         // Execute tools and collect results for storage
         let mut executed_tools = Vec::new();
 
@@ -1090,30 +1873,47 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
                 tool_call.args
             );
 
-            let result = if let Some(tool) = self.tools.get(&tool_call.name) {
-                match tool.execute(&tool_call.args).await {
-                    Ok(result) => {
-                        tracing::debug!("Tool {} result: {:?}", tool_call.name, result);
-                        result
-                    }
-                    Err(e) => {
-                        tracing::error!("Tool {} error: {}", tool_call.name, e);
-                        ToolResult::error(e.to_string())
-                    }
-                }
+            let tool_started = std::time::Instant::now();
+            let fingerprint = call_fingerprint(tool_call);
+            let repeatable = REPEATABLE_TOOLS.contains(&tool_call.name.as_str());
+            let result = if !repeatable && self.executed_calls.contains(&fingerprint) {
+                tracing::warn!("Refusing repeated tool call this turn: {}", tool_call.name);
+                ToolResult::success(ALREADY_EXECUTED)
             } else {
-                tracing::warn!("Unknown tool: {}", tool_call.name);
-                ToolResult::error(format!("Unknown tool: {}", tool_call.name))
+                let result = self.execute_tool(&tool_call.name, &tool_call.args).await;
+                // Failed calls may be retried as they were
+                if result.success && !repeatable {
+                    self.executed_calls.insert(fingerprint);
+                }
+                result
             };
+            let duration_ms = tool_started.elapsed().as_millis() as u64;
 
-            // Inject into current request cycle (for multi-step reasoning)
-            self.inject_tool_result(tool_call, &result);
+            // Inject into current request cycle (for multi-step reasoning),
+            // numbering any web sources so the agent can cite them
+            let note = match self.sources {
+                Some(ref mut sources)
+                    if result.success && SOURCE_TOOLS.contains(&tool_call.name.as_str()) =>
+                {
+                    sources.track(&result.output)
+                }
+                _ => None,
+            };
+            match note {
+                Some(note) => {
+                    let mut annotated = result.clone();
+                    annotated.output = format!("{}\n\n{}", result.output, note);
+                    self.inject_tool_result(tool_call, &annotated);
+                }
+                None => self.inject_tool_result(tool_call, &result),
+            }
 
             // Collect for storage (skip "done" tool - it's just a no-op signal)
             if tool_call.name != "done" {
                 executed_tools.push(ExecutedTool {
                     tool_call: tool_call.clone(),
                     result,
+                    duration_ms,
                 });
             }
         }
@@ -1133,10 +1933,36 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
             self.previous_step_summary = Some((messages.clone(), tool_names));
         }
 
+        let attachments = self
+            .outbox
+            .as_ref()
+            .and_then(|outbox| {
+                outbox
+                    .lock()
+                    .ok()
+                    .map(|mut queued| queued.drain(..).collect())
+            })
+            .unwrap_or_default();
+        let reactions = self
+            .reactions
+            .as_ref()
+            .and_then(|outbox| {
+                outbox
+                    .lock()
+                    .ok()
+                    .map(|mut queued| queued.drain(..).collect())
+            })
+            .unwrap_or_default();
+
         Ok(StepResult {
+            input: input_content,
+            llm_duration_ms,
             messages,
             tool_calls: response.tool_calls,
             executed_tools,
+            attachments,
+            reactions,
+            streamed: streamed.len(),
             done,
         })
     }
@@ -1170,6 +1996,87 @@ SELF-CHECK: Before ANY message, ask: "Is this new info the user hasn't seen?" If
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cacheable_prefix_grows_with_the_conversation() {
+        let mut ctx = AgentContext {
+            persona_block: "I am Sage".into(),
+            human_block: "Name: Ana".into(),
+            recent_conversation: "[user @ 10:00]: héllo\n".into(),
+            current_time: "10:00:01".into(),
+            ..Default::default()
+        };
+        let first = ctx.cacheable_prefix("tools");
+        ctx.recent_conversation
+            .push_str("[assistant @ 10:00]: hi\n");
+        ctx.current_time = "10:00:09".into();
+        let second = ctx.cacheable_prefix("tools");
+        assert_eq!(shared_prefix_len(&first, &second), first.len());
+
+        ctx.human_block = "Name: Ana, likes tea".into();
+        let third = ctx.cacheable_prefix("tools");
+        assert_eq!(
+            shared_prefix_len(&second, &third),
+            "I am Sage\ntools\nName: Ana".len()
+        );
+        assert_eq!(shared_prefix_len("é", "è"), 0);
+    }
+
+    #[test]
+    fn test_append_step_extends_the_conversation() {
+        let mut ctx = AgentContext {
+            recent_conversation: NO_CONVERSATION.to_string(),
+            ..Default::default()
+        };
+        ctx.append_step(&[], &["Looking that up".to_string()]);
+        assert_eq!(ctx.recent_conversation, "[assistant]: Looking that up\n");
+
+        let before = ctx.cacheable_prefix("tools");
+        let long = "x".repeat(MAX_TOOL_MESSAGE_CHARS + 10);
+        ctx.append_step(&[long], &[]);
+        assert!(ctx.cacheable_prefix("tools").starts_with(&before));
+        assert!(ctx.recent_conversation.ends_with(&format!(
+            "[tool]: {}...\n",
+            "x".repeat(MAX_TOOL_MESSAGE_CHARS)
+        )));
+
+        let unchanged = ctx.recent_conversation.clone();
+        ctx.append_step(&[], &[]);
+        assert_eq!(ctx.recent_conversation, unchanged);
+    }
+
+    #[test]
+    fn test_call_fingerprint() {
+        let call = |name: &str, args: &[(&str, &str)]| ToolCall {
+            name: name.to_string(),
+            args: args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        let insert = call(
+            "archival_insert",
+            &[("content", "likes tea"), ("tags", "food")],
+        );
+        assert_eq!(
+            call_fingerprint(&insert),
+            call_fingerprint(&call(
+                "archival_insert",
+                &[("tags", "food"), ("content", "likes tea")]
+            ))
+        );
+        assert_ne!(
+            call_fingerprint(&insert),
+            call_fingerprint(&call("archival_insert", &[("content", "likes tea")]))
+        );
+        assert_ne!(
+            call_fingerprint(&insert),
+            call_fingerprint(&call(
+                "memory_append",
+                &[("content", "likes tea"), ("tags", "food")]
+            ))
+        );
+    }
+
     #[test]
     fn test_tool_registry() {
         let registry = ToolRegistry::new();
@@ -1177,6 +2084,35 @@ mod tests {
         assert!(registry.tools.is_empty());
     }
 
+    #[test]
+    fn test_parse_concurrency_limits() {
+        let limits = ToolConcurrencyLimits::parse("shell=1, web_search=3,").unwrap();
+        assert_eq!(limits.get("shell"), Some(&1));
+        assert_eq!(limits.get("web_search"), Some(&3));
+        assert!(ToolConcurrencyLimits::parse("").unwrap().is_empty());
+        assert!(ToolConcurrencyLimits::parse("shell").is_err());
+        assert!(ToolConcurrencyLimits::parse("shell=0").is_err());
+        assert!(ToolConcurrencyLimits::parse("shell=many").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limits_acquire() {
+        let limits = ToolConcurrencyLimits::new(&ToolConcurrencyLimits::parse("shell=1").unwrap());
+        assert!(limits.acquire("web_search").await.is_none());
+
+        let permit = limits.acquire("shell").await.unwrap();
+        // Second shell execution has to wait for the first to finish
+        let waiting = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            limits.acquire("shell"),
+        )
+        .await;
+        assert!(waiting.is_err());
+
+        drop(permit);
+        assert!(limits.acquire("shell").await.is_some());
+    }
+
     #[test]
     fn test_tool_registry_description() {
         let registry = ToolRegistry::new();
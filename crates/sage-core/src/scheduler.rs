@@ -3,20 +3,22 @@
 //! Supports:
 //! - One-off scheduled messages or tool calls
 //! - Recurring tasks via cron expressions
+//! - The weekly self-maintenance routine (see `maintenance`)
+//! - Yearly birthday and anniversary check-ins (see `occasions`)
 //! - PostgreSQL-backed persistence
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
 use cron::Schedule;
-use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::db::DbConn;
 use crate::schema::scheduled_tasks;
 
 // ============================================================================
@@ -29,6 +31,12 @@ use crate::schema::scheduled_tasks;
 pub enum TaskType {
     Message,
     ToolCall,
+    /// Self-maintenance routine (created by Sage, not the schedule_task tool)
+    Maintenance,
+    /// Birthday or anniversary check-in (created from memory, see `occasions`)
+    Occasion,
+    /// Follow-up on a promise Sage made (see `commitments`)
+    Commitment,
 }
 
 impl TaskType {
@@ -36,6 +44,9 @@ impl TaskType {
         match self {
             TaskType::Message => "message",
             TaskType::ToolCall => "tool_call",
+            TaskType::Maintenance => "maintenance",
+            TaskType::Occasion => "occasion",
+            TaskType::Commitment => "commitment",
         }
     }
 }
@@ -47,6 +58,9 @@ impl FromStr for TaskType {
         match s {
             "message" => Ok(TaskType::Message),
             "tool_call" => Ok(TaskType::ToolCall),
+            "maintenance" => Ok(TaskType::Maintenance),
+            "occasion" => Ok(TaskType::Occasion),
+            "commitment" => Ok(TaskType::Commitment),
             _ => Err(anyhow::anyhow!(
                 "Invalid task type: {}. Must be 'message' or 'tool_call'",
                 s
@@ -106,16 +120,47 @@ pub struct ToolCallPayload {
     pub args: HashMap<String, String>,
 }
 
+/// Payload for a maintenance task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenancePayload {
+    pub routine: String,
+}
+
+/// Payload for a birthday or anniversary check-in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OccasionPayload {
+    /// Fact subject the date belongs to ("user", "sister_anna")
+    pub subject: String,
+    /// `birthday` or `anniversary`
+    pub occasion: String,
+    pub month: u32,
+    pub day: u32,
+    /// Year of birth or of the wedding, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+}
+
+/// Payload for a commitment follow-up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitmentPayload {
+    pub commitment_id: Uuid,
+    /// What Sage committed to do
+    pub description: String,
+}
+
 /// Union of possible payloads
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum TaskPayload {
     Message(MessagePayload),
     ToolCall(ToolCallPayload),
+    Maintenance(MaintenancePayload),
+    Occasion(OccasionPayload),
+    Commitment(CommitmentPayload),
 }
 
 /// A scheduled task
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 #[allow(dead_code)]
 pub struct ScheduledTask {
     pub id: Uuid,
@@ -198,21 +243,21 @@ impl TryFrom<ScheduledTaskRow> for ScheduledTask {
 // ============================================================================
 
 pub struct SchedulerDb {
-    conn: Arc<Mutex<PgConnection>>,
+    conn: Arc<DbConn>,
 }
 
 #[allow(dead_code)]
 impl SchedulerDb {
     /// Create a new SchedulerDb with a shared connection
-    pub fn new(conn: Arc<Mutex<PgConnection>>) -> Self {
+    pub fn new(conn: Arc<DbConn>) -> Self {
         Self { conn }
     }
 
     /// Create a new SchedulerDb with its own connection
     pub fn connect(db_url: &str) -> Result<Self> {
-        let conn = PgConnection::establish(db_url).context("Failed to connect to database")?;
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
         Ok(Self {
-            conn: Arc::new(Mutex::new(conn)),
+            conn: Arc::new(conn),
         })
     }
 
@@ -228,63 +273,69 @@ impl SchedulerDb {
         timezone: String,
         description: String,
     ) -> Result<ScheduledTask> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let id = Uuid::new_v4();
-        let payload_json = serde_json::to_value(&payload)?;
-
-        let new_task = NewScheduledTask {
-            id,
-            agent_id,
-            task_type: task_type.as_str().to_string(),
-            payload: payload_json,
-            next_run_at,
-            cron_expression: cron_expression.clone(),
-            timezone: timezone.clone(),
-            status: TaskStatus::Pending.as_str().to_string(),
-            description: description.clone(),
-        };
-
-        diesel::insert_into(scheduled_tasks::table)
-            .values(&new_task)
-            .execute(&mut *conn)
-            .context("Failed to insert scheduled task")?;
-
-        Ok(ScheduledTask {
-            id,
-            agent_id,
-            task_type,
-            payload,
-            next_run_at,
-            cron_expression,
-            timezone,
-            status: TaskStatus::Pending,
-            last_run_at: None,
-            run_count: 0,
-            last_error: None,
-            description,
-            created_at: Utc::now(),
+        self.conn.run(|conn| {
+            let id = Uuid::new_v4();
+            let payload_json = serde_json::to_value(&payload)?;
+
+            let new_task = NewScheduledTask {
+                id,
+                agent_id,
+                task_type: task_type.as_str().to_string(),
+                payload: payload_json,
+                next_run_at,
+                cron_expression: cron_expression.clone(),
+                timezone: timezone.clone(),
+                status: TaskStatus::Pending.as_str().to_string(),
+                description: description.clone(),
+            };
+
+            diesel::insert_into(scheduled_tasks::table)
+                .values(&new_task)
+                .execute(conn)
+                .context("Failed to insert scheduled task")?;
+
+            Ok(ScheduledTask {
+                id,
+                agent_id,
+                task_type,
+                payload,
+                next_run_at,
+                cron_expression,
+                timezone,
+                status: TaskStatus::Pending,
+                last_run_at: None,
+                run_count: 0,
+                last_error: None,
+                description,
+                created_at: Utc::now(),
+            })
         })
     }
 
     /// Get all due tasks (pending and next_run_at <= now)
     pub fn get_due_tasks(&self) -> Result<Vec<ScheduledTask>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let rows: Vec<ScheduledTaskRow> = scheduled_tasks::table
-            .filter(scheduled_tasks::status.eq("pending"))
-            .filter(scheduled_tasks::next_run_at.le(Utc::now()))
-            .order(scheduled_tasks::next_run_at.asc())
-            .load(&mut *conn)
-            .context("Failed to query due tasks")?;
-
-        rows.into_iter().map(ScheduledTask::try_from).collect()
+        self.conn.run(|conn| {
+            let rows: Vec<ScheduledTaskRow> = scheduled_tasks::table
+                .filter(scheduled_tasks::status.eq("pending"))
+                .filter(scheduled_tasks::next_run_at.le(Utc::now()))
+                .order(scheduled_tasks::next_run_at.asc())
+                .load(conn)
+                .context("Failed to query due tasks")?;
+
+            rows.into_iter().map(ScheduledTask::try_from).collect()
+        })
+    }
+
+    /// When the longest-waiting due task was due (None if nothing is due)
+    pub fn oldest_due_at(&self) -> Result<Option<DateTime<Utc>>> {
+        self.conn.run(|conn| {
+            scheduled_tasks::table
+                .filter(scheduled_tasks::status.eq("pending"))
+                .filter(scheduled_tasks::next_run_at.le(Utc::now()))
+                .select(diesel::dsl::min(scheduled_tasks::next_run_at))
+                .first(conn)
+                .context("Failed to query oldest due task")
+        })
     }
 
     /// Get tasks by agent and optional status filter
@@ -293,134 +344,113 @@ impl SchedulerDb {
         agent_id: Uuid,
         status_filter: Option<&str>,
     ) -> Result<Vec<ScheduledTask>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let mut query = scheduled_tasks::table
-            .filter(scheduled_tasks::agent_id.eq(agent_id))
-            .into_boxed();
+        self.conn.run(|conn| {
+            let mut query = scheduled_tasks::table
+                .filter(scheduled_tasks::agent_id.eq(agent_id))
+                .into_boxed();
 
-        if let Some(status) = status_filter {
-            query = query.filter(scheduled_tasks::status.eq(status));
-        }
+            if let Some(status) = status_filter {
+                query = query.filter(scheduled_tasks::status.eq(status));
+            }
 
-        let rows: Vec<ScheduledTaskRow> = query
-            .order(scheduled_tasks::next_run_at.asc())
-            .load(&mut *conn)
-            .context("Failed to query tasks")?;
+            let rows: Vec<ScheduledTaskRow> = query
+                .order(scheduled_tasks::next_run_at.asc())
+                .load(conn)
+                .context("Failed to query tasks")?;
 
-        rows.into_iter().map(ScheduledTask::try_from).collect()
+            rows.into_iter().map(ScheduledTask::try_from).collect()
+        })
     }
 
     /// Get a task by ID
     pub fn get_task(&self, task_id: Uuid) -> Result<Option<ScheduledTask>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let row: Option<ScheduledTaskRow> = scheduled_tasks::table
-            .filter(scheduled_tasks::id.eq(task_id))
-            .first(&mut *conn)
-            .optional()
-            .context("Failed to query task")?;
-
-        row.map(ScheduledTask::try_from).transpose()
+        self.conn.run(|conn| {
+            let row: Option<ScheduledTaskRow> = scheduled_tasks::table
+                .filter(scheduled_tasks::id.eq(task_id))
+                .first(conn)
+                .optional()
+                .context("Failed to query task")?;
+
+            row.map(ScheduledTask::try_from).transpose()
+        })
     }
 
     /// Mark a task as running
     pub fn mark_running(&self, task_id: Uuid) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
-            .set(scheduled_tasks::status.eq("running"))
-            .execute(&mut *conn)
-            .context("Failed to mark task as running")?;
+        self.conn.run(|conn| {
+            diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
+                .set(scheduled_tasks::status.eq("running"))
+                .execute(conn)
+                .context("Failed to mark task as running")?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Mark a task as completed (for one-off tasks)
     pub fn mark_completed(&self, task_id: Uuid) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
-            .set((
-                scheduled_tasks::status.eq("completed"),
-                scheduled_tasks::last_run_at.eq(Utc::now()),
-                scheduled_tasks::run_count.eq(scheduled_tasks::run_count + 1),
-            ))
-            .execute(&mut *conn)
-            .context("Failed to mark task as completed")?;
-
-        Ok(())
+        self.conn.run(|conn| {
+            diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
+                .set((
+                    scheduled_tasks::status.eq("completed"),
+                    scheduled_tasks::last_run_at.eq(Utc::now()),
+                    scheduled_tasks::run_count.eq(scheduled_tasks::run_count + 1),
+                ))
+                .execute(conn)
+                .context("Failed to mark task as completed")?;
+
+            Ok(())
+        })
     }
 
     /// Update a recurring task with next run time
     pub fn update_next_run(&self, task_id: Uuid, next_run_at: DateTime<Utc>) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
-            .set((
-                scheduled_tasks::status.eq("pending"),
-                scheduled_tasks::next_run_at.eq(next_run_at),
-                scheduled_tasks::last_run_at.eq(Utc::now()),
-                scheduled_tasks::run_count.eq(scheduled_tasks::run_count + 1),
-            ))
-            .execute(&mut *conn)
-            .context("Failed to update next run time")?;
-
-        Ok(())
+        self.conn.run(|conn| {
+            diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
+                .set((
+                    scheduled_tasks::status.eq("pending"),
+                    scheduled_tasks::next_run_at.eq(next_run_at),
+                    scheduled_tasks::last_run_at.eq(Utc::now()),
+                    scheduled_tasks::run_count.eq(scheduled_tasks::run_count + 1),
+                ))
+                .execute(conn)
+                .context("Failed to update next run time")?;
+
+            Ok(())
+        })
     }
 
     /// Mark a task as failed
     pub fn mark_failed(&self, task_id: Uuid, error: &str) -> Result<()> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
-            .set((
-                scheduled_tasks::status.eq("failed"),
-                scheduled_tasks::last_run_at.eq(Utc::now()),
-                scheduled_tasks::last_error.eq(error),
-                scheduled_tasks::run_count.eq(scheduled_tasks::run_count + 1),
-            ))
-            .execute(&mut *conn)
-            .context("Failed to mark task as failed")?;
-
-        Ok(())
+        self.conn.run(|conn| {
+            diesel::update(scheduled_tasks::table.filter(scheduled_tasks::id.eq(task_id)))
+                .set((
+                    scheduled_tasks::status.eq("failed"),
+                    scheduled_tasks::last_run_at.eq(Utc::now()),
+                    scheduled_tasks::last_error.eq(error),
+                    scheduled_tasks::run_count.eq(scheduled_tasks::run_count + 1),
+                ))
+                .execute(conn)
+                .context("Failed to mark task as failed")?;
+
+            Ok(())
+        })
     }
 
     /// Cancel a task
     pub fn cancel_task(&self, task_id: Uuid) -> Result<bool> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let updated = diesel::update(
-            scheduled_tasks::table
-                .filter(scheduled_tasks::id.eq(task_id))
-                .filter(scheduled_tasks::status.eq("pending")),
-        )
-        .set(scheduled_tasks::status.eq("cancelled"))
-        .execute(&mut *conn)
-        .context("Failed to cancel task")?;
-
-        Ok(updated > 0)
+        self.conn.run(|conn| {
+            let updated = diesel::update(
+                scheduled_tasks::table
+                    .filter(scheduled_tasks::id.eq(task_id))
+                    .filter(scheduled_tasks::status.eq("pending")),
+            )
+            .set(scheduled_tasks::status.eq("cancelled"))
+            .execute(conn)
+            .context("Failed to cancel task")?;
+
+            Ok(updated > 0)
+        })
     }
 }
 
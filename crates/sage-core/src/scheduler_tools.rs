@@ -4,38 +4,148 @@
 //! - schedule_task: Create a one-off or recurring scheduled task
 //! - list_schedules: List scheduled tasks
 //! - cancel_schedule: Cancel a pending scheduled task
+//!
+//! Wall-clock times (a datetime without offset, or a cron expression) are in
+//! the user's timezone. When neither the call nor the user's preferences give
+//! one, `schedule_task` schedules nothing and returns a structured
+//! `needs_timezone` result, so the agent asks instead of firing at the wrong
+//! hour in UTC.
 
 use anyhow::Result;
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::memory::{preference_keys, MemoryDb};
 use crate::sage_agent::{Tool, ToolResult};
 use crate::scheduler::{
-    is_cron_expression, next_cron_time, parse_cron, parse_datetime, MessagePayload, SchedulerDb,
-    TaskPayload, TaskType, ToolCallPayload,
+    is_cron_expression, next_cron_time, parse_cron, MessagePayload, SchedulerDb, TaskPayload,
+    TaskType, ToolCallPayload,
 };
 
+/// Wall-clock formats accepted for `run_at` (in the user's timezone)
+const LOCAL_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// When a task first runs, or that it depends on a timezone nobody gave
+#[derive(Debug, PartialEq)]
+enum RunAt {
+    /// Next run, and the cron expression for recurring tasks
+    At(DateTime<Utc>, Option<String>),
+    NeedsTimezone,
+}
+
+/// Work out the first run of `run_at`: an instant with an offset stands on
+/// its own, wall-clock times and cron expressions need `timezone`
+fn resolve_run_at(run_at: &str, timezone: Option<&str>) -> Result<RunAt> {
+    let run_at = run_at.trim();
+    if is_cron_expression(run_at) {
+        if let Err(e) = parse_cron(run_at) {
+            anyhow::bail!(
+                "Invalid cron expression: {}. Use standard cron format (e.g., '0 9 * * MON-FRI' for weekdays at 9am).",
+                e
+            );
+        }
+        let Some(timezone) = timezone else {
+            return Ok(RunAt::NeedsTimezone);
+        };
+        let next = next_cron_time(run_at, timezone)
+            .map_err(|e| anyhow::anyhow!("Failed to calculate next run time: {}", e))?;
+        return Ok(RunAt::At(next, Some(run_at.to_string())));
+    }
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(run_at) {
+        return Ok(RunAt::At(dt.with_timezone(&Utc), None));
+    }
+    let Some(naive) = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(run_at, format).ok())
+    else {
+        anyhow::bail!(
+            "Invalid datetime: '{}'. Use the user's local time (2026-01-26T15:30:00) or an ISO 8601 instant with offset (2026-01-26T15:30:00-06:00)",
+            run_at
+        );
+    };
+    let Some(timezone) = timezone else {
+        return Ok(RunAt::NeedsTimezone);
+    };
+    let tz: Tz = timezone.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Unknown timezone '{}'. Use an IANA name like 'America/Chicago'.",
+            timezone
+        )
+    })?;
+    // A time skipped by a DST change can't be scheduled; a repeated one
+    // resolves to the earlier instant
+    let local = tz
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist in {} (DST change)", run_at, timezone))?;
+    Ok(RunAt::At(local.with_timezone(&Utc), None))
+}
+
+/// What `schedule_task` returns when it can't tell which timezone a time is
+/// in: nothing is scheduled, and the agent is told to ask
+fn needs_timezone(run_at: &str) -> ToolResult {
+    ToolResult::error(
+        serde_json::json!({
+            "status": "needs_timezone",
+            "run_at": run_at,
+            "scheduled": false,
+            "ask_user": "Which timezone are you in? (a city is fine)",
+            "then": "Save the answer with set_preference (key 'timezone', IANA name like 'America/Chicago') and call schedule_task again. Don't assume UTC or guess.",
+        })
+        .to_string(),
+    )
+}
+
 // ============================================================================
 // Schedule Task Tool
 // ============================================================================
 
+/// `schedule_task` arguments (shared with the description-only registry)
+pub const SCHEDULE_TASK_ARGS: &str = r#"{"task_type": "message|tool_call", "description": "human-readable description", "run_at": "the user's local time as said (2026-01-26T15:30:00, no offset), an ISO instant with offset (2026-01-26T15:30:00Z) only if the user gave one, or cron (0 9 * * MON-FRI, local time)", "payload": "JSON: {\"message\": \"...\"} for message (may use {{date}}, {{time}}, {{weekday}}, {{user_name}}, {{weather}}, filled in when sent), {\"tool\": \"name\", \"args\": {...}} for tool_call", "timezone": "optional IANA timezone the user named for this (default: their timezone preference; if unknown the tool asks you to ask them)"}"#;
+
 pub struct ScheduleTaskTool {
     scheduler_db: Arc<SchedulerDb>,
     agent_id: Uuid,
-    default_timezone: String,
+    /// Preferences (read on each call, so a timezone set mid-conversation
+    /// counts) and the agent that owns them
+    memory_db: MemoryDb,
+    preference_agent_id: Uuid,
 }
 
 impl ScheduleTaskTool {
-    pub fn new(scheduler_db: Arc<SchedulerDb>, agent_id: Uuid, default_timezone: String) -> Self {
+    pub fn new(
+        scheduler_db: Arc<SchedulerDb>,
+        agent_id: Uuid,
+        memory_db: MemoryDb,
+        preference_agent_id: Uuid,
+    ) -> Self {
         Self {
             scheduler_db,
             agent_id,
-            default_timezone,
+            memory_db,
+            preference_agent_id,
         }
     }
+
+    /// The user's timezone preference, if set
+    fn preferred_timezone(&self) -> Option<String> {
+        self.memory_db
+            .preferences()
+            .get(self.preference_agent_id, preference_keys::TIMEZONE)
+            .ok()
+            .flatten()
+            .map(|p| p.value)
+    }
 }
 
 #[async_trait]
@@ -49,7 +159,7 @@ impl Tool for ScheduleTaskTool {
     }
 
     fn args_schema(&self) -> &str {
-        r#"{"task_type": "message|tool_call", "description": "human-readable description", "run_at": "ISO datetime (2026-01-26T15:30:00Z) or cron (0 9 * * MON-FRI)", "payload": "JSON: {\"message\": \"...\"} for message, {\"tool\": \"name\", \"args\": {...}} for tool_call", "timezone": "optional IANA timezone for cron (default: user preference or UTC)"}"#
+        SCHEDULE_TASK_ARGS
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
@@ -72,46 +182,25 @@ impl Tool for ScheduleTaskTool {
             anyhow::anyhow!("'run_at' argument required (ISO datetime or cron expression)")
         })?;
 
-        // Get timezone (from args, or use default)
+        // Timezone: named in the call, else the user's preference
         let timezone = args
             .get("timezone")
-            .cloned()
-            .unwrap_or_else(|| self.default_timezone.clone());
+            .map(|tz| tz.trim().to_string())
+            .filter(|tz| !tz.is_empty())
+            .or_else(|| self.preferred_timezone());
 
         // Determine if cron or one-off
-        let (next_run_at, cron_expression): (DateTime<Utc>, Option<String>) = if is_cron_expression(
-            run_at,
-        ) {
-            // Validate cron expression
-            if let Err(e) = parse_cron(run_at) {
-                return Ok(ToolResult::error(format!(
-                        "Invalid cron expression: {}. Use standard cron format (e.g., '0 9 * * MON-FRI' for weekdays at 9am).",
-                        e
-                    )));
-            }
-
-            // Calculate next run time
-            match next_cron_time(run_at, &timezone) {
-                Ok(next) => (next, Some(run_at.to_string())),
-                Err(e) => {
-                    return Ok(ToolResult::error(format!(
-                        "Failed to calculate next run time: {}",
-                        e
-                    )))
-                }
-            }
-        } else {
-            // Parse as datetime
-            match parse_datetime(run_at) {
-                Ok(dt) => {
-                    if dt <= Utc::now() {
-                        return Ok(ToolResult::error("Scheduled time must be in the future."));
-                    }
-                    (dt, None)
-                }
-                Err(e) => return Ok(ToolResult::error(format!("Invalid datetime: {}", e))),
-            }
-        };
+        let (next_run_at, cron_expression): (DateTime<Utc>, Option<String>) =
+            match resolve_run_at(run_at, timezone.as_deref()) {
+                Ok(RunAt::At(next, cron)) => (next, cron),
+                Ok(RunAt::NeedsTimezone) => return Ok(needs_timezone(run_at)),
+                Err(e) => return Ok(ToolResult::error(e.to_string())),
+            };
+        if cron_expression.is_none() && next_run_at <= Utc::now() {
+            return Ok(ToolResult::error("Scheduled time must be in the future."));
+        }
+        // Absolute instants don't depend on one; the task still records it
+        let timezone = timezone.unwrap_or_else(|| "UTC".to_string());
 
         // Parse payload
         let payload_str = args
@@ -178,6 +267,21 @@ impl Tool for ScheduleTaskTool {
                     }
                 }
             }
+            TaskType::Maintenance => {
+                return Ok(ToolResult::error(
+                    "Self-maintenance is scheduled automatically. Use task_type 'message' or 'tool_call'.",
+                ))
+            }
+            TaskType::Occasion => {
+                return Ok(ToolResult::error(
+                    "Birthday and anniversary check-ins are scheduled automatically from memory. Use task_type 'message' or 'tool_call'.",
+                ))
+            }
+            TaskType::Commitment => {
+                return Ok(ToolResult::error(
+                    "Follow-ups on your promises are scheduled automatically. Use task_type 'message' or 'tool_call'.",
+                ))
+            }
         };
 
         // Create the task
@@ -198,12 +302,13 @@ impl Tool for ScheduleTaskTool {
                 };
 
                 Ok(ToolResult::success(format!(
-                    "Scheduled {} {} task '{}' (id: {}). Next run: {}",
+                    "Scheduled {} {} task '{}' (id: {}). Next run: {} ({})",
                     schedule_type,
                     task_type.as_str(),
                     description,
                     task.id,
-                    next_run_at.format("%Y-%m-%d %H:%M:%S UTC")
+                    crate::itinerary::format_local(next_run_at, &timezone),
+                    timezone
                 )))
             }
             Err(e) => Ok(ToolResult::error(format!("Failed to create task: {}", e))),
@@ -340,3 +445,46 @@ impl Tool for CancelScheduleTool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_run_at() {
+        // Wall-clock times and cron need a timezone
+        assert_eq!(
+            resolve_run_at("2030-03-14T15:00:00", None).unwrap(),
+            RunAt::NeedsTimezone
+        );
+        assert_eq!(
+            resolve_run_at("0 0 9 * * MON-FRI", None).unwrap(),
+            RunAt::NeedsTimezone
+        );
+
+        // ...and are read in it
+        let expected = Utc.with_ymd_and_hms(2030, 3, 14, 20, 0, 0).unwrap();
+        assert_eq!(
+            resolve_run_at("2030-03-14 15:00", Some("America/Chicago")).unwrap(),
+            RunAt::At(expected, None)
+        );
+
+        // An explicit offset stands on its own
+        assert_eq!(
+            resolve_run_at("2030-03-14T15:00:00Z", None).unwrap(),
+            RunAt::At(Utc.with_ymd_and_hms(2030, 3, 14, 15, 0, 0).unwrap(), None)
+        );
+
+        assert!(resolve_run_at("next tuesday", Some("UTC")).is_err());
+        assert!(resolve_run_at("2030-03-14T15:00:00", Some("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn test_needs_timezone() {
+        let result = needs_timezone("2030-03-14T15:00:00");
+        assert!(!result.success);
+        let body: serde_json::Value = serde_json::from_str(&result.error.unwrap()).unwrap();
+        assert_eq!(body["status"], "needs_timezone");
+        assert_eq!(body["scheduled"], false);
+    }
+}
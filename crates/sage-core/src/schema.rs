@@ -1,6 +1,14 @@
 // @generated automatically by Diesel CLI.
 // Note: Some types manually adjusted for pgvector and UUID support
 
+diesel::table! {
+    active_topics (identifier) {
+        identifier -> Text,
+        topic -> Text,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use pgvector::sql_types::Vector;
@@ -19,6 +27,20 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    block_revisions (id) {
+        id -> Uuid,
+        agent_id -> Text,
+        label -> Varchar,
+        version -> Int4,
+        previous_value -> Text,
+        value -> Text,
+        source -> Text,
+        tool_args -> Nullable<Jsonb>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use pgvector::sql_types::Vector;
@@ -37,6 +59,108 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    commitments (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        description -> Text,
+        promise -> Text,
+        due_at -> Timestamptz,
+        task_id -> Nullable<Uuid>,
+        status -> Varchar,
+        resolved_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    embedding_jobs (message_id) {
+        message_id -> Uuid,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    facts (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        subject -> Text,
+        predicate -> Text,
+        object -> Text,
+        confidence -> Float4,
+        source_message_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        superseded_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    open_threads (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        content -> Text,
+        raised_at -> Timestamptz,
+        created_at -> Timestamptz,
+        resolved_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    message_delivery (id) {
+        id -> Uuid,
+        recipient -> Text,
+        sent_ms -> Int8,
+        delivered_at -> Nullable<Timestamptz>,
+        read_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    memory_sources (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        kind -> Text,
+        target -> Text,
+        excerpt -> Text,
+        message_ids -> Array<Uuid>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use pgvector::sql_types::Vector;
+
+    message_chunks (id) {
+        id -> Uuid,
+        message_id -> Uuid,
+        agent_id -> Uuid,
+        chunk_index -> Int4,
+        content -> Text,
+        embedding -> Vector,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    message_reactions (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        message_id -> Nullable<Uuid>,
+        target_ms -> Int8,
+        reactor -> Text,
+        emoji -> Text,
+        sentiment -> Int2,
+        message_chars -> Nullable<Int4>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use pgvector::sql_types::Vector;
@@ -48,11 +172,14 @@ diesel::table! {
         role -> Text,
         content -> Text,
         // embedding handled via raw SQL due to pgvector complexity
+        // search_tsv (generated tsvector) is only read by raw SQL
         sequence_id -> Int8,
         tool_calls -> Nullable<Jsonb>,
         tool_results -> Nullable<Jsonb>,
         created_at -> Timestamptz,
         attachment_text -> Nullable<Text>,
+        script -> Nullable<Text>,
+        pinned -> Bool,
     }
 }
 
@@ -67,6 +194,12 @@ diesel::table! {
         embedding -> Nullable<Vector>,
         tags -> Array<Text>,
         created_at -> Timestamptz,
+        // search_tsv (generated tsvector) is only read by raw SQL
+        importance -> Float4,
+        expires_at -> Nullable<Timestamptz>,
+        last_retrieved_at -> Nullable<Timestamptz>,
+        archived_at -> Nullable<Timestamptz>,
+        namespace -> Nullable<Text>,
     }
 }
 
@@ -83,6 +216,8 @@ diesel::table! {
         embedding -> Nullable<Vector>,
         previous_summary_id -> Nullable<Uuid>,
         created_at -> Timestamptz,
+        level -> Int4,
+        rolled_into -> Nullable<Uuid>,
     }
 }
 
@@ -132,15 +267,192 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+
+    expenses (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        merchant -> Text,
+        total_cents -> Int8,
+        currency -> Varchar,
+        purchased_on -> Date,
+        category -> Varchar,
+        line_items -> Jsonb,
+        source -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    export_keys (agent_id) {
+        agent_id -> Uuid,
+        salt -> Bytea,
+        key -> Bytea,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    held_messages (id) {
+        id -> Uuid,
+        recipient -> Text,
+        content -> Text,
+        violations -> Array<Text>,
+        status -> Varchar,
+        created_at -> Timestamptz,
+        reviewed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    inbox_messages (id) {
+        id -> Uuid,
+        source -> Text,
+        source_name -> Nullable<Text>,
+        message -> Text,
+        attachments -> Jsonb,
+        sent_at -> Int8,
+        reply_to -> Text,
+        reply_context -> Nullable<Text>,
+        status -> Varchar,
+        attempts -> Int4,
+        received_at -> Timestamptz,
+        quote -> Nullable<Jsonb>,
+        message_id -> Nullable<Text>,
+        mentions -> Jsonb,
+        edited -> Bool,
+    }
+}
+
+diesel::table! {
+    llm_usage (id) {
+        id -> Uuid,
+        agent_id -> Nullable<Uuid>,
+        kind -> Varchar,
+        model -> Text,
+        prompt_tokens -> Int8,
+        completion_tokens -> Int8,
+        cost_usd -> Float8,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    poll_votes (poll_id, voter) {
+        poll_id -> Uuid,
+        voter -> Text,
+        voter_name -> Nullable<Text>,
+        option_index -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    polls (id) {
+        id -> Uuid,
+        scope -> Text,
+        agent_id -> Uuid,
+        question -> Text,
+        options -> Array<Text>,
+        closes_at -> Timestamptz,
+        close_task_id -> Nullable<Uuid>,
+        status -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    todos (id) {
+        id -> Uuid,
+        scope -> Text,
+        title -> Text,
+        created_by -> Text,
+        assignee -> Nullable<Text>,
+        assignee_name -> Nullable<Text>,
+        due_at -> Nullable<Timestamptz>,
+        reminder_task_id -> Nullable<Uuid>,
+        status -> Varchar,
+        completed_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    travel_segments (id) {
+        id -> Uuid,
+        agent_id -> Uuid,
+        kind -> Varchar,
+        title -> Text,
+        confirmation -> Nullable<Text>,
+        origin -> Nullable<Text>,
+        destination -> Nullable<Text>,
+        starts_at -> Timestamptz,
+        starts_tz -> Text,
+        ends_at -> Nullable<Timestamptz>,
+        ends_tz -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    turn_events (id) {
+        id -> Uuid,
+        turn_id -> Uuid,
+        agent_id -> Uuid,
+        seq -> Int4,
+        step -> Nullable<Int4>,
+        kind -> Varchar,
+        content -> Text,
+        metadata -> Jsonb,
+        duration_ms -> Nullable<Int8>,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::joinable!(scheduled_tasks -> agents (agent_id));
+diesel::joinable!(expenses -> agents (agent_id));
+diesel::joinable!(export_keys -> agents (agent_id));
+diesel::joinable!(llm_usage -> agents (agent_id));
+diesel::joinable!(poll_votes -> polls (poll_id));
+diesel::joinable!(polls -> agents (agent_id));
+diesel::joinable!(commitments -> agents (agent_id));
+diesel::joinable!(todos -> scheduled_tasks (reminder_task_id));
+diesel::joinable!(travel_segments -> agents (agent_id));
+diesel::joinable!(turn_events -> agents (agent_id));
+diesel::joinable!(message_reactions -> messages (message_id));
+diesel::joinable!(message_chunks -> messages (message_id));
+diesel::joinable!(memory_sources -> agents (agent_id));
+diesel::joinable!(open_threads -> agents (agent_id));
+diesel::joinable!(embedding_jobs -> messages (message_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    active_topics,
     agents,
+    block_revisions,
     blocks,
     chat_contexts,
+    commitments,
+    embedding_jobs,
+    expenses,
+    export_keys,
+    facts,
+    held_messages,
+    inbox_messages,
+    llm_usage,
+    memory_sources,
+    message_chunks,
+    message_delivery,
+    message_reactions,
     messages,
+    open_threads,
     passages,
+    poll_votes,
+    polls,
     summaries,
     user_preferences,
     scheduled_tasks,
+    todos,
+    travel_segments,
+    turn_events,
 );
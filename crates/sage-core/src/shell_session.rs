@@ -0,0 +1,741 @@
+//! Persistent shell sessions with background jobs
+//!
+//! The plain `shell` tool runs one command to completion and is bounded by a
+//! timeout, which makes it unsuitable for long-running processes (Syncthing,
+//! dev servers, big downloads). This module gives the agent named sessions
+//! that outlive a single turn:
+//! - shell_session_start: run a command as a background job in a named session
+//! - shell_job_status: list sessions/jobs or show a job's recent output
+//! - shell_job_kill: stop a job, or a whole session
+//!
+//! Each job runs in its own session/process group (like the shell tool) with
+//! stdout and stderr redirected to a log file under the workspace, so output
+//! can be polled later and a job can never block on a full pipe. Killing a job
+//! sends SIGTERM to the whole group, followed by SIGKILL after a grace period.
+//!
+//! Session state (working directory and job table) lives in memory for the
+//! lifetime of the agent; it is not persisted across restarts.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::sage_agent::{Tool, ToolResult};
+use crate::shell_tool::{ShellConfig, ShellTool};
+
+/// Maximum number of concurrent sessions per agent
+const MAX_SESSIONS: usize = 8;
+
+/// Maximum number of running jobs per session
+const MAX_RUNNING_JOBS: usize = 8;
+
+/// Finished jobs kept per session before the oldest are forgotten
+const MAX_FINISHED_JOBS: usize = 20;
+
+/// Default seconds to wait for a new job before returning
+const DEFAULT_WAIT: u64 = 5;
+
+/// Maximum seconds shell_session_start will block waiting for a job
+const MAX_WAIT: u64 = 60;
+
+/// Default number of output bytes shown by shell_job_status
+const DEFAULT_TAIL: usize = 4_000;
+
+/// Maximum number of output bytes shown by shell_job_status
+const MAX_TAIL: usize = 20_000;
+
+/// Time between SIGTERM and SIGKILL when killing a job
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// Directory (inside the workspace) holding job logs
+const LOG_DIR: &str = ".shell_sessions";
+
+/// Lifecycle state of a background job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Exited(i32),
+    Killed,
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobState::Running => write!(f, "running"),
+            JobState::Exited(code) => write!(f, "exited with code {}", code),
+            JobState::Killed => write!(f, "killed"),
+        }
+    }
+}
+
+struct Job {
+    command: String,
+    started_at: DateTime<Utc>,
+    log_path: PathBuf,
+    pgid: Option<i32>,
+    state: Arc<Mutex<JobState>>,
+}
+
+impl Job {
+    fn state(&self) -> JobState {
+        self.state.lock().map(|s| *s).unwrap_or(JobState::Killed)
+    }
+
+    fn summary(&self, id: u32) -> String {
+        let elapsed = (Utc::now() - self.started_at).num_seconds().max(0) as u64;
+        format!(
+            "job {} [{}, started {} ago]: {}",
+            id,
+            self.state(),
+            format_elapsed(elapsed),
+            self.command
+        )
+    }
+}
+
+struct ShellSession {
+    cwd: PathBuf,
+    next_job_id: u32,
+    jobs: BTreeMap<u32, Job>,
+}
+
+impl ShellSession {
+    fn running_jobs(&self) -> usize {
+        self.jobs
+            .values()
+            .filter(|job| job.state() == JobState::Running)
+            .count()
+    }
+
+    /// Forget the oldest finished jobs beyond MAX_FINISHED_JOBS
+    fn prune_finished(&mut self) {
+        let finished: Vec<u32> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.state() != JobState::Running)
+            .map(|(id, _)| *id)
+            .collect();
+        if finished.len() > MAX_FINISHED_JOBS {
+            for id in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+                self.jobs.remove(id);
+            }
+        }
+    }
+}
+
+/// Per-agent registry of named shell sessions and their background jobs
+pub struct ShellSessionManager {
+    workspace: PathBuf,
+    sessions: Mutex<HashMap<String, ShellSession>>,
+}
+
+impl ShellSessionManager {
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        Self {
+            workspace: workspace.into(),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock_sessions(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, ShellSession>>> {
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire shell session lock"))
+    }
+
+    /// Start `command` as a background job in `session`, creating the session
+    /// (rooted at `cwd`, relative to the workspace) if it does not exist yet.
+    /// Returns the new job ID.
+    pub fn start_job(&self, session: &str, command: &str, cwd: Option<&str>) -> Result<u32> {
+        validate_session_name(session)?;
+
+        let mut sessions = self.lock_sessions()?;
+
+        if !sessions.contains_key(session) {
+            if sessions.len() >= MAX_SESSIONS {
+                anyhow::bail!(
+                    "Too many sessions ({} max). Kill an existing session first.",
+                    MAX_SESSIONS
+                );
+            }
+            let cwd = resolve_cwd(&self.workspace, cwd.unwrap_or(""))?;
+            std::fs::create_dir_all(&cwd)?;
+            sessions.insert(
+                session.to_string(),
+                ShellSession {
+                    cwd,
+                    next_job_id: 1,
+                    jobs: BTreeMap::new(),
+                },
+            );
+            info!("Created shell session '{}'", session);
+        } else if cwd.is_some() {
+            warn!(
+                "Ignoring cwd for existing shell session '{}' (kill it to change directory)",
+                session
+            );
+        }
+
+        let shell_session = sessions
+            .get_mut(session)
+            .ok_or_else(|| anyhow::anyhow!("Session '{}' not found", session))?;
+
+        if shell_session.running_jobs() >= MAX_RUNNING_JOBS {
+            anyhow::bail!(
+                "Session '{}' already has {} running jobs",
+                session,
+                MAX_RUNNING_JOBS
+            );
+        }
+
+        let job_id = shell_session.next_job_id;
+        let log_dir = self.workspace.join(LOG_DIR).join(session);
+        std::fs::create_dir_all(&log_dir)?;
+        let log_path = log_dir.join(format!("job-{}.log", job_id));
+        let log_file = std::fs::File::create(&log_path)?;
+
+        let mut cmd = Command::new("bash");
+        cmd.args(["-c", command])
+            .current_dir(&shell_session.cwd)
+            .env("HOME", &self.workspace)
+            .env("PWD", &shell_session.cwd)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::from(log_file.try_clone()?))
+            .stderr(std::process::Stdio::from(log_file));
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start job: {}", e))?;
+        let pgid = child.id().map(|pid| pid as i32);
+        let state = Arc::new(Mutex::new(JobState::Running));
+
+        // Reap the job leader in the background and record how it ended
+        let waiter_state = state.clone();
+        let session_name = session.to_string();
+        tokio::spawn(async move {
+            let code = match child.wait().await {
+                Ok(status) => status.code().unwrap_or(-1),
+                Err(e) => {
+                    warn!("Failed to wait on job {}: {}", job_id, e);
+                    -1
+                }
+            };
+            if let Ok(mut state) = waiter_state.lock() {
+                if *state == JobState::Running {
+                    *state = JobState::Exited(code);
+                }
+            }
+            info!(
+                "Shell session '{}' job {} finished (code {})",
+                session_name, job_id, code
+            );
+            if let Some(pgid) = pgid {
+                ShellTool::track_and_reap(pgid);
+            }
+        });
+
+        shell_session.jobs.insert(
+            job_id,
+            Job {
+                command: command.to_string(),
+                started_at: Utc::now(),
+                log_path,
+                pgid,
+                state,
+            },
+        );
+        shell_session.next_job_id += 1;
+        shell_session.prune_finished();
+
+        info!(
+            "Started job {} in shell session '{}': {}",
+            job_id, session, command
+        );
+
+        Ok(job_id)
+    }
+
+    /// Current state of a job
+    pub fn job_state(&self, session: &str, job_id: u32) -> Result<JobState> {
+        let sessions = self.lock_sessions()?;
+        let job = find_job(&sessions, session, job_id)?;
+        Ok(job.state())
+    }
+
+    /// One-line summary of a job followed by the tail of its output
+    pub fn job_report(&self, session: &str, job_id: u32, tail: usize) -> Result<String> {
+        let sessions = self.lock_sessions()?;
+        let job = find_job(&sessions, session, job_id)?;
+        let output = read_log_tail(&job.log_path, tail)?;
+
+        let mut report = format!("Session '{}' {}", session, job.summary(job_id));
+        if output.trim().is_empty() {
+            report.push_str("\n\n(no output yet)");
+        } else {
+            report.push_str(&format!("\n\nOUTPUT:\n{}", output.trim_end()));
+        }
+        Ok(report)
+    }
+
+    /// List sessions and their jobs (optionally a single session)
+    pub fn list(&self, session: Option<&str>) -> Result<String> {
+        let sessions = self.lock_sessions()?;
+
+        let mut names: Vec<&String> = match session {
+            Some(name) => {
+                if !sessions.contains_key(name) {
+                    anyhow::bail!("Session '{}' not found", name);
+                }
+                sessions.keys().filter(|k| k.as_str() == name).collect()
+            }
+            None => sessions.keys().collect(),
+        };
+        names.sort();
+
+        if names.is_empty() {
+            return Ok("No shell sessions.".to_string());
+        }
+
+        let mut lines = Vec::new();
+        for name in names {
+            let shell_session = &sessions[name];
+            let cwd = shell_session
+                .cwd
+                .strip_prefix(&self.workspace)
+                .unwrap_or(&shell_session.cwd);
+            lines.push(format!(
+                "Session '{}' (cwd: ./{}, {} running):",
+                name,
+                cwd.display(),
+                shell_session.running_jobs()
+            ));
+            if shell_session.jobs.is_empty() {
+                lines.push("  (no jobs)".to_string());
+            }
+            for (id, job) in &shell_session.jobs {
+                lines.push(format!("  {}", job.summary(*id)));
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    /// Kill a job's process group. Returns false if it had already finished
+    /// and nothing in its group was left running.
+    pub fn kill_job(&self, session: &str, job_id: u32) -> Result<bool> {
+        let sessions = self.lock_sessions()?;
+        let job = find_job(&sessions, session, job_id)?;
+        Ok(kill_job_group(job))
+    }
+
+    /// Kill every job in a session and forget the session.
+    /// Returns the number of jobs that were signalled.
+    pub fn kill_session(&self, session: &str) -> Result<usize> {
+        let mut sessions = self.lock_sessions()?;
+        let shell_session = sessions
+            .remove(session)
+            .ok_or_else(|| anyhow::anyhow!("Session '{}' not found", session))?;
+
+        let killed = shell_session
+            .jobs
+            .values()
+            .filter(|job| kill_job_group(job))
+            .count();
+        info!(
+            "Closed shell session '{}' ({} job(s) killed)",
+            session, killed
+        );
+        Ok(killed)
+    }
+}
+
+fn find_job<'a>(
+    sessions: &'a HashMap<String, ShellSession>,
+    session: &str,
+    job_id: u32,
+) -> Result<&'a Job> {
+    sessions
+        .get(session)
+        .ok_or_else(|| anyhow::anyhow!("Session '{}' not found", session))?
+        .jobs
+        .get(&job_id)
+        .ok_or_else(|| anyhow::anyhow!("Job {} not found in session '{}'", job_id, session))
+}
+
+/// SIGTERM the job's process group now and SIGKILL it after KILL_GRACE.
+///
+/// The whole group is signalled even if the leader already exited, since
+/// daemonizing commands leave their real work running in the group. The
+/// SIGKILL is skipped once the group holds none of our children: by then it
+/// may be gone and its pgid reused.
+fn kill_job_group(job: &Job) -> bool {
+    let Some(pgid) = job.pgid else {
+        return false;
+    };
+
+    if !ShellTool::signal_process_group(pgid, libc::SIGTERM) {
+        return false;
+    }
+
+    if let Ok(mut state) = job.state.lock() {
+        if *state == JobState::Running {
+            *state = JobState::Killed;
+        }
+    }
+
+    tokio::spawn(async move {
+        tokio::time::sleep(KILL_GRACE).await;
+        if ShellTool::owns_process_group(pgid) {
+            ShellTool::kill_process_group(pgid);
+        }
+        ShellTool::track_and_reap(pgid);
+    });
+
+    true
+}
+
+/// Session names become directory names, so keep them simple
+fn validate_session_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > 64 {
+        anyhow::bail!("Session name must be 1-64 characters");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!("Session name may only contain letters, digits, '-' and '_'");
+    }
+    Ok(())
+}
+
+/// Resolve a working directory relative to the workspace, refusing to escape it
+fn resolve_cwd(workspace: &Path, cwd: &str) -> Result<PathBuf> {
+    let relative = Path::new(cwd.trim());
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        anyhow::bail!("cwd must be a relative path inside the workspace");
+    }
+    Ok(workspace.join(relative))
+}
+
+/// Read at most `max_bytes` from the end of a log file
+fn read_log_tail(path: &Path, max_bytes: usize) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(max_bytes as u64);
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(tail_text(&bytes, start > 0))
+}
+
+/// Decode a log tail, dropping the partial first line if the read started
+/// mid-file
+fn tail_text(bytes: &[u8], truncated: bool) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    if !truncated {
+        return text.into_owned();
+    }
+    let rest = match text.find('\n') {
+        Some(pos) => &text[pos + 1..],
+        None => &text[..],
+    };
+    format!("[... earlier output omitted ...]\n{}", rest)
+}
+
+/// Human-readable elapsed time ("45s", "3m 12s", "2h 5m")
+fn format_elapsed(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+fn parse_job_id(args: &HashMap<String, String>) -> Result<Option<u32>> {
+    match args.get("job").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        Some(raw) => raw
+            .trim_start_matches('#')
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("Invalid job ID: {}", raw)),
+        None => Ok(None),
+    }
+}
+
+// ============================================================================
+// Shell Session Start Tool
+// ============================================================================
+
+pub struct ShellSessionStartTool {
+    manager: Arc<ShellSessionManager>,
+    config: ShellConfig,
+}
+
+impl ShellSessionStartTool {
+    pub fn new(manager: Arc<ShellSessionManager>) -> Self {
+        Self {
+            manager,
+            config: ShellConfig::default(),
+        }
+    }
+
+    /// Apply the configured shell options
+    pub fn with_config(mut self, config: ShellConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for ShellSessionStartTool {
+    fn name(&self) -> &str {
+        "shell_session_start"
+    }
+
+    fn description(&self) -> &str {
+        "Run a command as a background job in a named, persistent shell session (created on first use). Use for long-running processes (servers, sync daemons, big downloads) that must not block. Waits briefly and returns the output if the job finishes quickly; otherwise returns the job ID to poll with shell_job_status."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"session": "session name (letters, digits, - and _)", "command": "shell command to run as a background job", "cwd": "optional working directory relative to the workspace (only used when creating the session)", "wait": "optional seconds to wait for the job before returning (default 5, max 60)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let session = args
+            .get("session")
+            .ok_or_else(|| anyhow::anyhow!("'session' argument is required"))?
+            .trim();
+        let command = args
+            .get("command")
+            .ok_or_else(|| anyhow::anyhow!("'command' argument is required"))?;
+        let wait_secs: u64 = args
+            .get("wait")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_WAIT)
+            .min(MAX_WAIT);
+
+        if let Some(pattern) = ShellTool::is_blocked(command) {
+            warn!("Blocked dangerous command pattern: {}", pattern);
+            return Ok(ToolResult {
+                success: false,
+                output: format!("Command blocked: contains dangerous pattern '{}'", pattern),
+                error: Some("Security violation".to_string()),
+            });
+        }
+        if let Some(program) = self.config.disallowed_program(command) {
+            warn!("Blocked program not in SHELL_ALLOWED_BINARIES: {}", program);
+            return Ok(ToolResult::error(format!(
+                "Command blocked: '{}' is not an allowed program",
+                program
+            )));
+        }
+
+        let job_id =
+            match self
+                .manager
+                .start_job(session, command, args.get("cwd").map(|s| s.as_str()))
+            {
+                Ok(id) => id,
+                Err(e) => return Ok(ToolResult::error(e.to_string())),
+            };
+
+        // Give quick commands a chance to finish so they behave like `shell`
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(wait_secs);
+        let mut state = self.manager.job_state(session, job_id)?;
+        while state == JobState::Running && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            state = self.manager.job_state(session, job_id)?;
+        }
+
+        let report = self.manager.job_report(session, job_id, DEFAULT_TAIL)?;
+        Ok(match state {
+            JobState::Running => ToolResult::success(format!(
+                "{}\n\nJob is still running in the background. Use shell_job_status (session '{}', job {}) to check on it.",
+                report, session, job_id
+            )),
+            JobState::Exited(0) => ToolResult::success(report),
+            _ => ToolResult {
+                success: false,
+                output: report,
+                error: Some(format!("Job {} {}", job_id, state)),
+            },
+        })
+    }
+}
+
+// ============================================================================
+// Shell Job Status Tool
+// ============================================================================
+
+pub struct ShellJobStatusTool {
+    manager: Arc<ShellSessionManager>,
+}
+
+impl ShellJobStatusTool {
+    pub fn new(manager: Arc<ShellSessionManager>) -> Self {
+        Self { manager }
+    }
+}
+
+#[async_trait]
+impl Tool for ShellJobStatusTool {
+    fn name(&self) -> &str {
+        "shell_job_status"
+    }
+
+    fn description(&self) -> &str {
+        "Check background shell jobs. With session and job, shows the job's state and recent output. Without a job, lists sessions and their jobs."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"session": "optional session name", "job": "optional job ID (requires session)", "tail": "optional bytes of output to show (default 4000, max 20000)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let session = args
+            .get("session")
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty());
+        let job_id = parse_job_id(args)?;
+        let tail: usize = args
+            .get("tail")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TAIL)
+            .min(MAX_TAIL);
+
+        let result = match (session, job_id) {
+            (Some(session), Some(job_id)) => self.manager.job_report(session, job_id, tail),
+            (None, Some(_)) => Err(anyhow::anyhow!("'session' is required when 'job' is given")),
+            (session, None) => self.manager.list(session),
+        };
+
+        Ok(match result {
+            Ok(output) => ToolResult::success(output),
+            Err(e) => ToolResult::error(e.to_string()),
+        })
+    }
+}
+
+// ============================================================================
+// Shell Job Kill Tool
+// ============================================================================
+
+pub struct ShellJobKillTool {
+    manager: Arc<ShellSessionManager>,
+}
+
+impl ShellJobKillTool {
+    pub fn new(manager: Arc<ShellSessionManager>) -> Self {
+        Self { manager }
+    }
+}
+
+#[async_trait]
+impl Tool for ShellJobKillTool {
+    fn name(&self) -> &str {
+        "shell_job_kill"
+    }
+
+    fn description(&self) -> &str {
+        "Stop a background shell job (SIGTERM, then SIGKILL after a few seconds), including any processes it spawned. Omit the job to kill every job in the session and close it."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"session": "session name", "job": "optional job ID (omit to kill the whole session)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let session = args
+            .get("session")
+            .ok_or_else(|| anyhow::anyhow!("'session' argument is required"))?
+            .trim();
+
+        let result = match parse_job_id(args)? {
+            Some(job_id) => self.manager.kill_job(session, job_id).map(|killed| {
+                if killed {
+                    format!("Killed job {} in session '{}'", job_id, session)
+                } else {
+                    format!(
+                        "Job {} in session '{}' had already finished",
+                        job_id, session
+                    )
+                }
+            }),
+            None => self.manager.kill_session(session).map(|killed| {
+                format!(
+                    "Closed session '{}' ({} running job(s) killed)",
+                    session, killed
+                )
+            }),
+        };
+
+        Ok(match result {
+            Ok(output) => ToolResult::success(output),
+            Err(e) => ToolResult::error(e.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_session_name() {
+        assert!(validate_session_name("syncthing").is_ok());
+        assert!(validate_session_name("dev_server-2").is_ok());
+        assert!(validate_session_name("").is_err());
+        assert!(validate_session_name("../etc").is_err());
+        assert!(validate_session_name("a b").is_err());
+    }
+
+    #[test]
+    fn test_resolve_cwd_stays_in_workspace() {
+        let ws = Path::new("/workspace/agent");
+        assert_eq!(
+            resolve_cwd(ws, "").unwrap(),
+            PathBuf::from("/workspace/agent")
+        );
+        assert_eq!(
+            resolve_cwd(ws, "projects/app").unwrap(),
+            PathBuf::from("/workspace/agent/projects/app")
+        );
+        assert!(resolve_cwd(ws, "../other").is_err());
+        assert!(resolve_cwd(ws, "/etc").is_err());
+    }
+
+    #[test]
+    fn test_tail_text_drops_partial_line() {
+        assert_eq!(tail_text(b"one\ntwo\n", false), "one\ntwo\n");
+        assert_eq!(
+            tail_text(b"ne\ntwo\n", true),
+            "[... earlier output omitted ...]\ntwo\n"
+        );
+    }
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(45), "45s");
+        assert_eq!(format_elapsed(192), "3m 12s");
+        assert_eq!(format_elapsed(7500), "2h 5m");
+    }
+}
@@ -1,21 +1,35 @@
 //! Shell command execution tool
 //!
 //! Allows Sage to execute arbitrary shell commands within its container.
-//! Commands are run asynchronously with enforced timeouts. On timeout the
-//! entire process group is killed so that child/background processes cannot
-//! outlive the tool invocation and block the agent loop.
+//! Commands are run asynchronously with enforced timeouts. Each command is
+//! started in its own session (and therefore its own process group) so that
+//! on timeout the entire tree can be killed - child/background processes
+//! cannot outlive the tool invocation and block the agent loop.
 //!
 //! When a command is killed due to timeout, any partial stdout/stderr captured
 //! before the kill is included in the result so the agent can see what happened.
+//!
+//! Background processes that inherit the output pipes (e.g. `nohup daemon &`
+//! without redirection) would otherwise keep the pipes open forever after the
+//! shell exits. Output is drained with a short grace period; if the pipes are
+//! still held after that, the remaining process group is killed.
+//!
+//! Sage runs as PID 1 in its container, so orphaned grandchildren get
+//! reparented to us. Process groups we spawned are tracked and swept with
+//! `waitpid` after every command so killed orphans don't pile up as zombies.
 
 use anyhow::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
-use tokio::io::AsyncReadExt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::process::Command;
+use tokio::task::JoinHandle;
 use tracing::{debug, info, warn};
 
 use crate::sage_agent::{Tool, ToolResult};
+use crate::workspace_snapshot::{is_destructive, WorkspaceSnapshots};
 
 /// Dangerous command patterns that should be blocked
 const BLOCKED_PATTERNS: &[&str] = &[
@@ -42,20 +56,221 @@ const DEFAULT_TIMEOUT: u64 = 60;
 /// Maximum timeout in seconds (safety rail for clearly nonsensical values)
 const MAX_TIMEOUT: u64 = 86_400; // 24 hours
 
+/// How long to wait for stdout/stderr to reach EOF after the shell exits (or
+/// after the group is killed) before giving up on the pipes
+const PIPE_DRAIN_GRACE: Duration = Duration::from_secs(2);
+
+/// Process groups whose leader has already been reaped but which may still
+/// contain orphaned members waiting to be reaped by us.
+static ORPHAN_GROUPS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
+
+/// Captured output of a pipe, shared between the reader task and the tool
+type OutputBuffer = Arc<Mutex<Vec<u8>>>;
+
+/// Kills a command's process group if `run_command` is dropped before it
+/// finishes (e.g. the turn watchdog cancels the step). Without it the group
+/// would keep running with nothing left to reap it.
+struct GroupGuard {
+    pgid: Option<i32>,
+}
+
+impl GroupGuard {
+    /// The command finished and `run_command` cleaned up after it
+    fn disarm(&mut self) {
+        self.pgid = None;
+    }
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        if let Some(pgid) = self.pgid {
+            warn!("Shell command cancelled, killing process group {}", pgid);
+            ShellTool::kill_process_group(pgid);
+            ShellTool::track_and_reap(pgid);
+        }
+    }
+}
+
+/// Shell builtins that are always allowed
+const BUILTINS: &[&str] = &[
+    "cd", "echo", "export", "pwd", "true", "false", "test", "[", "[[", "set", "unset", "exit",
+    "read", "wait", "source", ".",
+];
+
+/// Words that start or continue a compound command; the program follows them
+const PREFIX_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "do", "while", "until", "!", "{", "time",
+];
+
+/// Words that end a compound command or introduce a loop's word list
+const SKIP_KEYWORDS: &[&str] = &["fi", "done", "esac", "}", "for", "case", "select"];
+
+/// Shell tool options (`SHELL_ALLOWED_BINARIES`)
+#[derive(Debug, Clone, Default)]
+pub struct ShellConfig {
+    /// Programs commands may run, by file name (empty = any)
+    pub allowed_binaries: Vec<String>,
+}
+
+impl ShellConfig {
+    /// The first program in the command that isn't allowed. This guards
+    /// against the agent reaching for tools it shouldn't; it reads the command
+    /// line, not what a program does, so it is not a sandbox (an allowed
+    /// interpreter can still run anything).
+    pub fn disallowed_program(&self, command: &str) -> Option<String> {
+        if self.allowed_binaries.is_empty() {
+            return None;
+        }
+        programs(command).into_iter().find(|program| {
+            !BUILTINS.contains(&program.as_str())
+                && !self.allowed_binaries.iter().any(|b| b == program)
+        })
+    }
+}
+
+/// File names of the programs a command line runs: the first word of each
+/// pipeline stage, list element and command substitution
+fn programs(command: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let chars: Vec<char> = command.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| chars[j]);
+        let next = chars.get(i + 1).copied();
+        let separator = match (quote, c) {
+            (Some('\''), '\'') => {
+                quote = None;
+                false
+            }
+            (Some('\''), _) => false,
+            (Some('"'), '"') => {
+                quote = None;
+                false
+            }
+            // Command substitution inside double quotes still runs
+            (Some('"'), '`') => true,
+            (Some('"'), '$') => next == Some('('),
+            (Some('"'), '(') => prev == Some('$'),
+            (Some('"'), ')') => true,
+            (Some(_), _) => false,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                false
+            }
+            (None, '|' | ';' | '\n' | '(' | ')' | '`') => true,
+            (None, '$') => next == Some('('),
+            // `2>&1` and `&>` are redirections, not background jobs
+            (None, '&') => !matches!(prev, Some('>' | '<')) && next != Some('>'),
+            _ => false,
+        };
+        if separator {
+            segments.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    segments.push(current);
+
+    segments
+        .iter()
+        .filter_map(|segment| first_program(segment))
+        .collect()
+}
+
+/// The program a simple command runs, skipping variable assignments,
+/// redirections and shell keywords
+fn first_program(segment: &str) -> Option<String> {
+    let mut redirect_target = false;
+    for word in segment.split_whitespace() {
+        let word = word.trim_matches(|c: char| c == '"' || c == '\'');
+        if std::mem::take(&mut redirect_target)
+            || word.is_empty()
+            || PREFIX_KEYWORDS.contains(&word)
+        {
+            continue;
+        }
+        if SKIP_KEYWORDS.contains(&word) {
+            return None;
+        }
+        let is_assignment = word.split_once('=').is_some_and(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        });
+        let operator = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '&');
+        if operator.starts_with(['<', '>']) {
+            // `> file` names its target in the next word
+            redirect_target = operator.chars().all(|c| matches!(c, '<' | '>' | '&'));
+            continue;
+        }
+        // Arithmetic like `$((1 + 2))` leaves bare numbers and operators
+        if is_assignment
+            || !word
+                .chars()
+                .any(|c| c.is_alphabetic() || c == '[' || c == '.')
+        {
+            continue;
+        }
+        return Some(word.rsplit('/').next().unwrap_or(word).to_string());
+    }
+    None
+}
+
 /// Shell command execution tool
 pub struct ShellTool {
     workspace: String,
+    snapshots: Option<Arc<WorkspaceSnapshots>>,
+    config: ShellConfig,
 }
 
 impl ShellTool {
     pub fn new(workspace: impl Into<String>) -> Self {
         Self {
             workspace: workspace.into(),
+            snapshots: None,
+            config: ShellConfig::default(),
+        }
+    }
+
+    /// Apply the configured shell options
+    pub fn with_config(mut self, config: ShellConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Snapshot the workspace before destructive commands
+    pub fn with_snapshots(mut self, snapshots: Arc<WorkspaceSnapshots>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    /// Take a workspace snapshot if the command looks destructive. Returns a
+    /// note for the tool output naming the snapshot.
+    async fn snapshot_before(&self, command: &str) -> Option<String> {
+        let snapshots = self.snapshots.clone()?;
+        if !is_destructive(command) {
+            return None;
+        }
+
+        let reason = command.to_string();
+        match tokio::task::spawn_blocking(move || snapshots.take(&reason)).await {
+            Ok(Ok(Some(snapshot))) => Some(format!(
+                "\n\n[Workspace snapshot {} was saved before this command; workspace_rollback can undo it]",
+                snapshot.id
+            )),
+            Ok(Ok(None)) => None,
+            Ok(Err(e)) => {
+                warn!("Workspace snapshot failed: {}", e);
+                None
+            }
+            Err(e) => {
+                warn!("Workspace snapshot task failed: {}", e);
+                None
+            }
         }
     }
 
     /// Check if a command contains blocked patterns
-    fn is_blocked(&self, command: &str) -> Option<&'static str> {
+    pub(crate) fn is_blocked(command: &str) -> Option<&'static str> {
         let lower = command.to_lowercase();
         BLOCKED_PATTERNS
             .iter()
@@ -63,30 +278,127 @@ impl ShellTool {
             .copied()
     }
 
-    /// Read all available bytes from an optional pipe handle.
-    /// Returns the content as a String (lossy UTF-8).
-    async fn drain_pipe(pipe: &mut Option<tokio::process::ChildStdout>) -> String {
-        // This generic approach won't work for ChildStderr directly, so we
-        // have a separate overload below. Rust doesn't support trait-object
-        // generics ergonomically here, so we just duplicate for the two types.
-        if let Some(ref mut handle) = pipe {
-            let mut buf = Vec::new();
-            let _ = handle.read_to_end(&mut buf).await;
-            String::from_utf8_lossy(&buf).into_owned()
-        } else {
-            String::new()
-        }
+    /// Continuously read a pipe into a shared buffer until EOF.
+    ///
+    /// Reading concurrently with `child.wait()` means a chatty command can
+    /// never block on a full pipe, and partial output is available on timeout.
+    /// Bytes beyond MAX_OUTPUT_SIZE are read and discarded.
+    fn spawn_pipe_reader<R>(pipe: Option<R>) -> (OutputBuffer, JoinHandle<()>)
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let buffer: OutputBuffer = Arc::new(Mutex::new(Vec::new()));
+        let sink = buffer.clone();
+
+        let handle = tokio::spawn(async move {
+            let Some(mut pipe) = pipe else {
+                return;
+            };
+            let mut chunk = [0u8; 8192];
+            loop {
+                match pipe.read(&mut chunk).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if let Ok(mut buf) = sink.lock() {
+                            let room = MAX_OUTPUT_SIZE.saturating_sub(buf.len());
+                            buf.extend_from_slice(&chunk[..n.min(room)]);
+                        }
+                    }
+                }
+            }
+        });
+
+        (buffer, handle)
     }
 
-    /// Read all available bytes from an optional stderr pipe handle.
-    async fn drain_stderr(pipe: &mut Option<tokio::process::ChildStderr>) -> String {
-        if let Some(ref mut handle) = pipe {
-            let mut buf = Vec::new();
-            let _ = handle.read_to_end(&mut buf).await;
-            String::from_utf8_lossy(&buf).into_owned()
-        } else {
-            String::new()
+    /// Wait for both pipe readers to hit EOF, up to PIPE_DRAIN_GRACE.
+    /// Returns false if a pipe is still held open by some process.
+    async fn wait_for_pipes(readers: &mut [&mut JoinHandle<()>]) -> bool {
+        tokio::time::timeout(PIPE_DRAIN_GRACE, async {
+            for reader in readers.iter_mut() {
+                // A JoinHandle must not be polled again once it has completed
+                if !reader.is_finished() {
+                    let _ = (&mut **reader).await;
+                }
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    /// Take the captured output of a pipe as a String (lossy UTF-8)
+    fn take_output(buffer: &OutputBuffer) -> String {
+        buffer
+            .lock()
+            .map(|buf| String::from_utf8_lossy(&buf).into_owned())
+            .unwrap_or_default()
+    }
+
+    /// SIGKILL every process in a process group (negative pid)
+    pub(crate) fn kill_process_group(pgid: i32) {
+        Self::signal_process_group(pgid, libc::SIGKILL);
+    }
+
+    /// Send a signal to every process in a process group.
+    /// Returns false if the group no longer exists.
+    pub(crate) fn signal_process_group(pgid: i32, signal: libc::c_int) -> bool {
+        unsafe { libc::kill(-pgid, signal) == 0 }
+    }
+
+    /// Remember a process group whose leader has been reaped so that any
+    /// orphaned members get reaped later, then sweep all tracked groups.
+    pub(crate) fn track_and_reap(pgid: i32) {
+        if let Ok(mut groups) = ORPHAN_GROUPS.lock() {
+            if !groups.contains(&pgid) {
+                groups.push(pgid);
+            }
         }
+        Self::reap_orphans();
+    }
+
+    /// Whether the group holds children of ours, running or exited but not
+    /// yet reaped. While it does, its pgid can't be handed to a new group.
+    pub(crate) fn owns_process_group(pgid: i32) -> bool {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let options = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+        unsafe { libc::waitid(libc::P_PGID, pgid as libc::id_t, &mut info, options) == 0 }
+    }
+
+    /// Reap zombies in tracked process groups and forget groups that hold
+    /// none of our children.
+    ///
+    /// Only groups whose leader was already reaped by tokio are tracked, and
+    /// a group is forgotten as soon as `waitpid` finds no children of ours in
+    /// it (ECHILD). Until then those children pin the pgid, so it can't be
+    /// reused by a process tokio is waiting on.
+    fn reap_orphans() {
+        let Ok(mut groups) = ORPHAN_GROUPS.lock() else {
+            return;
+        };
+
+        groups.retain(|&pgid| {
+            // kill(.., 0) fails with ESRCH once nothing is left in the group
+            if !Self::signal_process_group(pgid, 0) {
+                return false;
+            }
+
+            let mut reaped = 0;
+            let running = loop {
+                let mut status: libc::c_int = 0;
+                let pid = unsafe { libc::waitpid(-pgid, &mut status, libc::WNOHANG) };
+                if pid > 0 {
+                    reaped += 1;
+                    continue;
+                }
+                // 0: children of ours in the group are still running;
+                // -1 (ECHILD): none are left to reap
+                break pid == 0;
+            };
+            if reaped > 0 {
+                debug!("Reaped {} orphaned process(es) from group {}", reaped, pgid);
+            }
+            running
+        });
     }
 
     /// Build the standard output string from stdout, stderr, and exit code.
@@ -157,7 +469,7 @@ impl Tool for ShellTool {
         );
 
         // Check for blocked patterns
-        if let Some(pattern) = self.is_blocked(command) {
+        if let Some(pattern) = Self::is_blocked(command) {
             warn!("Blocked dangerous command pattern: {}", pattern);
             return Ok(ToolResult {
                 success: false,
@@ -165,22 +477,52 @@ impl Tool for ShellTool {
                 error: Some("Security violation".to_string()),
             });
         }
+        if let Some(program) = self.config.disallowed_program(command) {
+            warn!("Blocked program not in SHELL_ALLOWED_BINARIES: {}", program);
+            return Ok(ToolResult::error(format!(
+                "Command blocked: '{}' is not an allowed program",
+                program
+            )));
+        }
+
+        // Snapshot first so a botched command can be undone with workspace_rollback
+        let snapshot_note = self.snapshot_before(command).await;
+
+        let mut result = self.run_command(command, timeout_secs).await?;
+        if let Some(note) = snapshot_note {
+            result.output.push_str(&note);
+        }
+        Ok(result)
+    }
+}
 
+impl ShellTool {
+    /// Run the command in the workspace with the given timeout
+    async fn run_command(&self, command: &str, timeout_secs: u64) -> Result<ToolResult> {
         // Ensure workspace exists
         std::fs::create_dir_all(&self.workspace).ok();
 
-        // Spawn command in a new process group so we can kill the entire tree
-        // (including any child/background processes) on timeout.
-        let mut child = match Command::new("bash")
-            .args(["-c", command])
+        // Spawn command in a new session (which also makes it the leader of a
+        // new process group) so we can kill the entire tree on timeout and it
+        // can never grab a controlling terminal.
+        let mut cmd = Command::new("bash");
+        cmd.args(["-c", command])
             .current_dir(&self.workspace)
             .env("HOME", &self.workspace)
             .env("PWD", &self.workspace)
-            .process_group(0)
+            .stdin(std::process::Stdio::null())
             .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()
-        {
+            .stderr(std::process::Stdio::piped());
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = match cmd.spawn() {
             Ok(child) => child,
             Err(e) => {
                 return Ok(ToolResult {
@@ -191,43 +533,33 @@ impl Tool for ShellTool {
             }
         };
 
-        let timeout_duration = std::time::Duration::from_secs(timeout_secs);
-
-        // Take ownership of the pipe handles so we can read partial output on
-        // timeout. child.wait() only waits for exit -- it does not consume the
-        // pipes, unlike child.wait_with_output().
-        let mut child_stdout = child.stdout.take();
-        let mut child_stderr = child.stderr.take();
-        // Note: child_stdout is Option<ChildStdout>, child_stderr is Option<ChildStderr>.
-        // We use separate drain helpers because they are different types.
-        let child_pid = child.id();
-
-        match tokio::time::timeout(timeout_duration, child.wait()).await {
-            Ok(Ok(status)) => {
-                // Command finished within the timeout -- drain remaining output.
-                let stdout = Self::drain_pipe(&mut child_stdout).await;
-                let stderr = Self::drain_stderr(&mut child_stderr).await;
-                let exit_code = status.code().unwrap_or(-1);
+        let timeout_duration = Duration::from_secs(timeout_secs);
 
-                let output_str = self.format_output(&stdout, &stderr, exit_code);
+        // The session leader's pid doubles as the process group id
+        let pgid = child.id().map(|pid| pid as i32);
+        let mut guard = GroupGuard { pgid };
 
-                debug!("Shell command completed with exit code {}", exit_code);
+        // Read both pipes concurrently with the wait. child.wait() only waits
+        // for exit -- it does not consume the pipes, unlike wait_with_output().
+        let (stdout_buf, mut stdout_reader) = Self::spawn_pipe_reader(child.stdout.take());
+        let (stderr_buf, mut stderr_reader) = Self::spawn_pipe_reader(child.stderr.take());
 
-                Ok(ToolResult {
-                    success: status.success(),
-                    output: output_str,
-                    error: if status.success() {
-                        None
-                    } else {
-                        Some(format!("Command exited with code {}", exit_code))
-                    },
-                })
+        let wait_result = tokio::time::timeout(timeout_duration, child.wait()).await;
+
+        let status = match wait_result {
+            Ok(Ok(status)) => Some(status),
+            Ok(Err(e)) => {
+                if let Some(pgid) = pgid {
+                    Self::kill_process_group(pgid);
+                }
+                stdout_reader.abort();
+                stderr_reader.abort();
+                return Ok(ToolResult {
+                    success: false,
+                    output: String::new(),
+                    error: Some(format!("Failed to wait on command: {}", e)),
+                });
             }
-            Ok(Err(e)) => Ok(ToolResult {
-                success: false,
-                output: String::new(),
-                error: Some(format!("Failed to wait on command: {}", e)),
-            }),
             Err(_) => {
                 // Timeout -- kill the entire process group first, then drain
                 // whatever partial output was written before the kill.
@@ -235,22 +567,70 @@ impl Tool for ShellTool {
                     "Shell command timed out after {}s, killing process group: {}",
                     timeout_secs, command
                 );
-
-                if let Some(pid) = child_pid {
-                    let pgid = pid as i32;
-                    // SIGKILL the entire process group (negative pid)
-                    unsafe {
-                        libc::kill(-pgid, libc::SIGKILL);
-                    }
+                if let Some(pgid) = pgid {
+                    Self::kill_process_group(pgid);
                 }
 
-                // Reap the zombie so we don't leak it.
+                // Reap the leader so we don't leak it.
                 let _ = child.wait().await;
+                None
+            }
+        };
+
+        // Drain remaining output. If the shell exited but something in its
+        // group still holds the pipes open, kill the group so we don't hang.
+        let mut killed_lingering = false;
+        if !Self::wait_for_pipes(&mut [&mut stdout_reader, &mut stderr_reader]).await {
+            if status.is_some() {
+                warn!(
+                    "Background processes still hold the output pipes of '{}'; killing process group",
+                    command
+                );
+                killed_lingering = true;
+            }
+            if let Some(pgid) = pgid {
+                Self::kill_process_group(pgid);
+            }
+            if !Self::wait_for_pipes(&mut [&mut stdout_reader, &mut stderr_reader]).await {
+                // Something escaped the group (e.g. called setsid itself)
+                warn!("Output pipes still open after killing process group; abandoning them");
+                stdout_reader.abort();
+                stderr_reader.abort();
+            }
+        }
 
-                // Drain whatever was buffered in the pipes before the kill.
-                let stdout = Self::drain_pipe(&mut child_stdout).await;
-                let stderr = Self::drain_stderr(&mut child_stderr).await;
+        if let Some(pgid) = pgid {
+            Self::track_and_reap(pgid);
+        }
+        guard.disarm();
 
+        let stdout = Self::take_output(&stdout_buf);
+        let stderr = Self::take_output(&stderr_buf);
+
+        match status {
+            Some(status) => {
+                let exit_code = status.code().unwrap_or(-1);
+                let mut output_str = self.format_output(&stdout, &stderr, exit_code);
+                if killed_lingering {
+                    output_str.push_str(
+                        "\n\n[Background processes still holding the command's output were killed. \
+                         Redirect their output (e.g. `cmd > log 2>&1 &`) to keep them running.]",
+                    );
+                }
+
+                debug!("Shell command completed with exit code {}", exit_code);
+
+                Ok(ToolResult {
+                    success: status.success(),
+                    output: output_str,
+                    error: if status.success() {
+                        None
+                    } else {
+                        Some(format!("Command exited with code {}", exit_code))
+                    },
+                })
+            }
+            None => {
                 let mut result_parts = Vec::new();
 
                 if !stdout.is_empty() {
@@ -276,3 +656,144 @@ impl Tool for ShellTool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disallowed_program() {
+        let config = ShellConfig {
+            allowed_binaries: vec!["git".into(), "ls".into(), "grep".into()],
+        };
+        assert_eq!(config.disallowed_program("ls -la | grep foo"), None);
+        assert_eq!(
+            config.disallowed_program("cd repo && GIT_PAGER=cat /usr/bin/git log 2>&1 > out.txt"),
+            None
+        );
+        assert_eq!(
+            config.disallowed_program("echo 'a; curl x' \"$((1 + 2))\""),
+            None
+        );
+        assert_eq!(
+            config.disallowed_program("for f in *.md; do grep -l todo $f; done"),
+            None
+        );
+        assert_eq!(
+            config.disallowed_program("ls; curl https://example.com"),
+            Some("curl".into())
+        );
+        assert_eq!(
+            config.disallowed_program("echo \"$(wget -qO- x)\""),
+            Some("wget".into())
+        );
+        assert_eq!(
+            config.disallowed_program("ls `rm -rf out`"),
+            Some("rm".into())
+        );
+
+        // No list: anything goes
+        assert_eq!(ShellConfig::default().disallowed_program("curl x"), None);
+    }
+
+    fn args(command: &str, timeout: &str) -> HashMap<String, String> {
+        HashMap::from([
+            ("command".to_string(), command.to_string()),
+            ("timeout".to_string(), timeout.to_string()),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_background_children() {
+        let workspace = std::env::temp_dir().join("sage-shell-test-timeout");
+        let tool = ShellTool::new(workspace.to_string_lossy());
+
+        let started = std::time::Instant::now();
+        let result = tool
+            .execute(&args("sleep 30 & echo started; wait", "1"))
+            .await
+            .unwrap();
+
+        assert!(!result.success);
+        assert!(result.output.contains("started"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_lingering_child_holding_pipes_does_not_hang() {
+        let workspace = std::env::temp_dir().join("sage-shell-test-lingering");
+        let tool = ShellTool::new(workspace.to_string_lossy());
+
+        let started = std::time::Instant::now();
+        let result = tool
+            .execute(&args("nohup sleep 30 & echo hi", "20"))
+            .await
+            .unwrap();
+
+        assert!(result.output.contains("hi"));
+        assert!(result.output.contains("were killed"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    /// Processes in the group that haven't exited (zombies don't count)
+    fn live_members(pgid: i32) -> usize {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| std::fs::read_to_string(entry.path().join("stat")).ok())
+            .filter(|stat| {
+                // pid (comm) state ppid pgrp ...; comm may contain spaces
+                let Some((_, rest)) = stat.rsplit_once(") ") else {
+                    return false;
+                };
+                let fields: Vec<&str> = rest.split_whitespace().collect();
+                fields.len() > 2 && fields[0] != "Z" && fields[2] == pgid.to_string()
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_dropped_command_kills_its_group() {
+        let workspace = std::env::temp_dir().join("sage-shell-test-dropped");
+        let tool = ShellTool::new(workspace.to_string_lossy());
+        let pgid_file = workspace.join("pgid");
+        let _ = std::fs::remove_file(&pgid_file);
+
+        // Cancel the command the way the turn watchdog does: drop its future
+        let run = tool.run_command("echo $$ > pgid; sleep 30", 60);
+        assert!(tokio::time::timeout(Duration::from_secs(1), run)
+            .await
+            .is_err());
+
+        let pgid: i32 = std::fs::read_to_string(&pgid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let started = std::time::Instant::now();
+        while live_members(pgid) > 0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "process group {} still running",
+                pgid
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redirected_background_child_is_left_running() {
+        let workspace = std::env::temp_dir().join("sage-shell-test-redirected");
+        let tool = ShellTool::new(workspace.to_string_lossy());
+
+        let result = tool
+            .execute(&args("sleep 1 > /dev/null 2>&1 & echo ok", "20"))
+            .await
+            .unwrap();
+
+        assert!(result.success);
+        assert!(!result.output.contains("were killed"));
+    }
+}
@@ -3,36 +3,136 @@
 //! Supports two modes:
 //! 1. TCP mode: Connect to signal-cli daemon running in separate container (Docker)
 //! 2. Subprocess mode: Start signal-cli as subprocess (native/dev)
+//!
+//! Group messages are routed to a per-group agent (`reply_to` is
+//! `group:<groupId>`) and only passed on when Sage is addressed: an @mention,
+//! a trigger word, or a reply quoting one of Sage's messages.
+//!
+//! Delivery and read receipts are recorded in `message_delivery` (see
+//! `delivery`).
+//!
+//! When Sage is linked to the user's own account (`sage signal link`) and
+//! `SIGNAL_NOTE_TO_SELF` is on, what the user writes in Note to Self is a
+//! message too: signal-cli reports it as a sync transcript of a message the
+//! account sent to itself. Sage is asked to file it into memory, and replies
+//! land in Note to Self.
+//!
+//! Sync transcripts of what the account sent to others from its other
+//! devices are passed on as `own_message`s, so they are stored in the
+//! conversation's history instead of leaving gaps in it.
+//!
+//! Sends wait for signal-cli's JSON-RPC response, matched by request id, so
+//! rate limits, unregistered numbers and untrusted identities come back as
+//! errors instead of being logged as sent.
 
 use anyhow::{Context, Result};
+use base64::Engine;
 use serde_json::{json, Value};
 use socket2::{SockRef, TcpKeepalive};
+use std::collections::HashMap;
 use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
+use std::path::Path;
 use std::process::{Child, Command, Stdio};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Mutex;
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, RuntimeFlavor};
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 
-use crate::messenger::{IncomingAttachment, IncomingMessage, Messenger};
+use crate::delivery::{DeliveryDb, Receipt, ReceiptKind};
+use crate::messenger::{
+    self, IncomingAttachment, IncomingMessage, IncomingReaction, Messenger, MessengerCapabilities,
+    QuotedMessage,
+};
+
+/// Directory where signal-cli stores downloaded attachments
+pub const ATTACHMENTS_DIR: &str = "/signal-cli-data/.local/share/signal-cli/attachments";
+
+/// Path on disk of a downloaded attachment
+pub fn attachment_path(file: &str) -> String {
+    format!("{}/{}", ATTACHMENTS_DIR, file)
+}
+
+/// Line telling the agent that a message comes from the user's Note to Self
+pub const NOTE_TO_SELF_CONTEXT: &str = "[Note to Self: the user saved this for themselves. File it into memory (archival_insert, or a memory block for facts about them) and confirm in a few words]";
+
+/// Object replacement character signal-cli puts where an @mention was
+const MENTION_PLACEHOLDER: char = '\u{FFFC}';
+
+/// How long to wait for signal-cli to answer a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One-shot channel resolving a request with its JSON-RPC `result`, or the
+/// `error` message
+type ResponseSender = std_mpsc::SyncSender<std::result::Result<Value, String>>;
+
+/// Requests waiting for their JSON-RPC response, keyed by request id
+pub type PendingResponses = Arc<Mutex<HashMap<u64, ResponseSender>>>;
+
+/// Decides which group messages are addressed to Sage
+#[derive(Debug, Clone, Default)]
+pub struct GroupGate {
+    /// Sage's own identifiers (phone number, account UUID), to recognize
+    /// @mentions of and quotes from Sage
+    pub self_ids: Vec<String>,
+    /// Words that address Sage without an @mention (case-insensitive)
+    pub triggers: Vec<String>,
+    /// Accept the account's Note to Self messages (Sage linked to the user's
+    /// own account)
+    pub note_to_self: bool,
+}
+
+impl GroupGate {
+    fn is_self(&self, value: &Value, uuid_key: &str, number_key: &str) -> bool {
+        [uuid_key, number_key]
+            .iter()
+            .filter_map(|key| value.get(*key).and_then(|v| v.as_str()))
+            .any(|id| self.self_ids.iter().any(|s| s == id))
+    }
+
+    /// Whether `source` is the account Sage runs as
+    pub fn is_self_id(&self, source: &str) -> bool {
+        self.self_ids.iter().any(|s| s == source)
+    }
+
+    /// Whether the text contains a trigger word
+    fn has_trigger(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        text.split(|c: char| !c.is_alphanumeric())
+            .any(|word| !word.is_empty() && self.triggers.iter().any(|t| t.to_lowercase() == word))
+    }
+}
 
 /// Connection mode for signal-cli
 #[allow(dead_code)]
 enum ConnectionMode {
-    /// TCP connection to signal-cli daemon
+    /// TCP connection to signal-cli daemon (responses are read by a thread,
+    /// see `spawn_response_reader`)
     Tcp {
-        reader: BufReader<TcpStream>,
         writer: BufWriter<TcpStream>,
+        pending: PendingResponses,
     },
-    /// Subprocess running signal-cli
+    /// Subprocess running signal-cli (responses arrive on stdout, read by
+    /// `run_receive_loop`)
     Subprocess {
         process: Child,
         writer: BufWriter<std::process::ChildStdin>,
+        pending: PendingResponses,
     },
 }
 
+impl ConnectionMode {
+    fn pending(&self) -> &PendingResponses {
+        match self {
+            ConnectionMode::Tcp { pending, .. } | ConnectionMode::Subprocess { pending, .. } => {
+                pending
+            }
+        }
+    }
+}
+
 /// Signal client using signal-cli JSON-RPC
 pub struct SignalClient {
     mode: Mutex<ConnectionMode>,
@@ -53,11 +153,13 @@ impl SignalClient {
 
         let reader = BufReader::new(stream.try_clone()?);
         let writer = BufWriter::new(stream);
+        let pending = PendingResponses::default();
+        spawn_response_reader(reader, pending.clone());
 
         info!("Connected to signal-cli daemon");
 
         Ok(Self {
-            mode: Mutex::new(ConnectionMode::Tcp { reader, writer }),
+            mode: Mutex::new(ConnectionMode::Tcp { writer, pending }),
             request_id: AtomicU64::new(1),
             account: account.to_string(),
             tcp_host: Some(host.to_string()),
@@ -82,12 +184,18 @@ impl SignalClient {
 
         let reader = BufReader::new(stream.try_clone()?);
         let writer = BufWriter::new(stream);
+        let pending = PendingResponses::default();
+        spawn_response_reader(reader, pending.clone());
 
         let mut mode = self
             .mode
             .lock()
             .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-        *mode = ConnectionMode::Tcp { reader, writer };
+        // Close the old connection so its response reader fails what it had
+        if let ConnectionMode::Tcp { writer, .. } = &*mode {
+            let _ = writer.get_ref().shutdown(Shutdown::Both);
+        }
+        *mode = ConnectionMode::Tcp { writer, pending };
 
         info!("Reconnected to signal-cli daemon successfully");
         Ok(())
@@ -111,7 +219,11 @@ impl SignalClient {
         info!("signal-cli started successfully");
 
         Ok(Self {
-            mode: Mutex::new(ConnectionMode::Subprocess { process, writer }),
+            mode: Mutex::new(ConnectionMode::Subprocess {
+                process,
+                writer,
+                pending: PendingResponses::default(),
+            }),
             request_id: AtomicU64::new(1),
             account: account.to_string(),
             tcp_host: None,
@@ -127,8 +239,63 @@ impl SignalClient {
         Ok(())
     }
 
-    /// Send a JSON-RPC request (fire and forget for now)
-    fn send_request(&self, method: &str, mut params: Value) -> Result<Value> {
+    /// Send a JSON-RPC request without waiting for the response
+    fn send_request(&self, method: &str, params: Value) -> Result<u64> {
+        self.write_request(method, params, None)
+    }
+
+    /// Send a JSON-RPC request and wait for its response.
+    ///
+    /// Callers are async tasks, so on a multi-threaded runtime the wait goes
+    /// through `block_in_place`: the worker's other tasks move to another
+    /// thread instead of stalling for up to `RESPONSE_TIMEOUT`.
+    fn call(&self, method: &str, params: Value) -> Result<(u64, Value)> {
+        let (tx, rx) = std_mpsc::sync_channel(1);
+        let id = self.write_request(method, params, Some(tx))?;
+
+        let wait = || rx.recv_timeout(RESPONSE_TIMEOUT);
+        let response = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(wait)
+            }
+            _ => wait(),
+        };
+        match response {
+            Ok(Ok(result)) => Ok((id, result)),
+            Ok(Err(message)) => Err(anyhow::anyhow!(
+                "signal-cli rejected {} (req #{}): {}",
+                method,
+                id,
+                message
+            )),
+            Err(std_mpsc::RecvTimeoutError::Timeout) => {
+                if let Ok(mode) = self.mode.lock() {
+                    if let Ok(mut pending) = mode.pending().lock() {
+                        pending.remove(&id);
+                    }
+                }
+                Err(anyhow::anyhow!(
+                    "signal-cli didn't answer {} (req #{}) within {:?}",
+                    method,
+                    id,
+                    RESPONSE_TIMEOUT
+                ))
+            }
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => Err(anyhow::anyhow!(
+                "signal-cli connection closed before answering {} (req #{})",
+                method,
+                id
+            )),
+        }
+    }
+
+    /// Write a JSON-RPC request, registering `response` for its answer
+    fn write_request(
+        &self,
+        method: &str,
+        mut params: Value,
+        response: Option<ResponseSender>,
+    ) -> Result<u64> {
         let id = self.request_id.fetch_add(1, Ordering::SeqCst);
 
         // Add account parameter for TCP mode
@@ -152,22 +319,47 @@ impl SignalClient {
         let request_str = serde_json::to_string(&request)? + "\n";
         debug!("Sending request: {}", request_str.trim());
 
-        match &mut *mode {
-            ConnectionMode::Tcp { writer, .. } => {
-                writer.write_all(request_str.as_bytes())?;
-                writer.flush()?;
-            }
-            ConnectionMode::Subprocess { writer, .. } => {
-                writer.write_all(request_str.as_bytes())?;
-                writer.flush()?;
+        if let Some(response) = response {
+            mode.pending()
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?
+                .insert(id, response);
+        }
+
+        let written = match &mut *mode {
+            ConnectionMode::Tcp { writer, .. } => writer
+                .write_all(request_str.as_bytes())
+                .and_then(|_| writer.flush()),
+            ConnectionMode::Subprocess { writer, .. } => writer
+                .write_all(request_str.as_bytes())
+                .and_then(|_| writer.flush()),
+        };
+        if let Err(e) = written {
+            if let Ok(mut pending) = mode.pending().lock() {
+                pending.remove(&id);
             }
+            return Err(e.into());
         }
 
-        Ok(json!({"status": "sent", "id": id}))
+        Ok(id)
     }
 
     /// Send a message to a recipient with retry on connection failure
     pub fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
+        self.send_text(recipient, message, None)
+    }
+
+    /// Send a message that quotes the message it replies to
+    pub fn send_quoted(&self, recipient: &str, message: &str, quote: &QuotedMessage) -> Result<()> {
+        self.send_text(recipient, message, Some(quote))
+    }
+
+    fn send_text(
+        &self,
+        recipient: &str,
+        message: &str,
+        quote: Option<&QuotedMessage>,
+    ) -> Result<()> {
         // Find valid UTF-8 boundary for preview
         let preview_end = {
             let max_len = 50.min(message.len());
@@ -183,17 +375,17 @@ impl SignalClient {
         let mut last_error = None;
 
         for attempt in 1..=max_retries {
-            let result = self.send_request(
-                "send",
-                json!({
-                    "recipient": [recipient],
-                    "message": message
-                }),
-            );
+            let mut params = recipient_params(recipient);
+            params["message"] = json!(message);
+            if let Some(quote) = quote {
+                add_quote_params(&mut params, quote);
+            }
+            let result = self
+                .call("send", params)
+                .and_then(|(id, res)| check_send_result(&res).map(|_| id));
 
             match result {
-                Ok(res) => {
-                    let request_id = res.get("id").and_then(|v| v.as_u64()).unwrap_or(0);
+                Ok(request_id) => {
                     info!(
                         "Sent message (req #{}) to {}: {}...",
                         request_id,
@@ -215,6 +407,7 @@ impl SignalClient {
                         || error_str.contains("Connection reset")
                         || error_str.contains("os error 32")
                         || error_str.contains("os error 104")
+                        || error_str.contains("connection closed before")
                     {
                         if attempt < max_retries {
                             if let Err(reconnect_err) = self.reconnect() {
@@ -235,17 +428,73 @@ impl SignalClient {
             .unwrap_or_else(|| anyhow::anyhow!("Send failed after {} retries", max_retries)))
     }
 
+    /// Send a file with an optional caption. The file is inlined as a data URI
+    /// so it doesn't need to exist on the signal-cli host (TCP mode).
+    pub fn send_attachment(
+        &self,
+        recipient: &str,
+        path: &Path,
+        caption: Option<&str>,
+    ) -> Result<()> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read attachment {}", path.display()))?;
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("attachment");
+
+        let mut params = recipient_params(recipient);
+        params["attachments"] = json!([format!(
+            "data:{};filename={};base64,{}",
+            messenger::content_type_for_path(path),
+            filename,
+            base64::engine::general_purpose::STANDARD.encode(&data)
+        )]);
+        if let Some(caption) = caption {
+            params["message"] = json!(caption);
+        }
+
+        let (request_id, res) = self.call("send", params)?;
+        check_send_result(&res)?;
+        info!(
+            "Sent attachment (req #{}) to {}: {} ({})",
+            request_id,
+            recipient,
+            filename,
+            messenger::format_bytes(data.len() as u64)
+        );
+        Ok(())
+    }
+
+    /// React to a message with an emoji
+    pub fn send_reaction(
+        &self,
+        recipient: &str,
+        target_author: &str,
+        target_timestamp: u64,
+        emoji: &str,
+    ) -> Result<()> {
+        let mut params = recipient_params(recipient);
+        params["emoji"] = json!(emoji);
+        params["targetAuthor"] = json!(target_author);
+        params["targetTimestamp"] = json!(target_timestamp);
+
+        let (request_id, res) = self.call("sendReaction", params)?;
+        check_send_result(&res)?;
+        info!(
+            "Sent reaction (req #{}) {} to {} (message {})",
+            request_id, emoji, recipient, target_timestamp
+        );
+        Ok(())
+    }
+
     /// Send typing indicator to a recipient
     pub fn send_typing(&self, recipient: &str, stop: bool) -> Result<()> {
         debug!("Sending typing indicator (stop={}) to {}", stop, recipient);
 
-        self.send_request(
-            "sendTyping",
-            json!({
-                "recipient": [recipient],
-                "stop": stop
-            }),
-        )?;
+        let mut params = recipient_params(recipient);
+        params["stop"] = json!(stop);
+        self.send_request("sendTyping", params)?;
 
         Ok(())
     }
@@ -275,7 +524,7 @@ impl SignalClient {
     pub fn refresh_account(&self) -> Result<()> {
         info!("Refreshing Signal account (prekey health check)...");
 
-        self.send_request("updateAccount", json!({}))?;
+        self.call("updateAccount", json!({}))?;
 
         info!("Signal account refreshed successfully");
         Ok(())
@@ -297,9 +546,14 @@ impl SignalClient {
                     "TCP reader extraction not yet supported - use run_receive_loop_tcp"
                 ))
             }
-            ConnectionMode::Subprocess { process, .. } => {
+            ConnectionMode::Subprocess {
+                process, pending, ..
+            } => {
                 let stdout = process.stdout.take().context("stdout already taken")?;
-                Ok(SignalReader::Subprocess(BufReader::new(stdout)))
+                Ok(SignalReader::Subprocess(
+                    BufReader::new(stdout),
+                    pending.clone(),
+                ))
             }
         }
     }
@@ -330,6 +584,19 @@ impl SignalClient {
 }
 
 impl Messenger for SignalClient {
+    fn capabilities(&self) -> MessengerCapabilities {
+        MessengerCapabilities {
+            typing: true,
+            reactions: true,
+            attachments: true,
+            edits: true,
+            quotes: true,
+            max_message_length: None,
+            single_reply: false,
+            receipts: true,
+        }
+    }
+
     fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
         SignalClient::send_message(self, recipient, message)
     }
@@ -338,6 +605,24 @@ impl Messenger for SignalClient {
         SignalClient::send_typing(self, recipient, stop)
     }
 
+    fn send_attachment(&self, recipient: &str, path: &Path, caption: Option<&str>) -> Result<()> {
+        SignalClient::send_attachment(self, recipient, path, caption)
+    }
+
+    fn send_quoted(&self, recipient: &str, message: &str, quote: &QuotedMessage) -> Result<()> {
+        SignalClient::send_quoted(self, recipient, message, quote)
+    }
+
+    fn send_reaction(
+        &self,
+        recipient: &str,
+        target_author: &str,
+        target_timestamp: u64,
+        emoji: &str,
+    ) -> Result<()> {
+        SignalClient::send_reaction(self, recipient, target_author, target_timestamp, emoji)
+    }
+
     fn refresh(&self) -> Result<()> {
         self.refresh_account()
     }
@@ -359,13 +644,126 @@ impl Drop for SignalClient {
     }
 }
 
+/// Read JSON-RPC responses from a TCP send connection and hand them to the
+/// requests waiting for them. When the connection closes, everything still
+/// waiting fails.
+fn spawn_response_reader(reader: BufReader<TcpStream>, pending: PendingResponses) {
+    std::thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            if !resolve_response(&line, &pending) {
+                debug!("Unmatched signal-cli response: {}", line.trim());
+            }
+        }
+        debug!("signal-cli response reader ended");
+        if let Ok(mut pending) = pending.lock() {
+            pending.clear();
+        }
+    });
+}
+
+/// A JSON-RPC response line: its id with the `result`, or the `error`
+/// message. Notifications (`method` set) are not responses.
+fn parse_rpc_response(line: &str) -> Option<(u64, std::result::Result<Value, String>)> {
+    let value: Value = serde_json::from_str(line.trim()).ok()?;
+    if value.get("method").is_some() {
+        return None;
+    }
+    let id = value.get("id")?.as_u64()?;
+    if let Some(error) = value.get("error") {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| error.to_string());
+        return Some((id, Err(message)));
+    }
+    Some((id, Ok(value.get("result").cloned().unwrap_or(Value::Null))))
+}
+
+/// Resolve the request a response line answers. Returns false if the line
+/// isn't a response or nothing was waiting for it.
+fn resolve_response(line: &str, pending: &PendingResponses) -> bool {
+    let Some((id, result)) = parse_rpc_response(line) else {
+        return false;
+    };
+    let waiter = pending
+        .lock()
+        .ok()
+        .and_then(|mut pending| pending.remove(&id));
+    match waiter {
+        Some(tx) => {
+            let _ = tx.send(result);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Fail a `send` whose per-recipient results are all failures. Groups
+/// succeed if any member got the message.
+fn check_send_result(result: &Value) -> Result<()> {
+    let Some(results) = result.get("results").and_then(|r| r.as_array()) else {
+        return Ok(());
+    };
+    let failures: Vec<String> = results
+        .iter()
+        .filter_map(|r| {
+            let kind = r.get("type").and_then(|t| t.as_str()).unwrap_or("SUCCESS");
+            if kind == "SUCCESS" {
+                return None;
+            }
+            let address = r.get("recipientAddress");
+            let recipient = address
+                .and_then(|a| a.get("number").or_else(|| a.get("uuid")))
+                .and_then(|v| v.as_str())
+                .unwrap_or("recipient");
+            let reason = match kind {
+                "UNREGISTERED_FAILURE" => "not registered on Signal",
+                "IDENTITY_FAILURE" => "untrusted identity (safety number changed)",
+                "RATE_LIMIT_FAILURE" => "rate limited",
+                "NETWORK_FAILURE" => "network failure",
+                other => other,
+            };
+            Some(format!("{}: {}", recipient, reason))
+        })
+        .collect();
+
+    if failures.is_empty() || failures.len() < results.len() {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!("Send failed - {}", failures.join(", ")))
+}
+
+/// JSON-RPC addressing for a recipient: `groupId` for group conversations,
+/// `recipient` otherwise
+fn recipient_params(recipient: &str) -> Value {
+    match messenger::group_id(recipient) {
+        Some(group_id) => json!({ "groupId": group_id }),
+        None => json!({ "recipient": [recipient] }),
+    }
+}
+
+/// JSON-RPC `send` parameters quoting an earlier message
+fn add_quote_params(params: &mut Value, quote: &QuotedMessage) {
+    if let Ok(timestamp) = quote.id.parse::<u64>() {
+        params["quoteTimestamp"] = json!(timestamp);
+        params["quoteAuthor"] = json!(quote.author);
+        if let Some(ref text) = quote.text {
+            params["quoteMessage"] = json!(text);
+        }
+    }
+}
+
 /// Reader for incoming messages
 pub enum SignalReader {
-    Subprocess(BufReader<std::process::ChildStdout>),
+    /// signal-cli's stdout, which carries both notifications and responses
+    Subprocess(BufReader<std::process::ChildStdout>, PendingResponses),
 }
 
-/// Parse incoming JSON-RPC notifications for messages
-pub fn parse_incoming_message(line: &str) -> Option<IncomingMessage> {
+/// Parse incoming JSON-RPC notifications for messages.
+///
+/// Group messages that don't address Sage (see `GroupGate`) are skipped.
+pub fn parse_incoming_message(line: &str, gate: &GroupGate) -> Option<IncomingMessage> {
     let value: Value = serde_json::from_str(line).ok()?;
 
     // Check if this is a receive notification
@@ -379,8 +777,24 @@ pub fn parse_incoming_message(line: &str) -> Option<IncomingMessage> {
     // format to avoid processing the same message twice.
     let envelope = params.get("envelope")?;
 
-    // Get the message content
-    let data_message = envelope.get("dataMessage")?;
+    // Get the message content; an edit carries the replacement dataMessage
+    // and the timestamp of the message it edits
+    let edit = envelope.get("editMessage");
+    let note = note_to_self(envelope, gate);
+    let own = sent_elsewhere(envelope, gate);
+    let data_message = match (edit, note, own) {
+        (Some(edit), _, _) => edit.get("dataMessage")?,
+        (None, Some(sent), _) | (None, None, Some(sent)) => sent,
+        (None, None, None) => envelope.get("dataMessage")?,
+    };
+
+    // Reactions to Sage's messages are feedback, not something to reply to
+    if data_message.get("reaction").is_some() {
+        if own.is_some() {
+            return None;
+        }
+        return parse_reaction(envelope, data_message, gate);
+    }
     let message = data_message
         .get("message")
         .and_then(|v| v.as_str())
@@ -431,31 +845,290 @@ pub fn parse_incoming_message(line: &str) -> Option<IncomingMessage> {
 
     let timestamp = data_message.get("timestamp")?.as_u64()?;
 
+    let message_id = edit
+        .and_then(|e| e.get("targetSentTimestamp"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(timestamp)
+        .to_string();
+
+    let quote = data_message.get("quote").and_then(|q| parse_quote(q, gate));
+
+    let mention_values = data_message
+        .get("mentions")
+        .and_then(|v| v.as_array())
+        .map(|m| m.as_slice())
+        .unwrap_or_default();
+    let mentions = mention_values
+        .iter()
+        .filter_map(|m| {
+            m.get("uuid")
+                .or_else(|| m.get("number"))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        })
+        .collect();
+
+    let group_id = data_message
+        .get("groupInfo")
+        .and_then(|g| g.get("groupId"))
+        .and_then(|v| v.as_str());
+
+    let (reply_to, message) = match (group_id, own) {
+        // Written by the account itself: stored as is, never gated
+        (Some(group_id), Some(_)) => (
+            format!("{}{}", messenger::GROUP_PREFIX, group_id),
+            resolve_mentions(message, mention_values, gate).0,
+        ),
+        (None, Some(sent)) => {
+            let destination = ["destinationUuid", "destinationNumber", "destination"]
+                .iter()
+                .find_map(|key| sent.get(*key).and_then(|v| v.as_str()))?;
+            (destination.to_string(), message.to_string())
+        }
+        (Some(group_id), None) => {
+            let (text, mentioned) = resolve_mentions(message, mention_values, gate);
+            let quoted = quote.as_ref().is_some_and(|q| q.from_self);
+
+            if !(mentioned || quoted || gate.has_trigger(&text)) {
+                debug!("Ignoring group message not addressed to Sage");
+                return None;
+            }
+
+            // Several people share the conversation, so say who is speaking
+            let sender = source_name.as_deref().unwrap_or(&source);
+            (
+                format!("{}{}", messenger::GROUP_PREFIX, group_id),
+                format!("{}: {}", sender, text),
+            )
+        }
+        (None, None) if note.is_some() => (
+            source.clone(),
+            format!("{}\n{}", NOTE_TO_SELF_CONTEXT, message),
+        ),
+        (None, None) => (source.clone(), message.to_string()),
+    };
+
     Some(IncomingMessage {
-        reply_to: source.clone(),
+        reply_to,
         source,
         source_name,
-        message: message.to_string(),
+        message,
         attachments,
         timestamp,
         reply_context: None,
+        message_id: Some(message_id),
+        quote,
+        mentions,
+        edited: edit.is_some(),
+        reaction: None,
+        own_message: own.is_some(),
+        inbox_ids: Vec::new(),
     })
 }
 
+/// The `sentMessage` of a sync transcript for a message the account sent to
+/// itself (Note to Self), if `GroupGate::note_to_self` is on
+fn note_to_self<'a>(envelope: &'a Value, gate: &GroupGate) -> Option<&'a Value> {
+    if !gate.note_to_self {
+        return None;
+    }
+    let sent = envelope.get("syncMessage")?.get("sentMessage")?;
+    let to_self = gate.is_self(sent, "destinationUuid", "destinationNumber")
+        && sent.get("groupInfo").is_none();
+    to_self.then_some(sent)
+}
+
+/// The `sentMessage` of a sync transcript for a message the account sent
+/// from another of its devices (the user's phone while Sage is a linked
+/// device). Note to Self is left to `note_to_self`.
+fn sent_elsewhere<'a>(envelope: &'a Value, gate: &GroupGate) -> Option<&'a Value> {
+    let sent = envelope.get("syncMessage")?.get("sentMessage")?;
+    let to_self = gate.is_self(sent, "destinationUuid", "destinationNumber")
+        && sent.get("groupInfo").is_none();
+    (!to_self).then_some(sent)
+}
+
+/// Parse a `dataMessage.reaction`. Only reactions to Sage's own messages
+/// are passed on.
+fn parse_reaction(
+    envelope: &Value,
+    data_message: &Value,
+    gate: &GroupGate,
+) -> Option<IncomingMessage> {
+    let reaction = data_message.get("reaction")?;
+    if !(gate.is_self(reaction, "targetAuthorUuid", "targetAuthorNumber")
+        || gate.is_self(reaction, "targetAuthor", "targetAuthor"))
+    {
+        return None;
+    }
+
+    let source = ["sourceUuid", "sourceNumber", "source"]
+        .iter()
+        .find_map(|key| envelope.get(*key).and_then(|v| v.as_str()))?
+        .to_string();
+    let reply_to = match data_message
+        .get("groupInfo")
+        .and_then(|g| g.get("groupId"))
+        .and_then(|v| v.as_str())
+    {
+        Some(group_id) => format!("{}{}", messenger::GROUP_PREFIX, group_id),
+        None => source.clone(),
+    };
+
+    Some(IncomingMessage {
+        reply_to,
+        source_name: envelope
+            .get("sourceName")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        timestamp: data_message.get("timestamp")?.as_u64()?,
+        reaction: Some(IncomingReaction {
+            emoji: reaction.get("emoji")?.as_str()?.to_string(),
+            target_id: reaction.get("targetSentTimestamp")?.as_u64()?.to_string(),
+            removed: reaction
+                .get("isRemove")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+        }),
+        source,
+        ..Default::default()
+    })
+}
+
+/// Parse a delivery/read receipt notification (`envelope.receiptMessage`)
+pub fn parse_receipt(line: &str) -> Option<Receipt> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    if value.get("method")?.as_str()? != "receive" {
+        return None;
+    }
+    let envelope = value.get("params")?.get("envelope")?;
+    let receipt = envelope.get("receiptMessage")?;
+
+    let flag = |key: &str| receipt.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    let kind = if flag("isRead") || flag("isViewed") {
+        ReceiptKind::Read
+    } else if flag("isDelivery") {
+        ReceiptKind::Delivered
+    } else {
+        return None;
+    };
+
+    // Same precedence as message sources, so it matches the chat identifier
+    let source = ["sourceUuid", "sourceNumber", "source"]
+        .iter()
+        .find_map(|key| envelope.get(*key).and_then(|v| v.as_str()))?
+        .to_string();
+    let timestamps = receipt
+        .get("timestamps")?
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_u64())
+        .collect();
+
+    Some(Receipt {
+        source,
+        kind,
+        timestamps,
+    })
+}
+
+/// Record a receipt line, if it is one
+fn apply_receipt_line(line: &str, delivery: &DeliveryDb) {
+    let Some(receipt) = parse_receipt(line) else {
+        return;
+    };
+    match delivery.apply_receipt(&receipt) {
+        Ok(updated) => debug!(
+            "{:?} receipt from {} updated {} message(s)",
+            receipt.kind, receipt.source, updated
+        ),
+        Err(e) => warn!("Failed to apply receipt from {}: {}", receipt.source, e),
+    }
+}
+
+/// Parse a `dataMessage.quote` into the message it replies to
+fn parse_quote(quote: &Value, gate: &GroupGate) -> Option<QuotedMessage> {
+    let id = quote.get("id")?.as_u64()?;
+    let author = ["authorUuid", "authorNumber", "author"]
+        .iter()
+        .find_map(|key| quote.get(*key).and_then(|v| v.as_str()))?
+        .to_string();
+    Some(QuotedMessage {
+        author,
+        id: id.to_string(),
+        text: quote
+            .get("text")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        from_self: gate.is_self(quote, "authorUuid", "authorNumber"),
+    })
+}
+
+/// Replace mention placeholders with "@name" ("@Sage" for Sage itself).
+/// Returns the text and whether Sage was mentioned.
+fn resolve_mentions(text: &str, mentions: &[Value], gate: &GroupGate) -> (String, bool) {
+    let mut mentions: Vec<&Value> = mentions.iter().collect();
+    mentions.sort_by_key(|m| m.get("start").and_then(|v| v.as_u64()).unwrap_or(0));
+    let mut mentions = mentions.into_iter();
+    let mut mentioned_self = false;
+
+    let resolved: String = text
+        .chars()
+        .map(|c| {
+            if c != MENTION_PLACEHOLDER {
+                return c.to_string();
+            }
+            let Some(mention) = mentions.next() else {
+                return String::new();
+            };
+            if gate.is_self(mention, "uuid", "number") {
+                mentioned_self = true;
+                return "@Sage".to_string();
+            }
+            let name = ["name", "number", "uuid"]
+                .iter()
+                .find_map(|key| mention.get(*key).and_then(|v| v.as_str()))
+                .unwrap_or("someone");
+            format!("@{}", name)
+        })
+        .collect();
+
+    (resolved, mentioned_self)
+}
+
 /// Run the message receive loop for subprocess mode
 pub async fn run_receive_loop(
     reader: SignalReader,
+    gate: GroupGate,
+    delivery: Arc<DeliveryDb>,
     tx: mpsc::Sender<IncomingMessage>,
 ) -> Result<()> {
     match reader {
-        SignalReader::Subprocess(reader) => {
+        SignalReader::Subprocess(reader, pending) => {
+            // This reader also resolves responses to `call`, so it must never
+            // block on a full message channel while a send waits on it.
+            // Messages queue unbounded to a thread that forwards them.
+            let (queue, queued) = std_mpsc::channel::<IncomingMessage>();
+            std::thread::spawn(move || {
+                for msg in queued {
+                    if tx.blocking_send(msg).is_err() {
+                        error!("Failed to send message to channel");
+                        break;
+                    }
+                }
+            });
+
             tokio::task::spawn_blocking(move || {
                 for line in reader.lines() {
                     match line {
                         Ok(line) => {
                             debug!("Received from signal-cli: {}", line);
 
-                            if let Some(msg) = parse_incoming_message(&line) {
+                            if resolve_response(&line, &pending) {
+                                continue;
+                            }
+                            apply_receipt_line(&line, &delivery);
+                            if let Some(msg) = parse_incoming_message(&line, &gate) {
                                 // Find valid UTF-8 boundary for preview
                                 let preview_end = {
                                     let max_len = 100.min(msg.message.len());
@@ -471,8 +1144,7 @@ pub async fn run_receive_loop(
                                     &msg.message[..preview_end]
                                 );
 
-                                if tx.blocking_send(msg).is_err() {
-                                    error!("Failed to send message to channel");
+                                if queue.send(msg).is_err() {
                                     break;
                                 }
                             }
@@ -484,6 +1156,9 @@ pub async fn run_receive_loop(
                     }
                 }
                 warn!("Signal receive loop ended");
+                if let Ok(mut pending) = pending.lock() {
+                    pending.clear();
+                }
             })
             .await?;
         }
@@ -517,6 +1192,8 @@ pub async fn run_receive_loop_tcp(
     host: &str,
     port: u16,
     account: &str,
+    gate: GroupGate,
+    delivery: Arc<DeliveryDb>,
     tx: mpsc::Sender<IncomingMessage>,
 ) -> Result<()> {
     let host = host.to_string();
@@ -596,7 +1273,8 @@ pub async fn run_receive_loop_tcp(
                     last_activity = Instant::now();
                     awaiting_keepalive_response = false;
 
-                    if let Some(msg) = parse_incoming_message(&line) {
+                    apply_receipt_line(&line, &delivery);
+                    if let Some(msg) = parse_incoming_message(&line, &gate) {
                         messages_received += 1;
                         // Find valid UTF-8 boundary for preview
                         let preview_end = {
@@ -703,3 +1381,344 @@ pub async fn run_receive_loop_tcp(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate() -> GroupGate {
+        GroupGate {
+            self_ids: vec!["+15550001111".to_string(), "sage-uuid".to_string()],
+            triggers: vec!["sage".to_string()],
+            note_to_self: false,
+        }
+    }
+
+    fn receive(data_message: Value) -> String {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "receive",
+            "params": {
+                "envelope": {
+                    "sourceUuid": "alice-uuid",
+                    "sourceName": "Alice",
+                    "dataMessage": data_message
+                }
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_direct_message() {
+        let line = receive(json!({"timestamp": 1, "message": "hello"}));
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        assert_eq!(msg.reply_to, "alice-uuid");
+        assert_eq!(msg.message, "hello");
+        assert_eq!(msg.message_id.as_deref(), Some("1"));
+        assert!(!msg.edited);
+    }
+
+    #[test]
+    fn test_note_to_self() {
+        let sync = |destination: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "receive",
+                "params": {
+                    "envelope": {
+                        "sourceUuid": "sage-uuid",
+                        "syncMessage": {
+                            "sentMessage": {
+                                "destinationUuid": destination,
+                                "timestamp": 7,
+                                "message": "https://example.com/article"
+                            }
+                        }
+                    }
+                }
+            })
+            .to_string()
+        };
+        let gate = GroupGate {
+            note_to_self: true,
+            ..gate()
+        };
+
+        let msg = parse_incoming_message(&sync("sage-uuid"), &gate).unwrap();
+        assert_eq!(msg.reply_to, "sage-uuid");
+        assert_eq!(
+            msg.message,
+            format!("{}\nhttps://example.com/article", NOTE_TO_SELF_CONTEXT)
+        );
+        assert_eq!(msg.message_id.as_deref(), Some("7"));
+        assert!(!msg.own_message);
+
+        // Messages the user sent to someone else are their own messages,
+        // not notes; with the option off Note to Self is ignored
+        assert!(
+            parse_incoming_message(&sync("bob-uuid"), &gate)
+                .unwrap()
+                .own_message
+        );
+        assert!(parse_incoming_message(&sync("sage-uuid"), &gate()).is_none());
+    }
+
+    #[test]
+    fn test_sent_from_other_device() {
+        let sync = |sent: Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "receive",
+                "params": {
+                    "envelope": {
+                        "sourceUuid": "sage-uuid",
+                        "syncMessage": {"sentMessage": sent}
+                    }
+                }
+            })
+            .to_string()
+        };
+
+        let line = sync(json!({
+            "destinationUuid": "alice-uuid",
+            "timestamp": 8,
+            "message": "see you at 6"
+        }));
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        assert!(msg.own_message);
+        assert_eq!(msg.reply_to, "alice-uuid");
+        assert_eq!(msg.message, "see you at 6");
+
+        // Group messages are kept without addressing Sage
+        let line = sync(json!({
+            "timestamp": 9,
+            "message": "running late",
+            "groupInfo": {"groupId": "grp"}
+        }));
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        assert!(msg.own_message);
+        assert_eq!(msg.reply_to, "group:grp");
+        assert_eq!(msg.message, "running late");
+
+        // Reactions sent from another device are dropped
+        let line = sync(json!({
+            "destinationUuid": "alice-uuid",
+            "timestamp": 10,
+            "reaction": {"emoji": "👍", "targetAuthorUuid": "alice-uuid", "targetSentTimestamp": 1}
+        }));
+        assert!(parse_incoming_message(&line, &gate()).is_none());
+    }
+
+    #[test]
+    fn test_edit_message() {
+        let line = json!({
+            "jsonrpc": "2.0",
+            "method": "receive",
+            "params": {
+                "envelope": {
+                    "sourceUuid": "alice-uuid",
+                    "editMessage": {
+                        "targetSentTimestamp": 100,
+                        "dataMessage": {"timestamp": 200, "message": "meet at 4pm"}
+                    }
+                }
+            }
+        })
+        .to_string();
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        assert!(msg.edited);
+        assert_eq!(msg.message, "meet at 4pm");
+        assert_eq!(msg.timestamp, 200);
+        assert_eq!(msg.message_id.as_deref(), Some("100"));
+    }
+
+    #[test]
+    fn test_group_message_with_mention() {
+        let line = receive(json!({
+            "timestamp": 1,
+            "message": "\u{FFFC} can you ask \u{FFFC} about dinner?",
+            "groupInfo": {"groupId": "abc123==", "type": "DELIVER"},
+            "mentions": [
+                {"name": "Bob", "uuid": "bob-uuid", "start": 13, "length": 1},
+                {"name": "Sage", "uuid": "sage-uuid", "start": 0, "length": 1}
+            ]
+        }));
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        assert_eq!(msg.reply_to, "group:abc123==");
+        assert_eq!(msg.source, "alice-uuid");
+        assert_eq!(msg.message, "Alice: @Sage can you ask @Bob about dinner?");
+        assert_eq!(msg.mentions, vec!["bob-uuid", "sage-uuid"]);
+    }
+
+    #[test]
+    fn test_group_message_gating() {
+        // Not addressed: ignored
+        let line = receive(json!({
+            "timestamp": 1,
+            "message": "anyone up for lunch?",
+            "groupInfo": {"groupId": "abc123=="}
+        }));
+        assert!(parse_incoming_message(&line, &gate()).is_none());
+
+        // Trigger word
+        let line = receive(json!({
+            "timestamp": 1,
+            "message": "Sage, what's the weather?",
+            "groupInfo": {"groupId": "abc123=="}
+        }));
+        assert!(parse_incoming_message(&line, &gate()).is_some());
+
+        // Reply quoting one of Sage's messages
+        let line = receive(json!({
+            "timestamp": 1,
+            "message": "thanks!",
+            "groupInfo": {"groupId": "abc123=="},
+            "quote": {"id": 5, "authorNumber": "+15550001111"}
+        }));
+        assert!(parse_incoming_message(&line, &gate()).is_some());
+    }
+
+    #[test]
+    fn test_parse_quote() {
+        let line = receive(json!({
+            "timestamp": 10,
+            "message": "yes, that one",
+            "quote": {
+                "id": 7,
+                "authorUuid": "bob-uuid",
+                "text": "Which restaurant?"
+            }
+        }));
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        let quote = msg.quote.unwrap();
+        assert_eq!(quote.author, "bob-uuid");
+        assert_eq!(quote.id, "7");
+        assert_eq!(quote.text.as_deref(), Some("Which restaurant?"));
+        assert!(!quote.from_self);
+
+        let mut params = json!({});
+        add_quote_params(&mut params, &quote);
+        assert_eq!(params["quoteTimestamp"], json!(7));
+        assert_eq!(params["quoteAuthor"], json!("bob-uuid"));
+    }
+
+    #[test]
+    fn test_parse_reaction() {
+        let line = receive(json!({
+            "timestamp": 20,
+            "reaction": {
+                "emoji": "👎",
+                "targetAuthorNumber": "+15550001111",
+                "targetSentTimestamp": 10,
+                "isRemove": false
+            }
+        }));
+        let msg = parse_incoming_message(&line, &gate()).unwrap();
+        assert_eq!(msg.reply_to, "alice-uuid");
+        assert!(msg.message.is_empty());
+        let reaction = msg.reaction.unwrap();
+        assert_eq!(reaction.emoji, "👎");
+        assert_eq!(reaction.target_id, "10");
+        assert!(!reaction.removed);
+
+        // Reactions to someone else's message are ignored
+        let line = receive(json!({
+            "timestamp": 20,
+            "reaction": {"emoji": "👍", "targetAuthorUuid": "bob-uuid", "targetSentTimestamp": 10}
+        }));
+        assert!(parse_incoming_message(&line, &gate()).is_none());
+    }
+
+    #[test]
+    fn test_parse_receipt() {
+        let line = json!({
+            "jsonrpc": "2.0",
+            "method": "receive",
+            "params": {
+                "envelope": {
+                    "sourceUuid": "alice-uuid",
+                    "receiptMessage": {
+                        "when": 1760000009000u64,
+                        "isDelivery": false,
+                        "isRead": true,
+                        "isViewed": false,
+                        "timestamps": [1760000000000u64, 1760000005000u64]
+                    }
+                }
+            }
+        })
+        .to_string();
+        let receipt = parse_receipt(&line).unwrap();
+        assert_eq!(receipt.source, "alice-uuid");
+        assert_eq!(receipt.kind, ReceiptKind::Read);
+        assert_eq!(receipt.timestamps, vec![1760000000000, 1760000005000]);
+
+        let message = receive(json!({"timestamp": 1, "message": "hello"}));
+        assert!(parse_receipt(&message).is_none());
+    }
+
+    #[test]
+    fn test_recipient_params() {
+        assert_eq!(
+            recipient_params("group:abc123=="),
+            json!({"groupId": "abc123=="})
+        );
+        assert_eq!(
+            recipient_params("alice-uuid"),
+            json!({"recipient": ["alice-uuid"]})
+        );
+    }
+
+    #[test]
+    fn test_resolve_response() {
+        let pending = PendingResponses::default();
+        let (tx, rx) = std_mpsc::sync_channel(1);
+        pending.lock().unwrap().insert(5, tx);
+
+        let ok = r#"{"jsonrpc":"2.0","result":{"timestamp":123},"id":5}"#;
+        assert!(resolve_response(ok, &pending));
+        assert_eq!(rx.try_recv().unwrap(), Ok(json!({"timestamp": 123})));
+        assert!(!resolve_response(ok, &pending));
+
+        let (tx, rx) = std_mpsc::sync_channel(1);
+        pending.lock().unwrap().insert(6, tx);
+        let error =
+            r#"{"jsonrpc":"2.0","error":{"code":-1,"message":"Rate limit exceeded"},"id":6}"#;
+        assert!(resolve_response(error, &pending));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Err("Rate limit exceeded".to_string())
+        );
+
+        // Notifications are not responses
+        assert!(parse_rpc_response(&receive(json!({"timestamp": 1, "message": "hi"}))).is_none());
+    }
+
+    #[test]
+    fn test_check_send_result() {
+        let result = |types: &[&str]| {
+            json!({
+                "timestamp": 1,
+                "results": types
+                    .iter()
+                    .map(|t| json!({"recipientAddress": {"number": "+15550001111"}, "type": t}))
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert!(check_send_result(&result(&["SUCCESS"])).is_ok());
+        assert!(check_send_result(&json!({"timestamp": 1})).is_ok());
+        // A group send that reached some members succeeded
+        assert!(check_send_result(&result(&["SUCCESS", "NETWORK_FAILURE"])).is_ok());
+
+        let err = check_send_result(&result(&["IDENTITY_FAILURE"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Send failed - +15550001111: untrusted identity (safety number changed)"
+        );
+        assert!(check_send_result(&result(&["UNREGISTERED_FAILURE"]))
+            .unwrap_err()
+            .to_string()
+            .contains("not registered on Signal"));
+    }
+}
@@ -0,0 +1,460 @@
+//! Signal Device Linking
+//!
+//! `sage signal link` links Sage to an existing Signal account as a secondary
+//! device: it asks signal-cli for a device-link URI, shows it as a terminal QR
+//! code, waits for the phone to confirm, and then verifies the account is
+//! registered. `sage signal verify` only runs the verification, so
+//! provisioning scripts can check a deployment before starting Sage.
+//!
+//! Both talk to the signal-cli daemon when `SIGNAL_CLI_HOST` is set (JSON-RPC
+//! `startLink`/`finishLink`/`listAccounts`) and otherwise run the
+//! `signal-cli` binary directly. They run before the rest of Sage is
+//! configured, so settings are read straight from the environment.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DEFAULT_DEVICE_NAME: &str = "Sage";
+const DEFAULT_LINK_TIMEOUT_SECS: u64 = 300;
+
+const USAGE: &str = "Usage:
+  sage signal link [--name <device name>] [--timeout <seconds>]
+      Link Sage to your Signal account as a secondary device (scan the QR code
+      in Signal > Settings > Linked devices).
+  sage signal verify [--account <number>]
+      Check that the account (default SIGNAL_PHONE_NUMBER) is registered with signal-cli.";
+
+/// A `sage signal` subcommand
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalCommand {
+    Link {
+        device_name: String,
+        timeout: Duration,
+    },
+    Verify {
+        account: Option<String>,
+    },
+}
+
+/// Parse the arguments after `sage signal`
+pub fn parse_args(args: &[String]) -> Result<SignalCommand> {
+    let (command, rest) = args
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("missing subcommand\n\n{}", USAGE))?;
+
+    let mut options = std::collections::HashMap::new();
+    let mut iter = rest.iter();
+    while let Some(flag) = iter.next() {
+        let key = flag
+            .strip_prefix("--")
+            .ok_or_else(|| anyhow::anyhow!("unexpected argument '{}'\n\n{}", flag, USAGE))?;
+        let value = iter
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("--{} needs a value", key))?;
+        options.insert(key.to_string(), value.clone());
+    }
+
+    let command = match command.as_str() {
+        "link" => SignalCommand::Link {
+            device_name: options
+                .remove("name")
+                .unwrap_or_else(|| DEFAULT_DEVICE_NAME.to_string()),
+            timeout: Duration::from_secs(match options.remove("timeout") {
+                Some(t) => t
+                    .parse()
+                    .with_context(|| format!("invalid --timeout '{}'", t))?,
+                None => DEFAULT_LINK_TIMEOUT_SECS,
+            }),
+        },
+        "verify" => SignalCommand::Verify {
+            account: options.remove("account"),
+        },
+        other => anyhow::bail!("unknown subcommand '{}'\n\n{}", other, USAGE),
+    };
+
+    if let Some(key) = options.keys().next() {
+        anyhow::bail!("unknown option --{}\n\n{}", key, USAGE);
+    }
+    Ok(command)
+}
+
+/// Entry point for `sage signal ...`
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let backend = Backend::from_env();
+    match parse_args(args)? {
+        SignalCommand::Link {
+            device_name,
+            timeout,
+        } => link(&backend, &device_name, timeout),
+        SignalCommand::Verify { account } => {
+            let account = account
+                .or_else(|| std::env::var("SIGNAL_PHONE_NUMBER").ok())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("pass --account or set SIGNAL_PHONE_NUMBER to verify")
+                })?;
+            verify(&backend, &account)
+        }
+    }
+}
+
+/// Where to reach signal-cli
+enum Backend {
+    Daemon { host: String, port: u16 },
+    Binary,
+}
+
+impl Backend {
+    fn from_env() -> Self {
+        match std::env::var("SIGNAL_CLI_HOST") {
+            Ok(host) if !host.trim().is_empty() => Backend::Daemon {
+                host,
+                port: std::env::var("SIGNAL_CLI_PORT")
+                    .ok()
+                    .and_then(|p| p.parse().ok())
+                    .unwrap_or(7583),
+            },
+            _ => Backend::Binary,
+        }
+    }
+
+    /// Registered account numbers
+    fn list_accounts(&self) -> Result<Vec<String>> {
+        match self {
+            Backend::Daemon { host, port } => {
+                let mut rpc = RpcConnection::connect(host, *port, Duration::from_secs(30))?;
+                Ok(parse_accounts(&rpc.call("listAccounts", json!({}))?))
+            }
+            Backend::Binary => {
+                let output = Command::new("signal-cli")
+                    .args(["-o", "json", "listAccounts"])
+                    .output()
+                    .context("Failed to run signal-cli. Is it installed and in PATH?")?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "signal-cli listAccounts failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                let value: Value = serde_json::from_slice(&output.stdout)
+                    .context("Failed to parse signal-cli listAccounts output")?;
+                Ok(parse_accounts(&value))
+            }
+        }
+    }
+}
+
+fn link(backend: &Backend, device_name: &str, timeout: Duration) -> Result<()> {
+    eprintln!("Requesting a device link from signal-cli...");
+
+    let number = match backend {
+        Backend::Daemon { host, port } => {
+            let mut rpc = RpcConnection::connect(host, *port, timeout)?;
+            let started = rpc.call("startLink", json!({}))?;
+            let uri = started["deviceLinkUri"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("signal-cli returned no deviceLinkUri"))?
+                .to_string();
+            show_link_uri(&uri)?;
+
+            let finished = rpc
+                .call(
+                    "finishLink",
+                    json!({ "deviceLinkUri": uri, "deviceName": device_name }),
+                )
+                .context("Linking did not complete (timed out or was rejected on the phone)")?;
+            finished["number"].as_str().map(|s| s.to_string())
+        }
+        Backend::Binary => link_with_binary(device_name, timeout)?,
+    };
+
+    // signal-cli's link output doesn't always name the account; fall back to
+    // whatever is registered now.
+    let accounts = backend.list_accounts()?;
+    let number = number
+        .or_else(|| accounts.first().cloned())
+        .ok_or_else(|| anyhow::anyhow!("Linking finished but signal-cli has no accounts"))?;
+    if !accounts.contains(&number) {
+        anyhow::bail!(
+            "Linked {} but it is not listed by signal-cli listAccounts",
+            number
+        );
+    }
+
+    eprintln!("Linked \"{}\" to {}.", device_name, number);
+    if let Ok(configured) = std::env::var("SIGNAL_PHONE_NUMBER") {
+        if configured != number {
+            eprintln!(
+                "Warning: SIGNAL_PHONE_NUMBER is {}, but the linked account is {}.",
+                configured, number
+            );
+        }
+    }
+    eprintln!("Add this to your .env:");
+    println!("SIGNAL_PHONE_NUMBER={}", number);
+    Ok(())
+}
+
+/// Run `signal-cli link`, which prints the URI and exits once the phone
+/// confirms. Returns the linked number if signal-cli reports it.
+fn link_with_binary(device_name: &str, timeout: Duration) -> Result<Option<String>> {
+    let mut child = Command::new("signal-cli")
+        .args(["link", "-n", device_name])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run signal-cli. Is it installed and in PATH?")?;
+
+    let stdout = child.stdout.take().context("Failed to get stdout")?;
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + timeout;
+    let mut shown = false;
+    let mut number = None;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "Timed out after {:?} waiting for the link to be confirmed",
+                timeout
+            );
+        }
+
+        match rx.recv_timeout(remaining.min(Duration::from_millis(500))) {
+            Ok(line) => {
+                if let Some(uri) = extract_link_uri(&line) {
+                    if !shown {
+                        show_link_uri(uri)?;
+                        shown = true;
+                    }
+                } else if let Some(n) = extract_linked_number(&line) {
+                    number = Some(n.to_string());
+                }
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .context("Failed to wait for signal-cli")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "signal-cli link failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if !shown {
+        anyhow::bail!("signal-cli link exited without printing a device link URI");
+    }
+    Ok(number)
+}
+
+/// Print the link URI and a scannable QR code
+fn show_link_uri(uri: &str) -> Result<()> {
+    let qr = qrcode::QrCode::new(uri.as_bytes()).context("Failed to encode QR code")?;
+    let rendered = qr
+        .render::<qrcode::render::unicode::Dense1x2>()
+        .quiet_zone(true)
+        .build();
+    eprintln!(
+        "\nScan this in Signal > Settings > Linked devices > Link new device:\n\n{}\n",
+        rendered
+    );
+    eprintln!("Or open the link URI on the phone:\n{}\n", uri);
+    eprintln!("Waiting for confirmation...");
+    Ok(())
+}
+
+/// The device-link URI in a line of `signal-cli link` output
+pub fn extract_link_uri(line: &str) -> Option<&str> {
+    ["sgnl://linkdevice", "tsdevice:"]
+        .iter()
+        .find_map(|scheme| line.find(scheme))
+        .map(|start| line[start..].split_whitespace().next().unwrap_or(""))
+        .filter(|uri| !uri.is_empty())
+}
+
+/// The account number in signal-cli's "Associated with: +123" line
+pub fn extract_linked_number(line: &str) -> Option<&str> {
+    line.split_whitespace()
+        .find(|word| word.starts_with('+') && word[1..].chars().all(|c| c.is_ascii_digit()))
+        .filter(|word| word.len() > 4)
+}
+
+/// Account numbers from a `listAccounts` result
+pub fn parse_accounts(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|accounts| {
+            accounts
+                .iter()
+                .filter_map(|a| a.get("number").and_then(|n| n.as_str()))
+                .map(|n| n.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn verify(backend: &Backend, account: &str) -> Result<()> {
+    let accounts = backend.list_accounts()?;
+    if !accounts.iter().any(|a| a == account) {
+        anyhow::bail!(
+            "{} is not registered with signal-cli (registered: {}). Run `sage signal link` first.",
+            account,
+            if accounts.is_empty() {
+                "none".to_string()
+            } else {
+                accounts.join(", ")
+            }
+        );
+    }
+    println!("{} is registered with signal-cli", account);
+    Ok(())
+}
+
+/// Blocking request/response JSON-RPC connection to the signal-cli daemon.
+/// Notifications (incoming messages) arriving in between are skipped.
+struct RpcConnection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    next_id: u64,
+}
+
+impl RpcConnection {
+    fn connect(host: &str, port: u16, timeout: Duration) -> Result<Self> {
+        let stream = TcpStream::connect((host, port)).with_context(|| {
+            format!(
+                "Failed to connect to signal-cli daemon at {}:{}",
+                host, port
+            )
+        })?;
+        stream.set_read_timeout(Some(timeout))?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+            next_id: 1,
+        })
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id });
+        self.writer
+            .write_all((serde_json::to_string(&request)? + "\n").as_bytes())?;
+        self.writer.flush()?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self
+                .reader
+                .read_line(&mut line)
+                .with_context(|| format!("No response from signal-cli to {}", method))?
+                == 0
+            {
+                anyhow::bail!("signal-cli closed the connection during {}", method);
+            }
+
+            let Ok(response) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if response.get("id").and_then(|v| v.as_u64()) != Some(id) {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                anyhow::bail!(
+                    "signal-cli {} failed: {}",
+                    method,
+                    error["message"].as_str().unwrap_or("unknown error")
+                );
+            }
+            return Ok(response["result"].clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args(&args("link")).unwrap(),
+            SignalCommand::Link {
+                device_name: "Sage".to_string(),
+                timeout: Duration::from_secs(300),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("link --name sage-prod --timeout 60")).unwrap(),
+            SignalCommand::Link {
+                device_name: "sage-prod".to_string(),
+                timeout: Duration::from_secs(60),
+            }
+        );
+        assert_eq!(
+            parse_args(&args("verify --account +15551234567")).unwrap(),
+            SignalCommand::Verify {
+                account: Some("+15551234567".to_string())
+            }
+        );
+        assert!(parse_args(&args("")).is_err());
+        assert!(parse_args(&args("register")).is_err());
+        assert!(parse_args(&args("link --timeout")).is_err());
+        assert!(parse_args(&args("link --color red")).is_err());
+    }
+
+    #[test]
+    fn test_extract_link_uri() {
+        assert_eq!(
+            extract_link_uri("sgnl://linkdevice?uuid=abc&pub_key=xyz"),
+            Some("sgnl://linkdevice?uuid=abc&pub_key=xyz")
+        );
+        assert_eq!(
+            extract_link_uri("tsdevice:/?uuid=abc&pub_key=xyz"),
+            Some("tsdevice:/?uuid=abc&pub_key=xyz")
+        );
+        assert_eq!(extract_link_uri("Associated with: +15551234567"), None);
+    }
+
+    #[test]
+    fn test_extract_linked_number() {
+        assert_eq!(
+            extract_linked_number("Associated with: +15551234567"),
+            Some("+15551234567")
+        );
+        assert_eq!(extract_linked_number("+ not a number"), None);
+    }
+
+    #[test]
+    fn test_parse_accounts() {
+        let value = json!([{ "number": "+15551234567" }, { "number": "+15557654321" }]);
+        assert_eq!(
+            parse_accounts(&value),
+            vec!["+15551234567".to_string(), "+15557654321".to_string()]
+        );
+        assert!(parse_accounts(&json!(null)).is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! Speech Transcription
+//!
+//! Transcribes voice messages (Signal voice notes, audio attachments) by
+//! uploading them to a Whisper-compatible `/audio/transcriptions` endpoint.
+//! The transcript is injected into the conversation as
+//! `[Voice message: ...]`, the same way vision descriptions are.
+
+use anyhow::{Context, Result};
+use tracing::{debug, info, warn};
+
+/// Prefix of the text injected for a transcribed voice message
+pub const VOICE_PREFIX: &str = "[Voice message: ";
+
+/// Audio MIME types we accept for transcription
+pub const SUPPORTED_AUDIO: &[&str] = &[
+    "audio/aac",
+    "audio/mp4",
+    "audio/x-m4a",
+    "audio/m4a",
+    "audio/mpeg",
+    "audio/mp3",
+    "audio/ogg",
+    "audio/wav",
+    "audio/x-wav",
+    "audio/webm",
+    "audio/flac",
+];
+
+/// Whisper-compatible transcription endpoint
+#[derive(Debug, Clone)]
+pub struct SpeechConfig {
+    /// Base URL, e.g. `https://api.openai.com/v1`
+    pub api_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+/// Check if a MIME type is audio we can transcribe
+pub fn is_supported_audio(content_type: &str) -> bool {
+    SUPPORTED_AUDIO.contains(&content_type)
+}
+
+/// File name to upload under. Whisper servers pick the decoder from the
+/// extension, and Signal voice notes (`audio/aac`) are AAC in an M4A container.
+fn upload_file_name(content_type: &str) -> &'static str {
+    match content_type {
+        "audio/aac" | "audio/mp4" | "audio/x-m4a" | "audio/m4a" => "voice.m4a",
+        "audio/mpeg" | "audio/mp3" => "voice.mp3",
+        "audio/ogg" => "voice.ogg",
+        "audio/wav" | "audio/x-wav" => "voice.wav",
+        "audio/webm" => "voice.webm",
+        "audio/flac" => "voice.flac",
+        _ => "voice.bin",
+    }
+}
+
+/// Format a transcript for injection into the conversation
+pub fn voice_note(transcript: &str) -> String {
+    let transcript = transcript.trim();
+    if transcript.is_empty() {
+        format!("{}(no speech detected)]", VOICE_PREFIX)
+    } else {
+        format!("{}{}]", VOICE_PREFIX, transcript)
+    }
+}
+
+/// Render a message's stored `attachment_text` for the conversation. Voice
+/// notes are stored already labelled; anything else is an image description.
+pub fn display_attachment_text(attachment_text: &str) -> String {
+    if attachment_text.starts_with(VOICE_PREFIX) {
+        attachment_text.to_string()
+    } else {
+        format!("[Uploaded Image: {}]", attachment_text)
+    }
+}
+
+/// Transcribe an audio file via the `/audio/transcriptions` endpoint
+pub async fn transcribe(
+    config: &SpeechConfig,
+    audio_path: &str,
+    content_type: &str,
+) -> Result<String> {
+    let audio = std::fs::read(audio_path)
+        .with_context(|| format!("Failed to read audio file: {}", audio_path))?;
+
+    info!(
+        "Transcribing audio ({}, {} bytes) with model {}",
+        content_type,
+        audio.len(),
+        config.model
+    );
+
+    let part = reqwest::multipart::Part::bytes(audio)
+        .file_name(upload_file_name(content_type))
+        .mime_str(content_type)
+        .context("Invalid audio content type")?;
+    let form = reqwest::multipart::Form::new()
+        .text("model", config.model.clone())
+        .text("response_format", "json")
+        .part("file", part);
+
+    let url = format!(
+        "{}/audio/transcriptions",
+        config.api_url.trim_end_matches('/')
+    );
+    debug!("Transcription request to {}", url);
+
+    let client = reqwest::Client::new();
+    let mut request = client.post(&url).multipart(form);
+    if let Some(ref key) = config.api_key {
+        request = request.header("Authorization", format!("Bearer {}", key));
+    }
+    let response = request
+        .send()
+        .await
+        .context("Failed to call transcription API")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        warn!("Transcription API error {}: {}", status, body);
+        anyhow::bail!("Transcription API returned {}: {}", status, body);
+    }
+
+    let json: serde_json::Value = response
+        .json()
+        .await
+        .context("Failed to parse transcription API response")?;
+    let transcript = json["text"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Transcription API response has no text"))?
+        .trim()
+        .to_string();
+
+    info!("Audio transcribed ({} chars)", transcript.len());
+    Ok(transcript)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_supported_audio() {
+        assert!(is_supported_audio("audio/aac"));
+        assert!(is_supported_audio("audio/ogg"));
+        assert!(!is_supported_audio("image/png"));
+        assert!(!is_supported_audio("audio/midi"));
+    }
+
+    #[test]
+    fn test_upload_file_name() {
+        assert_eq!(upload_file_name("audio/aac"), "voice.m4a");
+        assert_eq!(upload_file_name("audio/mpeg"), "voice.mp3");
+    }
+
+    #[test]
+    fn test_voice_note() {
+        assert_eq!(
+            voice_note(" call the dentist tomorrow \n"),
+            "[Voice message: call the dentist tomorrow]"
+        );
+        assert_eq!(voice_note(""), "[Voice message: (no speech detected)]");
+    }
+
+    #[test]
+    fn test_display_attachment_text() {
+        assert_eq!(
+            display_attachment_text("A red bicycle"),
+            "[Uploaded Image: A red bicycle]"
+        );
+        assert_eq!(
+            display_attachment_text("[Voice message: hello]"),
+            "[Voice message: hello]"
+        );
+    }
+}
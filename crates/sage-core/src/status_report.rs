@@ -0,0 +1,266 @@
+//! Owner Status Report
+//!
+//! With `STATUS_REPORT_CRON` set, each owner's (`OWNER_USERS`) direct chat
+//! agent gets a recurring `maintenance` task with the `status` routine,
+//! created when the agent is loaded. Each run sends the owner a summary of the last 24 hours
+//! across all agents, so silent degradations (failing tools, slow turns,
+//! runaway spend) get noticed without a metrics stack:
+//! - messages handled and turns run, with the average turn time
+//! - tool calls and how many failed, and turns that ended in an error
+//! - LLM spend (`llm_usage`), against `STATUS_BUDGET_USD` if set
+//! - memory growth: new messages and archival passages, and the totals
+//!
+//! Everything is read from tables Sage already writes (`messages`,
+//! `turn_events`, `llm_usage`, `passages`).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Nullable, Timestamptz};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::scheduler::{self, MaintenancePayload, SchedulerDb, TaskPayload, TaskType};
+
+/// `MaintenancePayload::routine` of the status report task
+pub const ROUTINE: &str = "status";
+
+/// Description of the task in `list_schedules`
+const TASK_DESCRIPTION: &str = "Daily status report";
+
+/// Hours a report covers
+const REPORT_HOURS: i64 = 24;
+
+/// Tool failure rate worth pointing out
+const TOOL_FAILURE_WARN_RATIO: f64 = 0.2;
+
+/// What the deployment did over the report period
+#[derive(Debug, Clone, Default, PartialEq, QueryableByName)]
+pub struct StatusReport {
+    /// User messages stored
+    #[diesel(sql_type = BigInt)]
+    pub messages_handled: i64,
+    /// Finished turns
+    #[diesel(sql_type = BigInt)]
+    pub turns: i64,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub avg_turn_ms: Option<f64>,
+    #[diesel(sql_type = BigInt)]
+    pub tool_calls: i64,
+    #[diesel(sql_type = BigInt)]
+    pub failed_tool_calls: i64,
+    /// Turns that ended in an error
+    #[diesel(sql_type = BigInt)]
+    pub turn_errors: i64,
+    #[diesel(sql_type = Double)]
+    pub cost_usd: f64,
+    #[diesel(sql_type = BigInt)]
+    pub tokens: i64,
+    /// Messages stored by anyone (users, Sage, tools)
+    #[diesel(sql_type = BigInt)]
+    pub new_messages: i64,
+    #[diesel(sql_type = BigInt)]
+    pub new_passages: i64,
+    #[diesel(sql_type = BigInt)]
+    pub total_messages: i64,
+    #[diesel(sql_type = BigInt)]
+    pub total_passages: i64,
+}
+
+impl StatusReport {
+    /// The message sent to the owner
+    pub fn summary(&self, budget_usd: Option<f64>) -> String {
+        let mut lines = vec![format!("Status for the last {}h:", REPORT_HOURS)];
+
+        let latency = match self.avg_turn_ms {
+            Some(ms) => format!(", {:.1}s per turn on average", ms / 1000.0),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "- Handled {} message{} in {} turn{}{}.",
+            self.messages_handled,
+            plural(self.messages_handled),
+            self.turns,
+            plural(self.turns),
+            latency
+        ));
+
+        let mut tools = format!(
+            "- Tools: {} call{}, {} failed",
+            self.tool_calls,
+            plural(self.tool_calls),
+            self.failed_tool_calls
+        );
+        if self.tool_calls > 0
+            && self.failed_tool_calls as f64 / self.tool_calls as f64 >= TOOL_FAILURE_WARN_RATIO
+        {
+            tools.push_str(" (worth a look)");
+        }
+        tools.push('.');
+        lines.push(tools);
+        if self.turn_errors > 0 {
+            lines.push(format!(
+                "- {} turn{} ended in an error.",
+                self.turn_errors,
+                plural(self.turn_errors)
+            ));
+        }
+
+        let spend = match budget_usd {
+            Some(budget) if budget > 0.0 => format!(
+                "- Spend: ${:.2} of ${:.2} ({:.0}%), {} tokens.",
+                self.cost_usd,
+                budget,
+                self.cost_usd / budget * 100.0,
+                self.tokens
+            ),
+            _ => format!("- Spend: ${:.2}, {} tokens.", self.cost_usd, self.tokens),
+        };
+        lines.push(spend);
+        if budget_usd.is_some_and(|budget| budget > 0.0 && self.cost_usd > budget) {
+            lines.push("- Over budget!".to_string());
+        }
+
+        lines.push(format!(
+            "- Memory: +{} messages ({} total), +{} archival passages ({} total).",
+            self.new_messages, self.total_messages, self.new_passages, self.total_passages
+        ));
+        lines.join("\n")
+    }
+}
+
+fn plural(n: i64) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Create the owner agent's recurring status report task unless it already
+/// has one. A task the owner cancelled counts, so cancelling opts out.
+pub fn ensure_scheduled(
+    scheduler_db: &SchedulerDb,
+    agent_id: Uuid,
+    cron: &str,
+    timezone: &str,
+) -> Result<bool> {
+    let existing = scheduler_db.get_tasks_by_agent(agent_id, None)?;
+    if existing.iter().any(|task| {
+        matches!(&task.payload, TaskPayload::Maintenance(p) if p.routine == ROUTINE)
+            && task.status != scheduler::TaskStatus::Completed
+            && task.status != scheduler::TaskStatus::Failed
+    }) {
+        return Ok(false);
+    }
+
+    let next_run_at = scheduler::next_cron_time(cron, timezone)?;
+    scheduler_db.create_task(
+        agent_id,
+        TaskType::Maintenance,
+        TaskPayload::Maintenance(MaintenancePayload {
+            routine: ROUTINE.to_string(),
+        }),
+        next_run_at,
+        Some(cron.to_string()),
+        timezone.to_string(),
+        TASK_DESCRIPTION.to_string(),
+    )?;
+    info!("Scheduled status reports for agent {} ({})", agent_id, cron);
+    Ok(true)
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct StatusDb {
+    conn: Arc<DbConn>,
+    /// Daily LLM budget the spend is compared against
+    budget_usd: Option<f64>,
+}
+
+impl StatusDb {
+    /// Create a new StatusDb with its own connection
+    pub fn connect(db_url: &str, budget_usd: Option<f64>) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            budget_usd,
+        })
+    }
+
+    /// Gather the report for the last `REPORT_HOURS`
+    pub fn collect(&self) -> Result<StatusReport> {
+        let since: DateTime<Utc> = Utc::now() - Duration::hours(REPORT_HOURS);
+        self.conn.run(|conn| {
+            diesel::sql_query(
+                "SELECT \
+                    (SELECT COUNT(*) FROM messages WHERE role = 'user' AND created_at >= $1) AS messages_handled, \
+                    (SELECT COUNT(*) FROM turn_events WHERE kind = 'end' AND created_at >= $1) AS turns, \
+                    (SELECT AVG(duration_ms)::FLOAT8 FROM turn_events WHERE kind = 'end' AND created_at >= $1) AS avg_turn_ms, \
+                    (SELECT COUNT(*) FROM turn_events WHERE kind = 'tool_call' AND created_at >= $1) AS tool_calls, \
+                    (SELECT COUNT(*) FROM turn_events WHERE kind = 'tool_call' AND created_at >= $1 \
+                        AND metadata->>'success' = 'false') AS failed_tool_calls, \
+                    (SELECT COUNT(*) FROM turn_events WHERE kind = 'error' AND created_at >= $1) AS turn_errors, \
+                    (SELECT COALESCE(SUM(cost_usd), 0) FROM llm_usage WHERE created_at >= $1) AS cost_usd, \
+                    (SELECT COALESCE(SUM(prompt_tokens + completion_tokens), 0)::BIGINT FROM llm_usage \
+                        WHERE created_at >= $1) AS tokens, \
+                    (SELECT COUNT(*) FROM messages WHERE created_at >= $1) AS new_messages, \
+                    (SELECT COUNT(*) FROM passages WHERE created_at >= $1) AS new_passages, \
+                    (SELECT COUNT(*) FROM messages) AS total_messages, \
+                    (SELECT COUNT(*) FROM passages) AS total_passages",
+            )
+            .bind::<Timestamptz, _>(since)
+            .get_result(conn)
+            .context("Failed to collect status report")
+        })
+    }
+
+    /// The owner's message for the last `REPORT_HOURS`
+    pub fn summary(&self) -> Result<String> {
+        Ok(self.collect()?.summary(self.budget_usd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let report = StatusReport {
+            messages_handled: 42,
+            turns: 40,
+            avg_turn_ms: Some(5300.0),
+            tool_calls: 10,
+            failed_tool_calls: 3,
+            turn_errors: 1,
+            cost_usd: 1.5,
+            tokens: 120_000,
+            new_messages: 130,
+            new_passages: 2,
+            total_messages: 9000,
+            total_passages: 310,
+        };
+        assert_eq!(
+            report.summary(Some(1.0)),
+            "Status for the last 24h:\n\
+             - Handled 42 messages in 40 turns, 5.3s per turn on average.\n\
+             - Tools: 10 calls, 3 failed (worth a look).\n\
+             - 1 turn ended in an error.\n\
+             - Spend: $1.50 of $1.00 (150%), 120000 tokens.\n\
+             - Over budget!\n\
+             - Memory: +130 messages (9000 total), +2 archival passages (310 total)."
+        );
+
+        // A quiet day
+        let quiet = StatusReport::default().summary(None);
+        assert!(quiet.contains("- Handled 0 messages in 0 turns."));
+        assert!(quiet.contains("- Tools: 0 calls, 0 failed."));
+        assert!(quiet.contains("- Spend: $0.00, 0 tokens."));
+        assert!(!quiet.contains("error"));
+    }
+}
@@ -5,11 +5,10 @@
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use diesel::pg::PgConnection;
 use diesel::prelude::*;
-use std::sync::Mutex;
 use uuid::Uuid;
 
+use crate::db::DbConn;
 use crate::schema::messages;
 
 /// Message row from the database (basic fields, embedding handled separately)
@@ -42,15 +41,14 @@ struct NewMessage<'a> {
 /// Message store for basic CRUD operations
 /// For full memory features (embeddings, search), use MemoryManager
 pub struct MessageStore {
-    conn: Mutex<PgConnection>,
+    conn: DbConn,
 }
 
 #[allow(dead_code)]
 impl MessageStore {
     pub fn new(database_url: &str) -> Result<Self> {
-        let conn = PgConnection::establish(database_url)?;
         Ok(Self {
-            conn: Mutex::new(conn),
+            conn: DbConn::connect(database_url)?,
         })
     }
 
@@ -72,35 +70,29 @@ impl MessageStore {
             content,
         };
 
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        self.conn.run(|conn| {
+            diesel::insert_into(messages::table)
+                .values(&new_message)
+                .execute(conn)?;
 
-        diesel::insert_into(messages::table)
-            .values(&new_message)
-            .execute(&mut *conn)?;
-
-        Ok(id)
+            Ok(id)
+        })
     }
 
     #[allow(dead_code)]
     pub fn get_recent_messages(&self, agent_id: Uuid, limit: i64) -> Result<Vec<Message>> {
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let mut results: Vec<Message> = messages::table
-            .filter(messages::agent_id.eq(agent_id))
-            .order(messages::sequence_id.desc())
-            .limit(limit)
-            .select(Message::as_select())
-            .load(&mut *conn)?;
-
-        // Reverse to get chronological order
-        results.reverse();
-        Ok(results)
+        self.conn.run(|conn| {
+            let mut results: Vec<Message> = messages::table
+                .filter(messages::agent_id.eq(agent_id))
+                .order(messages::sequence_id.desc())
+                .limit(limit)
+                .select(Message::as_select())
+                .load(conn)?;
+
+            // Reverse to get chronological order
+            results.reverse();
+            Ok(results)
+        })
     }
 
     /// Get messages by their IDs (for loading context window)
@@ -110,17 +102,14 @@ impl MessageStore {
             return Ok(Vec::new());
         }
 
-        let mut conn = self
-            .conn
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
-
-        let results: Vec<Message> = messages::table
-            .filter(messages::id.eq_any(ids))
-            .order(messages::sequence_id.asc())
-            .select(Message::as_select())
-            .load(&mut *conn)?;
+        self.conn.run(|conn| {
+            let results: Vec<Message> = messages::table
+                .filter(messages::id.eq_any(ids))
+                .order(messages::sequence_id.asc())
+                .select(Message::as_select())
+                .load(conn)?;
 
-        Ok(results)
+            Ok(results)
+        })
     }
 }
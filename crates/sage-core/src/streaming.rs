@@ -0,0 +1,555 @@
+//! Streaming Replies
+//!
+//! A step normally sends nothing until the whole LLM call is back, so a reply
+//! of three chat bubbles waits on the last bubble and every tool call after
+//! it. With `STREAM_RESPONSES`, the worker hands the agent a channel and the
+//! step streams the completion instead: the answer is the same
+//! `[[ ## messages ## ]]` / `[[ ## tool_calls ## ]]` layout the DSRs chat
+//! adapter asks for, and `MessageScanner` picks each string out of the
+//! messages array as soon as its closing quote arrives, so the worker can
+//! send the first bubble while the model is still writing the rest.
+//!
+//! The prompt is the one the predictor would send: the DSRs chat adapter
+//! renders it from the `AgentResponse` signature (`render_prompt`). The
+//! finished text is parsed here (`parse_response`), and a malformed answer
+//! goes through the correction agent. If the stream fails, the step falls
+//! back to the usual DSRs call with its retries, told which messages already
+//! went out (see `retry_input` and `merge_streamed`).
+//!
+//! All streamed calls share one HTTP client. Its read timeout bounds the wait
+//! for the next chunk, so a stalled stream fails (and falls back) instead of
+//! running into the turn watchdog.
+//!
+//! The same call serves turns the model router sends to a cheaper model
+//! (see `model_router`): the DSRs LM is process-wide, so another model is
+//! only reachable this way.
+
+use anyhow::{Context, Result};
+use dspy_rs::ChatAdapter;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::sage_agent::{AgentResponse, AgentResponseInput, ToolCall, AGENT_INSTRUCTION};
+use crate::usage::{self, CallKind};
+
+/// Where streamed completions go (set with the DSRs LM, see `configure`)
+#[derive(Clone)]
+struct Endpoint {
+    api_base: String,
+    api_key: String,
+    model: String,
+}
+
+static ENDPOINT: RwLock<Option<Endpoint>> = RwLock::new(None);
+
+/// Connect and read timeouts of streamed calls unless configured
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The HTTP client every streamed call uses (see `configure_timeouts`)
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn build_client(connect: Duration, read: Duration) -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(connect)
+        .read_timeout(read)
+        .build()
+        .context("Failed to build the streaming HTTP client")
+}
+
+/// Build the shared client with these timeouts: `read` is the longest wait
+/// for the next chunk of an answer. Call at startup, before the first
+/// streamed call; later calls have no effect.
+pub fn configure_timeouts(connect: Duration, read: Duration) -> Result<()> {
+    let _ = CLIENT.set(build_client(connect, read)?);
+    Ok(())
+}
+
+fn client() -> Result<&'static reqwest::Client> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = build_client(DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT)?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// Use this endpoint for streamed completions. Called alongside the DSRs
+/// LM setup, so both talk to the same model.
+pub fn configure(api_base: &str, api_key: &str, model: &str) {
+    if let Ok(mut endpoint) = ENDPOINT.write() {
+        *endpoint = Some(Endpoint {
+            api_base: api_base.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+        });
+    }
+}
+
+pub fn is_configured() -> bool {
+    ENDPOINT.read().map(|e| e.is_some()).unwrap_or(false)
+}
+
+/// A model other than the configured one, for one call
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelOverride {
+    pub model: String,
+    pub max_tokens: usize,
+}
+
+const MESSAGES_MARKER: &str = "[[ ## messages ## ]]";
+
+/// System and user message for a step, rendered by the DSRs chat adapter
+/// from the `AgentResponse` signature as the predictor would send them
+pub fn render_prompt(input: &AgentResponseInput) -> Result<(String, String)> {
+    let system = ChatAdapter
+        .format_system_message_typed_with_instruction::<AgentResponse>(Some(AGENT_INSTRUCTION))?;
+    let user = ChatAdapter.format_user_message_typed::<AgentResponse>(input);
+    Ok((system, user))
+}
+
+// ============================================================================
+// Server-sent events
+// ============================================================================
+
+/// Splits a streamed response body into the payloads of its `data:` lines.
+/// Chunks may end mid-line (or mid-character); the rest waits for the next.
+#[derive(Default)]
+pub struct SseDecoder {
+    pending: Vec<u8>,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let mut payloads = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                payloads.push(data.trim_start().to_string());
+            }
+        }
+        payloads
+    }
+}
+
+// ============================================================================
+// Incremental messages
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanState {
+    /// Waiting for the messages marker
+    Marker,
+    /// After the marker, waiting for the array to open
+    Open,
+    /// Between elements of the array
+    Between,
+    /// Inside a string that started at this byte offset
+    InString(usize),
+    /// Past the array (or it wasn't an array of strings)
+    Done,
+}
+
+/// Picks the messages out of a streamed answer as each one completes
+pub struct MessageScanner {
+    text: String,
+    pos: usize,
+    escaped: bool,
+    state: ScanState,
+}
+
+impl Default for MessageScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageScanner {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            pos: 0,
+            escaped: false,
+            state: ScanState::Marker,
+        }
+    }
+
+    /// The answer so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Add streamed text; returns the messages completed by it
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.text.push_str(delta);
+        let mut complete = Vec::new();
+
+        if self.state == ScanState::Marker {
+            match self.text.find(MESSAGES_MARKER) {
+                Some(at) => {
+                    self.pos = at + MESSAGES_MARKER.len();
+                    self.state = ScanState::Open;
+                }
+                None => return complete,
+            }
+        }
+
+        let rest = &self.text[self.pos..];
+        let mut consumed = rest.len();
+        for (offset, c) in rest.char_indices() {
+            let at = self.pos + offset;
+            match self.state {
+                ScanState::Open if c.is_whitespace() => {}
+                ScanState::Open if c == '[' => self.state = ScanState::Between,
+                ScanState::Between if c.is_whitespace() || c == ',' => {}
+                ScanState::Between if c == '"' => {
+                    self.state = ScanState::InString(at);
+                    self.escaped = false;
+                }
+                ScanState::InString(start) => {
+                    if self.escaped {
+                        self.escaped = false;
+                    } else if c == '\\' {
+                        self.escaped = true;
+                    } else if c == '"' {
+                        if let Ok(message) = serde_json::from_str::<String>(&self.text[start..=at])
+                        {
+                            complete.push(message);
+                        }
+                        self.state = ScanState::Between;
+                    }
+                }
+                ScanState::Marker | ScanState::Done => {}
+                // Anything else ends the array; the full parse handles it
+                _ => self.state = ScanState::Done,
+            }
+            if self.state == ScanState::Done {
+                consumed = offset;
+                break;
+            }
+        }
+        self.pos += consumed;
+        complete
+    }
+}
+
+// ============================================================================
+// Full answer
+// ============================================================================
+
+/// Value of a `[[ ## field ## ]]` section (up to the next marker)
+fn field<'a>(raw: &'a str, name: &str) -> Option<&'a str> {
+    let marker = format!("[[ ## {} ## ]]", name);
+    let start = raw.find(&marker)? + marker.len();
+    let rest = &raw[start..];
+    let end = rest.find("[[ ## ").unwrap_or(rest.len());
+    Some(strip_fences(rest[..end].trim()))
+}
+
+fn strip_fences(value: &str) -> &str {
+    let Some(inner) = value.strip_prefix("```") else {
+        return value;
+    };
+    let inner = inner.strip_prefix("json").unwrap_or(inner);
+    inner.strip_suffix("```").unwrap_or(inner).trim()
+}
+
+fn arg_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Messages and tool calls of a finished answer
+pub fn parse_response(raw: &str) -> Result<(Vec<String>, Vec<ToolCall>)> {
+    let messages = field(raw, "messages").context("The answer has no messages field")?;
+    let messages: Vec<String> =
+        serde_json::from_str(messages).context("messages is not a JSON array of strings")?;
+
+    let calls = field(raw, "tool_calls").context("The answer has no tool_calls field")?;
+    let calls: Vec<Value> =
+        serde_json::from_str(calls).context("tool_calls is not a JSON array")?;
+    let tool_calls = calls
+        .iter()
+        .map(|call| {
+            let name = call["name"]
+                .as_str()
+                .context("A tool call has no name")?
+                .to_string();
+            let args: HashMap<String, String> = match &call["args"] {
+                Value::Object(args) => args
+                    .iter()
+                    .map(|(key, value)| (key.clone(), arg_string(value)))
+                    .collect(),
+                Value::Null => HashMap::new(),
+                _ => anyhow::bail!("The args of {} are not an object", name),
+            };
+            Ok(ToolCall { name, args })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((messages, tool_calls))
+}
+
+/// A step's messages when the first `streamed` went out while streaming.
+/// Usually the answer starts with them; after a correction the rest are the
+/// messages not sent yet.
+pub fn merge_streamed(streamed: &[String], messages: Vec<String>) -> Vec<String> {
+    if messages.starts_with(streamed) {
+        return messages;
+    }
+    let mut merged = streamed.to_vec();
+    merged.extend(messages.into_iter().filter(|m| !streamed.contains(m)));
+    merged
+}
+
+/// The input of a non-streamed retry after `streamed` messages already went
+/// out: it lists them so the answer goes on from there instead of writing
+/// them again.
+pub fn retry_input(input: &AgentResponseInput, streamed: &[String]) -> AgentResponseInput {
+    let mut input = input.clone();
+    if !streamed.is_empty() {
+        let sent = streamed
+            .iter()
+            .enumerate()
+            .map(|(i, m)| format!("  {}. \"{}\"", i + 1, m))
+            .collect::<Vec<_>>()
+            .join("\n");
+        input.input.push_str(&format!(
+            "\n\n[Your answer was cut off after these messages, which the user already got:\n{}\nDo not send them again; only write what comes after them, and your tool calls.]",
+            sent
+        ));
+    }
+    input
+}
+
+// ============================================================================
+// Request
+// ============================================================================
+
+/// Stream a step's answer, calling `on_message` with each message of the
+/// messages array as soon as it is complete. Returns the whole answer.
+/// `model` replaces the configured model for this call.
+pub async fn complete(
+    input: &AgentResponseInput,
+    model: Option<&ModelOverride>,
+    mut on_message: impl FnMut(String),
+) -> Result<String> {
+    let endpoint = ENDPOINT
+        .read()
+        .ok()
+        .and_then(|e| e.clone())
+        .context("Streaming is not configured")?;
+    let (model, max_tokens) = match model {
+        Some(choice) => (choice.model.clone(), choice.max_tokens),
+        None => (
+            endpoint.model.clone(),
+            crate::model_limits::current().max_output_tokens,
+        ),
+    };
+    let (system, user) = render_prompt(input)?;
+    let body = json!({
+        "model": model,
+        "messages": [
+            {"role": "system", "content": system},
+            {"role": "user", "content": user},
+        ],
+        "temperature": 0.7,
+        "max_tokens": max_tokens,
+        "stream": true,
+        "stream_options": {"include_usage": true},
+    });
+
+    let mut response = client()?
+        .post(format!("{}/chat/completions", endpoint.api_base))
+        .header("Authorization", format!("Bearer {}", endpoint.api_key))
+        .header("Content-Type", "application/json")
+        .json(&body)
+        .send()
+        .await
+        .context("Failed to call the LLM API")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("LLM API returned {}: {}", status, body);
+    }
+
+    let mut decoder = SseDecoder::default();
+    let mut scanner = MessageScanner::new();
+    let mut prompt_tokens = 0;
+    let mut completion_tokens = 0;
+    'stream: while let Some(chunk) = response
+        .chunk()
+        .await
+        .context("LLM stream was interrupted")?
+    {
+        for payload in decoder.push(&chunk) {
+            if payload == "[DONE]" {
+                break 'stream;
+            }
+            let Ok(event) = serde_json::from_str::<Value>(&payload) else {
+                debug!("Skipping stream event: {}", payload);
+                continue;
+            };
+            if let Some(usage) = event.get("usage").filter(|u| !u.is_null()) {
+                prompt_tokens = usage["prompt_tokens"].as_i64().unwrap_or(0);
+                completion_tokens = usage["completion_tokens"].as_i64().unwrap_or(0);
+            }
+            if let Some(delta) = event["choices"][0]["delta"]["content"].as_str() {
+                for message in scanner.push(delta) {
+                    on_message(message);
+                }
+            }
+        }
+    }
+    usage::record(
+        CallKind::Agent,
+        None,
+        &model,
+        prompt_tokens,
+        completion_tokens,
+    );
+
+    Ok(scanner.text().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(chunks: &[&str]) -> Vec<Vec<String>> {
+        let mut scanner = MessageScanner::new();
+        chunks.iter().map(|chunk| scanner.push(chunk)).collect()
+    }
+
+    #[test]
+    fn test_scanner_emits_each_message_when_complete() {
+        let emitted = scan(&[
+            "[[ ## mess",
+            "ages ## ]]\n[\"Hi",
+            " there!\", \"Second \\\"quoted\\",
+            "\" one\"",
+            "]\n\n[[ ## tool_calls ## ]]\n[\"not a message\"]",
+        ]);
+        assert_eq!(
+            emitted,
+            vec![
+                vec![],
+                vec![],
+                vec!["Hi there!".to_string()],
+                vec!["Second \"quoted\" one".to_string()],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scanner_decodes_escapes_and_unicode() {
+        let emitted = scan(&["[[ ## messages ## ]]\n[\"line\\none \u{1F600}\", \"caf\\u00e9\"]"]);
+        assert_eq!(
+            emitted[0],
+            vec!["line\none \u{1F600}".to_string(), "café".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_scanner_stops_on_non_string_array() {
+        let emitted = scan(&["[[ ## messages ## ]]\n[]\n", "[\"later\"]"]);
+        assert!(emitted.iter().all(|m| m.is_empty()));
+        let emitted = scan(&["[[ ## messages ## ]]\n{\"a\": \"b\"}"]);
+        assert!(emitted[0].is_empty());
+    }
+
+    #[test]
+    fn test_sse_decoder_joins_split_lines() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"data: {\"a\"").is_empty());
+        assert_eq!(
+            decoder.push(b": 1}\n\n: keep-alive\ndata: [DONE]\n"),
+            vec!["{\"a\": 1}".to_string(), "[DONE]".to_string()]
+        );
+        // A character split across chunks
+        let bytes = "data: é\n".as_bytes();
+        assert!(decoder.push(&bytes[..7]).is_empty());
+        assert_eq!(decoder.push(&bytes[7..]), vec!["é".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let raw = "[[ ## messages ## ]]\n[\"Done!\"]\n\n[[ ## tool_calls ## ]]\n```json\n[{\"name\": \"set_reminder\", \"args\": {\"message\": \"Call mom\", \"minutes\": 30}}, {\"name\": \"done\"}]\n```\n\n[[ ## completed ## ]]";
+        let (messages, calls) = parse_response(raw).unwrap();
+        assert_eq!(messages, vec!["Done!".to_string()]);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].name, "set_reminder");
+        assert_eq!(calls[0].args["message"], "Call mom");
+        assert_eq!(calls[0].args["minutes"], "30");
+        assert!(calls[1].args.is_empty());
+
+        assert!(parse_response("[[ ## messages ## ]]\n[\"cut off").is_err());
+        assert!(parse_response("[[ ## messages ## ]]\n[]\n").is_err());
+    }
+
+    #[test]
+    fn test_merge_streamed() {
+        let s = |v: &[&str]| v.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+        assert_eq!(merge_streamed(&s(&["a"]), s(&["a", "b"])), s(&["a", "b"]));
+        assert_eq!(merge_streamed(&[], s(&["a"])), s(&["a"]));
+        // A correction that reworded nothing keeps the order of what was sent
+        assert_eq!(
+            merge_streamed(&s(&["a", "b"]), s(&["b", "c"])),
+            s(&["a", "b", "c"])
+        );
+    }
+
+    #[test]
+    fn test_retry_input_lists_sent_messages() {
+        let input = AgentResponseInput {
+            persona_block: String::new(),
+            available_tools: String::new(),
+            human_block: String::new(),
+            memory_blocks: String::new(),
+            is_first_time_user: false,
+            previous_context_summary: String::new(),
+            recent_conversation: String::new(),
+            memory_metadata: String::new(),
+            current_time: String::new(),
+            input: "hello".to_string(),
+        };
+        assert_eq!(retry_input(&input, &[]).input, "hello");
+
+        let retry = retry_input(&input, &["hi!".to_string(), "one sec".to_string()]);
+        assert!(retry.input.starts_with("hello\n\n"));
+        assert!(retry.input.contains("  1. \"hi!\"\n  2. \"one sec\""));
+        assert!(retry.input.contains("Do not send them again"));
+    }
+
+    #[test]
+    fn test_render_prompt_follows_the_signature() {
+        let input = AgentResponseInput {
+            persona_block: "I am Sage.".to_string(),
+            available_tools: "done: finish".to_string(),
+            human_block: String::new(),
+            memory_blocks: String::new(),
+            is_first_time_user: true,
+            previous_context_summary: String::new(),
+            recent_conversation: String::new(),
+            memory_metadata: String::new(),
+            current_time: "now".to_string(),
+            input: "hello".to_string(),
+        };
+        let (system, user) = render_prompt(&input).unwrap();
+        // The answer layout the scanner and `parse_response` read
+        assert!(system.contains("[[ ## messages ## ]]"));
+        assert!(system.contains("[[ ## tool_calls ## ]]"));
+        assert!(system.contains(AGENT_INSTRUCTION));
+        for name in ["persona_block", "recent_conversation", "input"] {
+            assert!(user.contains(&format!("[[ ## {} ## ]]", name)));
+        }
+        assert!(user.contains("hello"));
+    }
+}
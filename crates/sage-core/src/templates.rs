@@ -0,0 +1,205 @@
+//! Message templates for recurring notifications
+//!
+//! Scheduled messages and owner alerts can contain `{{variable}}`
+//! placeholders that are filled in when the message is sent, not when it was
+//! scheduled, so a daily reminder doesn't read as the same frozen text:
+//!
+//! - `{{date}}`: today's date in the user's timezone ("Sunday, October 18")
+//! - `{{time}}`: the local time ("9:00 AM")
+//! - `{{weekday}}`: the day of the week
+//! - `{{user_name}}`: the `display_name` preference, else the contact name
+//! - `{{weather}}`: a one-line forecast for the `location` preference (else
+//!   the timezone's city), from Brave Search
+//! - `{{report}}`: the generated text of an owner alert (`OWNER_ALERT_TEMPLATE`)
+//!
+//! Unknown names are left as written. A known variable that can't be
+//! resolved (no weather without `BRAVE_API_KEY`) renders empty.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+
+/// Variables a template can use
+pub const VARIABLES: &[&str] = &["date", "time", "weekday", "user_name", "weather", "report"];
+
+/// Longest weather line kept from a search result
+const MAX_WEATHER_CHARS: usize = 160;
+
+/// Values of a template's variables for one send
+#[derive(Debug, Clone, Default)]
+pub struct TemplateVars {
+    values: HashMap<&'static str, String>,
+}
+
+impl TemplateVars {
+    /// Date and time variables for `now` in `tz`
+    pub fn at(now: DateTime<Utc>, tz: Tz) -> Self {
+        let local = now.with_timezone(&tz);
+        Self::default()
+            .with("date", local.format("%A, %B %-d").to_string())
+            .with("time", local.format("%-I:%M %p").to_string())
+            .with("weekday", local.format("%A").to_string())
+    }
+
+    /// Set a variable (ignored unless it is one of `VARIABLES`)
+    pub fn with(mut self, name: &str, value: impl Into<String>) -> Self {
+        if let Some(name) = VARIABLES.iter().find(|v| **v == name) {
+            self.values.insert(*name, value.into());
+        }
+        self
+    }
+}
+
+/// Names of the `{{...}}` placeholders in a template, in order
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        names.push(rest[start + 2..start + 2 + len].trim());
+        rest = &rest[start + 2 + len + 2..];
+    }
+    names
+}
+
+/// Whether a template uses the variable `name`
+pub fn uses(template: &str, name: &str) -> bool {
+    placeholders(template).contains(&name)
+}
+
+/// Fill in a template's placeholders
+pub fn render(template: &str, vars: &TemplateVars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + len + 2;
+        let name = rest[start + 2..start + 2 + len].trim();
+        out.push_str(&rest[..start]);
+        if VARIABLES.contains(&name) {
+            out.push_str(vars.values.get(name).map_or("", |v| v.as_str()));
+        } else {
+            out.push_str(&rest[start..end]);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// City of an IANA timezone ("America/New_York" -> "New York"), for the
+/// weather when the user hasn't set a location
+pub fn timezone_city(timezone: &str) -> Option<String> {
+    let (_, city) = timezone.rsplit_once('/')?;
+    Some(city.replace('_', " "))
+}
+
+/// Today's weather for `location` in one line, from Brave's summary or the
+/// best result. None when the search has nothing usable.
+pub async fn weather(api_key: &str, location: &str) -> Result<Option<String>> {
+    let client = sage_tools::BraveClient::new(api_key.to_string())
+        .map_err(|e| anyhow::anyhow!("Brave client: {}", e))?;
+    let options = sage_tools::SearchOptions {
+        count: Some(3),
+        freshness: Some("pd".to_string()),
+        location: Some(location.to_string()),
+        timezone: None,
+    };
+    let results = client
+        .search(&format!("weather today in {}", location), Some(options))
+        .await
+        .map_err(|e| anyhow::anyhow!("Weather search failed: {}", e))?;
+
+    let text = results
+        .summary_text
+        .or_else(|| results.infobox.and_then(|i| i.description))
+        .or_else(|| {
+            results
+                .web
+                .and_then(|w| w.results)
+                .and_then(|r| r.into_iter().find_map(|r| r.description))
+        });
+    Ok(text.map(|t| first_sentence(&strip_tags(&t), MAX_WEATHER_CHARS)))
+}
+
+/// Drop HTML tags (Brave highlights matches with `<strong>`)
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// The first sentence of `text`, cut to `max` chars
+fn first_sentence(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let sentence = match text.find(". ") {
+        Some(end) => &text[..end + 1],
+        None => text.as_str(),
+    };
+    if sentence.chars().count() <= max {
+        return sentence.to_string();
+    }
+    let cut: String = sentence.chars().take(max - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 14, 5, 0).unwrap();
+        let vars = TemplateVars::at(now, chrono_tz::America::Chicago).with("user_name", "Ana");
+
+        assert_eq!(
+            render("Morning {{user_name}}! It's {{ date }}, {{time}}.", &vars),
+            "Morning Ana! It's Sunday, October 18, 9:05 AM."
+        );
+        // Known but unresolved renders empty, unknown is left alone
+        assert_eq!(render("Weather: {{weather}}", &vars), "Weather: ");
+        assert_eq!(render("{{nope}} and {{", &vars), "{{nope}} and {{");
+        assert_eq!(render("no placeholders", &vars), "no placeholders");
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders("{{date}} {{ weather }} {{x"),
+            vec!["date", "weather"]
+        );
+        assert!(uses("Today: {{weather}}", "weather"));
+        assert!(!uses("Today: {{date}}", "weather"));
+    }
+
+    #[test]
+    fn test_weather_text() {
+        assert_eq!(
+            timezone_city("America/New_York"),
+            Some("New York".to_string())
+        );
+        assert_eq!(timezone_city("UTC"), None);
+        assert_eq!(
+            first_sentence(
+                &strip_tags("<strong>Sunny</strong>, high of 72°F. Wind  light."),
+                160
+            ),
+            "Sunny, high of 72°F."
+        );
+        assert_eq!(first_sentence("abcdef", 4), "abc…");
+    }
+}
@@ -0,0 +1,254 @@
+//! Conversation Threads
+//!
+//! A direct chat can run several parallel threads ("/topic budget",
+//! "/topic trip"). Each thread is its own agent with separate conversation
+//! history and summaries, but it shares core memory, archival memory and
+//! preferences with the chat's main agent, so what Sage learns in one thread
+//! is known in all of them.
+//!
+//! A thread's chat context is keyed by `<identifier>#topic:<name>`; replies
+//! still go to the plain identifier. The topic each chat is currently in is
+//! kept in `active_topics`.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use diesel::prelude::*;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::db::DbConn;
+use crate::messenger::IncomingMessage;
+use crate::schema::{active_topics, chat_contexts};
+
+/// Separates a chat identifier from its topic in a thread's identifier
+pub const TOPIC_SEPARATOR: &str = "#topic:";
+
+/// Longest topic name, in characters
+const MAX_TOPIC_LEN: usize = 32;
+
+/// Topic names that switch back to the main thread
+const MAIN_TOPICS: &[&str] = &["main", "off", "end", "none"];
+
+/// A `/topic` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopicCommand {
+    /// `/topic`: show the current topic and the known ones
+    Show,
+    /// `/topic <name>`: switch to (or start) a topic
+    Switch(String),
+    /// `/topic main`: back to the main thread
+    Main,
+}
+
+/// Parse a `/topic` command. Returns None for any other message.
+pub fn parse_topic_command(text: &str) -> Option<TopicCommand> {
+    let text = text.trim();
+    let rest = text.strip_prefix("/topic")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let name = normalize_topic(rest);
+    if name.is_empty() {
+        Some(TopicCommand::Show)
+    } else if MAIN_TOPICS.contains(&name.as_str()) {
+        Some(TopicCommand::Main)
+    } else {
+        Some(TopicCommand::Switch(name))
+    }
+}
+
+/// Lowercase a topic name and reduce it to letters, digits and dashes
+fn normalize_topic(name: &str) -> String {
+    let mut topic = String::new();
+    for word in name.split_whitespace() {
+        let word: String = word
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+            .flat_map(char::to_lowercase)
+            .collect();
+        if word.is_empty() {
+            continue;
+        }
+        if !topic.is_empty() {
+            topic.push('-');
+        }
+        topic.push_str(&word);
+    }
+    topic.chars().take(MAX_TOPIC_LEN).collect()
+}
+
+/// Chat context identifier for a topic of a chat (the chat itself for None)
+pub fn thread_identifier(identifier: &str, topic: Option<&str>) -> String {
+    match topic {
+        Some(topic) => format!("{}{}{}", identifier, TOPIC_SEPARATOR, topic),
+        None => identifier.to_string(),
+    }
+}
+
+/// Split a chat context identifier into the chat identifier and its topic
+pub fn split_thread(identifier: &str) -> (&str, Option<&str>) {
+    match identifier.split_once(TOPIC_SEPARATOR) {
+        Some((chat, topic)) => (chat, Some(topic)),
+        None => (identifier, None),
+    }
+}
+
+/// Reply to a `/topic` command
+pub fn describe_topics(active: Option<&str>, topics: &[String]) -> String {
+    let current = match active {
+        Some(topic) => format!("You're in the \"{}\" topic.", topic),
+        None => "You're in the main conversation.".to_string(),
+    };
+    let others: Vec<&str> = topics
+        .iter()
+        .map(String::as_str)
+        .filter(|t| Some(*t) != active)
+        .collect();
+    let mut reply = current;
+    if !others.is_empty() {
+        reply.push_str(&format!(" Other topics: {}.", others.join(", ")));
+    }
+    reply.push_str(" Use /topic <name> to switch and /topic main to go back.");
+    reply
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct ThreadDb {
+    conn: Arc<DbConn>,
+}
+
+impl ThreadDb {
+    /// Create a new ThreadDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    /// Topic a chat is currently in (None for the main thread)
+    pub fn active_topic(&self, identifier: &str) -> Result<Option<String>> {
+        self.conn.run(|conn| {
+            active_topics::table
+                .filter(active_topics::identifier.eq(identifier))
+                .select(active_topics::topic)
+                .first(conn)
+                .optional()
+                .context("Failed to load active topic")
+        })
+    }
+
+    /// Switch a chat to a topic, or back to the main thread with None
+    pub fn set_active_topic(&self, identifier: &str, topic: Option<&str>) -> Result<()> {
+        self.conn.run(|conn| {
+            match topic {
+                Some(topic) => {
+                    diesel::insert_into(active_topics::table)
+                        .values((
+                            active_topics::identifier.eq(identifier),
+                            active_topics::topic.eq(topic),
+                        ))
+                        .on_conflict(active_topics::identifier)
+                        .do_update()
+                        .set((
+                            active_topics::topic.eq(topic),
+                            active_topics::updated_at.eq(Utc::now()),
+                        ))
+                        .execute(conn)
+                        .context("Failed to set active topic")?;
+                }
+                None => {
+                    diesel::delete(
+                        active_topics::table.filter(active_topics::identifier.eq(identifier)),
+                    )
+                    .execute(conn)
+                    .context("Failed to clear active topic")?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Chat context identifier a message is handled under: the active topic's
+    /// thread of a direct chat, or the chat itself. Group chats have no
+    /// threads.
+    pub fn agent_identifier(&self, msg: &IncomingMessage) -> String {
+        if msg.group_id().is_some() {
+            return msg.reply_to.clone();
+        }
+        let topic = self.active_topic(&msg.reply_to).unwrap_or_else(|e| {
+            warn!("Failed to load active topic for {}: {}", msg.reply_to, e);
+            None
+        });
+        thread_identifier(&msg.reply_to, topic.as_deref())
+    }
+
+    /// Topics a chat has used, oldest first
+    pub fn list_topics(&self, identifier: &str) -> Result<Vec<String>> {
+        self.conn.run(|conn| {
+            let prefix = thread_identifier(identifier, Some(""));
+            let identifiers: Vec<String> = chat_contexts::table
+                .filter(chat_contexts::signal_identifier.like(format!("{}%", escape_like(&prefix))))
+                .order(chat_contexts::created_at.asc())
+                .select(chat_contexts::signal_identifier)
+                .load(conn)
+                .context("Failed to list topics")?;
+
+            Ok(identifiers
+                .iter()
+                .filter_map(|id| split_thread(id).1.map(str::to_string))
+                .collect())
+        })
+    }
+}
+
+/// Escape LIKE wildcards in a literal prefix
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_topic_command() {
+        assert_eq!(parse_topic_command("/topic"), Some(TopicCommand::Show));
+        assert_eq!(
+            parse_topic_command(" /topic Budget "),
+            Some(TopicCommand::Switch("budget".to_string()))
+        );
+        assert_eq!(
+            parse_topic_command("/topic Summer trip!"),
+            Some(TopicCommand::Switch("summer-trip".to_string()))
+        );
+        assert_eq!(parse_topic_command("/topic main"), Some(TopicCommand::Main));
+        assert_eq!(parse_topic_command("/topics"), None);
+        assert_eq!(parse_topic_command("what's the topic?"), None);
+    }
+
+    #[test]
+    fn test_thread_identifier() {
+        let id = thread_identifier("user-1", Some("budget"));
+        assert_eq!(id, "user-1#topic:budget");
+        assert_eq!(split_thread(&id), ("user-1", Some("budget")));
+        assert_eq!(thread_identifier("user-1", None), "user-1");
+        assert_eq!(split_thread("group:abc"), ("group:abc", None));
+    }
+
+    #[test]
+    fn test_describe_topics() {
+        let topics = vec!["budget".to_string(), "trip".to_string()];
+        assert_eq!(
+            describe_topics(Some("budget"), &topics),
+            "You're in the \"budget\" topic. Other topics: trip. Use /topic <name> to switch and /topic main to go back."
+        );
+        assert!(describe_topics(None, &[]).starts_with("You're in the main conversation. Use"));
+    }
+}
@@ -0,0 +1,654 @@
+//! Shared Todos
+//!
+//! Todo lists that can be assigned to people, so a group chat can double as
+//! a coordination hub ("@bob takes trash duty by Friday 7pm").
+//!
+//! A list belongs to a scope, not an agent: a Signal group, a Marmot group, or
+//! a direct chat (see `scope_key`). In Marmot every member talks to their own
+//! agent, so scoping by `nostr_group_id` is what lets all of them see and tick
+//! off the same list. Marmot group members are known from the chats whose
+//! latest group is the list's group, which lets an assignee be resolved by
+//! display name (or pubkey prefix).
+//!
+//! A todo with a due time gets a one-off reminder. When the assignee is a
+//! known member it is delivered to them (in Marmot, into the group they last
+//! wrote from); otherwise it goes to the chat the todo was added in, addressed
+//! to the assignee by name. Completing a todo cancels its reminder.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::itinerary;
+use crate::sage_agent::{Tool, ToolResult};
+use crate::scheduler::{self, MessagePayload, SchedulerDb, TaskPayload, TaskType};
+use crate::schema::{chat_contexts, todos};
+use crate::threads;
+
+/// Scope prefix of Marmot group lists
+pub const MARMOT_PREFIX: &str = "marmot:";
+
+/// Todos shown by `list_todos`
+const LIST_LIMIT: i64 = 50;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A stored todo
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = todos)]
+#[allow(dead_code)]
+pub struct Todo {
+    pub id: Uuid,
+    pub scope: String,
+    pub title: String,
+    pub created_by: String,
+    pub assignee: Option<String>,
+    pub assignee_name: Option<String>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub reminder_task_id: Option<Uuid>,
+    pub status: String,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Diesel model for inserting a new todo
+#[derive(Insertable)]
+#[diesel(table_name = todos)]
+struct NewTodo {
+    id: Uuid,
+    scope: String,
+    title: String,
+    created_by: String,
+    assignee: Option<String>,
+    assignee_name: Option<String>,
+    due_at: Option<DateTime<Utc>>,
+}
+
+/// A known member of a group list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    /// Chat identifier (Marmot pubkey)
+    pub identifier: String,
+    /// The member's own agent, which delivers their reminders
+    pub agent_id: Uuid,
+    pub name: Option<String>,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// List scope for a chat: Signal groups use their identifier, Marmot chats
+/// the group they last wrote from, direct chats their identifier (shared by
+/// all of the chat's `/topic` threads)
+pub fn scope_key(
+    identifier: &str,
+    context_type: &str,
+    reply_context: Option<&str>,
+    marmot: bool,
+) -> String {
+    let chat = threads::split_thread(identifier).0;
+    match (context_type, reply_context) {
+        ("group", _) => chat.to_string(),
+        (_, Some(group)) if marmot && !group.is_empty() => format!("{}{}", MARMOT_PREFIX, group),
+        _ => chat.to_string(),
+    }
+}
+
+/// Find the member an assignment names: display name (whole or first word,
+/// case-insensitive, with or without "@"), or a pubkey prefix
+pub fn resolve_assignee<'a>(name: &str, members: &'a [Member]) -> Option<&'a Member> {
+    let name = name.trim().trim_start_matches('@').to_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+    let by_name = |exact: bool| {
+        members.iter().find(|m| {
+            m.name.as_ref().is_some_and(|n| {
+                let n = n.trim().to_lowercase();
+                if exact {
+                    n == name
+                } else {
+                    n.split_whitespace().next() == Some(name.as_str())
+                }
+            })
+        })
+    };
+    by_name(true).or_else(|| by_name(false)).or_else(|| {
+        (name.len() >= 8)
+            .then(|| {
+                members
+                    .iter()
+                    .find(|m| m.identifier.to_lowercase().starts_with(&name))
+            })
+            .flatten()
+    })
+}
+
+/// Due time from "YYYY-MM-DD HH:MM" in the user's timezone, or ISO 8601
+pub fn parse_due(due: &str, timezone: &str) -> Result<DateTime<Utc>> {
+    itinerary::local_to_utc(due, timezone)
+        .or_else(|_| scheduler::parse_datetime(due.trim()))
+        .map_err(|_| {
+            anyhow!(
+                "Invalid due time '{}'. Use 'YYYY-MM-DD HH:MM' (user's timezone) or ISO 8601",
+                due
+            )
+        })
+}
+
+/// Short id shown to the agent and accepted by `complete_todo`
+fn short_id(id: Uuid) -> String {
+    id.to_string()[..8].to_string()
+}
+
+/// One line per todo: "[1a2b3c4d] Take out the trash → Bob (due Fri Oct 23, 19:00 EDT)"
+pub fn format_todo(todo: &Todo, timezone: &str) -> String {
+    let mut line = format!(
+        "[{}] {}{}",
+        short_id(todo.id),
+        if todo.status == "done" { "✓ " } else { "" },
+        todo.title
+    );
+    if let Some(ref name) = todo.assignee_name {
+        line.push_str(&format!(" → {}", name));
+    }
+    if let Some(due) = todo.due_at {
+        line.push_str(&format!(
+            " (due {})",
+            itinerary::format_local(due, timezone)
+        ));
+    }
+    line
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct TodoDb {
+    conn: Arc<DbConn>,
+    /// Whether chats are Marmot chats, whose reply_context is their group
+    marmot: bool,
+}
+
+impl TodoDb {
+    /// Create a new TodoDb with its own connection
+    pub fn connect(db_url: &str, marmot: bool) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            marmot,
+        })
+    }
+
+    /// Scope of the list an agent works on (resolved per call: a Marmot
+    /// member's current group can change)
+    pub fn scope_for(&self, agent_id: Uuid) -> Result<String> {
+        self.conn.run(|conn| {
+            let (identifier, context_type, reply_context): (String, String, Option<String>) =
+                chat_contexts::table
+                    .filter(chat_contexts::id.eq(agent_id))
+                    .select((
+                        chat_contexts::signal_identifier,
+                        chat_contexts::context_type,
+                        chat_contexts::reply_context,
+                    ))
+                    .first(conn)
+                    .context("Failed to load chat context")?;
+            Ok(scope_key(
+                &identifier,
+                &context_type,
+                reply_context.as_deref(),
+                self.marmot,
+            ))
+        })
+    }
+
+    /// Chat identifier of an agent
+    pub fn identifier_of(&self, agent_id: Uuid) -> Result<String> {
+        self.conn.run(|conn| {
+            chat_contexts::table
+                .filter(chat_contexts::id.eq(agent_id))
+                .select(chat_contexts::signal_identifier)
+                .first(conn)
+                .context("Failed to load chat context")
+        })
+    }
+
+    /// Known members of a Marmot group list (empty for other scopes)
+    pub fn members(&self, scope: &str) -> Result<Vec<Member>> {
+        let Some(group) = scope.strip_prefix(MARMOT_PREFIX) else {
+            return Ok(Vec::new());
+        };
+        self.conn.run(|conn| {
+            let rows: Vec<(Uuid, String, Option<String>)> = chat_contexts::table
+                .filter(chat_contexts::reply_context.eq(group))
+                .select((
+                    chat_contexts::id,
+                    chat_contexts::signal_identifier,
+                    chat_contexts::display_name,
+                ))
+                .load(conn)
+                .context("Failed to load group members")?;
+            Ok(rows
+                .into_iter()
+                .map(|(agent_id, identifier, name)| Member {
+                    identifier,
+                    agent_id,
+                    name,
+                })
+                .collect())
+        })
+    }
+
+    fn insert(&self, todo: &NewTodo) -> Result<Todo> {
+        self.conn.run(|conn| {
+            diesel::insert_into(todos::table)
+                .values(todo)
+                .returning(Todo::as_returning())
+                .get_result(conn)
+                .context("Failed to insert todo")
+        })
+    }
+
+    fn set_reminder(&self, id: Uuid, task_id: Uuid) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::update(todos::table.filter(todos::id.eq(id)))
+                .set(todos::reminder_task_id.eq(task_id))
+                .execute(conn)
+                .context("Failed to link todo reminder")?;
+            Ok(())
+        })
+    }
+
+    /// Todos in a scope (`status` None = all), open ones by due time first
+    pub fn list(&self, scope: &str, status: Option<&str>) -> Result<Vec<Todo>> {
+        self.conn.run(|conn| {
+            let mut query = todos::table
+                .filter(todos::scope.eq(scope))
+                .select(Todo::as_select())
+                .into_boxed();
+            if let Some(status) = status {
+                query = query.filter(todos::status.eq(status.to_string()));
+            }
+            query
+                .order((
+                    todos::status.desc(),
+                    todos::due_at.asc().nulls_last(),
+                    todos::created_at.asc(),
+                ))
+                .limit(LIST_LIMIT)
+                .load(conn)
+                .context("Failed to query todos")
+        })
+    }
+
+    /// Mark the open todo whose id starts with `id_prefix` done. Returns it,
+    /// or None if no open todo in the scope matches.
+    pub fn complete(&self, scope: &str, id_prefix: &str) -> Result<Option<Todo>> {
+        let id_prefix = id_prefix.trim().trim_matches(['[', ']']).to_lowercase();
+        if id_prefix.len() < 4 {
+            anyhow::bail!("Give at least the first 4 characters of the todo id");
+        }
+        let open = self.list(scope, Some("open"))?;
+        let matching: Vec<&Todo> = open
+            .iter()
+            .filter(|t| t.id.to_string().starts_with(&id_prefix))
+            .collect();
+        let todo = match matching.as_slice() {
+            [] => return Ok(None),
+            [todo] => *todo,
+            _ => anyhow::bail!("'{}' matches several todos - use more of the id", id_prefix),
+        };
+
+        self.conn.run(|conn| {
+            diesel::update(todos::table.filter(todos::id.eq(todo.id)))
+                .set((
+                    todos::status.eq("done"),
+                    todos::completed_at.eq(Some(Utc::now())),
+                ))
+                .returning(Todo::as_returning())
+                .get_result(conn)
+                .map(Some)
+                .context("Failed to complete todo")
+        })
+    }
+}
+
+// ============================================================================
+// Tools
+// ============================================================================
+
+pub struct AddTodoTool {
+    todo_db: Arc<TodoDb>,
+    scheduler_db: Arc<SchedulerDb>,
+    agent_id: Uuid,
+    default_timezone: String,
+}
+
+impl AddTodoTool {
+    pub fn new(
+        todo_db: Arc<TodoDb>,
+        scheduler_db: Arc<SchedulerDb>,
+        agent_id: Uuid,
+        default_timezone: String,
+    ) -> Self {
+        Self {
+            todo_db,
+            scheduler_db,
+            agent_id,
+            default_timezone,
+        }
+    }
+
+    /// Schedule the due-time reminder: to the assignee's own chat when they
+    /// are a known member, otherwise to this chat addressed by name
+    fn schedule_reminder(&self, todo: &Todo, member: Option<&Member>) -> Result<Option<Uuid>> {
+        let Some(due_at) = todo.due_at.filter(|due| *due > Utc::now()) else {
+            return Ok(None);
+        };
+        let (agent_id, message) = match (member, &todo.assignee_name) {
+            (Some(member), _) => (
+                member.agent_id,
+                format!("⏰ Reminder: {} (assigned to you)", todo.title),
+            ),
+            (None, Some(name)) => (self.agent_id, format!("⏰ {}: {}", name, todo.title)),
+            (None, None) => (self.agent_id, format!("⏰ Reminder: {}", todo.title)),
+        };
+        let task = self.scheduler_db.create_task(
+            agent_id,
+            TaskType::Message,
+            TaskPayload::Message(MessagePayload { message }),
+            due_at,
+            None,
+            self.default_timezone.clone(),
+            format!("Todo reminder: {}", todo.title),
+        )?;
+        self.todo_db.set_reminder(todo.id, task.id)?;
+        Ok(Some(task.id))
+    }
+}
+
+#[async_trait]
+impl Tool for AddTodoTool {
+    fn name(&self) -> &str {
+        "add_todo"
+    }
+
+    fn description(&self) -> &str {
+        "Add a todo to this chat's shared list, optionally assigned to someone (e.g. '@bob takes trash duty') and with a due time. In a group everyone shares the list. With a due time, a reminder is sent automatically - to the assignee when they are a known group member, otherwise in this chat."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"title": "what needs doing", "assignee": "optional: who it is assigned to (name or @name)", "due": "optional: 'YYYY-MM-DD HH:MM' in the user's timezone, or ISO 8601"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let title = args.get("title").map(|s| s.trim()).unwrap_or("");
+        if title.is_empty() {
+            return Ok(ToolResult::error("Missing required argument: title"));
+        }
+        let due_at = match args.get("due").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            Some(due) => match parse_due(due, &self.default_timezone) {
+                Ok(due_at) => Some(due_at),
+                Err(e) => return Ok(ToolResult::error(e.to_string())),
+            },
+            None => None,
+        };
+
+        let scope = self.todo_db.scope_for(self.agent_id)?;
+        let assignee = args
+            .get("assignee")
+            .map(|s| s.trim().trim_start_matches('@').trim())
+            .filter(|s| !s.is_empty());
+        let members = match assignee {
+            Some(_) => self.todo_db.members(&scope)?,
+            None => Vec::new(),
+        };
+        let member = assignee.and_then(|name| resolve_assignee(name, &members));
+
+        let todo = self.todo_db.insert(&NewTodo {
+            id: Uuid::new_v4(),
+            scope,
+            title: title.to_string(),
+            created_by: self.todo_db.identifier_of(self.agent_id)?,
+            assignee: member.map(|m| m.identifier.clone()),
+            assignee_name: member
+                .and_then(|m| m.name.clone())
+                .or_else(|| assignee.map(str::to_string)),
+            due_at,
+        })?;
+        let reminder = self.schedule_reminder(&todo, member)?;
+        info!("Added todo {} in {}", todo.id, todo.scope);
+
+        let mut result = format!("Added: {}", format_todo(&todo, &self.default_timezone));
+        match (reminder, member, &todo.assignee_name) {
+            (Some(_), Some(_), Some(name)) => result.push_str(&format!(
+                "\nReminder will be sent to {} at the due time.",
+                name
+            )),
+            (Some(_), _, _) => result.push_str("\nReminder scheduled in this chat."),
+            _ => {}
+        }
+        Ok(ToolResult::success(result))
+    }
+}
+
+pub struct ListTodosTool {
+    todo_db: Arc<TodoDb>,
+    agent_id: Uuid,
+    default_timezone: String,
+}
+
+impl ListTodosTool {
+    pub fn new(todo_db: Arc<TodoDb>, agent_id: Uuid, default_timezone: String) -> Self {
+        Self {
+            todo_db,
+            agent_id,
+            default_timezone,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for ListTodosTool {
+    fn name(&self) -> &str {
+        "list_todos"
+    }
+
+    fn description(&self) -> &str {
+        "List this chat's shared todos with their ids, assignees and due times. Open todos by default."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"assignee": "optional: only todos assigned to this name", "status": "open (default), done, or all"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let status = match args.get("status").map(|s| s.trim()) {
+            None | Some("") | Some("open") => Some("open"),
+            Some("done") => Some("done"),
+            Some("all") => None,
+            Some(other) => {
+                return Ok(ToolResult::error(format!(
+                    "Invalid status '{}'. Use open, done or all",
+                    other
+                )))
+            }
+        };
+        let scope = self.todo_db.scope_for(self.agent_id)?;
+        let mut todos = self.todo_db.list(&scope, status)?;
+        if let Some(name) = args
+            .get("assignee")
+            .map(|s| s.trim().trim_start_matches('@').to_lowercase())
+            .filter(|s| !s.is_empty())
+        {
+            todos.retain(|t| {
+                t.assignee_name
+                    .as_ref()
+                    .is_some_and(|n| n.to_lowercase().contains(&name))
+            });
+        }
+        if todos.is_empty() {
+            return Ok(ToolResult::success("No matching todos."));
+        }
+        Ok(ToolResult::success(
+            todos
+                .iter()
+                .map(|t| format_todo(t, &self.default_timezone))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ))
+    }
+}
+
+pub struct CompleteTodoTool {
+    todo_db: Arc<TodoDb>,
+    scheduler_db: Arc<SchedulerDb>,
+    agent_id: Uuid,
+}
+
+impl CompleteTodoTool {
+    pub fn new(todo_db: Arc<TodoDb>, scheduler_db: Arc<SchedulerDb>, agent_id: Uuid) -> Self {
+        Self {
+            todo_db,
+            scheduler_db,
+            agent_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for CompleteTodoTool {
+    fn name(&self) -> &str {
+        "complete_todo"
+    }
+
+    fn description(&self) -> &str {
+        "Mark a shared todo as done (its pending reminder is cancelled). Use the id from list_todos."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"id": "todo id (the 8 characters shown by list_todos)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let Some(id) = args.get("id").filter(|s| !s.trim().is_empty()) else {
+            return Ok(ToolResult::error("Missing required argument: id"));
+        };
+        let scope = self.todo_db.scope_for(self.agent_id)?;
+        let todo = match self.todo_db.complete(&scope, id) {
+            Ok(Some(todo)) => todo,
+            Ok(None) => {
+                return Ok(ToolResult::error(format!(
+                    "No open todo with id '{}' in this chat",
+                    id.trim()
+                )))
+            }
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        if let Some(task_id) = todo.reminder_task_id {
+            self.scheduler_db.cancel_task(task_id)?;
+        }
+        Ok(ToolResult::success(format!("Done: {}", todo.title)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(identifier: &str, name: Option<&str>) -> Member {
+        Member {
+            identifier: identifier.to_string(),
+            agent_id: Uuid::new_v4(),
+            name: name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_scope_key() {
+        assert_eq!(scope_key("group:abc", "group", None, false), "group:abc");
+        assert_eq!(
+            scope_key("npub1alice", "direct", Some("g123"), true),
+            "marmot:g123"
+        );
+        // Email reply contexts are not groups
+        assert_eq!(
+            scope_key(
+                "alice@example.com",
+                "direct",
+                Some("{\"subject\":\"x\"}"),
+                false
+            ),
+            "alice@example.com"
+        );
+        assert_eq!(
+            scope_key("uuid-1#topic:budget", "direct", None, false),
+            "uuid-1"
+        );
+    }
+
+    #[test]
+    fn test_resolve_assignee() {
+        let members = vec![
+            member("npub1bobbbbbbbb", Some("Bob Smith")),
+            member("npub1carolcarol", Some("Carol")),
+            member("npub1daveeeeeee", None),
+        ];
+        assert_eq!(
+            resolve_assignee("@bob", &members).unwrap().identifier,
+            "npub1bobbbbbbbb"
+        );
+        assert_eq!(
+            resolve_assignee("CAROL", &members).unwrap().identifier,
+            "npub1carolcarol"
+        );
+        assert_eq!(
+            resolve_assignee("npub1dave", &members).unwrap().identifier,
+            "npub1daveeeeeee"
+        );
+        assert!(resolve_assignee("npub1", &members).is_none());
+        assert!(resolve_assignee("eve", &members).is_none());
+        assert!(resolve_assignee("@", &members).is_none());
+    }
+
+    #[test]
+    fn test_format_todo() {
+        let id = Uuid::parse_str("1a2b3c4d-0000-0000-0000-000000000000").unwrap();
+        let mut todo = Todo {
+            id,
+            scope: "marmot:g123".to_string(),
+            title: "Take out the trash".to_string(),
+            created_by: "npub1alice".to_string(),
+            assignee: None,
+            assignee_name: Some("Bob".to_string()),
+            due_at: Some(parse_due("2026-10-23 19:00", "America/New_York").unwrap()),
+            reminder_task_id: None,
+            status: "open".to_string(),
+            completed_at: None,
+            created_at: Utc::now(),
+        };
+        assert_eq!(
+            format_todo(&todo, "America/New_York"),
+            "[1a2b3c4d] Take out the trash → Bob (due Fri Oct 23, 19:00 EDT)"
+        );
+        todo.status = "done".to_string();
+        todo.due_at = None;
+        assert_eq!(
+            format_todo(&todo, "UTC"),
+            "[1a2b3c4d] ✓ Take out the trash → Bob"
+        );
+        assert!(parse_due("2026-10-23T23:00:00Z", "UTC").is_ok());
+        assert!(parse_due("friday", "UTC").is_err());
+    }
+}
@@ -3,10 +3,15 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use crate::messenger::{format_bytes, AttachmentOutbox, OutgoingAttachment, ReactionOutbox};
 use crate::sage_agent::{Tool, ToolResult};
 
+/// Largest file send_file will deliver (Signal's attachment limit)
+const MAX_SEND_BYTES: u64 = 100 * 1024 * 1024;
+
 /// Done tool - signals the agent is finished and doesn't need to send another message
 pub struct DoneTool;
 
@@ -29,15 +34,192 @@ impl Tool for DoneTool {
     }
 }
 
+/// Send file tool - queues a workspace file for delivery to the user
+pub struct SendFileTool {
+    workspace: PathBuf,
+    outbox: AttachmentOutbox,
+}
+
+impl SendFileTool {
+    pub fn new(workspace: impl Into<PathBuf>, outbox: AttachmentOutbox) -> Self {
+        Self {
+            workspace: workspace.into(),
+            outbox,
+        }
+    }
+}
+
+/// Resolve `path` against the workspace, refusing anything outside it
+fn resolve_workspace_file(workspace: &Path, path: &str) -> Result<PathBuf, String> {
+    let workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("Workspace unavailable: {}", e))?;
+    let resolved = workspace
+        .join(path.trim())
+        .canonicalize()
+        .map_err(|_| format!("File not found: {}", path))?;
+
+    if !resolved.starts_with(&workspace) {
+        return Err(format!("{} is outside the workspace", path));
+    }
+    if !resolved.is_file() {
+        return Err(format!("{} is not a file", path));
+    }
+    Ok(resolved)
+}
+
+#[async_trait]
+impl Tool for SendFileTool {
+    fn name(&self) -> &str {
+        "send_file"
+    }
+
+    fn description(&self) -> &str {
+        "Send a file from your workspace to the user (an image, PDF, CSV, etc. you created or downloaded). Delivered right after this step's messages."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"path": "file path, relative to the workspace", "caption": "optional text sent with the file"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let Some(path) = args.get("path").filter(|p| !p.trim().is_empty()) else {
+            return Ok(ToolResult::error("Missing required argument: path"));
+        };
+
+        let resolved = match resolve_workspace_file(&self.workspace, path) {
+            Ok(resolved) => resolved,
+            Err(e) => return Ok(ToolResult::error(e)),
+        };
+        let size = std::fs::metadata(&resolved)?.len();
+        if size > MAX_SEND_BYTES {
+            return Ok(ToolResult::error(format!(
+                "{} is {}, over the {} limit",
+                path,
+                format_bytes(size),
+                format_bytes(MAX_SEND_BYTES)
+            )));
+        }
+
+        let caption = args
+            .get("caption")
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty());
+        self.outbox
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Outbox lock error: {}", e))?
+            .push(OutgoingAttachment {
+                path: resolved,
+                caption,
+            });
+
+        Ok(ToolResult::success(format!(
+            "Queued {} ({}) for delivery.",
+            path,
+            format_bytes(size)
+        )))
+    }
+}
+
+/// React tool - queues an emoji reaction to the user's latest message
+pub struct ReactTool {
+    outbox: ReactionOutbox,
+}
+
+impl ReactTool {
+    pub fn new(outbox: ReactionOutbox) -> Self {
+        Self { outbox }
+    }
+}
+
+/// Whether `s` is a single emoji (possibly a multi-codepoint sequence)
+fn is_emoji(s: &str) -> bool {
+    !s.is_empty() && s.chars().count() <= 8 && !s.chars().any(|c| c.is_ascii())
+}
+
+#[async_trait]
+impl Tool for ReactTool {
+    fn name(&self) -> &str {
+        "react"
+    }
+
+    fn description(&self) -> &str {
+        "React to the user's latest message with an emoji (e.g. 👍 for \"thanks!\" or \"ok\"). Use instead of a reply for simple acknowledgements; combine with done if nothing else needs saying."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"emoji": "a single emoji, e.g. 👍 ❤️ 😂"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let Some(emoji) = args.get("emoji").map(|e| e.trim()) else {
+            return Ok(ToolResult::error("Missing required argument: emoji"));
+        };
+        if !is_emoji(emoji) {
+            return Ok(ToolResult::error(format!(
+                "'{}' is not a single emoji",
+                emoji
+            )));
+        }
+
+        self.outbox
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Outbox lock error: {}", e))?
+            .push(emoji.to_string());
+        Ok(ToolResult::success(format!("Reacted with {}.", emoji)))
+    }
+}
+
+/// Web search options (`WEB_SEARCH_COUNT`, `WEB_SEARCH_FRESHNESS`,
+/// `BRAVE_SUMMARIZER`)
+#[derive(Debug, Clone)]
+pub struct WebSearchConfig {
+    /// Results per search when the agent doesn't ask for a count
+    pub default_count: u32,
+    /// Freshness filter when the agent doesn't give one (None = any age)
+    pub default_freshness: Option<String>,
+    /// Fetch Brave's AI summary of the results
+    pub summarizer: bool,
+}
+
+impl Default for WebSearchConfig {
+    fn default() -> Self {
+        Self {
+            default_count: 10,
+            default_freshness: None,
+            summarizer: true,
+        }
+    }
+}
+
+/// Brave freshness filter: `pd`, `pw`, `pm`, `py` or a date range like
+/// `2026-01-01to2026-06-30`
+pub fn parse_freshness(value: &str) -> Option<String> {
+    let value = value.trim().to_lowercase();
+    if matches!(value.as_str(), "pd" | "pw" | "pm" | "py") {
+        return Some(value);
+    }
+    let (from, to) = value.split_once("to")?;
+    let date = |d: &str| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d");
+    match (date(from), date(to)) {
+        (Ok(from), Ok(to)) if from <= to => Some(value),
+        _ => None,
+    }
+}
+
 /// Web search tool implementation using Brave Search API (Pro)
 pub struct WebSearchTool {
     client: Arc<sage_tools::BraveClient>,
+    config: WebSearchConfig,
 }
 
 impl WebSearchTool {
-    pub fn new(api_key: &str) -> Result<Self> {
+    pub fn new(api_key: &str, config: WebSearchConfig) -> Result<Self> {
+        let client =
+            sage_tools::BraveClient::new(api_key.to_string())?.with_summarizer(config.summarizer);
         Ok(Self {
-            client: Arc::new(sage_tools::BraveClient::new(api_key.to_string())?),
+            client: Arc::new(client),
+            config,
         })
     }
 }
@@ -54,7 +236,7 @@ impl Tool for WebSearchTool {
     }
 
     fn args_schema(&self) -> &str {
-        r#"{ "query": "search query", "count": "results (default 10)", "freshness": "pd=24h, pw=week, pm=month (optional)", "location": "city or 'city, state' for local results (optional)" }"#
+        r#"{ "query": "search query", "count": "number of results (optional)", "freshness": "pd=24h, pw=week, pm=month (optional)", "location": "city or 'city, state' for local results (optional)" }"#
     }
 
     async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
@@ -63,8 +245,15 @@ impl Tool for WebSearchTool {
             .ok_or_else(|| anyhow::anyhow!("query argument required"))?;
 
         let options = sage_tools::SearchOptions {
-            count: args.get("count").and_then(|c| c.parse().ok()),
-            freshness: args.get("freshness").cloned(),
+            count: Some(
+                args.get("count")
+                    .and_then(|c| c.parse().ok())
+                    .unwrap_or(self.config.default_count),
+            ),
+            freshness: args
+                .get("freshness")
+                .cloned()
+                .or_else(|| self.config.default_freshness.clone()),
             location: args.get("location").cloned(),
             timezone: None,
         };
@@ -78,3 +267,47 @@ impl Tool for WebSearchTool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_freshness() {
+        assert_eq!(parse_freshness(" PW "), Some("pw".to_string()));
+        assert_eq!(
+            parse_freshness("2026-01-01to2026-06-30"),
+            Some("2026-01-01to2026-06-30".to_string())
+        );
+        assert_eq!(parse_freshness("2026-06-30to2026-01-01"), None);
+        assert_eq!(parse_freshness("week"), None);
+    }
+
+    #[test]
+    fn test_resolve_workspace_file() {
+        let workspace =
+            std::env::temp_dir().join(format!("sage-send-file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(workspace.join("out")).unwrap();
+        std::fs::write(workspace.join("out/chart.png"), b"png").unwrap();
+
+        let resolved = resolve_workspace_file(&workspace, "out/chart.png").unwrap();
+        assert!(resolved.ends_with("out/chart.png"));
+        assert!(resolve_workspace_file(&workspace, "missing.png").is_err());
+        assert!(resolve_workspace_file(&workspace, "out").is_err());
+        assert!(resolve_workspace_file(&workspace, "../../etc/passwd").is_err());
+        assert!(resolve_workspace_file(&workspace, "/etc/passwd").is_err());
+
+        std::fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[test]
+    fn test_is_emoji() {
+        assert!(is_emoji("👍"));
+        assert!(is_emoji("❤️"));
+        assert!(is_emoji("👨‍👩‍👧"));
+        assert!(!is_emoji(""));
+        assert!(!is_emoji("ok"));
+        assert!(!is_emoji(":thumbsup:"));
+        assert!(!is_emoji("👍 thanks"));
+    }
+}
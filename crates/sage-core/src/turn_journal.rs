@@ -0,0 +1,629 @@
+//! Turn Journal
+//!
+//! Records what happened during each agent turn - the incoming message, the
+//! input handed to the LLM at every step, tool calls with their results,
+//! outgoing messages, errors, and timings - in the `turn_events` table.
+//!
+//! A full transcript for one turn can be fetched by turn ID over HTTP
+//! (`GET /turns/{turn_id}`) or by the agent itself via the `turn_transcript`
+//! tool, which helps answer "why did you do that?".
+//!
+//! `explain_last_action` condenses the last few turns into a factual record
+//! the agent explains itself from, rather than confabulating a rationale.
+//!
+//! Journal writes are best-effort: a failure is logged and never fails the turn.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::db::DbConn;
+use crate::sage_agent::{StepResult, Tool, ToolResult};
+use crate::schema::turn_events;
+
+/// Longest tool output / LLM input kept per event in the text transcript
+const TRANSCRIPT_FIELD_MAX_CHARS: usize = 4000;
+
+/// A single journaled event
+#[derive(Debug, Clone, Queryable, Selectable, Serialize)]
+#[diesel(table_name = turn_events)]
+pub struct TurnEvent {
+    pub id: Uuid,
+    pub turn_id: Uuid,
+    pub agent_id: Uuid,
+    pub seq: i32,
+    pub step: Option<i32>,
+    pub kind: String,
+    pub content: String,
+    pub metadata: serde_json::Value,
+    pub duration_ms: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Diesel model for inserting a new event
+#[derive(Insertable)]
+#[diesel(table_name = turn_events)]
+struct NewTurnEvent<'a> {
+    id: Uuid,
+    turn_id: Uuid,
+    agent_id: Uuid,
+    seq: i32,
+    step: Option<i32>,
+    kind: &'a str,
+    content: &'a str,
+    metadata: serde_json::Value,
+    duration_ms: Option<i64>,
+}
+
+/// All events of one turn, in order
+#[derive(Debug, Clone, Serialize)]
+pub struct TurnTranscript {
+    pub turn_id: Uuid,
+    pub agent_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub events: Vec<TurnEvent>,
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+pub struct TurnJournal {
+    conn: Arc<DbConn>,
+}
+
+impl TurnJournal {
+    /// Create a new TurnJournal with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert(
+        &self,
+        turn_id: Uuid,
+        agent_id: Uuid,
+        seq: i32,
+        step: Option<i32>,
+        kind: &str,
+        content: &str,
+        metadata: serde_json::Value,
+        duration_ms: Option<i64>,
+    ) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::insert_into(turn_events::table)
+                .values(&NewTurnEvent {
+                    id: Uuid::new_v4(),
+                    turn_id,
+                    agent_id,
+                    seq,
+                    step,
+                    kind,
+                    content,
+                    metadata,
+                    duration_ms,
+                })
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Load the transcript for a turn (None if no events were recorded)
+    pub fn transcript(&self, turn_id: Uuid) -> Result<Option<TurnTranscript>> {
+        self.conn.run(|conn| {
+            let events = turn_events::table
+                .filter(turn_events::turn_id.eq(turn_id))
+                .order(turn_events::seq.asc())
+                .select(TurnEvent::as_select())
+                .load(conn)?;
+
+            let Some(first) = events.first() else {
+                return Ok(None);
+            };
+            Ok(Some(TurnTranscript {
+                turn_id,
+                agent_id: first.agent_id,
+                started_at: first.created_at,
+                events,
+            }))
+        })
+    }
+
+    /// Most recent finished turn for an agent
+    pub fn last_finished_turn(&self, agent_id: Uuid) -> Result<Option<Uuid>> {
+        Ok(self.recent_finished_turns(agent_id, 1)?.into_iter().next())
+    }
+
+    /// Most recent finished turns for an agent, newest first
+    pub fn recent_finished_turns(&self, agent_id: Uuid, limit: i64) -> Result<Vec<Uuid>> {
+        self.conn.run(|conn| {
+            let turn_ids = turn_events::table
+                .filter(turn_events::agent_id.eq(agent_id))
+                .filter(turn_events::kind.eq("end"))
+                .order(turn_events::created_at.desc())
+                .limit(limit)
+                .select(turn_events::turn_id)
+                .load(conn)?;
+
+            Ok(turn_ids)
+        })
+    }
+}
+
+// ============================================================================
+// Recording
+// ============================================================================
+
+/// Records the events of one in-progress turn
+pub struct TurnRecorder {
+    journal: Arc<TurnJournal>,
+    turn_id: Uuid,
+    agent_id: Uuid,
+    seq: AtomicI32,
+    started: Instant,
+}
+
+impl TurnRecorder {
+    /// Start a new turn
+    pub fn start(journal: Arc<TurnJournal>, agent_id: Uuid) -> Self {
+        Self {
+            journal,
+            turn_id: Uuid::new_v4(),
+            agent_id,
+            seq: AtomicI32::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn turn_id(&self) -> Uuid {
+        self.turn_id
+    }
+
+    fn record(
+        &self,
+        step: Option<i32>,
+        kind: &str,
+        content: &str,
+        metadata: serde_json::Value,
+        duration_ms: Option<u64>,
+    ) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.journal.insert(
+            self.turn_id,
+            self.agent_id,
+            seq,
+            step,
+            kind,
+            content,
+            metadata,
+            duration_ms.map(|ms| ms as i64),
+        ) {
+            warn!(
+                "Failed to journal {} event for turn {}: {}",
+                kind, self.turn_id, e
+            );
+        }
+    }
+
+    /// The user message (after attachment pre-processing) that started the turn
+    pub fn input(&self, sender: &str, message: &str) {
+        self.record(
+            None,
+            "input",
+            message,
+            serde_json::json!({ "sender": sender }),
+            None,
+        );
+    }
+
+    /// One agent step: the LLM input, then each tool call with its result
+    pub fn step(&self, step: usize, result: &StepResult) {
+        let step = Some(step as i32);
+        let tool_names: Vec<&str> = result.tool_calls.iter().map(|t| t.name.as_str()).collect();
+        self.record(
+            step,
+            "step",
+            &result.input,
+            serde_json::json!({
+                "messages": result.messages.len(),
+                "tool_calls": tool_names,
+                "done": result.done,
+            }),
+            Some(result.llm_duration_ms),
+        );
+
+        for executed in &result.executed_tools {
+            let content = if executed.result.success {
+                executed.result.output.clone()
+            } else {
+                executed
+                    .result
+                    .error
+                    .clone()
+                    .unwrap_or_else(|| executed.result.output.clone())
+            };
+            self.record(
+                step,
+                "tool_call",
+                &content,
+                serde_json::json!({
+                    "tool": executed.tool_call.name,
+                    "args": executed.tool_call.args,
+                    "success": executed.result.success,
+                }),
+                Some(executed.duration_ms),
+            );
+        }
+    }
+
+    /// A message sent back to the user
+    pub fn output(&self, step: usize, message: &str) {
+        self.record(
+            Some(step as i32),
+            "output",
+            message,
+            serde_json::json!({}),
+            None,
+        );
+    }
+
+    /// An error that ended the turn early
+    pub fn error(&self, step: usize, error: &str) {
+        self.record(
+            Some(step as i32),
+            "error",
+            error,
+            serde_json::json!({}),
+            None,
+        );
+    }
+
+    /// Mark the turn finished, with its total duration
+    pub fn finish(&self, steps: usize) {
+        self.record(
+            None,
+            "end",
+            "",
+            serde_json::json!({ "steps": steps }),
+            Some(self.started.elapsed().as_millis() as u64),
+        );
+    }
+}
+
+// ============================================================================
+// Formatting
+// ============================================================================
+
+/// Render a transcript as plain text
+pub fn format_transcript(transcript: &TurnTranscript) -> String {
+    let mut out = format!(
+        "Turn {} (started {})\n",
+        transcript.turn_id,
+        transcript.started_at.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+
+    for event in &transcript.events {
+        let timing = event
+            .duration_ms
+            .map(|ms| format!(" [{}ms]", ms))
+            .unwrap_or_default();
+        let content = truncate(&event.content, TRANSCRIPT_FIELD_MAX_CHARS);
+
+        let line = match event.kind.as_str() {
+            "input" => format!("\n== Input ==\n{}", content),
+            "step" => format!(
+                "\n== Step {}{} ==\nLLM input:\n{}\nTool calls: {}",
+                event.step.unwrap_or(0),
+                timing,
+                content,
+                event.metadata["tool_calls"]
+                    .as_array()
+                    .map(|calls| calls
+                        .iter()
+                        .filter_map(|c| c.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "))
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| "none".to_string())
+            ),
+            "tool_call" => format!(
+                "-> {}({}){} {}:\n{}",
+                event.metadata["tool"].as_str().unwrap_or("?"),
+                event.metadata["args"],
+                timing,
+                if event.metadata["success"].as_bool().unwrap_or(false) {
+                    "ok"
+                } else {
+                    "FAILED"
+                },
+                content
+            ),
+            "output" => format!("<- sent: {}", content),
+            "error" => format!("!! error: {}", content),
+            "end" => format!(
+                "\n== End ({} steps){} ==",
+                event.metadata["steps"].as_u64().unwrap_or(0),
+                timing
+            ),
+            other => format!("[{}] {}", other, content),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Condensed, factual record of recent turns (oldest first) for the agent to
+/// explain its own actions from. Only what was journaled is included.
+pub fn format_action_record(transcripts: &[TurnTranscript]) -> String {
+    const INPUT_MAX_CHARS: usize = 500;
+    const RESULT_MAX_CHARS: usize = 300;
+
+    let mut out = String::from(
+        "Factual record of your recent actions (from the turn journal). Explain what you did \
+         and why using ONLY this record. Your private reasoning was not recorded: if the \
+         record doesn't show why you did something, say so honestly instead of guessing.\n",
+    );
+
+    for transcript in transcripts {
+        out.push_str(&format!(
+            "\n## Turn {} ({})\n",
+            transcript.turn_id,
+            transcript.started_at.format("%Y-%m-%d %H:%M UTC")
+        ));
+        for event in &transcript.events {
+            let line = match event.kind.as_str() {
+                "input" => format!("User said: {}", truncate(&event.content, INPUT_MAX_CHARS)),
+                "tool_call" => format!(
+                    "Step {}: called {} with {} -> {}: {}",
+                    event.step.unwrap_or(0),
+                    event.metadata["tool"].as_str().unwrap_or("?"),
+                    event.metadata["args"],
+                    if event.metadata["success"].as_bool().unwrap_or(false) {
+                        "ok"
+                    } else {
+                        "failed"
+                    },
+                    truncate(&event.content, RESULT_MAX_CHARS)
+                ),
+                "output" => format!(
+                    "Step {}: you replied: {}",
+                    event.step.unwrap_or(0),
+                    truncate(&event.content, INPUT_MAX_CHARS)
+                ),
+                "error" => format!("Error: {}", event.content),
+                _ => continue,
+            };
+            out.push_str("- ");
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        s.to_string()
+    } else {
+        let cut: String = s.chars().take(max_chars).collect();
+        format!("{}... [truncated]", cut)
+    }
+}
+
+// ============================================================================
+// Tool
+// ============================================================================
+
+/// Lets the agent look at the transcript of one of its own turns
+pub struct TurnTranscriptTool {
+    journal: Arc<TurnJournal>,
+    agent_id: Uuid,
+}
+
+impl TurnTranscriptTool {
+    pub fn new(journal: Arc<TurnJournal>, agent_id: Uuid) -> Self {
+        Self { journal, agent_id }
+    }
+}
+
+#[async_trait]
+impl Tool for TurnTranscriptTool {
+    fn name(&self) -> &str {
+        "turn_transcript"
+    }
+
+    fn description(&self) -> &str {
+        "Show the full transcript of one of your past turns: the input, what you saw at each step, every tool call with its result, the messages you sent, and timings. Use it when the user asks why you did something."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"turn_id": "turn UUID (optional, defaults to your previous turn)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let turn_id = match args
+            .get("turn_id")
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+        {
+            Some(id) => match Uuid::parse_str(id) {
+                Ok(id) => id,
+                Err(_) => return Ok(ToolResult::error(format!("Invalid turn_id '{}'", id))),
+            },
+            None => match self.journal.last_finished_turn(self.agent_id)? {
+                Some(id) => id,
+                None => return Ok(ToolResult::error("No finished turns recorded yet")),
+            },
+        };
+
+        match self.journal.transcript(turn_id)? {
+            Some(transcript) if transcript.agent_id == self.agent_id => {
+                Ok(ToolResult::success(format_transcript(&transcript)))
+            }
+            _ => Ok(ToolResult::error(format!("Turn {} not found", turn_id))),
+        }
+    }
+}
+
+/// Lets the agent explain its recent actions from the journal instead of
+/// reconstructing them from memory
+pub struct ExplainLastActionTool {
+    journal: Arc<TurnJournal>,
+    agent_id: Uuid,
+}
+
+impl ExplainLastActionTool {
+    pub fn new(journal: Arc<TurnJournal>, agent_id: Uuid) -> Self {
+        Self { journal, agent_id }
+    }
+}
+
+#[async_trait]
+impl Tool for ExplainLastActionTool {
+    fn name(&self) -> &str {
+        "explain_last_action"
+    }
+
+    fn description(&self) -> &str {
+        "Retrieve the factual record of your recent turns (what the user said, which tools you called with what arguments and results, what you replied) so you can honestly explain why you did something. Use this whenever the user asks why you did or said something - never guess from memory."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"turns": "how many recent turns to include (default 1, max 5)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let turns = args
+            .get("turns")
+            .and_then(|s| s.trim().parse::<i64>().ok())
+            .unwrap_or(1)
+            .clamp(1, 5);
+
+        let mut transcripts = Vec::new();
+        for turn_id in self.journal.recent_finished_turns(self.agent_id, turns)? {
+            if let Some(transcript) = self.journal.transcript(turn_id)? {
+                transcripts.push(transcript);
+            }
+        }
+        if transcripts.is_empty() {
+            return Ok(ToolResult::error(
+                "No recorded turns yet - there is nothing to explain from",
+            ));
+        }
+        transcripts.reverse();
+
+        Ok(ToolResult::success(format_action_record(&transcripts)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(seq: i32, step: Option<i32>, kind: &str, content: &str) -> TurnEvent {
+        TurnEvent {
+            id: Uuid::new_v4(),
+            turn_id: Uuid::nil(),
+            agent_id: Uuid::nil(),
+            seq,
+            step,
+            kind: kind.to_string(),
+            content: content.to_string(),
+            metadata: serde_json::json!({}),
+            duration_ms: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_format_transcript() {
+        let mut step = event(1, Some(0), "step", "what's the weather?");
+        step.metadata = serde_json::json!({ "tool_calls": ["web_search"], "done": false });
+        step.duration_ms = Some(1200);
+
+        let mut tool = event(2, Some(0), "tool_call", "Sunny, 21C");
+        tool.metadata = serde_json::json!({
+            "tool": "web_search",
+            "args": { "query": "weather" },
+            "success": true,
+        });
+        tool.duration_ms = Some(300);
+
+        let mut end = event(4, None, "end", "");
+        end.metadata = serde_json::json!({ "steps": 1 });
+        end.duration_ms = Some(1600);
+
+        let transcript = TurnTranscript {
+            turn_id: Uuid::nil(),
+            agent_id: Uuid::nil(),
+            started_at: Utc::now(),
+            events: vec![
+                event(0, None, "input", "what's the weather?"),
+                step,
+                tool,
+                event(3, Some(0), "output", "It's sunny."),
+                end,
+            ],
+        };
+
+        let text = format_transcript(&transcript);
+        assert!(text.contains("== Step 0 [1200ms] =="));
+        assert!(text.contains("Tool calls: web_search"));
+        assert!(text.contains("-> web_search({\"query\":\"weather\"}) [300ms] ok:"));
+        assert!(text.contains("<- sent: It's sunny."));
+        assert!(text.contains("== End (1 steps) [1600ms] =="));
+    }
+
+    #[test]
+    fn test_format_action_record() {
+        let mut tool = event(1, Some(0), "tool_call", "Reminder scheduled");
+        tool.metadata = serde_json::json!({
+            "tool": "schedule_task",
+            "args": { "when": "tomorrow 9am" },
+            "success": true,
+        });
+        let transcript = TurnTranscript {
+            turn_id: Uuid::nil(),
+            agent_id: Uuid::nil(),
+            started_at: Utc::now(),
+            events: vec![
+                event(0, None, "input", "remind me to call mom"),
+                event(1, Some(0), "step", "remind me to call mom"),
+                tool,
+                event(2, Some(0), "output", "Done, I'll remind you at 9."),
+                event(3, None, "end", ""),
+            ],
+        };
+
+        let text = format_action_record(&[transcript]);
+        assert!(text.contains("Your private reasoning was not recorded"));
+        assert!(text.contains("- User said: remind me to call mom"));
+        assert!(text.contains(
+            "- Step 0: called schedule_task with {\"when\":\"tomorrow 9am\"} -> ok: Reminder scheduled"
+        ));
+        assert!(text.contains("- Step 0: you replied: Done, I'll remind you at 9."));
+        // LLM inputs are left out of the condensed record
+        assert_eq!(text.matches("remind me to call mom").count(), 1);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("abcdef", 3), "abc... [truncated]");
+    }
+}
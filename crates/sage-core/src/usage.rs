@@ -0,0 +1,434 @@
+//! LLM Usage Accounting
+//!
+//! Records the prompt and completion tokens of every model call - agent
+//! steps, correction passes, vision, compaction and embeddings - in
+//! `llm_usage`, with a cost estimated from the configured per-million-token
+//! prices (`LLM_PROMPT_PRICE_PER_MTOK`, `LLM_COMPLETION_PRICE_PER_MTOK`,
+//! `EMBEDDING_PRICE_PER_MTOK`; vision is priced like chat). Other chat
+//! models, like the router's cheap tier, can have their own prices
+//! (`set_model_prices`).
+//!
+//! Like the DSRs LM, the recorder is process-wide: `install` it once at
+//! startup and call sites just report their counts. Calls are attributed to
+//! the agent whose turn is running (`with_agent`), or to the agent an
+//! embedding service belongs to. Rows are written off the async runtime; a
+//! failed write is logged, never surfaced to the call.
+//!
+//! `GET /usage` serves per-agent daily aggregates (see `UsageDb::report`);
+//! `spent_today` feeds the per-role daily budgets (see `roles`).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::DbConn;
+use crate::schema::llm_usage;
+
+/// Days covered by a usage report unless asked otherwise
+pub const DEFAULT_REPORT_DAYS: i64 = 30;
+
+/// Longest report window
+pub const MAX_REPORT_DAYS: i64 = 366;
+
+/// What a model call was for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// Main agent step
+    Agent,
+    /// Re-parse of a malformed agent or summary response
+    Correction,
+    /// Image description and receipt extraction
+    Vision,
+    /// Conversation summarization
+    Compaction,
+    /// Re-ranking of memory search results
+    Rerank,
+    /// Merging of near-duplicate archival passages
+    Consolidation,
+    /// Structured fact extraction from user messages
+    FactExtraction,
+    /// Birthday and anniversary check-ins
+    CheckIn,
+    /// Rewrites of full core memory blocks
+    MemoryRewrite,
+    /// Detection of promises in Sage's replies
+    Commitments,
+    Embedding,
+}
+
+impl CallKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CallKind::Agent => "agent",
+            CallKind::Correction => "correction",
+            CallKind::Vision => "vision",
+            CallKind::Compaction => "compaction",
+            CallKind::Rerank => "rerank",
+            CallKind::Consolidation => "consolidation",
+            CallKind::FactExtraction => "fact_extraction",
+            CallKind::CheckIn => "check_in",
+            CallKind::MemoryRewrite => "memory_rewrite",
+            CallKind::Commitments => "commitments",
+            CallKind::Embedding => "embedding",
+        }
+    }
+}
+
+/// Prices in USD per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Prices {
+    pub prompt_per_mtok: f64,
+    pub completion_per_mtok: f64,
+    pub embedding_per_mtok: f64,
+}
+
+impl Prices {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            prompt_per_mtok: config.llm_prompt_price_per_mtok,
+            completion_per_mtok: config.llm_completion_price_per_mtok,
+            embedding_per_mtok: config.embedding_price_per_mtok,
+        }
+    }
+
+    /// These prices with another chat model's prompt and completion prices
+    pub fn with_chat(self, prompt_per_mtok: f64, completion_per_mtok: f64) -> Self {
+        Self {
+            prompt_per_mtok,
+            completion_per_mtok,
+            ..self
+        }
+    }
+
+    /// Estimated cost of a call in USD
+    pub fn cost(&self, kind: CallKind, prompt_tokens: i64, completion_tokens: i64) -> f64 {
+        let (prompt, completion) = match kind {
+            CallKind::Embedding => (self.embedding_per_mtok, 0.0),
+            _ => (self.prompt_per_mtok, self.completion_per_mtok),
+        };
+        (prompt_tokens as f64 * prompt + completion_tokens as f64 * completion) / 1_000_000.0
+    }
+}
+
+/// Rough token count (~4 characters per token) for APIs that report none
+pub fn estimate_tokens(text: &str) -> i64 {
+    (text.chars().count() as i64 + 3) / 4
+}
+
+// ============================================================================
+// Recording
+// ============================================================================
+
+tokio::task_local! {
+    static CURRENT_AGENT: Uuid;
+}
+
+/// Run `fut` with its model calls attributed to `agent_id`
+pub async fn with_agent<F: Future>(agent_id: Uuid, fut: F) -> F::Output {
+    CURRENT_AGENT.scope(agent_id, fut).await
+}
+
+/// Agent whose turn is running on this task, if any
+pub fn current_agent() -> Option<Uuid> {
+    CURRENT_AGENT.try_with(|id| *id).ok()
+}
+
+struct Recorder {
+    db: Arc<UsageDb>,
+    prices: Prices,
+    /// Model behind the global DSRs LM
+    chat_model: String,
+}
+
+static RECORDER: OnceLock<Recorder> = OnceLock::new();
+
+/// Chat prices of models other than the DSRs one: prompt, completion
+static MODEL_PRICES: RwLock<BTreeMap<String, (f64, f64)>> = RwLock::new(BTreeMap::new());
+
+/// Price calls to `model` at these rates instead of the chat model's
+pub fn set_model_prices(model: &str, prompt_per_mtok: f64, completion_per_mtok: f64) {
+    if let Ok(mut prices) = MODEL_PRICES.write() {
+        prices.insert(model.to_string(), (prompt_per_mtok, completion_per_mtok));
+    }
+}
+
+fn prices_for(recorder: &Recorder, model: &str) -> Prices {
+    match MODEL_PRICES.read().ok().and_then(|p| p.get(model).copied()) {
+        Some((prompt, completion)) => recorder.prices.with_chat(prompt, completion),
+        None => recorder.prices,
+    }
+}
+
+/// Start recording usage (once, at startup; later calls are ignored)
+pub fn install(db: Arc<UsageDb>, prices: Prices, chat_model: &str) {
+    let _ = RECORDER.set(Recorder {
+        db,
+        prices,
+        chat_model: chat_model.to_string(),
+    });
+}
+
+/// Record a DSRs call (made with the configured chat model)
+pub fn record_chat(kind: CallKind, prompt_tokens: i64, completion_tokens: i64) {
+    if let Some(recorder) = RECORDER.get() {
+        record(
+            kind,
+            None,
+            &recorder.chat_model,
+            prompt_tokens,
+            completion_tokens,
+        );
+    }
+}
+
+/// Record a model call. `agent_id` overrides the running turn's agent.
+pub fn record(
+    kind: CallKind,
+    agent_id: Option<Uuid>,
+    model: &str,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    let row = NewUsage {
+        id: Uuid::new_v4(),
+        agent_id: agent_id.or_else(current_agent),
+        kind: kind.as_str(),
+        model: model.to_string(),
+        prompt_tokens,
+        completion_tokens,
+        cost_usd: prices_for(recorder, model).cost(kind, prompt_tokens, completion_tokens),
+    };
+    let db = recorder.db.clone();
+    runtime.spawn_blocking(move || {
+        if let Err(e) = db.insert(&row) {
+            warn!("Failed to record LLM usage: {}", e);
+        }
+    });
+}
+
+/// What an agent's model calls cost since UTC midnight. None when usage
+/// isn't recorded or can't be read.
+pub fn spent_today(agent_id: Uuid) -> Option<f64> {
+    let recorder = RECORDER.get()?;
+    let midnight = Utc::now().date_naive().and_hms_opt(0, 0, 0)?.and_utc();
+    match recorder.db.cost_since(agent_id, midnight) {
+        Ok(cost) => Some(cost),
+        Err(e) => {
+            warn!("Failed to read LLM usage of agent {}: {}", agent_id, e);
+            None
+        }
+    }
+}
+
+// ============================================================================
+// Aggregates
+// ============================================================================
+
+/// One agent's usage of one kind on one day (UTC)
+#[derive(Debug, Clone, PartialEq, Serialize, QueryableByName)]
+pub struct DailyUsage {
+    #[diesel(sql_type = diesel::sql_types::Date)]
+    pub day: NaiveDate,
+    /// None for calls made outside an agent's turn
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Uuid>)]
+    pub agent_id: Option<Uuid>,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub kind: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub calls: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub prompt_tokens: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub completion_tokens: i64,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    pub cost_usd: f64,
+}
+
+/// Sums over a report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost_usd: f64,
+}
+
+pub fn totals(rows: &[DailyUsage]) -> UsageTotals {
+    rows.iter().fold(UsageTotals::default(), |mut acc, row| {
+        acc.calls += row.calls;
+        acc.prompt_tokens += row.prompt_tokens;
+        acc.completion_tokens += row.completion_tokens;
+        acc.cost_usd += row.cost_usd;
+        acc
+    })
+}
+
+/// Response of `GET /usage`
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub since: DateTime<Utc>,
+    /// Only this agent's usage (None = all agents)
+    pub agent_id: Option<Uuid>,
+    pub totals: UsageTotals,
+    /// Newest day first
+    pub daily: Vec<DailyUsage>,
+}
+
+// ============================================================================
+// Database Operations
+// ============================================================================
+
+#[derive(Insertable)]
+#[diesel(table_name = llm_usage)]
+struct NewUsage {
+    id: Uuid,
+    agent_id: Option<Uuid>,
+    kind: &'static str,
+    model: String,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    cost_usd: f64,
+}
+
+pub struct UsageDb {
+    conn: Arc<DbConn>,
+}
+
+impl UsageDb {
+    /// Create a new UsageDb with its own connection
+    pub fn connect(db_url: &str) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+        })
+    }
+
+    fn insert(&self, row: &NewUsage) -> Result<()> {
+        self.conn.run(|conn| {
+            diesel::insert_into(llm_usage::table)
+                .values(row)
+                .execute(conn)
+                .context("Failed to insert LLM usage")?;
+            Ok(())
+        })
+    }
+
+    /// Total estimated cost of an agent's calls since `since`
+    pub fn cost_since(&self, agent_id: Uuid, since: DateTime<Utc>) -> Result<f64> {
+        self.conn.run(|conn| {
+            let cost: Option<f64> = llm_usage::table
+                .filter(llm_usage::agent_id.eq(agent_id))
+                .filter(llm_usage::created_at.ge(since))
+                .select(diesel::dsl::sum(llm_usage::cost_usd))
+                .first(conn)
+                .context("Failed to sum LLM usage")?;
+            Ok(cost.unwrap_or(0.0))
+        })
+    }
+
+    /// Daily usage per agent and kind over the last `days` days, for one
+    /// agent or all of them
+    pub fn report(&self, agent_id: Option<Uuid>, days: i64) -> Result<UsageReport> {
+        let since = Utc::now() - Duration::days(days.clamp(1, MAX_REPORT_DAYS));
+        self.conn.run(|conn| {
+            let daily: Vec<DailyUsage> = diesel::sql_query(
+                "SELECT (created_at AT TIME ZONE 'UTC')::date AS day, agent_id, kind, \
+                    COUNT(*) AS calls, \
+                    SUM(prompt_tokens)::BIGINT AS prompt_tokens, \
+                    SUM(completion_tokens)::BIGINT AS completion_tokens, \
+                    SUM(cost_usd) AS cost_usd \
+                 FROM llm_usage \
+                 WHERE created_at >= $1 AND ($2::uuid IS NULL OR agent_id = $2) \
+                 GROUP BY 1, 2, 3 \
+                 ORDER BY 1 DESC, 2, 3",
+            )
+            .bind::<diesel::sql_types::Timestamptz, _>(since)
+            .bind::<diesel::sql_types::Nullable<diesel::sql_types::Uuid>, _>(agent_id)
+            .load(conn)
+            .context("Failed to aggregate LLM usage")?;
+            Ok(UsageReport {
+                since,
+                agent_id,
+                totals: totals(&daily),
+                daily,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost() {
+        let prices = Prices {
+            prompt_per_mtok: 3.0,
+            completion_per_mtok: 15.0,
+            embedding_per_mtok: 0.02,
+        };
+        let cost = prices.cost(CallKind::Agent, 10_000, 1_000);
+        assert!((cost - 0.045).abs() < 1e-9);
+        // Embeddings have no completion tokens and their own price
+        let cost = prices.cost(CallKind::Embedding, 1_000_000, 500);
+        assert!((cost - 0.02).abs() < 1e-9);
+        assert_eq!(Prices::default().cost(CallKind::Vision, 5_000, 5_000), 0.0);
+        // A cheaper chat model keeps the embedding price
+        let cheap = prices.with_chat(0.3, 1.5);
+        assert!((cheap.cost(CallKind::Agent, 10_000, 1_000) - 0.0045).abs() < 1e-9);
+        assert_eq!(cheap.embedding_per_mtok, 0.02);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn test_totals() {
+        let row = |kind: &str, calls, prompt, completion, cost| DailyUsage {
+            day: NaiveDate::from_ymd_opt(2026, 10, 17).unwrap(),
+            agent_id: None,
+            kind: kind.to_string(),
+            calls,
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            cost_usd: cost,
+        };
+        let sum = totals(&[
+            row("agent", 3, 9_000, 600, 0.036),
+            row("embedding", 5, 400, 0, 0.0001),
+        ]);
+        assert_eq!(sum.calls, 8);
+        assert_eq!(sum.prompt_tokens, 9_400);
+        assert_eq!(sum.completion_tokens, 600);
+        assert!((sum.cost_usd - 0.0361).abs() < 1e-9);
+        assert_eq!(totals(&[]), UsageTotals::default());
+    }
+
+    #[test]
+    fn test_current_agent() {
+        assert_eq!(current_agent(), None);
+        let agent_id = Uuid::new_v4();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let seen = runtime.block_on(with_agent(agent_id, async { current_agent() }));
+        assert_eq!(seen, Some(agent_id));
+    }
+}
@@ -0,0 +1,193 @@
+//! Vector Index Maintenance
+//!
+//! Embedding search orders by cosine distance (`<=>`). On a small table an
+//! exact scan is fast and finds the true nearest neighbours, so tables start
+//! without a vector index. Once a table holds `VECTOR_INDEX_MIN_ROWS`
+//! embeddings, a background task builds an HNSW index on it
+//! (`CREATE INDEX CONCURRENTLY`, so writes carry on during the build).
+//!
+//! HNSW needs no training data and stays accurate as rows are added, unlike
+//! IVFFlat, whose lists are fixed when it is built. An index is only rebuilt
+//! when Postgres marks it invalid, e.g. after a build was interrupted.
+//!
+//! How many candidates an HNSW scan considers is `hnsw.ef_search`
+//! (`VECTOR_EF_SEARCH`), set on every pooled connection (see
+//! `db::configure_vector_search`). Searches are filtered by agent, so scans
+//! continue past the first candidates when too few of them match
+//! (`hnsw.iterative_scan`, pgvector 0.8+).
+
+use anyhow::{Context, Result};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Bool, Text};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::db::DbConn;
+
+/// Embedded rows a table needs before it gets an HNSW index
+pub const DEFAULT_INDEX_MIN_ROWS: i64 = 10_000;
+
+/// How often tables are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// First check after startup, once migrations and agents are up
+const STARTUP_DELAY: Duration = Duration::from_secs(60);
+
+/// Tables with an `embedding` column and the HNSW index each gets
+pub const VECTOR_TABLES: &[(&str, &str)] = &[
+    ("messages", "idx_messages_embedding_hnsw"),
+    ("message_chunks", "idx_message_chunks_embedding_hnsw"),
+    ("passages", "idx_passages_embedding_hnsw"),
+    ("summaries", "idx_summaries_embedding_hnsw"),
+];
+
+/// What to do about a table's vector index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexAction {
+    /// Small enough for exact scans, or indexed already
+    Keep,
+    Create,
+    /// The index exists but is invalid: drop it and build it again
+    Rebuild,
+}
+
+/// Decide on a table's index from its row count and whether its index
+/// exists (`Some(valid)`)
+pub fn plan(rows: i64, index: Option<bool>, min_rows: i64) -> IndexAction {
+    match index {
+        Some(true) => IndexAction::Keep,
+        Some(false) => IndexAction::Rebuild,
+        None if rows >= min_rows => IndexAction::Create,
+        None => IndexAction::Keep,
+    }
+}
+
+#[derive(QueryableByName)]
+struct Count {
+    #[diesel(sql_type = BigInt)]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct IndexState {
+    #[diesel(sql_type = Bool)]
+    valid: bool,
+}
+
+/// Creates and repairs the HNSW indexes of `VECTOR_TABLES`
+pub struct VectorIndexes {
+    conn: Arc<DbConn>,
+    min_rows: i64,
+}
+
+impl VectorIndexes {
+    pub fn connect(db_url: &str, min_rows: i64) -> Result<Self> {
+        let conn = DbConn::connect(db_url).context("Failed to connect to database")?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            min_rows,
+        })
+    }
+
+    /// Check every table and build the indexes that are due. Blocking: an
+    /// index build can take minutes on a large table.
+    pub fn check(&self) -> Result<()> {
+        for (table, index) in VECTOR_TABLES {
+            let rows = self.embedded_rows(table)?;
+            match plan(rows, self.index_state(index)?, self.min_rows) {
+                IndexAction::Keep => {}
+                IndexAction::Create => {
+                    info!(
+                        "{} has {} embeddings - building HNSW index {}",
+                        table, rows, index
+                    );
+                    self.build(table, index)?;
+                }
+                IndexAction::Rebuild => {
+                    warn!("HNSW index {} is invalid - rebuilding", index);
+                    self.conn.run(|conn| {
+                        diesel::sql_query(format!("DROP INDEX CONCURRENTLY IF EXISTS {}", index))
+                            .execute(conn)
+                            .with_context(|| format!("Failed to drop {}", index))
+                    })?;
+                    self.build(table, index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn embedded_rows(&self, table: &str) -> Result<i64> {
+        self.conn.run(|conn| {
+            let count: Count = diesel::sql_query(format!(
+                "SELECT COUNT(*) AS count FROM {} WHERE embedding IS NOT NULL",
+                table
+            ))
+            .get_result(conn)
+            .with_context(|| format!("Failed to count {}", table))?;
+            Ok(count.count)
+        })
+    }
+
+    /// Whether `index` exists, and if so whether it is valid
+    fn index_state(&self, index: &str) -> Result<Option<bool>> {
+        self.conn.run(|conn| {
+            let state: Option<IndexState> = diesel::sql_query(
+                "SELECT i.indisvalid AS valid FROM pg_class c \
+                 JOIN pg_index i ON i.indexrelid = c.oid \
+                 WHERE c.relname = $1",
+            )
+            .bind::<Text, _>(index)
+            .get_result(conn)
+            .optional()
+            .with_context(|| format!("Failed to look up {}", index))?;
+            Ok(state.map(|s| s.valid))
+        })
+    }
+
+    fn build(&self, table: &str, index: &str) -> Result<()> {
+        let started = std::time::Instant::now();
+        self.conn.run(|conn| {
+            diesel::sql_query(format!(
+                "CREATE INDEX CONCURRENTLY IF NOT EXISTS {} ON {} \
+                 USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64)",
+                index, table
+            ))
+            .execute(conn)
+            .with_context(|| format!("Failed to build {}", index))?;
+            info!("Built {} in {:?}", index, started.elapsed());
+            Ok(())
+        })
+    }
+}
+
+/// Check the vector indexes now and then, for the life of the process
+pub async fn run_index_loop(indexes: Arc<VectorIndexes>) {
+    tokio::time::sleep(STARTUP_DELAY).await;
+    loop {
+        let checker = indexes.clone();
+        match tokio::task::spawn_blocking(move || checker.check()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Vector index maintenance failed: {:#}", e),
+            Err(e) => warn!("Vector index maintenance task failed: {}", e),
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        // Exact scans until the threshold
+        assert_eq!(plan(9_999, None, 10_000), IndexAction::Keep);
+        assert_eq!(plan(10_000, None, 10_000), IndexAction::Create);
+        // Built indexes stay, however large the table grows
+        assert_eq!(plan(500_000, Some(true), 10_000), IndexAction::Keep);
+        // An interrupted build is redone, even on a table that shrank
+        assert_eq!(plan(10, Some(false), 10_000), IndexAction::Rebuild);
+    }
+}
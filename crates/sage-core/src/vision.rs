@@ -3,34 +3,48 @@
 //! Describes images sent via Signal by calling a vision-capable LLM (Kimi K2.5)
 //! directly via the OpenAI-compatible API. The resulting description is injected
 //! into the conversation as text alongside the user's message.
+//!
+//! Images that look like receipts or bills additionally go through a structured
+//! extraction pass (merchant, total, date, line items) - see `extract_receipt`.
+//!
+//! Images larger than `VisionConfig::max_image_px` on their longest side are
+//! scaled down with ImageMagick (`convert`, installed in the container) before
+//! upload; if that fails the original is sent.
 
 use anyhow::{Context, Result};
 use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
 use tracing::{debug, info, warn};
 
+use crate::usage::{self, CallKind};
+
+/// Vision model options (`MAPLE_VISION_MODEL`, `VISION_MAX_IMAGE_PX`)
+#[derive(Debug, Clone)]
+pub struct VisionConfig {
+    pub api_url: String,
+    pub api_key: String,
+    pub model: String,
+    /// Longest side images are scaled down to before upload (0 = send as is)
+    pub max_image_px: u32,
+}
+
 /// Describes an image using a vision-capable model via the OpenAI-compatible API.
 ///
 /// `recent_messages` should contain the last few user/assistant turns for context
 /// (formatted as simple "[role]: content" lines).
 pub async fn describe_image(
-    api_url: &str,
-    api_key: &str,
-    model: &str,
+    config: &VisionConfig,
     image_path: &str,
     content_type: &str,
     user_message: &str,
     recent_messages: &str,
 ) -> Result<String> {
-    let image_data = std::fs::read(image_path)
-        .with_context(|| format!("Failed to read image file: {}", image_path))?;
-    let base64_image = base64::engine::general_purpose::STANDARD.encode(&image_data);
-    let data_url = format!("data:{};base64,{}", content_type, base64_image);
+    let (data_url, image_len) = image_data_url(config, image_path, content_type).await?;
 
     info!(
         "Describing image ({}, {} bytes) with model {}",
-        content_type,
-        image_data.len(),
-        model
+        content_type, image_len, config.model
     );
 
     let system_prompt = "You are an image description agent. Your ONLY job is to describe the \
@@ -73,7 +87,7 @@ pub async fn describe_image(
     }));
 
     let request_body = serde_json::json!({
-        "model": model,
+        "model": config.model,
         "messages": [
             { "role": "system", "content": system_prompt },
             { "role": "user", "content": user_content }
@@ -81,6 +95,212 @@ pub async fn describe_image(
         "max_tokens": 2048,
     });
 
+    let description = chat_completion(&config.api_url, &config.api_key, &request_body)
+        .await?
+        .unwrap_or_else(|| "[Could not describe image]".to_string());
+
+    info!("Image described successfully ({} chars)", description.len());
+    debug!(
+        "Image description: {}",
+        &description[..description.len().min(200)]
+    );
+
+    Ok(description)
+}
+
+/// Structured data extracted from a receipt or bill image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptData {
+    pub merchant: String,
+    /// Grand total in major units (e.g. dollars)
+    pub total: f64,
+    /// ISO 4217 currency code, if printed
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Purchase date as YYYY-MM-DD, if printed
+    #[serde(default)]
+    pub date: Option<String>,
+    /// Spending category guessed from the merchant/items
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub line_items: Vec<ReceiptLineItem>,
+}
+
+/// A single line on a receipt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReceiptLineItem {
+    pub description: String,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+    /// Line total in major units
+    pub amount: f64,
+}
+
+/// Extract structured receipt data from an image.
+///
+/// Returns `Ok(None)` if the model decides the image is not a receipt or bill.
+pub async fn extract_receipt(
+    config: &VisionConfig,
+    image_path: &str,
+    content_type: &str,
+) -> Result<Option<ReceiptData>> {
+    let (data_url, image_len) = image_data_url(config, image_path, content_type).await?;
+
+    info!(
+        "Extracting receipt data ({}, {} bytes) with model {}",
+        content_type, image_len, config.model
+    );
+
+    let system_prompt = "You are a receipt parser. Decide whether the image is a receipt, \
+        invoice, or bill. Respond with ONLY a JSON object, no prose and no code fences. \
+        If it is not a receipt or bill, respond with {\"is_receipt\": false}. Otherwise respond with: \
+        {\"is_receipt\": true, \"merchant\": string, \"total\": number (grand total actually paid or due), \
+        \"currency\": ISO 4217 code or null, \"date\": \"YYYY-MM-DD\" or null, \
+        \"category\": one of groceries, dining, transport, utilities, shopping, health, \
+        entertainment, travel, housing, other, \
+        \"line_items\": [{\"description\": string, \"quantity\": number or null, \"amount\": number}]}. \
+        Use numbers without currency symbols. Transcribe merchant and item names exactly.";
+
+    let request_body = serde_json::json!({
+        "model": config.model,
+        "messages": [
+            { "role": "system", "content": system_prompt },
+            { "role": "user", "content": [
+                { "type": "image_url", "image_url": { "url": data_url } },
+                { "type": "text", "text": "Extract the receipt data as JSON." }
+            ]}
+        ],
+        "max_tokens": 2048,
+    });
+
+    let Some(content) = chat_completion(&config.api_url, &config.api_key, &request_body).await?
+    else {
+        anyhow::bail!("Vision API returned no content for receipt extraction");
+    };
+
+    let receipt = parse_receipt_response(&content)?;
+    match &receipt {
+        Some(r) => info!(
+            "Receipt extracted: {} total {} ({} line items)",
+            r.merchant,
+            r.total,
+            r.line_items.len()
+        ),
+        None => debug!("Image is not a receipt"),
+    }
+    Ok(receipt)
+}
+
+/// Parse the model's JSON answer for receipt extraction.
+///
+/// Tolerates code fences or stray text around the JSON object.
+pub fn parse_receipt_response(content: &str) -> Result<Option<ReceiptData>> {
+    let start = content
+        .find('{')
+        .ok_or_else(|| anyhow::anyhow!("No JSON object in receipt response"))?;
+    let end = content
+        .rfind('}')
+        .filter(|&end| end > start)
+        .ok_or_else(|| anyhow::anyhow!("Unterminated JSON object in receipt response"))?;
+
+    let value: serde_json::Value =
+        serde_json::from_str(&content[start..=end]).context("Failed to parse receipt JSON")?;
+
+    if !value["is_receipt"].as_bool().unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let receipt: ReceiptData =
+        serde_json::from_value(value).context("Receipt JSON missing required fields")?;
+    Ok(Some(receipt))
+}
+
+/// Cheap check on the image description (and the user's caption) to decide
+/// whether the receipt extraction pass is worth running.
+pub fn looks_like_receipt(description: &str, user_message: &str) -> bool {
+    const DESCRIPTION_HINTS: &[&str] = &[
+        "receipt",
+        "invoice",
+        "subtotal",
+        "sub-total",
+        "amount due",
+        "balance due",
+        "total due",
+        "utility bill",
+    ];
+    const MESSAGE_HINTS: &[&str] = &["receipt", "invoice", "bill", "expense", "spent"];
+
+    let description = description.to_lowercase();
+    let user_message = user_message.to_lowercase();
+    DESCRIPTION_HINTS.iter().any(|h| description.contains(h))
+        || MESSAGE_HINTS.iter().any(|h| user_message.contains(h))
+}
+
+/// Read an image file and encode it as a data URL. Returns the URL and the
+/// raw image size in bytes.
+async fn image_data_url(
+    config: &VisionConfig,
+    image_path: &str,
+    content_type: &str,
+) -> Result<(String, usize)> {
+    let mut image_data = std::fs::read(image_path)
+        .with_context(|| format!("Failed to read image file: {}", image_path))?;
+    if config.max_image_px > 0 {
+        match downscale(image_path, content_type, config.max_image_px).await {
+            Ok(scaled) => {
+                debug!(
+                    "Image scaled to at most {}px ({} -> {} bytes)",
+                    config.max_image_px,
+                    image_data.len(),
+                    scaled.len()
+                );
+                image_data = scaled;
+            }
+            Err(e) => warn!("Failed to scale image down, sending original: {}", e),
+        }
+    }
+    let base64_image = base64::engine::general_purpose::STANDARD.encode(&image_data);
+    Ok((
+        format!("data:{};base64,{}", content_type, base64_image),
+        image_data.len(),
+    ))
+}
+
+/// `convert` arguments that shrink an image to fit `max_px` (never enlarging
+/// it) and write it to stdout in its own format. Only the first frame of an
+/// animation is kept.
+fn downscale_args(image_path: &str, content_type: &str, max_px: u32) -> Vec<String> {
+    let format = content_type.strip_prefix("image/").unwrap_or("png");
+    vec![
+        format!("{}[0]", image_path),
+        "-resize".to_string(),
+        format!("{}x{}>", max_px, max_px),
+        format!("{}:-", format),
+    ]
+}
+
+async fn downscale(image_path: &str, content_type: &str, max_px: u32) -> Result<Vec<u8>> {
+    let output = Command::new("convert")
+        .args(downscale_args(image_path, content_type, max_px))
+        .output()
+        .await
+        .context("Failed to run convert")?;
+    if !output.status.success() || output.stdout.is_empty() {
+        anyhow::bail!(
+            "convert failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// POST a chat completion request and return the first choice's content
+async fn chat_completion(
+    api_url: &str,
+    api_key: &str,
+    request_body: &serde_json::Value,
+) -> Result<Option<String>> {
     debug!("Vision API request to {}/chat/completions", api_url);
 
     let client = reqwest::Client::new();
@@ -88,7 +308,7 @@ pub async fn describe_image(
         .post(format!("{}/chat/completions", api_url))
         .header("Authorization", format!("Bearer {}", api_key))
         .header("Content-Type", "application/json")
-        .json(&request_body)
+        .json(request_body)
         .send()
         .await
         .context("Failed to call vision API")?;
@@ -104,18 +324,16 @@ pub async fn describe_image(
         .json()
         .await
         .context("Failed to parse vision API response")?;
-    let description = json["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("[Could not describe image]")
-        .to_string();
-
-    info!("Image described successfully ({} chars)", description.len());
-    debug!(
-        "Image description: {}",
-        &description[..description.len().min(200)]
+    usage::record(
+        CallKind::Vision,
+        None,
+        request_body["model"].as_str().unwrap_or_default(),
+        json["usage"]["prompt_tokens"].as_i64().unwrap_or(0),
+        json["usage"]["completion_tokens"].as_i64().unwrap_or(0),
     );
-
-    Ok(description)
+    Ok(json["choices"][0]["message"]["content"]
+        .as_str()
+        .map(|s| s.to_string()))
 }
 
 /// Check if a MIME type is an image type we can process
@@ -125,3 +343,53 @@ pub fn is_supported_image(content_type: &str) -> bool {
         "image/jpeg" | "image/png" | "image/webp" | "image/gif"
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downscale_args() {
+        assert_eq!(
+            downscale_args("/data/a.jpg", "image/jpeg", 1600),
+            vec!["/data/a.jpg[0]", "-resize", "1600x1600>", "jpeg:-"]
+        );
+        assert_eq!(downscale_args("b.gif", "image/gif", 800)[3], "gif:-");
+    }
+
+    #[test]
+    fn test_parse_receipt_response() {
+        let content = r#"```json
+{"is_receipt": true, "merchant": "Corner Cafe", "total": 12.5, "currency": "USD",
+ "date": "2026-10-01", "category": "dining",
+ "line_items": [{"description": "Latte", "quantity": 2, "amount": 9.0}]}
+```"#;
+        let receipt = parse_receipt_response(content).unwrap().unwrap();
+        assert_eq!(receipt.merchant, "Corner Cafe");
+        assert_eq!(receipt.total, 12.5);
+        assert_eq!(receipt.date.as_deref(), Some("2026-10-01"));
+        assert_eq!(receipt.line_items.len(), 1);
+        assert_eq!(receipt.line_items[0].quantity, Some(2.0));
+    }
+
+    #[test]
+    fn test_parse_receipt_response_not_receipt() {
+        assert!(parse_receipt_response(r#"{"is_receipt": false}"#)
+            .unwrap()
+            .is_none());
+        assert!(parse_receipt_response("no json here").is_err());
+    }
+
+    #[test]
+    fn test_looks_like_receipt() {
+        assert!(looks_like_receipt(
+            "A paper receipt from a grocery store",
+            ""
+        ));
+        assert!(looks_like_receipt(
+            "A printed page",
+            "here's the electric bill"
+        ));
+        assert!(!looks_like_receipt("A dog playing in the park", "look!"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Webhook Messenger
+//!
+//! Lets any custom frontend talk to Sage over HTTP instead of Signal. A client
+//! `POST`s `{user_id, text, attachments}` to `/message`; the message goes
+//! through the normal pipeline and the request resolves with every reply the
+//! agent sent during that turn.
+//!
+//! Replies produced outside a waiting request (scheduled reminders, or a turn
+//! that outlived the request timeout) are queued per user and returned by
+//! `GET /messages/{user_id}` or with the user's next `POST /message`.
+//!
+//! Clients name the `user_id` themselves, so the webhook messenger only
+//! starts with `HTTP_AUTH_TOKEN` set.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::messenger::{IncomingAttachment, IncomingMessage, Messenger};
+use crate::signal;
+
+/// Body of `POST /message`
+#[derive(Debug, Deserialize)]
+pub struct WebhookRequest {
+    pub user_id: String,
+    #[serde(default)]
+    pub user_name: Option<String>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub attachments: Vec<WebhookAttachment>,
+}
+
+/// An attachment sent inline as base64
+#[derive(Debug, Deserialize)]
+pub struct WebhookAttachment {
+    pub content_type: String,
+    /// Base64-encoded file contents
+    pub data: String,
+}
+
+/// Response to `POST /message` and `GET /messages/{user_id}`
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+    /// "complete" when the turn finished, "pending" if it is still running
+    pub status: &'static str,
+    pub replies: Vec<String>,
+}
+
+#[derive(Default)]
+struct UserReplies {
+    /// Replies sent since the last delivery
+    replies: Vec<String>,
+    /// Requests waiting for the current turn to finish
+    waiters: Vec<oneshot::Sender<Vec<String>>>,
+}
+
+/// Shared state between the HTTP handlers and the outbound messenger
+pub struct WebhookHub {
+    tx: mpsc::Sender<IncomingMessage>,
+    reply_timeout: Duration,
+    users: Mutex<HashMap<String, UserReplies>>,
+}
+
+impl WebhookHub {
+    pub fn new(tx: mpsc::Sender<IncomingMessage>, reply_timeout: Duration) -> Self {
+        Self {
+            tx,
+            reply_timeout,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn reply_timeout(&self) -> Duration {
+        self.reply_timeout
+    }
+
+    /// Queue an incoming message for processing
+    pub async fn submit(&self, msg: IncomingMessage) -> Result<()> {
+        self.tx
+            .send(msg)
+            .await
+            .map_err(|_| anyhow::anyhow!("message channel closed"))
+    }
+
+    /// Wait for the user's next turn to complete
+    pub fn register(&self, user_id: &str) -> oneshot::Receiver<Vec<String>> {
+        let (tx, rx) = oneshot::channel();
+        if let Ok(mut users) = self.users.lock() {
+            users
+                .entry(user_id.to_string())
+                .or_default()
+                .waiters
+                .push(tx);
+        }
+        rx
+    }
+
+    fn push_reply(&self, user_id: &str, reply: &str) {
+        if let Ok(mut users) = self.users.lock() {
+            users
+                .entry(user_id.to_string())
+                .or_default()
+                .replies
+                .push(reply.to_string());
+        }
+    }
+
+    /// Hand the turn's replies to everyone waiting; keep them queued if no
+    /// request is still listening.
+    fn complete_turn(&self, user_id: &str) {
+        let Ok(mut users) = self.users.lock() else {
+            return;
+        };
+        let Some(entry) = users.get_mut(user_id) else {
+            return;
+        };
+        if entry.waiters.is_empty() {
+            return;
+        }
+
+        let replies = std::mem::take(&mut entry.replies);
+        let mut delivered = false;
+        for waiter in entry.waiters.drain(..) {
+            delivered |= waiter.send(replies.clone()).is_ok();
+        }
+        if !delivered {
+            debug!(
+                "No webhook request waiting for {}, queueing replies",
+                user_id
+            );
+            entry.replies = replies;
+        }
+    }
+
+    /// Take replies queued for a user
+    pub fn drain_replies(&self, user_id: &str) -> Vec<String> {
+        self.users
+            .lock()
+            .ok()
+            .and_then(|mut users| {
+                users
+                    .get_mut(user_id)
+                    .map(|e| std::mem::take(&mut e.replies))
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Outbound side of the webhook transport
+pub struct WebhookMessenger {
+    hub: Arc<WebhookHub>,
+}
+
+impl WebhookMessenger {
+    pub fn new(hub: Arc<WebhookHub>) -> Self {
+        Self { hub }
+    }
+}
+
+impl Messenger for WebhookMessenger {
+    fn send_message(&self, recipient: &str, message: &str) -> Result<()> {
+        self.hub.push_reply(recipient, message);
+        Ok(())
+    }
+
+    fn send_typing(&self, _recipient: &str, _stop: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_turn(&self, recipient: &str) -> Result<()> {
+        self.hub.complete_turn(recipient);
+        Ok(())
+    }
+}
+
+/// Convert a webhook request into an `IncomingMessage`, writing inline
+/// attachments to the attachments directory.
+pub fn to_incoming_message(req: WebhookRequest) -> Result<IncomingMessage> {
+    let user_id = req.user_id.trim().to_string();
+    if user_id.is_empty() {
+        anyhow::bail!("user_id is required");
+    }
+    if req.text.trim().is_empty() && req.attachments.is_empty() {
+        anyhow::bail!("text or attachments required");
+    }
+
+    let mut attachments = Vec::new();
+    if !req.attachments.is_empty() {
+        std::fs::create_dir_all(signal::ATTACHMENTS_DIR)
+            .context("Failed to create attachments directory")?;
+    }
+    for attachment in req.attachments {
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(attachment.data.trim())
+            .context("Attachment data is not valid base64")?;
+        let file = format!("webhook-{}", Uuid::new_v4());
+        std::fs::write(signal::attachment_path(&file), &data)
+            .with_context(|| format!("Failed to save attachment {}", file))?;
+        attachments.push(IncomingAttachment {
+            file,
+            content_type: attachment.content_type.to_lowercase(),
+            size: Some(data.len() as u64),
+        });
+    }
+
+    Ok(IncomingMessage {
+        source: user_id.clone(),
+        source_name: req.user_name,
+        message: req.text,
+        attachments,
+        timestamp: chrono::Utc::now().timestamp_millis() as u64,
+        reply_to: user_id,
+        reply_context: None,
+        message_id: None,
+        quote: None,
+        mentions: Vec::new(),
+        edited: false,
+        reaction: None,
+        own_message: false,
+        inbox_ids: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hub() -> WebhookHub {
+        let (tx, _rx) = mpsc::channel(1);
+        WebhookHub::new(tx, Duration::from_secs(1))
+    }
+
+    #[test]
+    fn test_turn_replies_go_to_waiter() {
+        let hub = hub();
+        let mut rx = hub.register("user-1");
+        hub.push_reply("user-1", "hello");
+        hub.push_reply("user-1", "how can I help?");
+        hub.complete_turn("user-1");
+
+        assert_eq!(rx.try_recv().unwrap(), vec!["hello", "how can I help?"]);
+        assert!(hub.drain_replies("user-1").is_empty());
+    }
+
+    #[test]
+    fn test_replies_without_waiter_are_queued() {
+        let hub = hub();
+        hub.push_reply("user-1", "reminder: call mom");
+        hub.complete_turn("user-1");
+        assert_eq!(hub.drain_replies("user-1"), vec!["reminder: call mom"]);
+
+        // A request that gave up (dropped receiver) doesn't lose the replies
+        drop(hub.register("user-1"));
+        hub.push_reply("user-1", "late reply");
+        hub.complete_turn("user-1");
+        assert_eq!(hub.drain_replies("user-1"), vec!["late reply"]);
+    }
+
+    #[test]
+    fn test_to_incoming_message_validation() {
+        let req = WebhookRequest {
+            user_id: " ".to_string(),
+            user_name: None,
+            text: "hi".to_string(),
+            attachments: vec![],
+        };
+        assert!(to_incoming_message(req).is_err());
+
+        let req = WebhookRequest {
+            user_id: "user-1".to_string(),
+            user_name: Some("Alice".to_string()),
+            text: "hi".to_string(),
+            attachments: vec![],
+        };
+        let msg = to_incoming_message(req).unwrap();
+        assert_eq!(msg.reply_to, "user-1");
+        assert_eq!(msg.source_name.as_deref(), Some("Alice"));
+    }
+}
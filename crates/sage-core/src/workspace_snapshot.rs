@@ -0,0 +1,399 @@
+//! Workspace Snapshots
+//!
+//! Before the shell tool runs a command that looks destructive (deleting,
+//! moving or overwriting files, `git reset --hard`, running a script), the
+//! agent's workspace is archived to a tarball outside the workspace. The
+//! `workspace_rollback` tool lists snapshots and restores one, so a botched
+//! script can be undone.
+//!
+//! Only the most recent `keep` snapshots are retained, and workspaces larger
+//! than `MAX_SNAPSHOT_BYTES` are not snapshotted.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::messenger::format_bytes;
+use crate::sage_agent::{Tool, ToolResult};
+
+/// Workspaces larger than this are not snapshotted
+const MAX_SNAPSHOT_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Commands (or command prefixes) that can destroy or overwrite files
+const DESTRUCTIVE_COMMANDS: &[&str] = &[
+    "rm", "rmdir", "mv", "unlink", "shred", "truncate", "cp", "rsync", "chmod", "chown", "python",
+    "python3", "node", "perl", "ruby", "bash", "sh", "make", "npm", "pip", "pip3",
+];
+
+/// Arguments that make an otherwise harmless command destructive
+const DESTRUCTIVE_GIT: &[&str] = &[
+    "reset", "clean", "checkout", "restore", "rebase", "stash", "rm", "mv", "pull",
+];
+
+/// Whether a shell command might destroy or overwrite workspace files
+pub fn is_destructive(command: &str) -> bool {
+    // Output redirection that truncates a file (">" but not ">>" or "2>&1")
+    let bytes = command.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        if b != b'>' {
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|j| bytes[j]);
+        let next = bytes.get(i + 1).copied();
+        if prev == Some(b'>') || next == Some(b'>') || next == Some(b'&') {
+            continue;
+        }
+        let target = command[i + 1..].trim_start();
+        if !target.starts_with("/dev/null") {
+            return true;
+        }
+    }
+
+    command
+        .split(['|', ';', '&', '\n', '(', ')'])
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .any(|segment| {
+            let mut words = segment
+                .split_whitespace()
+                .skip_while(|w| *w == "sudo" || w.contains('='));
+            let Some(program) = words.next() else {
+                return false;
+            };
+            let program = program.rsplit('/').next().unwrap_or(program);
+
+            if segment.starts_with("./") {
+                return true;
+            }
+            match program {
+                "git" => words
+                    .next()
+                    .is_some_and(|sub| DESTRUCTIVE_GIT.contains(&sub)),
+                "sed" | "perl" if segment.contains(" -i") => true,
+                "find" => segment.contains("-delete") || segment.contains("-exec"),
+                _ => DESTRUCTIVE_COMMANDS.contains(&program),
+            }
+        })
+}
+
+/// A saved copy of the workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub id: String,
+    /// What prompted the snapshot (usually the command about to run)
+    pub reason: String,
+    pub size: u64,
+}
+
+/// Tarball snapshots of one agent's workspace
+pub struct WorkspaceSnapshots {
+    workspace: PathBuf,
+    dir: PathBuf,
+    keep: usize,
+    /// Serializes snapshot/restore so they never interleave
+    lock: Mutex<()>,
+}
+
+impl WorkspaceSnapshots {
+    /// Snapshots of `workspace` are stored in `dir` (which must be outside it)
+    pub fn new(workspace: impl Into<PathBuf>, dir: impl Into<PathBuf>, keep: usize) -> Self {
+        Self {
+            workspace: workspace.into(),
+            dir: dir.into(),
+            keep: keep.max(1),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Archive the workspace. Returns None if it is too large to snapshot.
+    pub fn take(&self, reason: &str) -> Result<Option<Snapshot>> {
+        let _guard = self
+            .lock
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Snapshot lock error: {}", e))?;
+        let snapshot = self.take_locked(reason)?;
+        self.prune();
+        Ok(snapshot)
+    }
+
+    fn take_locked(&self, reason: &str) -> Result<Option<Snapshot>> {
+        let size = dir_size(&self.workspace);
+        if size > MAX_SNAPSHOT_BYTES {
+            warn!(
+                "Workspace {} is {}, skipping snapshot",
+                self.workspace.display(),
+                format_bytes(size)
+            );
+            return Ok(None);
+        }
+
+        std::fs::create_dir_all(&self.dir).context("Failed to create snapshot directory")?;
+        std::fs::create_dir_all(&self.workspace)?;
+
+        let mut id = chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f").to_string();
+        while self.archive_path(&id).exists() {
+            id.push('x');
+        }
+        let archive = self.archive_path(&id);
+
+        let status = Command::new("tar")
+            .arg("-czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&self.workspace)
+            .arg(".")
+            .status()
+            .context("Failed to run tar")?;
+        if !status.success() {
+            let _ = std::fs::remove_file(&archive);
+            anyhow::bail!("tar exited with {}", status);
+        }
+        std::fs::write(self.reason_path(&id), reason)?;
+
+        let snapshot = Snapshot {
+            id,
+            reason: reason.to_string(),
+            size: std::fs::metadata(&archive).map(|m| m.len()).unwrap_or(0),
+        };
+        info!(
+            "Workspace snapshot {} ({}) before: {}",
+            snapshot.id,
+            format_bytes(snapshot.size),
+            reason
+        );
+
+        Ok(Some(snapshot))
+    }
+
+    /// Snapshots, newest first
+    pub fn list(&self) -> Vec<Snapshot> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        let mut snapshots: Vec<Snapshot> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let id = name.strip_suffix(".tar.gz")?.to_string();
+                Some(Snapshot {
+                    reason: std::fs::read_to_string(self.reason_path(&id)).unwrap_or_default(),
+                    size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                    id,
+                })
+            })
+            .collect();
+        snapshots.sort_by(|a, b| b.id.cmp(&a.id));
+        snapshots
+    }
+
+    /// Replace the workspace with a snapshot. The current state is snapshotted
+    /// first, so a rollback can itself be undone.
+    pub fn restore(&self, id: &str) -> Result<()> {
+        let _guard = self
+            .lock
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Snapshot lock error: {}", e))?;
+
+        let archive = self.archive_path(id);
+        if id.contains('/') || !archive.is_file() {
+            anyhow::bail!("No snapshot with id {}", id);
+        }
+
+        self.take_locked(&format!("rollback to {}", id))?;
+
+        for entry in std::fs::read_dir(&self.workspace)? {
+            let path = entry?.path();
+            if path.is_dir() && !path.is_symlink() {
+                std::fs::remove_dir_all(&path)?;
+            } else {
+                std::fs::remove_file(&path)?;
+            }
+        }
+
+        let status = Command::new("tar")
+            .arg("-xzf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&self.workspace)
+            .status()
+            .context("Failed to run tar")?;
+        if !status.success() {
+            anyhow::bail!("tar exited with {}", status);
+        }
+
+        info!(
+            "Restored workspace {} from snapshot {}",
+            self.workspace.display(),
+            id
+        );
+        self.prune();
+        Ok(())
+    }
+
+    /// Delete all but the newest `keep` snapshots
+    fn prune(&self) {
+        for old in self.list().into_iter().skip(self.keep) {
+            let _ = std::fs::remove_file(self.archive_path(&old.id));
+            let _ = std::fs::remove_file(self.reason_path(&old.id));
+        }
+    }
+
+    fn archive_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.tar.gz", id))
+    }
+
+    fn reason_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.reason", id))
+    }
+}
+
+/// Total size of the files under a directory (symlinks not followed)
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Tool for listing and restoring workspace snapshots
+pub struct WorkspaceRollbackTool {
+    snapshots: Arc<WorkspaceSnapshots>,
+}
+
+impl WorkspaceRollbackTool {
+    pub fn new(snapshots: Arc<WorkspaceSnapshots>) -> Self {
+        Self { snapshots }
+    }
+}
+
+#[async_trait]
+impl Tool for WorkspaceRollbackTool {
+    fn name(&self) -> &str {
+        "workspace_rollback"
+    }
+
+    fn description(&self) -> &str {
+        "Undo changes to your workspace. A snapshot is taken automatically before shell commands that delete, move or overwrite files or run scripts. Use action 'list' to see snapshots, or 'restore' to roll the workspace back (the current state is snapshotted first, so a rollback can be undone too)."
+    }
+
+    fn args_schema(&self) -> &str {
+        r#"{"action": "list or restore (default list)", "snapshot": "snapshot id to restore (default: most recent)"}"#
+    }
+
+    async fn execute(&self, args: &HashMap<String, String>) -> Result<ToolResult> {
+        let action = args.get("action").map(|a| a.trim()).unwrap_or("list");
+        let snapshots = self.snapshots.clone();
+
+        match action {
+            "list" => {
+                let list = tokio::task::spawn_blocking(move || snapshots.list()).await?;
+                if list.is_empty() {
+                    return Ok(ToolResult::success("No workspace snapshots yet."));
+                }
+                let lines: Vec<String> = list
+                    .iter()
+                    .map(|s| format!("{} ({}) before: {}", s.id, format_bytes(s.size), s.reason))
+                    .collect();
+                Ok(ToolResult::success(format!(
+                    "Snapshots (newest first):\n{}",
+                    lines.join("\n")
+                )))
+            }
+            "restore" => {
+                let requested = args
+                    .get("snapshot")
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty());
+                let result = tokio::task::spawn_blocking(move || -> Result<Snapshot> {
+                    let list = snapshots.list();
+                    let snapshot = match requested {
+                        Some(id) => list.into_iter().find(|s| s.id == id),
+                        None => list.into_iter().next(),
+                    }
+                    .ok_or_else(|| anyhow::anyhow!("No matching snapshot"))?;
+                    snapshots.restore(&snapshot.id)?;
+                    Ok(snapshot)
+                })
+                .await?;
+
+                match result {
+                    Ok(snapshot) => Ok(ToolResult::success(format!(
+                        "Workspace restored to snapshot {} (taken before: {}).",
+                        snapshot.id, snapshot.reason
+                    ))),
+                    Err(e) => Ok(ToolResult::error(format!("Rollback failed: {}", e))),
+                }
+            }
+            other => Ok(ToolResult::error(format!(
+                "Unknown action '{}': use list or restore",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_destructive() {
+        assert!(is_destructive("rm -rf build"));
+        assert!(is_destructive("cd src && mv a.txt b.txt"));
+        assert!(is_destructive("echo hi > notes.txt"));
+        assert!(is_destructive("sed -i 's/a/b/' config.toml"));
+        assert!(is_destructive("git reset --hard HEAD~1"));
+        assert!(is_destructive("python3 cleanup.py"));
+        assert!(is_destructive("./migrate.sh"));
+        assert!(is_destructive("find . -name '*.tmp' -delete"));
+
+        assert!(!is_destructive("ls -la"));
+        assert!(!is_destructive("cat notes.txt | grep todo"));
+        assert!(!is_destructive("echo hi >> notes.txt"));
+        assert!(!is_destructive("curl -s https://example.com 2>&1"));
+        assert!(!is_destructive("make_report --help > /dev/null"));
+        assert!(!is_destructive("git status"));
+        assert!(!is_destructive("find . -name '*.rs'"));
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let root = std::env::temp_dir().join(format!("sage-snapshots-{}", uuid::Uuid::new_v4()));
+        let workspace = root.join("workspace");
+        std::fs::create_dir_all(workspace.join("data")).unwrap();
+        std::fs::write(workspace.join("data/notes.txt"), "keep me").unwrap();
+
+        let snapshots = WorkspaceSnapshots::new(&workspace, root.join("snapshots"), 3);
+        let snapshot = snapshots.take("rm -rf data").unwrap().unwrap();
+
+        std::fs::remove_dir_all(workspace.join("data")).unwrap();
+        std::fs::write(workspace.join("junk.txt"), "oops").unwrap();
+
+        snapshots.restore(&snapshot.id).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(workspace.join("data/notes.txt")).unwrap(),
+            "keep me"
+        );
+        assert!(!workspace.join("junk.txt").exists());
+
+        // The pre-rollback state was snapshotted too
+        let list = snapshots.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].reason, format!("rollback to {}", snapshot.id));
+
+        assert!(snapshots.restore("../etc").is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
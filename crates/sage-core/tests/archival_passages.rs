@@ -0,0 +1,270 @@
+//! Archival passage update, delete and list against a real database.
+//!
+//! Needs PostgreSQL with pgvector (see `common/mod.rs`).
+
+use sage_core::memory::MemoryDb;
+use uuid::Uuid;
+
+mod common;
+
+/// Removes the agent's passages
+const CLEANUP: &[&str] = &["DELETE FROM passages WHERE agent_id = $1"];
+
+const DIM: usize = 768;
+
+fn tags(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|t| t.to_string()).collect()
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_update_delete_and_list_passages() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4().to_string();
+    let _cleanup = common::Cleanup::new(&url, &agent_id, CLEANUP);
+    let passages = db.passages();
+    let embedding = vec![0.1; DIM];
+
+    let dog = passages
+        .insert_passage_with_embedding(&agent_id, "Has a dog named Rex", &embedding, &[], None)
+        .expect("insert");
+    let part1 = passages
+        .insert_passage_with_embedding(
+            &agent_id,
+            "Part one",
+            &embedding,
+            &tags(&["group:g1"]),
+            None,
+        )
+        .expect("insert");
+    passages
+        .insert_passage_with_embedding(
+            &agent_id,
+            "Part two",
+            &embedding,
+            &tags(&["group:g1"]),
+            None,
+        )
+        .expect("insert");
+
+    // Newest first, tag filter, paging
+    let all = passages
+        .list_passages(&agent_id, None, None, 10, 0)
+        .expect("list");
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[2].id, dog);
+    let grouped = passages
+        .list_passages(&agent_id, Some("group:g1"), None, 10, 0)
+        .expect("list");
+    assert_eq!(grouped.len(), 2);
+    let page = passages
+        .list_passages(&agent_id, None, None, 2, 2)
+        .expect("list");
+    assert_eq!(page.len(), 1);
+
+    // Update keeps tags unless given
+    assert!(passages
+        .update_passage(&agent_id, dog, "Has a dog named Max", &embedding, None)
+        .expect("update"));
+    let updated = passages.get_passage(&agent_id, dog).expect("get").unwrap();
+    assert_eq!(updated.content, "Has a dog named Max");
+    assert!(updated.tags.is_empty());
+    assert!(passages
+        .update_passage(
+            &agent_id,
+            part1,
+            "Part one",
+            &embedding,
+            Some(&tags(&["x"]))
+        )
+        .expect("update"));
+    assert_eq!(
+        passages
+            .get_passage(&agent_id, part1)
+            .expect("get")
+            .unwrap()
+            .tags,
+        tags(&["x"])
+    );
+
+    // Another agent can't touch these passages
+    let other = Uuid::new_v4().to_string();
+    assert!(passages.get_passage(&other, dog).expect("get").is_none());
+    assert!(!passages
+        .update_passage(&other, dog, "hijacked", &embedding, None)
+        .expect("update"));
+    assert!(!passages.delete_passage(&other, dog).expect("delete"));
+
+    assert_eq!(
+        passages
+            .delete_passages_tagged(&agent_id, "group:g1")
+            .expect("delete"),
+        1
+    );
+    assert!(passages.delete_passage(&agent_id, dog).expect("delete"));
+    assert!(!passages.delete_passage(&agent_id, dog).expect("delete"));
+    assert_eq!(passages.count_passages(&agent_id).expect("count"), 1);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_expired_passages_are_hidden() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4().to_string();
+    let _cleanup = common::Cleanup::new(&url, &agent_id, CLEANUP);
+    let passages = db.passages();
+    let embedding = vec![0.1; DIM];
+
+    let kept = passages
+        .insert_passage_with_embedding(
+            &agent_id,
+            "Flight to Lisbon on May 3",
+            &embedding,
+            &[],
+            None,
+        )
+        .expect("insert");
+    let expired = passages
+        .insert_passage_with_embedding(&agent_id, "Staying at hotel Lisbon", &embedding, &[], None)
+        .expect("insert");
+    passages
+        .set_passage_retention(
+            &agent_id,
+            &[expired],
+            Some(2.0),
+            Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+        )
+        .expect("set retention");
+
+    let listed = passages
+        .list_passages(&agent_id, None, None, 10, 0)
+        .expect("list");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, kept);
+    assert_eq!(passages.count_passages(&agent_id).expect("count"), 1);
+    let found = passages
+        .search_passages_full_text(&agent_id, "Lisbon", &embedding, 10, None, None)
+        .expect("search");
+    assert_eq!(found.len(), 1);
+
+    let importance = passages
+        .passage_importance(&agent_id, &[kept, expired])
+        .expect("importance");
+    assert_eq!(importance[&kept], 1.0);
+    assert_eq!(importance[&expired], 2.0);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_export_passages() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4().to_string();
+    let _cleanup = common::Cleanup::new(&url, &agent_id, CLEANUP);
+    let passages = db.passages();
+    let embedding = vec![0.1; DIM];
+
+    let first = passages
+        .insert_passage_with_embedding(&agent_id, "Allergic to peanuts", &embedding, &[], None)
+        .expect("insert");
+    let trip = passages
+        .insert_passage_with_embedding(
+            &agent_id,
+            "Trip to Porto",
+            &embedding,
+            &tags(&["travel"]),
+            None,
+        )
+        .expect("insert");
+    let expired = passages
+        .insert_passage_with_embedding(&agent_id, "Parked on level 2", &embedding, &[], None)
+        .expect("insert");
+    let in_a_week = chrono::Utc::now() + chrono::Duration::days(7);
+    passages
+        .set_passage_retention(&agent_id, &[trip], Some(0.8), Some(in_a_week))
+        .expect("set retention");
+    passages
+        .set_passage_retention(
+            &agent_id,
+            &[expired],
+            None,
+            Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+        )
+        .expect("set retention");
+
+    // Oldest first, live passages only, with importance and expiry
+    let exported = passages.export_passages(&agent_id).expect("export");
+    assert_eq!(exported.len(), 2);
+    assert_eq!(exported[0].0.id, first);
+    let (row, importance, expires_at) = &exported[1];
+    assert_eq!(row.id, trip);
+    assert_eq!(row.tags, tags(&["travel"]));
+    assert_eq!(*importance, 0.8);
+    assert!(expires_at.is_some());
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_passage_namespaces() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4().to_string();
+    let _cleanup = common::Cleanup::new(&url, &agent_id, CLEANUP);
+    let passages = db.passages();
+    let embedding = vec![0.1; DIM];
+
+    let standup = passages
+        .insert_passage_with_embedding(&agent_id, "Standup at 9:30", &embedding, &[], Some("work"))
+        .expect("insert");
+    let gym = passages
+        .insert_passage_with_embedding(
+            &agent_id,
+            "Gym on Tuesdays",
+            &embedding,
+            &[],
+            Some("personal"),
+        )
+        .expect("insert");
+    let name = passages
+        .insert_passage_with_embedding(&agent_id, "Goes by Sam", &embedding, &[], None)
+        .expect("insert");
+
+    // A namespace sees its own passages and those in none
+    let sorted = |mut ids: Vec<Uuid>| {
+        ids.sort();
+        ids
+    };
+    let expected = sorted(vec![standup, name]);
+    let listed = passages
+        .list_passages(&agent_id, None, Some("work"), 10, 0)
+        .expect("list");
+    assert_eq!(sorted(listed.iter().map(|row| row.id).collect()), expected);
+    let found = passages
+        .search_passages_by_embedding(&agent_id, &embedding, 10, None, Some("work"))
+        .expect("search");
+    assert_eq!(
+        sorted(found.iter().map(|(row, _)| row.id).collect()),
+        expected
+    );
+    let all = passages
+        .search_passages_by_embedding(&agent_id, &embedding, 10, None, None)
+        .expect("search");
+    assert_eq!(all.len(), 3);
+
+    assert_eq!(
+        passages.namespace_counts(&agent_id).expect("counts"),
+        vec![("personal".to_string(), 1), ("work".to_string(), 1)]
+    );
+    assert_eq!(
+        passages
+            .set_passage_namespace(&agent_id, &[gym], Some("work"))
+            .expect("move"),
+        1
+    );
+    assert_eq!(
+        passages.namespace_counts(&agent_id).expect("counts"),
+        vec![("work".to_string(), 2)]
+    );
+}
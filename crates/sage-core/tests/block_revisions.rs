@@ -0,0 +1,61 @@
+//! Core memory block revisions and rollback against a real database.
+//!
+//! Needs PostgreSQL with pgvector (see `common/mod.rs`).
+
+use sage_core::memory::{BlockManager, EditCause, MemoryDb};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+mod common;
+
+/// Removes the agent's blocks and revisions
+const CLEANUP: &[&str] = &[
+    "DELETE FROM blocks WHERE agent_id = $1",
+    "DELETE FROM block_revisions WHERE agent_id = $1",
+];
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_edits_are_recorded_and_rolled_back() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4();
+    let _cleanup = common::Cleanup::new(&url, agent_id, CLEANUP);
+    // New agents start with an empty human block at version 1
+    let blocks = BlockManager::new(agent_id, db).expect("load blocks");
+
+    blocks
+        .update("human", "Name: Ana", &EditCause::other("test"))
+        .expect("update");
+    let args = HashMap::from([("content".to_string(), "Likes jazz".to_string())]);
+    blocks
+        .append(
+            "human",
+            "Likes jazz",
+            &EditCause::tool("memory_append", &args),
+        )
+        .expect("append");
+    assert_eq!(blocks.get("human").unwrap().version, 3);
+
+    let revisions = blocks.revisions("human", 10).expect("revisions");
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].version, 3);
+    assert_eq!(revisions[0].source, "memory_append");
+    assert_eq!(revisions[0].previous_value, "Name: Ana");
+    assert_eq!(revisions[0].value, "Name: Ana\nLikes jazz");
+    assert!(revisions[0].tool_args.is_some());
+
+    // Undo the append, then go back to before the first edit
+    let undo = EditCause::other("memory_undo");
+    assert_eq!(blocks.rollback("human", None, &undo).expect("undo"), 2);
+    assert_eq!(blocks.get("human").unwrap().value, "Name: Ana");
+    assert_eq!(
+        blocks.rollback("human", Some(1), &undo).expect("rollback"),
+        1
+    );
+    assert_eq!(blocks.get("human").unwrap().value, "");
+    assert_eq!(blocks.revisions("human", 10).expect("revisions").len(), 4);
+
+    assert!(blocks.rollback("human", Some(40), &undo).is_err());
+    assert!(blocks.rollback("persona", None, &undo).is_err());
+}
@@ -0,0 +1,76 @@
+//! Structured fact storage against a real database.
+//!
+//! Needs PostgreSQL with pgvector (see `common/mod.rs`).
+
+use sage_core::memory::{FactChange, MemoryDb};
+use uuid::Uuid;
+
+mod common;
+
+/// Removes the agent (its facts cascade)
+const CLEANUP: &[&str] = &["DELETE FROM agents WHERE id = $1::uuid"];
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_corrections_supersede_facts() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4();
+    db.agents()
+        .ensure_agent_exists(agent_id, "sage")
+        .expect("create agent");
+    let _cleanup = common::Cleanup::new(&url, agent_id, CLEANUP);
+    let facts = db.facts();
+
+    assert_eq!(
+        facts
+            .upsert(agent_id, "user_dog", "age", "3", 1.0, None)
+            .expect("upsert"),
+        FactChange::Added
+    );
+    facts
+        .upsert(agent_id, "user_dog", "name", "Rex", 0.6, None)
+        .expect("upsert");
+    // Same value again only confirms it, keeping the higher confidence
+    assert_eq!(
+        facts
+            .upsert(agent_id, "user_dog", "name", "rex", 0.9, None)
+            .expect("upsert"),
+        FactChange::Confirmed
+    );
+    assert_eq!(
+        facts
+            .upsert(agent_id, "user_dog", "age", "4", 1.0, None)
+            .expect("upsert"),
+        FactChange::Replaced("3".to_string())
+    );
+
+    let age = facts
+        .current(agent_id, Some("user_dog"), Some("age"), &[], 10)
+        .expect("current");
+    assert_eq!(age.len(), 1);
+    assert_eq!(age[0].object, "4");
+    let name = facts
+        .current(agent_id, None, None, &["%rex%".to_string()], 10)
+        .expect("current");
+    assert_eq!(name.len(), 1);
+    assert_eq!(name[0].confidence, 0.9);
+    assert_eq!(facts.count(agent_id).expect("count"), 2);
+
+    let history = facts
+        .history(agent_id, "user_dog", "age", 10)
+        .expect("history");
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].object, "4");
+    assert!(history[0].superseded_at.is_none());
+    assert!(history[1].superseded_at.is_some());
+
+    // Deletes the current and the superseded age
+    assert_eq!(
+        facts
+            .delete_matching(agent_id, &["%dog%".to_string(), "%age%".to_string()])
+            .expect("delete"),
+        2
+    );
+    assert_eq!(facts.count(agent_id).expect("count"), 1);
+}
@@ -0,0 +1,146 @@
+//! Message embedding and full-text search against a real database.
+//!
+//! Needs PostgreSQL with pgvector (see `common/mod.rs`).
+
+use sage_core::memory::MemoryDb;
+use uuid::Uuid;
+
+mod common;
+
+/// Removes the agent's messages (chunks cascade)
+const CLEANUP: &[&str] = &["DELETE FROM messages WHERE agent_id = $1::uuid"];
+
+const DIM: usize = 768;
+
+/// Unit vector along `axis`
+fn axis(axis: usize) -> Vec<f32> {
+    let mut v = vec![0.0; DIM];
+    v[axis] = 1.0;
+    v
+}
+
+fn insert(db: &MemoryDb, agent_id: Uuid, content: &str, embedding: &[f32]) -> Uuid {
+    db.messages()
+        .insert_message(
+            agent_id, "user-1", "user", content, embedding, None, None, None,
+        )
+        .expect("insert message")
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_search_ranks_by_cosine_distance() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4();
+    let _cleanup = common::Cleanup::new(&url, agent_id, CLEANUP);
+
+    let tea = insert(&db, agent_id, "I love green tea", &axis(0));
+    let mut between = axis(0);
+    between[1] = 1.0;
+    let mixed = insert(&db, agent_id, "Tea and hiking", &between);
+    let hiking = insert(&db, agent_id, "Went hiking", &axis(1));
+    // Embedding still pending: never a result
+    insert(&db, agent_id, "Not embedded yet", &[0.0; DIM]);
+    // Another agent's message is invisible
+    let other = Uuid::new_v4();
+    let _other_cleanup = common::Cleanup::new(&url, other, CLEANUP);
+    insert(&db, other, "Someone else's tea", &axis(0));
+
+    let results = db
+        .messages()
+        .search_by_embedding(agent_id, &axis(0), 10)
+        .expect("search");
+    let ids: Vec<Uuid> = results.iter().map(|r| r.message.id).collect();
+    assert_eq!(ids, vec![tea, mixed, hiking]);
+    assert!(results[0].distance.abs() < 1e-6);
+    assert!((results[2].distance - 1.0).abs() < 1e-6);
+    assert_eq!(results[0].message.content, "I love green tea");
+    assert_eq!(results[0].message.role, "user");
+
+    let limited = db
+        .messages()
+        .search_by_embedding(agent_id, &axis(0), 1)
+        .expect("search");
+    assert_eq!(limited.len(), 1);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_search_matches_chunks_once() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4();
+    let _cleanup = common::Cleanup::new(&url, agent_id, CLEANUP);
+
+    let document = insert(&db, agent_id, "A long pasted document", &axis(2));
+    db.messages()
+        .replace_chunks(
+            document,
+            agent_id,
+            &[
+                ("first part".to_string(), axis(2)),
+                ("middle part".to_string(), axis(3)),
+            ],
+        )
+        .expect("store chunks");
+
+    // Found by its middle, listed once with the best chunk's distance
+    let results = db
+        .messages()
+        .search_by_embedding(agent_id, &axis(3), 10)
+        .expect("search");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].message.id, document);
+    assert!(results[0].distance.abs() < 1e-6);
+
+    // Replacing chunks drops the old ones
+    db.messages()
+        .replace_chunks(document, agent_id, &[("only part".to_string(), axis(4))])
+        .expect("replace chunks");
+    let results = db
+        .messages()
+        .search_by_embedding(agent_id, &axis(3), 10)
+        .expect("search");
+    assert!((results[0].distance - 1.0).abs() < 1e-6);
+}
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_full_text_search_finds_exact_tokens() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4();
+    let _cleanup = common::Cleanup::new(&url, agent_id, CLEANUP);
+
+    let ticket = insert(
+        &db,
+        agent_id,
+        "Opened ticket INC-4471 for the VPN",
+        &axis(5),
+    );
+    let both = insert(&db, agent_id, "INC-4471 is fixed, the VPN works", &axis(6));
+    // Not embedded yet, still found by its words
+    let pending = insert(&db, agent_id, "Ask about the VPN again", &[0.0; DIM]);
+    insert(&db, agent_id, "Lunch with Dana", &axis(5));
+
+    // Any word matches; more matching words rank higher
+    let results = db
+        .messages()
+        .search_full_text(agent_id, "VPN ticket INC-4471", &axis(5), 10)
+        .expect("search");
+    let ids: Vec<Uuid> = results.iter().map(|r| r.message.id).collect();
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids[0], ticket);
+    assert!(ids.contains(&both) && ids.contains(&pending));
+    assert!(results[0].distance.abs() < 1e-6);
+    let pending_result = results.iter().find(|r| r.message.id == pending).unwrap();
+    assert!((pending_result.distance - 1.0).abs() < 1e-6);
+
+    // Nothing searchable in the query
+    let results = db
+        .messages()
+        .search_full_text(agent_id, "?!", &axis(5), 10)
+        .expect("search");
+    assert!(results.is_empty());
+}
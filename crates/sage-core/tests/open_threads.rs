@@ -0,0 +1,57 @@
+//! Open thread storage against a real database.
+//!
+//! Needs PostgreSQL with pgvector (see `common/mod.rs`).
+
+use chrono::Utc;
+use sage_core::memory::{MemoryDb, OpenThreads};
+use uuid::Uuid;
+
+mod common;
+
+/// Removes the agent (its threads cascade)
+const CLEANUP: &[&str] = &["DELETE FROM agents WHERE id = $1::uuid"];
+
+#[test]
+#[ignore = "needs TEST_DATABASE_URL"]
+fn test_open_threads_resolve_by_number() {
+    let url = common::test_database();
+    let db = MemoryDb::new(&url).expect("connect");
+    let agent_id = Uuid::new_v4();
+    db.agents()
+        .ensure_agent_exists(agent_id, "sage")
+        .expect("create agent");
+    let _cleanup = common::Cleanup::new(&url, agent_id, CLEANUP);
+
+    for content in ["Venue for the party", "Bike repair shop", "Gift for Anna"] {
+        db.open_threads()
+            .insert(agent_id, content, Utc::now())
+            .expect("insert");
+    }
+    let threads = OpenThreads::new(db.clone(), agent_id);
+    let open = threads.open().expect("open");
+    let contents: Vec<&str> = open.iter().map(|t| t.content.as_str()).collect();
+    assert_eq!(
+        contents,
+        vec!["Venue for the party", "Bike repair shop", "Gift for Anna"]
+    );
+
+    // Numbers as listed; unknown ones are ignored
+    assert_eq!(threads.resolve_numbers(&[2, 7]).expect("resolve"), 1);
+    let open = threads.open().expect("open");
+    assert_eq!(open.len(), 2);
+    assert_eq!(open[1].content, "Gift for Anna");
+    // Already resolved threads aren't counted again
+    assert_eq!(
+        db.open_threads()
+            .resolve(agent_id, &[open[0].id, open[0].id])
+            .expect("resolve"),
+        1
+    );
+    assert_eq!(
+        db.open_threads()
+            .resolve(agent_id, &[open[0].id])
+            .expect("resolve"),
+        0
+    );
+    assert_eq!(threads.open().expect("open").len(), 1);
+}
@@ -40,6 +40,8 @@ pub struct SearchOptions {
 pub struct BraveClient {
     client: reqwest::Client,
     api_key: Arc<String>,
+    /// Request and fetch the AI summary of the results
+    summarizer: bool,
 }
 
 impl BraveClient {
@@ -52,9 +54,17 @@ impl BraveClient {
         Ok(Self {
             client,
             api_key: Arc::new(api_key),
+            summarizer: true,
         })
     }
 
+    /// Turn the AI summarizer on or off (on by default). Off saves a request
+    /// per search.
+    pub fn with_summarizer(mut self, enabled: bool) -> Self {
+        self.summarizer = enabled;
+        self
+    }
+
     /// Perform a search with full Pro features
     pub async fn search(
         &self,
@@ -67,12 +77,15 @@ impl BraveClient {
         // Build query parameters
         let mut params = vec![
             ("q", query.to_string()),
-            ("summary", "1".to_string()), // Enable AI summarizer
             ("extra_snippets", "true".to_string()), // Get additional context
             ("enable_rich_callback", "1".to_string()), // Enable rich data (Pro)
-            ("spellcheck", "true".to_string()), // Auto-correct typos
+            ("spellcheck", "true".to_string()),     // Auto-correct typos
         ];
 
+        if self.summarizer {
+            params.push(("summary", "1".to_string())); // Enable AI summarizer
+        }
+
         if let Some(c) = opts.count {
             params.push(("count", c.min(20).to_string()));
         }
@@ -118,7 +131,11 @@ impl BraveClient {
         let mut search_response: SearchResponse = response.json().await?;
 
         // Automatically fetch AI summary if available
-        if let Some(ref summarizer) = search_response.summarizer {
+        if let Some(summarizer) = search_response
+            .summarizer
+            .as_ref()
+            .filter(|_| self.summarizer)
+        {
             debug!("Fetching Brave AI summary...");
             match self.fetch_summary(&summarizer.key).await {
                 Ok(summary_response) => {